@@ -0,0 +1,68 @@
+use crate::probe::{self, OutputFormat};
+use crate::Result;
+use anyhow::Context;
+use cardinal::hexvec;
+use cardinal::probe::AtrProbe;
+use std::io::Read;
+use tracing::trace_span;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum AtrCommand {
+    /// Decode an ATR from the command line or stdin; no reader needed.
+    Decode {
+        /// ATR, as hex (spaces and colons are fine). (Default: read it from stdin.)
+        atr: Option<String>,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+
+        /// Shorthand for --output=json.
+        #[arg(long, conflicts_with = "output")]
+        json: bool,
+    },
+}
+
+impl AtrCommand {
+    pub fn exec(&self, args: &crate::Args) -> Result<()> {
+        match self {
+            Self::Decode { atr, output, json } => {
+                let output = if *json { OutputFormat::Json } else { *output };
+                decode(args, atr.as_deref(), output)
+            }
+        }
+    }
+}
+
+fn decode(args: &crate::Args, atr: Option<&str>, output: OutputFormat) -> Result<()> {
+    let span = trace_span!("atr decode");
+    let _enter = span.enter();
+
+    let text = match atr {
+        Some(atr) if atr != "-" => atr.to_owned(),
+        _ => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("couldn't read stdin")?;
+            text
+        }
+    };
+    // Logs tend to say "ATR: 3B ..."; anything before the colon isn't hex.
+    let text = text.trim();
+    let text = match text.split_once(": ") {
+        Some((_, rest)) => rest,
+        None => text,
+    };
+    let raw = hexvec::parse(text).with_context(|| format!("invalid ATR: {:?}", text))?;
+
+    let opts = probe::options(args)?;
+    let decoded = AtrProbe::decode(&raw, opts.atr_db.as_ref())?;
+    match output {
+        OutputFormat::Text => {
+            probe::render_atr_probe(&decoded.atr, &decoded.known_as, &decoded.atr_warnings)
+        }
+        output => probe::write_structured(&decoded, output)?,
+    }
+    Ok(())
+}
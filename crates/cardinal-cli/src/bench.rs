@@ -0,0 +1,129 @@
+use crate::i18n::tr;
+use crate::probe::{write_structured, OutputFormat};
+use crate::Result;
+use anyhow::Context as _;
+use cardinal::bench::{self, Bench};
+use cardinal::report::{Kind, Report};
+use cardinal::CardTransport;
+use owo_colors::OwoColorize;
+use std::path::{Path, PathBuf};
+use tracing::{debug, trace_span, warn};
+
+/// Benchmarks a reader, prints the results, and compares them with the last ones in the
+/// archive (if asked). Returns the sizes that got slower.
+pub fn bench_reader(
+    card: &mut impl CardTransport,
+    sizes: &[usize],
+    iterations: usize,
+    output: OutputFormat,
+    archive: Option<&Path>,
+) -> Result<Vec<bench::Comparison>> {
+    let span = trace_span!("bench_reader");
+    let _enter = span.enter();
+
+    let current = bench::run(card, sizes, iterations)?;
+    // Find the baseline before archiving, so we don't compare the results to themselves.
+    let baseline = match archive {
+        Some(archive) => baseline(archive, current.reader.as_deref())?,
+        None => None,
+    };
+    let comparisons = baseline
+        .as_ref()
+        .map(|(_, baseline)| bench::compare(baseline, &current))
+        .unwrap_or_default();
+
+    match output {
+        OutputFormat::Text => render(&current, baseline.as_ref(), &comparisons),
+        _ => write_structured(&Report::new(Kind::Bench, &current), output)?,
+    }
+
+    if let Some(archive) = archive {
+        let path = archive.join(format!(
+            "{}-bench.json",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        ));
+        debug!(path = %path.display(), "Archiving result");
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("couldn't create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &Report::new(Kind::Bench, &current))?;
+    }
+    Ok(comparisons
+        .into_iter()
+        .filter(|c| c.is_regression())
+        .collect())
+}
+
+/// Finds the latest benchmark of the same reader in an archive. Anything in there that
+/// isn't one (or can't be read) is skipped; it's probably from `cardinal check`.
+fn baseline(archive: &Path, reader: Option<&str>) -> Result<Option<(PathBuf, Bench)>> {
+    let mut paths = std::fs::read_dir(archive)
+        .with_context(|| format!("couldn't read {}", archive.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.to_string_lossy().ends_with("-bench.json"))
+        .collect::<Vec<_>>();
+    // They're named by time, so the latest sorts last.
+    paths.sort();
+    for path in paths.into_iter().rev() {
+        let bench = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| Ok(cardinal::report::load(&s)?))
+            .and_then(|report| {
+                anyhow::ensure!(report["kind"] == "bench", "not a benchmark");
+                Ok(serde_json::from_value::<Bench>(report["data"].clone())?)
+            });
+        match bench {
+            Ok(bench) if bench.reader.as_deref() == reader => return Ok(Some((path, bench))),
+            Ok(_) => debug!(path = %path.display(), "Skipping benchmark of another reader"),
+            Err(err) => warn!("Couldn't load {}: {:#}", path.display(), err),
+        }
+    }
+    Ok(None)
+}
+
+fn render(current: &Bench, baseline: Option<&(PathBuf, Bench)>, comparisons: &[bench::Comparison]) {
+    if let Some(reader) = current.reader.as_deref() {
+        println!("{}", reader.bold());
+    }
+    println!(
+        "{:>5} {:>9} {:>9} {:>9} {:>9} {:>9} {:>10}",
+        tr("size"),
+        "min",
+        "p50",
+        "p90",
+        "p99",
+        "max",
+        "bytes/s"
+    );
+    for stats in current.results.iter() {
+        println!(
+            "{:>5} {:>7}µs {:>7}µs {:>7}µs {:>7}µs {:>7}µs {:>10.0}",
+            stats.size,
+            stats.min_us,
+            stats.p50_us,
+            stats.p90_us,
+            stats.p99_us,
+            stats.max_us,
+            stats.bytes_per_sec
+        );
+    }
+
+    let Some((path, _)) = baseline else {
+        return;
+    };
+    println!();
+    println!("{}: {}", tr("baseline"), path.display().dimmed());
+    for cmp in comparisons {
+        let line = format!(
+            "{:>5} {:>7}µs -> {:>7}µs ({:+.0}%)",
+            cmp.size,
+            cmp.baseline_p50_us,
+            cmp.p50_us,
+            cmp.change * 100.0
+        );
+        if cmp.is_regression() {
+            println!("{} {}", line, tr("REGRESSION").red().bold());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
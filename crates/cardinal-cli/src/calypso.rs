@@ -0,0 +1,35 @@
+use crate::probe::{self, OutputFormat};
+use crate::probe_calypso;
+use crate::Result;
+use anyhow::anyhow;
+use cardinal::probe::calypso::probe_calypso;
+use cardinal::CardTransport;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum CalypsoCommand {
+    /// Read every file in the transit application, and decode what Intercode (Navigo and
+    /// most of France) says is in them.
+    Dump {
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+}
+
+impl CalypsoCommand {
+    pub fn exec(&self, card: &mut impl CardTransport) -> Result<()> {
+        match self {
+            Self::Dump { output } => {
+                let mut bufs = card.buffers();
+                let (wbuf, rbuf) = bufs.split();
+                let calypso = probe_calypso(card, wbuf, rbuf)?
+                    .ok_or_else(|| anyhow!("no Calypso transit application on this card"))?;
+                match output {
+                    OutputFormat::Text => probe_calypso::render_calypso(&calypso),
+                    output => probe::write_structured(&calypso, *output)?,
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::i18n::tr;
+use crate::probe::{write_structured, OutputFormat};
+use crate::Result;
+use anyhow::Context as _;
+use cardinal::atr;
+use cardinal::check::{Spec, Violation};
+use cardinal::report::{Kind, Report};
+use cardinal::transports::reader::{set_led, Led};
+use cardinal::CardTransport;
+use owo_colors::OwoColorize;
+use std::path::Path;
+use tracing::{debug, trace_span};
+
+/// Checks a card, prints the result, archives it (if asked), and sets the reader's LED.
+pub fn check(
+    card: &mut impl CardTransport,
+    spec: &Spec,
+    output: OutputFormat,
+    archive: Option<&Path>,
+) -> Result<Vec<Violation>> {
+    let span = trace_span!("check");
+    let _enter = span.enter();
+
+    // Grab the card's UID first, for the archive; not every card has one.
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+    let standard = (card.atr().ok())
+        .and_then(|raw| atr::parse_lenient(&raw).ok())
+        .map_or(atr::Standard::Iso14443a3, |(atr, _)| {
+            cardinal::probe::get_atr_card_standard(&atr)
+        });
+    let uid = cardinal::probe::probe_uid(card, wbuf, rbuf, standard);
+
+    let violations = cardinal::check::check(card, spec)?;
+    match output {
+        OutputFormat::Text => {
+            for violation in violations.iter() {
+                println!("{} {}", tr("FAIL").red().bold(), violation);
+            }
+            if violations.is_empty() {
+                println!("{}", tr("PASS").green().bold());
+            }
+        }
+        _ => write_structured(&Report::new(Kind::Check, &violations), output)?,
+    }
+
+    if let Some(archive) = archive {
+        let path = archive.join(format!(
+            "{}-{}-{}.json",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+            uid,
+            if violations.is_empty() {
+                "pass"
+            } else {
+                "fail"
+            },
+        ));
+        debug!(path = %path.display(), "Archiving result");
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("couldn't create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &Report::new(Kind::Check, &violations))?;
+    }
+
+    let led = if violations.is_empty() {
+        Led::Green
+    } else {
+        Led::Red
+    };
+    if let Err(err) = set_led(card, led) {
+        debug!(
+            "Couldn't set the reader's LED (not an ACS reader?): {}",
+            err
+        );
+    }
+    Ok(violations)
+}
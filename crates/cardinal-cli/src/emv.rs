@@ -0,0 +1,739 @@
+use crate::i18n::tr;
+use crate::probe::{self, OutputFormat};
+use crate::Result;
+use anyhow::{bail, Context};
+use cardinal::emv::{
+    self, generate_ac,
+    get_data::Counters,
+    gpo::ProcessingOptions,
+    oda::CaKeys,
+    scheme::{IssuerApplicationData, Scheme},
+    terminal::{self, Terminal, TerminalConfig},
+    track::MagStripe,
+    Cryptogram, CryptogramType,
+};
+use cardinal::money::Currency;
+use cardinal::{hexvec, CardTransport, HexVec};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use std::path::PathBuf;
+use tracing::{debug, trace_span, warn};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum EmvCommand {
+    /// Select one application and show what it says about itself, without the whole probe.
+    Select {
+        /// AID, as hex, or a well-known name: visa, mastercard, maestro, amex, discover, jcb,
+        /// unionpay, interac, visa-electron, vpay.
+        aid: String,
+
+        /// Also send GET PROCESSING OPTIONS, for the AIP and AFL. Most cards count this as a
+        /// transaction, so it bumps the ATC.
+        #[arg(long)]
+        gpo: bool,
+
+        /// Also read every record the AFL points at. (Implies --gpo.)
+        #[arg(long)]
+        records: bool,
+
+        /// What kind of terminal to be: a preset (pos, transit, unattended), or a TOML file
+        /// (see the docs for emv::terminal::TerminalConfig).
+        #[arg(long, default_value = "pos")]
+        terminal: String,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+
+    /// Run a transaction up to GENERATE AC, and show the Application Cryptogram the card
+    /// gives back. This is a real transaction as far as the card knows: the ATC goes up, and
+    /// too many declines can lock it up until it next goes online.
+    GenerateAc {
+        /// AID, as hex, or a well-known name (see `cardinal emv select`).
+        aid: String,
+
+        /// What to ask for; the card may give back something else.
+        #[arg(long = "type", value_enum, default_value_t = AcType::Arqc)]
+        kind: AcType,
+
+        /// Ask for an ARQC first, then send a second GENERATE AC (from CDOL2) for --type,
+        /// as if the terminal couldn't reach the issuer.
+        #[arg(long)]
+        second: bool,
+
+        /// Ask for a CDA signature over the response, too.
+        #[arg(long)]
+        cda: bool,
+
+        /// What kind of terminal to be: a preset (pos, transit, unattended), or a TOML file
+        /// (see the docs for emv::terminal::TerminalConfig).
+        #[arg(long, default_value = "pos")]
+        terminal: String,
+
+        /// Yes, really.
+        #[arg(long)]
+        i_know_this_hits_the_card: bool,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+
+    /// Walk through a contactless transaction, as far as a terminal can without asking for a
+    /// cryptogram: application selection, GET PROCESSING OPTIONS, reading records and
+    /// offline data authentication, and what the cryptogram would cover. GPO bumps the ATC
+    /// on most cards, but nothing here asks for a cryptogram.
+    Simulate {
+        /// Amount, in the currency's major unit, eg. 12.34.
+        #[arg(long, default_value = "0")]
+        amount: String,
+
+        /// What kind of terminal to be: a preset (pos, transit, unattended), or a TOML file
+        /// (see the docs for emv::terminal::TerminalConfig).
+        #[arg(long, default_value = "pos")]
+        terminal: String,
+
+        /// Currency, as an ISO 4217 code (GBP) or number (826), instead of the terminal's.
+        #[arg(long)]
+        currency: Option<String>,
+
+        /// Terminal Transaction Qualifiers, as hex, instead of the terminal's.
+        #[arg(long)]
+        ttq: Option<HexVec>,
+
+        /// CA public keys, to check the card's signatures with; a CSV file of
+        /// `rid,index,exponent,modulus`, in hex. Without it, ODA is skipped.
+        #[arg(long)]
+        ca_keys: Option<PathBuf>,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+
+    /// Show each application's PIN Try Counter, ATC and Last Online ATC, from GET DATA;
+    /// how close the card is to locking up, without a whole dump. Nothing here counts as
+    /// a transaction.
+    Status {
+        /// Just this application (hex, or a well-known name), instead of every one the card
+        /// lists.
+        aid: Option<String>,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum AcType {
+    /// Application Authentication Cryptogram (decline).
+    Aac,
+    /// Transaction Certificate (approve offline).
+    Tc,
+    /// Authorisation Request Cryptogram (go online).
+    Arqc,
+}
+
+impl From<AcType> for CryptogramType {
+    fn from(v: AcType) -> Self {
+        match v {
+            AcType::Aac => CryptogramType::AAC,
+            AcType::Tc => CryptogramType::TC,
+            AcType::Arqc => CryptogramType::ARQC,
+        }
+    }
+}
+
+/// What `cardinal emv select` found.
+#[derive(Debug, Serialize)]
+pub struct Selected {
+    pub aid: HexVec,
+    pub application: emv::Application,
+    pub processing_options: Option<ProcessingOptions>,
+    pub records: Vec<Record>,
+    /// Track data, if the application's in mag-stripe mode; from the GPO response, the
+    /// records, or both.
+    pub mag_stripe: Option<MagStripe>,
+}
+
+/// What `cardinal emv generate-ac` got back.
+#[derive(Debug, Serialize)]
+pub struct Generated {
+    pub aid: HexVec,
+    pub processing_options: ProcessingOptions,
+    pub cryptograms: Vec<Requested>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Requested {
+    pub request: CryptogramType,
+    pub cryptogram: Cryptogram,
+    /// The cryptogram's Issuer Application Data, decoded, if we know the scheme's layout.
+    pub iad: Option<IssuerApplicationData>,
+}
+
+/// What `cardinal emv status` found, for one application.
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub aid: HexVec,
+    pub label: String,
+    pub counters: Counters,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub sfi: u8,
+    pub num: u8,
+    pub data: HexVec,
+}
+
+/// Turns a name from [emv::WELL_KNOWN_AIDS], or hex, into an AID.
+fn parse_aid(s: &str) -> Result<Vec<u8>> {
+    match emv::aid_by_alias(s) {
+        Some(aid) => Ok(aid.to_vec()),
+        None => hexvec::parse(s).with_context(|| format!("not an AID or a known alias: {:?}", s)),
+    }
+}
+
+/// Parses an ISO 4217 code (GBP) or number (826).
+fn parse_currency(s: &str) -> Result<Currency> {
+    if let Ok(code) = s.parse::<u16>() {
+        return Ok(Currency::from(code));
+    }
+    Currency::from_code(s)
+        .with_context(|| format!("not a currency we know: {:?}; try its number", s))
+}
+
+/// Loads a [TerminalConfig] preset, or a TOML file.
+fn load_terminal(s: &str) -> Result<TerminalConfig> {
+    if let Some(config) = TerminalConfig::preset(s) {
+        return Ok(config);
+    }
+    let data = std::fs::read_to_string(s).with_context(|| {
+        format!(
+            "not a preset ({}) or a file: {:?}",
+            TerminalConfig::PRESETS.join(", "),
+            s
+        )
+    })?;
+    toml::from_str(&data).with_context(|| format!("couldn't parse {}", s))
+}
+
+/// Parses an amount in a currency's major unit (12.34) into its minor unit (1234).
+fn parse_amount(s: &str, currency: Currency) -> Result<u64> {
+    let exp = usize::from(currency.minor_units());
+    let (major, minor) = s.split_once('.').unwrap_or((s, ""));
+    if minor.len() > exp {
+        bail!("{} only has {} decimal places: {:?}", currency, exp, s);
+    }
+    let digits = format!("{}{:0<width$}", major, minor, width = exp);
+    digits
+        .parse()
+        .with_context(|| format!("not an amount: {:?}", s))
+}
+
+impl EmvCommand {
+    pub fn exec(&self, card: &mut impl CardTransport) -> Result<()> {
+        match self {
+            Self::Select {
+                aid,
+                gpo,
+                records,
+                terminal,
+                output,
+            } => {
+                let terminal = load_terminal(terminal)?;
+                let gpo = (*gpo || *records).then_some(&terminal);
+                let selected = select(card, &parse_aid(aid)?, gpo, *records)?;
+                match output {
+                    OutputFormat::Text => render(&selected),
+                    output => probe::write_structured(&selected, *output)?,
+                }
+                Ok(())
+            }
+            Self::GenerateAc {
+                aid,
+                kind,
+                second,
+                cda,
+                i_know_this_hits_the_card,
+                terminal,
+                output,
+            } => {
+                if !i_know_this_hits_the_card {
+                    bail!(
+                        "GENERATE AC is a real transaction: it bumps the card's ATC, and \
+                         declines count towards its offline limits; pass \
+                         --i-know-this-hits-the-card if you're sure"
+                    );
+                }
+                let terminal = load_terminal(terminal)?;
+                let aid = parse_aid(aid)?;
+                let generated = generate(card, &aid, (*kind).into(), *second, *cda, &terminal)?;
+                match output {
+                    OutputFormat::Text => render_generated(&generated),
+                    output => probe::write_structured(&generated, *output)?,
+                }
+                Ok(())
+            }
+            Self::Simulate {
+                amount,
+                terminal,
+                currency,
+                ttq,
+                ca_keys,
+                output,
+            } => {
+                let mut config = load_terminal(terminal)?;
+                if let Some(currency) = currency {
+                    config.currency = parse_currency(currency)?;
+                }
+                if let Some(ttq) = ttq {
+                    if ttq.0.len() != 4 {
+                        bail!("TTQ should be 4 bytes, not {}", ttq.0.len());
+                    }
+                    config.ttq = ttq.0.clone().into();
+                }
+                let amount = parse_amount(amount, config.currency)?;
+                let mut terminal = Terminal::new(config, chrono::Local::now().date_naive());
+                terminal.amount = amount;
+                if let Some(path) = ca_keys {
+                    terminal.ca_keys = CaKeys::load(path)
+                        .with_context(|| format!("couldn't read {}", path.display()))?;
+                }
+                let mut bufs = card.buffers();
+                let (wbuf, rbuf) = bufs.split();
+                let tx = terminal.run(card, wbuf, rbuf)?;
+                match output {
+                    OutputFormat::Text => render_transaction(&terminal, &tx),
+                    output => probe::write_structured(&tx, *output)?,
+                }
+                Ok(())
+            }
+            Self::Status { aid, output } => {
+                let aid = aid.as_deref().map(parse_aid).transpose()?;
+                let statuses = status(card, aid.as_deref())?;
+                match output {
+                    OutputFormat::Text => render_status(&statuses),
+                    output => probe::write_structured(&statuses, *output)?,
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn select(
+    card: &mut impl CardTransport,
+    aid: &[u8],
+    gpo: Option<&TerminalConfig>,
+    read_records: bool,
+) -> Result<Selected> {
+    let span = trace_span!("emv select", aid = hex::encode_upper(aid));
+    let _enter = span.enter();
+
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+
+    debug!("Selecting application...");
+    let application = emv::Application::select(card, wbuf, rbuf, aid)
+        .with_context(|| format!("couldn't select application {}", hex::encode_upper(aid)))?;
+
+    let processing_options = match gpo {
+        Some(terminal) => {
+            debug!("Getting processing options...");
+            let pdol = application.pdol.as_deref();
+            let opts = ProcessingOptions::get(card, wbuf, rbuf, pdol, terminal);
+            Some(opts.context("GPO failed")?)
+        }
+        None => None,
+    };
+
+    let mut records = vec![];
+    if let (true, Some(opts)) = (read_records, processing_options.as_ref()) {
+        for (sfi, num, data) in opts.read_records(card, wbuf, rbuf)? {
+            records.push(Record {
+                sfi,
+                num,
+                data: data.into(),
+            });
+        }
+    }
+
+    let mut mag_stripe = processing_options
+        .as_ref()
+        .and_then(|o| o.mag_stripe.clone());
+    for record in records.iter() {
+        match mag_stripe.as_mut() {
+            Some(ms) => ms.update(&record.data),
+            None => mag_stripe = MagStripe::parse(&record.data),
+        }
+    }
+
+    Ok(Selected {
+        aid: aid.to_vec().into(),
+        application,
+        processing_options,
+        records,
+        mag_stripe,
+    })
+}
+
+fn status(card: &mut impl CardTransport, aid: Option<&[u8]>) -> Result<Vec<Status>> {
+    let span = trace_span!("emv status");
+    let _enter = span.enter();
+
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+
+    let aids = match aid {
+        Some(aid) => vec![HexVec::from(aid)],
+        None => list_applications(card, wbuf, rbuf)?,
+    };
+    if aids.is_empty() {
+        bail!("the card doesn't list any applications; try giving it an AID");
+    }
+
+    let mut statuses = vec![];
+    for aid in aids {
+        let application = match emv::Application::select(card, wbuf, rbuf, &aid) {
+            Ok(application) => application,
+            Err(err) => {
+                warn!(
+                    aid = hex::encode_upper(&aid),
+                    "Couldn't select application: {}", err
+                );
+                continue;
+            }
+        };
+        statuses.push(Status {
+            counters: Counters::get(card, wbuf, rbuf)?,
+            label: application.app_label,
+            aid,
+        });
+    }
+    Ok(statuses)
+}
+
+/// Every application the PPSE or the PSE lists, once.
+fn list_applications(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<HexVec>> {
+    let mut aids = vec![];
+    if let Some(ppse) = emv::ProximityDirectory::lookup(card, wbuf, rbuf)?.present() {
+        aids.extend(ppse.applications.into_iter().map(|app| app.adf_name));
+    }
+    if let Some(pse) = emv::Directory::lookup(card, wbuf, rbuf)?.present() {
+        for res in pse.applications(card, wbuf, rbuf) {
+            match res {
+                Ok(app) if !aids.contains(&app.adf_name) => aids.push(app.adf_name),
+                Ok(_) => {}
+                Err(err) => warn!("Couldn't read a PSE record: {}", err),
+            }
+        }
+    }
+    Ok(aids)
+}
+
+fn generate(
+    card: &mut impl CardTransport,
+    aid: &[u8],
+    request: CryptogramType,
+    second: bool,
+    cda: bool,
+    terminal: &TerminalConfig,
+) -> Result<Generated> {
+    let span = trace_span!("emv generate-ac", aid = hex::encode_upper(aid));
+    let _enter = span.enter();
+
+    // GENERATE AC goes after GPO, and the CDOLs are in the records, so do it all again.
+    let selected = select(card, aid, Some(terminal), true)?;
+    let processing_options = selected.processing_options.unwrap_or_default();
+    let (cdol1, cdol2) = generate_ac::cdols(selected.records.iter().map(|r| &r.data[..]));
+    let cdol1 = cdol1.context("the application has no CDOL1 (8C) in its records")?;
+
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+
+    let steps = match second {
+        false => vec![(request, false, &cdol1)],
+        true => {
+            let cdol2 = cdol2
+                .as_ref()
+                .context("the application has no CDOL2 (8D) in its records")?;
+            vec![
+                (CryptogramType::ARQC, false, &cdol1),
+                (request, true, cdol2),
+            ]
+        }
+    };
+
+    let mut cryptograms = vec![];
+    for (request, second, cdol) in steps {
+        debug!(%request, second, "Generating AC...");
+        let cmd = emv::GenerateAC {
+            request,
+            second,
+            cda,
+        };
+        let cryptogram = cmd
+            .call(card, wbuf, rbuf, cdol, terminal)
+            .with_context(|| format!("GENERATE AC ({}) failed", request))?;
+        let iad = decode_iad(aid, cryptogram.iad.as_deref());
+        cryptograms.push(Requested {
+            request,
+            cryptogram,
+            iad,
+        });
+    }
+
+    Ok(Generated {
+        aid: aid.to_vec().into(),
+        processing_options,
+        cryptograms,
+    })
+}
+
+fn render_generated(generated: &Generated) {
+    println!("┏╸{} {}", "EMV".italic(), generated.aid);
+    println!(
+        " ┠─╴{}: {:04X}",
+        tr("Application Interchange Profile"),
+        generated.processing_options.aip
+    );
+    for Requested {
+        request,
+        cryptogram,
+        iad,
+    } in generated.cryptograms.iter()
+    {
+        println!(
+            " ┠─┬╴{}: {} ({} {})",
+            tr("Application Cryptogram"),
+            cryptogram.kind().bold(),
+            tr("requested"),
+            request
+        );
+        println!(
+            " ┃ ├─╴{}: {:02X}",
+            tr("Cryptogram Information Data"),
+            cryptogram.cid
+        );
+        println!(
+            " ┃ ├─╴{}: {}",
+            tr("Application Transaction Counter"),
+            cryptogram.atc
+        );
+        println!(" ┃ ├─╴AC: {}", hex::encode_upper(&cryptogram.ac));
+        if let Some(raw) = cryptogram.iad.as_ref() {
+            render_iad(raw, iad.as_ref());
+        }
+        println!(" ┃ ╵");
+    }
+}
+
+/// Decodes Issuer Application Data (9F10), if it's from a scheme we know the layout of.
+fn decode_iad(aid: &[u8], iad: Option<&[u8]>) -> Option<IssuerApplicationData> {
+    IssuerApplicationData::parse(Scheme::from_aid(aid)?, iad?)
+}
+
+fn render_iad(raw: &[u8], iad: Option<&IssuerApplicationData>) {
+    let Some(iad) = iad else {
+        println!(
+            " ┃ ├─╴{}: {}",
+            tr("Issuer Application Data"),
+            hex::encode_upper(raw)
+        );
+        return;
+    };
+    println!(
+        " ┃ ├┬╴{}: {}",
+        tr("Issuer Application Data"),
+        hex::encode_upper(raw)
+    );
+    println!(" ┃ │├─╴{}: {:02X}", tr("Derivation Key Index"), iad.dki);
+    println!(
+        " ┃ │├─╴{}: {:02X} ({})",
+        tr("Cryptogram Version Number"),
+        iad.cvn,
+        iad.cvn_name()
+    );
+    println!(
+        " ┃ │├┬╴{}: {}",
+        tr("Card Verification Results"),
+        hex::encode_upper(&iad.cvr)
+    );
+    for line in iad.explain_cvr() {
+        println!(" ┃ ││├─╴{}", line);
+    }
+    println!(" ┃ ││╵");
+    println!(" ┃ │╵");
+}
+
+fn render_status(statuses: &[Status]) {
+    let or_unknown = |v: Option<u16>| v.map(|v| v.to_string()).unwrap_or("?".into());
+    println!(
+        "{:<16} {:<16} {:>9} {:>6} {:>11} {:>13}",
+        "AID",
+        tr("Label"),
+        tr("PIN tries"),
+        "ATC",
+        tr("Last online"),
+        tr("Since online")
+    );
+    for Status {
+        aid,
+        label,
+        counters,
+    } in statuses
+    {
+        let tries = format!("{:>9}", or_unknown(counters.pin_try_counter.map(u16::from)));
+        let tries = match counters.pin_try_counter {
+            Some(0) => tries.red().bold().to_string(),
+            Some(1) => tries.yellow().bold().to_string(),
+            _ => tries.bold().to_string(),
+        };
+        println!(
+            "{:<16} {:<16} {} {:>6} {:>11} {:>13}",
+            aid.to_string(),
+            label,
+            tries,
+            or_unknown(counters.atc),
+            or_unknown(counters.last_online_atc),
+            or_unknown(counters.since_online())
+        );
+    }
+}
+
+fn render_transaction(terminal: &Terminal, tx: &terminal::Transaction) {
+    println!(
+        "┏╸{} {}",
+        "EMV".italic(),
+        cardinal::money::Amount::new(terminal.amount as i64, terminal.config.currency)
+    );
+    println!(" ┠─┬╴{}", tr("Decision Path"));
+    for step in tx.path.iter() {
+        println!(" ┃ ├─╴{:?}: {}", step.stage, step.detail);
+    }
+    println!(" ┃ ╵");
+    if let Some(oda) = tx.oda.as_ref() {
+        let outcome = match &oda.outcome {
+            terminal::OdaOutcome::Passed => oda.outcome.green().to_string(),
+            terminal::OdaOutcome::Failed(_) => oda.outcome.red().to_string(),
+            terminal::OdaOutcome::Skipped(_) => oda.outcome.yellow().to_string(),
+        };
+        println!(
+            " ┠─╴{}: {} {}",
+            tr("Offline Data Authentication"),
+            oda.method.bold(),
+            outcome
+        );
+    }
+    if let Some(coverage) = tx.coverage.as_ref() {
+        println!(" ┠─┬╴{} ({})", tr("Cryptogram would cover"), coverage.dol);
+        for field in coverage.fields.iter() {
+            println!(
+                " ┃ ├─╴{:X} {}: {}",
+                field.tag,
+                field.name.unwrap_or("?"),
+                field.value
+            );
+        }
+        println!(" ┃ ╵");
+    }
+}
+
+fn render_mag_stripe(aid: &[u8], ms: &MagStripe) {
+    println!(" ┠─┬╴{}", tr("Mag-Stripe Mode"));
+    if let Some(track2) = ms.track2.as_ref() {
+        println!(" ┃ ├┬╴{}", tr("Track 2"));
+        println!(" ┃ │├─╴PAN: {}", track2.pan);
+        match track2.expiry() {
+            Some(expiry) => println!(" ┃ │├─╴{}: {}", tr("Expiry"), expiry),
+            None => println!(" ┃ │├─╴{}: {}??", tr("Expiry"), track2.expiry),
+        }
+        println!(" ┃ │├─╴{}: {}", tr("Service Code"), track2.service_code);
+        println!(
+            " ┃ │├─╴{}: {}",
+            tr("Discretionary Data"),
+            track2.discretionary
+        );
+        println!(" ┃ │╵");
+    }
+    if let Some(track1) = ms.track1.as_ref() {
+        println!(" ┃ ├─╴{}: {:?}", tr("Track 1"), track1);
+    }
+    if let Some(name) = ms.cardholder_name.as_ref() {
+        println!(" ┃ ├─╴{}: {:?}", tr("Cardholder Name"), name);
+    }
+    if let Some(atc) = ms.atc {
+        println!(" ┃ ├─╴{}: {}", tr("Application Transaction Counter"), atc);
+    }
+    if let Some(raw) = ms.iad.as_ref() {
+        render_iad(raw, decode_iad(aid, Some(raw)).as_ref());
+    }
+    for (track, cvc3, pcvc3, punatc, natc) in [
+        (1, ms.cvc3.0, ms.pcvc3.0, ms.punatc.0, ms.natc.0),
+        (2, ms.cvc3.1, ms.pcvc3.1, ms.punatc.1, ms.natc.1),
+    ] {
+        if let Some(cvc3) = cvc3 {
+            println!(" ┃ ├─╴CVC3 ({} {}): {:04X}", tr("track"), track, cvc3);
+        }
+        if let (Some(pcvc3), Some(punatc), Some(natc)) = (pcvc3, punatc, natc) {
+            println!(
+                " ┃ ├─╴{} ({} {}): PCVC3 {:X}, PUNATC {:X}, NATC {}",
+                tr("CVC3 positions"),
+                tr("track"),
+                track,
+                pcvc3,
+                punatc,
+                natc
+            );
+        }
+    }
+    println!(" ┃ ╵");
+}
+
+fn render(selected: &Selected) {
+    println!("┏╸{}", "EMV".italic());
+    probe::render_emv_application(&selected.aid, &[], &selected.application);
+
+    if let Some(opts) = selected.processing_options.as_ref() {
+        println!(" ┠─┬╴{}", tr("Processing Options"));
+        println!(
+            " ┃ ├─╴{}: {:04X}",
+            tr("Application Interchange Profile"),
+            opts.aip
+        );
+        println!(" ┃ ├┬╴{}", tr("Application File Locator"));
+        for entry in opts.afl.iter() {
+            println!(
+                " ┃ │├─╴SFI {}: {} {}-{} ({} {})",
+                entry.sfi,
+                tr("records"),
+                entry.first,
+                entry.last,
+                entry.offline_auth,
+                tr("for offline auth")
+            );
+        }
+        println!(" ┃ │╵");
+        println!(" ┃ ╵");
+    }
+    if let Some(ms) = selected.mag_stripe.as_ref() {
+        render_mag_stripe(&selected.aid, ms);
+    }
+
+    for record in selected.records.iter() {
+        println!();
+        println!(
+            "{}",
+            format!("SFI {}, {} {}", record.sfi, tr("record"), record.num).bold()
+        );
+        crate::read::print_tlv(&record.data);
+    }
+}
@@ -0,0 +1,55 @@
+use crate::Result;
+use anyhow::{bail, Context as _};
+use cardinal::export::Dump;
+use cardinal::CardTransport;
+use std::path::Path;
+use tracing::{trace_span, warn};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Flipper Zero `.nfc` file.
+    Flipper,
+    /// Proxmark3 JSON dump, as `hf mf dump` (or `hf mfu dump`) writes.
+    ProxmarkJson,
+    /// Proxmark3 binary dump.
+    ProxmarkBin,
+}
+
+impl Format {
+    /// Guesses the format from a file extension.
+    fn guess(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "nfc" => Some(Self::Flipper),
+            "json" => Some(Self::ProxmarkJson),
+            "bin" => Some(Self::ProxmarkBin),
+            _ => None,
+        }
+    }
+}
+
+/// Dumps the card, and writes it to `out`.
+pub fn export(card: &mut impl CardTransport, format: Option<Format>, out: &Path) -> Result<()> {
+    let span = trace_span!("export");
+    let _enter = span.enter();
+
+    let Some(format) = format.or_else(|| Format::guess(out)) else {
+        bail!(
+            "can't tell what format {} is meant to be; use --format",
+            out.display()
+        );
+    };
+    let dump = Dump::read(card)?;
+    if let Dump::Felica { blocks, .. } = &dump {
+        if blocks.is_empty() {
+            warn!("Only FeliCa Lite-S blocks can be exported; this is just the IDm and PMm");
+        }
+    }
+    let data = match format {
+        Format::Flipper => dump.to_flipper().into_bytes(),
+        Format::ProxmarkJson => serde_json::to_vec_pretty(&dump.to_proxmark_json())?,
+        Format::ProxmarkBin => dump.to_proxmark_bin(),
+    };
+    std::fs::write(out, data).with_context(|| format!("couldn't write {}", out.display()))?;
+    eprintln!("Exported to {}", out.display());
+    Ok(())
+}
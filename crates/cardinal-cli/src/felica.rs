@@ -0,0 +1,209 @@
+use crate::probe::{self, OutputFormat};
+use crate::probe_felica;
+use crate::Result;
+use anyhow::{bail, Context};
+use cardinal::felica::{self, cybernet};
+use cardinal::money::{Amount, Currency};
+use cardinal::probe::felica::{read_blocks, system_idm, FelicaBlock};
+use cardinal::{transit, CardTransport};
+use owo_colors::OwoColorize;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use tracing::{debug, trace_span, warn};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum FelicaCommand {
+    /// Read blocks from one Service, with Read Without Encryption; much quicker than a probe.
+    Read {
+        /// System code, as hex (eg. 0003). (Default: the first System.)
+        #[arg(long, value_parser = parse_code)]
+        system: Option<u16>,
+
+        /// Service code, as hex (eg. 090F).
+        #[arg(long, value_parser = parse_code)]
+        service: u16,
+
+        /// Block number, or an inclusive range (eg. 0..19). (Default: all of them.)
+        #[arg(long, value_parser = parse_range)]
+        blocks: Option<RangeInclusive<u16>>,
+
+        /// Also decode the blocks as something.
+        #[arg(short, long, value_enum)]
+        decode: Option<Decode>,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+
+    /// Show a Japanese transit (CyberNet: Suica, PASMO, ICOCA...) card's history, or export it
+    /// for a spreadsheet or accounting tool.
+    History {
+        /// Export format, instead of a table.
+        #[arg(short, long, value_enum)]
+        export: Option<Export>,
+
+        /// Station names, as lines of `line,station,name` (codes in hex), eg. from a station
+        /// code list. (Default: just the codes.)
+        #[arg(long)]
+        stations: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Export {
+    /// CSV, with a header.
+    Csv,
+    /// JSON, as an array of transactions.
+    Json,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decode {
+    /// Japanese transit (CyberNet) history records, eg. service 090F.
+    History,
+    /// Japanese transit (CyberNet) balance, laid out like the attribute block (service 008B).
+    /// Purses in `felica::purse` have their balance shown without this.
+    Balance,
+}
+
+fn parse_code(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|err| format!("{}: {}", s, err))
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let num = |v: &str| v.parse::<u16>().map_err(|err| format!("{}: {}", v, err));
+    match s.split_once("..") {
+        Some((start, end)) => Ok(num(start)?..=num(end.trim_start_matches('='))?),
+        None => num(s).map(|n| n..=n),
+    }
+}
+
+impl FelicaCommand {
+    /// `batch` is `--batch-size`: how many blocks to read per command.
+    pub fn exec(&self, card: &mut impl CardTransport, batch: Option<u16>) -> Result<()> {
+        match self {
+            Self::Read {
+                system,
+                service,
+                blocks,
+                decode,
+                output,
+            } => {
+                let nums = blocks.clone().unwrap_or(0..=u16::MAX);
+                let blocks = read(card, *system, *service, nums.clone(), batch)?;
+                if blocks.len() < nums.len() && *nums.end() != u16::MAX {
+                    warn!(read = blocks.len(), "The card ran out of blocks");
+                }
+                match output {
+                    OutputFormat::Text => render(&blocks, *system, *service, *decode),
+                    output => probe::write_structured(&blocks, *output)?,
+                }
+                Ok(())
+            }
+            Self::History { export, stations } => {
+                let stations = match stations {
+                    Some(path) => transit::Stations::load(path)
+                        .with_context(|| format!("couldn't load {}", path.display()))?,
+                    None => transit::Stations::default(),
+                };
+                let blocks = read(
+                    card,
+                    Some(felica::SystemCode::Suica.into()),
+                    cybernet::HISTORY_SERVICE,
+                    0..=u16::MAX,
+                    batch,
+                )?;
+                let records =
+                    transit::parse_history(blocks.iter().filter_map(|b| b.data.as_deref()));
+                let txs = transit::transactions(&records, &stations);
+                match export {
+                    Some(Export::Csv) => print!("{}", transit::to_csv(&txs)),
+                    Some(Export::Json) => probe::write_structured(&txs, OutputFormat::Json)?,
+                    None => render_history(&txs),
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read(
+    card: &mut impl CardTransport,
+    system: Option<u16>,
+    service: u16,
+    nums: RangeInclusive<u16>,
+    batch: Option<u16>,
+) -> Result<Vec<FelicaBlock>> {
+    let span = trace_span!("felica read", service);
+    let _enter = span.enter();
+
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+
+    let cid = cardinal::probe::pcsc_get_data(card, wbuf, rbuf, 0x00)
+        .context("couldn't get the card's IDm")?;
+    let idm0 = felica::cid_to_idm(cid)?;
+    let idm = match system {
+        Some(code) => {
+            debug!(code, "Looking for system...");
+            let Some(idm) = system_idm(card, wbuf, rbuf, idm0, code.into())? else {
+                bail!("the card has no system {:04X}", code);
+            };
+            idm
+        }
+        None => idm0,
+    };
+    let known = card.response_limits().felica_blocks;
+    let mut batch = (batch.map(usize::from).or(known)).unwrap_or(felica::MAX_READ_BLOCKS);
+    Ok(read_blocks(
+        card, wbuf, rbuf, idm, service, nums, &mut batch,
+    )?)
+}
+
+fn render(blocks: &[FelicaBlock], system: Option<u16>, service: u16, decode: Option<Decode>) {
+    let system = system.map(felica::SystemCode::from);
+    for block in blocks.iter() {
+        let data = block.data.as_deref().unwrap_or_default();
+        print!("{:02X}  {}", block.num, hex::encode_upper(data));
+        print!("{}", probe_felica::balance(system, service, block));
+        match decode {
+            Some(Decode::History) => match cybernet::HistoryRecord::parse(data) {
+                Ok((_, rec)) => print!(
+                    "  {} {:?} {:?} {:#}",
+                    rec.date.format("%Y-%m-%d"),
+                    rec.terminal_type,
+                    rec.tx_type,
+                    rec.balance
+                ),
+                Err(err) => print!("  {}", format!("({})", err).dimmed()),
+            },
+            // Known purses have their balance shown anyway; this is for ones that aren't.
+            Some(Decode::Balance) => match cybernet::parse_balance(data) {
+                Ok((_, balance)) => print!("  {:#}", balance),
+                Err(err) => print!("  {}", format!("({})", err).dimmed()),
+            },
+            None => {}
+        }
+        println!();
+    }
+}
+
+fn render_history(txs: &[transit::Transaction]) {
+    for tx in txs.iter() {
+        let amount = tx.amount.map(|v| Amount::new(v, Currency::JPY));
+        let journey = match (tx.entry.as_deref(), tx.exit.as_deref()) {
+            (Some(entry), Some(exit)) => format!("{} → {}", entry, exit),
+            _ => tx.terminal.clone(),
+        };
+        println!(
+            "[{}] {}  {:<20} {:>8}  {:>8}  {}",
+            tx.seq,
+            tx.date,
+            tx.kind,
+            amount.map(|v| format!("{:#}", v)).unwrap_or_default(),
+            format!("{:#}", Amount::new(tx.balance, Currency::JPY)).bold(),
+            journey
+        );
+    }
+}
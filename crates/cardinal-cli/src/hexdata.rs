@@ -0,0 +1,59 @@
+use anyhow::{Context, Error};
+use cardinal::{heuristics, hexvec};
+use owo_colors::OwoColorize;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Binary data from the command line, so every command takes payloads the same way:
+///
+/// - `@path/to/file.bin` reads a file, as raw bytes.
+/// - `-` reads stdin, as raw bytes.
+/// - Anything else is hex: `00A40400`, `00 A4 04 00`, `00:a4:04:00` and `0x00,0xA4` all work.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HexData(pub Vec<u8>);
+
+impl std::ops::Deref for HexData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for HexData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for HexData {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            let mut buf = vec![];
+            std::io::stdin()
+                .read_to_end(&mut buf)
+                .context("couldn't read stdin")?;
+            Ok(Self(buf))
+        } else if let Some(path) = s.strip_prefix('@') {
+            Ok(Self(
+                std::fs::read(path).with_context(|| format!("couldn't read {}", path))?,
+            ))
+        } else {
+            Ok(Self(hexvec::parse(s).context("invalid hex")?))
+        }
+    }
+}
+
+/// Hex dump, followed by a guess at what the data is (if we have one).
+pub fn annotated(data: &[u8]) -> String {
+    match heuristics::guess(data) {
+        Some(guess) => format!(
+            "{} {}",
+            hex::encode_upper(data),
+            format!("({})", guess).dimmed()
+        ),
+        None => hex::encode_upper(data),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::Result;
+use anyhow::{bail, Context};
+use cardinal::probe::{summary::Summary, Probe};
+use cardinal::uid::CardUid;
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tracing::{debug, error, trace_span};
+
+/// How long a webhook gets to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Things to do when a card turns up, for `cardinal watch`.
+#[derive(clap::Args, Debug, Default)]
+pub struct HookArgs {
+    /// Run this (with `sh -c`) for every card, with the event as JSON on stdin, and
+    /// $CARDINAL_READER and $CARDINAL_UID set.
+    #[arg(long)]
+    exec: Option<String>,
+
+    /// POST the event as JSON to this URL, for every card.
+    #[arg(long)]
+    webhook: Option<String>,
+
+    /// Only for cards that talk this (eg. felica, emv, 14443), as in the probe's summary;
+    /// any part of the name will do. Can be given more than once. (Default: every card.)
+    #[arg(long = "on", value_name = "TECHNOLOGY")]
+    only: Vec<String>,
+}
+
+/// What hooks are told about.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    /// Always "inserted", for now.
+    pub event: &'static str,
+    pub reader: &'a str,
+    pub uid: &'a CardUid,
+    pub summary: &'a Summary,
+}
+
+impl HookArgs {
+    /// Are there any hooks to run?
+    pub fn any(&self) -> bool {
+        self.exec.is_some() || self.webhook.is_some()
+    }
+
+    /// Does the filter let this card through?
+    fn matches(&self, summary: &Summary) -> bool {
+        self.only.is_empty()
+            || (self.only.iter()).any(|only| {
+                let only = only.to_lowercase();
+                (summary.technologies.iter()).any(|tech| tech.to_lowercase().contains(&only))
+            })
+    }
+
+    /// Runs every hook for a card, and logs (rather than returns) what went wrong, so one
+    /// bad hook doesn't stop the watch.
+    pub fn fire(&self, reader: &str, report: &Probe) {
+        let span = trace_span!("hooks", reader);
+        let _enter = span.enter();
+
+        if !self.matches(&report.summary) {
+            debug!(technologies = ?report.summary.technologies, "Filtered out");
+            return;
+        }
+        let event = Event {
+            event: "inserted",
+            reader,
+            uid: &report.uid,
+            summary: &report.summary,
+        };
+        if let Some(cmd) = self.exec.as_deref() {
+            if let Err(err) = exec(cmd, &event) {
+                error!("--exec hook failed: {:#}", err);
+            }
+        }
+        if let Some(url) = self.webhook.as_deref() {
+            if let Err(err) = webhook(url, &event) {
+                error!("--webhook failed: {:#}", err);
+            }
+        }
+    }
+}
+
+fn exec(cmd: &str, event: &Event) -> Result<()> {
+    debug!(cmd, "Running hook...");
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("CARDINAL_READER", event.reader)
+        .env("CARDINAL_UID", event.uid.to_string())
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("couldn't run it")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its stdin is fine; don't fail on a broken pipe.
+        let _ = stdin.write_all(&serde_json::to_vec(event)?);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{}", status);
+    }
+    Ok(())
+}
+
+fn webhook(url: &str, event: &Event) -> Result<()> {
+    debug!(url, "Calling webhook...");
+    ureq::post(url)
+        .config()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .send_json(event)?;
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! A tiny message catalog for the human-readable bits of the CLI.
+//!
+//! Messages are looked up by their English text, gettext-style, so a missing translation
+//! just falls back to English. Decoded card data (labels, preferred names, etc.) is never
+//! translated; it comes with its own charset and language handling.
+
+use std::sync::OnceLock;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    /// English.
+    En,
+    /// Japanese (日本語).
+    Ja,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Sets the output language, either from --lang or from the locale environment variables.
+pub fn init(lang: Option<Lang>) {
+    let _ = LANG.set(lang.unwrap_or_else(lang_from_env));
+}
+
+fn lang_from_env() -> Lang {
+    // Same precedence as setlocale(3): LC_ALL > LC_MESSAGES > LANG.
+    for var in ["LC_ALL", "LC_MESSAGES", "LANG"] {
+        match std::env::var(var) {
+            Ok(v) if v.starts_with("ja") => return Lang::Ja,
+            Ok(v) if !v.is_empty() => return Lang::En,
+            _ => {}
+        }
+    }
+    Lang::En
+}
+
+/// Translates a message into the current language.
+pub fn tr(msg: &'static str) -> &'static str {
+    let catalog = match LANG.get().copied().unwrap_or(Lang::En) {
+        Lang::En => return msg,
+        Lang::Ja => JA,
+    };
+    catalog
+        .iter()
+        .find(|(k, _)| *k == msg)
+        .map(|(_, v)| *v)
+        .unwrap_or(msg)
+}
+
+const JA: &[(&str, &str)] = &[
+    // Section headers.
+    ("SUMMARY", "概要"),
+    ("READER STATE", "リーダー状態"),
+    ("PROTOCOL", "プロトコル"),
+    ("Protocol", "プロトコル"),
+    ("Clock", "クロック"),
+    ("Extra guard time", "追加ガードタイム"),
+    ("IDENTIFYING CARD", "カード識別"),
+    ("Card ID", "カードID"),
+    ("Known as", "既知のカード"),
+    ("CAPABILITIES", "機能"),
+    ("CROSS-REFERENCES", "相互参照"),
+    ("CHANGES ON CARD", "カードの変更点"),
+    ("matches", "一致"),
+    ("MISMATCH", "不一致"),
+    ("WARNINGS", "警告"),
+    // cardinal check.
+    ("PASS", "合格"),
+    ("FAIL", "不合格"),
+    // cardinal bench-reader.
+    ("size", "サイズ"),
+    ("baseline", "基準値"),
+    ("REGRESSION", "性能低下"),
+    // ATR.
+    ("Mode", "モード"),
+    ("historical bytes", "ヒストリカルバイト"),
+    ("voltage modifier", "電圧補正"),
+    ("timing modifier", "タイミング補正"),
+    ("extra guard time", "追加ガードタイム"),
+    ("protocol", "プロトコル"),
+    ("mode negotiation", "モードネゴシエーション"),
+    ("leading edge time", "立ち上がり時間"),
+    ("Error detection code", "誤り検出符号"),
+    ("INVALID!", "無効!"),
+    ("services", "サービス"),
+    ("Selection by Full DF Name", "完全DF名による選択"),
+    ("Selection by Partial DF Name", "部分DF名による選択"),
+    ("Data available in DIR file", "DIRファイルにデータあり"),
+    ("Data available in ATR file", "ATRファイルにデータあり"),
+    ("File I/O by READ BINARY", "READ BINARYによるファイルI/O"),
+    ("RESERVED", "予約済み"),
+    ("initial access", "初期アクセス"),
+    ("provider", "プロバイダ"),
+    ("standard", "規格"),
+    ("card name", "カード名"),
+    ("reserved for future use", "将来のための予約"),
+    ("pre-issuing data", "発行前データ"),
+    ("card capabilities", "カード機能"),
+    ("selection methods", "選択方式"),
+    ("Command chaining", "コマンドチェイニング"),
+    ("Extended Lc and Le fields", "拡張Lc・Leフィールド"),
+    (
+        "Extended length information in EF.ATR",
+        "EF.ATRに拡張長情報あり",
+    ),
+    ("logical channels", "論理チャネル数"),
+    // Capabilities.
+    ("Protocols", "プロトコル"),
+    ("Standards", "規格"),
+    ("Extended length", "拡張長"),
+    ("Logical channels", "論理チャネル数"),
+    ("Applications", "アプリケーション数"),
+    ("Secure messaging", "セキュアメッセージング"),
+    ("status", "ステータス"),
+    ("unknown data", "不明なデータ"),
+    ("checksum", "チェックサム"),
+    ("none", "なし"),
+    ("ATR warning", "ATR警告"),
+    // ATS.
+    (
+        "the ATR above is made up by the reader",
+        "上記のATRはリーダーが生成したもの",
+    ),
+    (
+        "reader only gave out the historical bytes",
+        "リーダーはヒストリカルバイトのみ返した",
+    ),
+    ("max frame size", "最大フレームサイズ"),
+    ("bit rates", "ビットレート"),
+    ("same both ways", "双方向同一"),
+    ("no historical bytes", "ヒストリカルバイトなし"),
+    // EMV.
+    ("Directory", "ディレクトリ"),
+    ("Proximity Directory", "近接ディレクトリ"),
+    ("SFI for Elementary File", "EFのSFI"),
+    ("Preferred Language(s)", "優先言語"),
+    ("Charset", "文字コード"),
+    ("Record", "レコード"),
+    ("Application", "アプリケーション"),
+    ("Application ID", "アプリケーションID"),
+    ("Label", "ラベル"),
+    ("Preferred Name", "優先名"),
+    ("Priority", "優先度"),
+    ("needs confirmation", "要確認"),
+    (
+        "Directory Discretionary Template",
+        "ディレクトリ任意テンプレート",
+    ),
+    (
+        "Data Objects for Processing Options",
+        "処理オプション用データオブジェクト",
+    ),
+    ("FCI Issuer Discretionary Data", "FCI発行者任意データ"),
+    ("Log Entries", "ログエントリ"),
+    ("records", "件"),
+    ("Application Capabilities Info", "アプリケーション機能情報"),
+    ("Card Number + Sequence", "カード番号+シーケンス"),
+    ("Form Factor Indicator", "フォームファクター識別子"),
+    ("Third Party Data", "サードパーティデータ"),
+    ("country", "国"),
+    ("device type", "デバイス種別"),
+    ("Unknown", "不明"),
+    (
+        "Application Selection Proprietary Data",
+        "アプリケーション選択独自データ",
+    ),
+    ("Path", "パス"),
+    ("Discretionary Data", "任意データ"),
+    ("Application Cryptogram", "アプリケーション暗号"),
+    ("requested", "要求"),
+    ("Cryptogram Information Data", "暗号情報データ"),
+    (
+        "Application Transaction Counter",
+        "アプリケーション取引カウンタ",
+    ),
+    ("Issuer Application Data", "発行者アプリケーションデータ"),
+    ("Derivation Key Index", "鍵導出インデックス"),
+    ("Cryptogram Version Number", "暗号バージョン番号"),
+    ("Card Verification Results", "カード検証結果"),
+    ("PIN tries", "PIN残り回数"),
+    ("Last online", "前回オンライン"),
+    ("Since online", "オンライン以降"),
+    ("Mag-Stripe Mode", "磁気ストライプモード"),
+    ("Track 1", "トラック1"),
+    ("Track 2", "トラック2"),
+    ("track", "トラック"),
+    ("Expiry", "有効期限"),
+    ("Service Code", "サービスコード"),
+    ("Cardholder Name", "カード所有者名"),
+    ("CVC3 positions", "CVC3の位置"),
+    // FeliCa.
+    ("System", "システム"),
+    ("Area", "エリア"),
+    ("Service", "サービス"),
+    ("Balance", "残高"),
+    ("authenticated, key ", "認証必要、鍵 "),
+    ("ROM Type", "ROM種別"),
+    ("IC Type", "IC種別"),
+    // Calypso.
+    ("Revision", "リビジョン"),
+    ("File", "ファイル"),
+    ("empty", "空"),
+    ("Environment", "環境"),
+    ("Event Log", "イベントログ"),
+    ("Contracts", "契約"),
+    ("Contract List", "契約リスト"),
+    ("Counters", "カウンタ"),
+    ("Special Events", "特別イベント"),
+    // SIM.
+    ("Service Provider Name", "サービスプロバイダ名"),
+    ("Display Condition", "表示条件"),
+    ("Operation Mode", "動作モード"),
+    ("normal", "通常"),
+    ("normal, with specific facilities", "通常（特定機能あり）"),
+    ("maintenance (off-line)", "保守（オフライン）"),
+    ("cell test", "セルテスト"),
+    ("type approval", "型式認証"),
+    (
+        "type approval, with specific facilities",
+        "型式認証（特定機能あり）",
+    ),
+    ("unknown", "不明"),
+    ("Files", "ファイル"),
+    ("bytes", "バイト"),
+];
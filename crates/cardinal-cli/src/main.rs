@@ -0,0 +1,975 @@
+mod atr;
+mod bench;
+mod calypso;
+mod check;
+mod emv;
+mod export;
+mod felica;
+mod hexdata;
+mod hook;
+mod i18n;
+mod probe;
+mod probe_calypso;
+mod probe_felica;
+mod progress;
+mod read;
+mod reader_cmd;
+mod sim;
+
+use anyhow::{bail, Context as _, Result};
+use cardinal::probe::Probe;
+use cardinal::reader_quirks::ReaderQuirks;
+use cardinal::report::{Kind, Report};
+use cardinal::retry::RetryPolicy;
+use cardinal::transports::reader::{self, CardEvent};
+use cardinal::transports::session::Session;
+use cardinal::transports::{tcp, trace, Interface};
+use cardinal::CardTransport as _;
+use clap::Parser as _;
+use owo_colors::OwoColorize;
+use pcsc::Context;
+use tracing::{debug, error, trace, trace_span, warn};
+
+#[derive(clap::Parser, Debug)]
+pub struct Args {
+    /// Increase log level.
+    #[arg(short, long, action=clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Decrease log level.
+    #[arg(short, long, action=clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Use a specific reader: by name, index (from list-readers), substring or glob.
+    #[arg(short, long)]
+    reader: Option<String>,
+
+    /// How to talk to the card: pcsc, tcp:host[:port] (see `serve`), nfc[:connstring] (with
+    /// libnfc support), or pn532:/dev/ttyUSB0 (with PN532 support).
+    #[arg(short, long, default_value_t)]
+    interface: Interface,
+
+    /// Which protocol to ask a PCSC reader for, for contact cards that do both.
+    #[arg(long, value_enum, default_value_t = ProtocolArg::Any)]
+    protocol: ProtocolArg,
+
+    /// Language for human-readable output. (Default: from $LANG.)
+    #[arg(long, value_enum)]
+    lang: Option<i18n::Lang>,
+
+    /// Force a specific standard.
+    #[arg(short = 'S', long, value_enum)]
+    force_standard: Option<cardinal::atr::Standard>,
+
+    /// When probing FeliCa cards that won't list their services, scan for them instead.
+    /// Slow, but useful for reverse engineering unknown cards.
+    #[arg(long)]
+    felica_scan: bool,
+
+    /// FeliCa blocks to read per command. (Default: as many as the card will take, which
+    /// is worked out by trial.)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..=cardinal::felica::MAX_READ_BLOCKS as i64))]
+    batch_size: Option<u16>,
+
+    /// Extra ATR databases, in pcsc-tools' smartcard_list.txt format; pcsc-tools' own is
+    /// used automatically, if it's installed.
+    #[arg(long)]
+    atr_db: Vec<std::path::PathBuf>,
+
+    /// Retry commands that fail with a comms error (or a status word readers use for one)
+    /// this many times.
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+
+    /// Give up on a command (and its retries) after this many milliseconds.
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Command.
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolArg {
+    /// T=0, character-oriented.
+    T0,
+    /// T=1, block-oriented.
+    T1,
+    /// Whichever the reader picks; usually T=1, if the card does it.
+    Any,
+}
+
+impl From<ProtocolArg> for pcsc::Protocols {
+    fn from(v: ProtocolArg) -> Self {
+        match v {
+            ProtocolArg::T0 => pcsc::Protocols::T0,
+            ProtocolArg::T1 => pcsc::Protocols::T1,
+            ProtocolArg::Any => pcsc::Protocols::ANY,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Probe connected card.
+    Probe {
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+
+        /// Probe cards in all readers at once, instead of just one.
+        #[arg(short, long)]
+        all_readers: bool,
+
+        /// Record everything sent to and from the card to a trace file, which can be
+        /// replayed in tests; see `cardinal::transports::trace`.
+        #[arg(long)]
+        record: Option<std::path::PathBuf>,
+
+        /// Probe the card this many times (power-cycling it in between, which is as good as
+        /// a new tap), and report what changed: random UIDs, counters, and so on.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+    },
+
+    /// Dump everything readable off the connected card for forensics, without sending
+    /// anything that could change it, and write a manifest of SHA-256 hashes; see
+    /// `cardinal::acquire`.
+    Acquire {
+        /// Directory to save the acquisition in; mustn't exist yet.
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+
+        /// Case (or incident) number, for the manifest.
+        #[arg(long)]
+        case_id: Option<String>,
+
+        /// Exhibit/evidence number of the card, for the manifest.
+        #[arg(long)]
+        evidence_id: Option<String>,
+
+        /// Who's doing the acquisition, for the manifest.
+        #[arg(long)]
+        operator: Option<String>,
+
+        /// Anything else for the manifest.
+        #[arg(long)]
+        notes: Option<String>,
+
+        /// Sign the manifest with this minisign secret key (needs `minisign` installed).
+        #[arg(long)]
+        minisign_key: Option<std::path::PathBuf>,
+    },
+
+    /// Dump a MIFARE Classic, Ultralight or FeliCa Lite-S card's memory, for a Flipper Zero
+    /// or a Proxmark3.
+    Export {
+        /// What to write. (Default: guess from the file extension; .nfc, .json or .bin.)
+        #[arg(short, long, value_enum)]
+        format: Option<export::Format>,
+
+        /// File to write the dump to.
+        out: std::path::PathBuf,
+    },
+
+    /// Wait for cards to be inserted or removed; with --exec or --webhook, tell something
+    /// else about each one (eg. for home automation, or attendance logs).
+    Watch {
+        /// Probe each card as it's inserted.
+        #[arg(short, long)]
+        probe: bool,
+
+        /// Output format, for --probe.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+
+        #[command(flatten)]
+        hooks: hook::HookArgs,
+    },
+
+    /// Read records or files from the connected card.
+    Read {
+        #[command(subcommand)]
+        what: read::ReadCommand,
+    },
+
+    /// Poke at a single EMV application, without running the whole probe. (Careful: --gpo
+    /// counts as a transaction on most cards, and bumps the transaction counter.)
+    Emv {
+        #[command(subcommand)]
+        what: emv::EmvCommand,
+    },
+
+    /// Read from a FeliCa card's Services, without probing the whole thing.
+    Felica {
+        #[command(subcommand)]
+        what: felica::FelicaCommand,
+    },
+
+    /// Dump a Calypso (European transit) card's files, without probing the whole thing.
+    Calypso {
+        #[command(subcommand)]
+        what: calypso::CalypsoCommand,
+    },
+
+    /// Browse a SIM card (or UICC), without probing the whole thing.
+    Sim {
+        #[command(subcommand)]
+        what: sim::SimCommand,
+    },
+
+    /// Compare two probe reports of the same card (or a saved one with the connected card),
+    /// and show what changed: fields, FeliCa blocks and balances. Handy for seeing what a
+    /// ride or a purchase did to a card.
+    Diff {
+        /// Report from before, eg. from `cardinal probe --output=json`.
+        before: std::path::PathBuf,
+
+        /// Report from after; if not given, the connected card is probed instead.
+        after: Option<std::path::PathBuf>,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+    },
+
+    /// Work with ATRs that aren't attached to a card, eg. from logs.
+    Atr {
+        #[command(subcommand)]
+        what: atr::AtrCommand,
+    },
+
+    /// Send raw APDUs to the connected card, and print the responses.
+    Apdu {
+        /// APDUs, as hex, @file or - for stdin.
+        #[arg(required = true)]
+        apdus: Vec<hexdata::HexData>,
+    },
+
+    /// Check the connected card against a spec, and fail if it doesn't match.
+    Check {
+        /// Spec of what should be on the card, as YAML; see `cardinal::check` for the format.
+        #[arg(short, long)]
+        profile: std::path::PathBuf,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+
+        /// Keep checking cards as they're inserted, and light up the reader's LED (on
+        /// readers that have one) green or red; an acceptance station for issuance lines.
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Save each card's result here, as a report named by time, card ID and result.
+        #[arg(long)]
+        archive: Option<std::path::PathBuf>,
+    },
+
+    /// List connected readers.
+    ListReaders,
+
+    /// Talk to the reader itself, rather than the card; no card needed.
+    Reader {
+        #[command(subcommand)]
+        what: reader_cmd::ReaderCommand,
+    },
+
+    /// Time round trips to the connected card, to catch reader or driver regressions.
+    /// Only sends harmless commands; see `cardinal::bench`.
+    BenchReader {
+        /// Sizes of command data to try, in bytes; 0 only goes as far as the reader.
+        #[arg(short, long, value_delimiter = ',')]
+        #[arg(value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(0..=255))]
+        #[arg(default_values_t = cardinal::bench::DEFAULT_SIZES.to_vec())]
+        sizes: Vec<usize>,
+
+        /// Round trips per size.
+        #[arg(short = 'n', long, default_value_t = 100)]
+        iterations: usize,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+
+        /// Compare with the last benchmark of the same reader in here, and save this one.
+        #[arg(long)]
+        archive: Option<std::path::PathBuf>,
+    },
+
+    /// Share the connected card over TCP, for `--interface tcp:host:port` on another machine.
+    Serve {
+        /// Address to listen on.
+        #[arg(short, long, default_value = "127.0.0.1:35963")]
+        listen: String,
+
+        /// Connect to a vpcd (vsmartcard's virtual reader) instead of listening, so the card
+        /// shows up in PCSC on that machine.
+        #[arg(long)]
+        vpcd: Option<String>,
+    },
+
+    /// Pretend to be a card, described by a profile, in a vpcd (vsmartcard) virtual reader.
+    Emulate {
+        /// Card profile, as TOML or JSON; see `cardinal::emulate` for the format.
+        profile: std::path::PathBuf,
+
+        /// Address of the vpcd to plug into.
+        #[arg(long, default_value = "localhost:35963")]
+        vpcd: String,
+    },
+
+    /// Probe an APDU log from another tool, as if it were a card that only knows the answers
+    /// to what's in the log.
+    Import {
+        /// What wrote the log: opensc, pcsc-spy or proxmark.
+        #[arg(short, long)]
+        format: trace::import::Format,
+
+        /// The log; - for stdin.
+        file: std::path::PathBuf,
+
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: probe::OutputFormat,
+
+        /// Save it as a cardinal trace too, like `probe --record` writes.
+        #[arg(long)]
+        save: Option<std::path::PathBuf>,
+    },
+}
+
+impl Command {
+    pub fn run(&self, args: &Args) -> Result<()> {
+        // These need things that only PCSC has: reader lists and card events.
+        if !args.interface.is_pcsc()
+            && matches!(
+                self,
+                Self::Probe {
+                    all_readers: true,
+                    ..
+                } | Self::Watch { .. }
+                    | Self::Check { watch: true, .. }
+                    | Self::ListReaders
+                    | Self::Reader { .. }
+            )
+        {
+            bail!("this command only works with --interface=pcsc");
+        }
+        match self {
+            Self::Probe {
+                output,
+                all_readers,
+                record,
+                repeat: 1,
+            } => self.probe(args, *output, *all_readers, record.as_deref()),
+            Self::Probe {
+                output,
+                all_readers,
+                record,
+                repeat,
+            } => {
+                if *all_readers || record.is_some() {
+                    bail!("--repeat only works with one card, and without --record");
+                }
+                self.probe_repeat(args, *output, *repeat)
+            }
+            Self::Acquire {
+                out,
+                case_id,
+                evidence_id,
+                operator,
+                notes,
+                minisign_key,
+            } => {
+                let case = cardinal::acquire::Case {
+                    case_id: case_id.clone(),
+                    evidence_id: evidence_id.clone(),
+                    operator: operator.clone(),
+                    notes: notes.clone(),
+                };
+                self.acquire(args, out, case, minisign_key.as_deref())
+            }
+            Self::Export { format, out } => export::export(&mut open_card(args)?, *format, out),
+            Self::Watch {
+                probe,
+                output,
+                hooks,
+            } => self.watch(args, *probe, *output, hooks),
+            Self::Read { what } => self.read(args, what),
+            Self::Emv { what } => what.exec(&mut open_card(args)?),
+            Self::Felica { what } => what.exec(&mut open_card(args)?, args.batch_size),
+            Self::Calypso { what } => what.exec(&mut open_card(args)?),
+            Self::Sim { what } => what.exec(&mut open_card(args)?),
+            Self::Diff {
+                before,
+                after,
+                output,
+            } => self.diff(args, before, after.as_deref(), *output),
+            Self::Atr { what } => what.exec(args),
+            Self::Apdu { apdus } => self.apdu(args, apdus),
+            Self::Check {
+                profile,
+                output,
+                watch,
+                archive,
+            } => self.check(args, profile, *output, *watch, archive.as_deref()),
+            Self::ListReaders => self.list_readers(args),
+            Self::Reader { what } => what.exec(args),
+            Self::BenchReader {
+                sizes,
+                iterations,
+                output,
+                archive,
+            } => self.bench_reader(args, sizes, *iterations, *output, archive.as_deref()),
+            Self::Serve { listen, vpcd } => self.serve(args, listen, vpcd.as_deref()),
+            Self::Emulate { profile, vpcd } => self.emulate(args, profile, vpcd),
+            Self::Import {
+                format,
+                file,
+                output,
+                save,
+            } => self.import(args, *format, file, *output, save.as_deref()),
+        }
+    }
+
+    fn probe(
+        &self,
+        args: &Args,
+        output: probe::OutputFormat,
+        all_readers: bool,
+        record: Option<&std::path::Path>,
+    ) -> Result<()> {
+        let span = trace_span!("probe");
+        let _enter = span.enter();
+
+        if all_readers && args.reader.is_some() {
+            bail!("--reader and --all-readers don't make sense together");
+        }
+        if all_readers && record.is_some() {
+            bail!("--record only works with one card at a time");
+        }
+
+        if all_readers {
+            debug!("Probing all readers...");
+            let ctx = Context::establish(pcsc::Scope::User)?;
+            return probe::probe_all(args, &ctx, output);
+        }
+        // Dumping a big card takes a while; if it slips out of the reader, wait for it, and if
+        // something else sharing the reader resets it, reconnect. Either way, whatever the
+        // probe was reading starts over from its SELECT (see cardinal::probe); FeliCa dumps
+        // just carry on from the block they stopped at.
+        let card = open_card(args)?.retry_on_reset(true).cache_selects(true);
+        let mut card = reader::Reattach::new(card, || wait_for_card_again(args));
+        debug!("Probing card...");
+        let Some(path) = record else {
+            let result = probe::probe(args, &mut card, output);
+            debug!(stats = ?card.inner.cache_stats(), "SELECT cache");
+            return result;
+        };
+        // Write the trace even if the probe fails; that's when you want it the most.
+        let mut recorder = trace::Recorder::new(card);
+        let result = probe::probe(args, &mut recorder, output);
+        debug!(stats = ?recorder.inner.inner.cache_stats(), "SELECT cache");
+        std::fs::write(path, recorder.trace.to_string())
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+        eprintln!(
+            "Recorded {} exchanges to {}",
+            recorder.trace.exchanges.len(),
+            path.display()
+        );
+        result
+    }
+
+    fn acquire(
+        &self,
+        args: &Args,
+        out: &std::path::Path,
+        case: cardinal::acquire::Case,
+        minisign_key: Option<&std::path::Path>,
+    ) -> Result<()> {
+        let span = trace_span!("acquire");
+        let _enter = span.enter();
+
+        if out.exists() {
+            bail!("{} already exists", out.display());
+        }
+        let mut card = open_card(args)?;
+        let progress = progress::Progress::new();
+        let opts = cardinal::probe::Options {
+            observer: Some(std::sync::Arc::new(progress.clone())),
+            ..probe::options(args)?
+        };
+        let acq = cardinal::acquire::Acquisition::run(&mut card, case, &opts);
+        progress.finish();
+        let acq = acq?;
+        let manifest = acq
+            .save(out)
+            .with_context(|| format!("couldn't save to {}", out.display()))?;
+
+        // Same format as sha256sum, so `sha256sum -c` can check it.
+        for file in acq.manifest.files.iter() {
+            println!("{}  {}", file.sha256, out.join(&file.name).display());
+        }
+        eprintln!(
+            "Acquired {} blobs; manifest: {}",
+            acq.manifest.blobs.len(),
+            manifest.display()
+        );
+
+        if let Some(key) = minisign_key {
+            let status = std::process::Command::new("minisign")
+                .arg("-S")
+                .arg("-s")
+                .arg(key)
+                .arg("-m")
+                .arg(&manifest)
+                .status()
+                .context("couldn't run minisign")?;
+            if !status.success() {
+                bail!("minisign failed ({}); the manifest is unsigned", status);
+            }
+            eprintln!("Signed: {}.minisig", manifest.display());
+        }
+        Ok(())
+    }
+
+    fn watch(
+        &self,
+        args: &Args,
+        probe: bool,
+        output: probe::OutputFormat,
+        hooks: &hook::HookArgs,
+    ) -> Result<()> {
+        let span = trace_span!("watch");
+        let _enter = span.enter();
+
+        let opts = probe::options(args)?;
+        let ctx = Context::establish(pcsc::Scope::User)?;
+        let only = match args.reader.as_deref() {
+            Some(query) => Some(
+                cardinal::transports::reader::match_reader(&ctx.list_readers_owned()?, query)?
+                    .to_owned(),
+            ),
+            None => None,
+        };
+        let mut watcher = reader::CardWatcher::new(&ctx)?;
+        loop {
+            let (name, inserted) = match watcher.wait(&ctx, None)? {
+                Some(CardEvent::Inserted(name)) => (name, true),
+                Some(CardEvent::Removed(name)) => (name, false),
+                None => continue,
+            };
+            if only.as_ref().is_some_and(|only| *only != name) {
+                debug!(?name, "Ignoring event from another reader");
+                continue;
+            }
+
+            // Status goes to stderr, so --probe --output=json still gives you clean JSON.
+            if !inserted {
+                eprintln!("Card removed: {}", name.to_string_lossy());
+                continue;
+            }
+            eprintln!("Card inserted: {}", name.to_string_lossy());
+            // Hooks need a probe too, for the summary; it's only printed if you asked.
+            if probe || hooks.any() {
+                // It might've gone again already; that's no reason to stop watching.
+                let card = match ctx.connect(&name, pcsc::ShareMode::Shared, args.protocol.into()) {
+                    Ok(card) => card,
+                    Err(err) => {
+                        error!("Couldn't connect to card: {}", err);
+                        continue;
+                    }
+                };
+                let mut card = session(args, card);
+                let report = match Probe::run_with(&mut card, &opts) {
+                    Ok(report) => report,
+                    Err(err) => {
+                        error!("Couldn't probe card: {:#}", err);
+                        continue;
+                    }
+                };
+                if probe {
+                    probe::print(&report, output)?;
+                }
+                hooks.fire(&name.to_string_lossy(), &report);
+            }
+        }
+    }
+
+    fn read(&self, args: &Args, what: &read::ReadCommand) -> Result<()> {
+        let span = trace_span!("read");
+        let _enter = span.enter();
+
+        let mut card = open_card(args)?;
+        what.exec(&mut card)
+    }
+
+    fn probe_repeat(&self, args: &Args, output: probe::OutputFormat, repeat: u32) -> Result<()> {
+        let span = trace_span!("probe_repeat");
+        let _enter = span.enter();
+
+        // No SELECT cache here; the whole point is to see what the card says every time.
+        let mut card = open_card(args)?;
+        let opts = probe::options(args)?;
+        let mut taps = vec![];
+        for i in 0..repeat {
+            if i > 0 {
+                debug!("Power-cycling card...");
+                card.reset(cardinal::transport::Reset::Cold)?;
+            }
+            eprintln!("Probing ({}/{})...", i + 1, repeat);
+            taps.push(cardinal::diff::Tap::run(&mut card, &opts)?);
+        }
+        let diff = cardinal::diff::Differential::compare(&taps);
+        match output {
+            probe::OutputFormat::Text => probe::render_differential(&diff),
+            _ => probe::write_structured(&Report::new(Kind::Differential, &diff), output)?,
+        }
+        Ok(())
+    }
+
+    fn diff(
+        &self,
+        args: &Args,
+        before: &std::path::Path,
+        after: Option<&std::path::Path>,
+        output: probe::OutputFormat,
+    ) -> Result<()> {
+        let span = trace_span!("diff");
+        let _enter = span.enter();
+
+        let load = |path: &std::path::Path| -> Result<serde_json::Value> {
+            let s = std::fs::read_to_string(path)
+                .with_context(|| format!("couldn't read {}", path.display()))?;
+            let report = cardinal::report::load(&s)
+                .with_context(|| format!("couldn't load {}", path.display()))?;
+            if report["kind"] != "probe" {
+                bail!("{} isn't a probe report", path.display());
+            }
+            Ok(report["data"].clone())
+        };
+        let before = load(before)?;
+        let after = match after {
+            Some(path) => load(path)?,
+            None => {
+                let mut card = open_card(args)?;
+                serde_json::to_value(Probe::run_with(&mut card, &probe::options(args)?)?)?
+            }
+        };
+        let diff = cardinal::diff::DumpDiff::compare(&before, &after);
+        match output {
+            probe::OutputFormat::Text => probe::render_dump_diff(&diff),
+            _ => probe::write_structured(&Report::new(Kind::Diff, &diff), output)?,
+        }
+        Ok(())
+    }
+
+    fn apdu(&self, args: &Args, apdus: &[hexdata::HexData]) -> Result<()> {
+        let span = trace_span!("apdu");
+        let _enter = span.enter();
+
+        let mut card = open_card(args)?;
+        let mut rbuf = [0; pcsc::MAX_BUFFER_SIZE];
+        for apdu in apdus {
+            println!(">> {}", hex::encode_upper(apdu));
+            let rsp = card.transmit(apdu, &mut rbuf)?;
+            // Annotate the data, not the status word.
+            let data = &rsp[..rsp.len().saturating_sub(2)];
+            match cardinal::heuristics::guess(data) {
+                Some(guess) => println!(
+                    "<< {} {}",
+                    hex::encode_upper(rsp),
+                    format!("({})", guess).dimmed()
+                ),
+                None => println!("<< {}", hex::encode_upper(rsp)),
+            }
+        }
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        args: &Args,
+        profile: &std::path::Path,
+        output: probe::OutputFormat,
+        watch: bool,
+        archive: Option<&std::path::Path>,
+    ) -> Result<()> {
+        let span = trace_span!("check");
+        let _enter = span.enter();
+
+        let spec = cardinal::check::Spec::load(profile)
+            .with_context(|| format!("couldn't load {}", profile.display()))?;
+        if let Some(archive) = archive {
+            std::fs::create_dir_all(archive)
+                .with_context(|| format!("couldn't create {}", archive.display()))?;
+        }
+        if !watch {
+            let mut card = open_card(args)?;
+            let violations = check::check(&mut card, &spec, output, archive)?;
+            // Exit non-zero, so pipelines can use this as a gate.
+            if !violations.is_empty() {
+                bail!("{} violation(s) of {}", violations.len(), profile.display());
+            }
+            return Ok(());
+        }
+
+        let ctx = Context::establish(pcsc::Scope::User)?;
+        let only = match args.reader.as_deref() {
+            Some(query) => Some(
+                cardinal::transports::reader::match_reader(&ctx.list_readers_owned()?, query)?
+                    .to_owned(),
+            ),
+            None => None,
+        };
+        let (mut passed, mut failed) = (0, 0);
+        eprintln!("Waiting for cards...");
+        let mut watcher = reader::CardWatcher::new(&ctx)?;
+        loop {
+            let name = match watcher.wait(&ctx, None)? {
+                Some(CardEvent::Inserted(name)) => name,
+                _ => continue,
+            };
+            if only.as_ref().is_some_and(|only| *only != name) {
+                debug!(?name, "Ignoring card in another reader");
+                continue;
+            }
+            eprintln!("Card inserted: {}", name.to_string_lossy());
+            // Pulled out too soon; that's a failure, not the end of the line.
+            let card = match ctx.connect(&name, pcsc::ShareMode::Shared, args.protocol.into()) {
+                Ok(card) => card,
+                Err(err) => {
+                    error!("Couldn't connect to card: {}", err);
+                    failed += 1;
+                    eprintln!("Passed: {}, failed: {}", passed, failed);
+                    continue;
+                }
+            };
+            let mut card = session(args, card);
+            match check::check(&mut card, &spec, output, archive) {
+                Ok(violations) if violations.is_empty() => passed += 1,
+                Ok(_) => failed += 1,
+                Err(err) => {
+                    error!("Couldn't check card: {:#}", err);
+                    failed += 1;
+                }
+            }
+            eprintln!("Passed: {}, failed: {}", passed, failed);
+        }
+    }
+
+    fn bench_reader(
+        &self,
+        args: &Args,
+        sizes: &[usize],
+        iterations: usize,
+        output: probe::OutputFormat,
+        archive: Option<&std::path::Path>,
+    ) -> Result<()> {
+        let span = trace_span!("bench_reader");
+        let _enter = span.enter();
+
+        if let Some(archive) = archive {
+            std::fs::create_dir_all(archive)
+                .with_context(|| format!("couldn't create {}", archive.display()))?;
+        }
+        let mut card = open_card(args)?;
+        let regressions = bench::bench_reader(&mut card, sizes, iterations, output, archive)?;
+        // Exit non-zero, so this can run on a schedule and complain.
+        if !regressions.is_empty() {
+            bail!("{} size(s) got slower than the baseline", regressions.len());
+        }
+        Ok(())
+    }
+
+    fn serve(&self, args: &Args, listen: &str, vpcd: Option<&str>) -> Result<()> {
+        let span = trace_span!("serve");
+        let _enter = span.enter();
+
+        // The card is (re)opened for each client, so you can swap cards in between.
+        let serve = |stream: std::net::TcpStream| -> Result<()> {
+            let mut card = open_card(args)?.cache_selects(true);
+            let atr = card.atr().unwrap_or_else(|err| {
+                warn!("Couldn't get ATR, sending an empty one: {}", err);
+                vec![]
+            });
+            let result = tcp::serve(&mut card, &atr, stream);
+            if let Some(stats) = card.cache_stats() {
+                eprintln!("SELECT cache: {}", stats);
+            }
+            result?;
+            Ok(())
+        };
+
+        if let Some(addr) = vpcd {
+            let addr = if addr.contains(':') {
+                addr.to_owned()
+            } else {
+                format!("{}:{}", addr, tcp::DEFAULT_PORT)
+            };
+            eprintln!("Connecting to vpcd at {}...", addr);
+            return serve(std::net::TcpStream::connect(&addr)?);
+        }
+
+        let listener = std::net::TcpListener::bind(listen)?;
+        eprintln!("Listening on {}...", listener.local_addr()?);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let peer = stream.peer_addr()?;
+            eprintln!("Client connected: {}", peer);
+            match serve(stream) {
+                Ok(()) => eprintln!("Client disconnected: {}", peer),
+                Err(err) => error!("Error serving {}: {:#}", peer, err),
+            }
+        }
+        Ok(())
+    }
+
+    fn emulate(&self, _args: &Args, profile: &std::path::Path, vpcd: &str) -> Result<()> {
+        let span = trace_span!("emulate");
+        let _enter = span.enter();
+
+        let profile = cardinal::emulate::Profile::load(profile)
+            .with_context(|| format!("couldn't load {}", profile.display()))?;
+        let atr = profile.atr.clone();
+        let mut card = cardinal::emulate::EmulatedCard::new(profile);
+
+        eprintln!("Connecting to vpcd at {}...", vpcd);
+        let stream = std::net::TcpStream::connect(vpcd)
+            .with_context(|| format!("couldn't connect to vpcd at {}", vpcd))?;
+        eprintln!("Card inserted; Ctrl+C to remove it.");
+        tcp::serve(&mut card, &atr, stream)?;
+        Ok(())
+    }
+
+    fn import(
+        &self,
+        args: &Args,
+        format: trace::import::Format,
+        file: &std::path::Path,
+        output: probe::OutputFormat,
+        save: Option<&std::path::Path>,
+    ) -> Result<()> {
+        let span = trace_span!("import");
+        let _enter = span.enter();
+
+        let text = if file.as_os_str() == "-" {
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+                .context("couldn't read stdin")?;
+            text
+        } else {
+            std::fs::read_to_string(file)
+                .with_context(|| format!("couldn't read {}", file.display()))?
+        };
+        let mut trace = trace::import::parse(format, &text)?;
+        eprintln!("Imported {} exchanges", trace.exchanges.len());
+        if let Some(path) = save {
+            std::fs::write(path, trace.to_string())
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+            eprintln!("Saved the trace to {}", path.display());
+        }
+
+        // The probe won't go anywhere without an ATR, and only some logs have one; make up the
+        // one a PCSC reader would for a contactless card that doesn't say anything about itself.
+        if trace.atr.is_none() {
+            warn!("No ATR in the log; pretending it's a contactless card");
+            trace.atr = Some(cardinal::ats::synthesize_atr(&[0x01])?);
+        }
+        let profile = cardinal::emulate::Profile::from_trace(&trace);
+        let mut card = cardinal::emulate::EmulatedCard::new(profile);
+        probe::probe(args, &mut card, output)
+    }
+
+    fn list_readers(&self, _args: &Args) -> Result<()> {
+        let span = trace_span!("list_readers");
+        let _enter = span.enter();
+
+        let ctx = Context::establish(pcsc::Scope::User)?;
+        let mut readers_buf = [0; 2048];
+        for (i, name) in ctx.list_readers(&mut readers_buf)?.enumerate() {
+            println!("{}: {}", i, name.to_str()?);
+        }
+        Ok(())
+    }
+}
+
+/// Opens the card, as the --interface and --reader flags say.
+fn open_card(args: &Args) -> cardinal::Result<Session<Box<dyn cardinal::CardTransport>>> {
+    let card = args
+        .interface
+        .open_with(args.reader.as_deref(), args.protocol.into())?;
+    Ok(session(args, card))
+}
+
+/// Wraps a card in a [Session], with the --retries and --timeout flags' [RetryPolicy], and
+/// whatever we can tell about the reader.
+fn session<T: cardinal::CardTransport>(args: &Args, mut card: T) -> Session<T> {
+    let session = match ReaderQuirks::detect(&mut card) {
+        Some(quirks) => Session::new(card).with_reader_quirks(quirks),
+        None => Session::new(card),
+    };
+    if args.retries == 0 && args.timeout.is_none() {
+        return session;
+    }
+    session.with_retry_policy(RetryPolicy {
+        timeout: args.timeout.map(std::time::Duration::from_millis),
+        ..RetryPolicy::new(args.retries)
+    })
+}
+
+/// Tells the user the card's gone, and waits for it to come back: in a PCSC reader, until
+/// one is put in the same reader; elsewhere, until they press Enter.
+fn wait_for_card_again(args: &Args) -> cardinal::Result<()> {
+    eprintln!("{}", "Card removed; put it back to carry on...".yellow());
+    if !args.interface.is_pcsc() {
+        std::io::stdin()
+            .read_line(&mut String::new())
+            .map_err(|err| cardinal::Error::Transport("stdin", err.to_string()))?;
+        return Ok(());
+    }
+
+    let ctx = Context::establish(pcsc::Scope::User)?;
+    let names = ctx.list_readers_owned()?;
+    let name = match args.reader.as_deref() {
+        Some(query) => reader::match_reader(&names, query)?,
+        None => names.first().ok_or(pcsc::Error::NoReadersAvailable)?,
+    };
+    let mut watcher = reader::CardWatcher::new(&ctx)?;
+    // It might be back already, in which case there's no insertion left to wait for.
+    if watcher.is_present(name) {
+        return Ok(());
+    }
+    loop {
+        match watcher.wait(&ctx, None)? {
+            Some(CardEvent::Inserted(inserted)) if inserted == *name => return Ok(()),
+            _ => continue,
+        }
+    }
+}
+
+fn init_logging(args: &Args) {
+    // Logs go to stderr, so they don't end up in the middle of --output=json.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_target(false)
+        .with_max_level(match 2 + args.verbose - args.quiet {
+            0 => tracing::Level::ERROR,
+            1 => tracing::Level::WARN,
+            2 => tracing::Level::INFO,
+            3 => tracing::Level::DEBUG,
+            4.. => tracing::Level::TRACE,
+        })
+        .init();
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args);
+    i18n::init(args.lang);
+    trace!(?args, "Starting up");
+    args.command.run(&args)
+}
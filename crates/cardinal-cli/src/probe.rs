@@ -0,0 +1,1098 @@
+use crate::hexdata::annotated;
+use crate::i18n::tr;
+use crate::progress::Progress;
+use crate::Result;
+use anyhow::{bail, Context as _};
+use cardinal::CardTransport;
+use cardinal::{
+    atr, ats,
+    diff::{Change, Differential, DumpDiff},
+    eid, emv, heuristics, iso7816,
+    pcsc_attrs::Link,
+    probe::{capabilities::Capabilities, EmvDirectory, EmvProbe, EmvRecord, Options, Probe},
+    report::{Kind, Report},
+    transports::reader::ContextExt,
+    uid::CardUid,
+};
+use indicatif::MultiProgress;
+use owo_colors::{colors, OwoColorize};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tap::TapOptional;
+use tracing::{error, trace_span, warn};
+
+/// Output format for `cardinal probe`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable, colourful tree.
+    #[default]
+    Text,
+    /// JSON, for scripts and diffing.
+    Json,
+    /// YAML, for humans who like scripts.
+    Yaml,
+}
+
+pub fn probe(
+    args: &crate::Args,
+    card: &mut impl CardTransport,
+    output: OutputFormat,
+) -> Result<()> {
+    let progress = Progress::new();
+    let opts = Options {
+        observer: Some(Arc::new(progress.clone())),
+        ..options(args)?
+    };
+    let report = Probe::run_with(card, &opts);
+    progress.finish();
+    print(&report?, output)
+}
+
+/// Prints a probe result, in whichever format.
+pub fn print(report: &Probe, output: OutputFormat) -> Result<()> {
+    match output {
+        OutputFormat::Text => render(report),
+        _ => write_structured(&Report::new(Kind::Probe, report), output)?,
+    }
+    Ok(())
+}
+
+/// Where pcsc-tools keeps its ATR database: as packaged, and as `update_smartcard_list`
+/// downloads it (relative to $HOME).
+const PCSC_TOOLS_ATR_DB: &str = "/usr/share/pcsc/smartcard_list.txt";
+const PCSC_TOOLS_ATR_DB_UPDATED: &str = ".cache/smartcard_list.txt";
+
+pub fn options(args: &crate::Args) -> Result<Options> {
+    let mut atr_db = atr::db::Database::bundled();
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from);
+    let pcsc_tools = [
+        Some(PCSC_TOOLS_ATR_DB.into()),
+        home.map(|h| h.join(PCSC_TOOLS_ATR_DB_UPDATED)),
+    ];
+    for path in pcsc_tools.into_iter().flatten().filter(|p| p.exists()) {
+        match atr::db::Database::load(&path) {
+            Ok(db) => atr_db.extend(db),
+            Err(err) => warn!(path = %path.display(), "Couldn't load ATR database: {}", err),
+        }
+    }
+    for path in args.atr_db.iter() {
+        atr_db.extend(
+            atr::db::Database::load(path)
+                .with_context(|| format!("couldn't load ATR database: {}", path.display()))?,
+        );
+    }
+    Ok(Options {
+        standard: args.force_standard,
+        felica_scan: args.felica_scan,
+        felica_batch: args.batch_size.map(usize::from),
+        atr_db: Some(atr_db),
+        observer: None,
+    })
+}
+
+/// Probes every card in every reader at the same time, then prints them grouped by reader.
+pub fn probe_all(args: &crate::Args, ctx: &pcsc::Context, output: OutputFormat) -> Result<()> {
+    let cards = ctx
+        .cards_with(args.protocol.into())?
+        .collect::<cardinal::Result<Vec<_>>>()?;
+    if cards.is_empty() {
+        bail!("No cards present in any reader");
+    }
+
+    let opts = options(args)?;
+    let multi = MultiProgress::new();
+    let results: Vec<_> = std::thread::scope(|s| {
+        let handles: Vec<_> = cards
+            .into_iter()
+            .map(|(name, mut card)| {
+                let name = name.to_string_lossy().into_owned();
+                let progress = Progress::in_multi(&multi, &name);
+                let opts = Options {
+                    observer: Some(Arc::new(progress.clone())),
+                    ..opts.clone()
+                };
+                s.spawn(move || {
+                    let span = trace_span!("reader", name = ?name);
+                    let _enter = span.enter();
+                    let result = Probe::run_with(&mut card, &opts);
+                    progress.finish();
+                    (name, result)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("probe thread panicked"))
+            .collect()
+    });
+
+    let mut reports = BTreeMap::new();
+    for (name, result) in results {
+        match result {
+            Ok(report) => {
+                reports.insert(name, report);
+            }
+            Err(err) => error!(reader = name, "Couldn't probe card: {}", err),
+        }
+    }
+    match output {
+        OutputFormat::Text => {
+            for (name, report) in reports.iter() {
+                println!("======== {} ========", name.bold());
+                render(report);
+                println!();
+            }
+        }
+        _ => write_structured(&Report::new(Kind::ProbeMulti, &reports), output)?,
+    }
+    Ok(())
+}
+
+pub fn write_structured<T: Serialize>(v: &T, output: OutputFormat) -> Result<()> {
+    match output {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), v)?;
+            println!();
+        }
+        OutputFormat::Yaml => serde_yaml::to_writer(std::io::stdout().lock(), v)?,
+        OutputFormat::Text => unreachable!("text output isn't structured"),
+    }
+    Ok(())
+}
+
+/// Renders what the reader and card negotiated; whatever the reader didn't say is left out.
+fn render_link(link: &Link) {
+    if let Some(protocol) = link.protocol.as_ref() {
+        println!("{}: {}", tr("Protocol"), protocol.bold());
+    }
+    if let Some(clock) = link.clock {
+        println!("{}: {} kHz", tr("Clock"), clock);
+    }
+    match (link.f, link.d, link.bit_rate()) {
+        (Some(f), Some(d), Some(rate)) => println!("F/D: {}/{} ({} bps)", f, d, rate),
+        (Some(f), Some(d), None) => println!("F/D: {}/{}", f, d),
+        _ => {}
+    }
+    if let Some(n) = link.n {
+        println!("{}: {} etu", tr("Extra guard time"), n);
+    }
+    if let Some(w) = link.w {
+        println!("WI: {}", w);
+    }
+    match (link.ifsc, link.ifsd) {
+        (None, None) => {}
+        (ifsc, ifsd) => println!(
+            "IFSC/IFSD: {}/{}",
+            ifsc.map(|v| v.to_string()).unwrap_or("?".into()),
+            ifsd.map(|v| v.to_string()).unwrap_or("?".into())
+        ),
+    }
+    match (link.bwt, link.cwt) {
+        (None, None) => {}
+        (bwt, cwt) => println!(
+            "BWI/CWI: {}/{}",
+            bwt.map(|v| v.to_string()).unwrap_or("?".into()),
+            cwt.map(|v| v.to_string()).unwrap_or("?".into())
+        ),
+    }
+    if let Some(edc) = link.edc {
+        println!("EDC: {}", edc);
+    }
+}
+
+/// Renders a probe result as a colourful tree.
+pub fn render(report: &Probe) {
+    // The short version first, for people who don't want to read the rest.
+    println!("-------------- {} --------------", tr("SUMMARY"));
+    println!("{}", report.summary.bold());
+
+    println!("------------ {} ------------", tr("READER STATE"));
+    for attr in report.reader.iter() {
+        match &attr.decoded {
+            Some(v) => println!("{} => {}", attr.attribute, v),
+            None => println!("{} => {}", attr.attribute, hex::encode_upper(&attr.value)),
+        }
+    }
+    if let Some(link) = report.link.as_ref() {
+        println!("------------- {} -------------", tr("PROTOCOL"));
+        render_link(link);
+    }
+
+    println!("---------- {} ----------", tr("IDENTIFYING CARD"));
+    match &report.uid {
+        CardUid::None => {}
+        CardUid::Iccid(iccid) => println!("ICCID: {}", iccid),
+        uid => println!("{}: {}", tr("Card ID"), uid),
+    }
+    render_atr_probe(&report.atr, &report.known_as, &report.atr_warnings);
+    if let Some(ats) = report.ats.as_ref() {
+        render_ats(ats);
+    }
+
+    if let Some(felica) = report.felica.as_ref() {
+        println!("--------------- FeliCa ---------------");
+        crate::probe_felica::render_felica(felica);
+    } else if let Some(emv) = report.emv.as_ref() {
+        println!("-------------- ISO 14443 -------------");
+        render_emv(emv);
+    } else if let Some(calypso) = report.calypso.as_ref() {
+        println!("--------------- Calypso --------------");
+        crate::probe_calypso::render_calypso(calypso);
+    } else if report.eid.is_some() || report.ef_dir.is_some() {
+        println!("-------------- ISO 7816 --------------");
+        report.eid.as_ref().tap_some(|apps| render_eid(apps));
+        report.ef_dir.as_ref().tap_some(|apps| render_ef_dir(apps));
+    }
+
+    println!("------------ {} ------------", tr("CAPABILITIES"));
+    render_capabilities(&report.capabilities, report.ef_atr.as_deref());
+
+    if !report.xrefs.is_empty() {
+        println!("---------- {} ----------", tr("CROSS-REFERENCES"));
+        for xref in report.xrefs.iter() {
+            if xref.consistent {
+                println!("{}: {}", xref.what, tr("matches").green());
+            } else {
+                println!("{}: {}", xref.what, tr("MISMATCH").red().bold());
+            }
+            for s in xref.sightings.iter() {
+                println!("  {}: {}", s.source, hex::encode_upper(&s.value));
+            }
+        }
+    }
+    if !report.warnings.is_empty() {
+        println!("-------------- {} -------------", tr("WARNINGS"));
+        for warning in report.warnings.iter() {
+            println!("{}", warning.yellow());
+        }
+    }
+}
+
+/// Renders an ATR, what it's known as, and what was wrong with it.
+pub fn render_atr_probe(atr: &atr::ATR, known_as: &[String], warnings: &[String]) {
+    render_atr(atr);
+    if let Some((first, rest)) = known_as.split_first() {
+        println!("{}: {}", tr("Known as"), first.bold());
+        for also in rest {
+            println!("  {}", also);
+        }
+    }
+    for warning in warnings.iter() {
+        println!("{}: {}", tr("ATR warning").yellow(), warning);
+    }
+}
+
+/// Renders an ISO 14443-4 ATS; the T=CL parameters the reader's made-up ATR leaves out.
+fn render_ats(ats: &ats::ATS) {
+    println!(
+        "┏╸{}╺ (T=CL; {})",
+        "ATS".italic(),
+        tr("the ATR above is made up by the reader")
+    );
+    match &ats.format {
+        None => println!("┠─╴{}", tr("reader only gave out the historical bytes")),
+        Some(format) => {
+            println!(
+                "┠─╴FSCI {:X} — {}: {}",
+                format.fsci.fg::<ATRColorTXn>(),
+                tr("max frame size"),
+                format.fsc
+            );
+            if let Some(ta) = &format.ta {
+                let rates = |v: &[u16]| (v.iter().map(u16::to_string)).collect::<Vec<_>>();
+                println!(
+                    "┠─╴TA1 — {}: kbit/s ↑{} ↓{}{}",
+                    tr("bit rates"),
+                    rates(&ta.reader_to_card).join("/"),
+                    rates(&ta.card_to_reader).join("/"),
+                    if ta.same_both_ways {
+                        format!(" ({})", tr("same both ways"))
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+            if let Some(tb) = &format.tb {
+                println!(
+                    "┠─╴TB1 — FWT {:.1}ms (FWI {}), SFGT {}µs (SFGI {})",
+                    tb.fwt_us as f64 / 1000.0,
+                    tb.fwi,
+                    tb.sfgt_us,
+                    tb.sfgi
+                );
+            }
+            if let Some(tc) = &format.tc {
+                let yn = |v: bool| {
+                    if v {
+                        "✓".green().to_string()
+                    } else {
+                        "✗".red().to_string()
+                    }
+                };
+                println!("┠─╴TC1 — NAD {}, CID {}", yn(tc.nad), yn(tc.cid));
+            }
+        }
+    }
+    match &ats.historical_bytes {
+        Some(hb) => println!(
+            "┗─╴HB {} — {}",
+            hex::encode_upper(hb.to_bytes()).fg::<ATRColorHB>(),
+            tr("historical bytes")
+        ),
+        None => println!("┗─╴{}", tr("no historical bytes")),
+    }
+}
+
+type ATRColorTS = colors::Cyan;
+type ATRColorTDnMask = colors::Yellow;
+type ATRColorTDnProtocol = colors::Green;
+type ATRColorTXn = colors::Yellow;
+type ATRColorHB = colors::Magenta;
+type ATRColorTck = colors::Cyan;
+
+/// Renders what changed between repeated probes.
+pub fn render_differential(diff: &Differential) {
+    let change = |c: Change| match c {
+        Change::Counting => tr("counting").yellow().to_string(),
+        Change::Varying => tr("varying").to_string(),
+    };
+    println!(
+        "-------------- {} --------------",
+        tr("CHANGES ACROSS TAPS")
+    );
+    if diff.fields.is_empty() && diff.exchanges.is_empty() {
+        println!("{}", tr("Nothing changed."));
+    }
+    for field in diff.fields.iter() {
+        let values = (field.values.iter()).map(|v| v.to_string());
+        println!(
+            "{} [{}]: {}",
+            field.path.bold(),
+            change(field.change),
+            values.collect::<Vec<_>>().join(" → ")
+        );
+    }
+    for ex in diff.exchanges.iter() {
+        println!(
+            "#{} {} [{}]",
+            ex.index,
+            ex.command.to_string().bold(),
+            change(ex.change)
+        );
+        for rsp in ex.responses.iter() {
+            println!("  → {}", rsp);
+        }
+    }
+    if let Some(index) = diff.diverged_at {
+        warn!(
+            index,
+            "Probes sent different commands from here on; not compared"
+        );
+    }
+    if diff.fields.iter().any(|f| f.change == Change::Counting)
+        || diff.exchanges.iter().any(|e| e.change == Change::Counting)
+    {
+        println!(
+            "{}",
+            tr("Something counted up between taps: the probe may be changing the card.").yellow()
+        );
+    }
+    for capdu in diff.mutating.iter() {
+        println!(
+            "{}: {}",
+            tr("Sent a command that could change the card").red(),
+            capdu
+        );
+    }
+}
+
+/// Renders what changed between two probes of the same card.
+pub fn render_dump_diff(diff: &DumpDiff) {
+    let or_none = |v: Option<String>| v.unwrap_or_else(|| tr("none").dimmed().to_string());
+    println!("------------ {} ------------", tr("CHANGES ON CARD"));
+    if diff.is_empty() {
+        println!("{}", tr("Nothing changed."));
+    }
+    for balance in diff.balances.iter() {
+        println!(
+            "{} ({}): {} → {}",
+            tr("Balance"),
+            balance.purse.bold(),
+            or_none(balance.before.map(|a| a.to_string())),
+            or_none(balance.after.map(|a| a.to_string())).bold()
+        );
+    }
+    for field in diff.fields.iter() {
+        println!("{}: {} → {}", field.path.bold(), field.before, field.after);
+    }
+    for block in diff.blocks.iter() {
+        println!(
+            "{} {:04X} #{}:",
+            block.system.bold(),
+            block.service,
+            block.num
+        );
+        println!(
+            "  - {}",
+            or_none(block.before.as_ref().map(|v| v.to_string()))
+        );
+        println!(
+            "  + {}",
+            or_none(block.after.as_ref().map(|v| v.to_string()))
+        );
+    }
+}
+
+/// Renders an ISO 7816 ATR (Answer-to-Reset).
+fn render_atr(atr: &atr::ATR) {
+    // Colourise the raw ATR.
+    print!(
+        "┏╸{}╺ {:02X} {:01X}{:01X}",
+        "ATR".italic(),
+        u8::from(atr.ts).fg::<ATRColorTS>(),
+        atr.t0.tx1.fg::<ATRColorTDnMask>(),
+        atr.t0.k.fg::<ATRColorHB>(),
+    );
+    for txn in [atr.tx1, atr.tx2, atr.tx3] {
+        if txn.ta.is_some() || txn.tb.is_some() || txn.tc.is_some() || txn.td.is_some() {
+            print!(" ");
+        }
+        for ob in [txn.ta, txn.tb, txn.tc] {
+            if let Some(b) = ob {
+                print!("{:02X}", b.fg::<ATRColorTXn>());
+            }
+        }
+        if let Some(td) = txn.td {
+            print!(
+                "{:01X}{:01X}",
+                td.txn.fg::<ATRColorTDnMask>(),
+                u8::from(td.protocol).fg::<ATRColorTDnProtocol>(),
+            );
+        }
+    }
+    if let Some(hb) = atr.historical_bytes.as_ref() {
+        match hb {
+            atr::HistoricalBytes::Status(atr::HistoricalBytesStatus { status, sw1sw2 }) => {
+                print!(" {:02X}", 0x10.fg::<ATRColorHB>());
+                status.tap_some(|v| print!(" {:02X}", v));
+                sw1sw2.tap_some(|v| print!(" {:02X}", v));
+            }
+            atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV { category, raw, .. }) => print!(
+                " {:02X} {}",
+                category.fg::<ATRColorHB>(),
+                hex::encode_upper(raw).fg::<ATRColorHB>()
+            ),
+            atr::HistoricalBytes::Unknown(tag, data) => print!(
+                " {:02X} {}",
+                tag.fg::<ATRColorHB>(),
+                hex::encode_upper(data).fg::<ATRColorHB>()
+            ),
+        }
+    }
+    match atr.tck {
+        Some(tck) => println!(" {:02X}", tck.fg::<ATRColorTck>()),
+        None => println!(),
+    }
+
+    // TS, T0 are always there.
+    println!(
+        "┗┱─╴TS {:02X} — {:?} {}",
+        u8::from(atr.ts).fg::<ATRColorTS>(),
+        atr.ts.fg::<ATRColorTS>(),
+        tr("Mode")
+    );
+    println!(
+        " ┠─╴T0 {:01X}{:01X} — {} {}",
+        atr.t0.tx1.fg::<ATRColorTDnMask>(),
+        atr.t0.k.fg::<ATRColorHB>(),
+        atr.t0.k.fg::<ATRColorHB>(),
+        tr("historical bytes")
+    );
+
+    // Tx1
+    if let Some(v) = atr.tx1.ta {
+        println!(
+            " ┠╴Ta1 {:02X} — {}",
+            v.fg::<ATRColorTXn>(),
+            tr("voltage modifier")
+        );
+    }
+    if let Some(v) = atr.tx1.tb {
+        println!(
+            " ┠╴Tb1 {:02X} — {}",
+            v.fg::<ATRColorTXn>(),
+            tr("timing modifier")
+        );
+    }
+    if let Some(v) = atr.tx1.tc {
+        println!(
+            " ┠╴Tc1 {:02X} — {}",
+            v.fg::<ATRColorTXn>(),
+            tr("extra guard time")
+        );
+    }
+    if let Some(v) = atr.tx1.td {
+        println!(
+            " ┠╴Td1 {:01X}{:01X} — {}: T={}",
+            v.txn.fg::<ATRColorTDnMask>(),
+            u8::from(v.protocol).fg::<ATRColorTDnProtocol>(),
+            tr("protocol"),
+            u8::from(v.protocol).fg::<ATRColorTDnProtocol>(),
+        );
+    }
+
+    // Tx2
+    if let Some(v) = atr.tx2.ta {
+        println!(
+            " ┠╴Ta2 {:02X} — {}",
+            v.fg::<ATRColorTXn>(),
+            tr("mode negotiation")
+        );
+    }
+    if let Some(v) = atr.tx2.tb {
+        println!(
+            " ┠╴Tb2 {:02X} — {}",
+            v.fg::<ATRColorTXn>(),
+            tr("voltage modifier")
+        );
+    }
+    if let Some(v) = atr.tx2.tc {
+        println!(
+            " ┠╴Tc2 {:02X} — {} [T=0]",
+            v.fg::<ATRColorTXn>(),
+            tr("leading edge time")
+        );
+    }
+    if let Some(v) = atr.tx2.td {
+        println!(
+            " ┠╴Td2 {:01X}{:01X} — {}: T={}",
+            v.txn.fg::<ATRColorTDnMask>(),
+            u8::from(v.protocol).fg::<ATRColorTDnProtocol>(),
+            tr("protocol"),
+            u8::from(v.protocol).fg::<ATRColorTDnProtocol>(),
+        );
+    }
+
+    // Tx3
+    if let Some(v) = atr.tx3.ta {
+        println!(" ┠╴Ta3 {:02X} — IFS [T=1]", v.fg::<ATRColorTXn>());
+    }
+    if let Some(v) = atr.tx3.tb {
+        println!(" ┠╴Tb3 {:02X} — CWI [T=1]", v.fg::<ATRColorTXn>());
+    }
+    if let Some(v) = atr.tx3.tc {
+        println!(
+            " ┠╴Tc3 {:02X} — {} [T=1]",
+            v.fg::<ATRColorTXn>(),
+            tr("Error detection code")
+        );
+    }
+    // Td3 should never be present!
+    if let Some(v) = atr.tx3.td {
+        println!(
+            " ┠╴Td3 {:01X}{:01X} — {}: T={} {}",
+            v.txn.red(),
+            u8::from(v.protocol).red(),
+            tr("protocol"),
+            u8::from(v.protocol).fg::<ATRColorTDnProtocol>(),
+            format!("[{}]", tr("INVALID!")).red()
+        );
+    }
+
+    // Historical Bytes - TODO: There's a lot of duplicated magic numbers in here rn.
+    if let Some(hb) = atr.historical_bytes.as_ref() {
+        match hb {
+            atr::HistoricalBytes::Status(atr::HistoricalBytesStatus { status, sw1sw2 }) => {
+                print!(" ┠┬╴HB {:02X}", 0x10.fg::<ATRColorHB>());
+                status.tap_some(|v| print!(" {:02X}", v));
+                sw1sw2.tap_some(|v| print!(" {:02X}", v));
+                println!("");
+
+                print!(" ┃└");
+                status.tap_some(|v| print!(" status: {:02X}", v));
+                sw1sw2.tap_some(|v| print!(" SW1SW2: {:04X}", v));
+            }
+            atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV {
+                category,
+                raw,
+                service_data,
+                initial_access,
+                pre_issuing_data,
+                card_capabilities,
+                status,
+            }) => {
+                println!(
+                    " ┠┬╴HB {:02X} {}",
+                    category.fg::<ATRColorHB>(),
+                    hex::encode_upper(raw).fg::<ATRColorHB>()
+                );
+                println!(" ┃└──┬ {:02X} — TLV", category.fg::<ATRColorHB>());
+                if let Some(v) = service_data {
+                    println!(
+                        " ┃   ├──┬ {:} — {}: {:02X}",
+                        "3X".fg::<ATRColorHB>(),
+                        tr("services"),
+                        v.fg::<ATRColorHB>()
+                    );
+                    if v & 0b1000_0000 > 0 {
+                        println!(
+                            " ┃   │  ├── [1--- ----] — {}",
+                            tr("Selection by Full DF Name")
+                        );
+                    }
+                    if v & 0b0100_0000 > 0 {
+                        println!(
+                            " ┃   │  ├── [-1-- ----] — {}",
+                            tr("Selection by Partial DF Name")
+                        );
+                    }
+                    if v & 0b0010_0000 > 0 {
+                        println!(
+                            " ┃   │  ├── [--1- ----] — {}",
+                            tr("Data available in DIR file")
+                        );
+                    }
+                    if v & 0b0001_0000 > 0 {
+                        println!(
+                            " ┃   │  ├── [---1 ----] — {}",
+                            tr("Data available in ATR file")
+                        );
+                    }
+                    if v & 0b0000_1000 > 0 {
+                        println!(
+                            " ┃   │  ├── [---- 1---] — {}",
+                            tr("File I/O by READ BINARY")
+                        );
+                    }
+                    if v & 0b0000_0100 > 0 {
+                        println!(" ┃   │  ├── [---- -1--] — {}", tr("RESERVED").red());
+                    }
+                    if v & 0b0000_0010 > 0 {
+                        println!(" ┃   │  ├── [---- --1-] — {}", tr("RESERVED").red());
+                    }
+                    if v & 0b0000_0001 > 0 {
+                        println!(" ┃   │  ├── [---- ---1] — {}", tr("RESERVED").red());
+                    }
+                };
+
+                if let Some(ia) = initial_access.as_ref() {
+                    println!(
+                        " ┃   ├──┬ {:} — {}",
+                        "4X".fg::<ATRColorHB>(),
+                        tr("initial access")
+                    );
+
+                    // Provider.
+                    println!(
+                        " ┃   │  ├── {} — {}: {}",
+                        hex::encode_upper(ia.rid.id()).fg::<ATRColorHB>(),
+                        tr("provider"),
+                        ia.rid.fg::<ATRColorHB>()
+                    );
+                    println!(
+                        " ┃   │  ├── {:02X} — {}: {}",
+                        u8::from(ia.standard).fg::<ATRColorHB>(),
+                        tr("standard"),
+                        ia.standard.fg::<ATRColorHB>()
+                    );
+                    println!(
+                        " ┃   │  ├── {:04X} — {}: {}",
+                        u16::from(ia.card_name).fg::<ATRColorHB>(),
+                        tr("card name"),
+                        ia.card_name.fg::<ATRColorHB>()
+                    );
+                    println!(
+                        " ┃   │  └── {:04X} — {}",
+                        ia.rfu.fg::<ATRColorHB>(),
+                        tr("reserved for future use")
+                    );
+                }
+                if let Some(pi) = pre_issuing_data.as_ref() {
+                    println!(
+                        " ┃   ├─── {:} — {}: {}",
+                        "6X".fg::<ATRColorHB>(),
+                        tr("pre-issuing data"),
+                        hex::encode_upper(pi)
+                    );
+                }
+                if let Some(cc) = card_capabilities.as_ref() {
+                    println!(
+                        " ┃   ├──┬ {:} — {}",
+                        "7X".fg::<ATRColorHB>(),
+                        tr("card capabilities")
+                    );
+                    println!(
+                        " ┃   │  ├── {:02X} — {}",
+                        cc.selection_methods.fg::<ATRColorHB>(),
+                        tr("selection methods")
+                    );
+                    if cc.command_chaining {
+                        println!(" ┃   │  ├── {}", tr("Command chaining"));
+                    }
+                    if cc.extended_length {
+                        println!(" ┃   │  ├── {}", tr("Extended Lc and Le fields"));
+                    }
+                    if cc.extended_length_info {
+                        println!(
+                            " ┃   │  ├── {}",
+                            tr("Extended length information in EF.ATR")
+                        );
+                    }
+                    println!(
+                        " ┃   │  └── {}: {}",
+                        tr("logical channels"),
+                        cc.logical_channels
+                    );
+                }
+                if let Some(atr::HistoricalBytesStatus { status, sw1sw2 }) = status.as_ref() {
+                    print!(" ┃   └─── {:} — {}:", "8X".fg::<ATRColorHB>(), tr("status"));
+                    status.tap_some(|v| print!(" {:02X}", v));
+                    sw1sw2.tap_some(|v| print!(" {:02X}", v));
+                    println!("");
+                }
+                println!(" ┃");
+            }
+            atr::HistoricalBytes::Unknown(tag, data) => {
+                // Like pcsc_scan, show any text in there next to the hex.
+                match hb.text() {
+                    Some(text) => println!(
+                        " ┠┬╴HB {:02X} {} {:?}",
+                        tag.fg::<ATRColorHB>(),
+                        hex::encode_upper(data).fg::<ATRColorHB>(),
+                        text.italic()
+                    ),
+                    None => println!(
+                        " ┠┬╴HB {:02X} {}",
+                        tag.fg::<ATRColorHB>(),
+                        hex::encode_upper(data).fg::<ATRColorHB>()
+                    ),
+                }
+                match heuristics::guess(data) {
+                    Some(guess) => println!(" ┃└╴ {} — {}", tr("unknown data").red(), guess),
+                    None => println!(" ┃└╴ {}", tr("unknown data").red()),
+                }
+            }
+        }
+    }
+
+    match atr.tck {
+        Some(tck) => println!(
+            " ┖ Tck: {:02X} — {}",
+            tck.fg::<ATRColorTck>(),
+            tr("checksum")
+        ),
+        None => println!(" ┖ Tck: {}", tr("none")),
+    }
+}
+
+fn render_emv(emv: &EmvProbe) {
+    println!("┏╸{}", "EMV".italic());
+    if let Some(dir) = emv.directory.as_ref() {
+        render_emv_directory(dir, &emv.records);
+    }
+    if let Some(dir) = emv.proximity_directory.as_ref() {
+        render_emv_proximity_directory(dir, emv.directory.is_none());
+    }
+    for app in emv.applications.iter() {
+        render_emv_application(&app.adf_name, &app.directories, &app.application);
+    }
+}
+
+fn render_emv_directory(dir: &emv::Directory, records: &[EmvRecord]) {
+    println!("┗┱─┬╴{}", tr("Directory").italic());
+    println!(" ┃ ├─╴{}: {}", tr("SFI for Elementary File"), dir.ef_sfi);
+    dir.lang_prefs.as_ref().tap_some(|s| {
+        print!(" ┃ ├─╴{}:", tr("Preferred Language(s)"));
+        let mut cursor: &str = s.as_str();
+        while cursor.len() >= 2 {
+            let (lang, rest) = cursor.split_at(2);
+            cursor = rest;
+            print!(" {}", lang);
+        }
+        println!("");
+    });
+    dir.issuer_code_table_idx
+        .tap_some(|v| println!(" ┃ ├─╴{}: ISO-8859-{}", tr("Charset"), v));
+    dir.fci_issuer_discretionary_data
+        .as_ref()
+        .tap_some(|v| print_fci_issuer_discretionary_data(v));
+
+    for EmvRecord { num, record: rec } in records.iter() {
+        println!(" ┃ │");
+        println!(" ┃ ├┬╴{}", format!("{} #{}", tr("Record"), num).italic());
+        for (i, app) in rec.entry.applications.iter().enumerate() {
+            render_emv_directory_application(i, app);
+        }
+    }
+    println!(" ┃ │");
+
+    println!(" ┃ ╵");
+}
+
+fn render_emv_proximity_directory(dir: &emv::ProximityDirectory, first: bool) {
+    let corner = if first { "┗┱" } else { " ┠" };
+    println!("{}─┬╴{}", corner, tr("Proximity Directory").italic());
+    dir.lang_prefs.as_ref().tap_some(|s| {
+        print!(" ┃ ├─╴{}:", tr("Preferred Language(s)"));
+        let mut cursor: &str = s.as_str();
+        while cursor.len() >= 2 {
+            let (lang, rest) = cursor.split_at(2);
+            cursor = rest;
+            print!(" {}", lang);
+        }
+        println!();
+    });
+    dir.issuer_code_table_idx
+        .tap_some(|v| println!(" ┃ ├─╴{}: ISO-8859-{}", tr("Charset"), v));
+
+    println!(" ┃ │");
+    println!(" ┃ ├┬╴{}", tr("FCI Issuer Discretionary Data").italic());
+    for (i, app) in dir.applications.iter().enumerate() {
+        render_emv_directory_application(i, app);
+    }
+    println!(" ┃ │");
+
+    println!(" ┃ ╵");
+}
+
+fn render_emv_directory_application(i: usize, app: &emv::DirectoryApplication) {
+    println!(
+        " ┃ │└┬╴{}",
+        format!("{} #{}", tr("Application"), i + 1).italic()
+    );
+    println!(
+        " ┃ │ ├─╴{}: {}",
+        tr("Application ID"),
+        hex::encode_upper(&app.adf_name)
+    );
+    println!(" ┃ │ ├─╴{}: {}", tr("Label"), app.app_label);
+    app.app_preferred_name
+        .as_ref()
+        .tap_some(|v| println!(" ┃ │ ├─╴{}: {}", tr("Preferred Name"), v));
+    app.app_priority.tap_some(|v| {
+        println!(
+            " ┃ │ ├─╴{}: {} — {}: {}",
+            tr("Priority"),
+            v & 0b0000_1111,
+            tr("needs confirmation"),
+            (v & 0b1000_0000) >> 7 > 0
+        )
+    });
+    app.dir_discretionary_template.as_ref().tap_some(|v| {
+        println!(
+            " ┃ │ ├─╴{}: {}",
+            tr("Directory Discretionary Template"),
+            annotated(v)
+        )
+    });
+}
+
+pub fn render_emv_application(
+    adf_name: &[u8],
+    directories: &[EmvDirectory],
+    app: &emv::Application,
+) {
+    let directories = directories
+        .iter()
+        .map(|dir| match dir {
+            EmvDirectory::Pse => "PSE",
+            EmvDirectory::Ppse => "PPSE",
+        })
+        .collect::<Vec<_>>();
+    let found_in = match directories.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", directories.join(", ")),
+    };
+    println!(
+        " ┠─┬╴{}╺╸{}{}",
+        tr("Application"),
+        hex::encode_upper(adf_name).italic(),
+        found_in.dimmed()
+    );
+    println!(" ┃ ├─╴{}: {}", tr("Label"), app.app_label);
+    app.app_priority.tap_some(|v| {
+        println!(
+            " ┃ ├─╴{}: {} — {}: {}",
+            tr("Priority"),
+            v & 0b0000_1111,
+            tr("needs confirmation"),
+            (v & 0b1000_0000) >> 7 > 0
+        )
+    });
+    app.lang_prefs.as_ref().tap_some(|s| {
+        print!(" ┃ ├─╴{}:", tr("Preferred Language(s)"));
+        let mut cursor: &str = s.as_str();
+        while cursor.len() >= 2 {
+            let (lang, rest) = cursor.split_at(2);
+            cursor = rest;
+            print!(" {}", lang);
+        }
+        println!("");
+    });
+    app.issuer_code_table_idx
+        .tap_some(|v| println!(" ┃ ├─╴{}: ISO-8859-{}", tr("Charset"), v));
+    app.app_preferred_name
+        .as_ref()
+        .tap_some(|v| println!(" ┃ ├─╴{}: {}", tr("Preferred Name"), v));
+
+    if app.pdol.is_some() || app.fci_issuer_discretionary_data.is_some() {
+        println!(" ┃ │");
+    }
+    app.pdol.as_ref().tap_some(|v| {
+        println!(" ┃ ├┬╴{}", tr("Data Objects for Processing Options"));
+        for (tag, _) in v.iter() {
+            let name = match tag {
+                // From: https://neapay.com/online-tools/emv-tags-list.html
+                0x9F5C => "DS Requested Operator ID",
+                _ => "???",
+            };
+            println!(" ┃ │├─╴[{:04X}] {}", tag, name);
+        }
+        println!(" ┃ │╵");
+    });
+    app.fci_issuer_discretionary_data
+        .as_ref()
+        .tap_some(|v| print_fci_issuer_discretionary_data(v));
+    println!(" ┃ ╵");
+}
+
+fn render_capabilities(caps: &Capabilities, ef_atr: Option<&[u8]>) {
+    let yn = |v: Option<bool>| match v {
+        Some(true) => "✓".green().to_string(),
+        Some(false) => "✗".red().to_string(),
+        None => "?".dimmed().to_string(),
+    };
+    println!("{}: {}", tr("Protocols"), caps.protocols.join(", "));
+    if !caps.standards.is_empty() {
+        println!("{}: {}", tr("Standards"), caps.standards.join(", "));
+    }
+    println!("{}: {}", tr("Applications"), caps.applications);
+    print!("{}: {}", tr("Extended length"), yn(caps.extended_length));
+    if let (Some(cmd), Some(rsp)) = (caps.max_command_len, caps.max_response_len) {
+        print!(" (↑{} ↓{})", cmd, rsp);
+    }
+    println!();
+    println!("{}: {}", tr("Command chaining"), yn(caps.command_chaining));
+    match caps.logical_channels {
+        Some(n) => println!("{}: {}", tr("Logical channels"), n),
+        None => println!("{}: {}", tr("Logical channels"), "?".dimmed()),
+    }
+    if !caps.secure_messaging.is_empty() {
+        println!(
+            "{}: {}",
+            tr("Secure messaging"),
+            caps.secure_messaging.join(", ")
+        );
+    }
+    if let Some(ef_atr) = ef_atr {
+        println!("EF.ATR/INFO: {}", annotated(ef_atr));
+    }
+}
+
+fn render_eid(apps: &[eid::Application]) {
+    println!("┏╸{}", "eID".italic());
+    for app in apps.iter() {
+        println!(
+            " ┠─┬╴{}╺╸{}",
+            app.scheme.bold(),
+            hex::encode_upper(app.scheme.aid()).italic()
+        );
+        for field in app.fields.iter() {
+            println!(" ┃ ├─╴{}: {}", field.name, field.value);
+        }
+        println!(" ┃ ╵");
+    }
+}
+
+fn render_ef_dir(apps: &[iso7816::ApplicationTemplate]) {
+    println!("┏╸{}", "EF.DIR".italic());
+    for app in apps.iter() {
+        println!(
+            " ┠─┬╴{}╺╸{}",
+            tr("Application"),
+            hex::encode_upper(&app.aid).italic()
+        );
+        app.label
+            .as_ref()
+            .tap_some(|v| println!(" ┃ ├─╴{}: {}", tr("Label"), v));
+        app.path
+            .as_ref()
+            .tap_some(|v| println!(" ┃ ├─╴{}: {}", tr("Path"), hex::encode_upper(v)));
+        app.discretionary_data
+            .as_ref()
+            .tap_some(|v| println!(" ┃ ├─╴{}: {}", tr("Discretionary Data"), annotated(v)));
+        println!(" ┃ ╵");
+    }
+}
+
+fn print_fci_issuer_discretionary_data(v: &emv::FCIIssuerDiscretionaryData) {
+    println!(" ┃ ├┬╴{}", tr("FCI Issuer Discretionary Data"));
+    v.log_entry.tap_some(|(sfi, num)| {
+        println!(
+            " ┃ │├─╴{} — SFI: {} — {} {}",
+            tr("Log Entries"),
+            sfi,
+            num,
+            tr("records")
+        );
+    });
+    v.app_capability_info.tap_some(|(v1, v2, v3)| {
+        println!(
+            " ┃ │├─╴{}: {:02X} {:02X} {:02X}",
+            tr("Application Capabilities Info"),
+            v1,
+            v2,
+            v3
+        );
+    });
+    v.ds_id.as_ref().tap_some(|v| {
+        println!(
+            " ┃ │├─╴{}: {}",
+            tr("Card Number + Sequence"),
+            hex::encode_upper(v)
+        );
+    });
+    v.ffi_or_third_party_data
+        .as_ref()
+        .tap_some(|v| match v.decoded.as_ref() {
+            Some(emv::scheme::Decoded9F6E::FormFactorIndicator(ffi)) => {
+                print!(
+                    " ┃ │├─╴{}: {} — {} (v{})",
+                    tr("Form Factor Indicator"),
+                    hex::encode_upper(&v.raw),
+                    ffi.form_factor,
+                    ffi.version
+                );
+                for (i, feature) in ffi.features.iter().enumerate() {
+                    print!("{} {}", if i == 0 { ";" } else { "," }, feature);
+                }
+                println!();
+            }
+            Some(emv::scheme::Decoded9F6E::ThirdPartyData(tpd)) => {
+                print!(
+                    " ┃ │├─╴{}: {} — {} {:03}, ID {:04X}",
+                    tr("Third Party Data"),
+                    hex::encode_upper(&v.raw),
+                    tr("country"),
+                    tpd.country_code,
+                    tpd.unique_identifier
+                );
+                match (tpd.device_type.as_deref(), tpd.device_type_name()) {
+                    (Some(_), Some(name)) => print!(", {}", name),
+                    (Some(dt), None) => print!(", {} {:?}", tr("device type"), dt),
+                    _ => {}
+                }
+                println!();
+            }
+            None => println!(" ┃ │├─╴{} (9F6E): {}", tr("Unknown"), annotated(&v.raw)),
+        });
+    v.app_selection_reg_propr_data.as_ref().tap_some(|v| {
+        println!(" ┃ │├┬╴{}", tr("Application Selection Proprietary Data"));
+        for (tag, val) in v.iter() {
+            println!(" ┃ ││├─╴{:04X} — {}", tag, annotated(val));
+        }
+        println!(" ┃ ││╵");
+    });
+    println!(" ┃ │╵");
+}
@@ -0,0 +1,47 @@
+use crate::i18n::tr;
+use cardinal::probe::calypso::{CalypsoFile, CalypsoProbe};
+use owo_colors::OwoColorize;
+
+pub fn render_calypso(report: &CalypsoProbe) {
+    println!(
+        "┏╸{}╺╸{}: {:?} (CLA {:02X})",
+        "Calypso".italic(),
+        tr("Revision"),
+        report.revision,
+        report.revision.cla()
+    );
+    for file in report.files.iter() {
+        render_file(file);
+    }
+}
+
+fn render_file(file: &CalypsoFile) {
+    println!(
+        " ┠─┬╴{}╺╸{} (SFI {:02X}, LID {:04X})",
+        tr("File"),
+        tr(file.file.name()).italic(),
+        file.file.sfi(),
+        file.file.lid()
+    );
+    for rec in file.records.iter() {
+        let data = hex::encode_upper(&rec.data);
+        match rec.fields.as_ref() {
+            None if rec.data.iter().all(|b| *b == 0) => {
+                println!(
+                    " ┃ ├─╴{} {}: {}",
+                    tr("Record"),
+                    rec.num,
+                    tr("empty").dimmed()
+                )
+            }
+            None => println!(" ┃ ├─╴{} {}: {}", tr("Record"), rec.num, data),
+            Some(fields) => {
+                println!(" ┃ ├┬╴{} {}: {}", tr("Record"), rec.num, data.dimmed());
+                for field in fields.0.iter() {
+                    println!(" ┃ │├─╴{}: {}", field.name, field.describe());
+                }
+            }
+        }
+    }
+    println!(" ┃ ╵");
+}
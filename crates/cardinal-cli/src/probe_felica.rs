@@ -0,0 +1,128 @@
+use crate::i18n::tr;
+use cardinal::{
+    felica,
+    probe::felica::{FelicaBlock, FelicaNode, FelicaProbe},
+};
+use owo_colors::OwoColorize;
+use pad::PadStr;
+use tap::TapOptional;
+
+pub fn render_felica(report: &FelicaProbe) {
+    println!("┏╸{}", "FeliCa".italic());
+    println!("┠─╴IDm: {:016X}", report.idm);
+    report.pmm.as_ref().tap_some(|pmm| {
+        println!("┠┬╴PMm: {}", hex::encode_upper(pmm));
+        println!("┃└┬╴{}: {:02X}", tr("ROM Type"), pmm[0]);
+        println!("┃ └╴{}: {}", tr("IC Type"), felica::ICType::from(pmm[1]));
+    });
+    println!("┃");
+
+    for (i, sys) in report.systems.iter().enumerate() {
+        if i == 0 {
+            print!("┗┳");
+        } else {
+            print!(" ┣");
+        }
+        println!(
+            "┯╸{} {:04X}╺╸{}",
+            tr("System").italic(),
+            u16::from(sys.code),
+            sys.code
+        );
+        println!(" ┃└┬╴IDm: {:016X}", sys.idm);
+
+        let mut last_service_num = None;
+        for node in sys.nodes.iter() {
+            match node {
+                FelicaNode::Area { code } => {
+                    if last_service_num.is_some() {
+                        println!(" ┃ │╵");
+                        last_service_num = None;
+                    }
+                    print!(
+                        " ┃ ├╴{:04X}-{:04X}╶╴{}",
+                        code.number,
+                        code.end_number,
+                        tr("Area").italic()
+                    );
+                    if code.can_subdivide {
+                        print!(" +");
+                    }
+                    println!("");
+                }
+                FelicaNode::Service {
+                    code,
+                    key_version,
+                    blocks,
+                } => {
+                    // Print the header once per distinct service number.
+                    if last_service_num != Some(code.number) {
+                        if last_service_num.is_some() {
+                            println!(" ┃ │╵");
+                        }
+                        last_service_num = Some(code.number);
+                        println!(" ┃ ├┬╴{:04X} {}: {}", code.number, tr("Service"), code.kind);
+                    }
+
+                    // Print the subtitle once per access mode (1+ times).
+                    if code.is_authenticated {
+                        println!(
+                            " ┃ │├─╴{:04X}╶╴{}╶╴{}{}",
+                            code.code,
+                            code.access,
+                            tr("authenticated, key ").italic(),
+                            key_version.unwrap_or_default().italic()
+                        );
+                    } else {
+                        println!(" ┃ │├┬╴{:04X}╶╴{}", code.code, code.access);
+                        for (j, block) in blocks.iter().enumerate() {
+                            render_felica_block(j == 0, Some(sys.code), code.code, block);
+                        }
+                    }
+                }
+            }
+        }
+
+        println!(" ┃ │╵");
+        println!(" ┃ ╵");
+    }
+}
+
+fn render_felica_block(
+    first: bool,
+    system: Option<felica::SystemCode>,
+    service: u16,
+    block: &FelicaBlock,
+) {
+    let data = match block.data.as_ref() {
+        Some(data) => hex::encode_upper(data) + &balance(system, service, block),
+        None => String::from_utf8(vec![b'?'; 32]).unwrap(),
+    };
+    if let Some(name) = block.name {
+        let name_p =
+            format!("╴{:02X}╶╴{}╶", block.num, name).pad(13, '─', pad::Alignment::Left, false);
+        if first {
+            println!(" ┃ ││└┬{:}╴{}", name_p, data);
+        } else {
+            println!(" ┃ ││ ├{:}╴{}", name_p, data);
+        }
+    } else if first {
+        println!(" ┃ ││└┤ {}", data);
+    } else {
+        println!(" ┃ ││ │ {}", data);
+    }
+}
+
+/// " — Balance: ¥2,329 (Suica)", if the block is from a purse; see [felica::purse].
+pub fn balance(system: Option<felica::SystemCode>, service: u16, block: &FelicaBlock) -> String {
+    let data = block.data.as_deref().unwrap_or_default();
+    match felica::purse::balance(system, service, block.num, data) {
+        Some((purse, amount)) => format!(
+            " — {}: {} ({})",
+            tr("Balance"),
+            format!("{:#}", amount).bold(),
+            purse.name
+        ),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,73 @@
+use crate::i18n::tr;
+use cardinal::probe::progress::{Event, ProbeObserver};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+/// A spinner on stderr, saying what the probe's up to; it's hidden if stderr isn't a
+/// terminal, so redirected output stays clean.
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::with_bar(ProgressBar::new_spinner())
+    }
+
+    /// One of several, eg. one per reader with `--all-readers`.
+    pub fn in_multi(multi: &MultiProgress, name: &str) -> Self {
+        let progress = Self::with_bar(multi.add(ProgressBar::new_spinner()));
+        progress.bar.set_prefix(name.to_owned());
+        progress
+    }
+
+    fn with_bar(bar: ProgressBar) -> Self {
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {prefix:.bold} {wide_msg} {pos} [{elapsed}]")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self { bar }
+    }
+
+    /// Gets rid of the spinner, before the results are printed.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+impl ProbeObserver for Progress {
+    fn event(&self, event: &Event) {
+        match *event {
+            Event::Stage(stage) => self.bar.set_message(stage),
+            Event::RecordRead { sfi, num } => {
+                self.bar.inc(1);
+                self.bar.set_message(format!("SFI {} #{}", sfi, num));
+            }
+            Event::ApplicationDiscovered { aid, index, total } => self.bar.set_message(format!(
+                "{} {} ({}/{})",
+                tr("Application"),
+                hex::encode_upper(aid),
+                index + 1,
+                total
+            )),
+            Event::SystemDiscovered { code, index, total } => self.bar.set_message(format!(
+                "{} {} ({}/{})",
+                tr("System"),
+                code,
+                index + 1,
+                total
+            )),
+            Event::ServiceDiscovered { code } => {
+                self.bar
+                    .set_message(format!("{} {:04X}", tr("Service"), code));
+            }
+            Event::BlockRead { service, num } => {
+                self.bar.inc(1);
+                self.bar
+                    .set_message(format!("{} {:04X} #{}", tr("Service"), service, num));
+            }
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::hexdata::{annotated, HexData};
+use crate::Result;
+use anyhow::{bail, Context};
+use cardinal::emv::decode;
+use cardinal::{ber, iso7816, x509, CardTransport};
+use owo_colors::OwoColorize;
+use std::io::IsTerminal;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use tracing::{debug, trace_span, warn};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ReadCommand {
+    /// Read records from a record-oriented EF, with READ RECORD.
+    Record {
+        /// Application to select first, as hex.
+        #[arg(long)]
+        aid: Option<HexData>,
+
+        /// Short File Identifier (SFI) of the EF.
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..31))]
+        sfi: u8,
+
+        /// Record number, or an inclusive range (eg. 1..5). (Default: all of them.)
+        #[arg(long, value_parser = parse_range)]
+        num: Option<RangeInclusive<u8>>,
+
+        #[command(flatten)]
+        out: OutputArgs,
+    },
+
+    /// Read a transparent EF, with READ BINARY.
+    Binary {
+        /// Application to select first, as hex.
+        #[arg(long)]
+        aid: Option<HexData>,
+
+        /// File identifier of the EF to select, as hex (eg. 0101). (Default: current EF.)
+        #[arg(long)]
+        ef: Option<HexData>,
+
+        /// Offset to start reading at.
+        #[arg(long, default_value_t = 0)]
+        offset: u16,
+
+        /// Number of bytes to read. Stops early if the file is shorter.
+        #[arg(long, default_value_t = 256)]
+        len: usize,
+
+        #[command(flatten)]
+        out: OutputArgs,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// How to print what we read.
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Also write the raw data to a file. Records are concatenated.
+    #[arg(short = 'w', long)]
+    write: Option<PathBuf>,
+
+    /// Write the X.509 certificate in the data to a file, as DER. Unlike --write, this
+    /// leaves out any padding after it.
+    #[arg(long)]
+    der: Option<PathBuf>,
+
+    /// Write the X.509 certificate in the data to a file, as PEM.
+    #[arg(long)]
+    pem: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Hex, one line per record.
+    #[default]
+    Hex,
+    /// Decoded as BER-TLV, as a tree.
+    Tlv,
+    /// Decoded as BER-TLV, flattened into a table of tags, one per line (tab-separated).
+    Tags,
+    /// Raw bytes, straight to stdout.
+    Raw,
+    /// Summarised as an X.509 certificate (subject, validity, key, fingerprint, etc).
+    Cert,
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<u8>, String> {
+    let num = |v: &str| v.parse::<u8>().map_err(|err| format!("{}: {}", v, err));
+    match s.split_once("..") {
+        Some((start, end)) => Ok(num(start)?..=num(end.trim_start_matches('='))?),
+        None => num(s).map(|n| n..=n),
+    }
+}
+
+impl ReadCommand {
+    pub fn exec(&self, card: &mut impl CardTransport) -> Result<()> {
+        let mut bufs = card.buffers();
+        let (wbuf, rbuf) = bufs.split();
+
+        match self {
+            Self::Record { aid, sfi, num, out } => {
+                select_aid(card, wbuf, rbuf, aid.as_deref())?;
+                let records = read_records(card, wbuf, rbuf, *sfi, num.clone())?;
+                out.write(&records)
+            }
+            Self::Binary {
+                aid,
+                ef,
+                offset,
+                len,
+                out,
+            } => {
+                select_aid(card, wbuf, rbuf, aid.as_deref())?;
+                if let Some(ef) = ef {
+                    debug!(ef = hex::encode_upper(ef), "Selecting EF...");
+                    iso7816::Select {
+                        id: iso7816::SelectID::EF(ef),
+                        mode: iso7816::SelectMode::First,
+                    }
+                    .exec(card, wbuf, rbuf)
+                    .with_context(|| format!("couldn't select EF {}", hex::encode_upper(ef)))?;
+                }
+                let data = iso7816::read_binary(card, wbuf, rbuf, *offset, *len)?;
+                out.write(&[data])
+            }
+        }
+    }
+}
+
+fn select_aid(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    aid: Option<&[u8]>,
+) -> Result<()> {
+    if let Some(aid) = aid {
+        debug!(aid = hex::encode_upper(aid), "Selecting application...");
+        iso7816::Select {
+            id: iso7816::SelectID::Name(aid),
+            mode: iso7816::SelectMode::First,
+        }
+        .exec(card, wbuf, rbuf)
+        .with_context(|| format!("couldn't select application {}", hex::encode_upper(aid)))?;
+    }
+    Ok(())
+}
+
+fn read_records(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    sfi: u8,
+    num: Option<RangeInclusive<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    let span = trace_span!("read_records", sfi);
+    let _enter = span.enter();
+
+    // If you asked for a specific range, you get an error if one's missing; if you didn't,
+    // we just read until the card runs out.
+    let (range, explicit) = match num {
+        Some(range) => (range, true),
+        None => (1..=254, false),
+    };
+    let mut records = vec![];
+    for i in range {
+        debug!(sfi, num = i, "Reading record...");
+        match (iso7816::ReadRecord {
+            sfi,
+            id: iso7816::RecordID::Number(i),
+        })
+        .call(card, wbuf, rbuf)
+        {
+            Ok(rsp) => records.push(rsp.data.to_owned()),
+            Err(err) if err.sw() == Some((0x6A, 0x83)) && !explicit => {
+                debug!(sfi, num = i, "No more records");
+                break;
+            }
+            Err(err) => return Err(err).with_context(|| format!("couldn't read record {}", i)),
+        }
+    }
+    if records.is_empty() {
+        bail!("no records found");
+    }
+    Ok(records)
+}
+
+impl OutputArgs {
+    fn write(&self, chunks: &[Vec<u8>]) -> Result<()> {
+        if let Some(path) = self.write.as_ref() {
+            std::fs::write(path, chunks.concat())
+                .with_context(|| format!("couldn't write to {}", path.display()))?;
+        }
+        if self.der.is_some() || self.pem.is_some() {
+            let cert = x509::Certificate::parse(&chunks.concat())
+                .context("data doesn't look like a certificate")?;
+            if let Some(path) = self.der.as_ref() {
+                std::fs::write(path, &cert.der)
+                    .with_context(|| format!("couldn't write to {}", path.display()))?;
+            }
+            if let Some(path) = self.pem.as_ref() {
+                std::fs::write(path, cert.to_pem())
+                    .with_context(|| format!("couldn't write to {}", path.display()))?;
+            }
+        }
+        match self.format {
+            Format::Hex => {
+                // Only annotate for humans; scripts get one clean line of hex per record.
+                let tty = std::io::stdout().is_terminal();
+                for chunk in chunks {
+                    if tty {
+                        println!("{}", annotated(chunk));
+                    } else {
+                        println!("{}", hex::encode_upper(chunk));
+                    }
+                }
+            }
+            Format::Tlv => {
+                for chunk in chunks {
+                    print_tlv(chunk);
+                    println!();
+                }
+            }
+            Format::Tags => {
+                for chunk in chunks {
+                    print_tags(chunk);
+                }
+            }
+            Format::Raw => {
+                use std::io::Write;
+                let mut stdout = std::io::stdout().lock();
+                for chunk in chunks {
+                    stdout.write_all(chunk)?;
+                }
+            }
+            Format::Cert => {
+                let cert = x509::Certificate::parse(&chunks.concat())
+                    .context("data doesn't look like a certificate")?;
+                println!("{}", cert);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn print_tlv(data: &[u8]) {
+    let currency = decode::currency(data);
+    for res in ber::iter_deep(data) {
+        match res {
+            Ok((depth, tag, _)) if ber::is_constructed(tag) => {
+                println!("{}{}", "  ".repeat(depth), hex::encode_upper(tag));
+            }
+            Ok((depth, tag, value)) => {
+                let indent = "  ".repeat(depth);
+                let tag_u32 = ber::tag_to_u32(tag);
+                match (
+                    decode::label(tag_u32),
+                    decode::decode(tag_u32, value, currency),
+                ) {
+                    (Some(label), Some(decoded)) => println!(
+                        "{}{} {} — {}: {}",
+                        indent,
+                        hex::encode_upper(tag),
+                        hex::encode_upper(value),
+                        label,
+                        decoded.bold()
+                    ),
+                    _ => println!("{}{} {}", indent, hex::encode_upper(tag), annotated(value)),
+                }
+            }
+            Err(err) => {
+                warn!(?err, "Not valid BER-TLV");
+                println!("?? {}", err);
+            }
+        }
+    }
+}
+
+/// Prints every primitive value as a line of `path<TAB>length<TAB>value`, where the path is
+/// the tags it's nested in, eg. `6F/A5/BF0C/9F5E`; easy to grep, or to load into anything
+/// that reads TSV.
+fn print_tags(data: &[u8]) {
+    let mut path: Vec<&[u8]> = vec![];
+    for res in ber::iter_deep(data) {
+        let (depth, tag, value) = match res {
+            Ok(item) => item,
+            Err(err) => {
+                warn!(?err, "Not valid BER-TLV");
+                continue;
+            }
+        };
+        path.truncate(depth);
+        path.push(tag);
+        if !ber::is_constructed(tag) {
+            let path = (path.iter()).map(hex::encode_upper).collect::<Vec<_>>();
+            println!(
+                "{}\t{}\t{}",
+                path.join("/"),
+                value.len(),
+                hex::encode_upper(value)
+            );
+        }
+    }
+}
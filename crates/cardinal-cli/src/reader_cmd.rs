@@ -0,0 +1,96 @@
+use crate::hexdata::HexData;
+use crate::Result;
+use anyhow::Context as _;
+use cardinal::reader_quirks::ReaderQuirks;
+use cardinal::transports::escape::{self, Dialect};
+use cardinal::transports::reader::{self, Led};
+use std::time::Duration;
+use tracing::trace_span;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ReaderCommand {
+    /// Send an escape command (SCardControl) to the reader, and print what it says back.
+    /// Works with no card in the reader. (Careful: some escapes change the reader's
+    /// settings for good.)
+    Escape {
+        /// Command, as hex, @file or - for stdin.
+        data: HexData,
+
+        /// Send it with SCARD_CTL_CODE(N) instead of the usual escape code.
+        #[arg(long, value_name = "N")]
+        code: Option<u32>,
+    },
+
+    /// Print the reader's firmware version. (ACS readers only.)
+    Firmware,
+
+    /// Beep the reader's buzzer. (ACS readers only.)
+    Beep {
+        /// How long for, in milliseconds.
+        #[arg(default_value_t = 100)]
+        ms: u64,
+    },
+
+    /// Set the reader's LED. (ACS readers only.)
+    Led { led: LedArg },
+
+    /// Turn the reader's automatic polling for cards on or off, eg. to let something else
+    /// (like libnfc) drive it. (ACS readers only.)
+    AutoPolling {
+        #[arg(value_parser = clap::builder::BoolishValueParser::new())]
+        on: bool,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum LedArg {
+    Off,
+    Red,
+    Green,
+}
+
+impl From<LedArg> for Led {
+    fn from(v: LedArg) -> Self {
+        match v {
+            LedArg::Off => Led::Off,
+            LedArg::Red => Led::Red,
+            LedArg::Green => Led::Green,
+        }
+    }
+}
+
+impl ReaderCommand {
+    pub fn exec(&self, args: &crate::Args) -> Result<()> {
+        let span = trace_span!("reader");
+        let _enter = span.enter();
+
+        let ctx = pcsc::Context::establish(pcsc::Scope::User)?;
+        let mut card = reader::connect_direct(&ctx, args.reader.as_deref())?;
+        if let Self::Escape { data, code } = self {
+            let mut rbuf = [0; pcsc::MAX_BUFFER_SIZE];
+            let rsp = match code {
+                Some(code) => card.control(pcsc::ctl_code((*code).into()), data, &mut rbuf)?,
+                None => escape::escape(&mut card, data, &mut rbuf)?,
+            };
+            println!(">> {}", hex::encode_upper(&data[..]));
+            println!("<< {}", hex::encode_upper(rsp));
+            return Ok(());
+        }
+
+        let quirks = ReaderQuirks::detect(&mut card).unwrap_or_default();
+        let dialect = Dialect::of(&quirks).with_context(|| {
+            format!(
+                "don't know the escape commands for this reader ({} {}); try `cardinal reader escape`",
+                quirks.vendor, quirks.model
+            )
+        })?;
+        match self {
+            Self::Escape { .. } => unreachable!(),
+            Self::Firmware => println!("{}", escape::firmware_version(&mut card, dialect)?),
+            Self::Beep { ms } => escape::buzzer(&mut card, dialect, Duration::from_millis(*ms))?,
+            Self::Led { led } => escape::led(&mut card, dialect, (*led).into())?,
+            Self::AutoPolling { on } => escape::set_auto_polling(&mut card, dialect, *on)?,
+        }
+        Ok(())
+    }
+}
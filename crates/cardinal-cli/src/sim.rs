@@ -0,0 +1,92 @@
+use crate::i18n::tr;
+use crate::probe::{self, OutputFormat};
+use crate::Result;
+use cardinal::uicc::{self, Class, FileKind, SimInfo};
+use cardinal::CardTransport;
+use owo_colors::OwoColorize;
+use tap::TapOptional;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum SimCommand {
+    /// Show a SIM's ICCID, IMSI, operator name and administrative data; nothing that
+    /// needs a PIN.
+    Info {
+        /// Output format.
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+}
+
+impl SimCommand {
+    pub fn exec(&self, card: &mut impl CardTransport) -> Result<()> {
+        match self {
+            Self::Info { output } => {
+                let mut bufs = card.buffers();
+                let (wbuf, rbuf) = bufs.split();
+                let info = uicc::read_info(card, wbuf, rbuf)?;
+                match output {
+                    OutputFormat::Text => render(&info),
+                    output => probe::write_structured(&info, *output)?,
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn render(info: &SimInfo) {
+    let what = match info.class {
+        Class::Uicc => "UICC (ETSI TS 102 221)",
+        Class::Gsm => "SIM (GSM 11.11)",
+    };
+    println!("┏╸{}╺╸CLA {:02X}", what.italic(), info.class.cla());
+    info.usim
+        .as_ref()
+        .tap_some(|aid| println!(" ┠─╴USIM: {}", hex::encode_upper(aid)));
+    info.iccid
+        .as_ref()
+        .tap_some(|iccid| println!(" ┠─╴ICCID: {}", iccid.bold()));
+    if let Some(imsi) = info.imsi.as_ref() {
+        println!(" ┠─┬╴IMSI: {}", imsi.bold());
+        info.mcc
+            .as_ref()
+            .tap_some(|mcc| println!(" ┃ ├─╴MCC: {}", mcc));
+        info.mnc
+            .as_ref()
+            .tap_some(|mnc| println!(" ┃ ├─╴MNC: {}", mnc));
+        println!(" ┃ ╵");
+    }
+    if let Some(spn) = info.spn.as_ref() {
+        println!(" ┠─┬╴{}: {:?}", tr("Service Provider Name"), spn.name);
+        println!(
+            " ┃ ├─╴{}: {:02X}",
+            tr("Display Condition"),
+            spn.display_condition
+        );
+        println!(" ┃ ╵");
+    }
+    if let Some(ad) = info.ad.as_ref() {
+        println!(
+            " ┠─╴{}: {:02X} ({})",
+            tr("Operation Mode"),
+            ad.mode,
+            tr(ad.mode_name())
+        );
+    }
+    println!(" ┠─┬╴{}", tr("Files"));
+    for (ef, fcp) in info.files.iter() {
+        let kind = match fcp.kind {
+            Some(FileKind::EF(structure)) => format!("{:?}", structure),
+            Some(FileKind::DF) => "DF".to_owned(),
+            None => "?".to_owned(),
+        };
+        print!(" ┃ ├─╴{} ({:04X}): {}", ef, ef.fid(), kind);
+        fcp.size
+            .tap_some(|size| print!(", {} {}", size, tr("bytes")));
+        if let (Some(len), Some(num)) = (fcp.record_len, fcp.records) {
+            print!(", {} × {} {}", num, len, tr("bytes"));
+        }
+        println!();
+    }
+    println!(" ┃ ╵");
+}
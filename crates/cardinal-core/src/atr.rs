@@ -0,0 +1,1068 @@
+//! ATR (Answer-to-Reset) parser.
+//!
+//! Aside from ISO 7816, this is covered by the EMV L1 Contact Interface Specification,
+//! Section 8: "Answer to Reset", which is freely available from EMVCo's website.
+//! For ease of access, this is written using the EMV specs, as well as the surprisingly
+//! complete Wikipedia page: https://en.wikipedia.org/wiki/Answer_to_reset
+//!
+//! Useful online ATR parser: https://smartcard-atr.apdu.fr/
+
+pub mod db;
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt::Display;
+
+use crate::compact_tlv;
+use crate::limits::{self, Limit};
+use crate::{warnings, HexVec};
+
+use nom::bytes::complete::take;
+use nom::combinator::{cond, map};
+use nom::number::complete::{be_u16, be_u32, be_u8};
+use num_enum::{FromPrimitive, IntoPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{trace_span, warn};
+
+pub type IResult<'a, T> = nom::IResult<&'a [u8], T>;
+
+/// Initial Character TS, a known bit pattern to tell electrical transmission convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum TS {
+    /// Direct Convention, 1 is high - (H)LHHLHHHLLH.
+    Direct = 0x3B,
+
+    /// Inverse Convention, 1 is low - (H)LHHLLLLLLH.
+    /// This is relatively rare, and EMV (but not ISO 7816) has deprecated this form.
+    Inverse = 0x3F,
+
+    /// Invalid TS.
+    #[num_enum(catch_all)]
+    Invalid(u8) = 0xFF,
+}
+
+/// Format Byte indicating which other bytes are present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct T0 {
+    /// K, aka number of historical bytes present.
+    pub k: u8,
+    /// Bitmask: Whether the ta1, tb1, tc1 and td1 bytes are present.
+    pub tx1: u8,
+}
+
+impl From<u8> for T0 {
+    fn from(v: u8) -> Self {
+        Self {
+            k: v & 0b0000_1111,
+            tx1: (v & 0b1111_0000) >> 4,
+        }
+    }
+}
+
+impl From<T0> for u8 {
+    fn from(t0: T0) -> Self {
+        t0.k | (t0.tx1 << 4)
+    }
+}
+
+/// A transmission protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum Protocol {
+    T0 = 0,
+    T1 = 1,
+    #[num_enum(catch_all)]
+    Invalid(u8) = 0xFF,
+}
+
+/// Interface Byte, describing a protocol and whether further bytes are present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TDn {
+    /// Protocol, eg. T=0 or T=1.
+    pub protocol: Protocol,
+    /// Bitmask: Whether the next t(x)(n+1) bytes are present.
+    pub txn: u8,
+}
+
+impl From<u8> for TDn {
+    fn from(v: u8) -> Self {
+        Self {
+            protocol: (v & 0b0000_1111).into(),
+            txn: (v & 0b1111_0000) >> 4,
+        }
+    }
+}
+
+impl From<TDn> for u8 {
+    fn from(tdn: TDn) -> Self {
+        u8::from(tdn.protocol) | (tdn.txn << 4)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TXn<Ta: From<u8>, Tb: From<u8>, Tc: From<u8>> {
+    pub ta: Option<Ta>,
+    pub tb: Option<Tb>,
+    pub tc: Option<Tc>,
+    pub td: Option<TDn>,
+}
+
+fn parse_txn<Ta: From<u8>, Tb: From<u8>, Tc: From<u8>>(
+    data: &[u8],
+    last_td: u8,
+) -> IResult<TXn<Ta, Tb, Tc>> {
+    let (data, ta) = cond(last_td & 1 << 0 > 0, be_u8)(data)?;
+    let (data, tb) = cond(last_td & 1 << 1 > 0, be_u8)(data)?;
+    let (data, tc) = cond(last_td & 1 << 2 > 0, be_u8)(data)?;
+    let (data, td) = map(cond(last_td & 1 << 3 > 0, be_u8), |v| v.map(|v| v.into()))(data)?;
+    Ok((
+        data,
+        TXn {
+            ta: ta.map(|v| v.into()),
+            tb: tb.map(|v| v.into()),
+            tc: tc.map(|v| v.into()),
+            td,
+        },
+    ))
+}
+
+/// ISO 7816-4 Section 12.1.1 - Historical bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HistoricalBytes {
+    Status(HistoricalBytesStatus),
+    /// Category Indicator 0x00 or 0x80. If 0x00, must be followed by a status indicator,
+    /// for 0x80, the last element may contain a status indicator in COMPACT-TLV format.
+    TLV(HistoricalBytesTLV),
+    Unknown(u8, HexVec),
+}
+
+impl HistoricalBytes {
+    /// Readable text in proprietary historical bytes, if there's any; see
+    /// [crate::heuristics::ascii_runs]. The category byte is included, since the text often
+    /// starts right there. Standard formats never have any.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            Self::Unknown(cat, data) => {
+                crate::heuristics::ascii_runs(&[&[*cat][..], data].concat())
+            }
+            Self::Status(_) | Self::TLV(_) => None,
+        }
+    }
+
+    /// The raw bytes, category indicator and all.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Status(status) => [0x10]
+                .into_iter()
+                .chain(status.status)
+                .chain(status.sw1sw2.into_iter().flat_map(u16::to_be_bytes))
+                .collect(),
+            Self::TLV(tlv) => [tlv.category].into_iter().chain(tlv.raw.clone()).collect(),
+            Self::Unknown(cat, data) => [*cat].into_iter().chain(data.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HistoricalBytesTLV {
+    /// Category Indicator, 0x00 or 0x80.
+    pub category: u8,
+    pub raw: HexVec,
+    pub service_data: Option<u8>,
+    pub initial_access: Option<InitialAccess>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pre_issuing_data: Option<HexVec>,
+    pub card_capabilities: Option<CardCapabilities>,
+    pub status: Option<HistoricalBytesStatus>,
+}
+
+/// Card capabilities (COMPACT-TLV tag 7); ISO 7816-4, 12.1.1.9. All three bytes are
+/// optional, and anything missing means "no".
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CardCapabilities {
+    /// Selection methods, as a bitmask; same layout as the card service data's top bits
+    /// (0x80 is by full DF name, 0x40 by partial DF name, and so on).
+    pub selection_methods: u8,
+    /// Data coding byte (same as in an FCI), if there is one.
+    pub data_coding: Option<u8>,
+    /// Supports command chaining.
+    pub command_chaining: bool,
+    /// Supports extended Lc and Le fields.
+    pub extended_length: bool,
+    /// EF.ATR/INFO says how long an extended length command or response can be.
+    pub extended_length_info: bool,
+    /// How many logical channels there are, counting the basic one; 1 means just that.
+    pub logical_channels: u8,
+}
+
+impl CardCapabilities {
+    pub fn parse(data: &[u8]) -> Self {
+        let mut slf = Self {
+            selection_methods: data.first().copied().unwrap_or(0),
+            data_coding: data.get(1).copied(),
+            logical_channels: 1,
+            ..Default::default()
+        };
+        if let Some(&b) = data.get(2) {
+            slf.command_chaining = b & 0x80 != 0;
+            slf.extended_length = b & 0x40 != 0;
+            slf.extended_length_info = b & 0x20 != 0;
+            // Bits 5-4 say who assigns channel numbers, if anyone; bits 3-1 are how many
+            // there are, minus one, with 7 meaning "8 or more".
+            if b & 0x18 != 0 {
+                slf.logical_channels = (b & 0x07) + 1;
+            }
+        }
+        slf
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HistoricalBytesStatus {
+    pub status: Option<u8>,
+    pub sw1sw2: Option<u16>,
+}
+
+fn parse_historical_bytes_status(data: &[u8]) -> Option<HistoricalBytesStatus> {
+    match data.len() {
+        1 => Some(HistoricalBytesStatus {
+            status: Some(data[0]),
+            sw1sw2: None,
+        }),
+        2 => Some(HistoricalBytesStatus {
+            status: None,
+            sw1sw2: Some(u16::from_be_bytes([data[0], data[1]])),
+        }),
+        3 => Some(HistoricalBytesStatus {
+            status: Some(data[0]),
+            sw1sw2: Some(u16::from_be_bytes([data[1], data[2]])),
+        }),
+        _ => {
+            let msg = format!("invalid status: {}", hex::encode_upper(data));
+            warnings::other("HistoricalBytes", msg);
+            None
+        }
+    }
+}
+
+/// 0x4Y "Initial access bytes" inside the Historical Bytes.
+///
+/// I'm genuinely unsure about the proper spec for this - I think it's in PC/SC, but the
+/// PC/SC specifications are incomprehensible cryptids and I can never even tell if I'm
+/// reading the right document. This is just based on the docs for my ACR 1252-U reader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InitialAccess {
+    /// Registered Application Provider Identifier (RID), eg. A0 00 00 03 06.
+    pub rid: Provider,
+    /// Standard in use.
+    pub standard: Standard,
+    /// Card name.
+    pub card_name: CardName,
+    /// Unused, reserved for future use.
+    pub rfu: u32,
+}
+
+fn parse_initial_access(data: &[u8]) -> IResult<InitialAccess> {
+    let (data, rid) = map(take(5usize), |v: &[u8]| match v {
+        PROVIDER_ID_PCSC_WORKGROUP => Provider::PCSCWorkgroup,
+        _ => Provider::Unknown(v.into()),
+    })(data)?;
+    let (data, standard) = map(be_u8, |v| v.into())(data)?;
+    let (data, card_name) = map(be_u16, |v| v.into())(data)?;
+    let (data, rfu) = be_u32(data)?;
+    Ok((
+        data,
+        InitialAccess {
+            rid,
+            standard,
+            card_name,
+            rfu,
+        },
+    ))
+}
+
+const PROVIDER_ID_PCSC_WORKGROUP: &[u8] = &[0xA0, 0x00, 0x00, 0x03, 0x06];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Provider {
+    PCSCWorkgroup,
+    Unknown(HexVec),
+}
+
+impl Provider {
+    pub fn id(&self) -> &[u8] {
+        match self {
+            Self::PCSCWorkgroup => PROVIDER_ID_PCSC_WORKGROUP,
+            Self::Unknown(v) => &v,
+        }
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::PCSCWorkgroup => write!(f, "PC/SC Workgroup"),
+            Self::Unknown(v) => write!(f, "Unknown({})", hex::encode_upper(v)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum Standard {
+    Iso14443a3 = 0x03,
+    FeliCa = 0x11,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[cfg(feature = "clap")]
+impl clap::ValueEnum for Standard {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Iso14443a3, Self::FeliCa]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        use clap::builder::PossibleValue;
+        match self {
+            Self::Iso14443a3 => Some(PossibleValue::new("iso14443")),
+            Self::FeliCa => Some(PossibleValue::new("felica")),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Standard {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Iso14443a3 => write!(f, "ISO 14443"),
+            Self::FeliCa => write!(f, "FeliCa"),
+            Self::Unknown(v) => write!(f, "Unknown({:02X})", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u16)]
+pub enum CardName {
+    MifareClassic1K = 0x0001,
+    MifareClassic4K = 0x0002,
+    MifareUltralight = 0x0003,
+    MifareMini = 0x0026,
+    MifareUltralightC = 0x003A,
+    MifarePlusSL12K = 0x0036,
+    MifarePlusSL14K = 0x0037,
+    MifarePlusSL22K = 0x0038,
+    MifarePlusSL24K = 0x0039,
+    TopazJewel = 0x0030,
+    FeliCa = 0x003B,
+    JCOP30 = 0xFF28,
+    SRIX = 0x0007,
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl Display for CardName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MifareClassic1K => write!(f, "MIFARE Classic 1K"),
+            Self::MifareClassic4K => write!(f, "MIFARE Classic 4K"),
+            Self::MifareUltralight => write!(f, "MIFARE Ultralight"),
+            Self::MifareMini => write!(f, "MIFARE Mini"),
+            Self::MifareUltralightC => write!(f, "MIFARE Ultralight C"),
+            Self::MifarePlusSL12K => write!(f, "MIFARE Plus SL1 2K"),
+            Self::MifarePlusSL14K => write!(f, "MIFARE Plus SL1 4K"),
+            Self::MifarePlusSL22K => write!(f, "MIFARE Plus SL2 2K"),
+            Self::MifarePlusSL24K => write!(f, "MIFARE Plus SL2 4K"),
+            Self::TopazJewel => write!(f, "Topaz/Jewel"),
+            Self::FeliCa => write!(f, "FeliCa"),
+            Self::JCOP30 => write!(f, "JCOP 30"),
+            Self::SRIX => write!(f, "SRIX"),
+            Self::Unknown(v) => write!(f, "Unknown({:04X})", v),
+        }
+    }
+}
+
+/// Parses COMPACT-TLV data objects into a [HistoricalBytesTLV]; the same ones can turn up
+/// in the historical bytes or in EF.ATR/INFO.
+fn parse_data_objects<'a>(tlv: &mut HistoricalBytesTLV, mut data: &'a [u8]) -> IResult<'a, ()> {
+    while !data.is_empty() {
+        let (rest, (tag, value)) = compact_tlv::parse_next(data)?;
+        match tag {
+            0x0 => {} // Skip empty padding elements.
+            0x3 => tlv.service_data = value.first().copied(),
+            0x4 => {
+                tlv.initial_access = parse_initial_access(value)
+                    .inspect_err(|_| {
+                        warnings::other("HistoricalBytes", "couldn't parse initial access bytes")
+                    })
+                    .map(|(_, v)| v)
+                    .ok()
+            }
+            0x6 => tlv.pre_issuing_data = Some(value.into()),
+            0x7 => tlv.card_capabilities = Some(CardCapabilities::parse(value)),
+            0x8 => tlv.status = parse_historical_bytes_status(value).or(tlv.status.take()),
+            _ => warnings::unknown_field("HistoricalBytes", &[tag], value),
+        }
+        data = rest;
+    }
+    Ok((data, ()))
+}
+
+/// Parses the contents of EF.ATR/INFO (2F01), which holds whatever didn't fit in the
+/// historical bytes, in the same COMPACT-TLV format. There's no category indicator in
+/// there, so that's always 0x80 (and there's no status indicator at the end).
+pub fn parse_ef_atr(data: &[u8]) -> crate::Result<HistoricalBytesTLV> {
+    let span = trace_span!("atr::parse_ef_atr");
+    let _enter = span.enter();
+
+    let mut tlv = HistoricalBytesTLV {
+        category: 0x80,
+        raw: data.into(),
+        ..Default::default()
+    };
+    parse_data_objects(&mut tlv, data)?;
+    Ok(tlv)
+}
+
+/// Parses historical bytes on their own, eg. from an ATS (see [crate::ats]); anything
+/// malformed is kept as [HistoricalBytes::Unknown].
+pub fn historical_bytes(raw: &[u8]) -> Option<HistoricalBytes> {
+    match raw {
+        [] => None,
+        [cat, rest @ ..] => Some(match parse_historical_bytes(raw) {
+            Ok((_, hb)) => hb,
+            Err(_) => HistoricalBytes::Unknown(*cat, rest.into()),
+        }),
+    }
+}
+
+fn parse_historical_bytes<'a>(data: &'a [u8]) -> IResult<HistoricalBytes> {
+    let span = trace_span!("HistoricalBytes");
+    let _enter = span.enter();
+
+    match be_u8(data)? {
+        (data, tag @ 0x10) => Ok((
+            &data[data.len()..],
+            if let Some(status) = parse_historical_bytes_status(data) {
+                HistoricalBytes::Status(status)
+            } else {
+                HistoricalBytes::Unknown(tag, data.into())
+            },
+        )),
+        (data, ci @ 0x00) | (data, ci @ 0x80) => {
+            Ok({
+                let mut tlv = HistoricalBytesTLV::default();
+                tlv.category = ci;
+                tlv.raw = data.into();
+
+                let mut rest = data;
+                // If the Category Indicator is 0x00, the last 3 bytes are a status code.
+                if ci == 0x00 {
+                    let split = rest.len().checked_sub(3).ok_or(nom::Err::Error(
+                        nom::error::Error::new(rest, nom::error::ErrorKind::Eof),
+                    ))?;
+                    let (rest_, raw_status) = rest.split_at(split);
+                    rest = rest_;
+                    tlv.status = parse_historical_bytes_status(raw_status);
+                }
+                parse_data_objects(&mut tlv, rest)?;
+                (data, HistoricalBytes::TLV(tlv))
+            })
+        }
+        (data, cat) => Ok((
+            &data[data.len()..],
+            HistoricalBytes::Unknown(cat, data.into()),
+        )),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ATR {
+    /// Electrical transmission convention (hi=1 or lo=1).
+    pub ts: TS,
+    /// K (number of historical bytes), whether the t(x)1 bytes are present.
+    pub t0: T0,
+
+    /// Global hardware flags. These are all handled by the reader/driver.
+    /// TA1: Timing modifier. TB2: Voltage modifier. (Deprecated since 2006)
+    /// TC1: Extra guard time. TD1: Presence of TX2.
+    pub tx1: TXn<u8, u8, u8>,
+    /// TA2: Mode negotiation. TB2: Voltage modifier. (Deprecated since 2006)
+    /// TC2: Leading edge time. (T=0 only) TD2: Protocol support + TX2 presence.
+    pub tx2: TXn<u8, u8, u8>,
+    /// TA3: T=1 IFS. TB3: T=1 CWI. TC3: T=1 Error detection code.
+    /// TD3: Protocol support. No further TXn fields should be present.
+    pub tx3: TXn<u8, u8, u8>,
+
+    /// Historical bytes.
+    pub historical_bytes: Option<HistoricalBytes>,
+
+    /// Checksum byte, which is only there if a protocol other than T=0 is mentioned.
+    /// (We trust the reader to validate this.)
+    pub tck: Option<u8>,
+}
+
+/// Returns descriptions of known cards with this (raw) ATR, from the bundled database;
+/// see [db] for more options.
+pub fn identify(atr: &[u8]) -> Vec<String> {
+    let db = db::Database::bundled();
+    db.identify(atr).into_iter().map(|s| s.to_owned()).collect()
+}
+
+/// Serializes an ATR; the inverse of [parse]. T0, which TXn bytes each TDn says follow, and
+/// the TCK are all worked out from the rest, so they don't need to be filled in correctly.
+///
+/// A TCK is added if a protocol other than T=0 is mentioned, or if the ATR already had one.
+pub fn serialize(atr: &ATR) -> crate::Result<Vec<u8>> {
+    let span = trace_span!("atr::serialize");
+    let _enter = span.enter();
+
+    let hb = (atr.historical_bytes.as_ref())
+        .map(HistoricalBytes::to_bytes)
+        .unwrap_or_default();
+    let k = u8::try_from(hb.len())
+        .ok()
+        .filter(|&k| k <= 0x0F)
+        .ok_or(crate::Error::AtrInvalid(
+            "there can't be more than 15 historical bytes",
+        ))?;
+
+    // Which of TA, TB, TC and TD are there, as a T0 or TDn bitmask.
+    let present = |tx: &TXn<u8, u8, u8>| {
+        [
+            tx.ta.is_some(),
+            tx.tb.is_some(),
+            tx.tc.is_some(),
+            tx.td.is_some(),
+        ]
+        .into_iter()
+        .rev()
+        .fold(0, |mask, bit| mask << 1 | bit as u8)
+    };
+    let txs = [&atr.tx1, &atr.tx2, &atr.tx3];
+    let mut data = vec![
+        atr.ts.into(),
+        T0 {
+            k,
+            tx1: present(txs[0]),
+        }
+        .into(),
+    ];
+    let mut needs_tck = false;
+    for (n, tx) in txs.iter().enumerate() {
+        data.extend([tx.ta, tx.tb, tx.tc].into_iter().flatten());
+        let Some(td) = tx.td else {
+            break;
+        };
+        needs_tck |= td.protocol != Protocol::T0;
+        let txn = txs.get(n + 1).map_or(0, |next| present(next));
+        data.push(TDn { txn, ..td }.into());
+    }
+    data.extend(hb);
+    if needs_tck || atr.tck.is_some() {
+        // Everything but TS, XORed together, should come out to 0.
+        data.push(data[1..].iter().fold(0, |tck, b| tck ^ b));
+    }
+    Ok(data)
+}
+
+/// Parses an ATR, failing on anything malformed.
+pub fn parse(data: &[u8]) -> crate::Result<ATR> {
+    Parser::default().parse(data)
+}
+
+/// Parses as much of an ATR as possible, and returns everything that was wrong with it
+/// alongside it, instead of failing. Parts that couldn't be parsed are left out.
+///
+/// This only fails if there isn't even a TS and T0, because then there's nothing to return.
+pub fn parse_lenient(data: &[u8]) -> crate::Result<(ATR, Vec<crate::Error>)> {
+    let mut parser = Parser {
+        lenient: true,
+        ..Default::default()
+    };
+    let atr = parser.parse(data)?;
+    Ok((atr, parser.warnings))
+}
+
+#[derive(Default)]
+struct Parser {
+    lenient: bool,
+    warnings: Vec<crate::Error>,
+}
+
+impl Parser {
+    /// Fails, or in lenient mode, writes it down and carries on.
+    fn problem(&mut self, err: crate::Error) -> crate::Result<()> {
+        if !self.lenient {
+            return Err(err);
+        }
+        warn!("{}", err);
+        self.warnings.push(err);
+        Ok(())
+    }
+
+    fn parse(&mut self, data: &[u8]) -> crate::Result<ATR> {
+        let span = trace_span!("atr::parse", lenient = self.lenient);
+        let _enter = span.enter();
+
+        // A real ATR is at most 33 bytes, but this isn't the place to be strict about it.
+        limits::check(Limit::Size, data.len())?;
+        let [ts, t0, rest @ ..] = data else {
+            return Err(crate::Error::AtrTruncated("there's no TS and T0"));
+        };
+        let mut data = rest;
+        let (ts, t0) = (TS::from(*ts), T0::from(*t0));
+        if let TS::Invalid(_) = ts {
+            self.problem(crate::Error::AtrInvalid("TS should be 3B or 3F"))?;
+        }
+
+        // Interface bytes: each TDn says which TX(n+1) bytes follow, and which protocol.
+        let mut txs = [TXn::default(); 3];
+        let (mut next, mut needs_tck) = (t0.tx1, false);
+        for n in 0.. {
+            if next == 0 {
+                break;
+            }
+            let Ok((rest, tx)) = parse_txn::<u8, u8, u8>(data, next) else {
+                self.problem(crate::Error::AtrTruncated("interface bytes are cut off"))?;
+                data = &[];
+                break;
+            };
+            data = rest;
+            next = tx.td.map(|td| td.txn).unwrap_or_default();
+            needs_tck |= tx.td.is_some_and(|td| td.protocol != Protocol::T0);
+            match txs.get_mut(n) {
+                Some(txn) => *txn = tx,
+                // ISO 7816-3 allows TX4 and onwards, but I've never seen a card use them.
+                // Leniently, they're skipped, so the historical bytes still line up.
+                None if n == 3 => self.problem(crate::Error::AtrInvalid(
+                    "TD3 says there's a TX4, which isn't supported",
+                ))?,
+                None => {}
+            }
+        }
+        let [tx1, tx2, tx3] = txs;
+
+        let (rawhb, rest) = data.split_at((t0.k as usize).min(data.len()));
+        data = rest;
+        if rawhb.len() < t0.k as usize {
+            self.problem(crate::Error::AtrTruncated("historical bytes are cut off"))?;
+        }
+        let historical_bytes = match rawhb {
+            [] => None,
+            [cat, rest @ ..] => Some(match parse_historical_bytes(rawhb) {
+                Ok((_, hb)) => hb,
+                Err(_) => {
+                    self.problem(crate::Error::AtrInvalid("malformed historical bytes"))?;
+                    HistoricalBytes::Unknown(*cat, rest.into())
+                }
+            }),
+        };
+
+        let tck = match data {
+            [] if needs_tck => {
+                self.problem(crate::Error::AtrTruncated("TCK is missing"))?;
+                None
+            }
+            [] => None,
+            [tck, rest @ ..] => {
+                if !rest.is_empty() {
+                    self.problem(crate::Error::AtrInvalid("there's junk after the TCK"))?;
+                }
+                Some(*tck)
+            }
+        };
+
+        Ok(ATR {
+            ts,
+            t0,
+            tx1,
+            tx2,
+            tx3,
+            historical_bytes,
+            tck,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_t0_u8() {
+        assert_eq!(u8::from(T0::from(0x8E)), 0x8E);
+    }
+
+    #[test]
+    fn test_tdn_u8() {
+        assert_eq!(u8::from(TDn::from(0x81)), 0x81);
+    }
+
+    #[test]
+    fn test_parse_curve() {
+        // ATR from a 2018 Curve (UK, Gemalto) card.
+        let atr = parse(&[
+            0x3B, 0x8E, 0x80, 0x01, 0x80, 0x31, 0x80, 0x66, 0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01,
+            0x83, 0x00, 0x90, 0x00, 0x1C,
+        ])
+        .expect("couldn't parse ATR");
+        assert_eq!(
+            atr,
+            ATR {
+                ts: TS::Direct,
+                t0: T0 {
+                    tx1: 0b0000_1000,
+                    k: 14,
+                },
+                tx1: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T0,
+                        txn: 0b1000,
+                    }),
+                },
+                tx2: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T1,
+                        txn: 0x00,
+                    }),
+                },
+                tx3: TXn::default(),
+                historical_bytes: Some(HistoricalBytes::TLV(HistoricalBytesTLV {
+                    category: 0x80,
+                    raw: vec![
+                        0x31, 0x80, 0x66, 0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01, 0x83, 0x00, 0x90,
+                        0x00
+                    ]
+                    .into(),
+                    service_data: Some(0x80),
+                    initial_access: None,
+                    pre_issuing_data: Some(vec![0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01].into()),
+                    card_capabilities: None,
+                    status: Some(HistoricalBytesStatus {
+                        status: Some(0x00),
+                        sw1sw2: Some(0x9000)
+                    }),
+                })),
+                tck: Some(0x1C),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_pasmo() {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let atr = parse(&[
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ])
+        .expect("couldn't parse ATR");
+        assert_eq!(
+            atr,
+            ATR {
+                ts: TS::Direct,
+                t0: T0 { tx1: 0b1000, k: 15 },
+                tx1: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T0,
+                        txn: 0b1000,
+                    }),
+                },
+                tx2: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T1,
+                        txn: 0b0000,
+                    }),
+                },
+                tx3: TXn::default(),
+                historical_bytes: Some(HistoricalBytes::TLV(HistoricalBytesTLV {
+                    category: 0x80,
+                    raw: vec![
+                        0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00, 0x3B, 0x00, 0x00,
+                        0x00, 0x00
+                    ]
+                    .into(),
+                    initial_access: Some(InitialAccess {
+                        rid: Provider::PCSCWorkgroup,
+                        standard: Standard::FeliCa,
+                        card_name: CardName::FeliCa,
+                        rfu: 0x00000000,
+                    }),
+                    service_data: None,
+                    pre_issuing_data: None,
+                    card_capabilities: None,
+                    status: None,
+                })),
+                tck: Some(0x42),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_apple_pay() {
+        // ATR from Apple Pay. Seems static regardless of virtual card, standard or network used.
+        let atr = parse(&[
+            0x3B, 0x88, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x80, 0x81, 0x71, 0x00, 0x79,
+        ])
+        .expect("couldn't parse ATR");
+
+        assert_eq!(
+            atr,
+            ATR {
+                ts: TS::Direct,
+                t0: T0 { tx1: 0b1000, k: 8 },
+                tx1: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T0,
+                        txn: 0b1000,
+                    }),
+                },
+                tx2: TXn {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(TDn {
+                        protocol: Protocol::T1,
+                        txn: 0b0000,
+                    }),
+                },
+                tx3: TXn::default(),
+                // This is complete gibberish. 3 empty tags with length 0, then an empty status?
+                historical_bytes: Some(HistoricalBytes::TLV(HistoricalBytesTLV {
+                    category: 0x00,
+                    raw: vec![0x00, 0x00, 0x00, 0x80, 0x81, 0x71, 0x00].into(),
+                    status: Some(HistoricalBytesStatus {
+                        status: Some(0x81),
+                        sw1sw2: Some(0x7100)
+                    }),
+                    ..Default::default()
+                })),
+                tck: Some(0x79),
+            },
+        );
+    }
+
+    #[test]
+    fn test_parse_tx4() {
+        // TD3 says there's a TA4.
+        let data = [0x3B, 0x80, 0x80, 0x80, 0x10, 0x00];
+        assert!(matches!(parse(&data), Err(crate::Error::AtrInvalid(_))));
+        let (atr, warnings) = parse_lenient(&data).unwrap();
+        assert_eq!(atr.tx3.td, Some(TDn::from(0x10)));
+        assert_eq!((atr.historical_bytes, atr.tck), (None, None));
+        assert!(matches!(warnings[..], [crate::Error::AtrInvalid(_)]));
+    }
+
+    #[test]
+    fn test_parse_short_status() {
+        // Category Indicator 00 should be followed by a 3-byte status, but there's 1 byte.
+        let data = [0x3B, 0x02, 0x00, 0x90];
+        assert!(matches!(parse(&data), Err(crate::Error::AtrInvalid(_))));
+        let (atr, warnings) = parse_lenient(&data).unwrap();
+        assert_eq!(
+            atr.historical_bytes,
+            Some(HistoricalBytes::Unknown(0x00, vec![0x90].into()))
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        // The Curve ATR from above, without its TCK; T=1 is mentioned, so it needs one.
+        let data = [
+            0x3B, 0x8E, 0x80, 0x01, 0x80, 0x31, 0x80, 0x66, 0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01,
+            0x83, 0x00, 0x90, 0x00,
+        ];
+        assert!(matches!(parse(&data), Err(crate::Error::AtrTruncated(_))));
+        let (atr, warnings) = parse_lenient(&data).unwrap();
+        assert!(matches!(
+            atr.historical_bytes,
+            Some(HistoricalBytes::TLV(_))
+        ));
+        assert_eq!(atr.tck, None);
+        assert!(matches!(warnings[..], [crate::Error::AtrTruncated(_)]));
+
+        // Cut off in the interface bytes, after TD1; TX2 and the historical bytes are lost.
+        let (atr, warnings) = parse_lenient(&data[..3]).unwrap();
+        assert_eq!(atr.tx1.td, Some(TDn::from(0x80)));
+        assert_eq!((atr.tx2, atr.historical_bytes), (TXn::default(), None));
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(
+            parse_lenient(&[0x3B]),
+            Err(crate::Error::AtrTruncated(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_ef_atr() {
+        // Card service data, pre-issuing data, and a status indicator.
+        let tlv = parse_ef_atr(&[0x31, 0xC0, 0x63, 0x01, 0x02, 0x03, 0x82, 0x90, 0x00]).unwrap();
+        assert_eq!(tlv.service_data, Some(0xC0));
+        assert_eq!(tlv.pre_issuing_data, Some(vec![0x01, 0x02, 0x03].into()));
+        assert_eq!(
+            tlv.status,
+            Some(HistoricalBytesStatus {
+                status: None,
+                sw1sw2: Some(0x9000),
+            })
+        );
+        assert!(parse_ef_atr(&[0x31, 0xC0, 0x63, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_parse_card_capabilities() {
+        // A JavaCard's: full/partial DF name selection, chaining, extended lengths, and 4
+        // logical channels, assigned by the card and the reader.
+        let tlv = parse_ef_atr(&[0x73, 0xC0, 0x21, 0xDB]).unwrap();
+        assert_eq!(
+            tlv.card_capabilities,
+            Some(CardCapabilities {
+                selection_methods: 0xC0,
+                data_coding: Some(0x21),
+                command_chaining: true,
+                extended_length: true,
+                extended_length_info: false,
+                logical_channels: 4,
+            })
+        );
+        assert_eq!(CardCapabilities::parse(&[0x80]).logical_channels, 1);
+        assert!(!CardCapabilities::parse(&[0x80]).extended_length);
+    }
+
+    #[test]
+    fn test_parse_t0_no_tck() {
+        // Only T=0 is (implicitly) mentioned, so there's no TCK.
+        let atr = parse(&[0x3B, 0x02, 0x14, 0x50]).unwrap();
+        assert_eq!(atr.tck, None);
+        assert!(parse(&[0x3B, 0x02, 0x14, 0x50, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        // Curve, PASMO and Apple Pay, from above.
+        for data in [
+            &[
+                0x3B, 0x8E, 0x80, 0x01, 0x80, 0x31, 0x80, 0x66, 0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01,
+                0x83, 0x00, 0x90, 0x00, 0x1C,
+            ][..],
+            &[
+                0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+                0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+            ],
+            &[
+                0x3B, 0x88, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x80, 0x81, 0x71, 0x00, 0x79,
+            ],
+            &[0x3B, 0x02, 0x14, 0x50],
+        ] {
+            assert_eq!(serialize(&parse(data).unwrap()).unwrap(), data);
+        }
+
+        // Built from scratch, with T0, TD1 and TCK left for serialize to work out.
+        let atr = ATR {
+            ts: TS::Direct,
+            t0: T0 { k: 0, tx1: 0 },
+            tx1: TXn {
+                ta: Some(0x11),
+                td: Some(TDn::from(0x01)),
+                ..Default::default()
+            },
+            tx2: TXn {
+                tc: Some(0x00),
+                ..Default::default()
+            },
+            tx3: TXn::default(),
+            historical_bytes: Some(HistoricalBytes::Status(HistoricalBytesStatus {
+                status: None,
+                sw1sw2: Some(0x9000),
+            })),
+            tck: None,
+        };
+        let data = serialize(&atr).unwrap();
+        assert_eq!(data, [0x3B, 0x93, 0x11, 0x41, 0x00, 0x10, 0x90, 0x00, 0x43]);
+        assert_eq!(parse(&data).unwrap().historical_bytes, atr.historical_bytes);
+
+        let atr = ATR {
+            historical_bytes: Some(HistoricalBytes::Unknown(0x00, vec![0; 15].into())),
+            ..atr
+        };
+        assert!(matches!(serialize(&atr), Err(crate::Error::AtrInvalid(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        // PASMO, from above.
+        let atr = parse(&[
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ])
+        .unwrap();
+        let mut json = serde_json::to_value(&atr).unwrap();
+        assert_eq!(serde_json::from_value::<ATR>(json.clone()).unwrap(), atr);
+
+        // Byte strings are written out as hex, but arrays of bytes read back too.
+        let tlv = &mut json["historical_bytes"]["TLV"];
+        assert_eq!(tlv["raw"], "4F0CA00000030611003B00000000");
+        let raw = crate::hexvec::parse(tlv["raw"].as_str().unwrap()).unwrap();
+        tlv["raw"] = raw.into();
+        assert_eq!(serde_json::from_value::<ATR>(json).unwrap(), atr);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_serialize_roundtrip(data: Vec<u8>) {
+            // Anything that parses should come back the same, except maybe for a bad TCK.
+            if let Ok(atr) = parse(&data) {
+                let again = parse(&serialize(&atr).unwrap()).unwrap();
+                assert_eq!(again, ATR { tck: again.tck, ..atr });
+            }
+        }
+
+        #[test]
+        fn test_parse_anything(data: Vec<u8>) {
+            match parse(&data) {
+                // Lenient parsing should agree with strict parsing on anything valid.
+                Ok(atr) => {
+                    let (lenient, warnings) = parse_lenient(&data).unwrap();
+                    assert_eq!(lenient, atr);
+                    assert!(warnings.is_empty());
+                }
+                Err(_) => { let _ = parse_lenient(&data); }
+            }
+        }
+    }
+}
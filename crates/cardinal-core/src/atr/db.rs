@@ -0,0 +1,200 @@
+//! Looking up ATRs in a database of known cards, in the format of pcsc-tools'
+//! `smartcard_list.txt` (which is what's behind https://smartcard-atr.apdu.fr/).
+//!
+//! A small database is bundled, and more can be loaded on top of it. Descriptions are
+//! indented with a tab:
+//!
+//! ```text
+//! # Comments start with a #.
+//! 3B 8F 80 01 80 4F 0C A0 00 00 03 06 11 00 3B 00 00 00 00 42
+//!     FeliCa (as reported by a PC/SC reader)
+//!     Suica / PASMO / ICOCA ...
+//! ```
+//!
+//! The real database's ATRs are regexes, but nearly all of them only use `.` (any hex
+//! digit); entries with anything fancier are skipped.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::path::Path;
+use tracing::{debug, trace_span};
+
+const BUNDLED: &str = include_str!("smartcard_list.txt");
+
+/// A list of known ATRs, and what they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Database {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Entry {
+    /// ATR patterns, as uppercase hex with no spaces; `.` matches any digit.
+    patterns: Vec<String>,
+    descriptions: Vec<String>,
+}
+
+impl Database {
+    /// The database that comes with cardinal.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED)
+    }
+
+    /// Loads a database file, eg. `/usr/share/pcsc/smartcard_list.txt`.
+    #[cfg(feature = "std")]
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        // Older copies of the real one are Latin-1, so don't insist on UTF-8.
+        Ok(Self::parse(&String::from_utf8_lossy(&std::fs::read(path)?)))
+    }
+
+    /// Parses a database. Anything that doesn't make sense is skipped, not an error; the
+    /// real database has a few odd entries, and one bad ATR shouldn't spoil the rest.
+    pub fn parse(s: &str) -> Self {
+        let span = trace_span!("atr::Database::parse");
+        let _enter = span.enter();
+
+        let mut entries = vec![];
+        let mut entry = Entry::default();
+        for (i, line) in s.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            if let Some(description) = line.strip_prefix('\t') {
+                if entry.patterns.is_empty() {
+                    debug!(line = i + 1, "Description without an ATR");
+                    continue;
+                }
+                entry.descriptions.push(description.trim().to_owned());
+                continue;
+            }
+            // An ATR after some descriptions starts a new entry.
+            if !entry.descriptions.is_empty() {
+                entries.push(core::mem::take(&mut entry));
+            }
+            let pattern: String = line.split_whitespace().collect::<String>().to_uppercase();
+            if pattern.chars().all(|c| c.is_ascii_hexdigit() || c == '.') {
+                entry.patterns.push(pattern);
+            } else {
+                debug!(line = i + 1, pattern, "Skipping unsupported ATR pattern");
+            }
+        }
+        entries.push(entry);
+        entries.retain(|e| !e.patterns.is_empty() && !e.descriptions.is_empty());
+        Self { entries }
+    }
+
+    /// Adds another database's entries to this one's.
+    pub fn extend(&mut self, other: Self) {
+        self.entries.extend(other.entries);
+    }
+
+    /// Is it empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns descriptions of every known card that could have this ATR.
+    pub fn identify(&self, atr: &[u8]) -> Vec<&str> {
+        let atr = hex::encode_upper(atr);
+        let mut descriptions = vec![];
+        for entry in self.entries.iter() {
+            if entry.patterns.iter().any(|p| matches(p, &atr)) {
+                for description in entry.descriptions.iter() {
+                    if !descriptions.contains(&description.as_str()) {
+                        descriptions.push(description.as_str());
+                    }
+                }
+            }
+        }
+        descriptions
+    }
+
+    /// Returns descriptions that mention any of the (space-separated) words in `text`,
+    /// case-insensitively; for when the ATR itself isn't in here, but the card says what it
+    /// is in its historical bytes (see [crate::atr::HistoricalBytes::text]). Short words match
+    /// too much to be any use, so they're ignored.
+    pub fn search(&self, text: &str) -> Vec<&str> {
+        let words: Vec<_> = (text.split_whitespace())
+            .filter(|w| w.len() >= crate::heuristics::MIN_ASCII_RUN)
+            .map(|w| w.to_lowercase())
+            .collect();
+        let mut descriptions = vec![];
+        for description in self.entries.iter().flat_map(|e| e.descriptions.iter()) {
+            let lc = description.to_lowercase();
+            if words.iter().any(|w| lc.contains(w.as_str()))
+                && !descriptions.contains(&description.as_str())
+            {
+                descriptions.push(description.as_str());
+            }
+        }
+        descriptions
+    }
+}
+
+fn matches(pattern: &str, atr: &str) -> bool {
+    pattern.len() == atr.len()
+        && (pattern.chars().zip(atr.chars())).all(|(p, c)| p == '.' || p == c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let db = Database::parse(
+            "# A comment.\n\
+             3B 02 14 50\n\
+             \tSchlumberger Multiflex 3k\n\
+             \n\
+             3B 8F 80 01 80 4F 0C A0 00 00 03 06 .. 00 3B 00 00 00 00 ..\n\
+             3b 8f 80 01 80 4f 0c a0 00 00 03 06 11 00 3b 00 00 00 00 42\n\
+             \tSomething FeliCa-ish\n\
+             \tOn two lines\n\
+             3B (AA|BB)\n\
+             \tA real regex, which we don't do\n\
+             \tAn orphan\n",
+        );
+        assert_eq!(db.entries.len(), 2);
+        assert_eq!(
+            db.identify(&[0x3B, 0x02, 0x14, 0x50]),
+            ["Schlumberger Multiflex 3k"]
+        );
+        assert_eq!(db.identify(&[0x3B, 0x02, 0x14]), Vec::<&str>::new());
+        assert_eq!(db.identify(&[0x3B, 0xAA]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_search() {
+        let db = Database::parse(
+            "3B 02 14 50\n\
+             \tSchlumberger Multiflex 3k\n\
+             3B FA 13 00 00 81 31 FE 45 4A 43 4F 50 34 31 56 32 32 31 96\n\
+             \tNXP JCOP 41 v2.2.1\n\
+             \tjcop41v221 dual interface\n",
+        );
+        assert_eq!(db.search("JCOP41V221 PROD"), ["jcop41v221 dual interface"]);
+        assert_eq!(db.search("multiflex"), ["Schlumberger Multiflex 3k"]);
+        assert_eq!(db.search("3k 41"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_identify_bundled() {
+        let db = Database::bundled();
+        let pasmo = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        let known_as = db.identify(&pasmo);
+        assert_eq!(known_as.len(), 2);
+        assert!(known_as[1].starts_with("Suica / PASMO"));
+
+        // Loading the same thing twice doesn't repeat anything.
+        let mut twice = db.clone();
+        twice.extend(Database::bundled());
+        assert_eq!(twice.identify(&pasmo), known_as);
+    }
+}
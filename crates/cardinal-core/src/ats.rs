@@ -0,0 +1,315 @@
+//! ATS (Answer-to-Select) parser, for ISO 14443-4 (T=CL) cards.
+//!
+//! Contactless cards don't have an ATR; when one's selected, it answers with an ATS, and
+//! the reader makes up an ATR to go with it (PC/SC Part 3, 3.1.3.2.3): `3B 8n 80 01`, the
+//! ATS' historical bytes, and a TCK. Everything else in the ATS (frame size, bit rates,
+//! timings) is lost along the way, but most readers will tell you the ATS if you ask
+//! (`FF CA 01 00`), and some hand out all of it rather than just the historical bytes.
+//!
+//! The format bytes are in ISO 14443-4, Section 5.2: "Answer to select".
+
+use crate::atr::{self, HistoricalBytes, Protocol};
+use crate::{Error, Result};
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::trace_span;
+
+/// Carrier frequency, in Hz; all the timings are in multiples of it.
+const FC: u64 = 13_560_000;
+
+/// A parsed ATS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ATS {
+    /// T0 and the interface bytes; None if all we have is the historical bytes (which is
+    /// all most readers will give out).
+    pub format: Option<FormatBytes>,
+    /// Historical bytes; same format as an ATR's.
+    pub historical_bytes: Option<HistoricalBytes>,
+}
+
+/// Format byte T0, and the interface bytes it says are there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FormatBytes {
+    /// Frame Size for proximity Card Integer.
+    pub fsci: u8,
+    /// Biggest frame the card takes, in bytes (FSC).
+    pub fsc: u16,
+    /// TA(1): Supported bit rates.
+    pub ta: Option<BitRates>,
+    /// TB(1): Frame waiting time, and start-up frame guard time.
+    pub tb: Option<Timings>,
+    /// TC(1): Optional protocol features.
+    pub tc: Option<Features>,
+}
+
+/// TA(1): Which bit rates (in kbit/s) the card can do, besides 106 which everyone does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BitRates {
+    /// Both directions have to use the same bit rate.
+    pub same_both_ways: bool,
+    /// Card to reader (DS).
+    pub card_to_reader: Vec<u16>,
+    /// Reader to card (DR).
+    pub reader_to_card: Vec<u16>,
+}
+
+/// TB(1): Timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Timings {
+    /// Frame Waiting time Integer.
+    pub fwi: u8,
+    /// Frame Waiting Time: how long the card can take to answer, in microseconds.
+    pub fwt_us: u64,
+    /// Start-up Frame Guard time Integer.
+    pub sfgi: u8,
+    /// Start-up Frame Guard Time: how long the card needs after the ATS before it's ready
+    /// for the next frame, in microseconds; 0 if it doesn't.
+    pub sfgt_us: u64,
+}
+
+/// TC(1): Which optional fields in the block prologue the card supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Features {
+    /// Node ADdress.
+    pub nad: bool,
+    /// Card IDentifier.
+    pub cid: bool,
+}
+
+impl ATS {
+    /// Parses a whole ATS, starting from TL (its length). A trailing CRC is ignored, if the
+    /// reader didn't strip it.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("ATS::parse");
+        let _enter = span.enter();
+
+        let (&tl, rest) = data.split_first().ok_or(Error::AtsInvalid("empty"))?;
+        let len = tl as usize;
+        if len == 0 || (data.len() != len && data.len() != len + 2) {
+            return Err(Error::AtsInvalid("TL doesn't match its length"));
+        }
+        let Some((&t0, mut rest)) = rest[..len - 1].split_first() else {
+            // Just TL; nothing else to say, so everything's the default.
+            return Ok(Self {
+                format: None,
+                historical_bytes: None,
+            });
+        };
+        if t0 & 0x80 != 0 {
+            return Err(Error::AtsInvalid("T0's top bit is set"));
+        }
+        let mut next = |present: bool| -> Result<Option<u8>> {
+            if !present {
+                return Ok(None);
+            }
+            let (&v, r) = rest
+                .split_first()
+                .ok_or(Error::AtsInvalid("interface bytes are cut off"))?;
+            rest = r;
+            Ok(Some(v))
+        };
+        let ta = next(t0 & 0x10 != 0)?.map(BitRates::from);
+        let tb = next(t0 & 0x20 != 0)?.map(Timings::from);
+        let tc = next(t0 & 0x40 != 0)?.map(Features::from);
+        let fsci = t0 & 0x0F;
+        Ok(Self {
+            format: Some(FormatBytes {
+                fsci,
+                fsc: fsc(fsci),
+                ta,
+                tb,
+                tc,
+            }),
+            historical_bytes: atr::historical_bytes(rest),
+        })
+    }
+
+    /// Makes an ATS out of just the historical bytes, eg. from a made-up ATR.
+    pub fn from_historical_bytes(raw: &[u8]) -> Self {
+        Self {
+            format: None,
+            historical_bytes: atr::historical_bytes(raw),
+        }
+    }
+}
+
+/// Whether an ATR was made up by a PC/SC reader for a contactless card: `3B 8n 80 01 ..`.
+pub fn is_synthesized(atr: &atr::ATR) -> bool {
+    let td = |txn: &atr::TXn<u8, u8, u8>, next: u8, protocol: Protocol| {
+        txn.ta.is_none()
+            && txn.tb.is_none()
+            && txn.tc.is_none()
+            && txn
+                .td
+                .is_some_and(|td| td.txn == next && td.protocol == protocol)
+    };
+    atr.ts == atr::TS::Direct
+        && atr.t0.tx1 == 0x8
+        && td(&atr.tx1, 0x8, Protocol::T0)
+        && td(&atr.tx2, 0x0, Protocol::T1)
+}
+
+/// Makes up the ATR a PC/SC reader would for a card with this ATS (from TL; a trailing CRC
+/// is ignored): `3B 8n 80 01`, the historical bytes, and a TCK.
+pub fn synthesize_atr(ats: &[u8]) -> Result<Vec<u8>> {
+    let len = *ats.first().ok_or(Error::AtsInvalid("empty"))? as usize;
+    if len == 0 || (ats.len() != len && ats.len() != len + 2) {
+        return Err(Error::AtsInvalid("TL doesn't match its length"));
+    }
+    // Skip T0, and whichever interface bytes it says are there.
+    let hist = match ats[1..len].split_first() {
+        Some((&t0, rest)) => {
+            let skip = (t0 >> 4 & 0x07).count_ones() as usize;
+            rest.get(skip..)
+                .ok_or(Error::AtsInvalid("interface bytes are cut off"))?
+        }
+        None => &[],
+    };
+    if hist.len() > 0x0F {
+        return Err(Error::AtsInvalid(
+            "too many historical bytes to fit in an ATR",
+        ));
+    }
+    let mut atr = [&[0x3B, 0x80 | hist.len() as u8, 0x80, 0x01], hist].concat();
+    atr.push(atr[1..].iter().fold(0, |tck, b| tck ^ b));
+    Ok(atr)
+}
+
+/// FSCI to FSC; ISO 14443-4:2016 went up to 4K, anything past that is RFU, and means 256.
+fn fsc(fsci: u8) -> u16 {
+    match fsci {
+        0..=4 => 16 + 8 * u16::from(fsci),
+        5 => 64,
+        6 => 96,
+        7 => 128,
+        8 => 256,
+        9..=12 => 512 << (fsci - 9),
+        _ => 256,
+    }
+}
+
+impl From<u8> for BitRates {
+    fn from(v: u8) -> Self {
+        let rates = |bits: u8| {
+            core::iter::once(106)
+                .chain(
+                    [212, 424, 847]
+                        .into_iter()
+                        .enumerate()
+                        .filter_map(|(i, rate)| (bits & (1 << i) != 0).then_some(rate)),
+                )
+                .collect()
+        };
+        Self {
+            same_both_ways: v & 0x80 != 0,
+            card_to_reader: rates((v >> 4) & 0x07),
+            reader_to_card: rates(v & 0x07),
+        }
+    }
+}
+
+impl From<u8> for Timings {
+    fn from(v: u8) -> Self {
+        // (256 * 16 / fc) * 2^n; an FWI of 15 is RFU, and means the default of 4.
+        let time = |n: u8| ((256 * 16 * 1_000_000) << n) / FC;
+        let (fwi, sfgi) = (v >> 4, v & 0x0F);
+        Self {
+            fwi,
+            fwt_us: time(if fwi == 15 { 4 } else { fwi }),
+            sfgi,
+            sfgt_us: if sfgi == 0 || sfgi == 15 {
+                0
+            } else {
+                time(sfgi)
+            },
+        }
+    }
+}
+
+impl From<u8> for Features {
+    fn from(v: u8) -> Self {
+        Self {
+            nad: v & 0x01 != 0,
+            cid: v & 0x02 != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_parse() {
+        // A DESFire EV1.
+        let ats = ATS::parse(&[0x06, 0x75, 0x77, 0x81, 0x02, 0x80]).unwrap();
+        let format = ats.format.unwrap();
+        assert_eq!(format.fsci, 5);
+        assert_eq!(format.fsc, 64);
+        assert_eq!(
+            format.ta,
+            Some(BitRates {
+                same_both_ways: false,
+                card_to_reader: vec![106, 212, 424, 847],
+                reader_to_card: vec![106, 212, 424, 847],
+            })
+        );
+        let tb = format.tb.unwrap();
+        assert_eq!((tb.fwi, tb.sfgi), (8, 1));
+        assert_eq!(tb.fwt_us, 77_328);
+        assert_eq!(tb.sfgt_us, 604);
+        assert_eq!(
+            format.tc,
+            Some(Features {
+                nad: false,
+                cid: true
+            })
+        );
+        assert!(matches!(
+            ats.historical_bytes,
+            Some(HistoricalBytes::TLV(_))
+        ));
+
+        // With its CRC still on.
+        assert!(ATS::parse(&[0x02, 0x05, 0xAA, 0xBB]).is_ok());
+        assert_eq!(ATS::parse(&[0x01]).unwrap().format, None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ATS::parse(&[]).is_err());
+        assert!(ATS::parse(&[0x05, 0x75]).is_err());
+        assert!(ATS::parse(&[0x02, 0x85]).is_err());
+        // T0 says TA, TB and TC are there, but there's only room for one.
+        assert!(ATS::parse(&[0x03, 0x75, 0x77]).is_err());
+    }
+
+    #[test]
+    fn test_synthesize_atr() {
+        // A DESFire EV1 (with its CRC), whose historical bytes are just a category of 80.
+        let atr = synthesize_atr(&[0x06, 0x75, 0x77, 0x81, 0x02, 0x80, 0xAA, 0xBB]).unwrap();
+        assert_eq!(atr, vec![0x3B, 0x81, 0x80, 0x01, 0x80, 0x80]);
+        assert!(is_synthesized(&atr::parse(&atr).unwrap()));
+
+        assert_eq!(
+            synthesize_atr(&[0x01]).unwrap(),
+            vec![0x3B, 0x80, 0x80, 0x01, 0x01]
+        );
+        assert!(synthesize_atr(&[0x05, 0x70, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_is_synthesized() {
+        let atr = atr::parse(&[0x3B, 0x81, 0x80, 0x01, 0x80, 0x80]).unwrap();
+        assert!(is_synthesized(&atr));
+        let atr = atr::parse(&[0x3B, 0x02, 0x14, 0x50]).unwrap();
+        assert!(!is_synthesized(&atr));
+    }
+}
@@ -13,6 +13,7 @@
 //! is freely available from EMVCo's website. For ease of access, this implementation is
 //! written using the EMV specs rather than ISO 7816 or ISO 8825 unless otherwise noted.
 
+use crate::limits::{self, Limit, Limits};
 use byteorder::{BigEndian, ByteOrder};
 use nom::bytes::complete::take;
 use nom::number::complete::be_u8;
@@ -91,7 +92,8 @@ pub fn take_len(data_: &[u8]) -> IResult<usize> {
                 nom::error::ErrorKind::TooLarge,
             )))
         } else {
-            Ok((&data[lenlen..], BigEndian::read_uint(data, lenlen) as usize))
+            let (data, len) = take(lenlen)(data)?;
+            Ok((data, BigEndian::read_uint(len, lenlen) as usize))
         }
     }
 }
@@ -104,28 +106,130 @@ pub fn parse_next(data: &[u8]) -> IResult<(&[u8], &[u8])> {
     Ok((data, (tag, val)))
 }
 
+/// Iterates over the (tag, value) pairs in a BER-TLV blob, without descending into
+/// constructed values. Stops with an error if the blob is bigger, or has more elements,
+/// than the [crate::limits] allow.
 pub fn iter<'a>(data: &'a [u8]) -> Iter<'a> {
-    Iter { data }
+    Iter {
+        data,
+        count: 0,
+        limits: limits::get(),
+    }
 }
 
 pub struct Iter<'a> {
     data: &'a [u8],
+    count: usize,
+    limits: Limits,
 }
 
 impl<'a> Iterator for Iter<'a> {
     type Item = crate::Result<(&'a [u8], &'a [u8])>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Err(err) = (self.limits.check(Limit::Size, self.data.len()))
+            .and_then(|_| self.limits.check(Limit::Elements, self.count + 1))
+        {
+            // Don't bother complaining about an empty blob's size.
+            if self.data.is_empty() {
+                return None;
+            }
+            self.data = &[];
+            return Some(Err(err));
+        }
         match parse_next(self.data) {
             Ok((rest, (tag, value))) => {
                 self.data = rest;
+                self.count += 1;
                 Some(Ok((tag, value)))
             }
             Err(nom::Err::Error(nom::error::Error {
                 input: _,
                 code: nom::error::ErrorKind::Eof,
             })) => None,
-            Err(err) => Some(Err(err.into())),
+            Err(err) => {
+                // Don't keep tripping over the same error forever.
+                self.data = &[];
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+/// The deepest [iter_deep] can go, whatever the [crate::limits] say.
+pub const MAX_DEPTH: usize = 32;
+
+/// Walks a BER-TLV blob depth-first, descending into constructed values as it goes, and
+/// yields `(depth, tag, value)` for everything in it; constructed values are yielded too,
+/// right before their contents. Nothing is allocated, so this is cheap enough to use for a
+/// quick look through a response, without building a tree out of it first.
+///
+/// If something doesn't parse, you get an error, and the walk carries on after the value
+/// it was in (if it was in one). Going past the [crate::limits] ends the walk.
+pub fn iter_deep(data: &[u8]) -> DeepIter<'_> {
+    let mut stack = [&[][..]; MAX_DEPTH];
+    stack[0] = data;
+    DeepIter {
+        stack,
+        depth: 0,
+        count: 0,
+        limits: limits::get(),
+    }
+}
+
+pub struct DeepIter<'a> {
+    /// What's left at each level, from the top down to `depth`.
+    stack: [&'a [u8]; MAX_DEPTH],
+    depth: usize,
+    count: usize,
+    limits: Limits,
+}
+
+impl DeepIter<'_> {
+    fn stop(&mut self, err: crate::Error) -> Option<<Self as Iterator>::Item> {
+        (self.stack[0], self.depth) = (&[], 0);
+        Some(Err(err))
+    }
+}
+
+impl<'a> Iterator for DeepIter<'a> {
+    type Item = crate::Result<(usize, &'a [u8], &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.stack[self.depth].is_empty() {
+            self.depth = self.depth.checked_sub(1)?;
+        }
+        if self.count == 0 {
+            if let Err(err) = self.limits.check(Limit::Size, self.stack[0].len()) {
+                return self.stop(err);
+            }
+        }
+        if let Err(err) = self.limits.check(Limit::Elements, self.count + 1) {
+            return self.stop(err);
+        }
+        let depth = self.depth;
+        match parse_next(self.stack[depth]) {
+            Ok((rest, (tag, value))) => {
+                self.stack[depth] = rest;
+                self.count += 1;
+                if is_constructed(tag) && !value.is_empty() {
+                    let max_depth = self.limits.max_depth.min(MAX_DEPTH - 1);
+                    if depth + 1 > max_depth {
+                        return self.stop(crate::Error::LimitExceeded {
+                            limit: Limit::Depth,
+                            max: max_depth,
+                        });
+                    }
+                    self.depth += 1;
+                    self.stack[self.depth] = value;
+                }
+                Some(Ok((depth, tag, value)))
+            }
+            Err(err) => {
+                // Give up on this level, rather than tripping over the same error forever.
+                self.stack[depth] = &[];
+                Some(Err(err.into()))
+            }
         }
     }
 }
@@ -161,8 +265,58 @@ impl<'a> scroll::ctx::TryIntoCtx<()> for TV<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
     use scroll::Pwrite;
 
+    #[test]
+    fn test_iter_deep() {
+        // A PSE's FCI: 6F { 84, A5 { 88, BF0C { 9F5E } } }, then a stray 5A.
+        let data = [
+            0x6F, 0x11, 0x84, 0x02, 0x31, 0x50, 0xA5, 0x0B, 0x88, 0x01, 0x01, 0xBF, 0x0C, 0x05,
+            0x9F, 0x5E, 0x02, 0x47, 0x61, 0x5A, 0x01, 0x42,
+        ];
+        let tags = iter_deep(&data)
+            .map(|res| res.map(|(depth, tag, _)| (depth, tag_to_u32(tag))))
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                (0, 0x6F),
+                (1, 0x84),
+                (1, 0xA5),
+                (2, 0x88),
+                (2, 0xBF0C),
+                (3, 0x9F5E),
+                (0, 0x5A)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iter_deep_errors() {
+        // The A5 claims more than there is; the 5A after its parent still comes out.
+        let data = [0x6F, 0x04, 0xA5, 0x02, 0x88, 0x05, 0x5A, 0x01, 0x42];
+        let items = iter_deep(&data).collect::<Vec<_>>();
+        assert_eq!(items.len(), 4);
+        assert!(items[2].is_err());
+        assert!(matches!(items[3], Ok((0, [0x5A], [0x42]))));
+
+        // Nested deeper than anything has any business being.
+        let mut deep = vec![0x5A, 0x00];
+        for _ in 0..MAX_DEPTH {
+            deep = [vec![0x61, deep.len() as u8], deep].concat();
+        }
+        assert!(matches!(
+            iter_deep(&deep).last(),
+            Some(Err(crate::Error::LimitExceeded {
+                limit: Limit::Depth,
+                ..
+            }))
+        ));
+    }
+
     #[test]
     fn test_tag_to_u32() {
         assert_eq!(tag_to_u32(&[0x6F]), 0x6F);
@@ -438,7 +592,7 @@ mod tests {
                 0x01, 0x01
             ]
         );
-        assert_eq!(rest, &[]);
+        assert_eq!(rest, &[] as &[u8]);
 
         // Parse 0x6F - the FCI Template.
         let (rest, (tag, val)) = parse_next(val).expect("couldn't parse 0x6F[0]");
@@ -457,7 +611,7 @@ mod tests {
             val,
             &[0x88, 0x01, 0x01, 0x5F, 0x2D, 0x02, 0x65, 0x6E, 0x9F, 0x11, 0x01, 0x01]
         );
-        assert_eq!(rest, &[]);
+        assert_eq!(rest, &[] as &[u8]);
 
         // Parse 0xA5 - the FCI Proprietary Template.
         let (rest, (tag, val)) = parse_next(val).expect("couldn't parse 0x6F[1] 0xA5[0]");
@@ -479,7 +633,7 @@ mod tests {
         assert_eq!(tag, &[0x9F, 0x11]);
         assert_eq!(is_constructed(tag), false);
         assert_eq!(val, &[0x01]);
-        assert_eq!(rest, &[]);
+        assert_eq!(rest, &[] as &[u8]);
     }
 
     #[test]
@@ -553,4 +707,20 @@ mod tests {
         let offset = buf.pwrite(TV(&[0x6F], &[]), 0).unwrap();
         assert_eq!(&buf[..offset], &[0x6F, 0x00]);
     }
+
+    #[test]
+    fn test_take_length_truncated() {
+        // Says there's a 1-byte length, then doesn't have one.
+        assert!(take_len(&[0x81]).is_err());
+        assert!(parse_next(&[0x00, 0x81]).is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_parse_anything(data: Vec<u8>) {
+            let _ = parse_next(&data);
+            // The iterator should stop after the first error, rather than loop forever.
+            assert!(iter(&data).skip_while(|r| r.is_ok()).nth(1).is_none());
+        }
+    }
 }
@@ -0,0 +1,154 @@
+//! Buffers for commands to be written into, and responses to be read back out of.
+//!
+//! Every command takes a `wbuf` to put the APDU together in, and an `rbuf` for the card to
+//! answer into, and hands back a view into `rbuf` instead of copying the response out.
+//! Rather than every caller keeping two [crate::MAX_BUFFER_SIZE] arrays on the stack, get
+//! a pair from [CardTransport::buffers]; transports that send a lot of commands (see
+//! `Session` in cardinal-transports) keep a [BufferPool], so they're reused instead of
+//! being allocated again.
+//!
+//! [CardTransport::buffers]: crate::CardTransport::buffers
+
+use crate::{util, CardTransport, Result, MAX_BUFFER_SIZE};
+use std::sync::{Arc, Mutex};
+
+/// Most spare buffers a [BufferPool] holds on to; there's rarely more than one in use.
+pub const MAX_POOLED: usize = 4;
+
+struct Pair {
+    wbuf: [u8; MAX_BUFFER_SIZE],
+    rbuf: [u8; MAX_BUFFER_SIZE],
+}
+
+impl Pair {
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            wbuf: [0; MAX_BUFFER_SIZE],
+            rbuf: [0; MAX_BUFFER_SIZE],
+        })
+    }
+}
+
+/// Spare [Buffers], to be handed out again. Clones share the same pool.
+#[derive(Clone, Default)]
+pub struct BufferPool {
+    // Boxed, so handing them out doesn't move both buffers around.
+    #[allow(clippy::vec_box)]
+    free: Arc<Mutex<Vec<Box<Pair>>>>,
+}
+
+impl std::fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let free = self.free.lock().map_or(0, |free| free.len());
+        f.debug_struct("BufferPool").field("free", &free).finish()
+    }
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands out a pair of buffers, which go back in the pool when they're dropped.
+    pub fn get(&self) -> Buffers {
+        let pair = (self.free.lock().ok()).and_then(|mut free| free.pop());
+        Buffers {
+            pair: Some(pair.unwrap_or_else(Pair::new)),
+            pool: Some(self.clone()),
+        }
+    }
+
+    fn put(&self, pair: Box<Pair>) {
+        if let Ok(mut free) = self.free.lock() {
+            if free.len() < MAX_POOLED {
+                free.push(pair);
+            }
+        }
+    }
+}
+
+/// A request buffer and a response buffer, both big enough for any short APDU. They're on
+/// the heap, so they can be moved around (and kept) cheaply.
+pub struct Buffers {
+    /// Only None while it's being dropped.
+    pair: Option<Box<Pair>>,
+    pool: Option<BufferPool>,
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Buffers {
+    /// A pair that doesn't belong to a pool.
+    pub fn new() -> Self {
+        Self {
+            pair: Some(Pair::new()),
+            pool: None,
+        }
+    }
+
+    fn pair(&mut self) -> &mut Pair {
+        self.pair
+            .as_mut()
+            .expect("buffers used after being dropped")
+    }
+
+    /// The request and response buffers, for functions that take a `wbuf` and an `rbuf`.
+    pub fn split(&mut self) -> (&mut [u8], &mut [u8]) {
+        let pair = self.pair();
+        (&mut pair.wbuf, &mut pair.rbuf)
+    }
+
+    /// Sends an APDU with [util::call_apdu]; the response data borrows the buffers.
+    pub fn call_apdu(
+        &mut self,
+        card: &mut impl CardTransport,
+        cmd: apdu::Command,
+    ) -> Result<&[u8]> {
+        let (wbuf, rbuf) = self.split();
+        util::call_apdu(card, wbuf, rbuf, cmd)
+    }
+}
+
+impl Drop for Buffers {
+    fn drop(&mut self) {
+        if let (Some(pair), Some(pool)) = (self.pair.take(), self.pool.as_ref()) {
+            pool.put(pair);
+        }
+    }
+}
+
+impl std::fmt::Debug for Buffers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Buffers")
+            .field("pooled", &self.pool.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new();
+        let mut bufs = pool.get();
+        let ptr = bufs.split().0.as_ptr();
+        drop(bufs);
+
+        // The same ones come back, instead of new ones.
+        let (mut a, mut b) = (pool.get(), pool.get());
+        assert_eq!(a.split().0.as_ptr(), ptr);
+        assert_ne!(b.split().0.as_ptr(), ptr);
+        drop((a, b));
+        assert_eq!(pool.free.lock().unwrap().len(), 2);
+
+        // Only so many are kept, though.
+        drop((0..MAX_POOLED + 2).map(|_| pool.get()).collect::<Vec<_>>());
+        assert_eq!(pool.free.lock().unwrap().len(), MAX_POOLED);
+    }
+}
@@ -0,0 +1,321 @@
+//! Calypso, the contactless transit card standard behind Navigo (Paris) and a good part of
+//! the rest of Europe's ticketing.
+//!
+//! A Calypso card is an ISO 7816-4 card with one transit application (usually "1TIC.ICA"),
+//! whose data is in a handful of linear record files, with well-known SFIs: who the card
+//! belongs to (Environment), what tickets and passes are on it (Contracts), and where it's
+//! been lately (Events). Reading them doesn't need keys; writing them does.
+//!
+//! Revision 1 cards want CLA 94 for everything; later ones take the usual 00, and we find
+//! out which we've got by asking. What's in the records is up to the network; most of
+//! France (and a few others) use Intercode, which is decoded in [intercode].
+//!
+//! References: Calypso Handbook; https://github.com/L1L1/cardpeek (calypso.lua).
+
+pub mod intercode;
+
+use crate::{util, CardTransport, Error, HexVec, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// The transit application's DF name.
+pub const AID: &[u8] = b"1TIC.ICA";
+
+/// Most records a file can have; record numbers are 5 bits.
+pub const MAX_RECORDS: u8 = 31;
+
+/// Which CLA the card wants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Revision {
+    /// Revision 1: CLA 94.
+    Rev1,
+    /// Revision 2 and later: CLA 00.
+    Rev2,
+}
+
+impl Revision {
+    pub fn cla(self) -> u8 {
+        match self {
+            Self::Rev1 => 0x94,
+            Self::Rev2 => 0x00,
+        }
+    }
+}
+
+/// The files in a transit application that are worth reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum File {
+    /// The chip's serial number and manufacturing data.
+    Icc,
+    /// Holder's identification, on personalised cards.
+    Id,
+    /// Who the card belongs to: network, issuer, expiry, holder profile.
+    Environment,
+    /// The last few validations, newest first.
+    EventLog,
+    /// Tickets and passes.
+    Contracts,
+    /// Which contracts are where, and which to use first.
+    ContractList,
+    /// Counters (ticket books, stored value) for the contracts.
+    Counters,
+    /// Events worth keeping longer than the log does (eg. sales, inspections).
+    SpecialEvents,
+}
+
+impl File {
+    /// In the order `cardinal calypso dump` shows them.
+    pub const ALL: &'static [Self] = &[
+        Self::Icc,
+        Self::Id,
+        Self::Environment,
+        Self::Contracts,
+        Self::ContractList,
+        Self::Counters,
+        Self::EventLog,
+        Self::SpecialEvents,
+    ];
+
+    /// Short File Identifier.
+    pub fn sfi(self) -> u8 {
+        match self {
+            Self::Icc => 0x02,
+            Self::Id => 0x03,
+            Self::Environment => 0x07,
+            Self::EventLog => 0x08,
+            Self::Contracts => 0x09,
+            Self::ContractList => 0x1E,
+            Self::Counters => 0x19,
+            Self::SpecialEvents => 0x1D,
+        }
+    }
+
+    /// What it's called, in English.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Icc => "ICC",
+            Self::Id => "ID",
+            Self::Environment => "Environment",
+            Self::EventLog => "Event Log",
+            Self::Contracts => "Contracts",
+            Self::ContractList => "Contract List",
+            Self::Counters => "Counters",
+            Self::SpecialEvents => "Special Events",
+        }
+    }
+
+    /// Long (two-byte) File Identifier, for cards that can't read by SFI.
+    pub fn lid(self) -> u16 {
+        match self {
+            Self::Icc => 0x0002,
+            Self::Id => 0x0003,
+            Self::Environment => 0x2001,
+            Self::EventLog => 0x2010,
+            Self::Contracts => 0x2020,
+            Self::ContractList => 0x2050,
+            Self::Counters => 0x2069,
+            Self::SpecialEvents => 0x2040,
+        }
+    }
+}
+
+impl std::fmt::Display for File {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A file, and every record we could read from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileRecords {
+    pub file: File,
+    /// Records, from 1 up; empty (all-zero) ones included, since "nothing here" is worth
+    /// knowing too.
+    pub records: Vec<HexVec>,
+}
+
+/// Selects the transit application; returns what the card said (its FCI).
+pub fn select_application(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<u8>> {
+    let span = trace_span!("calypso::select_application");
+    let _enter = span.enter();
+
+    let rsp = util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload_le(0x00, 0xA4, 0x04, 0x00, 0x00, AID),
+    )?;
+    Ok(rsp.to_vec())
+}
+
+/// Selects a file by its LID, under the transit DF (2000), for [read_record] with SFI 0.
+pub fn select_file(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    rev: Revision,
+    lid: u16,
+) -> Result<()> {
+    let span = trace_span!("calypso::select_file", lid = format!("{:04X}", lid));
+    let _enter = span.enter();
+
+    let [hi, lo] = lid.to_be_bytes();
+    util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload_le(
+            rev.cla(),
+            0xA4,
+            0x08,
+            0x00,
+            0x00,
+            &[0x20, 0x00, hi, lo],
+        ),
+    )?;
+    Ok(())
+}
+
+/// Reads one record, from a file by SFI, or the currently selected one (SFI 0).
+pub fn read_record<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    rev: Revision,
+    sfi: u8,
+    num: u8,
+) -> Result<&'r [u8]> {
+    util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_le(rev.cla(), 0xB2, num, (sfi << 3) | 0b100, 0x00),
+    )
+}
+
+/// Works out which [Revision] the card is, by reading the Environment both ways.
+pub fn detect_revision(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Revision> {
+    let span = trace_span!("calypso::detect_revision");
+    let _enter = span.enter();
+
+    let sfi = File::Environment.sfi();
+    match read_record(card, wbuf, rbuf, Revision::Rev2, sfi, 1) {
+        // Class not supported; 6D00 for some that don't know what it is at all.
+        Err(Error::APDU(0x6E, 0x00) | Error::APDU(0x6D, 0x00)) => {
+            read_record(card, wbuf, rbuf, Revision::Rev1, sfi, 1)?;
+            Ok(Revision::Rev1)
+        }
+        res => res.map(|_| Revision::Rev2),
+    }
+}
+
+/// Reads every record in a file, until the card runs out. Cards that won't read it by SFI
+/// get asked to select it first.
+pub fn read_file(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    rev: Revision,
+    file: File,
+) -> Result<FileRecords> {
+    let span = trace_span!("calypso::read_file", %file);
+    let _enter = span.enter();
+
+    let mut sfi = file.sfi();
+    let mut records = vec![];
+    for num in 1..=MAX_RECORDS {
+        match read_record(card, wbuf, rbuf, rev, sfi, num) {
+            Ok(data) => records.push(data.into()),
+            // Record not found: that's all of them.
+            Err(Error::APDU(0x6A, 0x83)) => break,
+            // File not found, or no SFI; try it the long way round.
+            Err(Error::APDU(0x6A, 0x82) | Error::APDU(0x69, 0x81)) if num == 1 && sfi != 0 => {
+                debug!("Couldn't read by SFI, selecting by LID");
+                select_file(card, wbuf, rbuf, rev, file.lid())?;
+                sfi = 0;
+                records.push(read_record(card, wbuf, rbuf, rev, sfi, num)?.into());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(FileRecords { file, records })
+}
+
+/// Selects the transit application, and reads every [File] it has.
+pub fn read_all(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<(Revision, Vec<FileRecords>)> {
+    let span = trace_span!("calypso::read_all");
+    let _enter = span.enter();
+
+    select_application(card, wbuf, rbuf)?;
+    let rev = detect_revision(card, wbuf, rbuf)?;
+    debug!(?rev, "Calypso card");
+    let mut files = vec![];
+    for &file in File::ALL {
+        match read_file(card, wbuf, rbuf, rev, file) {
+            Ok(records) => files.push(records),
+            Err(err) if err.is_card_lost() => return Err(err),
+            Err(err) => debug!(%file, %err, "Couldn't read file, skipping"),
+        }
+    }
+    Ok((rev, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A revision 1 card, with one Environment record and two Contracts, and no other
+    /// files.
+    struct Rev1Card;
+
+    impl CardTransport for Rev1Card {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x00, 0xA4, 0x04, 0x00, 0x08, ..] => &[0x6F, 0x00, 0x90, 0x00],
+                [0x00, ..] => &[0x6E, 0x00],
+                [0x94, 0xB2, 0x01, 0x3C, ..] => &[0x01, 0x02, 0x90, 0x00],
+                [0x94, 0xB2, 0x01 | 0x02, 0x4C, ..] => &[0x03, 0x04, 0x90, 0x00],
+                [0x94, 0xB2, _, 0x3C | 0x4C, ..] => &[0x6A, 0x83],
+                _ => &[0x6A, 0x82],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_read_all() {
+        let (mut wbuf, mut rbuf) = ([0; 32], [0; 64]);
+        let (rev, files) = read_all(&mut Rev1Card, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(rev, Revision::Rev1);
+        assert_eq!(
+            files,
+            vec![
+                FileRecords {
+                    file: File::Environment,
+                    records: vec![vec![0x01, 0x02].into()],
+                },
+                FileRecords {
+                    file: File::Contracts,
+                    records: vec![vec![0x03, 0x04].into(), vec![0x03, 0x04].into()],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! Intercode (NF P99-405): what's in a French Calypso card's records.
+//!
+//! Records are bit-packed, EN 1545 style: fields are big-endian runs of bits, and optional
+//! ones come after a bitmap saying which are there (bit 0 for the first one). Bitmaps nest,
+//! so a record is described by a tree of [Spec]s, and comes out as a flat list of [Field]s.
+//!
+//! Dates are days since 1997-01-01, and times are minutes since midnight. Field names are
+//! the standard's, so they can be looked up; sizes are from cardpeek's en1545 tables.
+
+use super::File;
+use crate::{warnings, HexVec};
+use chrono::{Days, NaiveDate};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// How a field is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spec {
+    /// A number (or, past 64 bits, raw bits), this many bits long. Zero means we know
+    /// it's there, but not how big it is, which can only happen at the end of a record.
+    Int(&'static str, usize),
+    /// A bitmap, one bit for each of these, then the ones that are set.
+    Bitmap(&'static str, &'static [Spec]),
+    /// A count of this many bits, then that many of these.
+    Repeat(&'static str, usize, &'static [Spec]),
+}
+
+use Spec::{Bitmap, Int, Repeat};
+
+/// Environment and Holder (the Environment file's first record).
+pub const ENVIRONMENT: &[Spec] = &[
+    Int("EnvVersionNumber", 6),
+    Bitmap(
+        "EnvBitmap",
+        &[
+            Int("EnvNetworkId", 24),
+            Int("EnvApplicationIssuerId", 8),
+            Int("EnvApplicationValidityEndDate", 14),
+            Int("EnvPayMethod", 11),
+            Int("EnvAuthenticator", 16),
+            Int("EnvSelectList", 32),
+            Bitmap("EnvData", &[Int("EnvCardStatus", 1), Int("EnvExtra", 0)]),
+        ],
+    ),
+    Bitmap(
+        "HolderBitmap",
+        &[
+            Bitmap(
+                "HolderName",
+                &[Int("HolderSurname", 85), Int("HolderForename", 85)],
+            ),
+            Bitmap(
+                "HolderBirth",
+                &[Int("HolderBirthDate", 32), Int("HolderBirthPlace", 115)],
+            ),
+            Int("HolderBirthName", 85),
+            Int("HolderIdNumber", 32),
+            Int("HolderCountryAlpha", 24),
+            Int("HolderCompany", 32),
+            Repeat(
+                "HolderProfiles",
+                4,
+                &[Bitmap(
+                    "HolderProfileBitmap",
+                    &[
+                        Int("HolderNetworkId", 24),
+                        Int("HolderProfileNumber", 8),
+                        Int("HolderProfileDate", 14),
+                    ],
+                )],
+            ),
+            Bitmap(
+                "HolderData",
+                &[
+                    Int("HolderCardStatus", 4),
+                    Int("HolderTelereglement", 4),
+                    Int("HolderResidence", 17),
+                    Int("HolderCommercialId", 6),
+                    Int("HolderWorkPlace", 17),
+                    Int("HolderStudyPlace", 17),
+                    Int("HolderSaleDevice", 16),
+                    Int("HolderAuthenticator", 16),
+                    Int("HolderProfileStartDate1", 14),
+                    Int("HolderProfileStartDate2", 14),
+                    Int("HolderProfileStartDate3", 14),
+                    Int("HolderProfileStartDate4", 14),
+                ],
+            ),
+        ],
+    ),
+];
+
+/// A contract (ticket or pass), as on Navigo cards.
+pub const CONTRACT: &[Spec] = &[Bitmap(
+    "ContractBitmap",
+    &[
+        Int("ContractNetworkId", 24),
+        Int("ContractProvider", 8),
+        Int("ContractTariff", 16),
+        Int("ContractSerialNumber", 32),
+        Bitmap(
+            "ContractCustomerInfo",
+            &[
+                Int("ContractCustomerProfile", 6),
+                Int("ContractCustomerNumber", 32),
+            ],
+        ),
+        Bitmap(
+            "ContractPassengerInfo",
+            &[
+                Int("ContractPassengerClass", 8),
+                Int("ContractPassengerTotal", 8),
+            ],
+        ),
+        Int("ContractVehicleClassAllowed", 6),
+        Int("ContractPaymentPointer", 32),
+        Int("ContractPayMethod", 11),
+        Int("ContractServices", 16),
+        Int("ContractPriceAmount", 16),
+        Int("ContractPriceUnit", 16),
+        Bitmap(
+            "ContractRestriction",
+            &[
+                Int("ContractRestrictStartTime", 11),
+                Int("ContractRestrictEndTime", 11),
+                Int("ContractRestrictDay", 8),
+                Int("ContractRestrictTimeCode", 8),
+                Int("ContractRestrictCode", 8),
+                Int("ContractRestrictProduct", 16),
+                Int("ContractRestrictLocation", 16),
+            ],
+        ),
+        Bitmap(
+            "ContractValidityInfo",
+            &[
+                Int("ContractValidityStartDate", 14),
+                Int("ContractValidityStartTime", 11),
+                Int("ContractValidityEndDate", 14),
+                Int("ContractValidityEndTime", 11),
+                Int("ContractValidityDuration", 8),
+                Int("ContractValidityLimitDate", 14),
+                Int("ContractValidityZones", 8),
+                Int("ContractValidityJourneys", 16),
+                Int("ContractPeriodJourneys", 16),
+            ],
+        ),
+        Bitmap(
+            "ContractJourneyData",
+            &[
+                Int("ContractJourneyOrigin", 16),
+                Int("ContractJourneyDestination", 16),
+                Int("ContractJourneyRouteNumbers", 16),
+                Int("ContractJourneyRouteVariants", 8),
+                Int("ContractJourneyRun", 16),
+                Int("ContractJourneyVia", 16),
+                Int("ContractJourneyDistance", 16),
+                Int("ContractJourneyInterchanges", 8),
+            ],
+        ),
+        Bitmap(
+            "ContractSaleData",
+            &[
+                Int("ContractSaleDate", 14),
+                Int("ContractSaleTime", 11),
+                Int("ContractSaleAgent", 8),
+                Int("ContractSaleDevice", 16),
+            ],
+        ),
+        Int("ContractStatus", 8),
+        Int("ContractLoyaltyPoints", 16),
+        Int("ContractAuthenticator", 16),
+        Int("ContractData", 0),
+    ],
+)];
+
+/// An event (validation, inspection, sale...), from the Event Log or Special Events.
+pub const EVENT: &[Spec] = &[
+    Int("EventDateStamp", 14),
+    Int("EventTimeStamp", 11),
+    Bitmap(
+        "EventBitmap",
+        &[
+            Int("EventDisplayData", 8),
+            Int("EventNetworkId", 24),
+            Int("EventCode", 8),
+            Int("EventResult", 8),
+            Int("EventServiceProvider", 8),
+            Int("EventNotOkCounter", 8),
+            Int("EventSerialNumber", 24),
+            Int("EventDestination", 16),
+            Int("EventLocationId", 16),
+            Int("EventLocationGate", 8),
+            Int("EventDevice", 16),
+            Int("EventRouteNumber", 16),
+            Int("EventRouteVariant", 8),
+            Int("EventJourneyRun", 16),
+            Int("EventVehicleId", 16),
+            Int("EventVehicleClass", 8),
+            Int("EventLocationType", 5),
+            Int("EventEmployee", 240),
+            Int("EventLocationReference", 16),
+            Int("EventJourneyInterchanges", 8),
+            Int("EventPeriodJourneys", 16),
+            Int("EventTotalJourneys", 16),
+            Int("EventJourneyDistance", 16),
+            Int("EventPriceAmount", 16),
+            Int("EventPriceUnit", 16),
+            Int("EventContractPointer", 5),
+            Int("EventAuthenticator", 16),
+            Bitmap(
+                "EventData",
+                &[
+                    Int("EventDataDateFirstStamp", 14),
+                    Int("EventDataTimeFirstStamp", 11),
+                    Int("EventDataSimulation", 1),
+                    Int("EventDataTrip", 2),
+                    Int("EventDataRouteDirection", 2),
+                ],
+            ),
+        ],
+    ),
+];
+
+/// Intercode networks, by network ID (country and network, in BCD).
+const NETWORKS: &[(u64, &str)] = &[(0x250901, "Île-de-France Mobilités (Navigo)")];
+
+/// A field's value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Value {
+    Int(u64),
+    /// Fields too long for a number; left-aligned, so the last byte may be padded.
+    Bits(HexVec),
+}
+
+/// One field from a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Field {
+    pub name: &'static str,
+    pub value: Value,
+}
+
+impl Field {
+    /// The value, the way people write it: dates as dates, network IDs as digits, etc.
+    pub fn describe(&self) -> String {
+        let v = match self.value {
+            Value::Int(v) => v,
+            Value::Bits(ref bits) => return hex::encode_upper(bits),
+        };
+        let name = self.name;
+        if name.ends_with("BirthDate") {
+            format!("{:08X}", v) // YYYYMMDD, in BCD.
+        } else if name.contains("Date") {
+            date(v).map_or_else(|| v.to_string(), |d| d.to_string())
+        } else if name.contains("Time") && !name.ends_with("Code") {
+            format!("{:02}:{:02}", v / 60, v % 60)
+        } else if name.ends_with("NetworkId") {
+            match NETWORKS.iter().find(|(id, _)| *id == v) {
+                Some((_, network)) => format!("{:06X} ({})", v, network),
+                None => format!("{:06X}", v),
+            }
+        } else if name == "EventCode" {
+            format!("{:02X} ({})", v, event_code(v as u8))
+        } else {
+            v.to_string()
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.describe())
+    }
+}
+
+/// Everything in a record, in the order it was in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+pub struct Fields(pub Vec<Field>);
+
+impl Fields {
+    /// The first field with this name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        (self.0.iter().find(|f| f.name == name)).map(|f| &f.value)
+    }
+}
+
+/// Parses a record from one of the files Intercode describes; None for other files, and
+/// for records that are all zeroes (ie. empty).
+pub fn parse(file: File, data: &[u8]) -> Option<Fields> {
+    let spec = match file {
+        File::Environment => ENVIRONMENT,
+        File::Contracts => CONTRACT,
+        File::EventLog | File::SpecialEvents => EVENT,
+        _ => return None,
+    };
+    if data.iter().all(|b| *b == 0) {
+        return None;
+    }
+    Some(parse_spec(spec, data))
+}
+
+/// Parses a record with any spec; if it runs out of bits, you get what was there.
+pub fn parse_spec(spec: &[Spec], data: &[u8]) -> Fields {
+    let mut reader = BitReader { data, pos: 0 };
+    let mut fields = vec![];
+    if let Err(name) = read_specs(&mut reader, spec, &mut fields) {
+        warnings::other(
+            "Intercode",
+            format!("record ends in the middle of {}", name),
+        );
+    }
+    Fields(fields)
+}
+
+fn read_specs(
+    reader: &mut BitReader,
+    specs: &[Spec],
+    out: &mut Vec<Field>,
+) -> Result<(), &'static str> {
+    for spec in specs {
+        read_spec(reader, spec, out)?;
+    }
+    Ok(())
+}
+
+fn read_spec(
+    reader: &mut BitReader,
+    spec: &Spec,
+    out: &mut Vec<Field>,
+) -> Result<(), &'static str> {
+    match *spec {
+        Int(name, bits) => {
+            let value = match bits {
+                0..=64 => Value::Int(reader.read(bits).ok_or(name)?),
+                _ => Value::Bits(reader.read_bits(bits).ok_or(name)?.into()),
+            };
+            out.push(Field { name, value });
+        }
+        Bitmap(name, specs) => {
+            let bitmap = reader.read(specs.len()).ok_or(name)?;
+            for (i, spec) in specs.iter().enumerate() {
+                if bitmap & (1 << i) != 0 {
+                    read_spec(reader, spec, out)?;
+                }
+            }
+        }
+        Repeat(name, bits, specs) => {
+            for _ in 0..reader.read(bits).ok_or(name)? {
+                read_specs(reader, specs, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// In bits, from the start.
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    /// Up to 64 bits, big-endian.
+    fn read(&mut self, bits: usize) -> Option<u64> {
+        if self.pos + bits > self.data.len() * 8 {
+            return None;
+        }
+        let mut v = 0;
+        for _ in 0..bits {
+            let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            v = v << 1 | u64::from(bit);
+            self.pos += 1;
+        }
+        Some(v)
+    }
+
+    /// Any number of bits, left-aligned in bytes.
+    fn read_bits(&mut self, bits: usize) -> Option<Vec<u8>> {
+        if self.pos + bits > self.data.len() * 8 {
+            return None;
+        }
+        let mut out = vec![0; bits.div_ceil(8)];
+        for i in 0..bits {
+            out[i / 8] |= (self.read(1)? as u8) << (7 - i % 8);
+        }
+        Some(out)
+    }
+}
+
+/// Days since 1997-01-01.
+fn date(days: u64) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(1997, 1, 1)?.checked_add_days(Days::new(days))
+}
+
+/// What an EventCode means: the mode of transport in the high nibble, and what happened in
+/// the low one.
+fn event_code(code: u8) -> String {
+    let mode = match code >> 4 {
+        0 => "unspecified",
+        1 => "urban bus",
+        2 => "interurban bus",
+        3 => "metro",
+        4 => "tram",
+        5 => "train",
+        8 => "parking",
+        _ => "unknown mode",
+    };
+    let what = match code & 0x0F {
+        1 => "entry",
+        2 => "exit",
+        4 => "inspection",
+        6 => "interchange entry",
+        7 => "interchange exit",
+        _ => "other",
+    };
+    format!("{} {}", mode, what)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `(value, bits)` big-endian, and pads it out to bytes.
+    fn pack(fields: &[(u64, usize)]) -> Vec<u8> {
+        let bits: Vec<u8> = (fields.iter())
+            .flat_map(|&(v, n)| (0..n).rev().map(move |i| (v >> i & 1) as u8))
+            .collect();
+        (bits.chunks(8))
+            .map(|c| (0..8).fold(0, |b, i| b << 1 | c.get(i).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_environment() {
+        // Version 1, with a network ID and validity end date, and no holder data.
+        let data = pack(&[(1, 6), (0b0000101, 7), (0x250901, 24), (10_000, 14), (0, 8)]);
+        let fields = parse(File::Environment, &data).unwrap();
+        assert_eq!(fields.get("EnvVersionNumber"), Some(&Value::Int(1)));
+        assert_eq!(
+            (fields.0.iter().map(|f| f.to_string())).collect::<Vec<_>>(),
+            vec![
+                "EnvVersionNumber: 1",
+                "EnvNetworkId: 250901 (Île-de-France Mobilités (Navigo))",
+                "EnvApplicationValidityEndDate: 2024-05-19",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_event() {
+        // A metro entry at 08:30, with the location, in a nested bitmap's worth of fields.
+        let data = pack(&[
+            (10_000, 14),
+            (8 * 60 + 30, 11),
+            (1 << 2 | 1 << 8 | 1 << 27, 28),
+            (0x31, 8),
+            (1234, 16),
+            (0b00001, 5),
+            (10_001, 14),
+        ]);
+        let fields = parse(File::EventLog, &data).unwrap();
+        assert_eq!(
+            (fields.0.iter().map(|f| f.to_string())).collect::<Vec<_>>(),
+            vec![
+                "EventDateStamp: 2024-05-19",
+                "EventTimeStamp: 08:30",
+                "EventCode: 31 (metro entry)",
+                "EventLocationId: 1234",
+                "EventDataDateFirstStamp: 2024-05-20",
+            ]
+        );
+
+        // Empty records are skipped, and short ones give what they've got.
+        assert_eq!(parse(File::EventLog, &[0; 29]), None);
+        let fields = parse(File::EventLog, &data[..3]).unwrap();
+        assert_eq!(fields.0.len(), 1);
+    }
+
+    #[test]
+    fn test_read_bits() {
+        let mut reader = BitReader {
+            data: &[0b1010_1010, 0b1100_0000],
+            pos: 1,
+        };
+        assert_eq!(reader.read_bits(10), Some(vec![0b0101_0101, 0b1000_0000]));
+        assert_eq!(reader.read(6), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! ISO 7816 COMPACT-TLV, the BER-TLV's little cousin.
+//!
+//! Where space is tight (the historical bytes in an ATR, or the EF.ATR/INFO file that
+//! carries on where they leave off), ISO 7816-4 packs the tag and length into a single
+//! byte: the high nibble is the tag, the low nibble is the length. So a tag is 0x0-0xF,
+//! and a value is at most 15 bytes long...
+//!
+//! ...except when the length is F and the next byte is the real length. I can only find
+//! this mentioned in the docs for my ACR 1252-U reader, but its initial access bytes
+//! don't fit in 15 bytes otherwise, so here we are.
+
+use crate::limits::{self, Limit, Limits};
+use nom::bytes::complete::take;
+use nom::number::complete::be_u8;
+
+pub type IResult<'a, T> = nom::IResult<&'a [u8], T>;
+
+/// Parses the next (tag, value) pair from a COMPACT-TLV blob.
+pub fn parse_next(data: &[u8]) -> IResult<'_, (u8, &[u8])> {
+    let (data, tl) = be_u8(data)?;
+    let (tag, len) = (tl >> 4, tl & 0x0F);
+    let (data, len) = match len {
+        0x0F => be_u8(data)?,
+        len => (data, len),
+    };
+    let (data, value) = take(len)(data)?;
+    Ok((data, (tag, value)))
+}
+
+/// Iterates over the (tag, value) pairs in a COMPACT-TLV blob. Stops with an error if the
+/// blob is bigger, or has more elements, than the [crate::limits] allow.
+pub fn iter(data: &[u8]) -> Iter<'_> {
+    Iter {
+        data,
+        count: 0,
+        limits: limits::get(),
+    }
+}
+
+pub struct Iter<'a> {
+    data: &'a [u8],
+    count: usize,
+    limits: Limits,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = crate::Result<(u8, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if let Err(err) = (self.limits.check(Limit::Size, self.data.len()))
+            .and_then(|_| self.limits.check(Limit::Elements, self.count + 1))
+        {
+            self.data = &[];
+            return Some(Err(err));
+        }
+        match parse_next(self.data) {
+            Ok((rest, (tag, value))) => {
+                self.data = rest;
+                self.count += 1;
+                Some(Ok((tag, value)))
+            }
+            Err(err) => {
+                // Don't keep tripping over the same error forever.
+                self.data = &[];
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_iter() {
+        // Card service data, then a status indicator.
+        let data = [0x31, 0xC0, 0x82, 0x90, 0x00];
+        assert_eq!(
+            iter(&data).collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![(0x3, &[0xC0][..]), (0x8, &[0x90, 0x00][..])]
+        );
+        assert_eq!(iter(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_iter_extended_length() {
+        let mut data = vec![0x4F, 0x10];
+        data.extend([0xAA; 0x10]);
+        data.push(0x00);
+        assert_eq!(
+            iter(&data).collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![(0x4, &[0xAA; 0x10][..]), (0x0, &[][..])]
+        );
+    }
+
+    #[test]
+    fn test_iter_truncated() {
+        let mut it = iter(&[0x31, 0xC0, 0x45, 0xA0, 0x00]);
+        assert!(it.next().unwrap().is_ok());
+        assert!(it.next().unwrap().is_err());
+        assert!(it.next().is_none());
+    }
+}
@@ -0,0 +1,391 @@
+//! National eID cards (and driving licences): which one is this, and what does it say
+//! without a PIN?
+//!
+//! eIDs don't have directories the way payment cards do, and most don't bother with EF.DIR
+//! either; the only way to find out is to try selecting each scheme's application, and see
+//! who answers. Some (Estonia, Belgium) then have a personal data file anyone can read;
+//! others (the German nPA) won't say anything without PACE, so all we can say is that it's
+//! there, and hand over EF.CardAccess, which says how to talk to it.
+//!
+//! References: "EstEID 2018 Developer's Guide" (Estonia); "Belgian Electronic Identity Card
+//! content" v4.x (Belgium); BSI TR-03110 (nPA); gematik "Spezifikation der elektronischen
+//! Gesundheitskarte" (eGK); ISO/IEC 18013-2 (driving licences).
+
+use crate::iso7816::{self, Select, SelectID, SelectMode};
+use crate::{util, CardTransport, Error, HexVec, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// An eID scheme we know how to find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Scheme {
+    /// Estonian ID card (and residence permit, e-Residency card), 2018 and later.
+    Estonia,
+    /// Belgian eID (BELPIC).
+    Belgium,
+    /// German ID card (neuer Personalausweis); needs PACE for anything interesting.
+    GermanyNpa,
+    /// German health insurance card (elektronische Gesundheitskarte).
+    GermanyEgk,
+    /// ISO 18013-2 driving licence.
+    DrivingLicence,
+}
+
+impl Scheme {
+    /// Every scheme, in the order they're tried.
+    pub const ALL: &'static [Self] = &[
+        Self::Estonia,
+        Self::Belgium,
+        Self::GermanyNpa,
+        Self::GermanyEgk,
+        Self::DrivingLicence,
+    ];
+
+    /// The application's AID.
+    pub fn aid(self) -> &'static [u8] {
+        match self {
+            Self::Estonia => &[
+                0xA0, 0x00, 0x00, 0x00, 0x77, 0x01, 0x08, 0x00, 0x07, 0x00, 0x00, 0xFE, 0x00, 0x00,
+                0x01, 0x00,
+            ],
+            // "\x01wPKCS-15".
+            Self::Belgium => &[
+                0xA0, 0x00, 0x00, 0x01, 0x77, 0x50, 0x4B, 0x43, 0x53, 0x2D, 0x31, 0x35,
+            ],
+            Self::GermanyNpa => &[0xE8, 0x07, 0x04, 0x00, 0x7F, 0x00, 0x07, 0x03, 0x02],
+            Self::GermanyEgk => &[0xD2, 0x76, 0x00, 0x01, 0x44, 0x80, 0x00],
+            Self::DrivingLicence => &[0xA0, 0x00, 0x00, 0x02, 0x48, 0x02, 0x00],
+        }
+    }
+
+    /// Where it's from, for the summary.
+    pub fn country(self) -> Option<&'static str> {
+        match self {
+            Self::Estonia => Some("Estonia"),
+            Self::Belgium => Some("Belgium"),
+            Self::GermanyNpa | Self::GermanyEgk => Some("Germany"),
+            Self::DrivingLicence => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Estonia => write!(f, "Estonian ID card"),
+            Self::Belgium => write!(f, "Belgian eID"),
+            Self::GermanyNpa => write!(f, "German ID card (nPA)"),
+            Self::GermanyEgk => write!(f, "German health card (eGK)"),
+            Self::DrivingLicence => write!(f, "ISO 18013 driving licence"),
+        }
+    }
+}
+
+/// Something an eID says about itself (or its holder).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Field {
+    /// What it is, in English.
+    pub name: String,
+    /// Text, or hex for binary data.
+    pub value: String,
+}
+
+impl Field {
+    fn new(name: &str, value: impl Into<String>) -> Self {
+        Self {
+            name: name.to_owned(),
+            value: value.into(),
+        }
+    }
+}
+
+/// An eID application that answered, and whatever it let us read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Application {
+    pub scheme: Scheme,
+    /// What the card said when it was selected.
+    pub fci: HexVec,
+    pub fields: Vec<Field>,
+}
+
+/// Tries every [Scheme], and reads what it can from the ones that are there.
+pub fn discover(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<Application>> {
+    let span = trace_span!("eid::discover");
+    let _enter = span.enter();
+
+    let mut apps = vec![];
+    for &scheme in Scheme::ALL {
+        let fci = match select_aid(card, wbuf, rbuf, scheme.aid()) {
+            Ok(fci) => fci,
+            // Not there; whatever the card's reason, it's not this one.
+            Err(err) if err.sw().is_some() => {
+                debug!(%scheme, %err, "Not found");
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        debug!(%scheme, "Found eID application");
+        let mut fields = vec![];
+        let res = match scheme {
+            Scheme::Estonia => read_estonia(card, wbuf, rbuf, &mut fields),
+            Scheme::Belgium => read_belgium(card, wbuf, rbuf, &mut fields),
+            Scheme::GermanyNpa => read_card_access(card, wbuf, rbuf, &mut fields),
+            Scheme::GermanyEgk => read_egk(card, wbuf, rbuf, &mut fields),
+            Scheme::DrivingLicence => Ok(()),
+        };
+        match res {
+            Err(err) if err.is_card_lost() => return Err(err),
+            Err(err) => debug!(%scheme, %err, "Couldn't read everything"),
+            Ok(()) => {}
+        }
+        apps.push(Application {
+            scheme,
+            fci: fci.into(),
+            fields,
+        });
+    }
+    Ok(apps)
+}
+
+fn select_aid(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    aid: &[u8],
+) -> Result<Vec<u8>> {
+    Select {
+        id: SelectID::Name(aid),
+        mode: SelectMode::First,
+    }
+    .exec(card, wbuf, rbuf)
+    .map(|fci| fci.to_vec())
+}
+
+/// Selects a file without asking for an FCI (P2 0C), which some of these don't have.
+fn select_file(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    p1: u8,
+    id: &[u8],
+) -> Result<()> {
+    util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload(0x00, 0xA4, p1, 0x0C, id),
+    )?;
+    Ok(())
+}
+
+/// The Estonian personal data file: DF 5000, with one EF per field, in UTF-8.
+const ESTONIA_FIELDS: &[(u8, &str)] = &[
+    (0x01, "Surname"),
+    (0x02, "First name"),
+    (0x03, "Sex"),
+    (0x04, "Citizenship"),
+    (0x05, "Date and place of birth"),
+    (0x06, "Personal identification code"),
+    (0x07, "Document number"),
+    (0x08, "Expiry date"),
+    (0x09, "Date and place of issuance"),
+    (0x0A, "Type of residence permit"),
+    (0x0B, "Notes"),
+    (0x0C, "Notes"),
+    (0x0D, "Notes"),
+    (0x0E, "Notes"),
+    (0x0F, "Notes"),
+];
+
+fn read_estonia(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    fields: &mut Vec<Field>,
+) -> Result<()> {
+    select_file(card, wbuf, rbuf, 0x01, &[0x50, 0x00])?;
+    for &(ef, name) in ESTONIA_FIELDS {
+        // Empty fields are either missing, empty files, or a single space.
+        match select_file(card, wbuf, rbuf, 0x02, &[0x50, ef]) {
+            Err(Error::APDU(0x6A, 0x82)) => continue,
+            res => res?,
+        }
+        let data = match iso7816::read_binary(card, wbuf, rbuf, 0, 256) {
+            Ok(data) => data,
+            Err(Error::APDU(0x6B, 0x00)) => continue,
+            Err(err) => return Err(err),
+        };
+        let value = String::from_utf8_lossy(&data).trim().to_owned();
+        if !value.is_empty() {
+            fields.push(Field::new(name, value));
+        }
+    }
+    Ok(())
+}
+
+/// The Belgian identity file's tags; anything else is skipped.
+const BELGIUM_FIELDS: &[(u8, &str)] = &[
+    (0x01, "Card number"),
+    (0x02, "Chip number"),
+    (0x03, "Validity start date"),
+    (0x04, "Validity end date"),
+    (0x05, "Delivery municipality"),
+    (0x06, "National number"),
+    (0x07, "Surname"),
+    (0x08, "First names"),
+    (0x09, "Third first name initial"),
+    (0x0A, "Nationality"),
+    (0x0B, "Place of birth"),
+    (0x0C, "Date of birth"),
+    (0x0D, "Sex"),
+    (0x0E, "Noble condition"),
+    (0x0F, "Document type"),
+    (0x10, "Special status"),
+    (0x11, "Photo hash"),
+];
+
+fn read_belgium(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    fields: &mut Vec<Field>,
+) -> Result<()> {
+    // Identity file: 3F00/DF01/4031, selected by path from the MF.
+    select_file(card, wbuf, rbuf, 0x08, &[0xDF, 0x01, 0x40, 0x31])?;
+    let data = iso7816::read_binary(card, wbuf, rbuf, 0, 1024)?;
+    fields.extend(parse_belgium_id(&data));
+    Ok(())
+}
+
+/// Parses the Belgian identity file: a simple TLV, with one-byte tags, and lengths that
+/// carry on into the next byte if the top bit is set.
+pub fn parse_belgium_id(mut data: &[u8]) -> Vec<Field> {
+    let mut fields = vec![];
+    while let Some((&tag, rest)) = data.split_first() {
+        let mut len = 0usize;
+        let mut rest = rest;
+        while let Some((&b, r)) = rest.split_first() {
+            len = len << 7 | usize::from(b & 0x7F);
+            rest = r;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        let Some(value) = rest.get(..len) else {
+            break;
+        };
+        data = &rest[len..];
+        let Some(&(_, name)) = BELGIUM_FIELDS.iter().find(|(t, _)| *t == tag) else {
+            continue;
+        };
+        let value = match tag {
+            0x02 | 0x11 => hex::encode_upper(value),
+            _ => String::from_utf8_lossy(value).trim().to_owned(),
+        };
+        fields.push(Field::new(name, value));
+    }
+    fields
+}
+
+/// EF.CardAccess (011C, under the MF): the PACE parameters, for whoever wants to try.
+fn read_card_access(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    fields: &mut Vec<Field>,
+) -> Result<()> {
+    select_file(card, wbuf, rbuf, 0x00, &[0x3F, 0x00])?;
+    select_file(card, wbuf, rbuf, 0x02, &[0x01, 0x1C])?;
+    let data = iso7816::read_binary(card, wbuf, rbuf, 0, 1024)?;
+    fields.push(Field::new("EF.CardAccess", hex::encode_upper(data)));
+    Ok(())
+}
+
+/// EF.GDO (2F02, under the MF): the card's serial number (ICCSN), as 5A.
+fn read_egk(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    fields: &mut Vec<Field>,
+) -> Result<()> {
+    select_file(card, wbuf, rbuf, 0x00, &[0x3F, 0x00])?;
+    select_file(card, wbuf, rbuf, 0x02, &[0x2F, 0x02])?;
+    let data = iso7816::read_binary(card, wbuf, rbuf, 0, 12)?;
+    if let Some(Ok((&[0x5A], iccsn))) = crate::ber::iter(&data).next() {
+        fields.push(Field::new("ICCSN", hex::encode_upper(iccsn)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_belgium_id() {
+        let data = [
+            0x01, 0x03, b'5', b'9', b'2', 0x02, 0x02, 0x53, 0x4C, 0x07, 0x05, b'S', b'p', b'e',
+            b'c', b'i', 0x1F, 0x01, 0x00, 0x0D, 0x01, b'F',
+        ];
+        assert_eq!(
+            parse_belgium_id(&data),
+            vec![
+                Field::new("Card number", "592"),
+                Field::new("Chip number", "534C"),
+                Field::new("Surname", "Speci"),
+                Field::new("Sex", "F"),
+            ]
+        );
+        // Truncated: keep what there was.
+        assert_eq!(parse_belgium_id(&data[..4]), vec![]);
+    }
+
+    /// An Estonian card, with a surname and a blank first name, and nothing else.
+    struct Estonian {
+        selected: u8,
+    }
+
+    impl CardTransport for Estonian {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match *capdu {
+                [0x00, 0xA4, 0x04, 0x00, 0x10, 0xA0, ..] => &[0x90, 0x00],
+                [0x00, 0xA4, 0x01, 0x0C, 0x02, 0x50, 0x00] => &[0x90, 0x00],
+                [0x00, 0xA4, 0x02, 0x0C, 0x02, 0x50, ef] => {
+                    self.selected = ef;
+                    &[0x90, 0x00]
+                }
+                [0x00, 0xB0, 0x00, 0x00, 0x00] => match self.selected {
+                    0x01 => b"J\xC3\x95EORG\x90\x00",
+                    0x02 => b" \x90\x00",
+                    _ => &[0x6B, 0x00],
+                },
+                [0x00, 0xB0, ..] => &[0x6B, 0x00],
+                _ => &[0x6A, 0x82],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_discover_estonia() {
+        let (mut wbuf, mut rbuf) = ([0; 32], [0; 64]);
+        let apps = discover(&mut Estonian { selected: 0 }, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(
+            apps,
+            vec![Application {
+                scheme: Scheme::Estonia,
+                fci: HexVec::default(),
+                fields: vec![Field::new("Surname", "JÕEORG")],
+            }]
+        );
+    }
+}
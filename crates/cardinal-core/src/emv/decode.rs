@@ -0,0 +1,269 @@
+//! Numeric data elements: dates, countries, currencies and amounts.
+//!
+//! EMV stores all of these as BCD ("n" format, EMV Book 3, 4.3): two digits a byte, padded
+//! with leading zeroes. Dates are YYMMDD, countries and currencies are ISO 3166 and 4217
+//! numeric codes (n3, in 2 bytes), and amounts are in the currency's minor unit (n12, in 6
+//! bytes), which means they need a currency from somewhere else to make sense.
+
+use crate::ber;
+use crate::money::{Amount, Currency};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// What a numeric data element means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Decoded {
+    /// An expiry date; the card expires at the end of the month, so the day doesn't matter.
+    Expiry {
+        year: u16,
+        month: u8,
+    },
+    Date {
+        year: u16,
+        month: u8,
+        day: u8,
+    },
+    /// ISO 3166 numeric country code.
+    Country(u16),
+    Currency(Currency),
+    Amount(Amount),
+    /// An amount, in some currency's minor unit, but we don't know which.
+    Number(u64),
+}
+
+/// Numeric data elements we know how to decode, and what they're called.
+const ELEMENTS: &[(u32, &str)] = &[
+    (0x5F24, "Expiry"),
+    (0x5F25, "Effective"),
+    (0x9A, "Transaction date"),
+    (0x5F28, "Issuer country"),
+    (0x9F1A, "Terminal country"),
+    (0x5F2A, "Transaction currency"),
+    (0x9F42, "Application currency"),
+    (0x9F02, "Amount"),
+    (0x9F03, "Other amount"),
+    (0x9F5D, "Available offline spending"), // [neaPay]
+];
+
+/// What a tag is called, if it's one [decode] knows.
+pub fn label(tag: u32) -> Option<&'static str> {
+    (ELEMENTS.iter())
+        .find(|(t, _)| *t == tag)
+        .map(|(_, label)| *label)
+}
+
+/// Decodes a numeric data element. Amounts are in `currency` if given, which is usually
+/// the Application Currency Code (see [currency]). None if it's not a tag we know, or the
+/// value isn't valid BCD.
+pub fn decode(tag: u32, value: &[u8], currency: Option<Currency>) -> Option<Decoded> {
+    match tag {
+        0x5F24 => {
+            let (year, month, _) = date(value)?;
+            Some(Decoded::Expiry { year, month })
+        }
+        0x5F25 | 0x9A => {
+            let (year, month, day) = date(value)?;
+            Some(Decoded::Date { year, month, day })
+        }
+        0x5F28 | 0x9F1A => Some(Decoded::Country(n3(value)?)),
+        0x5F2A | 0x9F42 => Some(Decoded::Currency(Currency::from(n3(value)?))),
+        0x9F02 | 0x9F03 | 0x9F5D => {
+            let value = bcd(<[u8; 6]>::try_from(value).ok()?)?;
+            Some(match currency {
+                Some(currency) => Decoded::Amount(Amount::new(value as i64, currency)),
+                None => Decoded::Number(value),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Finds the currency amounts in a BER-TLV blob are in: the Application Currency Code
+/// (9F42), or failing that, the Transaction Currency Code (5F2A).
+pub fn currency(data: &[u8]) -> Option<Currency> {
+    let mut found = None;
+    for (_, tag, value) in ber::iter_deep(data).flatten() {
+        match ber::tag_to_u32(tag) {
+            0x9F42 => return n3(value).map(Currency::from),
+            0x5F2A => found = found.or(n3(value).map(Currency::from)),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// ISO 3166 country name, for the countries you're likely to find on a card.
+pub fn country_name(code: u16) -> Option<&'static str> {
+    COUNTRIES
+        .binary_search_by_key(&code, |(c, _)| *c)
+        .ok()
+        .map(|i| COUNTRIES[i].1)
+}
+
+/// BCD digits as a number; None if any nibble isn't a digit.
+fn bcd<const N: usize>(v: [u8; N]) -> Option<u64> {
+    v.iter()
+        .flat_map(|b| [b >> 4, b & 0x0F])
+        .try_fold(0u64, |n, d| (d < 10).then(|| n * 10 + u64::from(d)))
+}
+
+/// An n3 code in 2 bytes, eg. `08 26`.
+pub(crate) fn n3(v: &[u8]) -> Option<u16> {
+    let v = <[u8; 2]>::try_from(v).ok()?;
+    // Three digits, so the first nibble is padding.
+    if v[0] >> 4 != 0 {
+        return None;
+    }
+    bcd(v).map(|n| n as u16)
+}
+
+/// YYMMDD; years are 2000-2049 for 00-49, and 1950-1999 otherwise (EMV Book 4, A.1).
+fn date(v: &[u8]) -> Option<(u16, u8, u8)> {
+    let [yy, mm, dd] = <[u8; 3]>::try_from(v)
+        .ok()?
+        .map(|b| bcd([b]).map(|n| n as u8));
+    let (yy, month, day) = (yy?, mm?, dd?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if yy < 50 { 2000 } else { 1900 } + u16::from(yy);
+    Some((year, month, day))
+}
+
+impl std::fmt::Display for Decoded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Expiry { year, month } => write!(f, "{:04}-{:02}", year, month),
+            Self::Date { year, month, day } => write!(f, "{:04}-{:02}-{:02}", year, month, day),
+            Self::Country(code) => match country_name(*code) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "{:03}", code),
+            },
+            Self::Currency(currency) => write!(f, "{}", currency),
+            Self::Amount(amount) => write!(f, "{:#}", amount),
+            Self::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// ISO 3166 numeric codes, sorted; not the full table, like [Currency].
+const COUNTRIES: &[(u16, &str)] = &[
+    (32, "Argentina"),
+    (36, "Australia"),
+    (40, "Austria"),
+    (50, "Bangladesh"),
+    (56, "Belgium"),
+    (76, "Brazil"),
+    (100, "Bulgaria"),
+    (124, "Canada"),
+    (152, "Chile"),
+    (156, "China"),
+    (158, "Taiwan"),
+    (170, "Colombia"),
+    (191, "Croatia"),
+    (196, "Cyprus"),
+    (203, "Czechia"),
+    (208, "Denmark"),
+    (233, "Estonia"),
+    (246, "Finland"),
+    (250, "France"),
+    (276, "Germany"),
+    (300, "Greece"),
+    (344, "Hong Kong"),
+    (348, "Hungary"),
+    (352, "Iceland"),
+    (356, "India"),
+    (360, "Indonesia"),
+    (372, "Ireland"),
+    (376, "Israel"),
+    (380, "Italy"),
+    (392, "Japan"),
+    (404, "Kenya"),
+    (410, "South Korea"),
+    (414, "Kuwait"),
+    (428, "Latvia"),
+    (440, "Lithuania"),
+    (442, "Luxembourg"),
+    (446, "Macao"),
+    (458, "Malaysia"),
+    (470, "Malta"),
+    (484, "Mexico"),
+    (528, "Netherlands"),
+    (554, "New Zealand"),
+    (566, "Nigeria"),
+    (578, "Norway"),
+    (586, "Pakistan"),
+    (604, "Peru"),
+    (608, "Philippines"),
+    (616, "Poland"),
+    (620, "Portugal"),
+    (634, "Qatar"),
+    (642, "Romania"),
+    (643, "Russia"),
+    (682, "Saudi Arabia"),
+    (702, "Singapore"),
+    (703, "Slovakia"),
+    (704, "Vietnam"),
+    (705, "Slovenia"),
+    (710, "South Africa"),
+    (724, "Spain"),
+    (752, "Sweden"),
+    (756, "Switzerland"),
+    (764, "Thailand"),
+    (784, "United Arab Emirates"),
+    (792, "Türkiye"),
+    (804, "Ukraine"),
+    (818, "Egypt"),
+    (826, "United Kingdom"),
+    (840, "United States"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let decoded = |tag, value: &[u8]| decode(tag, value, None).map(|d| d.to_string());
+        assert_eq!(decoded(0x5F24, &[0x27, 0x08, 0x31]).unwrap(), "2027-08");
+        assert_eq!(decoded(0x5F25, &[0x99, 0x12, 0x01]).unwrap(), "1999-12-01");
+        assert_eq!(decoded(0x5F28, &[0x08, 0x26]).unwrap(), "United Kingdom");
+        assert_eq!(decoded(0x9F1A, &[0x09, 0x99]).unwrap(), "999");
+        assert_eq!(decoded(0x9F42, &[0x09, 0x78]).unwrap(), "EUR");
+        assert_eq!(
+            decoded(0x9F02, &[0x00, 0x00, 0x00, 0x01, 0x23, 0x45]).unwrap(),
+            "12345"
+        );
+        assert_eq!(
+            decode(
+                0x9F02,
+                &[0x00, 0x00, 0x00, 0x01, 0x23, 0x45],
+                Some(Currency::GBP)
+            ),
+            Some(Decoded::Amount(Amount::new(12345, Currency::GBP)))
+        );
+
+        // Not BCD, not a real date, or the wrong length.
+        assert_eq!(decoded(0x5F24, &[0x27, 0x0A, 0x31]), None);
+        assert_eq!(decoded(0x5F24, &[0x27, 0x13, 0x31]), None);
+        assert_eq!(decoded(0x5F28, &[0x08, 0x26, 0x00]), None);
+        assert_eq!(decoded(0x5A, &[0x12, 0x34]), None);
+    }
+
+    #[test]
+    fn test_currency() {
+        // A record with both: the application's currency wins.
+        let data = [
+            0x70, 0x0A, 0x5F, 0x2A, 0x02, 0x08, 0x26, 0x9F, 0x42, 0x02, 0x03, 0x92,
+        ];
+        assert_eq!(currency(&data), Some(Currency::JPY));
+        assert_eq!(currency(&data[2..7]), Some(Currency::GBP));
+        assert_eq!(currency(&[0x70, 0x00]), None);
+    }
+
+    #[test]
+    fn test_countries_sorted() {
+        assert!(COUNTRIES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
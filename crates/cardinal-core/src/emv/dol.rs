@@ -0,0 +1,149 @@
+//! Data Object Lists: how an application asks the terminal for data. EMV Book 3, 5.4.
+//!
+//! A DOL is a list of tags and lengths, with no values: the PDOL (9F38) for GET PROCESSING
+//! OPTIONS, CDOL1 and CDOL2 (8C, 8D) for GENERATE AC, the DDOL (9F49) for INTERNAL
+//! AUTHENTICATE, and the TDOL (97) for the TC Hash Value. The terminal answers with just the
+//! values, run together in the same order, each cut or padded to the length asked for.
+//!
+//! Where the values come from is a [Provider]: a list of `(tag, value)` pairs, a
+//! [super::terminal::TerminalConfig], or a few of them in a tuple (the first one that has
+//! a tag wins). Anything nobody has is zeroes.
+
+use crate::{ber, Result};
+use std::borrow::Cow;
+
+/// A Data Object List, as (tag, length) pairs.
+pub type Dol = Vec<(u32, usize)>;
+
+/// Numeric (n) data elements the terminal might be asked for; these are right-aligned,
+/// so they're padded and cut on the left.
+const NUMERIC: &[u32] = &[
+    0x9A, 0x9C, 0x5F2A, 0x5F36, 0x9F02, 0x9F03, 0x9F15, 0x9F1A, 0x9F21, 0x9F41,
+];
+
+/// Compressed numeric (cn) data elements; these are padded with F, not zeroes.
+const COMPRESSED_NUMERIC: &[u32] = &[0x5A, 0x9F20];
+
+/// Parses a DOL.
+pub fn parse(mut data: &[u8]) -> Result<Dol> {
+    let mut dol = vec![];
+    while !data.is_empty() {
+        let (rest, tag) = ber::take_tag(data).map(|(i, v)| (i, ber::tag_to_u32(v)))?;
+        let (rest, len) = ber::take_len(rest)?;
+        data = rest;
+        dol.push((tag, len));
+    }
+    Ok(dol)
+}
+
+/// Knows the values of some tags.
+pub trait Provider {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>>;
+}
+
+impl Provider for [(u32, &[u8])] {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        (self.iter())
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| Cow::Borrowed(*v))
+    }
+}
+
+impl Provider for [(u32, Vec<u8>)] {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        (self.iter())
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| Cow::Borrowed(&v[..]))
+    }
+}
+
+impl<P: Provider + ?Sized> Provider for &P {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        (**self).value(tag)
+    }
+}
+
+impl<A: Provider, B: Provider> Provider for (A, B) {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        self.0.value(tag).or_else(|| self.1.value(tag))
+    }
+}
+
+impl<A: Provider, B: Provider, C: Provider> Provider for (A, B, C) {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        (self.0.value(tag))
+            .or_else(|| self.1.value(tag))
+            .or_else(|| self.2.value(tag))
+    }
+}
+
+/// Fills in a DOL from `provider`.
+pub fn fill(dol: &[(u32, usize)], provider: &impl Provider) -> Vec<u8> {
+    let mut data = vec![];
+    for &(tag, len) in dol {
+        let value = provider.value(tag).unwrap_or_default();
+        data.extend(fit(tag, &value, len));
+    }
+    data
+}
+
+/// Cuts or pads a value to `len`, according to its format. EMV Book 3, 5.4.
+pub fn fit(tag: u32, value: &[u8], len: usize) -> Vec<u8> {
+    if NUMERIC.contains(&tag) {
+        let pad = len.saturating_sub(value.len());
+        let cut = value.len().saturating_sub(len);
+        return [&vec![0; pad][..], &value[cut..]].concat();
+    }
+    let pad = if COMPRESSED_NUMERIC.contains(&tag) {
+        0xFF
+    } else {
+        0x00
+    };
+    (value.iter().copied())
+        .chain(std::iter::repeat(pad))
+        .take(len)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // TTQ, Amount, Unpredictable Number.
+        assert_eq!(
+            parse(&[0x9F, 0x66, 0x04, 0x9F, 0x02, 0x06, 0x9F, 0x37, 0x04]).unwrap(),
+            vec![(0x9F66, 4), (0x9F02, 6), (0x9F37, 4)]
+        );
+        assert_eq!(parse(&[]).unwrap(), vec![]);
+        assert!(parse(&[0x9F]).is_err());
+    }
+
+    #[test]
+    fn test_fill() {
+        let data: &[(u32, &[u8])] = &[
+            (0x9F02, &[0x00, 0x00, 0x00, 0x00, 0x12, 0x34]),
+            (0x9F37, &[0x12, 0x34, 0x56, 0x78]),
+            (0x5A, &[0x47, 0x61]),
+        ];
+        let more = [(0x9F37, vec![0xFF; 4]), (0x9C, vec![0x00])];
+        let dol = [(0x9F02, 4), (0x9F37, 2), (0x5A, 3), (0x9C, 1), (0x95, 5)];
+        assert_eq!(
+            fill(&dol, &(data, &more[..])),
+            vec![
+                0x00, 0x00, 0x12, 0x34, // Numeric, so the left's cut off.
+                0x12, 0x34, // Everything else is cut on the right.
+                0x47, 0x61, 0xFF, // Compressed numeric is padded with F.
+                0x00, // From the second provider.
+                0x00, 0x00, 0x00, 0x00, 0x00, // From nowhere.
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit(0x9F02, &[0x12, 0x34], 4), vec![0x00, 0x00, 0x12, 0x34]);
+        assert_eq!(fit(0x9F66, &[0x36], 2), vec![0x36, 0x00]);
+    }
+}
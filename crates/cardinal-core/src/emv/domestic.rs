@@ -0,0 +1,120 @@
+//! Domestic debit schemes, and the ways their directory entries bend EMV Book 1.
+//!
+//! A directory entry (0x61) is supposed to hold an ADF name, a label, maybe a preferred
+//! name and a priority, and anything else in a Directory Discretionary Template (0x73);
+//! Book 1, 12.2.3. Plenty of regional schemes didn't get the memo: they list several ADF
+//! names in one entry, put their proprietary tags (and the Issuer Code Table Index) right
+//! in the entry, or leave a 0x73 hanging after the entry instead of inside it.
+//!
+//! None of that is a reason to lose data, so [super::DirectoryApplication::parse_all] keeps
+//! it all, wherever it is. For schemes listed here, that's just how their cards are, and it
+//! isn't worth a warning; for anyone else, it still is.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A domestic scheme whose cards are known to have quirky directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DomesticScheme {
+    /// Canada.
+    Interac,
+    /// Germany.
+    Girocard,
+    /// France.
+    CartesBancaires,
+    /// Denmark.
+    Dankort,
+    /// Norway.
+    BankAxept,
+    /// Australia.
+    Eftpos,
+    /// India.
+    RuPay,
+    /// Russia.
+    Mir,
+}
+
+impl DomesticScheme {
+    /// Every scheme we know about.
+    pub const ALL: &'static [Self] = &[
+        Self::Interac,
+        Self::Girocard,
+        Self::CartesBancaires,
+        Self::Dankort,
+        Self::BankAxept,
+        Self::Eftpos,
+        Self::RuPay,
+        Self::Mir,
+    ];
+
+    /// The scheme's RID.
+    pub fn rid(&self) -> &'static [u8; 5] {
+        match self {
+            Self::Interac => &[0xA0, 0x00, 0x00, 0x02, 0x77],
+            Self::Girocard => &[0xA0, 0x00, 0x00, 0x03, 0x59],
+            Self::CartesBancaires => &[0xA0, 0x00, 0x00, 0x00, 0x42],
+            Self::Dankort => &[0xA0, 0x00, 0x00, 0x01, 0x21],
+            Self::BankAxept => &[0xD5, 0x78, 0x00, 0x00, 0x02],
+            Self::Eftpos => &[0xA0, 0x00, 0x00, 0x03, 0x84],
+            Self::RuPay => &[0xA0, 0x00, 0x00, 0x05, 0x24],
+            Self::Mir => &[0xA0, 0x00, 0x00, 0x06, 0x58],
+        }
+    }
+
+    /// Works out the scheme from an AID (or ADF name), by its RID.
+    pub fn from_aid(aid: &[u8]) -> Option<Self> {
+        let rid = aid.get(..5)?;
+        Self::ALL.iter().copied().find(|s| s.rid() == rid)
+    }
+}
+
+impl std::fmt::Display for DomesticScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interac => write!(f, "Interac"),
+            Self::Girocard => write!(f, "girocard"),
+            Self::CartesBancaires => write!(f, "Cartes Bancaires"),
+            Self::Dankort => write!(f, "Dankort"),
+            Self::BankAxept => write!(f, "BankAxept"),
+            Self::Eftpos => write!(f, "eftpos"),
+            Self::RuPay => write!(f, "RuPay"),
+            Self::Mir => write!(f, "Mir"),
+        }
+    }
+}
+
+/// Tags that belong in a Directory Discretionary Template (0x73), but turn up straight in
+/// the entry; they're moved into the template, where everything else expects them.
+pub const LOOSE_TAGS: &[&[u8]] = &[
+    &[0x9F, 0x0A], // Application Selection Registered Proprietary Data.
+    &[0x42],       // Issuer Identification Number.
+    &[0x5F, 0x53], // IBAN.
+    &[0x5F, 0x54], // Bank Identifier Code.
+    &[0x5F, 0x55], // Issuer Country Code (alpha2).
+    &[0x5F, 0x56], // Issuer Country Code (alpha3).
+    &[0x5F, 0x2D], // Language Preference.
+    &[0xBF, 0x0C], // FCI Issuer Discretionary Data, which is the wrong template entirely.
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_aid() {
+        assert_eq!(
+            DomesticScheme::from_aid(&[0xA0, 0x00, 0x00, 0x02, 0x77, 0x10, 0x10]),
+            Some(DomesticScheme::Interac)
+        );
+        assert_eq!(
+            DomesticScheme::from_aid(&[0xA0, 0x00, 0x00, 0x03, 0x59, 0x10, 0x10, 0x02, 0x80]),
+            Some(DomesticScheme::Girocard)
+        );
+        assert_eq!(
+            DomesticScheme::from_aid(&[0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10]),
+            None
+        );
+        assert_eq!(DomesticScheme::from_aid(&[0xA0, 0x00]), None);
+    }
+}
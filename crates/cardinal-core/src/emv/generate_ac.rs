@@ -0,0 +1,278 @@
+//! GENERATE AC: asking the card for an Application Cryptogram. EMV Book 3, 6.5.5 and 9.
+//!
+//! The terminal hands the application whatever its CDOL (Card Risk Management Data Object
+//! List) asks for, and says what it'd like: to decline offline (AAC), approve offline (TC),
+//! or go online (ARQC). The card answers with the cryptogram it decided on, which might
+//! not be the one asked for, signed over the transaction data with a key only the issuer
+//! knows. A second GENERATE AC (with CDOL2) finishes off a transaction that went online.
+//!
+//! Every one of these is a transaction as far as the card is concerned: the ATC goes up,
+//! and declines count towards the card's offline limits, so a few too many can leave it
+//! wanting to go online (or refusing outright) until it next sees the issuer. Like GET
+//! PROCESSING OPTIONS, it's behind the `write` feature.
+
+use super::dol::{self, Dol, Provider};
+use super::gpo;
+use crate::{ber, util, CardTransport, Error, HexVec, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// What kind of cryptogram to ask for, or the card gave back (the top two bits of P1, or
+/// of the Cryptogram Information Data).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CryptogramType {
+    /// Application Authentication Cryptogram: transaction declined.
+    AAC,
+    /// Transaction Certificate: transaction approved.
+    TC,
+    /// Authorisation Request Cryptogram: go online, and ask the issuer.
+    ARQC,
+    /// 11, which is reserved.
+    RFU,
+}
+
+impl CryptogramType {
+    pub fn bits(self) -> u8 {
+        match self {
+            Self::AAC => 0x00,
+            Self::TC => 0x40,
+            Self::ARQC => 0x80,
+            Self::RFU => 0xC0,
+        }
+    }
+
+    pub fn from_bits(v: u8) -> Self {
+        match v & 0xC0 {
+            0x00 => Self::AAC,
+            0x40 => Self::TC,
+            0x80 => Self::ARQC,
+            _ => Self::RFU,
+        }
+    }
+}
+
+impl std::fmt::Display for CryptogramType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AAC => write!(f, "AAC"),
+            Self::TC => write!(f, "TC"),
+            Self::ARQC => write!(f, "ARQC"),
+            Self::RFU => write!(f, "RFU"),
+        }
+    }
+}
+
+/// A GENERATE AC command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerateAC {
+    /// What to ask for.
+    pub request: CryptogramType,
+    /// Whether this is the second GENERATE AC in a transaction, which is filled in from
+    /// CDOL2 instead of CDOL1, and comes with an Authorisation Response Code.
+    pub second: bool,
+    /// Ask for Combined DDA/Application Cryptogram Generation, ie. a signature over the
+    /// response (which we don't check).
+    pub cda: bool,
+}
+
+impl GenerateAC {
+    /// Reference control parameter (P1).
+    pub fn p1(&self) -> u8 {
+        self.request.bits() | if self.cda { 0x10 } else { 0x00 }
+    }
+
+    /// Fills in a CDOL from `data` (see [dol]). A second GENERATE AC also gets an
+    /// Authorisation Response Code (8A), saying the terminal couldn't go online and
+    /// approved (Y3) or declined (Z3) it offline, depending on what's being asked for.
+    pub fn cdol_data(&self, cdol: &[(u32, usize)], data: &impl Provider) -> Vec<u8> {
+        let arc: &[u8] = match (self.second, self.request) {
+            (false, _) => &[],
+            (true, CryptogramType::TC) => b"Y3",
+            (true, _) => b"Z3",
+        };
+        dol::fill(cdol, &(&[(0x8A, arc)][..], data, gpo::DEFAULTS))
+    }
+
+    /// Sends it to the currently selected application (after GET PROCESSING OPTIONS, and
+    /// usually reading the records, which is where the CDOLs live; see [cdols]).
+    pub fn call(
+        &self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+        cdol: &[(u32, usize)],
+        data: &impl Provider,
+    ) -> Result<Cryptogram> {
+        let span = trace_span!("GENERATE AC", request = %self.request, second = self.second);
+        let _enter = span.enter();
+
+        let data = self.cdol_data(cdol, data);
+        let rsp = util::call_apdu(
+            card,
+            wbuf,
+            rbuf,
+            apdu::Command::new_with_payload_le(0x80, 0xAE, self.p1(), 0x00, 0x00, &data),
+        )?;
+        Cryptogram::parse(rsp)
+    }
+}
+
+/// What GENERATE AC said.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cryptogram {
+    /// 0x9F27: Cryptogram Information Data; what kind of cryptogram this is (see
+    /// [Cryptogram::kind]), and a few bits of advice.
+    pub cid: u8,
+    /// 0x9F36: Application Transaction Counter.
+    pub atc: u16,
+    /// 0x9F26: Application Cryptogram.
+    pub ac: HexVec,
+    /// 0x9F10: Issuer Application Data; proprietary, for the issuer.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub iad: Option<HexVec>,
+}
+
+impl Cryptogram {
+    /// What kind of cryptogram the card decided on.
+    pub fn kind(&self) -> CryptogramType {
+        CryptogramType::from_bits(self.cid)
+    }
+
+    /// Parses a response in either format: 0x80 (CID, ATC, AC and IAD, run together), or
+    /// 0x77 (a template with them, and maybe more, inside).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("Cryptogram");
+        let _enter = span.enter();
+
+        let (tag, value) = ber::iter(data).next().ok_or(Error::WrongTag {
+            expected: vec![0x77],
+            actual: vec![],
+        })??;
+        match tag {
+            [0x80] if value.len() >= 11 => Ok(Self {
+                cid: value[0],
+                atc: u16::from_be_bytes([value[1], value[2]]),
+                ac: value[3..11].into(),
+                iad: Some(HexVec::from(&value[11..])).filter(|v| !v.is_empty()),
+            }),
+            [0x77] => {
+                let (mut cid, mut atc, mut ac, mut iad) = (None, None, None, None);
+                for item in ber::iter(value) {
+                    match item? {
+                        (&[0x9F, 0x27], &[v]) => cid = Some(v),
+                        (&[0x9F, 0x36], &[a, b]) => atc = Some(u16::from_be_bytes([a, b])),
+                        (&[0x9F, 0x26], v) => ac = Some(v.into()),
+                        (&[0x9F, 0x10], v) => iad = Some(v.into()),
+                        (tag, v) => debug!(
+                            tag = hex::encode_upper(tag),
+                            value = hex::encode_upper(v),
+                            "Extra data in GENERATE AC response"
+                        ),
+                    }
+                }
+                // The CID, ATC and AC are mandatory; without them it's not much of an answer.
+                let missing = |tag: &[u8]| Error::WrongTag {
+                    expected: tag.to_vec(),
+                    actual: vec![],
+                };
+                Ok(Self {
+                    cid: cid.ok_or_else(|| missing(&[0x9F, 0x27]))?,
+                    atc: atc.ok_or_else(|| missing(&[0x9F, 0x36]))?,
+                    ac: ac.ok_or_else(|| missing(&[0x9F, 0x26]))?,
+                    iad,
+                })
+            }
+            tag => util::expect_tag(&[0x77], tag).map(|_| Self::default()),
+        }
+    }
+}
+
+/// Finds CDOL1 (8C) and CDOL2 (8D) in an application's records.
+pub fn cdols<'a>(records: impl IntoIterator<Item = &'a [u8]>) -> (Option<Dol>, Option<Dol>) {
+    let (mut cdol1, mut cdol2) = (None, None);
+    for record in records {
+        for (_, tag, value) in ber::iter_deep(record).flatten() {
+            match tag {
+                [0x8C] => cdol1 = dol::parse(value).ok(),
+                [0x8D] => cdol2 = dol::parse(value).ok(),
+                _ => {}
+            }
+        }
+    }
+    (cdol1, cdol2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emv::terminal::TerminalConfig;
+
+    #[test]
+    fn test_cdol_data() {
+        // Amount, Unpredictable Number, Authorisation Response Code.
+        let cdol = [(0x9F02, 6), (0x9F37, 4), (0x8A, 2)];
+        let config = TerminalConfig::default();
+        let mut cmd = GenerateAC {
+            request: CryptogramType::ARQC,
+            second: false,
+            cda: false,
+        };
+        assert_eq!(cmd.p1(), 0x80);
+        assert_eq!(
+            cmd.cdol_data(&cdol, &config),
+            vec![0, 0, 0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78, 0, 0]
+        );
+
+        cmd.request = CryptogramType::TC;
+        cmd.second = true;
+        cmd.cda = true;
+        assert_eq!(cmd.p1(), 0x50);
+        assert_eq!(cmd.cdol_data(&cdol, &config)[10..], *b"Y3");
+    }
+
+    #[test]
+    fn test_parse_cryptogram() {
+        // Format 1: ARQC, ATC 0x0012, an AC, then the IAD.
+        let format1 = [
+            0x80, 0x12, 0x80, 0x00, 0x12, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x06,
+            0x01, 0x0A, 0x03, 0xA0, 0x00, 0x00,
+        ];
+        let expected = Cryptogram {
+            cid: 0x80,
+            atc: 0x0012,
+            ac: vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08].into(),
+            iad: Some(vec![0x06, 0x01, 0x0A, 0x03, 0xA0, 0x00, 0x00].into()),
+        };
+        let cryptogram = Cryptogram::parse(&format1).unwrap();
+        assert_eq!(cryptogram, expected);
+        assert_eq!(cryptogram.kind(), CryptogramType::ARQC);
+
+        // Format 2: the same, in a template, in a different order.
+        let format2 = [
+            0x77, 0x1E, 0x9F, 0x36, 0x02, 0x00, 0x12, 0x9F, 0x27, 0x01, 0x80, 0x9F, 0x26, 0x08,
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x9F, 0x10, 0x07, 0x06, 0x01, 0x0A,
+            0x03, 0xA0, 0x00, 0x00,
+        ];
+        assert_eq!(Cryptogram::parse(&format2).unwrap(), expected);
+
+        // Too short, missing the AC, or not a response at all.
+        assert!(Cryptogram::parse(&format1[..8]).is_err());
+        assert!(Cryptogram::parse(&[0x77, 0x04, 0x9F, 0x27, 0x01, 0x00]).is_err());
+        assert!(Cryptogram::parse(&[0x6F, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_cdols() {
+        let record = [
+            0x70, 0x0C, 0x8C, 0x03, 0x9F, 0x02, 0x06, 0x8D, 0x05, 0x8A, 0x02, 0x9F, 0x37, 0x04,
+        ];
+        assert_eq!(
+            cdols([&record[..]]),
+            (Some(vec![(0x9F02, 6)]), Some(vec![(0x8A, 2), (0x9F37, 4)]))
+        );
+        assert_eq!(cdols([&[0x70, 0x00][..]]), (None, None));
+    }
+}
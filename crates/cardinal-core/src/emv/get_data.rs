@@ -0,0 +1,135 @@
+//! GET DATA, for what a card doesn't keep in its records: mostly counters, which change
+//! with every transaction. EMV Book 3, 6.5.7.
+//!
+//! Unlike GPO or GENERATE AC, asking doesn't change anything, so it's safe to do as often
+//! as you like; cards are free to say no (usually 6A88, "not found"), though, and plenty
+//! won't give out some of these.
+
+use crate::{ber, util, CardTransport, Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// 0x9F17: PIN Try Counter; how many wrong PINs are left before the card locks up.
+pub const PIN_TRY_COUNTER: u16 = 0x9F17;
+/// 0x9F36: Application Transaction Counter.
+pub const ATC: u16 = 0x9F36;
+/// 0x9F13: Last Online ATC Register; the ATC as of the last time the card went online.
+pub const LAST_ONLINE_ATC: u16 = 0x9F13;
+/// 0x9F4F: Log Format; a DOL for the entries in the transaction log.
+pub const LOG_FORMAT: u16 = 0x9F4F;
+
+/// Asks the currently selected application for a data object, and returns its value.
+pub fn get_data(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    tag: u16,
+) -> Result<Vec<u8>> {
+    let span = trace_span!("GET DATA", tag = format!("{:X}", tag));
+    let _enter = span.enter();
+
+    let [p1, p2] = tag.to_be_bytes();
+    let rsp = util::call_le(card, wbuf, rbuf, 0x80, 0xCA, p1, p2, 0x00)?;
+    // The response should be the whole data object, tag and all; some cards just send
+    // the value.
+    match ber::iter(rsp).next() {
+        Some(Ok((t, value))) if ber::tag_to_u32(t) == u32::from(tag) => Ok(value.to_vec()),
+        _ => {
+            debug!(
+                rsp = hex::encode_upper(rsp),
+                "GET DATA response isn't a TLV"
+            );
+            Ok(rsp.to_vec())
+        }
+    }
+}
+
+/// The counters that say how close a card is to locking up, or to insisting on going online.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Counters {
+    /// 0x9F17: PIN Try Counter.
+    pub pin_try_counter: Option<u8>,
+    /// 0x9F36: Application Transaction Counter.
+    pub atc: Option<u16>,
+    /// 0x9F13: Last Online ATC Register.
+    pub last_online_atc: Option<u16>,
+}
+
+impl Counters {
+    /// Asks the currently selected application for each of them. Ones it won't give out
+    /// are None; anything other than a status word is still an error.
+    pub fn get(card: &mut impl CardTransport, wbuf: &mut [u8], rbuf: &mut [u8]) -> Result<Self> {
+        let span = trace_span!("Counters");
+        let _enter = span.enter();
+
+        let mut get = |tag| match get_data(card, wbuf, rbuf, tag) {
+            Ok(v) => Ok(Some(v)),
+            Err(err @ Error::APDU(..)) => {
+                debug!(tag = format!("{:X}", tag), %err, "Card won't say");
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        };
+        Ok(Self {
+            pin_try_counter: get(PIN_TRY_COUNTER)?.and_then(|v| v.first().copied()),
+            atc: get(ATC)?.and_then(|v| Some(u16::from_be_bytes(v.try_into().ok()?))),
+            last_online_atc: (get(LAST_ONLINE_ATC)?)
+                .and_then(|v| Some(u16::from_be_bytes(v.try_into().ok()?))),
+        })
+    }
+
+    /// Transactions since the card last went online.
+    pub fn since_online(&self) -> Option<u16> {
+        Some(self.atc?.wrapping_sub(self.last_online_atc?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Has a PIN Try Counter and an ATC, but won't say when it last went online.
+    struct CounterCard;
+
+    impl CardTransport for CounterCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x80, 0xCA, 0x9F, 0x17, 0x00] => &[0x9F, 0x17, 0x01, 0x03, 0x90, 0x00],
+                // Just the value.
+                [0x80, 0xCA, 0x9F, 0x36, 0x00] => &[0x01, 0x2C, 0x90, 0x00],
+                _ => &[0x6A, 0x88],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_counters() {
+        let (mut wbuf, mut rbuf) = ([0; 16], [0; 64]);
+        let counters = Counters::get(&mut CounterCard, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(
+            counters,
+            Counters {
+                pin_try_counter: Some(3),
+                atc: Some(300),
+                last_online_atc: None,
+            }
+        );
+        assert_eq!(counters.since_online(), None);
+        assert_eq!(
+            Counters {
+                last_online_atc: Some(290),
+                ..counters
+            }
+            .since_online(),
+            Some(10)
+        );
+        assert!(matches!(
+            get_data(&mut CounterCard, &mut wbuf, &mut rbuf, LOG_FORMAT),
+            Err(Error::APDU(0x6A, 0x88))
+        ));
+    }
+}
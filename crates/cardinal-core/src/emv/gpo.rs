@@ -0,0 +1,278 @@
+//! GET PROCESSING OPTIONS, and the records it points at. EMV Book 3, 6.5.8 and 10.2.
+//!
+//! This is how a terminal starts a transaction: it hands the application whatever its PDOL
+//! asked for, and gets back what the application can do (the AIP) and which records to
+//! read (the AFL). It's the only way to find out where an application keeps its data.
+//!
+//! It isn't free, though: most cards count it as a transaction (the ATC goes up), which is
+//! why this is behind the `write` feature, and why nothing does it unless you ask.
+
+use super::dol::{self, Provider};
+use super::terminal::TerminalConfig;
+use super::track::MagStripe;
+use crate::iso7816;
+use crate::{ber, util, warnings, CardTransport, Error, Result};
+use scroll::Pwrite;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// What's left for DOLs that ask for it, after the terminal's had its say: an
+/// Unpredictable Number.
+pub(crate) const DEFAULTS: &[(u32, &[u8])] = &[(0x9F37, &[0x12, 0x34, 0x56, 0x78])];
+
+/// Terminal Transaction Qualifiers for a terminal that only does mag-stripe mode (MSD),
+/// for legacy cards that won't talk to anything else.
+const TTQ_MSD: &[(u32, &[u8])] = &[(0x9F66, &[0x86, 0x00, 0x00, 0x00])];
+
+/// Fills in a PDOL, as the default [TerminalConfig]: an attended terminal in the UK,
+/// for nothing.
+pub fn pdol_data(pdol: &[(u32, usize)]) -> Vec<u8> {
+    dol::fill(pdol, &(TerminalConfig::default(), DEFAULTS))
+}
+
+/// One entry in an Application File Locator: which records in which file to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AflEntry {
+    pub sfi: u8,
+    pub first: u8,
+    pub last: u8,
+    /// How many records (from the first) are used for offline data authentication.
+    pub offline_auth: u8,
+}
+
+/// What GET PROCESSING OPTIONS said.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProcessingOptions {
+    /// 0x82: Application Interchange Profile; what the application supports.
+    pub aip: u16,
+    /// 0x94: Application File Locator.
+    pub afl: Vec<AflEntry>,
+    /// Track data, from a card in mag-stripe mode (which may not have an AFL at all).
+    pub mag_stripe: Option<MagStripe>,
+}
+
+impl ProcessingOptions {
+    /// Parses a response in either format: 0x80 (AIP and AFL, run together), or 0x77 (a
+    /// template with them, and maybe more, inside).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("ProcessingOptions");
+        let _enter = span.enter();
+
+        let (tag, value) = ber::iter(data).next().ok_or(Error::WrongTag {
+            expected: vec![0x77],
+            actual: vec![],
+        })??;
+        let (aip, afl) = match tag {
+            [0x80] if value.len() >= 2 => (&value[..2], &value[2..]),
+            [0x77] => {
+                let (mut aip, mut afl) = (None, &[][..]);
+                for item in ber::iter(value) {
+                    match item? {
+                        (&[0x82], v) => aip = Some(v),
+                        (&[0x94], v) => afl = v,
+                        (tag, v) => debug!(
+                            tag = hex::encode_upper(tag),
+                            value = hex::encode_upper(v),
+                            "Extra data in GPO response"
+                        ),
+                    }
+                }
+                (aip.unwrap_or_default(), afl)
+            }
+            tag => return util::expect_tag(&[0x77], tag).map(|_| Self::default()),
+        };
+        if afl.len() % 4 != 0 {
+            warnings::other("ProcessingOptions", "AFL isn't a multiple of 4 bytes");
+        }
+        Ok(Self {
+            aip: match aip {
+                [a, b] => u16::from_be_bytes([*a, *b]),
+                _ => 0,
+            },
+            afl: (afl.chunks_exact(4))
+                .map(|e| AflEntry {
+                    sfi: e[0] >> 3,
+                    first: e[1],
+                    last: e[2],
+                    offline_auth: e[3],
+                })
+                .collect(),
+            mag_stripe: MagStripe::parse(data),
+        })
+    }
+
+    /// Sends GET PROCESSING OPTIONS to the currently selected application, with the PDOL
+    /// (if it has one) filled in as `terminal`.
+    ///
+    /// If the card says the conditions of use aren't satisfied (6985), and it asked for
+    /// Terminal Transaction Qualifiers, it might be a legacy card that only does mag-stripe
+    /// mode: that gets another go, with a terminal that only does MSD. (This counts as a
+    /// second transaction, on cards that count the first.)
+    pub fn get(
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+        pdol: Option<&[(u32, usize)]>,
+        terminal: &TerminalConfig,
+    ) -> Result<Self> {
+        let pdol = pdol.unwrap_or_default();
+        Self::parse(&Self::get_raw(card, wbuf, rbuf, pdol, terminal)?)
+    }
+
+    /// Like [ProcessingOptions::get], but with the PDOL filled in from `data` (see [dol]),
+    /// and the raw response, for anything that wants more out of it than this has room for
+    /// (eg. the fDDA signature some cards hand back).
+    pub fn get_raw(
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+        pdol: &[(u32, usize)],
+        data: &impl Provider,
+    ) -> Result<Vec<u8>> {
+        let span = trace_span!("GET PROCESSING OPTIONS");
+        let _enter = span.enter();
+
+        let data = (data, DEFAULTS);
+        match Self::call(card, wbuf, rbuf, &dol::fill(pdol, &data)) {
+            Err(Error::APDU(0x69, 0x85)) if pdol.iter().any(|(tag, _)| *tag == 0x9F66) => {
+                debug!("GPO refused; trying again as a mag-stripe only terminal");
+                Self::call(card, wbuf, rbuf, &dol::fill(pdol, &(TTQ_MSD, data)))
+            }
+            res => res,
+        }
+    }
+
+    fn call(
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut payload = vec![0; data.len() + 4];
+        let len = payload.pwrite(ber::TV(&[0x83], data), 0)?;
+        payload.truncate(len);
+        let rsp = util::call_apdu(
+            card,
+            wbuf,
+            rbuf,
+            apdu::Command::new_with_payload_le(0x80, 0xA8, 0x00, 0x00, 0x00, &payload),
+        )?;
+        Ok(rsp.to_vec())
+    }
+
+    /// Reads every record the AFL points at, as (SFI, record number, data).
+    pub fn read_records(
+        &self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<Vec<(u8, u8, Vec<u8>)>> {
+        let mut records = vec![];
+        for entry in self.afl.iter() {
+            for num in entry.first..=entry.last {
+                debug!(sfi = entry.sfi, num, "Reading record...");
+                let rsp = iso7816::ReadRecord {
+                    sfi: entry.sfi,
+                    id: iso7816::RecordID::Number(num),
+                }
+                .call(card, wbuf, rbuf)?;
+                records.push((entry.sfi, num, rsp.data.to_vec()));
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pdol_data() {
+        assert_eq!(
+            pdol_data(&[(0x9F66, 4), (0x9F02, 6), (0x9F1A, 1)]),
+            // The country code's numeric, so a short one loses its left end.
+            vec![0x36, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0x26]
+        );
+        assert_eq!(pdol_data(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_parse_processing_options() {
+        // Format 1: AIP 1980, then AFL entries for SFI 1 (records 1-1) and SFI 2 (1-2).
+        let format1 = [
+            0x80, 0x0A, 0x19, 0x80, 0x08, 0x01, 0x01, 0x00, 0x10, 0x01, 0x02, 0x01,
+        ];
+        let expected = ProcessingOptions {
+            aip: 0x1980,
+            afl: vec![
+                AflEntry {
+                    sfi: 1,
+                    first: 1,
+                    last: 1,
+                    offline_auth: 0,
+                },
+                AflEntry {
+                    sfi: 2,
+                    first: 1,
+                    last: 2,
+                    offline_auth: 1,
+                },
+            ],
+            mag_stripe: None,
+        };
+        assert_eq!(ProcessingOptions::parse(&format1).unwrap(), expected);
+
+        // Format 2: the same, in a template, with an ATC thrown in.
+        let format2 = [
+            0x77, 0x13, 0x82, 0x02, 0x19, 0x80, 0x94, 0x08, 0x08, 0x01, 0x01, 0x00, 0x10, 0x01,
+            0x02, 0x01, 0x9F, 0x36, 0x02, 0x00, 0x01,
+        ];
+        assert_eq!(ProcessingOptions::parse(&format2).unwrap(), expected);
+
+        assert!(ProcessingOptions::parse(&[0x6F, 0x00]).is_err());
+    }
+
+    /// A legacy Visa card that only does MSD, and hands back track 2 with no AFL.
+    struct MsdCard;
+
+    impl CardTransport for MsdCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x80, 0xA8, 0x00, 0x00, 0x06, 0x83, 0x04, 0x86, ..] => &[
+                    0x77, 0x12, 0x82, 0x02, 0x00, 0x80, 0x57, 0x0C, 0x47, 0x61, 0x73, 0x90, 0x01,
+                    0x01, 0x00, 0x10, 0xD2, 0x71, 0x22, 0x01, 0x90, 0x00,
+                ],
+                [0x80, 0xA8, ..] => &[0x69, 0x85],
+                _ => &[0x6D, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_get_mag_stripe() {
+        let (mut wbuf, mut rbuf) = ([0; 32], [0; 64]);
+        let config = TerminalConfig::default();
+        let opts = ProcessingOptions::get(
+            &mut MsdCard,
+            &mut wbuf,
+            &mut rbuf,
+            Some(&[(0x9F66, 4)]),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(opts.aip, 0x0080);
+        assert!(opts.afl.is_empty());
+        let track2 = opts.mag_stripe.unwrap().track2.unwrap();
+        assert_eq!(track2.pan, "4761739001010010");
+        assert_eq!(track2.service_code, "201");
+
+        // No TTQ in the PDOL, so no reason to think MSD would help.
+        assert!(ProcessingOptions::get(&mut MsdCard, &mut wbuf, &mut rbuf, None, &config).is_err());
+    }
+}
@@ -0,0 +1,545 @@
+//! Offline Data Authentication: checking that a card's data was signed by its issuer, and
+//! (for DDA) that the card has a key of its own the issuer vouched for. EMV Book 2.
+//!
+//! Every signature here is RSA with message recovery: "decrypting" it with the public key
+//! gives back a block that starts with 6A and a format byte, ends with a SHA-1 hash and
+//! BC, and has whatever's being vouched for in between. The chain starts with one of the
+//! payment scheme's CA keys, which terminals get from their acquirer; they're public, but
+//! not ours to ship, so they come from a file (see [CaKeys::parse]).
+//!
+//! - SDA: the issuer signed the card's static data (the records the AFL says are for
+//!   offline authentication), in the Signed Static Application Data (93).
+//! - DDA: the issuer signed the card's public key (and the static data, with it), and the
+//!   card signs something the terminal made up with it, to show it's not a copy. fDDA is
+//!   the contactless version, where the card does it unasked, during GPO.
+//! - CDA: like DDA, but the card signs the cryptogram from GENERATE AC.
+
+#[cfg(feature = "write")]
+use super::dol::{self, Provider};
+use crate::{ber, Error, Result};
+#[cfg(feature = "write")]
+use crate::{util, CardTransport};
+use chrono::{Datelike, NaiveDate};
+use num_bigint::BigUint;
+use sha1::{Digest, Sha1};
+use std::path::Path;
+use tracing::{debug, trace_span};
+
+/// Why a signature or certificate didn't check out.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum OdaError {
+    #[error("no CA public key {index:02X} for RID {rid}")]
+    NoCaKey { rid: String, index: u8 },
+    #[error("the card has no {0}")]
+    Missing(&'static str),
+    #[error("{0} isn't as long as the key it's signed with")]
+    WrongLength(&'static str),
+    #[error("{0} doesn't recover to anything sensible; wrong key, or not signed at all")]
+    Garbled(&'static str),
+    #[error("{0} says it's hashed with algorithm {1:02X}, not SHA-1")]
+    UnknownHash(&'static str, u8),
+    #[error("{0}'s hash doesn't match; something's been changed since it was signed")]
+    HashMismatch(&'static str),
+    #[error("{0} expired at the end of {1}")]
+    Expired(&'static str, String),
+    #[error("{0} is for a different card (PAN {1})")]
+    WrongPan(&'static str, String),
+}
+
+/// A payment scheme's CA public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaKey {
+    /// Registered Application Provider Identifier: the first 5 bytes of the AID.
+    pub rid: Vec<u8>,
+    /// Which of the scheme's keys it is; the card says which one it wants (8F).
+    pub index: u8,
+    pub key: PublicKey,
+}
+
+/// CA public keys, for the schemes you care about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaKeys(pub Vec<CaKey>);
+
+impl CaKeys {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parses `rid,index,exponent,modulus` lines, all in hex (eg. `A000000003,92,03,996A...`).
+    /// Anything else is skipped, including a header and comments.
+    pub fn parse(s: &str) -> Self {
+        let span = trace_span!("CaKeys::parse");
+        let _enter = span.enter();
+
+        let mut keys = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let fields: Vec<_> = line.split(',').map(|v| hex::decode(v.trim())).collect();
+            match fields.as_slice() {
+                [Ok(rid), Ok(index), Ok(exponent), Ok(modulus)] if index.len() == 1 => {
+                    keys.push(CaKey {
+                        rid: rid.clone(),
+                        index: index[0],
+                        key: PublicKey {
+                            modulus: modulus.clone(),
+                            exponent: exponent.clone(),
+                        },
+                    })
+                }
+                _ => debug!(line = i + 1, "Not a CA key, skipping"),
+            }
+        }
+        Self(keys)
+    }
+
+    pub fn find(&self, rid: &[u8], index: u8) -> Option<&CaKey> {
+        self.0.iter().find(|k| k.rid == rid && k.index == index)
+    }
+}
+
+/// An RSA public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub modulus: Vec<u8>,
+    pub exponent: Vec<u8>,
+}
+
+impl PublicKey {
+    /// Recovers a signature, and checks that it's in `format`, and that its hash covers the
+    /// rest of it, then `extra`. Returns the whole recovered block.
+    fn open(
+        &self,
+        what: &'static str,
+        sig: &[u8],
+        format: u8,
+        extra: &[&[u8]],
+    ) -> std::result::Result<Vec<u8>, OdaError> {
+        let n = self.modulus.len();
+        if sig.len() != n || n < 22 {
+            return Err(OdaError::WrongLength(what));
+        }
+        let modulus = BigUint::from_bytes_be(&self.modulus);
+        let x =
+            BigUint::from_bytes_be(sig).modpow(&BigUint::from_bytes_be(&self.exponent), &modulus);
+        let mut x = x.to_bytes_be();
+        // A leading zero wouldn't survive the round trip through a number.
+        x.splice(0..0, std::iter::repeat_n(0, n.saturating_sub(x.len())));
+        if x.len() != n || x[0] != 0x6A || x[1] != format || x[n - 1] != 0xBC {
+            return Err(OdaError::Garbled(what));
+        }
+        let mut hash = Sha1::new();
+        hash.update(&x[1..n - 21]);
+        for data in extra {
+            hash.update(data);
+        }
+        if hash.finalize()[..] != x[n - 21..n - 1] {
+            return Err(OdaError::HashMismatch(what));
+        }
+        Ok(x)
+    }
+}
+
+/// What's needed to check a certificate for a public key: the certificate, the part of the
+/// key that didn't fit in it, and the key's exponent.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Certificate<'a> {
+    pub cert: &'a [u8],
+    pub remainder: &'a [u8],
+    pub exponent: &'a [u8],
+}
+
+/// Recovers the issuer's public key from its certificate (90, 92, 9F32), signed by `ca`.
+/// The certificate has to be for the card's PAN (well, its first few digits), and not have
+/// expired by `today`. EMV Book 2, 5.3.
+pub fn issuer_key(
+    ca: &PublicKey,
+    cert: Certificate,
+    pan: &[u8],
+    today: NaiveDate,
+) -> std::result::Result<PublicKey, OdaError> {
+    const WHAT: &str = "Issuer Public Key Certificate";
+    let x = ca.open(WHAT, cert.cert, 0x02, &[cert.remainder, cert.exponent])?;
+    check_hash_algorithm(WHAT, x[11])?;
+    check_pan(WHAT, &x[2..6], pan)?;
+    check_expiry(WHAT, [x[6], x[7]], today)?;
+    Ok(PublicKey {
+        modulus: modulus(&x[15..x.len() - 21], cert.remainder, x[13]),
+        exponent: cert.exponent.to_vec(),
+    })
+}
+
+/// Recovers the card's public key from its certificate (9F46, 9F48, 9F47), signed by the
+/// issuer; the hash covers the static data too, so that's checked at the same time. EMV
+/// Book 2, 6.4.
+pub fn icc_key(
+    issuer: &PublicKey,
+    cert: Certificate,
+    static_data: &[u8],
+    pan: &[u8],
+    today: NaiveDate,
+) -> std::result::Result<PublicKey, OdaError> {
+    const WHAT: &str = "ICC Public Key Certificate";
+    let extra = [cert.remainder, cert.exponent, static_data];
+    let x = issuer.open(WHAT, cert.cert, 0x04, &extra)?;
+    check_hash_algorithm(WHAT, x[17])?;
+    // This one's the whole PAN, not just the start of it.
+    if digits(&x[2..12]) != digits(pan) {
+        return Err(OdaError::WrongPan(WHAT, digits(&x[2..12])));
+    }
+    check_expiry(WHAT, [x[12], x[13]], today)?;
+    Ok(PublicKey {
+        modulus: modulus(&x[21..x.len() - 21], cert.remainder, x[19]),
+        exponent: cert.exponent.to_vec(),
+    })
+}
+
+/// Checks the Signed Static Application Data (93), for SDA. EMV Book 2, 5.4.
+pub fn verify_sda(
+    issuer: &PublicKey,
+    ssad: &[u8],
+    static_data: &[u8],
+) -> std::result::Result<(), OdaError> {
+    const WHAT: &str = "Signed Static Application Data";
+    let x = issuer.open(WHAT, ssad, 0x03, &[static_data])?;
+    check_hash_algorithm(WHAT, x[2])
+}
+
+/// Checks the Signed Dynamic Application Data (9F4B), for DDA or fDDA, over what the
+/// terminal sent (see [fdda_terminal_data]). Returns the ICC Dynamic Data the card signed.
+/// EMV Book 2, 6.5.
+pub fn verify_dda(
+    icc: &PublicKey,
+    sdad: &[u8],
+    terminal_data: &[u8],
+) -> std::result::Result<Vec<u8>, OdaError> {
+    const WHAT: &str = "Signed Dynamic Application Data";
+    let x = icc.open(WHAT, sdad, 0x05, &[terminal_data])?;
+    check_hash_algorithm(WHAT, x[2])?;
+    let len = usize::from(x[3]);
+    x.get(4..4 + len)
+        .filter(|_| 4 + len <= x.len() - 21)
+        .map(|v| v.to_vec())
+        .ok_or(OdaError::Garbled(WHAT))
+}
+
+/// What a DDOL (9F49) asks for if the card doesn't have one: just an Unpredictable Number.
+/// EMV Book 3, 10.3.
+pub const DEFAULT_DDOL: &[(u32, usize)] = &[(0x9F37, 4)];
+
+/// Sends INTERNAL AUTHENTICATE, for DDA: hands the card what its DDOL asked for (or
+/// [DEFAULT_DDOL]), and returns the Signed Dynamic Application Data it signed that with,
+/// and the data, for [verify_dda]. EMV Book 3, 6.5.9.
+#[cfg(feature = "write")]
+pub fn internal_authenticate(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    ddol: Option<&[(u32, usize)]>,
+    data: &impl Provider,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let span = trace_span!("INTERNAL AUTHENTICATE");
+    let _enter = span.enter();
+
+    let terminal_data = dol::fill(ddol.unwrap_or(DEFAULT_DDOL), data);
+    let rsp = util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload_le(0x00, 0x88, 0x00, 0x00, 0x00, &terminal_data),
+    )?;
+    Ok((parse_sdad(rsp)?, terminal_data))
+}
+
+/// Digs the Signed Dynamic Application Data out of an INTERNAL AUTHENTICATE response,
+/// in either format: 0x80 (just the SDAD), or 0x77 (a template, with it in 0x9F4B).
+pub fn parse_sdad(data: &[u8]) -> Result<Vec<u8>> {
+    let span = trace_span!("parse_sdad");
+    let _enter = span.enter();
+
+    let (tag, value) = ber::iter(data).next().ok_or(Error::WrongTag {
+        expected: vec![0x77],
+        actual: vec![],
+    })??;
+    match tag {
+        [0x80] => Ok(value.to_vec()),
+        [0x77] => {
+            for item in ber::iter(value) {
+                if let (&[0x9F, 0x4B], v) = item? {
+                    return Ok(v.to_vec());
+                }
+            }
+            Err(Error::WrongTag {
+                expected: vec![0x9F, 0x4B],
+                actual: vec![],
+            })
+        }
+        _ => Err(Error::WrongTag {
+            expected: vec![0x77],
+            actual: tag.to_vec(),
+        }),
+    }
+}
+
+/// What fDDA's signature covers, on the terminal's side: the Unpredictable Number, and for
+/// fDDA version 01 (which has Card Authentication Related Data, 9F69), the amount and
+/// currency too. (Visa's Contactless Payment Spec, 4.2.)
+pub fn fdda_terminal_data(
+    un: &[u8],
+    amount: &[u8],
+    currency: &[u8],
+    card: Option<&[u8]>,
+) -> Vec<u8> {
+    match card {
+        Some(card) if card.first() == Some(&0x01) => [un, amount, currency, card].concat(),
+        _ => un.to_vec(),
+    }
+}
+
+/// Puts together the static data to be authenticated: the records the AFL says are for
+/// offline authentication (just what's inside the 70 template, for SFIs 1-10, or the whole
+/// thing for 11-30), then the values of the tags in the SDA Tag List (9F4A), which can only
+/// be the AIP (82). EMV Book 3, 10.3.
+pub fn static_data(
+    records: &[(u8, Vec<u8>)],
+    tag_list: Option<&[u8]>,
+    aip: u16,
+) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    for (sfi, record) in records {
+        match sfi {
+            1..=10 => match ber::iter(record).next() {
+                Some(Ok(([0x70], value))) => data.extend(value),
+                _ => data.extend(record), // Invalid; the hash will tell.
+            },
+            _ => data.extend(record),
+        }
+    }
+    if tag_list == Some(&[0x82]) {
+        data.extend(aip.to_be_bytes());
+    }
+    Ok(data)
+}
+
+/// A public key's modulus, from the leftmost digits in its certificate and the remainder
+/// (92, 9F48), if it didn't all fit.
+fn modulus(leftmost: &[u8], remainder: &[u8], len: u8) -> Vec<u8> {
+    let len = usize::from(len);
+    match len <= leftmost.len() {
+        true => leftmost[..len].to_vec(),
+        false => [leftmost, remainder].concat(),
+    }
+}
+
+fn check_hash_algorithm(what: &'static str, algo: u8) -> std::result::Result<(), OdaError> {
+    match algo {
+        0x01 => Ok(()),
+        _ => Err(OdaError::UnknownHash(what, algo)),
+    }
+}
+
+/// Checks that the PAN starts with the digits in `prefix` (BCD, padded with F).
+fn check_pan(what: &'static str, prefix: &[u8], pan: &[u8]) -> std::result::Result<(), OdaError> {
+    let prefix = digits(prefix);
+    match digits(pan).starts_with(&prefix) {
+        true => Ok(()),
+        false => Err(OdaError::WrongPan(what, prefix)),
+    }
+}
+
+/// Checks a certificate's expiry date (MMYY); it's good until the end of the month.
+fn check_expiry(
+    what: &'static str,
+    mmyy: [u8; 2],
+    today: NaiveDate,
+) -> std::result::Result<(), OdaError> {
+    let bcd = |v: u8| u32::from(v >> 4) * 10 + u32::from(v & 0x0F);
+    let (month, year) = (bcd(mmyy[0]), 2000 + bcd(mmyy[1]) as i32);
+    match (year, month) >= (today.year(), today.month()) {
+        true => Ok(()),
+        false => Err(OdaError::Expired(what, format!("{:04}-{:02}", year, month))),
+    }
+}
+
+/// BCD digits, up to the first bit of padding.
+fn digits(bcd: &[u8]) -> String {
+    (bcd.iter())
+        .flat_map(|b| [b >> 4, b & 0x0F])
+        .take_while(|&d| d < 10)
+        .map(|d| char::from(b'0' + d))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test keys, with their private exponents; all with a public exponent of 3.
+    const CA: (&str, &str) = (
+        "F90F374855A917163A01D692A8B0AA43F0F67FA824DA0EDEA8B6552C917D9ADE9024AE0BDB2305DB3BB3F2E69C8E9C6AE80671F9BA014C812644723FB44A3D4B",
+        "A60A24DAE3C60F6426ABE461C5CB1C2D4B4EFFC56DE6B49470798E1DB653BC930F6AB9E6191E5ABE03B683E1947A19E50CE96EEC2B3CAB656E55AB8FB6BCD73B",
+    );
+    const ISSUER: (&str, &str) = (
+        "DEA70DF5429AE3A04BB6EE954BAEA421C3D7AFC4230A2A8EBED434F47BC56F6F5C8DB2DA834B898E25BD276C28FA58DFCBB45C0FB536C60D",
+        "946F5EA381BC97C03279F46387C9C2C12D3A752D6CB171B47F38234C698559F8C124109C464516A2CBDE3BFA2F60E18F8F2E8913921E22EB",
+    );
+    const ICC: (&str, &str) = (
+        "B9926B301FAF9DDF702A88A254871B2629C5D34CA453F17E2877B12CF904BA7ADD20E3B583BEE88A79D0BD94275C575D",
+        "7BB6F220151FBE94F571B06C385A1219712E8CDDC2E2A0FDA2E77A7C3CB7A3D503D7B696FC675F6DB6DF463A0DA1F793",
+    );
+    const PAN: &[u8] = &[0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x00, 0x10];
+
+    fn public((n, _): (&str, &str)) -> PublicKey {
+        PublicKey {
+            modulus: hex::decode(n).unwrap(),
+            exponent: vec![0x03],
+        }
+    }
+
+    /// Signs `body` (everything between the header and the hash) with a private key,
+    /// hashing `extra` along with it, and padding it out to the key's length with BB.
+    fn sign((n, d): (&str, &str), body: &[u8], extra: &[&[u8]]) -> Vec<u8> {
+        let len = n.len() / 2;
+        let mut body = body.to_vec();
+        body.resize(len - 22, 0xBB);
+        let mut hash = Sha1::new();
+        hash.update(&body);
+        extra.iter().for_each(|v| hash.update(v));
+        let x = [&[0x6A], &body[..], &hash.finalize()[..], &[0xBC]].concat();
+        let (n, d) = (
+            BigUint::parse_bytes(n.as_bytes(), 16),
+            BigUint::parse_bytes(d.as_bytes(), 16),
+        );
+        let sig = BigUint::from_bytes_be(&x)
+            .modpow(&d.unwrap(), &n.unwrap())
+            .to_bytes_be();
+        [vec![0; len - sig.len()], sig].concat()
+    }
+
+    #[test]
+    fn test_oda() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let static_data = [
+            0x5A, 0x08, 0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x00, 0x10, 0x19, 0x80,
+        ];
+
+        // The issuer's key is longer than there's room for in a certificate from a key
+        // this short, so some of it is in the remainder.
+        let issuer_n = hex::decode(ISSUER.0).unwrap();
+        let (left, remainder) = issuer_n.split_at(64 - 36);
+        let body = [
+            &[
+                0x02, 0x47, 0x61, 0x73, 0xFF, 0x12, 0x30, 0x00, 0x00, 0x01, 0x01, 0x01,
+            ],
+            &[issuer_n.len() as u8, 0x01][..],
+            left,
+        ]
+        .concat();
+        let cert = sign(CA, &body, &[remainder, &[0x03]]);
+        let issuer_cert = Certificate {
+            cert: &cert,
+            remainder,
+            exponent: &[0x03],
+        };
+        let issuer = issuer_key(&public(CA), issuer_cert, PAN, today).unwrap();
+        assert_eq!(issuer, public(ISSUER));
+
+        // Someone else's CA, someone else's card, and next year.
+        assert_eq!(
+            issuer_key(&public(ICC), issuer_cert, PAN, today),
+            Err(OdaError::WrongLength("Issuer Public Key Certificate"))
+        );
+        assert_eq!(
+            issuer_key(&public(CA), issuer_cert, &[0x51, 0x23], today),
+            Err(OdaError::WrongPan(
+                "Issuer Public Key Certificate",
+                "476173".into()
+            ))
+        );
+        let later = NaiveDate::from_ymd_opt(2031, 1, 1).unwrap();
+        assert_eq!(
+            issuer_key(&public(CA), issuer_cert, PAN, later),
+            Err(OdaError::Expired(
+                "Issuer Public Key Certificate",
+                "2030-12".into()
+            ))
+        );
+
+        // SDA.
+        let ssad = sign(ISSUER, &[0x03, 0x01, 0xDA, 0xC0], &[&static_data]);
+        assert_eq!(verify_sda(&issuer, &ssad, &static_data), Ok(()));
+        assert_eq!(
+            verify_sda(&issuer, &ssad, &static_data[1..]),
+            Err(OdaError::HashMismatch("Signed Static Application Data"))
+        );
+
+        // Same again for the card's key, in the issuer's certificate.
+        let icc_n = hex::decode(ICC.0).unwrap();
+        let (left, remainder) = icc_n.split_at(56 - 42);
+        let header = [
+            0x04, 0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x00, 0x10, 0xFF, 0xFF, 0x12, 0x30, 0x00,
+            0x00, 0x02, 0x01, 0x01,
+        ];
+        let body = [&header[..], &[icc_n.len() as u8, 0x01], left].concat();
+        let cert = sign(ISSUER, &body, &[remainder, &[0x03], &static_data]);
+        let icc_cert = Certificate {
+            cert: &cert,
+            remainder,
+            exponent: &[0x03],
+        };
+        let icc = icc_key(&issuer, icc_cert, &static_data, PAN, today).unwrap();
+        assert_eq!(icc, public(ICC));
+
+        // fDDA.
+        let card = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let terminal = fdda_terminal_data(
+            &[0x12, 0x34, 0x56, 0x78],
+            &[0x00, 0x00, 0x00, 0x00, 0x12, 0x34],
+            &[0x08, 0x26],
+            Some(&card),
+        );
+        let sdad = sign(ICC, &[0x05, 0x01, 0x03, 0x02, 0xAB, 0xCD], &[&terminal]);
+        assert_eq!(
+            verify_dda(&icc, &sdad, &terminal),
+            Ok(vec![0x02, 0xAB, 0xCD])
+        );
+        assert_eq!(
+            verify_dda(&icc, &sdad, &terminal[..4]),
+            Err(OdaError::HashMismatch("Signed Dynamic Application Data"))
+        );
+    }
+
+    #[test]
+    fn test_parse_sdad() {
+        assert_eq!(
+            parse_sdad(&[0x80, 0x02, 0x12, 0x34]).unwrap(),
+            vec![0x12, 0x34]
+        );
+        assert_eq!(
+            parse_sdad(&[0x77, 0x05, 0x9F, 0x4B, 0x02, 0x12, 0x34]).unwrap(),
+            vec![0x12, 0x34]
+        );
+        assert!(parse_sdad(&[0x77, 0x00]).is_err());
+        assert!(parse_sdad(&[0x6F, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_static_data() {
+        let records = [
+            (1, vec![0x70, 0x03, 0x5A, 0x01, 0x47]),
+            (11, vec![0x70, 0x03, 0x9F, 0x01, 0x00]),
+        ];
+        assert_eq!(
+            static_data(&records, Some(&[0x82]), 0x1980).unwrap(),
+            vec![0x5A, 0x01, 0x47, 0x70, 0x03, 0x9F, 0x01, 0x00, 0x19, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_parse_ca_keys() {
+        let keys =
+            CaKeys::parse("rid,index,exponent,modulus\n# Test key\nA000000003,92,03,F90F37\n");
+        assert_eq!(keys.0.len(), 1);
+        let key = keys.find(&[0xA0, 0x00, 0x00, 0x00, 0x03], 0x92).unwrap();
+        assert_eq!(key.key.modulus, vec![0xF9, 0x0F, 0x37]);
+        assert!(keys.find(&[0xA0, 0x00, 0x00, 0x00, 0x04], 0x92).is_none());
+    }
+}
@@ -0,0 +1,558 @@
+//! Data that means something different depending on whose application it is.
+//!
+//! Payment schemes each have their own proprietary tags, and sometimes they pick the same
+//! tag for completely different things. Which scheme an application belongs to is in its
+//! AID: the first 5 bytes are the RID (Registered Application Provider Identifier), which
+//! is assigned to the scheme. To decode another scheme's data, add it to [Scheme], then
+//! teach the decoders (eg. [Data9F6E::decode]) about it; anything they don't know is kept
+//! as raw bytes.
+
+use crate::{warnings, HexVec};
+use num_enum::{FromPrimitive, IntoPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A payment scheme, as far as proprietary data goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Scheme {
+    Visa,
+    Mastercard,
+}
+
+impl Scheme {
+    /// Every scheme we know about.
+    pub const ALL: &'static [Self] = &[Self::Visa, Self::Mastercard];
+
+    /// The scheme's RID.
+    pub fn rid(&self) -> &'static [u8; 5] {
+        match self {
+            Self::Visa => &[0xA0, 0x00, 0x00, 0x00, 0x03],
+            Self::Mastercard => &[0xA0, 0x00, 0x00, 0x00, 0x04],
+        }
+    }
+
+    /// Works out the scheme from an AID (or ADF name), by its RID.
+    pub fn from_aid(aid: &[u8]) -> Option<Self> {
+        let rid = aid.get(..5)?;
+        Self::ALL.iter().copied().find(|s| s.rid() == rid)
+    }
+}
+
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Visa => write!(f, "Visa"),
+            Self::Mastercard => write!(f, "Mastercard"),
+        }
+    }
+}
+
+/// 0x9F6E: [Visa] Form Factor Indicator, or [Mastercard] Third Party Data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Data9F6E {
+    /// As it came from the card; always there, even if we understood it.
+    pub raw: HexVec,
+    /// What it means, if we know whose it is, and it made sense.
+    pub decoded: Option<Decoded9F6E>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Decoded9F6E {
+    FormFactorIndicator(FormFactorIndicator),
+    ThirdPartyData(ThirdPartyData),
+}
+
+impl Data9F6E {
+    /// Just the raw bytes; see [Data9F6E::decode] for the rest.
+    pub fn new(raw: &[u8]) -> Self {
+        Self {
+            raw: raw.into(),
+            decoded: None,
+        }
+    }
+
+    /// Decodes it for a scheme, if it's one that uses this tag.
+    pub fn decode(&mut self, scheme: Scheme) {
+        self.decoded = match scheme {
+            Scheme::Visa => {
+                FormFactorIndicator::parse(&self.raw).map(Decoded9F6E::FormFactorIndicator)
+            }
+            Scheme::Mastercard => ThirdPartyData::parse(&self.raw).map(Decoded9F6E::ThirdPartyData),
+        };
+        if self.decoded.is_none() {
+            let err = format!("not valid {} data", scheme);
+            warnings::unparseable("FCIIssuerDiscretionaryData", &[0x9F, 0x6E], err);
+        }
+    }
+}
+
+/// [Visa] Form Factor Indicator: what the card (or not-a-card) physically is. (b, 4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FormFactorIndicator {
+    /// Byte 1, bits 8-6: FFI version.
+    pub version: u8,
+    /// Byte 1, bits 5-1.
+    pub form_factor: FormFactor,
+    /// Byte 2.
+    pub features: Vec<DeviceFeature>,
+}
+
+impl FormFactorIndicator {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let [b1, b2, _, _] = *data else {
+            return None;
+        };
+        Some(Self {
+            version: b1 >> 5,
+            form_factor: FormFactor::from(b1 & 0x1F),
+            features: (DeviceFeature::ALL.iter())
+                .filter(|&&f| b2 & u8::from(f) != 0)
+                .copied()
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum FormFactor {
+    StandardCard = 0x00,
+    MiniCard = 0x01,
+    NonCard = 0x02,
+    MobilePhone = 0x03,
+    WristWorn = 0x04,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl std::fmt::Display for FormFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StandardCard => write!(f, "Standard card"),
+            Self::MiniCard => write!(f, "Mini card"),
+            Self::NonCard => write!(f, "Non-card form factor"),
+            Self::MobilePhone => write!(f, "Mobile phone"),
+            Self::WristWorn => write!(f, "Wrist-worn device"),
+            Self::Unknown(v) => write!(f, "Unknown({:02X})", v),
+        }
+    }
+}
+
+/// Bits in the FFI's second byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum DeviceFeature {
+    Passcode = 0x80,
+    SignaturePanel = 0x40,
+    Hologram = 0x20,
+    Cvv2 = 0x10,
+    TwoWayMessaging = 0x08,
+    CloudBasedCredentials = 0x04,
+    BiometricVerification = 0x02,
+}
+
+impl DeviceFeature {
+    pub const ALL: &'static [Self] = &[
+        Self::Passcode,
+        Self::SignaturePanel,
+        Self::Hologram,
+        Self::Cvv2,
+        Self::TwoWayMessaging,
+        Self::CloudBasedCredentials,
+        Self::BiometricVerification,
+    ];
+}
+
+impl std::fmt::Display for DeviceFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passcode => write!(f, "passcode"),
+            Self::SignaturePanel => write!(f, "signature panel"),
+            Self::Hologram => write!(f, "hologram"),
+            Self::Cvv2 => write!(f, "CVV2"),
+            Self::TwoWayMessaging => write!(f, "two-way messaging"),
+            Self::CloudBasedCredentials => write!(f, "cloud-based credentials"),
+            Self::BiometricVerification => write!(f, "biometric verification"),
+        }
+    }
+}
+
+/// [Mastercard] Third Party Data: who (other than the issuer) has a say in the card, and
+/// what kind of device it is. (b, 5-32)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ThirdPartyData {
+    /// ISO 3166 numeric country code of the third party. (n3, 2)
+    pub country_code: u16,
+    /// Who the third party is. (b, 2)
+    pub unique_identifier: u16,
+    /// Device Type, if the unique identifier's top bit is 0; eg. "00" for a card. (an2, 2)
+    pub device_type: Option<String>,
+    /// Whatever's left, which the third party defines. (b, 1-26)
+    pub proprietary_data: HexVec,
+}
+
+impl ThirdPartyData {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if !(5..=32).contains(&data.len()) {
+            return None;
+        }
+        let (cc, rest) = data.split_at(2);
+        let (uid, rest) = rest.split_at(2);
+        let unique_identifier = u16::from_be_bytes([uid[0], uid[1]]);
+        let (device_type, rest) = match rest {
+            [a, b, rest @ ..] if unique_identifier & 0x8000 == 0 => {
+                (Some(String::from_utf8_lossy(&[*a, *b]).into()), rest)
+            }
+            rest => (None, rest),
+        };
+        Some(Self {
+            country_code: super::decode::n3(cc)?,
+            unique_identifier,
+            device_type,
+            proprietary_data: rest.into(),
+        })
+    }
+
+    /// What the device type means.
+    pub fn device_type_name(&self) -> Option<&'static str> {
+        Some(match self.device_type.as_deref()? {
+            "00" => "Card",
+            "01" => "Mobile phone",
+            "02" => "Key fob",
+            "03" => "Watch",
+            "04" => "Mobile tag",
+            "05" => "Wristband",
+            "06" => "Mobile phone case",
+            _ => return None,
+        })
+    }
+}
+
+/// 0x9F10: Issuer Application Data, for schemes whose layout we know: [Visa] VIS (CVN 10,
+/// 18, etc; IAD formats 0, 1, 2 and 3), and [Mastercard] M/Chip 4 and M/Chip Advance.
+/// Anything past the CVR is the issuer's business, so it's left as bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IssuerApplicationData {
+    pub scheme: Scheme,
+    /// Derivation Key Index: which of the issuer's master keys the card's key came from.
+    pub dki: u8,
+    /// Cryptogram Version Number: how the cryptogram was made.
+    pub cvn: u8,
+    /// Card Verification Results: what the card made of this transaction (and the last).
+    pub cvr: HexVec,
+    /// Whatever's left; [Visa] Issuer Discretionary Data, or [Mastercard] the DAC/ICC
+    /// Dynamic Number and counters.
+    pub rest: HexVec,
+}
+
+impl IssuerApplicationData {
+    pub fn parse(scheme: Scheme, data: &[u8]) -> Option<Self> {
+        let (dki, cvn, cvr, rest) = match (scheme, data) {
+            // Format 2 (32 bytes, starting with its length): CVN first, then the DKI, then a
+            // 5-byte CVR.
+            (Scheme::Visa, [0x1F, cvn, dki, rest @ ..]) if rest.len() >= 5 => {
+                (*dki, *cvn, &rest[..5], &rest[5..])
+            }
+            // Formats 0, 1 and 3: the length of the VIS part, DKI, CVN, then the CVR, with
+            // its own length byte.
+            (Scheme::Visa, [6..=7, dki, cvn, len, rest @ ..]) if rest.len() >= *len as usize => {
+                let (cvr, rest) = rest.split_at(*len as usize);
+                (*dki, *cvn, cvr, rest)
+            }
+            (Scheme::Visa, _) => return None,
+            (Scheme::Mastercard, [dki, cvn, rest @ ..]) if rest.len() >= 6 => {
+                (*dki, *cvn, &rest[..6], &rest[6..])
+            }
+            (Scheme::Mastercard, _) => return None,
+        };
+        Some(Self {
+            scheme,
+            dki,
+            cvn,
+            cvr: cvr.into(),
+            rest: rest.into(),
+        })
+    }
+
+    /// What the CVN's called. Visa's are numbered in decimal, Mastercard's in hex.
+    pub fn cvn_name(&self) -> String {
+        match self.scheme {
+            Scheme::Visa => format!("CVN {}", self.cvn),
+            Scheme::Mastercard => format!("CVN {:02X}", self.cvn),
+        }
+    }
+
+    /// The CVR, explained: what kinds of cryptogram the card returned, then every bit
+    /// that's set, in plain-ish English. Empty if it's not a CVR we know the layout of.
+    pub fn explain_cvr(&self) -> Vec<String> {
+        let bits = match (self.scheme, self.cvr.len()) {
+            (Scheme::Visa, 3) => VISA_CVR,
+            (Scheme::Mastercard, 6) => MCHIP_CVR,
+            _ => return vec![],
+        };
+        let (ac, counters) = (self.cvr[0], self.cvr[2]);
+        let mut out = vec![
+            format!("first GENERATE AC: {}", ac_type(ac >> 4, true)),
+            format!("second GENERATE AC: {}", ac_type(ac >> 6, false)),
+        ];
+        for (byte, names) in self.cvr.iter().zip(bits) {
+            for (i, name) in names.iter().enumerate() {
+                if !name.is_empty() && byte & (0x80 >> i) != 0 {
+                    out.push(name.to_string());
+                }
+            }
+        }
+        if counters >> 4 > 0 {
+            out.push(format!("{} script commands processed", counters >> 4));
+        }
+        if self.scheme == Scheme::Mastercard {
+            out.push(format!("{} PIN tries left", counters & 0x0F));
+        }
+        out
+    }
+}
+
+/// Two bits of CVR byte 1: which cryptogram a GENERATE AC returned.
+fn ac_type(bits: u8, first: bool) -> &'static str {
+    match (bits & 0x03, first) {
+        (0b00, _) => "AAC",
+        (0b01, _) => "TC",
+        (0b10, true) => "ARQC",
+        (0b10, false) => "not requested",
+        _ => "RFU",
+    }
+}
+
+/// [Visa] VIS CVR bits, byte by byte, from bit 8 down; byte 1's top four (the cryptogram
+/// types) and byte 3's top four (the script counter) are handled separately. Blanks are
+/// RFU, or not bits at all.
+const VISA_CVR: &[&[&str]] = &[
+    &[
+        "",
+        "",
+        "",
+        "",
+        "issuer authentication failed",
+        "offline PIN verification performed",
+        "offline PIN verification failed",
+        "unable to go online",
+    ],
+    &[
+        "last online transaction not completed",
+        "PIN try limit exceeded",
+        "velocity checking counters exceeded",
+        "new card",
+        "issuer authentication failed on last online transaction",
+        "issuer authentication not performed after online authorisation",
+        "application blocked; PIN try limit exceeded",
+        "offline SDA failed on last transaction, and it was declined offline",
+    ],
+    &[
+        "",
+        "",
+        "",
+        "",
+        "issuer script processing failed",
+        "offline DDA failed on last transaction, and it was declined offline",
+        "offline DDA performed",
+        "",
+    ],
+];
+
+/// [Mastercard] M/Chip CVR bits; as [VISA_CVR], except byte 3 is two counters (scripts and
+/// PIN tries).
+const MCHIP_CVR: &[&[&str]] = &[
+    &[
+        "",
+        "",
+        "",
+        "",
+        "date check failed",
+        "offline PIN verification performed",
+        "offline encrypted PIN verification performed",
+        "offline PIN verification successful",
+    ],
+    &[
+        "DDA returned",
+        "CDA in first GENERATE AC",
+        "CDA in second GENERATE AC",
+        "issuer authentication performed",
+        "CIAC-Default skipped on CAT3",
+        "offline change PIN result",
+        "",
+        "",
+    ],
+    &[],
+    &[
+        "last online transaction not completed",
+        "unable to go online",
+        "offline PIN verification not performed",
+        "offline PIN verification failed",
+        "PIN try limit exceeded",
+        "international transaction",
+        "domestic transaction",
+        "terminal erroneously considers offline PIN OK",
+    ],
+    &[
+        "lower consecutive offline limit exceeded",
+        "upper consecutive offline limit exceeded",
+        "lower cumulative offline limit exceeded",
+        "upper cumulative offline limit exceeded",
+        "go online on next transaction was set",
+        "issuer authentication failed",
+        "script received",
+        "script failed",
+    ],
+    &[
+        "",
+        "",
+        "",
+        "",
+        "",
+        "",
+        "match found in additional check table",
+        "no match found in additional check table",
+    ],
+];
+
+/// Decodes a 3-digit BCD number, eg. `[0x08, 0x26]` for 826.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheme_from_aid() {
+        let aid = [0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10];
+        assert_eq!(Scheme::from_aid(&aid), Some(Scheme::Mastercard));
+        assert_eq!(Scheme::from_aid(&aid[..4]), None);
+        assert_eq!(
+            Scheme::from_aid(&[0xA0, 0x00, 0x00, 0x00, 0x25, 0x01]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_issuer_application_data() {
+        // Visa CVN 10: ARQC, nothing else to say.
+        let iad =
+            IssuerApplicationData::parse(Scheme::Visa, &[0x06, 0x01, 0x0A, 0x03, 0xA0, 0x00, 0x00])
+                .unwrap();
+        assert_eq!(
+            (iad.dki, iad.cvn, &iad.cvr[..]),
+            (0x01, 0x0A, &[0xA0, 0, 0][..])
+        );
+        assert_eq!(iad.cvn_name(), "CVN 10");
+        assert_eq!(
+            iad.explain_cvr(),
+            vec![
+                "first GENERATE AC: ARQC",
+                "second GENERATE AC: not requested"
+            ]
+        );
+
+        // Visa CVN 18, with Issuer Discretionary Data; a new card that couldn't go online.
+        let iad = IssuerApplicationData::parse(
+            Scheme::Visa,
+            &[0x06, 0x01, 0x12, 0x03, 0x10, 0x90, 0x22, 0x01, 0xFF],
+        )
+        .unwrap();
+        assert_eq!(iad.cvn_name(), "CVN 18");
+        assert_eq!(iad.rest, vec![0x01, 0xFF]);
+        assert_eq!(
+            iad.explain_cvr()[..],
+            [
+                "first GENERATE AC: TC",
+                "second GENERATE AC: AAC",
+                "last online transaction not completed",
+                "new card",
+                "offline DDA performed",
+                "2 script commands processed",
+            ]
+        );
+
+        // Visa format 2.
+        let mut data = vec![0x1F, 0x22, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
+        data.resize(32, 0);
+        let iad = IssuerApplicationData::parse(Scheme::Visa, &data).unwrap();
+        assert_eq!((iad.dki, iad.cvn, iad.cvr.len()), (0x01, 0x22, 5));
+        assert!(iad.explain_cvr().is_empty());
+
+        // M/Chip: ARQC, offline PIN OK, 3 tries left, unable to go online; then the DAC
+        // and counters.
+        let iad = IssuerApplicationData::parse(
+            Scheme::Mastercard,
+            &[
+                0x01, 0x10, 0xA5, 0x00, 0x03, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0xFF,
+            ],
+        )
+        .unwrap();
+        assert_eq!(iad.cvn_name(), "CVN 10");
+        assert_eq!(iad.rest.len(), 10);
+        assert_eq!(
+            iad.explain_cvr()[..],
+            [
+                "first GENERATE AC: ARQC",
+                "second GENERATE AC: not requested",
+                "offline PIN verification performed",
+                "offline PIN verification successful",
+                "unable to go online",
+                "3 PIN tries left",
+            ]
+        );
+
+        // Too short for either.
+        assert_eq!(
+            IssuerApplicationData::parse(Scheme::Visa, &[0x06, 0x01]),
+            None
+        );
+        assert_eq!(
+            IssuerApplicationData::parse(Scheme::Mastercard, &[0x01, 0x10, 0xA5]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_9f6e() {
+        // From a Debit Mastercard.
+        let mut data = Data9F6E::new(&[0x08, 0x26, 0x00, 0x00, 0x30, 0x30, 0x00]);
+        data.decode(Scheme::Mastercard);
+        let Some(Decoded9F6E::ThirdPartyData(tpd)) = data.decoded.as_ref() else {
+            panic!("not decoded as Third Party Data: {:?}", data);
+        };
+        assert_eq!(tpd.country_code, 826);
+        assert_eq!(tpd.device_type.as_deref(), Some("00"));
+        assert_eq!(tpd.device_type_name(), Some("Card"));
+        assert_eq!(tpd.proprietary_data, vec![0x00]);
+
+        // A Visa card with a signature panel, hologram and CVV2.
+        let mut data = Data9F6E::new(&[0x20, 0x70, 0x00, 0x00]);
+        data.decode(Scheme::Visa);
+        assert_eq!(
+            data.decoded,
+            Some(Decoded9F6E::FormFactorIndicator(FormFactorIndicator {
+                version: 1,
+                form_factor: FormFactor::StandardCard,
+                features: vec![
+                    DeviceFeature::SignaturePanel,
+                    DeviceFeature::Hologram,
+                    DeviceFeature::Cvv2
+                ],
+            }))
+        );
+
+        // The wrong length for either; the raw bytes are still there.
+        let mut data = Data9F6E::new(&[0x08, 0x26]);
+        data.decode(Scheme::Mastercard);
+        assert_eq!(data.decoded, None);
+        assert_eq!(data.raw, vec![0x08, 0x26]);
+    }
+}
@@ -0,0 +1,844 @@
+//! A pretend contactless terminal, for seeing what a card would do in a transaction,
+//! without actually doing one.
+//!
+//! It goes through the motions the way a kernel would (EMV Book A and B, more or less):
+//! SELECT the PPSE, pick the application with the highest priority (falling back to the
+//! next if it won't be selected), GET PROCESSING OPTIONS with the amount and TTQ filled in,
+//! read the records in the AFL, and check their signatures (see [oda]). Then it stops, short
+//! of GENERATE AC, and says what the cryptogram would have covered.
+//!
+//! It's only an approximation: there's no risk management, no CVM, and no kernel-specific
+//! rules, so it won't tell you whether a real terminal would approve it. And GPO still
+//! counts as a transaction on most cards (see [gpo]).
+//!
+//! What kind of terminal it is comes from a [TerminalConfig], which is also what GPO and
+//! GENERATE AC can fill in DOLs with (see [dol]). There are a few [presets](TerminalConfig::preset), or it
+//! can be read from TOML (anything left out is the same as the `pos` preset):
+//!
+//! ```toml
+//! terminal_type = 0x25     # Unattended, offline with online capability.
+//! capabilities = "60 08 C8"
+//! ttq = "34 00 00 00"
+//! floor_limit = 0
+//! contactless_cvm_limit = 2500
+//! country = 250            # France.
+//! currency = "EUR"
+//! ```
+
+use super::dol::{self, Dol, Provider};
+use super::generate_ac;
+use super::gpo::ProcessingOptions;
+use super::oda::{self, CaKeys, Certificate, OdaError, PublicKey};
+use super::{Application, DirectoryLookup, ProximityDirectory};
+use crate::money::Currency;
+use crate::{ber, iso7816, CardTransport, Error, HexVec, Result};
+use chrono::{Datelike, NaiveDate};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use tracing::{debug, trace_span};
+
+/// Names of tags that DOLs commonly ask for, so you can tell what a cryptogram covers.
+const DOL_TAGS: &[(u32, &str)] = &[
+    (0x95, "Terminal Verification Results"),
+    (0x9A, "Transaction Date"),
+    (0x9C, "Transaction Type"),
+    (0x5F2A, "Transaction Currency Code"),
+    (0x8A, "Authorisation Response Code"),
+    (0x9F02, "Amount, Authorised"),
+    (0x9F03, "Amount, Other"),
+    (0x9F1A, "Terminal Country Code"),
+    (0x9F21, "Transaction Time"),
+    (0x9F34, "CVM Results"),
+    (0x9F35, "Terminal Type"),
+    (0x9F37, "Unpredictable Number"),
+    (0x9F45, "Data Authentication Code"),
+    (0x9F4C, "ICC Dynamic Number"),
+    (0x9F66, "Terminal Transaction Qualifiers"),
+    (0x9F7C, "Customer Exclusive Data"),
+];
+
+/// Which kernel goes with which RID, by EMV Book C's numbering.
+const KERNELS: &[(&[u8], u8, &str)] = &[
+    (&[0xA0, 0x00, 0x00, 0x00, 0x04], 2, "Mastercard"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x03], 3, "Visa"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x25], 4, "American Express"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x65], 5, "JCB"),
+    (&[0xA0, 0x00, 0x00, 0x01, 0x52], 6, "Discover"),
+    (&[0xA0, 0x00, 0x00, 0x03, 0x24], 6, "Discover"),
+    (&[0xA0, 0x00, 0x00, 0x03, 0x33], 7, "UnionPay"),
+];
+
+/// What kind of terminal to pretend to be; see [TerminalConfig::preset]. Amounts are in
+/// the currency's minor unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct TerminalConfig {
+    /// 0x9F35: Terminal Type; attended or not, and whether it can go online. EMV Book 4, A1.
+    pub terminal_type: u8,
+    /// 0x9F33: Terminal Capabilities; card input, CVMs and ODA methods. EMV Book 4, A2.
+    pub capabilities: HexVec,
+    /// 0x9F66: Terminal Transaction Qualifiers; the contactless equivalent. The
+    /// "online cryptogram required" and "CVM required" bits are set per transaction, from
+    /// the limits below.
+    pub ttq: HexVec,
+    /// 0x9F1B: Terminal Floor Limit; anything over it goes online.
+    pub floor_limit: u64,
+    /// Reader Contactless Floor Limit; like the floor limit, for contactless. None if
+    /// there isn't one.
+    pub contactless_floor_limit: Option<u64>,
+    /// Reader CVM Required Limit; anything over it needs a PIN (or similar). None if
+    /// there isn't one.
+    pub contactless_cvm_limit: Option<u64>,
+    /// 0x9F1A: Terminal Country Code (ISO 3166 numeric).
+    pub country: u16,
+    /// 0x5F2A: Transaction Currency Code.
+    pub currency: Currency,
+    /// 0x9C: Transaction Type; 00 is a purchase.
+    pub transaction_type: u8,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self::pos()
+    }
+}
+
+impl TerminalConfig {
+    /// Names of the built-in presets.
+    pub const PRESETS: &[&str] = &["pos", "transit", "unattended"];
+
+    /// One of the [TerminalConfig::PRESETS], by name.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "pos" => Some(Self::pos()),
+            "transit" => Some(Self::transit()),
+            "unattended" => Some(Self::unattended()),
+            _ => None,
+        }
+    }
+
+    /// A shop's card terminal in the UK: chip, stripe and contactless, online when it has
+    /// to be, and a PIN for contactless over £100.
+    pub fn pos() -> Self {
+        Self {
+            terminal_type: 0x22, // Attended, offline with online capability.
+            capabilities: vec![0xE0, 0xF8, 0xC8].into(),
+            ttq: vec![0x36, 0x00, 0x00, 0x00].into(),
+            floor_limit: 0,
+            contactless_floor_limit: None,
+            contactless_cvm_limit: Some(10000),
+            country: 826,
+            currency: Currency::GBP,
+            transaction_type: 0x00,
+        }
+    }
+
+    /// An open-loop transit gate: contactless only, no CVM, approves offline and settles
+    /// up later, and asks for ODA even when it does go online.
+    pub fn transit() -> Self {
+        Self {
+            terminal_type: 0x25, // Unattended, offline with online capability.
+            capabilities: vec![0x00, 0x08, 0xC8].into(),
+            ttq: vec![0x21, 0x00, 0x00, 0x00].into(),
+            floor_limit: 0,
+            contactless_floor_limit: None,
+            contactless_cvm_limit: None,
+            country: 826,
+            currency: Currency::GBP,
+            transaction_type: 0x00,
+        }
+    }
+
+    /// A vending machine, or a parking meter: no one to sign anything, so online PIN or
+    /// nothing, and always online.
+    pub fn unattended() -> Self {
+        Self {
+            terminal_type: 0x24, // Unattended, online only.
+            capabilities: vec![0x60, 0x48, 0xC8].into(),
+            ttq: vec![0x34, 0x00, 0x00, 0x00].into(),
+            floor_limit: 0,
+            contactless_floor_limit: Some(0),
+            contactless_cvm_limit: Some(10000),
+            country: 826,
+            currency: Currency::GBP,
+            transaction_type: 0x00,
+        }
+    }
+
+    /// The TTQ for a transaction of `amount`, with the bits that depend on it set.
+    pub fn ttq_for(&self, amount: u64) -> Vec<u8> {
+        let mut ttq = self.ttq.to_vec();
+        ttq.resize(4, 0);
+        if self.online_required(amount) {
+            ttq[1] |= 0x80; // Online cryptogram required.
+        }
+        if self.cvm_required(amount) {
+            ttq[1] |= 0x40; // CVM required.
+        }
+        ttq
+    }
+
+    /// Whether `amount` is over a floor limit.
+    pub fn online_required(&self, amount: u64) -> bool {
+        self.contactless_floor_limit
+            .is_some_and(|limit| amount > limit)
+    }
+
+    /// Whether `amount` is over the CVM limit.
+    pub fn cvm_required(&self, amount: u64) -> bool {
+        self.contactless_cvm_limit
+            .is_some_and(|limit| amount > limit)
+    }
+}
+
+/// A transaction, from the terminal's point of view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminal {
+    pub config: TerminalConfig,
+    /// Today, as far as the card (and certificate expiry dates) are concerned.
+    pub date: NaiveDate,
+    /// Amount, in the currency's minor unit.
+    pub amount: u64,
+    pub unpredictable_number: [u8; 4],
+    /// CA public keys, to check signatures with; without them, ODA is skipped.
+    pub ca_keys: CaKeys,
+}
+
+impl Terminal {
+    /// A terminal that takes nothing, today.
+    pub fn new(config: TerminalConfig, date: NaiveDate) -> Self {
+        Self {
+            config,
+            date,
+            amount: 0,
+            unpredictable_number: [0x12, 0x34, 0x56, 0x78],
+            ca_keys: CaKeys::default(),
+        }
+    }
+
+    /// Runs a transaction, as far as it can without GENERATE AC. Errors from the card
+    /// (status words) are part of the decision path, not errors; only a card that goes
+    /// away or talks nonsense is.
+    pub fn run(
+        &self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<Transaction> {
+        let span = trace_span!("Terminal::run");
+        let _enter = span.enter();
+
+        let mut tx = Transaction::default();
+
+        debug!("Selecting PPSE...");
+        let ppse = match ProximityDirectory::lookup(card, wbuf, rbuf)? {
+            DirectoryLookup::Present(ppse) => ppse,
+            DirectoryLookup::Absent => {
+                tx.step(Stage::Ppse, "No PPSE; not a contactless payment card");
+                return Ok(tx);
+            }
+            DirectoryLookup::Refused(sw) => {
+                tx.step(Stage::Ppse, format!("SELECT PPSE refused ({})", sw));
+                return Ok(tx);
+            }
+        };
+
+        // Lowest number first, and 0 (no priority) last; ties keep the card's order.
+        let mut candidates = ppse.applications;
+        candidates.sort_by_key(|app| match app.app_priority.map(|p| p & 0x0F) {
+            None | Some(0) => 0x10,
+            Some(p) => p,
+        });
+        tx.step(
+            Stage::Ppse,
+            format!("{} candidate application(s)", candidates.len()),
+        );
+
+        let mut selected = None;
+        for candidate in candidates {
+            let aid = candidate.adf_name;
+            let kernel = (KERNELS.iter())
+                .find(|(rid, _, _)| aid.starts_with(rid))
+                .map(|(_, k, scheme)| format!("kernel {} ({})", k, scheme))
+                .unwrap_or_else(|| "no kernel we know of".into());
+            debug!(aid = hex::encode_upper(&aid), "Selecting application...");
+            match Application::select(card, wbuf, rbuf, &aid) {
+                Ok(app) => {
+                    tx.step(
+                        Stage::Select,
+                        format!(
+                            "Selected {} ({}), {}",
+                            aid,
+                            label(&app, &candidate.app_label),
+                            kernel
+                        ),
+                    );
+                    selected = Some((aid, app));
+                    break;
+                }
+                Err(err) => match iso7816::StatusWord::from_error(&err) {
+                    Some(sw) => tx.step(
+                        Stage::Select,
+                        format!("SELECT {} failed ({}); trying the next one", aid, sw),
+                    ),
+                    None => return Err(err),
+                },
+            }
+        }
+        let Some((aid, app)) = selected else {
+            tx.step(Stage::Select, "No application could be selected");
+            return Ok(tx);
+        };
+        tx.aid = Some(aid.clone());
+
+        if self.config.online_required(self.amount) {
+            tx.step(
+                Stage::ProcessingOptions,
+                "Over the contactless floor limit; asking for an online cryptogram",
+            );
+        }
+        if self.config.cvm_required(self.amount) {
+            tx.step(
+                Stage::ProcessingOptions,
+                "Over the contactless CVM limit; asking for a CVM",
+            );
+        }
+
+        debug!("Getting processing options...");
+        let pdol = app.pdol.clone().unwrap_or_default();
+        let gpo = match ProcessingOptions::get_raw(card, wbuf, rbuf, &pdol, self) {
+            Ok(rsp) => rsp,
+            Err(Error::APDU(sw1, sw2)) => {
+                tx.step(
+                    Stage::ProcessingOptions,
+                    format!("GPO refused ({:02X}{:02X}); a kernel would end the transaction, or try another interface", sw1, sw2),
+                );
+                return Ok(tx);
+            }
+            Err(err) => return Err(err),
+        };
+        let opts = ProcessingOptions::parse(&gpo)?;
+        tx.step(
+            Stage::ProcessingOptions,
+            format!("AIP {:04X}, {} AFL entries", opts.aip, opts.afl.len()),
+        );
+
+        debug!("Reading records...");
+        let mut tags = Tags::default();
+        tags.add(&gpo);
+        let mut auth_records = vec![];
+        let mut records = vec![];
+        for (sfi, num, record) in opts.read_records(card, wbuf, rbuf)? {
+            tags.add(&record);
+            if (opts.afl.iter())
+                .any(|e| e.sfi == sfi && num >= e.first && num - e.first < e.offline_auth)
+            {
+                auth_records.push((sfi, record.clone()));
+            }
+            records.push(record);
+        }
+        tx.step(
+            Stage::Records,
+            format!(
+                "Read {} record(s), {} for offline authentication",
+                records.len(),
+                auth_records.len()
+            ),
+        );
+
+        let oda = self.oda(&aid, &opts, &tags, &auth_records);
+        tx.step(
+            Stage::Oda,
+            match &oda {
+                None => "The card doesn't do offline data authentication".into(),
+                Some(Oda { method, outcome }) => format!("{}: {}", method, outcome),
+            },
+        );
+        let tvr = tvr(oda.as_ref());
+        tx.oda = oda;
+
+        // qVSDC cards hand back a cryptogram with GPO, over what the PDOL asked for.
+        let (dol, list) = match tags.get(0x9F26) {
+            Some(_) => ("PDOL", Some(pdol)),
+            None => (
+                "CDOL1",
+                generate_ac::cdols(records.iter().map(|r| &r[..])).0,
+            ),
+        };
+        let data = (&[(0x95, &tvr[..])][..], self);
+        match list {
+            Some(list) => {
+                tx.step(
+                    Stage::Cryptogram,
+                    format!(
+                        "The cryptogram would cover the {} fields below, and the card's own data",
+                        dol
+                    ),
+                );
+                tx.coverage = Some(Coverage {
+                    dol,
+                    fields: fields(&list, &data),
+                });
+            }
+            None => tx.step(
+                Stage::Cryptogram,
+                "No CDOL1; nothing to say about the cryptogram",
+            ),
+        }
+        tx.processing_options = Some(opts);
+        Ok(tx)
+    }
+
+    /// Picks an ODA method, and checks what can be checked without GENERATE AC.
+    fn oda(
+        &self,
+        aid: &[u8],
+        opts: &ProcessingOptions,
+        tags: &Tags,
+        records: &[(u8, Vec<u8>)],
+    ) -> Option<Oda> {
+        let [aip, _] = opts.aip.to_be_bytes();
+        let method = if tags.get(0x9F4B).is_some() {
+            OdaMethod::FDDA
+        } else if aip & 0x01 != 0 {
+            OdaMethod::CDA
+        } else if aip & 0x20 != 0 {
+            OdaMethod::DDA
+        } else if aip & 0x40 != 0 {
+            OdaMethod::SDA
+        } else {
+            return None;
+        };
+        let outcome = match self.check(method, aid, opts, tags, records) {
+            Ok(()) if matches!(method, OdaMethod::DDA | OdaMethod::CDA) => {
+                OdaOutcome::Skipped(format!(
+                    "the certificates check out, but {} needs {}, which we don't send",
+                    method,
+                    match method {
+                        OdaMethod::CDA => "GENERATE AC",
+                        _ => "INTERNAL AUTHENTICATE",
+                    }
+                ))
+            }
+            Ok(()) => OdaOutcome::Passed,
+            Err(err @ OdaError::NoCaKey { .. }) => OdaOutcome::Skipped(err.to_string()),
+            Err(err) => OdaOutcome::Failed(err.to_string()),
+        };
+        Some(Oda { method, outcome })
+    }
+
+    fn check(
+        &self,
+        method: OdaMethod,
+        aid: &[u8],
+        opts: &ProcessingOptions,
+        tags: &Tags,
+        records: &[(u8, Vec<u8>)],
+    ) -> std::result::Result<(), OdaError> {
+        let rid = aid.get(..5).unwrap_or(aid);
+        let index = tags
+            .need(0x8F, "CA Public Key Index")?
+            .first()
+            .copied()
+            .unwrap_or_default();
+        let ca = self
+            .ca_keys
+            .find(rid, index)
+            .ok_or_else(|| OdaError::NoCaKey {
+                rid: hex::encode_upper(rid),
+                index,
+            })?;
+        // The PAN in track 2 works too, since the digits stop at the separator.
+        let pan = (tags.get(0x5A).or(tags.get(0x57))).ok_or(OdaError::Missing("PAN"))?;
+        let static_data = oda::static_data(records, tags.get(0x9F4A), opts.aip)
+            .map_err(|_| OdaError::Garbled("static data"))?;
+
+        let issuer = oda::issuer_key(
+            &ca.key,
+            Certificate {
+                cert: tags.need(0x90, "Issuer Public Key Certificate")?,
+                remainder: tags.get(0x92).unwrap_or_default(),
+                exponent: tags.need(0x9F32, "Issuer Public Key Exponent")?,
+            },
+            pan,
+            self.date,
+        )?;
+        if method == OdaMethod::SDA {
+            let ssad = tags.need(0x93, "Signed Static Application Data")?;
+            return oda::verify_sda(&issuer, ssad, &static_data);
+        }
+        let icc = icc_key(&issuer, tags, &static_data, pan, self.date)?;
+        if method == OdaMethod::FDDA {
+            let terminal = oda::fdda_terminal_data(
+                &self.unpredictable_number,
+                &bcd(self.amount, 6),
+                &bcd(u16::from(self.config.currency).into(), 2),
+                tags.get(0x9F69),
+            );
+            let sdad = tags.need(0x9F4B, "Signed Dynamic Application Data")?;
+            oda::verify_dda(&icc, sdad, &terminal)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the terminal fills in DOLs with; see [dol].
+impl Provider for TerminalConfig {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        Some(match tag {
+            0x9F66 => Cow::Borrowed(&self.ttq[..]),
+            0x9F1A => bcd(self.country.into(), 2).into(),
+            0x5F2A => bcd(u16::from(self.currency).into(), 2).into(),
+            0x9C => vec![self.transaction_type].into(),
+            0x9F35 => vec![self.terminal_type].into(),
+            0x9F33 => Cow::Borrowed(&self.capabilities[..]),
+            0x9F1B => (self.floor_limit as u32).to_be_bytes().to_vec().into(),
+            _ => return None,
+        })
+    }
+}
+
+/// What this transaction fills in DOLs with, on top of the [TerminalConfig].
+impl Provider for Terminal {
+    fn value(&self, tag: u32) -> Option<Cow<'_, [u8]>> {
+        Some(match tag {
+            0x9F02 => bcd(self.amount, 6).into(),
+            0x9F03 => bcd(0, 6).into(),
+            0x9F66 => self.config.ttq_for(self.amount).into(),
+            0x9A => {
+                let (y, m, d) = (self.date.year() % 100, self.date.month(), self.date.day());
+                [y as u32, m, d]
+                    .iter()
+                    .flat_map(|v| bcd((*v).into(), 1))
+                    .collect::<Vec<_>>()
+                    .into()
+            }
+            0x9F37 => Cow::Borrowed(&self.unpredictable_number[..]),
+            _ => return self.config.value(tag),
+        })
+    }
+}
+
+fn icc_key(
+    issuer: &PublicKey,
+    tags: &Tags,
+    static_data: &[u8],
+    pan: &[u8],
+    today: NaiveDate,
+) -> std::result::Result<PublicKey, OdaError> {
+    let cert = Certificate {
+        cert: tags.need(0x9F46, "ICC Public Key Certificate")?,
+        remainder: tags.get(0x9F48).unwrap_or_default(),
+        exponent: tags.need(0x9F47, "ICC Public Key Exponent")?,
+    };
+    oda::icc_key(issuer, cert, static_data, pan, today)
+}
+
+/// Terminal Verification Results, as far as ODA goes (byte 1); EMV Book 3, C5.
+fn tvr(oda: Option<&Oda>) -> [u8; 5] {
+    let byte1 = match oda {
+        Some(Oda {
+            method,
+            outcome: OdaOutcome::Failed(_),
+        }) => match method {
+            OdaMethod::SDA => 0x40,
+            OdaMethod::DDA | OdaMethod::FDDA => 0x08,
+            OdaMethod::CDA => 0x04,
+        },
+        Some(Oda {
+            outcome: OdaOutcome::Passed,
+            ..
+        }) => 0x00,
+        _ => 0x80, // Offline data authentication was not performed.
+    };
+    [byte1, 0, 0, 0, 0]
+}
+
+fn fields(dol: &Dol, data: &impl Provider) -> Vec<Field> {
+    (dol.iter())
+        .map(|&(tag, len)| Field {
+            tag,
+            name: (DOL_TAGS.iter())
+                .find(|(t, _)| *t == tag)
+                .map(|(_, name)| *name),
+            value: dol::fill(&[(tag, len)], data).into(),
+        })
+        .collect()
+}
+
+fn label(app: &Application, fallback: &str) -> String {
+    match app.app_label.is_empty() {
+        true => fallback.into(),
+        false => app.app_label.clone(),
+    }
+}
+
+/// `n` as `len` bytes of BCD, with leading zeroes.
+fn bcd(mut n: u64, len: usize) -> Vec<u8> {
+    let mut out = vec![0; len];
+    for b in out.iter_mut().rev() {
+        *b = (n % 10) as u8 | ((n / 10 % 10) as u8) << 4;
+        n /= 100;
+    }
+    out
+}
+
+/// Every tag the card's given us so far; the first one wins.
+#[derive(Debug, Default)]
+struct Tags(BTreeMap<u32, Vec<u8>>);
+
+impl Tags {
+    fn add(&mut self, data: &[u8]) {
+        for (_, tag, value) in ber::iter_deep(data).flatten() {
+            (self.0.entry(ber::tag_to_u32(tag))).or_insert_with(|| value.to_vec());
+        }
+    }
+
+    fn get(&self, tag: u32) -> Option<&[u8]> {
+        self.0.get(&tag).map(|v| &v[..])
+    }
+
+    fn need(&self, tag: u32, name: &'static str) -> std::result::Result<&[u8], OdaError> {
+        self.get(tag).ok_or(OdaError::Missing(name))
+    }
+}
+
+/// How far a transaction got, and what happened on the way.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Transaction {
+    /// Every decision, in order.
+    pub path: Vec<Step>,
+    /// The application that was selected.
+    pub aid: Option<HexVec>,
+    pub processing_options: Option<ProcessingOptions>,
+    pub oda: Option<Oda>,
+    /// What the cryptogram would cover, on the terminal's side.
+    pub coverage: Option<Coverage>,
+}
+
+impl Transaction {
+    fn step(&mut self, stage: Stage, detail: impl Into<String>) {
+        let detail = detail.into();
+        debug!(?stage, detail, "Step");
+        self.path.push(Step { stage, detail });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Stage {
+    Ppse,
+    Select,
+    ProcessingOptions,
+    Records,
+    Oda,
+    Cryptogram,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Step {
+    pub stage: Stage,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Oda {
+    pub method: OdaMethod,
+    pub outcome: OdaOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum OdaMethod {
+    SDA,
+    DDA,
+    /// Fast DDA; contactless DDA, done during GPO.
+    FDDA,
+    CDA,
+}
+
+impl std::fmt::Display for OdaMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SDA => write!(f, "SDA"),
+            Self::DDA => write!(f, "DDA"),
+            Self::FDDA => write!(f, "fDDA"),
+            Self::CDA => write!(f, "CDA"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum OdaOutcome {
+    Passed,
+    Failed(String),
+    /// Couldn't be done, or not all of it; why.
+    Skipped(String),
+}
+
+impl std::fmt::Display for OdaOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passed => write!(f, "passed"),
+            Self::Failed(why) => write!(f, "failed: {}", why),
+            Self::Skipped(why) => write!(f, "skipped: {}", why),
+        }
+    }
+}
+
+/// Which DOL a cryptogram covers, and what would be in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Coverage {
+    /// "CDOL1", or "PDOL" if the card generated it during GPO.
+    pub dol: &'static str,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Field {
+    pub tag: u32,
+    pub name: Option<&'static str>,
+    pub value: HexVec,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Visa qVSDC card with two applications, the preferred one of which is broken.
+    struct QvsdcCard;
+
+    impl CardTransport for QvsdcCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x00, 0xA4, 0x04, 0x00, 0x0E, b'2', ..] => &[
+                    0x6F, 0x31, 0x84, 0x0E, b'2', b'P', b'A', b'Y', b'.', b'S', b'Y', b'S', b'.',
+                    b'D', b'D', b'F', b'0', b'1', 0xA5, 0x1F, 0xBF, 0x0C, 0x1C, 0x61, 0x0C, 0x4F,
+                    0x07, 0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10, 0x87, 0x01, 0x02, 0x61, 0x0C,
+                    0x4F, 0x07, 0xA0, 0x00, 0x00, 0x00, 0x03, 0x20, 0x10, 0x87, 0x01, 0x01, 0x90,
+                    0x00,
+                ],
+                [0x00, 0xA4, 0x04, 0x00, 0x07, .., 0x20, 0x10, 0x00] => &[0x6A, 0x82],
+                [0x00, 0xA4, 0x04, 0x00, 0x07, .., 0x10, 0x10, 0x00] => &[
+                    0x6F, 0x1A, 0x84, 0x07, 0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10, 0xA5, 0x0F,
+                    0x50, 0x04, b'V', b'I', b'S', b'A', 0x9F, 0x38, 0x06, 0x9F, 0x66, 0x04, 0x9F,
+                    0x02, 0x06, 0x90, 0x00,
+                ],
+                // TTQ 36000000, amount 12.34.
+                [0x80, 0xA8, 0x00, 0x00, 0x0C, 0x83, 0x0A, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00] => {
+                    &[
+                        0x77, 0x15, 0x82, 0x02, 0x20, 0x40, 0x94, 0x04, 0x08, 0x01, 0x01, 0x00,
+                        0x9F, 0x26, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x90,
+                        0x00,
+                    ]
+                }
+                [0x00, 0xB2, 0x01, 0x0C, 0x00] => &[
+                    0x70, 0x0A, 0x5A, 0x08, 0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x00, 0x10, 0x90,
+                    0x00,
+                ],
+                _ => &[0x6D, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let (mut wbuf, mut rbuf) = ([0; 256], [0; 256]);
+        let date = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let mut terminal = Terminal::new(TerminalConfig::default(), date);
+        terminal.amount = 1234;
+        let tx = terminal.run(&mut QvsdcCard, &mut wbuf, &mut rbuf).unwrap();
+
+        let stages: Vec<_> = tx.path.iter().map(|s| s.stage).collect();
+        assert_eq!(
+            stages,
+            vec![
+                Stage::Ppse,
+                Stage::Select, // Visa Electron, which doesn't work.
+                Stage::Select,
+                Stage::ProcessingOptions,
+                Stage::Records,
+                Stage::Oda,
+                Stage::Cryptogram,
+            ]
+        );
+        assert_eq!(
+            tx.aid,
+            Some(vec![0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10].into())
+        );
+        assert_eq!(tx.processing_options.unwrap().aip, 0x2040);
+        // DDA, without the certificates for it.
+        assert_eq!(
+            tx.oda,
+            Some(Oda {
+                method: OdaMethod::DDA,
+                outcome: OdaOutcome::Failed("the card has no CA Public Key Index".into()),
+            })
+        );
+        let coverage = tx.coverage.unwrap();
+        assert_eq!(coverage.dol, "PDOL");
+        assert_eq!(
+            coverage.fields,
+            vec![
+                Field {
+                    tag: 0x9F66,
+                    name: Some("Terminal Transaction Qualifiers"),
+                    value: vec![0x36, 0x00, 0x00, 0x00].into(),
+                },
+                Field {
+                    tag: 0x9F02,
+                    name: Some("Amount, Authorised"),
+                    value: vec![0x00, 0x00, 0x00, 0x00, 0x12, 0x34].into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_config() {
+        let config: TerminalConfig = toml::from_str(
+            r#"
+            terminal_type = 0x25
+            ttq = "34 00 00 00"
+            contactless_cvm_limit = 2500
+            country = 250
+            currency = "EUR"
+            "#,
+        )
+        .unwrap();
+        // Anything left out is the same as the default.
+        assert_eq!(config.capabilities, TerminalConfig::pos().capabilities);
+        assert_eq!(config.currency, Currency::EUR);
+        assert!(toml::from_str::<TerminalConfig>("ttl = \"34000000\"").is_err());
+
+        assert_eq!(config.ttq_for(2500), vec![0x34, 0x00, 0x00, 0x00]);
+        assert_eq!(config.ttq_for(2501), vec![0x34, 0x40, 0x00, 0x00]);
+        let data = dol::fill(&[(0x9F35, 1), (0x9F1A, 2), (0x5F2A, 2)], &config);
+        assert_eq!(data, vec![0x25, 0x02, 0x50, 0x09, 0x78]);
+
+        for name in TerminalConfig::PRESETS {
+            assert!(TerminalConfig::preset(name).is_some(), "{}", name);
+        }
+        let unattended = TerminalConfig::preset("unattended").unwrap();
+        assert_eq!(unattended.ttq_for(1), vec![0x34, 0x80, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_bcd() {
+        assert_eq!(bcd(1234, 6), vec![0x00, 0x00, 0x00, 0x00, 0x12, 0x34]);
+        assert_eq!(bcd(826, 2), vec![0x08, 0x26]);
+        assert_eq!(bcd(26, 1), vec![0x26]);
+    }
+}
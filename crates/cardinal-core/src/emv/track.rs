@@ -0,0 +1,177 @@
+//! Magnetic stripe data, as a chip hands it out.
+//!
+//! Contactless cards that predate (or pretend to predate) full EMV do "mag-stripe mode":
+//! instead of a cryptogram, the terminal gets the same track data a swipe would, with a
+//! dynamic CVV or CVC3 folded into the discretionary data. Visa's MSD hands it back right
+//! in the GET PROCESSING OPTIONS response, with no AFL to read; Mastercard's puts it in a
+//! record, along with bitmaps saying where the CVC3 and its counters go. Either way, it's
+//! the same handful of tags, so we pick them out of whatever we're given.
+
+use super::decode::{self, Decoded};
+use crate::{ber, HexVec};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Track 2 (ISO 7813), from 0x57 (Track 2 Equivalent Data) or 0x9F6B (Track 2 Data):
+/// `PAN D YYMM service-code discretionary-data`, in BCD, padded with an F.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Track2 {
+    pub pan: String,
+    /// YYMM, as it is on the stripe; see [Track2::expiry].
+    pub expiry: String,
+    pub service_code: String,
+    /// Issuer's business; for mag-stripe mode, this is where the dynamic CVV/CVC3 and ATC
+    /// (or UN) go.
+    pub discretionary: String,
+}
+
+impl Track2 {
+    /// Parses it; None if there's no separator, or it's too short to have an expiry date
+    /// and service code after it.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let digits = hex::encode_upper(data);
+        let digits = digits.trim_end_matches('F');
+        let (pan, rest) = digits.split_once('D')?;
+        if rest.len() < 7 || !pan.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Self {
+            pan: pan.to_owned(),
+            expiry: rest[..4].to_owned(),
+            service_code: rest[4..7].to_owned(),
+            discretionary: rest[7..].to_owned(),
+        })
+    }
+
+    /// The expiry date, as a [Decoded::Expiry]; None if it isn't a real date.
+    pub fn expiry(&self) -> Option<Decoded> {
+        let yymm = hex::decode(&self.expiry).ok()?;
+        let [yy, mm] = <[u8; 2]>::try_from(yymm).ok()?;
+        decode::decode(0x5F24, &[yy, mm, 0x01], None)
+    }
+}
+
+/// Mag-stripe mode data elements; anything the card didn't send is None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MagStripe {
+    /// 0x56 (Track 1 Data), or 0x9F1F (Track 1 Discretionary Data) if that's all there is.
+    pub track1: Option<String>,
+    /// 0x57 or 0x9F6B.
+    pub track2: Option<Track2>,
+    /// 0x5F20: Cardholder Name.
+    pub cardholder_name: Option<String>,
+    /// 0x9F36: Application Transaction Counter.
+    pub atc: Option<u16>,
+    /// 0x9F10: Issuer Application Data; Visa MSD puts its dCVV bits in here.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub iad: Option<HexVec>,
+    /// 0x9F60, 0x9F61: CVC3 for track 1 and 2, from COMPUTE CRYPTOGRAPHIC CHECKSUM.
+    pub cvc3: (Option<u16>, Option<u16>),
+    /// 0x9F62, 0x9F65: where the CVC3 goes in track 1 and 2's discretionary data.
+    pub pcvc3: (Option<u64>, Option<u64>),
+    /// 0x9F63, 0x9F66: where the unpredictable number and ATC go.
+    pub punatc: (Option<u64>, Option<u64>),
+    /// 0x9F64, 0x9F67: how many of those digits are the ATC.
+    pub natc: (Option<u8>, Option<u8>),
+}
+
+impl MagStripe {
+    /// Picks mag-stripe data out of a BER-TLV blob (a GPO response, a record...); None if
+    /// there isn't any track data in it.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut slf = Self::default();
+        slf.update(data);
+        slf.track2.is_some().then_some(slf)
+    }
+
+    /// Adds whatever's in a BER-TLV blob; later values win.
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, tag, value) in ber::iter_deep(data).flatten() {
+            match ber::tag_to_u32(tag) {
+                0x56 => self.track1 = Some(String::from_utf8_lossy(value).into()),
+                0x9F1F => {
+                    self.track1 =
+                        (self.track1.take()).or_else(|| Some(String::from_utf8_lossy(value).into()))
+                }
+                0x57 | 0x9F6B => self.track2 = Track2::parse(value).or(self.track2.take()),
+                0x5F20 => self.cardholder_name = Some(String::from_utf8_lossy(value).trim().into()),
+                0x9F36 => self.atc = uint(value).map(|v| v as u16),
+                0x9F10 => self.iad = Some(value.into()),
+                0x9F60 => self.cvc3.0 = uint(value).map(|v| v as u16),
+                0x9F61 => self.cvc3.1 = uint(value).map(|v| v as u16),
+                0x9F62 => self.pcvc3.0 = uint(value),
+                0x9F65 => self.pcvc3.1 = uint(value),
+                0x9F63 => self.punatc.0 = uint(value),
+                0x9F66 => self.punatc.1 = uint(value),
+                0x9F64 => self.natc.0 = value.first().copied(),
+                0x9F67 => self.natc.1 = value.first().copied(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A big-endian binary number, of up to 8 bytes.
+fn uint(v: &[u8]) -> Option<u64> {
+    (v.len() <= 8).then(|| v.iter().fold(0, |n, b| n << 8 | u64::from(*b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_track2() {
+        let track2 = Track2::parse(&[
+            0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x00, 0x10, 0xD2, 0x71, 0x22, 0x01, 0x12, 0x34,
+            0x56, 0x7F,
+        ])
+        .unwrap();
+        assert_eq!(
+            track2,
+            Track2 {
+                pan: "4761739001010010".into(),
+                expiry: "2712".into(),
+                service_code: "201".into(),
+                discretionary: "1234567".into(),
+            }
+        );
+        assert_eq!(track2.expiry().unwrap().to_string(), "2027-12");
+
+        // No separator, or nothing after it.
+        assert_eq!(Track2::parse(&[0x47, 0x61, 0x73, 0x90]), None);
+        assert_eq!(Track2::parse(&[0x47, 0x61, 0xD2, 0x71]), None);
+    }
+
+    #[test]
+    fn test_parse_mag_stripe() {
+        // A Visa MSD GPO response: AIP, Track 2, IAD and cardholder name, with no AFL.
+        let gpo = [
+            0x77, 0x20, 0x82, 0x02, 0x00, 0x80, 0x57, 0x0C, 0x47, 0x61, 0x73, 0x90, 0x01, 0x01,
+            0x00, 0x10, 0xD2, 0x71, 0x22, 0x01, 0x9F, 0x10, 0x02, 0x06, 0x01, 0x5F, 0x20, 0x06,
+            0x56, 0x49, 0x53, 0x41, 0x20, 0x20,
+        ];
+        let ms = MagStripe::parse(&gpo).unwrap();
+        assert_eq!(ms.track2.unwrap().pan, "4761739001010010");
+        assert_eq!(ms.iad, Some(vec![0x06, 0x01].into()));
+        assert_eq!(ms.cardholder_name.as_deref(), Some("VISA"));
+
+        // A PayPass mag-stripe record: Track 2 Data, and where the CVC3 goes in it.
+        let record = [
+            0x70, 0x19, 0x9F, 0x6B, 0x08, 0x54, 0x13, 0xD2, 0x71, 0x22, 0x01, 0x00, 0x0F, 0x9F,
+            0x65, 0x02, 0x00, 0x0E, 0x9F, 0x66, 0x02, 0x0E, 0x70, 0x9F, 0x67, 0x01, 0x02,
+        ];
+        let ms = MagStripe::parse(&record).unwrap();
+        assert_eq!(ms.track2.as_ref().unwrap().pan, "5413");
+        assert_eq!(ms.pcvc3, (None, Some(0x000E)));
+        assert_eq!(ms.punatc, (None, Some(0x0E70)));
+        assert_eq!(ms.natc, (None, Some(2)));
+
+        assert_eq!(
+            MagStripe::parse(&[0x77, 0x04, 0x82, 0x02, 0x19, 0x80]),
+            None
+        );
+    }
+}
@@ -0,0 +1,1356 @@
+pub mod cybernet;
+pub mod octopus;
+pub mod purse;
+
+#[cfg(feature = "std")]
+use crate::reader_quirks::FelicaPassthrough;
+#[cfg(feature = "std")]
+use crate::{transparent, util, CardTransport, PCSCTransparentError};
+use crate::{Error, HexVec, Result};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use nom::bytes::complete::{tag, take};
+use nom::combinator::map;
+use nom::number::complete::{be_u64, be_u8, le_u16};
+use num_enum::{FromPrimitive, IntoPrimitive};
+use scroll::ctx::TryIntoCtx;
+use scroll::{Pread, Pwrite, BE, LE};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use tracing::debug;
+
+pub type IResult<'a, T> = nom::IResult<&'a [u8], T>;
+
+/// Longest a frame can be, length byte included.
+pub const MAX_FRAME_LEN: usize = 255;
+
+/// Parses a CID retrieved from PCSC into an IDm.
+/// In other words, casts an 8-byte &[u8] into an u64.
+pub fn cid_to_idm(cid: &[u8]) -> Result<u64> {
+    Ok(cid.pread_with(0, BE)?)
+}
+
+/// Most Systems a card can have; their numbers go in 4 bits of the IDm.
+pub const MAX_SYSTEMS: usize = 0x0F;
+/// Most Services one command can refer to; Block List Elements index them in 4 bits.
+pub const MAX_SERVICES: usize = 16;
+/// Most blocks one ReadWithoutEncryption can return, before the response overflows a frame.
+/// Cards can have lower limits (FeliCa Lite-S reads 4 at most); they say "illegal number of
+/// blocks" (A2) if you ask for more.
+pub const MAX_READ_BLOCKS: usize = (MAX_FRAME_LEN - 13) / 16;
+
+/// Returns the IDm for System number N on the card identified by IDm0,
+/// eg. 0 for the default system, 1 for the next, etc.
+pub fn idm_for_service(idm0: u64, n: usize) -> Result<u64> {
+    if n >= MAX_SYSTEMS {
+        return Err(Error::FelicaEncoding(format!(
+            "system {} doesn't fit in an IDm (max {})",
+            n,
+            MAX_SYSTEMS - 1
+        )));
+    }
+    let mut idm_bytes = idm0.to_be_bytes();
+    idm_bytes[0] = (idm_bytes[0] & 0b0000_1111) | ((n as u8) << 4);
+    Ok(u64::from_be_bytes(idm_bytes))
+}
+
+/// Checks that a list fits in a command, and returns the one-byte count that goes in
+/// front of it.
+fn count(what: &str, len: usize, max: usize) -> Result<u8> {
+    match len <= max.min(u8::MAX as usize) {
+        true => Ok(len as u8),
+        false => Err(Error::FelicaEncoding(format!(
+            "{} {}, but a command can have at most {}",
+            len, what, max
+        ))),
+    }
+}
+
+/// Pulls the FeliCa frame out of a `FF 00 00 00` pseudo-APDU, as built by [Command::apdu].
+/// For transports that can talk to FeliCa cards natively, rather than through PCSC.
+pub fn unwrap_apdu(capdu: &[u8]) -> Option<&[u8]> {
+    match capdu {
+        [0xFF, 0x00, 0x00, 0x00, lc, frame @ ..] if frame.len() == *lc as usize => Some(frame),
+        _ => None,
+    }
+}
+
+pub trait Command<'a>: Sized + TryIntoCtx
+where
+    <Self as TryIntoCtx>::Error: From<scroll::Error>,
+    crate::Error: From<<Self as TryIntoCtx>::Error>,
+{
+    /// Associated command code.
+    const CODE: CommandCode;
+
+    /// Associated response code.
+    type Response: Response<'a>;
+
+    /// Writes the frame (a length byte, followed by the command itself) into `buf`, and
+    /// returns how long it is.
+    fn frame(self, buf: &mut [u8]) -> Result<usize> {
+        let cmd_len = buf.pwrite(self, 1)?; // Write the command.
+        if cmd_len + 1 > MAX_FRAME_LEN {
+            return Err(Error::FelicaFrameTooLong(cmd_len + 1));
+        }
+        buf.pwrite::<u8>((cmd_len + 1) as u8, 0)?; // Go back and add the length byte.
+        Ok(cmd_len + 1)
+    }
+
+    /// Return an APDU wrapper.
+    #[cfg(feature = "std")]
+    fn apdu<'w>(self, wbuf: &'w mut [u8]) -> Result<apdu::Command<'w>> {
+        let len = self.frame(wbuf)?;
+
+        // Wrap in a PCSC pseudo-APDU that sends it straight through to the card.
+        let pl = &wbuf[..len];
+        Ok(apdu::Command::new_with_payload(0xFF, 0x00, 0x00, 0x00, pl))
+    }
+
+    /// Executes the command against the given card and returns the response. Errors say
+    /// which command it was, eg. "FeliCa 0x06" ([Error::Command]).
+    #[cfg(feature = "std")]
+    fn call(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'a mut [u8],
+    ) -> Result<Self::Response> {
+        exchange(self, card, wbuf, rbuf)
+            .map_err(|err| err.in_command(format!("FeliCa 0x{:02X}", u8::from(Self::CODE))))
+    }
+}
+
+/// Does the actual work for [Command::call].
+#[cfg(feature = "std")]
+fn exchange<'a, C: Command<'a>>(
+    cmd: C,
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'a mut [u8],
+) -> Result<C::Response>
+where
+    <C as TryIntoCtx>::Error: From<scroll::Error>,
+    crate::Error: From<<C as TryIntoCtx>::Error>,
+{
+    // The frame goes straight in after the wrapper's header, rather than being put
+    // together somewhere else and copied in.
+    let frame_len = cmd.frame(wbuf.get_mut(5..).ok_or(Error::InsufficientBuffer)?)?;
+    wbuf[..5].copy_from_slice(&[0xFF, 0x00, 0x00, 0x00, frame_len as u8]);
+    let wrapped = &wbuf[..5 + frame_len];
+
+    // The FF 00 00 00 wrapper is an ACS-ism; other readers say 6A81 (function not
+    // supported), so fall back to a PC/SC transparent session, which does the same.
+    // If we know what the reader is, go straight for the one it takes.
+    let passthrough = card.reader_quirks().map(|q| q.felica).unwrap_or_default();
+    let len = match passthrough {
+        FelicaPassthrough::Unsupported => return Err(Error::FelicaPassthroughUnsupported),
+        FelicaPassthrough::Transparent => None,
+        FelicaPassthrough::Wrapper => Some(util::call_raw(card, wrapped, rbuf)?.len()),
+        FelicaPassthrough::Auto => match util::call_raw(card, wrapped, rbuf) {
+            Ok(data) => Some(data.len()),
+            Err(Error::APDU(0x6A, 0x81)) => None,
+            Err(err) => return Err(err),
+        },
+    };
+    let data = match len {
+        Some(len) => &rbuf[..len],
+        None => {
+            debug!("Reader doesn't support the FeliCa wrapper, trying a transparent session");
+            // That needs wbuf for itself, so the frame has to move out of the way.
+            let mut frame = [0u8; MAX_FRAME_LEN];
+            frame[..frame_len].copy_from_slice(&wbuf[5..5 + frame_len]);
+            let frame = &frame[..frame_len];
+            transparent::transceive(card, wbuf, rbuf, transparent::Framing::FeliCa, frame).map_err(
+                |err| match err {
+                    Error::APDU(0x6A, 0x81)
+                    | Error::APDU(0x6D, 0x00)
+                    | Error::APDU(0x6E, 0x00)
+                    | Error::PCSCTransparent(_, PCSCTransparentError::NotSupported) => {
+                        Error::FelicaPassthroughUnsupported
+                    }
+                    err => err,
+                },
+            )?
+        }
+    };
+
+    let rsp = C::Response::parse(data)?;
+    match rsp.status() {
+        status if status.is_ok() => Ok(rsp),
+        status => Err(Error::FelicaStatus(status)),
+    }
+}
+
+pub trait Response<'a>: Sized {
+    const CODE: CommandCode;
+
+    /// Returns the status code of the response.
+    fn status(&self) -> StatusFlags;
+
+    fn iparse(data: &'a [u8]) -> IResult<Self>;
+
+    /// Parses a response frame, length byte and all. Anything after the length is ignored;
+    /// a frame that's shorter than it says (or than its contents need) is a
+    /// [Error::FelicaTruncated], rather than a cryptic nom error.
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let len = match data.first() {
+            None => return Err(Error::FelicaTruncated("empty response".into())),
+            Some(&len) if len as usize > data.len() => {
+                return Err(Error::FelicaTruncated(format!(
+                    "frame says {} bytes, got {}",
+                    len,
+                    data.len()
+                )))
+            }
+            Some(&len) => len as usize,
+        };
+        match Self::iparse(&data[..len]) {
+            Ok((_, v)) => Ok(v),
+            Err(nom::Err::Error(err) | nom::Err::Failure(err))
+                if err.code == nom::error::ErrorKind::Eof =>
+            {
+                Err(Error::FelicaTruncated(format!(
+                    "{:?} ends {} bytes in, partway through a field",
+                    Self::CODE,
+                    len - err.input.len()
+                )))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Status flags from a response: flag 1 says where it went wrong, flag 2 says what.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatusFlags {
+    pub flag1: u8,
+    pub flag2: u8,
+}
+
+impl StatusFlags {
+    pub const OK: Self = Self::new(0x00, 0x00);
+
+    pub const fn new(flag1: u8, flag2: u8) -> Self {
+        Self { flag1, flag2 }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        *self == Self::OK
+    }
+
+    /// What went wrong, going by flag 2, if it's one we know.
+    /// From the FeliCa Card User's Manual, Section 4.5.
+    pub fn description(&self) -> Option<&'static str> {
+        Some(match self.flag2 {
+            0x00 => "success",
+            0x01 => "purse data underflow or overflow",
+            0x02 => "cashback data exceeds the purse data",
+            0x70 => "memory error",
+            0x71 => "memory has been written too many times",
+            0xA1 => "illegal number of services",
+            0xA2 => "illegal number of blocks",
+            0xA3 => "block list doesn't match the service list",
+            0xA4 => "illegal service type",
+            0xA5 => "access denied",
+            0xA6 => "service doesn't exist",
+            0xA7 => "illegal access mode in the block list",
+            0xA8 => "block number out of range",
+            0xA9 => "write failed",
+            0xAA => "key change failed",
+            0xAB => "illegal package parity or MAC",
+            _ => return None,
+        })
+    }
+}
+
+impl core::fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.description() {
+            Some(desc) => write!(f, "{}", desc)?,
+            None => write!(f, "unknown error")?,
+        }
+        write!(f, " (flag1={:02X} flag2={:02X})", self.flag1, self.flag2)
+    }
+}
+
+fn parse_status_flags(data: &[u8]) -> IResult<'_, StatusFlags> {
+    let (data, flag1) = be_u8(data)?;
+    let (data, flag2) = be_u8(data)?;
+    Ok((data, StatusFlags::new(flag1, flag2)))
+}
+
+/// Helper to parse a standard response header (length, code, IDm) and return the IDm.
+fn parse_response_header(code: CommandCode, data: &[u8]) -> IResult<u64> {
+    let (data, _) = tag(&[data.len() as u8])(data)?;
+    let (data, _) = tag(&[code.into()])(data)?;
+    be_u64(data)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum ICType {
+    FeliCaRCSA212 = 0x46,
+    FeliCaRCSA202 = 0x45,
+    FeliCaRCSA201 = 0x44,
+    FeliCaRCSA012 = 0x35,
+    FeliCaRCSA001 = 0x32,
+    FeliCaRCS962 = 0x20,
+    FeliCaRCS960 = 0x0D,
+    FeliCaRCS953 = 0x09,
+    FeliCaRCS952 = 0x08,
+    FeliCaRCS915 = 0x01,
+    MobileFeliCaV3(u8) = 0x14, // 0x14-0x1F
+    MobileFeliCaV2(u8) = 0x10, // 0x10-0x13
+    MobileFeliCaV1(u8) = 0x06, // 0x06-0x07
+    FeliCaLiteRCS966 = 0xF1,
+    FeliCaLiteRCS965 = 0xF0,
+    FeliCaLinkRCS967LiteSMode = 0xF2,
+    FeliCaLinkRCS967PlugMode = 0xE1,
+    FeliCaLinkRCS967NFCDEPMode = 0xFF,
+    FeliCaPlugRCS926 = 0xE0,
+    Unknown(u8) = 0x00,
+}
+
+impl From<u8> for ICType {
+    fn from(v: u8) -> Self {
+        match v {
+            0x46 => Self::FeliCaRCSA212,
+            0x45 => Self::FeliCaRCSA202,
+            0x44 => Self::FeliCaRCSA201,
+            0x35 => Self::FeliCaRCSA012,
+            0x32 => Self::FeliCaRCSA001,
+            0x20 => Self::FeliCaRCS962,
+            0x0D => Self::FeliCaRCS960,
+            0x09 => Self::FeliCaRCS953,
+            0x08 => Self::FeliCaRCS952,
+            0x01 => Self::FeliCaRCS915,
+            0x14..=0x1F => Self::MobileFeliCaV3(v),
+            0x10..=0x13 => Self::MobileFeliCaV2(v),
+            0x06..=0x07 => Self::MobileFeliCaV1(v),
+            0xF1 => Self::FeliCaLiteRCS966,
+            0xF0 => Self::FeliCaLiteRCS965,
+            0xF2 => Self::FeliCaLinkRCS967LiteSMode,
+            0xE1 => Self::FeliCaLinkRCS967PlugMode,
+            0xFF => Self::FeliCaLinkRCS967NFCDEPMode,
+            0xE0 => Self::FeliCaPlugRCS926,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl core::fmt::Display for ICType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::FeliCaRCSA212 => write!(f, "FeliCa RC-SA21/2"),
+            Self::FeliCaRCSA202 => write!(f, "FeliCa RC-SA20/2"),
+            Self::FeliCaRCSA201 => write!(f, "FeliCa RC-SA20/1"),
+            Self::FeliCaRCSA012 => write!(f, "FeliCa RC-SA01/2"),
+            Self::FeliCaRCSA001 => write!(f, "FeliCa RC-SA00/1"),
+            Self::FeliCaRCS962 => write!(f, "FeliCa RC-S962"),
+            Self::FeliCaRCS960 => write!(f, "FeliCa RC-S960"),
+            Self::FeliCaRCS953 => write!(f, "FeliCa RC-S953"),
+            Self::FeliCaRCS952 => write!(f, "FeliCa RC-S952"),
+            Self::FeliCaRCS915 => write!(f, "FeliCa RC-S915"),
+            Self::MobileFeliCaV3(v) => write!(f, "Mobile FeliCa 3.{}", v - 0x14),
+            Self::MobileFeliCaV2(v) => write!(f, "Mobile FeliCa 2.{}", v - 0x10),
+            Self::MobileFeliCaV1(v) => write!(f, "Mobile FeliCa 1.{}", v - 0x06),
+            Self::FeliCaLiteRCS966 => write!(f, "FeliCa Lite RC-S966"),
+            Self::FeliCaLiteRCS965 => write!(f, "FeliCa Lite RC-S965"),
+            Self::FeliCaLinkRCS967LiteSMode => write!(f, "FeliCa Link RC-S967 (Lite-S/HT Mode)"),
+            Self::FeliCaLinkRCS967PlugMode => write!(f, "FeliCa Link RC-S967 (Plug Mode)"),
+            Self::FeliCaLinkRCS967NFCDEPMode => write!(f, "FeliCa Link RC-S967 (NFC-DEP Mode)"),
+            Self::FeliCaPlugRCS926 => write!(f, "FeliCa Plug RC-S926"),
+            Self::Unknown(v) => write!(f, "Unknown (0x{:02X})", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[repr(u8)]
+pub enum ROMType {
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+/// A SystemCode identifies the type of a System on a card with multiple ones.
+///
+/// The generic ones (NDEF, HostEmulation, and the ones starting with "FeliCa") are
+/// defined in the "FeliCa Technology Code Descriptions" available from Sony:
+///   https://www.sony.net/Products/felica/business/tech-support/
+///
+/// The branded ones are from scanning different cards, and various websites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u16)]
+pub enum SystemCode {
+    /// Suica (JR East). Also on many compatible cards, eg. Pasmo, ICOCA.
+    Suica = 0x0003,
+    /// Sytem that uses regular NFC NDEF.
+    NDEF = 0x12FC,
+    /// Host-based Emulation for NFC-F (HCE-F).
+    HostEmulation = 0x4000,
+    /// Octopus (Hong Kong).
+    Octopus = 0x8008,
+    /// IruCa (Takamatsu-Kotohira Electric Railroad).
+    IruCa = 0x80DE,
+    /// PASPY (Hiroshima).
+    PASPY = 0x8592,
+    /// SAPICA (Sapporo).
+    SAPICA = 0x865E,
+    /// OKICA (Okinawa).
+    OKICA = 0x8FC1,
+    /// Ryuto (Niigata).
+    Ryuto = 0x8B5D,
+    /// FeliCa Lite-S
+    FeliCaLiteS = 0x88B4,
+    /// FeliCa Secure ID
+    FeliCaSecureID = 0x957A,
+    /// FeliCa Networks "Common Area".
+    FeliCaCommon = 0xFE00,
+    /// FeliCa Plug
+    FeliCaPlug = 0xFEE1,
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl core::fmt::Display for SystemCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Suica => write!(f, "Suica"),
+            Self::NDEF => write!(f, "NFC NDEF"),
+            Self::HostEmulation => write!(f, "Host-based Emulation"),
+            Self::Octopus => write!(f, "Octopus"),
+            Self::IruCa => write!(f, "IruCa"),
+            Self::PASPY => write!(f, "PASPY"),
+            Self::SAPICA => write!(f, "SAPICA"),
+            Self::OKICA => write!(f, "OKICA"),
+            Self::Ryuto => write!(f, "Ryuto"),
+            Self::FeliCaLiteS => write!(f, "FeliCa Lite-S"),
+            Self::FeliCaSecureID => write!(f, "FeliCa Secure ID"),
+            Self::FeliCaCommon => write!(f, "FeliCa Common Area"),
+            Self::FeliCaPlug => write!(f, "FeliCa Plug"),
+            Self::Unknown(v) => write!(f, "Unknown({:04X})", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ServiceKind {
+    Invalid,
+    Random,
+    Cyclic,
+    Purse,
+}
+
+impl core::fmt::Display for ServiceKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "INVALID"),
+            Self::Random => write!(f, "Random"),
+            Self::Cyclic => write!(f, "Cyclic"),
+            Self::Purse => write!(f, "Purse"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ServiceAccess {
+    Invalid,
+    ReadWrite,
+    ReadOnly,
+    PurseDirect,
+    PurseCashback,
+    PurseDecrement,
+}
+
+impl core::fmt::Display for ServiceAccess {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "INVALID"),
+            Self::ReadWrite => write!(f, "Read/Write"),
+            Self::ReadOnly => write!(f, "Read"),
+            Self::PurseDirect => write!(f, "Direct"),
+            Self::PurseCashback => write!(f, "Cashback"),
+            Self::PurseDecrement => write!(f, "Decrement"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServiceCode {
+    pub code: u16,   // Full code.
+    pub number: u16, // 10 bits.
+    pub kind: ServiceKind,
+    pub access: ServiceAccess,
+    pub is_authenticated: bool, // Authentication required?
+}
+
+impl From<u16> for ServiceCode {
+    fn from(v: u16) -> Self {
+        let attrs = (v as u8) & 0x3F;
+        let (kind, access, is_authenticated) = match attrs & 0b00_111111 {
+            0b00_001000 => (ServiceKind::Random, ServiceAccess::ReadWrite, true),
+            0b00_001001 => (ServiceKind::Random, ServiceAccess::ReadWrite, false),
+            0b00_001010 => (ServiceKind::Random, ServiceAccess::ReadOnly, true),
+            0b00_001011 => (ServiceKind::Random, ServiceAccess::ReadOnly, false),
+            0b00_001100 => (ServiceKind::Cyclic, ServiceAccess::ReadWrite, true),
+            0b00_001101 => (ServiceKind::Cyclic, ServiceAccess::ReadWrite, false),
+            0b00_001110 => (ServiceKind::Cyclic, ServiceAccess::ReadOnly, true),
+            0b00_001111 => (ServiceKind::Cyclic, ServiceAccess::ReadOnly, false),
+            0b00_010000 => (ServiceKind::Purse, ServiceAccess::PurseDirect, true),
+            0b00_010001 => (ServiceKind::Purse, ServiceAccess::PurseDirect, false),
+            0b00_010010 => (ServiceKind::Purse, ServiceAccess::PurseCashback, true),
+            0b00_010011 => (ServiceKind::Purse, ServiceAccess::PurseCashback, false),
+            0b00_010100 => (ServiceKind::Purse, ServiceAccess::PurseDecrement, true),
+            0b00_010101 => (ServiceKind::Purse, ServiceAccess::PurseDecrement, false),
+            0b00_010110 => (ServiceKind::Purse, ServiceAccess::ReadOnly, true),
+            0b00_010111 => (ServiceKind::Purse, ServiceAccess::ReadOnly, false),
+            _ => (ServiceKind::Invalid, ServiceAccess::Invalid, false),
+        };
+        Self {
+            code: v,
+            number: v >> 6,
+            kind,
+            access,
+            is_authenticated,
+        }
+    }
+}
+
+/// An Area, which owns a range of Service (and sub-Area) numbers, from its own up to its
+/// end. Unlike Services, there's no "no key" attribute for Areas: every Area has a key,
+/// which you need to create things in it, but not to use the Services in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AreaCode {
+    pub code: u16,   // Full code.
+    pub number: u16, // 10 bits.
+    /// Can sub-Areas be created in it? (Attribute 000000b; 000001b means no.)
+    pub can_subdivide: bool,
+    pub end: u16,        // Full end code; the attribute bits are always 111111b.
+    pub end_number: u16, // 10 bits; the last number that belongs to this Area.
+}
+
+impl AreaCode {
+    /// Decodes an Area from its code and end code, as in Search Service Code.
+    pub fn new(code: u16, end: u16) -> Self {
+        Self {
+            code,
+            number: code >> 6,
+            can_subdivide: code & 0x3F == 0b00_000000,
+            end,
+            end_number: end >> 6,
+        }
+    }
+
+    /// Does a Service or Area number fall inside this Area?
+    pub fn contains(&self, number: u16) -> bool {
+        (self.number..=self.end_number).contains(&number)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[repr(u8)]
+pub enum CommandCode {
+    RequestService = 0x02,
+    RequestServiceResponse = 0x03,
+    RequestResponse = 0x04,
+    RequestResponseResponse = 0x05, // yo dawg
+    ReadWithoutEncryption = 0x06,
+    ReadWithoutEncryptionResponse = 0x07,
+    WriteWithoutEncryption = 0x08,
+    WriteWithoutEncryptionResponse = 0x09,
+    SearchServiceCode = 0x0A,
+    SearchServiceCodeResponse = 0x0B,
+    RequestSystemCode = 0x0C,
+    RequestSystemCodeResponse = 0x0D,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+/// Most node codes a single [RequestService] can ask about.
+pub const MAX_REQUEST_SERVICE_NODES: usize = 32;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestService {
+    pub idm: u64,
+    /// At most [MAX_REQUEST_SERVICE_NODES]; use [request_service_all] for more.
+    pub node_codes: Vec<u16>,
+}
+
+impl<'a> Command<'a> for &RequestService {
+    const CODE: CommandCode = CommandCode::RequestService;
+    type Response = RequestServiceResponse;
+}
+
+impl TryIntoCtx for &RequestService {
+    type Error = Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let num = count(
+            "node codes",
+            self.node_codes.len(),
+            MAX_REQUEST_SERVICE_NODES,
+        )?;
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        wbuf.gwrite::<u8>(num, &mut offset)?;
+        for code in &self.node_codes {
+            wbuf.gwrite_with::<u16>(*code, &mut offset, LE)?;
+        }
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RequestServiceResponse {
+    pub idm: u64,
+    pub key_versions: Vec<u16>,
+}
+
+impl<'a> Response<'a> for RequestServiceResponse {
+    const CODE: CommandCode = CommandCode::RequestServiceResponse;
+
+    fn status(&self) -> StatusFlags {
+        StatusFlags::OK
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, num) = be_u8(data)?;
+        let (mut data, mut key_versions) = (data, vec![]);
+        for _ in 0..num {
+            let (data_, ver) = le_u16(data)?;
+            data = data_;
+            key_versions.push(ver);
+        }
+        Ok((data, Self { idm, key_versions }))
+    }
+}
+
+/// Asks for the key versions of any number of Areas and Services, in as many
+/// [RequestService]s as it takes. Key versions come back in the same order as the codes;
+/// nodes that don't exist have FFFF.
+#[cfg(feature = "std")]
+pub fn request_service_all(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    idm: u64,
+    codes: &[u16],
+) -> Result<RequestServiceResponse> {
+    let mut key_versions = Vec::with_capacity(codes.len());
+    for chunk in codes.chunks(MAX_REQUEST_SERVICE_NODES) {
+        let rsp = RequestService {
+            idm,
+            node_codes: chunk.to_vec(),
+        }
+        .call(card, wbuf, rbuf)?;
+        // Zipping a short answer up with the rest would pin key versions on the wrong nodes.
+        if rsp.key_versions.len() != chunk.len() {
+            return Err(Error::FelicaTruncated(format!(
+                "asked for {} key versions, got {}",
+                chunk.len(),
+                rsp.key_versions.len()
+            )));
+        }
+        key_versions.extend(rsp.key_versions);
+    }
+    Ok(RequestServiceResponse { idm, key_versions })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestResponse {
+    pub idm: u64,
+}
+
+impl<'a> Command<'a> for &RequestResponse {
+    const CODE: CommandCode = CommandCode::RequestResponse;
+    type Response = RequestResponseResponse;
+}
+
+impl TryIntoCtx for &RequestResponse {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RequestResponseResponse {
+    pub idm: u64,
+    pub mode: u8,
+}
+
+impl<'a> Response<'a> for RequestResponseResponse {
+    const CODE: CommandCode = CommandCode::RequestResponseResponse;
+
+    fn status(&self) -> StatusFlags {
+        StatusFlags::OK
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, mode) = be_u8(data)?;
+        Ok((data, Self { idm, mode }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReadWithoutEncryption {
+    pub idm: u64,
+    pub services: Vec<u16>,
+    pub blocks: Vec<BlockListElement>,
+}
+
+impl<'a> Command<'a> for &ReadWithoutEncryption {
+    const CODE: CommandCode = CommandCode::ReadWithoutEncryption;
+    type Response = ReadWithoutEncryptionResponse;
+}
+
+impl TryIntoCtx for &ReadWithoutEncryption {
+    type Error = Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        wbuf.gwrite::<u8>(
+            count("services", self.services.len(), MAX_SERVICES)?,
+            &mut offset,
+        )?;
+        for sid in self.services.iter() {
+            wbuf.gwrite_with(sid, &mut offset, LE)?;
+        }
+        wbuf.gwrite::<u8>(count("blocks", self.blocks.len(), 0xFF)?, &mut offset)?;
+        for bid in self.blocks.iter() {
+            wbuf.gwrite(bid, &mut offset)?;
+        }
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReadWithoutEncryptionResponse {
+    pub idm: u64,
+    pub status: StatusFlags,
+    pub blocks: Vec<HexVec>,
+}
+
+impl<'a> Response<'a> for ReadWithoutEncryptionResponse {
+    const CODE: CommandCode = CommandCode::ReadWithoutEncryptionResponse;
+
+    fn status(&self) -> StatusFlags {
+        self.status
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, status) = parse_status_flags(data)?;
+
+        // Block data is only included if status is (0x00, 0x00).
+        let (data, blocks) = if status.is_ok() {
+            let (mut data, num_blocks) = be_u8(data)?;
+            let mut blocks = vec![];
+            for _ in 0..num_blocks {
+                let (data_, block) = take(16usize)(data)?;
+                data = data_;
+                blocks.push(block.into());
+            }
+            (data, blocks)
+        } else {
+            (data, vec![])
+        };
+        Ok((
+            data,
+            Self {
+                idm,
+                status,
+                blocks,
+            },
+        ))
+    }
+}
+
+/// Only with the `write` feature; see the crate's Cargo.toml.
+#[cfg(feature = "write")]
+#[derive(Debug, PartialEq, Eq)]
+pub struct WriteWithoutEncryption {
+    pub idm: u64,
+    pub services: Vec<u16>,
+    pub blocks: Vec<BlockListElement>,
+    pub block_data: Vec<[u8; 16]>,
+}
+
+#[cfg(feature = "write")]
+impl<'a> Command<'a> for &WriteWithoutEncryption {
+    const CODE: CommandCode = CommandCode::WriteWithoutEncryption;
+    type Response = WriteWithoutEncryptionResponse;
+}
+
+#[cfg(feature = "write")]
+impl TryIntoCtx for &WriteWithoutEncryption {
+    type Error = Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        wbuf.gwrite::<u8>(
+            count("services", self.services.len(), MAX_SERVICES)?,
+            &mut offset,
+        )?;
+        for sid in self.services.iter() {
+            wbuf.gwrite_with(sid, &mut offset, LE)?;
+        }
+        wbuf.gwrite::<u8>(count("blocks", self.blocks.len(), 0xFF)?, &mut offset)?;
+        for bid in self.blocks.iter() {
+            wbuf.gwrite(bid, &mut offset)?;
+        }
+        for data in self.block_data.iter() {
+            wbuf.gwrite(&data[..], &mut offset)?;
+        }
+        Ok(offset)
+    }
+}
+
+#[cfg(feature = "write")]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WriteWithoutEncryptionResponse {
+    pub idm: u64,
+    pub status: StatusFlags,
+}
+
+#[cfg(feature = "write")]
+impl<'a> Response<'a> for WriteWithoutEncryptionResponse {
+    const CODE: CommandCode = CommandCode::WriteWithoutEncryptionResponse;
+
+    fn status(&self) -> StatusFlags {
+        self.status
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, status) = parse_status_flags(data)?;
+
+        Ok((data, Self { idm, status }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SearchServiceCode {
+    pub idm: u64,
+    pub idx: u16,
+}
+
+impl<'a> Command<'a> for &SearchServiceCode {
+    const CODE: CommandCode = CommandCode::SearchServiceCode;
+    type Response = SearchServiceCodeResponse;
+}
+
+impl TryIntoCtx for &SearchServiceCode {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        wbuf.gwrite_with(self.idx, &mut offset, LE)?;
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SearchServiceCodeResult {
+    Area(AreaCode),
+    Service(ServiceCode),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SearchServiceCodeResponse {
+    pub idm: u64,
+    pub result: Option<SearchServiceCodeResult>,
+}
+
+impl<'a> Response<'a> for SearchServiceCodeResponse {
+    const CODE: CommandCode = CommandCode::SearchServiceCodeResponse;
+
+    fn status(&self) -> StatusFlags {
+        StatusFlags::OK
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, result) = if data.len() == 2 {
+            map(le_u16, |code| {
+                if code == 0xFFFF {
+                    None
+                } else {
+                    Some(SearchServiceCodeResult::Service(code.into()))
+                }
+            })(data)?
+        } else {
+            let (data, code) = le_u16(data)?;
+            let (data, end) = le_u16(data)?;
+            let result = SearchServiceCodeResult::Area(AreaCode::new(code, end));
+            (data, Some(result))
+        };
+        Ok((data, Self { idm, result }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestSystemCode {
+    pub idm: u64,
+}
+
+impl<'a> Command<'a> for &RequestSystemCode {
+    const CODE: CommandCode = CommandCode::RequestSystemCode;
+    type Response = RequestSystemCodeResponse;
+}
+
+impl TryIntoCtx for &RequestSystemCode {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(Self::CODE.into(), &mut offset)?;
+        wbuf.gwrite_with(self.idm, &mut offset, BE)?;
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RequestSystemCodeResponse {
+    pub idm: u64,
+    pub systems: Vec<SystemCode>,
+}
+
+impl<'a> Response<'a> for RequestSystemCodeResponse {
+    const CODE: CommandCode = CommandCode::RequestSystemCodeResponse;
+
+    fn status(&self) -> StatusFlags {
+        StatusFlags::OK
+    }
+
+    fn iparse(data: &'a [u8]) -> IResult<Self> {
+        let (data, idm) = parse_response_header(Self::CODE, data)?;
+        let (data, num_systems) = be_u8(data)?;
+        let (data, systems_data) = take(num_systems as usize * 2)(data)?;
+        let systems = systems_data
+            .chunks(2)
+            .map(|data| u16::from_be_bytes([data[0], data[1]]).into())
+            .collect();
+        Ok((data, Self { idm, systems }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[repr(u8)]
+pub enum AccessMode {
+    Normal = 0,
+    Cashback = 1,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+/// A list of Block List Elements makes up a Block List. Block List Elements can have
+/// 2 or 3 byte lengths (indicated by their first byte), but this type smooths this over.
+///
+/// To save bytes, the Service Code is transmitted separately alongside the blocklist,
+/// and we reference them by indexes into that list. For example, a ReadWithoutEncryption
+/// command for service 8 -> blocks 10 and 12, and service 20 -> block 22 would say:
+///   services: [8, 20], blocks: [(0, 10), (0, 12), (1, 22)].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockListElement {
+    /// Access Mode. Always Normal unless you're doing Cashback to a Purse service.
+    pub mode: AccessMode,
+    /// Index in the service list sent alongside this blocklist, max 15 (0x0F).
+    pub service_idx: u8,
+    /// Block number (max 3 bytes).
+    pub block_num: u16,
+}
+
+impl scroll::ctx::TryIntoCtx<()> for &BlockListElement {
+    type Error = Error;
+
+    fn try_into_ctx(self, wbuf: &mut [u8], _: ()) -> Result<usize, Self::Error> {
+        if usize::from(self.service_idx) >= MAX_SERVICES {
+            return Err(Error::FelicaEncoding(format!(
+                "service index {} doesn't fit in a block list element (max {})",
+                self.service_idx,
+                MAX_SERVICES - 1
+            )));
+        }
+        let mut offset = 0;
+        wbuf.gwrite::<u8>(
+            // 0bX---_---- is 1 if self is 2 bytes (num fits in u8), else 0 for 3 (u16).
+            if self.block_num <= u8::MAX as u16 { 0b1000_0000 } else { 0b0000_0000}
+            // 0b-XXX_---- is the mode. Why this needs 3 bits is anyone's guess.
+            | (u8::from(self.mode) << 4)
+            // 0b----_XXXX is the service code index.
+            | (self.service_idx & 0b0000_1111),
+            &mut offset,
+        )?;
+        if self.block_num <= u8::MAX as u16 {
+            wbuf.gwrite::<u8>(self.block_num as u8, &mut offset)?;
+        } else {
+            wbuf.gwrite_with::<u16>(self.block_num, &mut offset, LE)?;
+        }
+        Ok(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_unwrap_apdu() {
+        let mut buf = [0u8; 32];
+        let mut apdu_buf = [0u8; 32];
+        let apdu = RequestSystemCode {
+            idm: 0x0112_0412_711A_6A0E,
+        }
+        .apdu(&mut apdu_buf)
+        .unwrap();
+        apdu.write(&mut buf);
+        assert_eq!(
+            unwrap_apdu(&buf[..apdu.len()]),
+            Some(&[0x0A, 0x0C, 0x01, 0x12, 0x04, 0x12, 0x71, 0x1A, 0x6A, 0x0E][..])
+        );
+        assert_eq!(unwrap_apdu(&[0x00, 0xA4, 0x04, 0x00]), None);
+    }
+
+    #[test]
+    fn test_cid_to_idm() {
+        // IDm from the example in the ACR-1252U manual.
+        assert_eq!(
+            cid_to_idm(&[0x01, 0x01, 0x06, 0x01, 0xCB, 0x09, 0x57, 0x03]).unwrap(),
+            0x01010601CB095703
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_without_encryption() {
+        // Example command from the ACR-1252U manual.
+        let mut wbuf = [0u8; 256];
+        let apdu = ReadWithoutEncryption {
+            idm: 0x01010601CB095703,
+            services: vec![0x0109],
+            blocks: vec![BlockListElement {
+                mode: AccessMode::Normal,
+                service_idx: 0,
+                block_num: 0,
+            }],
+        }
+        .apdu(&mut wbuf)
+        .unwrap();
+        assert_eq!(
+            (apdu.cla, apdu.ins, apdu.p1, apdu.p2, apdu.le),
+            (0xFF, 0x00, 0x00, 0x00, None)
+        );
+        assert_eq!(
+            apdu.payload.expect("no payload"),
+            &[
+                16, 0x06, 0x01, 0x01, 0x06, 0x01, 0xCB, 0x09, 0x57, 0x03, 0x01, 0x09, 0x01, 0x01,
+                0x80, 0x00
+            ],
+        );
+
+        let mut apdu_buf = [0u8; 256];
+        apdu.write(&mut apdu_buf);
+        assert_eq!(
+            &apdu_buf[..apdu.len()],
+            &[
+                0xFF, 0x00, 0x00, 0x00, 16, 16, 0x06, 0x01, 0x01, 0x06, 0x01, 0xCB, 0x09, 0x57,
+                0x03, 0x01, 0x09, 0x01, 0x01, 0x80, 0x00
+            ],
+        );
+    }
+
+    #[test]
+    fn test_idm_for_service() {
+        let idm0 = 0x01010A108E1BAD39;
+        assert_eq!(idm_for_service(idm0, 0).unwrap(), idm0);
+        assert_eq!(idm_for_service(idm0, 14).unwrap(), 0xE1010A108E1BAD39);
+        assert!(matches!(
+            idm_for_service(idm0, 15),
+            Err(Error::FelicaEncoding(_))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_without_encryption_limits() {
+        let mut wbuf = [0u8; 256];
+        let block = |service_idx, block_num| BlockListElement {
+            mode: AccessMode::Normal,
+            service_idx,
+            block_num,
+        };
+        let read = |services: Vec<u16>, blocks| ReadWithoutEncryption {
+            idm: 0x01010601CB095703,
+            services,
+            blocks,
+        };
+
+        // 16 services is as many as the block list can point at...
+        let services: Vec<u16> = (0..16).map(|i| 0x1009 + (i << 6)).collect();
+        assert!(read(services.clone(), vec![block(15, 0)])
+            .apdu(&mut wbuf)
+            .is_ok());
+        assert!(matches!(
+            read(services.clone(), vec![block(16, 0)]).apdu(&mut wbuf),
+            Err(Error::FelicaEncoding(_))
+        ));
+        // ...so a 17th is an error, not a silently wrapped index.
+        let mut more = services;
+        more.push(0x1409);
+        assert!(matches!(
+            read(more, vec![block(0, 0)]).apdu(&mut wbuf),
+            Err(Error::FelicaEncoding(_))
+        ));
+
+        // Blocks are limited by the frame length first, but too many is never a panic.
+        let mut wbuf = [0u8; 1024];
+        let blocks = (0..120).map(|n| block(0, n)).collect::<Vec<_>>();
+        assert!(read(vec![0x1009], blocks).apdu(&mut wbuf).is_ok());
+        let blocks = (0..130).map(|n| block(0, n)).collect::<Vec<_>>();
+        assert!(matches!(
+            read(vec![0x1009], blocks).apdu(&mut wbuf),
+            Err(Error::FelicaFrameTooLong(274))
+        ));
+        let blocks = (0..300).map(|n| block(0, n)).collect::<Vec<_>>();
+        assert!(matches!(
+            read(vec![0x1009], blocks).apdu(&mut wbuf),
+            Err(Error::FelicaEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_without_encryption_response_error() {
+        let rsp = ReadWithoutEncryptionResponse::parse(&[
+            0x0C, 0x07, 0x01, 0x01, 0x06, 0x01, 0xCB, 0x09, 0x57, 0x03, 0x01, 0xA8,
+        ])
+        .unwrap();
+        assert_eq!(rsp.status, StatusFlags::new(0x01, 0xA8));
+        assert_eq!(rsp.blocks, Vec::<Vec<u8>>::new());
+        assert_eq!(
+            Error::FelicaStatus(rsp.status).to_string(),
+            "[felica] command failed: block number out of range (flag1=01 flag2=A8)"
+        );
+    }
+
+    #[test]
+    fn test_status_flags_unknown() {
+        assert!(StatusFlags::OK.is_ok());
+        assert_eq!(StatusFlags::new(0xFF, 0xC0).description(), None);
+        assert_eq!(
+            StatusFlags::new(0xFF, 0xC0).to_string(),
+            "unknown error (flag1=FF flag2=C0)"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_request_system_code() {
+        let mut wbuf = [0u8; 256];
+        let apdu = RequestSystemCode {
+            idm: 0x1122334455667788,
+        }
+        .apdu(&mut wbuf)
+        .unwrap();
+        assert_eq!(
+            (apdu.cla, apdu.ins, apdu.p1, apdu.p2, apdu.le),
+            (0xFF, 0x00, 0x00, 0x00, None)
+        );
+        assert_eq!(
+            apdu.payload.expect("no payload"),
+            &[10, 0x0C, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]
+        );
+    }
+
+    #[test]
+    fn test_request_system_code_response() {
+        assert_eq!(
+            RequestSystemCodeResponse::parse(&[
+                0x0F, 0x0D, 0x01, 0x01, 0x0A, 0x10, 0x8E, 0x1B, 0xAD, 0x39, 0x02, 0x00, 0x03, 0xFE,
+                0x00,
+            ])
+            .unwrap(),
+            RequestSystemCodeResponse {
+                idm: 0x01010A108E1BAD39,
+                systems: vec![SystemCode::Suica, SystemCode::FeliCaCommon],
+            },
+        )
+    }
+
+    #[test]
+    fn test_request_system_code_response_truncated() {
+        // Claims 128 systems (256 bytes), which used to overflow a u8.
+        assert!(RequestSystemCodeResponse::parse(&[
+            0x0B, 0x0D, 0x01, 0x01, 0x0A, 0x10, 0x8E, 0x1B, 0xAD, 0x39, 0x80,
+        ])
+        .is_err());
+    }
+
+    /// Knows about every Service with an odd code, which all have key version 1.
+    #[cfg(feature = "std")]
+    struct ServiceCard {
+        requests: usize,
+    }
+
+    #[cfg(feature = "std")]
+    impl CardTransport for ServiceCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.requests += 1;
+            let frame = unwrap_apdu(capdu).unwrap();
+            assert_eq!(frame[1], 0x02);
+            let (idm, num, codes) = (&frame[2..10], frame[10], &frame[11..]);
+            let mut rsp = [&[0x03][..], idm, &[num]].concat();
+            for code in codes.chunks(2) {
+                let key_version: u16 = if code[0] & 1 == 1 { 0x0001 } else { 0xFFFF };
+                rsp.extend(key_version.to_le_bytes());
+            }
+            let rsp = [&[rsp.len() as u8 + 1][..], &rsp, &[0x90, 0x00]].concat();
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_request_service_all() {
+        let mut card = ServiceCard { requests: 0 };
+        let (mut wbuf, mut rbuf) = ([0; 256], [0; 256]);
+        let codes: Vec<u16> = (0x1000..0x1000 + 70).collect();
+        let rsp = request_service_all(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            0x0101_0A10_8E1B_AD39,
+            &codes,
+        )
+        .unwrap();
+        assert_eq!(card.requests, 3); // 32 + 32 + 6.
+        assert_eq!(rsp.key_versions.len(), 70);
+        assert_eq!(rsp.key_versions[..2], [0xFFFF, 0x0001]);
+        assert_eq!(rsp.key_versions[69], 0x0001);
+
+        // Asking for too many at once is an error, not a panic.
+        let err = RequestService {
+            idm: 0,
+            node_codes: codes,
+        }
+        .apdu(&mut wbuf)
+        .unwrap_err();
+        assert!(matches!(err, Error::FelicaEncoding(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_area_code() {
+        // The layout of a Suica: the root Area, which owns everything...
+        let root = AreaCode::new(0x0000, 0xFFFE);
+        assert_eq!((root.number, root.end_number), (0x000, 0x3FF));
+        assert!(root.can_subdivide);
+        assert!(root.contains(0x090F >> 6));
+
+        // ...and (among others) one for the transit services, which can't have sub-Areas.
+        let transit = AreaCode::new(0x1001, 0x17FF);
+        assert_eq!((transit.number, transit.end_number), (0x040, 0x05F));
+        assert!(!transit.can_subdivide);
+        assert!(transit.contains(0x1008 >> 6));
+        assert!(!transit.contains(0x090F >> 6));
+    }
+
+    #[test]
+    fn test_search_service_code_response() {
+        let idm = [0x01, 0x01, 0x0A, 0x10, 0x8E, 0x1B, 0xAD, 0x39];
+        let mut area = vec![0x0E, 0x0B];
+        area.extend(idm);
+        area.extend([0x00, 0x08, 0xFF, 0x0F]);
+        assert_eq!(
+            SearchServiceCodeResponse::parse(&area).unwrap().result,
+            Some(SearchServiceCodeResult::Area(AreaCode {
+                code: 0x0800,
+                number: 0x020,
+                can_subdivide: true,
+                end: 0x0FFF,
+                end_number: 0x03F,
+            }))
+        );
+
+        let mut service = vec![0x0C, 0x0B];
+        service.extend(idm);
+        service.extend([0x0F, 0x09]);
+        assert_eq!(
+            SearchServiceCodeResponse::parse(&service).unwrap().result,
+            Some(SearchServiceCodeResult::Service(0x090F.into()))
+        );
+    }
+
+    #[test]
+    fn test_response_truncated() {
+        // A read of two blocks, where the reader only passed on the first.
+        let mut rsp = vec![0x2D, 0x07, 0x01, 0x01, 0x06, 0x01, 0xCB, 0x09, 0x57, 0x03];
+        rsp.extend([0x00, 0x00, 0x02]);
+        rsp.extend([0xAB; 16]);
+        let err = ReadWithoutEncryptionResponse::parse(&rsp).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[felica] response truncated: frame says 45 bytes, got 29"
+        );
+
+        // The length adds up, but the block count doesn't.
+        rsp[0] = rsp.len() as u8;
+        let err = ReadWithoutEncryptionResponse::parse(&rsp).unwrap_err();
+        assert!(matches!(err, Error::FelicaTruncated(_)), "{:?}", err);
+
+        // Trailing junk is the reader's problem, not ours.
+        rsp[12] = 0x01;
+        rsp.extend([0x90, 0x00]);
+        let rsp = ReadWithoutEncryptionResponse::parse(&rsp).unwrap();
+        assert_eq!(rsp.blocks, vec![vec![0xAB; 16]]);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_parse_anything(code in 0u8..0x20, idm: u64, data: Vec<u8>) {
+            // Give it a plausible header, or it'll never get past the first few bytes.
+            let mut rsp = vec![0, code];
+            rsp.extend(idm.to_be_bytes());
+            rsp.extend(data.iter().take(0xF0));
+            rsp[0] = rsp.len() as u8;
+            let _ = RequestServiceResponse::parse(&rsp);
+            let _ = RequestResponseResponse::parse(&rsp);
+            let _ = ReadWithoutEncryptionResponse::parse(&rsp);
+            #[cfg(feature = "write")]
+            let _ = WriteWithoutEncryptionResponse::parse(&rsp);
+            let _ = SearchServiceCodeResponse::parse(&rsp);
+            let _ = RequestSystemCodeResponse::parse(&rsp);
+        }
+    }
+}
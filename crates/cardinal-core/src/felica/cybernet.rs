@@ -8,14 +8,24 @@
 //!
 //! Station codes: https://www.denno.net/SFCardFan/ (offline as of writing, but on archive.org)
 use chrono::{DateTime, TimeZone, Utc};
-use nom::combinator::map;
-use nom::number::complete::{be_u16, be_u8};
+use nom::bytes::complete::take;
+use nom::combinator::{map, map_opt};
+use nom::number::complete::{be_u16, be_u8, le_u16};
 use num_enum::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use super::IResult;
+use crate::money::{Amount, Currency};
+
+/// Service with the card's attributes, including its balance; see [parse_balance].
+pub const ATTRIBUTE_SERVICE: u16 = 0x008B;
+/// Service with the last 20 or so transactions, as [HistoryRecord]s.
+pub const HISTORY_SERVICE: u16 = 0x090F;
 
 // I do not know Japanesa rail terminology, assume I've mistranslated all of these.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TerminalType {
     FareAdjustmentMachine = 3, // "精算機"
@@ -43,6 +53,7 @@ pub enum TerminalType {
 
 // I do not know Japanesa rail terminology, assume I've mistranslated all of these.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TransactionType {
     ExitFareGate = 1,                  // "運賃支払(改札出場)"
@@ -76,11 +87,38 @@ pub enum TransactionType {
 
 /// Historical record (also known as an Entry/Exit record).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HistoryRecord {
     pub terminal_type: TerminalType,
     pub tx_type: TransactionType,
     pub unknown: u16,        // ???
     pub date: DateTime<Utc>, // Somehow, I suspect this will be in JST, not UTC.
+    /// Time or stations, depending on the terminal; see [HistoryRecord::stations].
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            serialize_with = "crate::serde_hex::serialize",
+            deserialize_with = "crate::serde_hex::array"
+        )
+    )]
+    pub extra: [u8; 4],
+    pub balance: Amount, // Remaining balance after the transaction.
+    /// Transaction sequence number; goes up by one (or more) per transaction.
+    pub seq: u16,
+}
+
+/// A station, as a line code and a station code on that line. Names are in station code
+/// lists, which are a whole project of their own; see the links at the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Station {
+    pub line: u8,
+    pub station: u8,
+}
+
+impl core::fmt::Display for Station {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:02X}-{:02X}", self.line, self.station)
+    }
 }
 
 impl HistoryRecord {
@@ -88,7 +126,8 @@ impl HistoryRecord {
         let (data, terminal_type) = map(be_u8, |v| v.into())(data)?;
         let (data, tx_type) = map(be_u8, |v| v.into())(data)?;
         let (data, unknown) = be_u16(data)?;
-        let (data, date) = map(be_u16, |v| {
+        // An unused (zeroed) record has no valid date; that's an error, not a panic.
+        let (data, date) = map_opt(be_u16, |v| {
             Utc.with_ymd_and_hms(
                 (((v >> 9) & 0x007f) + 2000).into(),
                 ((v >> 5) & 0x000f).into(),
@@ -97,8 +136,12 @@ impl HistoryRecord {
                 0,
                 0,
             )
-            .unwrap()
+            .single()
         })(data)?;
+        let (data, extra) = map(take(4usize), |v: &[u8]| [v[0], v[1], v[2], v[3]])(data)?;
+        let (data, balance) = map(le_u16, |v| Amount::new(v.into(), Currency::JPY))(data)?;
+        let (data, _) = be_u8(data)?; // ???
+        let (data, seq) = be_u16(data)?;
         Ok((
             data,
             Self {
@@ -106,14 +149,61 @@ impl HistoryRecord {
                 tx_type,
                 unknown,
                 date,
+                extra,
+                balance,
+                seq,
             },
         ))
     }
+
+    /// Where you got on and off, for records from fare gates and the like; anything else
+    /// (shops, buses) uses those bytes for something else.
+    pub fn stations(&self) -> Option<(Station, Station)> {
+        match self.terminal_type {
+            TerminalType::FareGate
+            | TerminalType::SimpleFareGate
+            | TerminalType::FareGateTerminal
+            | TerminalType::ContactFareGate
+            | TerminalType::FareAdjustmentMachine
+            | TerminalType::TransferMachine => Some((
+                Station {
+                    line: self.extra[0],
+                    station: self.extra[1],
+                },
+                Station {
+                    line: self.extra[2],
+                    station: self.extra[3],
+                },
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the balance out of an attribute block (block 0 of [ATTRIBUTE_SERVICE]).
+pub fn parse_balance(data: &[u8]) -> IResult<'_, Amount> {
+    let (data, _) = take(11usize)(data)?; // Card type, region, and ???.
+    map(le_u16, |v| Amount::new(v.into(), Currency::JPY))(data)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_parse_balance() {
+        let block = [
+            0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ???
+            0x19, 0x09, // Balance (little endian, 0x0919 => ¥2329)
+            0x00, 0x00, 0x6F, // Transaction Sequence Number 111
+        ];
+        assert_eq!(
+            parse_balance(&block).map(|(_, v)| v).unwrap(),
+            Amount::new(2329, Currency::JPY)
+        );
+        assert!(parse_balance(&block[..12]).is_err());
+    }
 
     #[test]
     fn test_history_record_vending_machine_384yen() {
@@ -136,6 +226,9 @@ mod tests {
                 tx_type: TransactionType::ProductSale,
                 unknown: 0x0000_0000,
                 date: Utc.with_ymd_and_hms(2019, 11, 23, 0, 0, 0).unwrap(),
+                extra: [0x31, 0x2B, 0x20, 0x21],
+                balance: Amount::new(850, Currency::JPY),
+                seq: 114,
             }
         )
     }
@@ -162,7 +255,46 @@ mod tests {
                 tx_type: TransactionType::ExitFareGate,
                 unknown: 0x0000_0002,
                 date: Utc.with_ymd_and_hms(2019, 11, 22, 0, 0, 0).unwrap(),
+                extra: [0xE0, 0x2E, 0xE0, 0x27],
+                balance: Amount::new(2329, Currency::JPY),
+                seq: 111,
             }
         )
     }
+
+    #[test]
+    fn test_history_record_stations() {
+        let (_, rec) = HistoryRecord::parse(&[
+            0x16, 0x01, 0x00, 0x02, 0x27, 0x76, 0xE0, 0x2E, 0xE0, 0x27, 0x19, 0x09, 0x00, 0x00,
+            0x6F, 0x00,
+        ])
+        .unwrap();
+        let (entry, exit) = rec.stations().unwrap();
+        assert_eq!(entry.to_string(), "E0-2E");
+        assert_eq!(exit.to_string(), "E0-27");
+
+        // Unused records are all zeroes, which isn't a valid date.
+        assert!(HistoryRecord::parse(&[0; 16]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_history_record_serde() {
+        let record = HistoryRecord {
+            terminal_type: TerminalType::VendingMachine,
+            tx_type: TransactionType::Unknown(0xEE),
+            unknown: 0,
+            date: Utc.with_ymd_and_hms(2019, 11, 23, 0, 0, 0).unwrap(),
+            extra: [0x31, 0x2B, 0x20, 0x21],
+            balance: Amount::new(850, Currency::JPY),
+            seq: 114,
+        };
+        let json = serde_json::to_value(record).unwrap();
+        assert_eq!(json["extra"], "312B2021");
+        assert_eq!(json["tx_type"], serde_json::json!({ "Unknown": 238 }));
+        assert_eq!(
+            serde_json::from_value::<HistoryRecord>(json).unwrap(),
+            record
+        );
+    }
 }
@@ -0,0 +1,50 @@
+//! Octopus (Hong Kong); System 8008.
+//!
+//! The only thing readable without keys is the stored value, in Service 0117: a big-endian
+//! count of 10-cent units, offset so the card can go a little negative (you're allowed to
+//! overdraw it by one journey). The offset was HK$35 until October 2017, and HK$50 since;
+//! cards issued before then may read HK$15 low. Transactions are in Services that need
+//! authentication, so there's no history to show.
+//!
+//! From Metrodroid and FeliCa dumps; there's no official documentation.
+
+use super::IResult;
+use crate::money::{Amount, Currency};
+use nom::combinator::map;
+use nom::number::complete::be_u32;
+
+/// Service with the balance in block 0.
+pub const BALANCE_SERVICE: u16 = 0x0117;
+
+/// What's added to the balance, in 10-cent units, so it can be stored unsigned (HK$50).
+pub const OFFSET: i64 = 500;
+/// The same, before October 2017 (HK$35).
+pub const OFFSET_BEFORE_2017: i64 = 350;
+
+/// Turns a raw balance into an amount, with the given [OFFSET].
+pub fn balance(raw: u32, offset: i64) -> Amount {
+    Amount::new((i64::from(raw) - offset) * 10, Currency::HKD)
+}
+
+/// Reads the balance out of block 0 of [BALANCE_SERVICE], assuming a current card.
+pub fn parse_balance(data: &[u8]) -> IResult<'_, Amount> {
+    map(be_u32, |raw| balance(raw, OFFSET))(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_parse_balance() {
+        // 0x0000027B = 635 units, minus 500 => HK$13.50.
+        let block = [0x00, 0x00, 0x02, 0x7B, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let (_, amount) = parse_balance(&block).unwrap();
+        assert_eq!(amount, Amount::new(1350, Currency::HKD));
+        assert_eq!(amount.to_string(), "HK$13.50");
+
+        // Overdrawn, on an old card.
+        assert_eq!(balance(300, OFFSET_BEFORE_2017).value, -500);
+    }
+}
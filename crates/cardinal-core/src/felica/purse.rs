@@ -0,0 +1,135 @@
+//! Where common transit and e-money cards keep their balance, and how to read it.
+//!
+//! None of this is officially documented; it's from reverse engineering (see [super::cybernet]
+//! and [super::octopus] for sources), and from other people's readers. Japanese balances are
+//! all in yen, and the cards top out at ¥20,000-50,000, so there's no need for anything
+//! wider than the layouts say.
+
+use super::{cybernet, octopus, IResult, SystemCode};
+use crate::money::{Amount, Currency};
+use nom::combinator::map;
+use nom::number::complete::{le_u16, le_u32};
+
+/// A Service that holds a balance.
+#[derive(Debug, Clone, Copy)]
+pub struct Purse {
+    /// What it's called, eg. "Suica".
+    pub name: &'static str,
+    pub system: SystemCode,
+    pub service: u16,
+    /// Which block has the balance in it.
+    pub block: u16,
+    pub parse: fn(&[u8]) -> IResult<Amount>,
+}
+
+/// Purses we know about.
+pub const PURSES: &[Purse] = &[
+    // Suica, ICOCA, PASMO, etc: the newest history record (block 0) has the balance after
+    // it, and so does the attribute block.
+    Purse {
+        name: "Suica",
+        system: SystemCode::Suica,
+        service: cybernet::HISTORY_SERVICE,
+        block: 0,
+        parse: parse_history,
+    },
+    Purse {
+        name: "Suica",
+        system: SystemCode::Suica,
+        service: cybernet::ATTRIBUTE_SERVICE,
+        block: 0,
+        parse: cybernet::parse_balance,
+    },
+    // The rest live in the Common Area, with a little-endian balance at the start.
+    Purse {
+        name: "Edy",
+        system: SystemCode::FeliCaCommon,
+        service: 0x1317,
+        block: 0,
+        parse: parse_le_u32,
+    },
+    Purse {
+        name: "nanaco",
+        system: SystemCode::FeliCaCommon,
+        service: 0x5597,
+        block: 0,
+        parse: parse_le_u32,
+    },
+    Purse {
+        name: "WAON",
+        system: SystemCode::FeliCaCommon,
+        service: 0x6817,
+        block: 0,
+        parse: parse_le_u16,
+    },
+    Purse {
+        name: "Octopus",
+        system: SystemCode::Octopus,
+        service: octopus::BALANCE_SERVICE,
+        block: 0,
+        parse: octopus::parse_balance,
+    },
+];
+
+/// Finds the purse for a Service, if it is one. The System is optional, since you don't
+/// always know it; the service codes don't overlap anyway.
+pub fn find(system: Option<SystemCode>, service: u16) -> Option<&'static Purse> {
+    (PURSES.iter()).find(|p| p.service == service && system.is_none_or(|s| s == p.system))
+}
+
+/// Reads the balance out of a block, if it's from a known purse, and the one with the
+/// balance in it.
+pub fn balance(
+    system: Option<SystemCode>,
+    service: u16,
+    block: u16,
+    data: &[u8],
+) -> Option<(&'static Purse, Amount)> {
+    let purse = find(system, service).filter(|p| p.block == block)?;
+    let (_, amount) = (purse.parse)(data).ok()?;
+    Some((purse, amount))
+}
+
+fn parse_history(data: &[u8]) -> IResult<'_, Amount> {
+    map(cybernet::HistoryRecord::parse, |rec| rec.balance)(data)
+}
+
+fn parse_le_u32(data: &[u8]) -> IResult<'_, Amount> {
+    map(le_u32, |v| Amount::new(v.into(), Currency::JPY))(data)
+}
+
+fn parse_le_u16(data: &[u8]) -> IResult<'_, Amount> {
+    map(le_u16, |v| Amount::new(v.into(), Currency::JPY))(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance() {
+        // [111] 2019-11-22: Tokidaigaku-Mae -> Hon-Atsugi, ¥2,329 left; see cybernet.
+        let history = [
+            0x16, 0x01, 0x00, 0x02, 0x27, 0x76, 0xE0, 0x2E, 0xE0, 0x27, 0x19, 0x09, 0x00, 0x00,
+            0x6F, 0x00,
+        ];
+        let (purse, amount) = balance(None, 0x090F, 0, &history).unwrap();
+        assert_eq!(purse.name, "Suica");
+        assert_eq!(amount, Amount::new(2329, Currency::JPY));
+        // Older records have old balances, not the current one.
+        assert!(balance(None, 0x090F, 1, &history).is_none());
+
+        let edy = [0x10, 0x27, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let (purse, amount) = balance(Some(SystemCode::FeliCaCommon), 0x1317, 0, &edy).unwrap();
+        assert_eq!(purse.name, "Edy");
+        assert_eq!(amount, Amount::new(10000, Currency::JPY));
+
+        // Right service, wrong System.
+        assert!(balance(Some(SystemCode::Suica), 0x1317, 0, &edy).is_none());
+        assert!(balance(None, 0x1234, 0, &edy).is_none());
+
+        let (purse, amount) = balance(Some(SystemCode::Octopus), 0x0117, 0, &edy).unwrap();
+        assert_eq!(purse.name, "Octopus");
+        assert_eq!(amount.currency, Currency::HKD);
+    }
+}
@@ -0,0 +1,374 @@
+//! Guesswork for data we don't otherwise understand.
+//!
+//! Cards are full of fields nobody documented, and raw reads hand you whatever's in the
+//! file. A surprising amount of it turns out to be something recognisable: a DER blob
+//! (often a certificate), an NDEF message, a BCD number, or just plain text. None of this
+//! is reliable, so it's only ever used to annotate hex dumps, never to decide anything.
+
+use crate::ber;
+use crate::x509::name;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+/// A guess at what some data is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Guess {
+    /// An X.509 certificate.
+    Certificate { subject: String, issuer: String },
+    /// Some other well-formed DER structure.
+    Der,
+    /// An NDEF message.
+    Ndef(Vec<NdefRecord>),
+    /// Plain ASCII text.
+    Text(String),
+    /// A BCD number (eg. a card number or a date), with any padding removed.
+    Bcd(String),
+}
+
+impl core::fmt::Display for Guess {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Certificate { subject, issuer } => {
+                write!(f, "X.509 certificate: {} (issued by {})", subject, issuer)
+            }
+            Self::Der => write!(f, "DER structure"),
+            Self::Ndef(records) => {
+                write!(f, "NDEF:")?;
+                for (i, rec) in records.iter().enumerate() {
+                    write!(f, "{} {}", if i > 0 { "," } else { "" }, rec)?;
+                }
+                Ok(())
+            }
+            Self::Text(s) => write!(f, "text: {:?}", s),
+            Self::Bcd(s) => write!(f, "BCD: {}", s),
+        }
+    }
+}
+
+/// A single record in an NDEF message, summarised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdefRecord {
+    Uri(String),
+    Text { lang: String, text: String },
+    Mime { mime: String, len: usize },
+    Other { tnf: u8, typ: Vec<u8>, len: usize },
+}
+
+impl core::fmt::Display for NdefRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Uri(uri) => write!(f, "URI {}", uri),
+            Self::Text { lang, text } => write!(f, "text ({}) {:?}", lang, text),
+            Self::Mime { mime, len } => write!(f, "{} ({} bytes)", mime, len),
+            Self::Other { tnf, typ, len } => write!(
+                f,
+                "TNF {} type {:?} ({} bytes)",
+                tnf,
+                String::from_utf8_lossy(typ),
+                len
+            ),
+        }
+    }
+}
+
+/// Takes a guess at what the data is. Returns None if nothing seems plausible.
+pub fn guess(data: &[u8]) -> Option<Guess> {
+    if data.is_empty() {
+        return None;
+    }
+    guess_der(data)
+        .or_else(|| guess_ndef(data).map(Guess::Ndef))
+        .or_else(|| guess_text(data).map(Guess::Text))
+        .or_else(|| guess_bcd(data).map(Guess::Bcd))
+}
+
+/// Is this a single, well-formed DER SEQUENCE, all the way down?
+fn guess_der(data: &[u8]) -> Option<Guess> {
+    if data.first() != Some(&0x30) || !is_der(data) {
+        return None;
+    }
+    Some(certificate(data).unwrap_or(Guess::Der))
+}
+
+fn is_der(data: &[u8]) -> bool {
+    // This runs on anything we're asked to annotate, so it mustn't recurse on its own.
+    ber::iter_deep(data).all(|res| res.is_ok())
+}
+
+/// Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signatureValue }, where
+/// tbsCertificate ::= SEQUENCE { [0] version, serialNumber, signature, issuer, validity,
+/// subject, ... } (RFC 5280, section 4.1).
+fn certificate(data: &[u8]) -> Option<Guess> {
+    let (_, (_, cert)) = ber::parse_next(data).ok()?;
+    let (_, (_, tbs)) = ber::parse_next(cert).ok()?;
+    let mut fields = ber::iter(tbs).filter_map(|r| r.ok());
+    let (tag, _) = fields.next()?;
+    if tag == [0xA0] {
+        fields.next()?; // serialNumber
+    }
+    fields.next()?; // signature
+    let (_, issuer) = fields.next()?;
+    fields.next()?; // validity
+    let (_, subject) = fields.next()?;
+    Some(Guess::Certificate {
+        subject: name(subject)?,
+        issuer: name(issuer)?,
+    })
+}
+
+/// Tries to parse an NDEF message; either bare, with a 2-byte length in front (NFC Forum
+/// Type 4 Tags), or in an NDEF Message TLV (Type 2 Tags).
+fn guess_ndef(data: &[u8]) -> Option<Vec<NdefRecord>> {
+    let unwrapped = match data {
+        [0x03, 0xFF, hi, lo, rest @ ..] => rest.get(..u16::from_be_bytes([*hi, *lo]) as usize),
+        [0x03, len, rest @ ..] => rest.get(..*len as usize),
+        [hi, lo, rest @ ..] => rest.get(..u16::from_be_bytes([*hi, *lo]) as usize),
+        _ => None,
+    };
+    unwrapped
+        .and_then(ndef_message)
+        .or_else(|| ndef_message(data))
+}
+
+fn ndef_message(mut data: &[u8]) -> Option<Vec<NdefRecord>> {
+    let mut records = vec![];
+    loop {
+        let hdr = *data.first()?;
+        let (mb, me, cf, sr, il, tnf) = (
+            hdr & 0x80 != 0,
+            hdr & 0x40 != 0,
+            hdr & 0x20 != 0,
+            hdr & 0x10 != 0,
+            hdr & 0x08 != 0,
+            hdr & 0x07,
+        );
+        // Only the first record has MB set; we don't do chunked records.
+        if mb != records.is_empty() || cf || tnf > 0x06 {
+            return None;
+        }
+        let type_len = *data.get(1)? as usize;
+        let (payload_len, mut pos) = if sr {
+            (*data.get(2)? as usize, 3)
+        } else {
+            let b = data.get(2..6)?;
+            (u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize, 6)
+        };
+        let id_len = if il {
+            pos += 1;
+            *data.get(pos - 1)? as usize
+        } else {
+            0
+        };
+        let typ = data.get(pos..pos + type_len)?;
+        pos += type_len + id_len;
+        let payload = data.get(pos..pos + payload_len)?;
+        pos += payload_len;
+        records.push(ndef_record(tnf, typ, payload)?);
+
+        data = &data[pos..];
+        if me {
+            return data
+                .iter()
+                .all(|&b| b == 0x00 || b == 0xFE)
+                .then_some(records);
+        }
+    }
+}
+
+fn ndef_record(tnf: u8, typ: &[u8], payload: &[u8]) -> Option<NdefRecord> {
+    Some(match (tnf, typ) {
+        // NFC Forum URI RTD, section 3.2.2.
+        (0x01, b"U") => {
+            let (code, rest) = payload.split_first()?;
+            let prefix = URI_PREFIXES.get(*code as usize).copied().unwrap_or("");
+            NdefRecord::Uri(format!("{}{}", prefix, String::from_utf8_lossy(rest)))
+        }
+        // NFC Forum Text RTD, section 3.2.1.
+        (0x01, b"T") => {
+            let (status, rest) = payload.split_first()?;
+            let lang_len = (status & 0x3F) as usize;
+            let lang = rest.get(..lang_len)?;
+            let text = &rest[lang_len..];
+            NdefRecord::Text {
+                lang: String::from_utf8_lossy(lang).into(),
+                text: if status & 0x80 != 0 {
+                    let units: Vec<u16> = text
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                } else {
+                    String::from_utf8_lossy(text).into()
+                },
+            }
+        }
+        (0x02, mime) => NdefRecord::Mime {
+            mime: String::from_utf8_lossy(mime).into(),
+            len: payload.len(),
+        },
+        (tnf, typ) => NdefRecord::Other {
+            tnf,
+            typ: typ.to_vec(),
+            len: payload.len(),
+        },
+    })
+}
+
+/// URI identifier codes, from the NFC Forum URI RTD.
+const URI_PREFIXES: &[&str] = &[
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// Printable ASCII, allowing for trailing NUL/space/0xFF padding. Anything short is too
+/// likely to be a coincidence.
+fn guess_text(data: &[u8]) -> Option<String> {
+    let end = data
+        .iter()
+        .rposition(|&b| !matches!(b, 0x00 | 0x20 | 0xFF))?
+        + 1;
+    let text = &data[..end];
+    (text.len() >= 4 && text.iter().all(|b| (0x20..0x7F).contains(b)))
+        .then(|| String::from_utf8_lossy(text).into())
+}
+
+/// Shortest run of printable ASCII that [ascii_runs] will believe is text.
+pub const MIN_ASCII_RUN: usize = 4;
+
+/// Picks the printable ASCII out of otherwise binary data, the way pcsc_scan does for
+/// historical bytes: runs of at least [MIN_ASCII_RUN] characters, joined with spaces. Lots of
+/// cards hide a product name in there, between bytes that mean something to someone else.
+pub fn ascii_runs(data: &[u8]) -> Option<String> {
+    let runs: Vec<_> = (data.split(|b| !(0x21..0x7F).contains(b)))
+        .filter(|run| run.len() >= MIN_ASCII_RUN)
+        .map(String::from_utf8_lossy)
+        .collect();
+    (!runs.is_empty()).then(|| runs.join(" "))
+}
+
+/// Every nibble is a decimal digit, allowing for trailing F padding (as in EMV's cn).
+/// Single bytes and runs of zeroes are too common to be worth pointing out.
+fn guess_bcd(data: &[u8]) -> Option<String> {
+    let encoded = hex::encode_upper(data);
+    let digits = encoded.trim_end_matches('F');
+    (data.len() >= 2
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && digits.bytes().any(|b| b != b'0'))
+    .then(|| digits.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_guess_ndef() {
+        // Type 4 Tag NDEF file: NLEN, then a URI record for https://example.com.
+        let data = [
+            0x00, 0x10, 0xD1, 0x01, 0x0C, 0x55, 0x04, b'e', b'x', b'a', b'm', b'p', b'l', b'e',
+            b'.', b'c', b'o', b'm',
+        ];
+        assert_eq!(
+            guess(&data),
+            Some(Guess::Ndef(vec![NdefRecord::Uri(
+                "https://example.com".into()
+            )]))
+        );
+
+        // Type 2 Tag TLV, with a text record.
+        let data = [
+            0x03, 0x0B, 0xD1, 0x01, 0x07, 0x54, 0x02, b'e', b'n', b'h', b'e', b'y', b'!', 0xFE,
+        ];
+        assert_eq!(
+            guess(&data).unwrap().to_string(),
+            r#"NDEF: text (en) "hey!""#
+        );
+    }
+
+    #[test]
+    fn test_ascii_runs() {
+        // Historical bytes from a JCOP card: a category byte that spells 'J', and some
+        // binary around the version.
+        let data = b"JCOP41V221\x01\x02abc\xFFPROD";
+        assert_eq!(ascii_runs(data), Some("JCOP41V221 PROD".into()));
+        assert_eq!(ascii_runs(&[0x80, 0x31, 0xC0, 0x73]), None);
+        assert_eq!(ascii_runs(&[]), None);
+    }
+
+    #[test]
+    fn test_guess_der() {
+        // A (truncated) certificate: just the bits we look at, plus an empty signature.
+        let name = |cn: &[u8]| {
+            // SEQUENCE { SET { SEQUENCE { OID 2.5.4.3 (CN), UTF8String } } }
+            let len = cn.len() as u8;
+            let mut v = vec![0x30, len + 11, 0x31, len + 9, 0x30, len + 7];
+            v.extend([0x06, 0x03, 0x55, 0x04, 0x03, 0x0C, len]);
+            v.extend(cn);
+            v
+        };
+        let mut tbs = vec![0xA0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01, 0x30, 0x00];
+        tbs.extend(name(b"Issuer"));
+        tbs.extend([0x30, 0x00]);
+        tbs.extend(name(b"Subject"));
+        let mut cert = vec![0x30, tbs.len() as u8 + 6, 0x30, tbs.len() as u8];
+        cert.extend(tbs);
+        cert.extend([0x30, 0x00, 0x03, 0x00]);
+        assert_eq!(
+            guess(&cert),
+            Some(Guess::Certificate {
+                subject: "CN=Subject".into(),
+                issuer: "CN=Issuer".into(),
+            })
+        );
+        assert_eq!(guess(&[0x30, 0x03, 0x02, 0x01, 0x05]), Some(Guess::Der));
+    }
+
+    #[test]
+    fn test_guess_text_bcd() {
+        assert_eq!(
+            guess(b"1PAY.SYS.DDF01"),
+            Some(Guess::Text("1PAY.SYS.DDF01".into()))
+        );
+        assert_eq!(
+            guess(&[0x49, 0x80, 0x12, 0x34, 0x5F]),
+            Some(Guess::Bcd("498012345".into()))
+        );
+        assert_eq!(guess(&[0xDE, 0xAD, 0xBE, 0xEF]), None);
+    }
+}
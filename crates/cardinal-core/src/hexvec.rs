@@ -0,0 +1,165 @@
+//! Bytes that people read and write as hex.
+//!
+//! AIDs, filters and the like come in from the command line and config files as hex, and
+//! go back out as hex in messages and reports; a [HexVec] does both, so nobody has to
+//! hand-roll the parsing again.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+/// A `Vec<u8>` that displays, parses and (with the `serde` feature) serializes as hex.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HexVec(pub Vec<u8>);
+
+/// Parses hex the way people write it: `A0000000041010`, `A0 00 00 00 04`,
+/// `a0:00:00:00:04` and `0xA0,0x00` all work.
+pub fn parse(s: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    let digits: String = s
+        .split(|c: char| c.is_whitespace() || ":-,_".contains(c))
+        .map(|v| v.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    hex::decode(digits)
+}
+
+impl core::fmt::Display for HexVec {
+    /// Uppercase, with a space between bytes, eg. `A0 00 00 00 04`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HexVec {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).map(Self)
+    }
+}
+
+impl core::ops::Deref for HexVec {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for HexVec {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for HexVec {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&[u8]> for HexVec {
+    fn from(value: &[u8]) -> Self {
+        Self(value.to_vec())
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for HexVec {
+    fn from(value: [u8; N]) -> Self {
+        Self(value.to_vec())
+    }
+}
+
+impl From<HexVec> for Vec<u8> {
+    fn from(value: HexVec) -> Self {
+        value.0
+    }
+}
+
+impl PartialEq<[u8]> for HexVec {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<Vec<u8>> for HexVec {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        &self.0 == other
+    }
+}
+
+impl FromIterator<u8> for HexVec {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for HexVec {
+    type Item = u8;
+    type IntoIter = alloc::vec::IntoIter<u8>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a HexVec {
+    type Item = &'a u8;
+    type IntoIter = core::slice::Iter<'a, u8>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HexVec {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&hex::encode_upper(&self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HexVec {
+    /// Hex, or an array of bytes; see [crate::serde_hex].
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        crate::serde_hex::bytes(d).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_parse() {
+        let aid = [0xA0, 0x00, 0x00, 0x00, 0x04];
+        for s in [
+            "A000000004",
+            "A0 00 00 00 04",
+            "a0:00:00:00:04",
+            "0xA0,0x00,0x00,0x00,0x04",
+        ] {
+            assert_eq!(s.parse::<HexVec>().unwrap(), aid[..], "{}", s);
+        }
+        assert!("A0 0".parse::<HexVec>().is_err());
+        assert!("hello".parse::<HexVec>().is_err());
+        assert_eq!(HexVec::from(aid).to_string(), "A0 00 00 00 04");
+        assert_eq!(HexVec::default().to_string(), "");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let v = HexVec::from(vec![0x3B, 0x8F]);
+        assert_eq!(serde_json::to_string(&v).unwrap(), r#""3B8F""#);
+        assert_eq!(serde_json::from_str::<HexVec>(r#""3B 8F""#).unwrap(), v);
+        assert_eq!(serde_json::from_str::<HexVec>("[59, 143]").unwrap(), v);
+    }
+}
@@ -0,0 +1,1166 @@
+use crate::response_limits::{self, ResponseLimits};
+use crate::{ber, util, warnings, CardTransport, HexVec, Result};
+use apdu::Command;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use tracing::{trace_span, warn};
+
+pub fn select_name<'r, R: TryFrom<&'r [u8]>>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    name: &[u8],
+) -> Result<R, R::Error>
+where
+    R::Error: From<crate::Error>,
+{
+    Select {
+        id: SelectID::Name(name),
+        mode: SelectMode::First,
+    }
+    .call(card, wbuf, rbuf)?
+    .parse_into()
+}
+
+/// A status word (SW1-SW2), as returned with every response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatusWord(pub u8, pub u8);
+
+impl StatusWord {
+    /// Pulls the status word out of an [crate::Error::APDU], if that's what it is (or
+    /// what's under an [crate::Error::Command]).
+    pub fn from_error(err: &crate::Error) -> Option<Self> {
+        err.sw().map(|(sw1, sw2)| Self(sw1, sw2))
+    }
+}
+
+impl std::fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02X}{:02X}", self.0, self.1)
+    }
+}
+
+/// ID for a SELECT command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SelectID<'a> {
+    /// Select by DF name.
+    Name(&'a [u8]),
+    /// Select an EF under the current DF, by file identifier (eg. `[0x01, 0x01]`).
+    EF(&'a [u8]),
+    /// Select the MF (3F00), the root of the filesystem.
+    MF,
+}
+
+/// Mode for a SELECT command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Select the first or only instance.
+    First,
+    /// Select the next instance. Can be called repeatedly.
+    Next,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Select<'a> {
+    pub id: SelectID<'a>,
+    pub mode: SelectMode,
+}
+
+impl<'a> Select<'a> {
+    pub fn exec<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        let cmd = match self.id {
+            SelectID::Name(aid) => format!("SELECT AID {}", hex::encode_upper(aid)),
+            SelectID::EF(fid) => format!("SELECT EF {}", hex::encode_upper(fid)),
+            SelectID::MF => "SELECT MF".into(),
+        };
+        util::call_apdu(card, wbuf, rbuf, self.into()).map_err(|err| err.in_command(cmd))
+    }
+
+    pub fn call<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<SelectResponse<'r>> {
+        self.exec(card, wbuf, rbuf)?.try_into()
+    }
+
+    /// Like [Select::call], but the response doesn't borrow rbuf.
+    pub fn call_owned(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<OwnedSelectResponse> {
+        Ok(self.call(card, wbuf, rbuf)?.to_owned_response())
+    }
+}
+
+impl<'a> From<Select<'a>> for Command<'a> {
+    fn from(v: Select<'a>) -> Self {
+        Self::new_with_payload_le(
+            0x00,
+            0xA4,
+            match v.id {
+                SelectID::Name(_) => 0b0000_0100,
+                SelectID::EF(_) => 0b0000_0010,
+                SelectID::MF => 0b0000_0000,
+            },
+            match v.mode {
+                SelectMode::First => 0b0000_0000,
+                SelectMode::Next => 0b0000_0010,
+            },
+            0x00,
+            match v.id {
+                SelectID::Name(name) => name,
+                SelectID::EF(fid) => fid,
+                SelectID::MF => MF,
+            },
+        )
+    }
+}
+
+/// Response type for a SELECT command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SelectResponse<'a> {
+    /// 0x6F File Control Information. Describes the selected file.
+    pub fci: FileControlInfo<'a>,
+}
+
+impl<'a> SelectResponse<'a> {
+    /// Parses a SELECT response's FCI Proprietary Template data into a domain-specific type.
+    pub fn parse_into<R: TryFrom<&'a [u8]>>(&self) -> Result<R, R::Error>
+    where
+        R::Error: From<crate::Error>,
+    {
+        R::try_from(self.fci.pt.unwrap_or_default().into())
+    }
+
+    /// Copies the response out of rbuf.
+    pub fn to_owned_response(&self) -> OwnedSelectResponse {
+        OwnedSelectResponse {
+            fci: OwnedFileControlInfo {
+                df_name: self.fci.df_name.into(),
+                pt: self.fci.pt.map(|pt| pt.into()),
+            },
+        }
+    }
+}
+
+/// A [SelectResponse] that owns its data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedSelectResponse {
+    pub fci: OwnedFileControlInfo,
+}
+
+impl OwnedSelectResponse {
+    /// See [SelectResponse::parse_into].
+    pub fn parse_into<'a, R: TryFrom<&'a [u8]>>(&'a self) -> Result<R, R::Error>
+    where
+        R::Error: From<crate::Error>,
+    {
+        R::try_from(self.fci.pt.as_deref().unwrap_or_default())
+    }
+}
+
+/// A [FileControlInfo] that owns its data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedFileControlInfo {
+    pub df_name: HexVec,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pt: Option<HexVec>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for SelectResponse<'a> {
+    type Error = crate::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let span = trace_span!("SelectResponse");
+        let _enter = span.enter();
+
+        let (_, (tag, value)) = ber::parse_next(data)?;
+        util::expect_tag(&[0x6F], tag)?;
+
+        Ok(Self {
+            fci: value.try_into()?,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FileControlInfo<'a> {
+    /// 0x84 DF Name. (Required)
+    pub df_name: &'a [u8],
+
+    /// 0xA5 FCI Proprietary Template (the contents of the file).
+    /// Contents and encoding depend on the file selected.
+    ///
+    /// Selecting a file and reading the contents of said file is the single most common
+    /// operation there is, so I'm not typing `.fci_proprietary_template` every time.
+    pub pt: Option<&'a [u8]>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for FileControlInfo<'a> {
+    type Error = crate::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self> {
+        let span = trace_span!("FileControlInfo");
+        let _enter = span.enter();
+
+        let mut slf = Self::default();
+        for res in ber::iter(data) {
+            let (tag, value) = res?;
+            match tag {
+                &[0x84] => slf.df_name = value,
+                &[0xA5] => slf.pt = Some(value),
+                _ => warnings::unknown_field("FileControlInfo", tag, value),
+            }
+        }
+
+        Ok(slf)
+    }
+}
+
+/// ID for a READ RECORD command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecordID {
+    /// Select by DF name.
+    Number(u8),
+}
+
+// A READ RECORD command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReadRecord {
+    pub sfi: u8,
+    pub id: RecordID,
+}
+
+impl ReadRecord {
+    pub fn exec<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        let cmd = match self.id {
+            RecordID::Number(num) => format!("READ RECORD {}/{}", self.sfi, num),
+        };
+        util::call_apdu(card, wbuf, rbuf, self.into()).map_err(|err| err.in_command(cmd))
+    }
+
+    pub fn call<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<ReadRecordResponse<'r>> {
+        Ok(self.exec(card, wbuf, rbuf)?.into())
+    }
+
+    /// Like [ReadRecord::call], but the response doesn't borrow rbuf.
+    pub fn call_owned(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<OwnedReadRecordResponse> {
+        Ok(self.call(card, wbuf, rbuf)?.to_owned_response())
+    }
+}
+
+impl<'a> From<ReadRecord> for Command<'a> {
+    fn from(v: ReadRecord) -> Self {
+        Self::new_with_le(
+            0x00,
+            0xB2,
+            match v.id {
+                RecordID::Number(num) => num,
+            },
+            (v.sfi << 3)
+                | match v.id {
+                    RecordID::Number(_) => 0b0000_0100,
+                },
+            0x00,
+        )
+    }
+}
+
+/// Response type for a READ RECORD command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReadRecordResponse<'a> {
+    pub data: &'a [u8],
+}
+
+impl<'a> ReadRecordResponse<'a> {
+    pub fn parse_into<R: TryFrom<&'a [u8]>>(&self) -> Result<R, R::Error>
+    where
+        R::Error: From<crate::Error>,
+    {
+        R::try_from(self.data.into())
+    }
+
+    /// Copies the response out of rbuf.
+    pub fn to_owned_response(&self) -> OwnedReadRecordResponse {
+        OwnedReadRecordResponse {
+            data: self.data.into(),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for ReadRecordResponse<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+/// A [ReadRecordResponse] that owns its data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedReadRecordResponse {
+    pub data: HexVec,
+}
+
+impl OwnedReadRecordResponse {
+    /// See [ReadRecordResponse::parse_into].
+    pub fn parse_into<'a, R: TryFrom<&'a [u8]>>(&'a self) -> Result<R, R::Error>
+    where
+        R::Error: From<crate::Error>,
+    {
+        R::try_from(&self.data)
+    }
+}
+
+/// Reads the records in an EF, from 1 until the card says there are no more (6A83); see
+/// [Records].
+pub fn records<'c, T: CardTransport>(
+    card: &'c mut T,
+    wbuf: &'c mut [u8],
+    rbuf: &'c mut [u8],
+    sfi: u8,
+) -> Records<'c, T> {
+    Records {
+        card,
+        wbuf,
+        rbuf,
+        sfi,
+        next: Some(1),
+    }
+}
+
+/// Iterator over the records in an EF, with their numbers. Records that can't be read
+/// are errors, but don't stop the iteration; only running out of records does.
+pub struct Records<'c, T: CardTransport> {
+    card: &'c mut T,
+    wbuf: &'c mut [u8],
+    rbuf: &'c mut [u8],
+    sfi: u8,
+    next: Option<u8>,
+}
+
+impl<T: CardTransport> Iterator for Records<'_, T> {
+    type Item = Result<(u8, OwnedReadRecordResponse)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num = self.next?;
+        self.next = num.checked_add(1);
+        debug!(sfi = self.sfi, num, "Trying next record...");
+        let cmd = ReadRecord {
+            sfi: self.sfi,
+            id: RecordID::Number(num),
+        };
+        match cmd.call_owned(self.card, self.wbuf, self.rbuf) {
+            Ok(rsp) => Some(Ok((num, rsp))),
+            Err(err) if err.sw() == Some((0x6A, 0x83)) => {
+                debug!(sfi = self.sfi, num, "No more records");
+                self.next = None;
+                None
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// File identifier of the MF.
+pub const MF: &[u8] = &[0x3F, 0x00];
+
+/// File identifier of EF.DIR, under the MF; lists the applications on the card.
+pub const EF_DIR: &[u8] = &[0x2F, 0x00];
+
+/// File identifier of EF.ICCID, under the MF; the serial number of a SIM (or any other
+/// UICC), in swapped BCD. See [crate::uid::CardUid::from_iccid].
+pub const EF_ICCID: &[u8] = &[0x2F, 0xE2];
+
+/// File identifier of EF.ATR/INFO, under the MF; whatever didn't fit in the historical
+/// bytes. See [crate::atr::parse_ef_atr].
+pub const EF_ATR: &[u8] = &[0x2F, 0x01];
+
+/// 0x61 Application template, from EF.DIR.
+///
+/// This is how non-payment cards (eIDs, PIV, JavaCards...) list their applications;
+/// EMV has its own directories, which use the same template with extra EMV-specific tags.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ApplicationTemplate {
+    /// 0x4F Application identifier.
+    pub aid: HexVec,
+    /// 0x50 Application label.
+    pub label: Option<String>,
+    /// 0x51 Path to the application's DF, for cards that don't do SELECT by name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub path: Option<HexVec>,
+    /// 0x53 Discretionary data, or 0x73 discretionary template.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub discretionary_data: Option<HexVec>,
+}
+
+impl ApplicationTemplate {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("ApplicationTemplate");
+        let _enter = span.enter();
+
+        let mut slf = Self::default();
+        for res in ber::iter(data) {
+            let (tag, value) = res?;
+            match tag {
+                [0x4F] => slf.aid = value.into(),
+                [0x50] => slf.label = Some(String::from_utf8_lossy(value).into()),
+                [0x51] => slf.path = Some(value.into()),
+                [0x53] | [0x73] => slf.discretionary_data = Some(value.into()),
+                _ => warnings::unknown_field("ApplicationTemplate", tag, value),
+            }
+        }
+        Ok(slf)
+    }
+}
+
+/// Parses every application template in (a record of) EF.DIR. Unused space at the end is
+/// usually padded with 00 or FF, which isn't a template, so that's where we stop.
+pub fn parse_ef_dir(data: &[u8]) -> Result<Vec<ApplicationTemplate>> {
+    let span = trace_span!("parse_ef_dir");
+    let _enter = span.enter();
+
+    let mut apps = vec![];
+    let mut data = data;
+    while !matches!(data.first(), None | Some(0x00 | 0xFF)) {
+        let (rest, (tag, value)) = ber::parse_next(data)?;
+        match tag {
+            [0x61] => apps.push(ApplicationTemplate::parse(value)?),
+            _ => warnings::unknown_field("EF.DIR", tag, value),
+        }
+        data = rest;
+    }
+    Ok(apps)
+}
+
+/// Selects the MF, then reads and parses EF.DIR. It's usually a record file, but some cards
+/// make it a transparent one, so that's tried too.
+pub fn read_ef_dir(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<ApplicationTemplate>> {
+    let span = trace_span!("read_ef_dir");
+    let _enter = span.enter();
+
+    // Some cards won't let you select the MF, but EF.DIR is right there anyway.
+    let mf = Select {
+        id: SelectID::MF,
+        mode: SelectMode::First,
+    };
+    if let Err(err) = mf.exec(card, wbuf, rbuf) {
+        debug!("Couldn't select MF: {}", err);
+    }
+    Select {
+        id: SelectID::EF(EF_DIR),
+        mode: SelectMode::First,
+    }
+    .exec(card, wbuf, rbuf)?;
+
+    let mut apps = vec![];
+    for i in 1..=u8::MAX {
+        let id = RecordID::Number(i);
+        match (ReadRecord { sfi: 0, id }).call(card, wbuf, rbuf) {
+            Ok(rsp) => apps.extend(parse_ef_dir(rsp.data)?),
+            Err(err) if err.sw() == Some((0x6A, 0x83)) => break,
+            // Command incompatible with file structure; it's a transparent file.
+            Err(err) if err.sw() == Some((0x69, 0x81)) && i == 1 => {
+                debug!("EF.DIR isn't a record file, reading it as a transparent one");
+                return parse_ef_dir(&read_binary(card, wbuf, rbuf, 0, 256)?);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(apps)
+}
+
+/// Reads EF.ICCID, raw.
+pub fn read_iccid(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<u8>> {
+    let span = trace_span!("read_iccid");
+    let _enter = span.enter();
+
+    let mf = Select {
+        id: SelectID::MF,
+        mode: SelectMode::First,
+    };
+    if let Err(err) = mf.exec(card, wbuf, rbuf) {
+        debug!("Couldn't select MF: {}", err);
+    }
+    Select {
+        id: SelectID::EF(EF_ICCID),
+        mode: SelectMode::First,
+    }
+    .exec(card, wbuf, rbuf)?;
+    read_binary(card, wbuf, rbuf, 0, 10)
+}
+
+/// Reads EF.ATR/INFO, raw. It's a transparent file, and rarely more than a few dozen bytes.
+pub fn read_ef_atr(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<u8>> {
+    let span = trace_span!("read_ef_atr");
+    let _enter = span.enter();
+
+    let mf = Select {
+        id: SelectID::MF,
+        mode: SelectMode::First,
+    };
+    if let Err(err) = mf.exec(card, wbuf, rbuf) {
+        debug!("Couldn't select MF: {}", err);
+    }
+    Select {
+        id: SelectID::EF(EF_ATR),
+        mode: SelectMode::First,
+    }
+    .exec(card, wbuf, rbuf)?;
+    read_binary(card, wbuf, rbuf, 0, 256)
+}
+
+/// Reads `len` bytes from the currently selected EF, starting at `offset`, in as many READ
+/// BINARY commands as it takes. Stops early (without an error) if the file is shorter.
+///
+/// Each one asks for as much as the card's [ResponseLimits] say it'll send; if it won't
+/// take that, it's asked for less (see [response_limits::next_le]), and that's remembered.
+pub fn read_binary(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    offset: u16,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let span = trace_span!("read_binary");
+    let _enter = span.enter();
+
+    let mut max_le = usize::from(card.response_limits().max_le);
+    let mut data = Vec::with_capacity(len);
+    let mut le = len.min(max_le) as u16;
+    while data.len() < len {
+        let offset = offset + data.len() as u16;
+        match (ReadBinary { offset, le }).call(card, wbuf, rbuf) {
+            Ok(rsp) if rsp.data.is_empty() => break,
+            Ok(rsp) => data.extend_from_slice(rsp.data),
+            // Wrong Le; SW2 tells us how many bytes there actually are.
+            Err(crate::Error::APDU(0x6C, sw2)) if u16::from(sw2) != le => {
+                debug!(le, sw2, "Wrong Le, retrying");
+                le = sw2.into();
+                continue;
+            }
+            // Asked for more than the card sends at once; it doesn't say how much it would.
+            Err(err @ crate::Error::APDU(0x67, 0x00)) => {
+                le = response_limits::next_le(card, le).ok_or(err)?;
+                debug!(le, "Wrong length, asking for less");
+                ResponseLimits::learn_le(card, le);
+                max_le = le.into();
+                continue;
+            }
+            // Offset is past the end of the file.
+            Err(crate::Error::APDU(0x6B, 0x00)) if !data.is_empty() => break,
+            Err(err) => return Err(err),
+        }
+        le = (len - data.len()).min(max_le) as u16;
+    }
+    data.truncate(len);
+    Ok(data)
+}
+
+// A READ BINARY command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReadBinary {
+    /// Offset into the currently selected EF. Only 15 bits are usable.
+    pub offset: u16,
+    /// Expected length of the response; 256 (or 0) means "as much as you've got".
+    pub le: u16,
+}
+
+impl ReadBinary {
+    pub fn exec<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        util::call_apdu(card, wbuf, rbuf, self.into())
+    }
+
+    pub fn call<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<ReadBinaryResponse<'r>> {
+        Ok(self.exec(card, wbuf, rbuf)?.into())
+    }
+
+    /// Like [ReadBinary::call], but the response doesn't borrow rbuf.
+    pub fn call_owned(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<OwnedReadBinaryResponse> {
+        Ok(self.call(card, wbuf, rbuf)?.to_owned_response())
+    }
+}
+
+impl<'a> From<ReadBinary> for Command<'a> {
+    fn from(v: ReadBinary) -> Self {
+        Self::new_with_le(
+            0x00,
+            0xB0,
+            ((v.offset >> 8) & 0x7F) as u8,
+            (v.offset & 0xFF) as u8,
+            v.le % 256,
+        )
+    }
+}
+
+/// Response type for a READ BINARY command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReadBinaryResponse<'a> {
+    pub data: &'a [u8],
+}
+
+impl ReadBinaryResponse<'_> {
+    /// Copies the response out of rbuf.
+    pub fn to_owned_response(&self) -> OwnedReadBinaryResponse {
+        OwnedReadBinaryResponse {
+            data: self.data.into(),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for ReadBinaryResponse<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+/// A [ReadBinaryResponse] that owns its data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedReadBinaryResponse {
+    pub data: HexVec,
+}
+
+/// A MANAGE CHANNEL command, for logical channels. Always sent on the basic channel.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ManageChannel {
+    /// Open a new channel; the card picks the number.
+    Open,
+    /// Close a channel (not the basic one, 0).
+    Close(u8),
+}
+
+impl ManageChannel {
+    pub fn exec<'r>(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        util::call_apdu(card, wbuf, rbuf, self.into())
+    }
+
+    pub fn call(
+        self,
+        card: &mut impl CardTransport,
+        wbuf: &mut [u8],
+        rbuf: &mut [u8],
+    ) -> Result<ManageChannelResponse> {
+        Ok(self.exec(card, wbuf, rbuf)?.into())
+    }
+}
+
+impl<'a> From<ManageChannel> for Command<'a> {
+    fn from(v: ManageChannel) -> Self {
+        match v {
+            ManageChannel::Open => Self::new_with_le(0x00, 0x70, 0x00, 0x00, 1),
+            ManageChannel::Close(n) => Self::new(0x00, 0x70, 0x80, n),
+        }
+    }
+}
+
+/// Response type for a MANAGE CHANNEL command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ManageChannelResponse {
+    /// The channel that was opened; nothing for a close.
+    pub channel: Option<u8>,
+}
+
+impl From<&[u8]> for ManageChannelResponse {
+    fn from(data: &[u8]) -> Self {
+        Self {
+            channel: data.first().copied(),
+        }
+    }
+}
+
+/// Highest logical channel number there is; 1-3 fit in the first interindustry CLA, 4-19
+/// need the further interindustry one.
+pub const MAX_CHANNEL: u8 = 19;
+
+/// Encodes a logical channel number into a CLA byte, keeping the proprietary bit, secure
+/// messaging and command chaining. ISO 7816-4, Section 5.4.1.
+pub fn encode_cla(cla: u8, channel: u8) -> Result<u8> {
+    let proprietary = cla & 0b1000_0000;
+    // In the first interindustry CLA, SM is bits 3-4 and chaining is bit 5; in the further
+    // one, it's bit 6 (and there's only one kind of SM) and bit 5.
+    let (sm, chaining) = match cla & 0b0100_0000 {
+        0 => (cla & 0b0000_1100, cla & 0b0001_0000),
+        _ => ((cla & 0b0010_0000) >> 2, cla & 0b0001_0000),
+    };
+    match channel {
+        0..=3 => Ok(proprietary | chaining | sm | channel),
+        4..=MAX_CHANNEL => {
+            let sm = if sm != 0 { 0b0010_0000 } else { 0 };
+            Ok(proprietary | 0b0100_0000 | sm | chaining | (channel - 4))
+        }
+        _ => Err(crate::Error::Transport(
+            "channel",
+            format!("there's no logical channel {}", channel),
+        )),
+    }
+}
+
+/// A logical channel on a card. Everything sent through it goes to that channel, so one
+/// application can stay selected on it while others are selected on the basic channel,
+/// which multi-applet cards (eg. GlobalPlatform + PIV) sometimes need.
+///
+/// Dropping it closes the channel; use [Channel::close] if you care whether that worked.
+pub struct Channel<'c, T: CardTransport> {
+    card: &'c mut T,
+    number: u8,
+    closed: bool,
+}
+
+impl<'c, T: CardTransport> Channel<'c, T> {
+    /// Opens a new channel.
+    pub fn open(card: &'c mut T) -> Result<Self> {
+        let span = trace_span!("Channel::open");
+        let _enter = span.enter();
+
+        let mut wbuf = [0; 5];
+        let mut rbuf = [0; 3];
+        let rsp = ManageChannel::Open.call(card, &mut wbuf, &mut rbuf)?;
+        let number = rsp.channel.ok_or(crate::Error::Transport(
+            "channel",
+            "MANAGE CHANNEL didn't say which channel it opened".into(),
+        ))?;
+        debug!(number, "Opened logical channel");
+        Ok(Self::new(card, number))
+    }
+
+    /// Uses a channel that's already open (eg. one the card opened with a SELECT).
+    pub fn new(card: &'c mut T, number: u8) -> Self {
+        Self {
+            card,
+            number,
+            closed: false,
+        }
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Closes the channel.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        let mut wbuf = [0; 4];
+        let mut rbuf = [0; 2];
+        ManageChannel::Close(self.number).exec(self.card, &mut wbuf, &mut rbuf)?;
+        Ok(())
+    }
+}
+
+impl<T: CardTransport> Drop for Channel<'_, T> {
+    fn drop(&mut self) {
+        if !self.closed && self.number != 0 {
+            let mut wbuf = [0; 4];
+            let mut rbuf = [0; 2];
+            if let Err(err) =
+                ManageChannel::Close(self.number).exec(self.card, &mut wbuf, &mut rbuf)
+            {
+                warn!(
+                    number = self.number,
+                    "Couldn't close logical channel: {}", err
+                );
+            }
+        }
+    }
+}
+
+impl<T: CardTransport> CardTransport for Channel<'_, T> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let mut capdu = capdu.to_vec();
+        match capdu.first_mut() {
+            // PCSC pseudo-APDUs go to the reader, which has no channels.
+            Some(0xFF) | None => {}
+            Some(cla) => *cla = encode_cla(*cla, self.number)?,
+        }
+        let res = self.card.transmit(&capdu, rbuf);
+        crate::secret::scrub(&mut capdu);
+        res
+    }
+
+    fn protocol(&mut self) -> crate::protocol::Protocol {
+        self.card.protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.card.atr()
+    }
+
+    #[cfg(feature = "hardware")]
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.card.get_attribute(attr, rbuf)
+    }
+
+    #[cfg(feature = "hardware")]
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        self.card.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.card.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.card.reconnect()
+    }
+
+    fn retry_policy(&self) -> Option<&crate::retry::RetryPolicy> {
+        self.card.retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&crate::reader_quirks::ReaderQuirks> {
+        self.card.reader_quirks()
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        self.card.response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        self.card.learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> crate::buffers::Buffers {
+        self.card.buffers()
+    }
+
+    fn reset(&mut self, kind: crate::transport::Reset) -> Result<()> {
+        // A reset closes every channel but the basic one; don't try to close it again.
+        self.closed = true;
+        self.card.reset(kind)
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        let number = self.number;
+        self.card.transaction(&mut |mut card| {
+            f(&mut Channel {
+                card: &mut card,
+                number,
+                closed: true,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_response_parse_emv_dir() {
+        let rsp: SelectResponse = [
+            0x6F, 0x1E, 0x84, 0x0E, 0x31, 0x50, 0x41, 0x59, 0x2E, 0x53, 0x59, 0x53, 0x2E, 0x44,
+            0x44, 0x46, 0x30, 0x31, 0xA5, 0x0C, 0x88, 0x01, 0x01, 0x5F, 0x2D, 0x02, 0x65, 0x6E,
+            0x9F, 0x11, 0x01, 0x01,
+        ][..]
+            .try_into()
+            .expect("couldn't parse SelectResponse");
+        assert_eq!(rsp.fci.df_name, "1PAY.SYS.DDF01".as_bytes());
+        assert_eq!(
+            rsp.fci.pt,
+            Some(&[0x88, 0x01, 0x01, 0x5F, 0x2D, 0x02, 0x65, 0x6E, 0x9F, 0x11, 0x01, 0x01][..])
+        );
+    }
+
+    #[test]
+    fn test_apdu_read_record() {
+        let c: apdu::Command = (ReadRecord {
+            sfi: 1,
+            id: RecordID::Number(1),
+        })
+        .into();
+        let mut buf = [0u8; 256];
+        c.write(&mut buf[..]);
+        assert_eq!(&buf[..c.len()], &[0x00, 0xB2, 0x01, 0x0C, 0x00]);
+    }
+
+    #[test]
+    fn test_apdu_select_ef() {
+        let c: apdu::Command = (Select {
+            id: SelectID::EF(&[0x01, 0x01]),
+            mode: SelectMode::First,
+        })
+        .into();
+        let mut buf = [0u8; 256];
+        c.write(&mut buf[..]);
+        assert_eq!(
+            &buf[..c.len()],
+            &[0x00, 0xA4, 0x02, 0x00, 0x02, 0x01, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_apdu_read_binary() {
+        let c: apdu::Command = (ReadBinary {
+            offset: 0x0123,
+            le: 256,
+        })
+        .into();
+        let mut buf = [0u8; 256];
+        c.write(&mut buf[..]);
+        assert_eq!(&buf[..c.len()], &[0x00, 0xB0, 0x01, 0x23, 0x00]);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_select_response_parse_anything(data: Vec<u8>) {
+            let _ = SelectResponse::try_from(&data[..]);
+        }
+
+        #[test]
+        fn test_select_response_parse_any_fci(data: Vec<u8>) {
+            // Most garbage fails on the first byte, so also try it wrapped in an FCI template.
+            let mut fci = vec![0x6F, data.len() as u8];
+            fci.extend(data.iter().take(0x7F));
+            let _ = SelectResponse::try_from(&fci[..]);
+        }
+    }
+
+    #[test]
+    fn test_parse_ef_dir() {
+        // A PIV applet and an OpenPGP one, padded out with FF.
+        let data = [
+            0x61, 0x0F, 0x4F, 0x09, 0xA0, 0x00, 0x00, 0x03, 0x08, 0x00, 0x00, 0x10, 0x00, 0x50,
+            0x02, 0x50, 0x49, 0x61, 0x0A, 0x4F, 0x06, 0xD2, 0x76, 0x00, 0x01, 0x24, 0x01, 0x51,
+            0x00, 0xFF, 0xFF,
+        ];
+        let apps = parse_ef_dir(&data).unwrap();
+        assert_eq!(
+            apps,
+            vec![
+                ApplicationTemplate {
+                    aid: vec![0xA0, 0x00, 0x00, 0x03, 0x08, 0x00, 0x00, 0x10, 0x00].into(),
+                    label: Some("PI".into()),
+                    ..Default::default()
+                },
+                ApplicationTemplate {
+                    aid: vec![0xD2, 0x76, 0x00, 0x01, 0x24, 0x01].into(),
+                    path: Some(vec![].into()),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert!(parse_ef_dir(&[0x61, 0x05, 0x4F]).is_err());
+    }
+
+    #[test]
+    fn test_select_mf() {
+        let cmd: Command = Select {
+            id: SelectID::MF,
+            mode: SelectMode::First,
+        }
+        .into();
+        assert_eq!(
+            Vec::<u8>::from(cmd),
+            vec![0x00, 0xA4, 0x00, 0x00, 0x02, 0x3F, 0x00, 0x00]
+        );
+    }
+
+    /// Has two records in SFI 1; the second one is locked.
+    struct RecordCard;
+
+    impl CardTransport for RecordCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x00, 0xB2, 0x01, 0x0C, ..] => &[0x70, 0x00, 0x90, 0x00],
+                [0x00, 0xB2, 0x02, 0x0C, ..] => &[0x69, 0x82],
+                _ => &[0x6A, 0x83],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let (mut wbuf, mut rbuf) = ([0; 16], [0; 16]);
+        let mut card = RecordCard;
+        let mut records = records(&mut card, &mut wbuf, &mut rbuf, 1);
+        let (num, rsp) = records.next().unwrap().unwrap();
+        assert_eq!((num, rsp.data), (1, vec![0x70, 0x00].into()));
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.sw(), Some((0x69, 0x82)));
+        assert_eq!(
+            err.to_string(),
+            "READ RECORD 1/2: error from card: SW1=0x69 SW2=0x82"
+        );
+        assert!(records.next().is_none());
+        assert!(records.next().is_none());
+    }
+
+    /// T=1, with an IFSC of 64, and a 100-byte EF it won't send more than 64 bytes of at once.
+    #[derive(Default)]
+    struct ShortCard {
+        limits: ResponseLimits,
+        wrong_lengths: usize,
+    }
+
+    impl CardTransport for ShortCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp = match *capdu {
+                [0x00, 0xB0, hi, lo, le] => {
+                    let offset = usize::from(u16::from_be_bytes([hi, lo]));
+                    let le = if le == 0 { 256 } else { usize::from(le) };
+                    if le > 64 {
+                        self.wrong_lengths += 1;
+                        vec![0x67, 0x00]
+                    } else {
+                        let end = (offset + le).min(100);
+                        let mut rsp: Vec<u8> = (offset..end).map(|n| n as u8).collect();
+                        rsp.extend([0x90, 0x00]);
+                        rsp
+                    }
+                }
+                _ => vec![0x6D, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+
+        fn atr(&mut self) -> Result<Vec<u8>> {
+            Ok(vec![0x3B, 0x80, 0x81, 0x31, 0x40, 0x45, 0x35])
+        }
+
+        fn response_limits(&self) -> ResponseLimits {
+            self.limits
+        }
+
+        fn learn_response_limits(&mut self, limits: ResponseLimits) {
+            self.limits = limits;
+        }
+    }
+
+    #[test]
+    fn test_read_binary_short_responses() {
+        let (mut wbuf, mut rbuf) = ([0; 16], [0; 128]);
+        let mut card = ShortCard::default();
+        let data = read_binary(&mut card, &mut wbuf, &mut rbuf, 0, 100).unwrap();
+        assert_eq!(data, (0..100).collect::<Vec<u8>>());
+        assert_eq!(card.limits.max_le, 64);
+        assert_eq!(card.wrong_lengths, 1);
+
+        // It's remembered for next time.
+        let data = read_binary(&mut card, &mut wbuf, &mut rbuf, 10, 80).unwrap();
+        assert_eq!(data, (10..90).collect::<Vec<u8>>());
+        assert_eq!(card.wrong_lengths, 1);
+    }
+
+    /// Answers MANAGE CHANNEL with channel 1, and everything else with 9000; remembers
+    /// what it was sent.
+    #[derive(Default)]
+    struct ChannelCard(Vec<Vec<u8>>);
+
+    impl CardTransport for ChannelCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.0.push(capdu.to_vec());
+            let rsp: &[u8] = match capdu {
+                [0x00, 0x70, 0x00, ..] => &[0x01, 0x90, 0x00],
+                _ => &[0x90, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_encode_cla() {
+        assert_eq!(encode_cla(0x00, 1).unwrap(), 0x01);
+        assert_eq!(encode_cla(0x0C, 3).unwrap(), 0x0F); // SM.
+        assert_eq!(encode_cla(0x80, 2).unwrap(), 0x82); // GlobalPlatform.
+        assert_eq!(encode_cla(0x10, 4).unwrap(), 0x50); // Chaining.
+        assert_eq!(encode_cla(0x84, 19).unwrap(), 0xEF); // Proprietary + SM.
+        assert_eq!(encode_cla(0x43, 0).unwrap(), 0x00);
+        assert!(encode_cla(0x00, 20).is_err());
+    }
+
+    #[test]
+    fn test_channel() {
+        let mut card = ChannelCard::default();
+        let mut channel = Channel::open(&mut card).unwrap();
+        assert_eq!(channel.number(), 1);
+        let mut rbuf = [0; 2];
+        channel
+            .transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+            .unwrap();
+        channel
+            .transmit(&[0xFF, 0xCA, 0x00, 0x00, 0x00], &mut rbuf)
+            .unwrap();
+        drop(channel);
+        assert_eq!(
+            card.0,
+            vec![
+                vec![0x00, 0x70, 0x00, 0x00, 0x01],
+                vec![0x01, 0xA4, 0x04, 0x00, 0x00],
+                vec![0xFF, 0xCA, 0x00, 0x00, 0x00],
+                vec![0x00, 0x70, 0x80, 0x01],
+            ]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Parsers, encoders and commands for talking to smartcards.
+//!
+//! Without the `std` feature, this is a no_std crate (but it still needs `alloc`), with
+//! only the parsers that don't need anything else: [atr], [ats], [ber], [compact_tlv], the
+//! [felica] frame encodings, and what they need to go with them.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod atr;
+pub mod ats;
+pub mod ber;
+#[cfg(feature = "std")]
+pub mod buffers;
+#[cfg(feature = "std")]
+pub mod calypso;
+pub mod compact_tlv;
+#[cfg(feature = "std")]
+pub mod diversify;
+#[cfg(feature = "std")]
+pub mod eid;
+#[cfg(feature = "std")]
+pub mod emv;
+pub mod felica;
+pub mod heuristics;
+pub mod hexvec;
+#[cfg(feature = "std")]
+pub mod iso7816;
+pub mod limits;
+#[cfg(feature = "std")]
+pub mod mifare;
+pub mod money;
+#[cfg(feature = "std")]
+pub mod pcsc_attrs;
+#[cfg(feature = "std")]
+pub mod protocol;
+#[cfg(feature = "std")]
+pub mod reader_quirks;
+#[cfg(feature = "std")]
+pub mod response_limits;
+#[cfg(feature = "std")]
+pub mod retry;
+#[cfg(feature = "std")]
+pub mod secret;
+#[cfg(feature = "serde")]
+pub mod serde_hex;
+#[cfg(feature = "std")]
+pub mod transparent;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
+pub mod uicc;
+#[cfg(feature = "std")]
+pub mod uid;
+#[cfg(feature = "std")]
+pub mod util;
+pub mod warnings;
+pub mod x509;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+pub use hexvec::HexVec;
+#[cfg(feature = "std")]
+pub use transport::CardTransport;
+
+/// Big enough for any short APDU, or its response. (Same as PCSC's MAX_BUFFER_SIZE.)
+pub const MAX_BUFFER_SIZE: usize = 264;
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The card returned a non-standard response code (not 0x90, 0x00).
+    #[error("error from card: SW1=0x{0:02X} SW2=0x{1:02X}")]
+    APDU(u8, u8),
+    // Same thing, but in a PCSC Transparent Session (eg. felica::Session).
+    #[error("transparent session error: DO={0:02} - {1}")]
+    PCSCTransparent(u8, PCSCTransparentError),
+
+    #[error("expected tag {expected:04X?}, got {actual:04X?}")]
+    WrongTag { expected: Vec<u8>, actual: Vec<u8> },
+
+    #[error("[felica] command failed: {0}")]
+    FelicaStatus(felica::StatusFlags),
+
+    #[error("[felica] reader cannot pass through FeliCa frames (it supports neither the FF 00 00 00 wrapper nor PC/SC transparent sessions)")]
+    FelicaPassthroughUnsupported,
+
+    /// A response frame ended before it should have; usually a reader that only passed on
+    /// part of it.
+    #[error("[felica] response truncated: {0}")]
+    FelicaTruncated(String),
+
+    /// Frames have a one-byte length, so there's only so much a command can say.
+    #[error("[felica] command frame is {0} bytes, but can be at most 255")]
+    FelicaFrameTooLong(usize),
+
+    /// Something doesn't fit in a command, eg. too many blocks for a one-byte count.
+    #[error("[felica] can't encode command: {0}")]
+    FelicaEncoding(String),
+
+    #[error("[felica] expected a {expected:?} payload, got a {actual:?}")]
+    FelicaCommandCode {
+        expected: felica::CommandCode,
+        actual: felica::CommandCode,
+    },
+
+    // Not #[from]: without std, these don't implement Error, so they can't be a source.
+    #[error("{0}")]
+    Scroll(scroll::Error),
+
+    #[error("{0}")]
+    Nom(nom::error::Error<HexVec>),
+
+    #[cfg(feature = "hardware")]
+    #[error(transparent)]
+    PCSC(#[from] pcsc::Error),
+
+    /// Something went wrong in a non-PCSC transport (libnfc, etc.)
+    #[error("[{0}] {1}")]
+    Transport(&'static str, String),
+
+    #[error("[atr] truncated: {0}")]
+    AtrTruncated(&'static str),
+
+    #[error("[atr] invalid: {0}")]
+    AtrInvalid(&'static str),
+
+    #[error("[ats] invalid: {0}")]
+    AtsInvalid(&'static str),
+
+    #[error("[x509] {0}")]
+    X509(&'static str),
+
+    #[error("[diversify] {0}")]
+    Diversify(&'static str),
+
+    #[error("response doesn't fit in the buffer")]
+    InsufficientBuffer,
+
+    /// Tried to send a command [util::is_mutating] refuses with the `write` feature off.
+    #[error("refusing to send INS {0:02X}: this build is read-only")]
+    ReadOnly(u8),
+
+    /// A call took longer than its [retry::RetryPolicy] allowed.
+    #[error("gave up after {0:?}")]
+    Timeout(core::time::Duration),
+
+    /// Something from the card went past one of the [limits].
+    #[error("[limits] {limit} is over the limit of {max}")]
+    LimitExceeded { limit: limits::Limit, max: usize },
+
+    #[error("no reader matches {query:?}; available: {available:?}")]
+    ReaderNotFound {
+        query: String,
+        available: Vec<String>,
+    },
+
+    #[error("{query:?} matches more than one reader: {matches:?}")]
+    AmbiguousReader { query: String, matches: Vec<String> },
+
+    /// An error from a particular command, eg. "SELECT AID A0000000041010"; to look at
+    /// what went wrong rather than where, see [Error::root] and [Error::sw].
+    #[error("{cmd}: {source}")]
+    Command {
+        cmd: String,
+        source: alloc::boxed::Box<Error>,
+    },
+}
+
+impl Error {
+    /// Wraps this in an [Error::Command], saying which command it came from.
+    pub fn in_command(self, cmd: impl Into<String>) -> Self {
+        Self::Command {
+            cmd: cmd.into(),
+            source: alloc::boxed::Box::new(self),
+        }
+    }
+
+    /// The actual error, underneath any [Error::Command]s.
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Command { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The status word, if this is the card saying no ([Error::APDU]).
+    pub fn sw(&self) -> Option<(u8, u8)> {
+        match self.root() {
+            Self::APDU(sw1, sw2) => Some((*sw1, *sw2)),
+            _ => None,
+        }
+    }
+
+    /// Did the card go away (as opposed to saying no)?
+    pub fn is_card_removed(&self) -> bool {
+        match self.root() {
+            #[cfg(feature = "hardware")]
+            Self::PCSC(pcsc::Error::RemovedCard | pcsc::Error::NoSmartcard) => true,
+            _ => false,
+        }
+    }
+
+    /// Was the card reset under our feet, eg. by another process sharing the reader?
+    pub fn is_card_reset(&self) -> bool {
+        match self.root() {
+            #[cfg(feature = "hardware")]
+            Self::PCSC(pcsc::Error::ResetCard) => true,
+            _ => false,
+        }
+    }
+
+    /// Was the card reset or pulled out? Either way, it's forgotten what was selected, so
+    /// this is for starting over (see [transport::transaction]), not skipping like a no.
+    pub fn is_card_lost(&self) -> bool {
+        self.is_card_reset() || self.is_card_removed()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[repr(u16)]
+pub enum PCSCTransparentError {
+    NoError = 0x9000,
+    WarnUnavailable = 0x6282,
+    NoInfo = 0x6300,
+    ExecStoppedOtherDOFailed = 0x6301,
+    NotSupported = 0x6A81,
+    UnexpectedLength = 0x6700,
+    UnexpectedValue = 0x6A80,
+    NoResponseFromIFD = 0x6400,
+    NoResponseFromICC = 0x6401,
+    FailedUnknown = 0x6F00,
+    #[num_enum(catch_all)]
+    Unknown(u16) = 0x0000,
+}
+
+impl core::fmt::Display for PCSCTransparentError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoError => write!(f, "{:04X} No Error", u16::from(*self)),
+            Self::WarnUnavailable => write!(
+                f,
+                "{:04X} Warning: Requested information not available",
+                u16::from(*self)
+            ),
+            Self::NoInfo => write!(f, "{:04X} No information", u16::from(*self)),
+            Self::ExecStoppedOtherDOFailed => write!(
+                f,
+                "{:04X} Execution stooped due to failure in other data object",
+                u16::from(*self)
+            ),
+            Self::NotSupported => {
+                write!(f, "{:04X} Data Object not supported", u16::from(*self))
+            }
+            Self::UnexpectedLength => write!(
+                f,
+                "{:04X} Data Object has unexpected length",
+                u16::from(*self)
+            ),
+            Self::UnexpectedValue => {
+                write!(
+                    f,
+                    "{:04X} Data Object has unexpected value",
+                    u16::from(*self)
+                )
+            }
+            Self::NoResponseFromIFD => {
+                write!(
+                    f,
+                    "{:04X} Data Object execution error: No response from IFD",
+                    u16::from(*self)
+                )
+            }
+            Self::NoResponseFromICC => {
+                write!(
+                    f,
+                    "{:04X} Data Object execution error: No response from ICC",
+                    u16::from(*self)
+                )
+            }
+            Self::FailedUnknown => write!(
+                f,
+                "{:04X} Data object failed, no precise diagnosis",
+                u16::from(*self)
+            ),
+            Self::Unknown(v) => write!(f, "{:04X} Unknown Error", v),
+        }
+    }
+}
+
+impl From<scroll::Error> for Error {
+    fn from(value: scroll::Error) -> Self {
+        Self::Scroll(value)
+    }
+}
+
+impl From<nom::error::Error<HexVec>> for Error {
+    fn from(value: nom::error::Error<HexVec>) -> Self {
+        Self::Nom(value)
+    }
+}
+
+impl From<nom::error::Error<&[u8]>> for Error {
+    fn from(value: nom::error::Error<&[u8]>) -> Self {
+        nom::error::Error::new(HexVec(value.input.into()), value.code).into()
+    }
+}
+
+impl From<nom::Err<nom::error::Error<&[u8]>>> for Error {
+    fn from(value: nom::Err<nom::error::Error<&[u8]>>) -> Self {
+        match value {
+            nom::Err::Error(err) => err.into(),
+            nom::Err::Failure(err) => err.into(),
+            nom::Err::Incomplete(_) => {
+                panic!("can't convert nom::Err::Incomplete into cardinal::Error")
+            }
+        }
+    }
+}
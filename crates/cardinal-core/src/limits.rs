@@ -0,0 +1,120 @@
+//! Limits on how much the parsers will put up with.
+//!
+//! Everything we parse comes from a card (or a file someone handed us), and cards can say
+//! anything they like; a broken or malicious one could nest TLVs until we run out of stack,
+//! or keep listing services forever. The parsers check these limits, and return an
+//! [Error::LimitExceeded] instead of finding out the hard way.
+//!
+//! The limits are global; the defaults are far beyond anything a real card does, but if
+//! you're feeding the parsers something bigger (or want to be stricter), call [set].
+
+use crate::{Error, Result};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Which limit was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// How deeply TLVs can be nested.
+    Depth,
+    /// How many elements (TLVs, FeliCa services, blocks...) a single thing can have.
+    Elements,
+    /// How big a single input can be, in bytes.
+    Size,
+}
+
+impl core::fmt::Display for Limit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Depth => write!(f, "nesting depth"),
+            Self::Elements => write!(f, "number of elements"),
+            Self::Size => write!(f, "size"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum TLV nesting depth; the top level is 0. Capped at [crate::ber::MAX_DEPTH].
+    pub max_depth: usize,
+    /// Maximum number of elements in a single parse.
+    pub max_elements: usize,
+    /// Maximum size of a single input, in bytes.
+    pub max_size: usize,
+}
+
+impl Limits {
+    pub const DEFAULT: Self = Self {
+        max_depth: 16,
+        max_elements: 4096,
+        max_size: 64 * 1024,
+    };
+
+    /// Errors if `value` is over the limit for `limit`.
+    pub fn check(&self, limit: Limit, value: usize) -> Result<()> {
+        let max = match limit {
+            Limit::Depth => self.max_depth,
+            Limit::Elements => self.max_elements,
+            Limit::Size => self.max_size,
+        };
+        if value > max {
+            return Err(Error::LimitExceeded { limit, max });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// Atomics rather than a lock, so it works without std (and on microcontrollers that can
+// only load and store them). The three aren't set together, so a parse that's running
+// while they change might see some of the old ones and some of the new.
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_depth);
+static MAX_ELEMENTS: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_elements);
+static MAX_SIZE: AtomicUsize = AtomicUsize::new(Limits::DEFAULT.max_size);
+
+/// The current limits.
+pub fn get() -> Limits {
+    Limits {
+        max_depth: MAX_DEPTH.load(Ordering::Relaxed),
+        max_elements: MAX_ELEMENTS.load(Ordering::Relaxed),
+        max_size: MAX_SIZE.load(Ordering::Relaxed),
+    }
+}
+
+/// Changes the limits, for everything, from now on.
+pub fn set(limits: Limits) {
+    MAX_DEPTH.store(limits.max_depth, Ordering::Relaxed);
+    MAX_ELEMENTS.store(limits.max_elements, Ordering::Relaxed);
+    MAX_SIZE.store(limits.max_size, Ordering::Relaxed);
+}
+
+/// Shorthand for `get().check(limit, value)`.
+pub fn check(limit: Limit, value: usize) -> Result<()> {
+    get().check(limit, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limits = Limits {
+            max_depth: 2,
+            ..Default::default()
+        };
+        assert!(limits.check(Limit::Depth, 2).is_ok());
+        assert!(matches!(
+            limits.check(Limit::Depth, 3),
+            Err(Error::LimitExceeded {
+                limit: Limit::Depth,
+                max: 2
+            })
+        ));
+        assert!(limits.check(Limit::Size, 64 * 1024).is_ok());
+    }
+}
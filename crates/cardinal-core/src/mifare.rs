@@ -0,0 +1,300 @@
+//! Reading MIFARE Classic and Ultralight memory, through a PC/SC reader.
+//!
+//! These aren't ISO 7816 cards, so there's nothing to SELECT; instead, PC/SC Part 3 has
+//! "storage card" pseudo-APDUs, which the reader turns into the card's own commands:
+//! `FF B0` reads a block (or 4 Ultralight pages), and for Classic, `FF 82` loads a key into
+//! the reader and `FF 86` authenticates a sector with it.
+//!
+//! Classic sectors are only tried with [DEFAULT_KEY] (as key A, then key B); anything
+//! that's been given proper keys comes back as None. There's no guessing from a dictionary,
+//! that's what a Proxmark is for.
+//!
+//! [load_key] and [authenticate] are built without the `write` feature too (and let
+//! through by [util::is_mutating]): nothing can be read without them, and unlike a PIN, a
+//! wrong key doesn't use anything up.
+
+use crate::atr::CardName;
+use crate::{util, CardTransport, Error, Result};
+use tracing::{debug, trace_span};
+
+/// Key that new cards ship with, and a lot of them never get changed from.
+pub const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+/// Size of a Classic block.
+pub const BLOCK_SIZE: usize = 16;
+
+/// Size of an Ultralight page.
+pub const PAGE_SIZE: usize = 4;
+
+/// Most pages there are on anything in the Ultralight family (an NTAG216 has 231).
+pub const MAX_PAGES: u8 = 231;
+
+/// Which key to authenticate a sector with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyType {
+    A = 0x60,
+    B = 0x61,
+}
+
+/// How many blocks a Classic card has; None if it isn't one.
+pub fn classic_blocks(name: CardName) -> Option<u16> {
+    match name {
+        CardName::MifareMini => Some(20),
+        CardName::MifareClassic1K | CardName::MifarePlusSL12K | CardName::MifarePlusSL22K => {
+            Some(64)
+        }
+        CardName::MifareClassic4K | CardName::MifarePlusSL14K | CardName::MifarePlusSL24K => {
+            Some(256)
+        }
+        _ => None,
+    }
+}
+
+/// Is it in the Ultralight family? (NTAGs say they're Ultralights too.)
+pub fn is_ultralight(name: CardName) -> bool {
+    matches!(
+        name,
+        CardName::MifareUltralight | CardName::MifareUltralightC
+    )
+}
+
+/// The first block of the sector `block` is in, and how many blocks are in it; sectors
+/// above 32 (on a 4K) have 16 blocks instead of 4.
+pub fn sector(block: u16) -> (u16, u16) {
+    match block {
+        0..128 => (block & !3, 4),
+        _ => (block & !15, 16),
+    }
+}
+
+/// Loads a key into the reader's volatile memory, in `slot`.
+pub fn load_key(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    slot: u8,
+    key: &[u8; 6],
+) -> Result<()> {
+    let cmd = apdu::Command::new_with_payload(0xFF, 0x82, 0x00, slot, key.as_slice());
+    util::call_apdu(card, wbuf, rbuf, cmd)?;
+    Ok(())
+}
+
+/// Authenticates the sector `block` is in, with the key in `slot`.
+pub fn authenticate(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    block: u16,
+    key_type: KeyType,
+    slot: u8,
+) -> Result<()> {
+    let [msb, lsb] = block.to_be_bytes();
+    let data = [0x01, msb, lsb, key_type as u8, slot];
+    let cmd = apdu::Command::new_with_payload(0xFF, 0x86, 0x00, 0x00, data.as_slice());
+    util::call_apdu(card, wbuf, rbuf, cmd)?;
+    Ok(())
+}
+
+/// Reads `len` bytes, starting at `block` (or page, on an Ultralight).
+pub fn read<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    block: u16,
+    len: u8,
+) -> Result<&'r [u8]> {
+    let [msb, lsb] = block.to_be_bytes();
+    util::call_le(card, wbuf, rbuf, 0xFF, 0xB0, msb, lsb, len.into())
+}
+
+/// Reads every block of a Classic card with `blocks` blocks; see [classic_blocks]. Blocks in
+/// sectors that won't take [DEFAULT_KEY] are None.
+pub fn read_classic(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    blocks: u16,
+) -> Result<Vec<Option<[u8; BLOCK_SIZE]>>> {
+    let span = trace_span!("mifare::read_classic", blocks);
+    let _enter = span.enter();
+
+    load_key(card, wbuf, rbuf, 0, &DEFAULT_KEY)?;
+    let mut out = vec![];
+    while out.len() < blocks.into() {
+        let (first, len) = sector(out.len() as u16);
+        let authed = [KeyType::A, KeyType::B].into_iter().any(|key_type| {
+            authenticate(card, wbuf, rbuf, first, key_type, 0)
+                .inspect_err(|err| debug!(first, ?key_type, ?err, "Couldn't authenticate"))
+                .is_ok()
+        });
+        for block in first..first + len {
+            let data = match authed {
+                true => Some(read_block(card, wbuf, rbuf, block)?),
+                false => None,
+            };
+            out.push(data);
+        }
+    }
+    Ok(out)
+}
+
+fn read_block(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    block: u16,
+) -> Result<[u8; BLOCK_SIZE]> {
+    let data = read(card, wbuf, rbuf, block, BLOCK_SIZE as u8)?;
+    data.try_into().map_err(|_| {
+        Error::Transport(
+            "mifare",
+            format!(
+                "block {} is {} bytes, not {}",
+                block,
+                data.len(),
+                BLOCK_SIZE
+            ),
+        )
+    })
+}
+
+/// How many pages a card has, from the data area size in its capability container; only
+/// for the ones that don't have a multiple of 4, since reading past the end of those wraps
+/// around to page 0 instead of failing.
+pub fn ultralight_pages(cc_size: u8) -> Option<usize> {
+    match cc_size {
+        0x10 => Some(41),  // Ultralight EV1 (MF0UL21)
+        0x12 => Some(45),  // NTAG213
+        0x3E => Some(135), // NTAG215
+        0x6D => Some(231), // NTAG216
+        _ => None,
+    }
+}
+
+/// Reads an Ultralight's pages, 4 at a time, until it won't give any more (an error, or
+/// nothing at all).
+pub fn read_ultralight(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Vec<[u8; PAGE_SIZE]>> {
+    let span = trace_span!("mifare::read_ultralight");
+    let _enter = span.enter();
+
+    let mut pages = vec![];
+    while pages.len() < MAX_PAGES.into() {
+        let data = match read(card, wbuf, rbuf, pages.len() as u16, BLOCK_SIZE as u8) {
+            Ok(data) => data,
+            Err(Error::APDU(..)) if !pages.is_empty() => break,
+            Err(err) => return Err(err),
+        };
+        if data.is_empty() {
+            break;
+        }
+        if data.len() % PAGE_SIZE != 0 {
+            return Err(Error::Transport(
+                "mifare",
+                format!(
+                    "page {} read is {} bytes, not a multiple of {}",
+                    pages.len(),
+                    data.len(),
+                    PAGE_SIZE
+                ),
+            ));
+        }
+        pages.extend(
+            data.chunks_exact(PAGE_SIZE)
+                .map(|p| <[u8; PAGE_SIZE]>::try_from(p).unwrap()),
+        );
+    }
+    // Page 3 is the capability container.
+    if let Some(total) = (pages.get(3))
+        .filter(|cc| cc[0] == 0xE1)
+        .and_then(|cc| ultralight_pages(cc[2]))
+    {
+        debug!(total, "Capability container says");
+        pages.truncate(total);
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A MIFARE Ultralight that wraps around after its last page, like an NTAG does.
+    struct Ultralight(Vec<[u8; 4]>);
+
+    /// A reader that says 9000 to everything, with the same (wrong) data every time.
+    struct Short(Vec<u8>);
+
+    impl CardTransport for Short {
+        fn transmit<'r>(&mut self, _capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp = [&self.0[..], &[0x90, 0x00]].concat();
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    impl CardTransport for Ultralight {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let n = self.0.len();
+            let mut rsp: Vec<u8> = match capdu {
+                [0xFF, 0xB0, 0x00, page, 0x10] if usize::from(*page) < n => {
+                    let page = usize::from(*page);
+                    (page..page + 4).flat_map(|p| self.0[p % n]).collect()
+                }
+                _ => vec![],
+            };
+            rsp.extend(match rsp.is_empty() {
+                true => [0x6A, 0x82],
+                false => [0x90, 0x00],
+            });
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_read_ultralight() {
+        let (mut wbuf, mut rbuf) = ([0; 256], [0; 256]);
+
+        // An original Ultralight, with 16 pages.
+        let mut card = Ultralight((0..16).map(|p| [p; 4]).collect());
+        let pages = read_ultralight(&mut card, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(pages, card.0);
+
+        // An NTAG213 has 45, so the last read wraps around.
+        card.0 = (0..45).map(|p| [p; 4]).collect();
+        card.0[3] = [0xE1, 0x10, 0x12, 0x00];
+        let pages = read_ultralight(&mut card, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(pages, card.0);
+    }
+
+    #[test]
+    fn test_read_ultralight_short() {
+        let (mut wbuf, mut rbuf) = ([0; 256], [0; 256]);
+
+        // Nothing at all is the end, rather than a reason to ask again forever.
+        let pages = read_ultralight(&mut Short(vec![]), &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(pages, Vec::<[u8; 4]>::new());
+
+        // Part of a page is an error, rather than something to quietly drop.
+        for len in [3, 6] {
+            let mut card = Short(vec![0x01; len]);
+            let err = read_ultralight(&mut card, &mut wbuf, &mut rbuf).unwrap_err();
+            assert!(matches!(err, Error::Transport("mifare", _)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_sector() {
+        assert_eq!(sector(0), (0, 4));
+        assert_eq!(sector(7), (4, 4));
+        assert_eq!(sector(127), (124, 4));
+        assert_eq!(sector(128), (128, 16));
+        assert_eq!(sector(255), (240, 16));
+    }
+}
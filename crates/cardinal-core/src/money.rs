@@ -0,0 +1,308 @@
+//! Money amounts, and the ISO 4217 currency table needed to print them.
+//!
+//! Cards store amounts as plain integers in the currency's minor unit (eg. pence, cents),
+//! except when the currency doesn't have one (eg. yen); ISO 4217 tells us which is which.
+
+use alloc::string::ToString;
+use num_enum::{FromPrimitive, IntoPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// ISO 4217 currency, by numeric code (which is what EMV tag 5F2A and friends use).
+/// This is not the full table, just the ones you're likely to find on a card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromPrimitive, IntoPrimitive)]
+#[repr(u16)]
+pub enum Currency {
+    AUD = 36,
+    CAD = 124,
+    CNY = 156,
+    CZK = 203,
+    DKK = 208,
+    HKD = 344,
+    HUF = 348,
+    ISK = 352,
+    INR = 356,
+    JPY = 392,
+    KRW = 410,
+    KWD = 414,
+    NOK = 578,
+    NZD = 554,
+    SGD = 702,
+    ZAR = 710,
+    SEK = 752,
+    CHF = 756,
+    THB = 764,
+    GBP = 826,
+    USD = 840,
+    TWD = 901,
+    TRY = 949,
+    EUR = 978,
+    PLN = 985,
+    BRL = 986,
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl Currency {
+    /// Parses a currency from EMV-style BCD, eg. `[0x03, 0x92]` for JPY.
+    pub fn from_bcd(v: [u8; 2]) -> Self {
+        let digit = |n: u8| u16::from(n.min(9));
+        Self::from(digit(v[0] & 0x0F) * 100 + digit(v[1] >> 4) * 10 + digit(v[1] & 0x0F))
+    }
+
+    /// ISO 4217 alphabetic code, eg. "JPY".
+    pub fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::AUD => "AUD",
+            Self::CAD => "CAD",
+            Self::CNY => "CNY",
+            Self::CZK => "CZK",
+            Self::DKK => "DKK",
+            Self::HKD => "HKD",
+            Self::HUF => "HUF",
+            Self::ISK => "ISK",
+            Self::INR => "INR",
+            Self::JPY => "JPY",
+            Self::KRW => "KRW",
+            Self::KWD => "KWD",
+            Self::NOK => "NOK",
+            Self::NZD => "NZD",
+            Self::SGD => "SGD",
+            Self::ZAR => "ZAR",
+            Self::SEK => "SEK",
+            Self::CHF => "CHF",
+            Self::THB => "THB",
+            Self::GBP => "GBP",
+            Self::USD => "USD",
+            Self::TWD => "TWD",
+            Self::TRY => "TRY",
+            Self::EUR => "EUR",
+            Self::PLN => "PLN",
+            Self::BRL => "BRL",
+            Self::Unknown(_) => return None,
+        })
+    }
+
+    /// Looks up a currency by its alphabetic code (case doesn't matter); only the ones
+    /// [Currency::code] knows.
+    pub fn from_code(code: &str) -> Option<Self> {
+        (0..1000)
+            .map(Self::from)
+            .find(|c| c.code().is_some_and(|c| c.eq_ignore_ascii_case(code)))
+    }
+
+    /// Number of decimals in the minor unit, eg. 2 for GBP (pence), 0 for JPY (no sen).
+    /// For currencies we don't know, 2 is the least wrong guess.
+    pub fn minor_units(&self) -> u8 {
+        match self {
+            Self::JPY | Self::KRW | Self::ISK => 0,
+            Self::KWD => 3,
+            _ => 2,
+        }
+    }
+
+    /// Symbol to put in front of formatted amounts, if there's a well-known one.
+    fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Self::JPY | Self::CNY => Some("¥"),
+            Self::GBP => Some("£"),
+            Self::USD => Some("$"),
+            Self::HKD => Some("HK$"),
+            Self::EUR => Some("€"),
+            Self::KRW => Some("₩"),
+            Self::INR => Some("₹"),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Currency {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.code() {
+            Some(code) => write!(f, "{}", code),
+            None => write!(f, "{:03}", u16::from(*self)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Either an alphabetic code ("GBP") or a number (826, or "826" the way unknown ones are
+/// written out), for config files.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum CodeOrNumber {
+            Code(alloc::string::String),
+            Number(u16),
+        }
+        match CodeOrNumber::deserialize(d)? {
+            CodeOrNumber::Code(code) => match code.parse::<u16>() {
+                Ok(n) => Ok(Self::from(n)),
+                Err(_) => Self::from_code(&code).ok_or_else(|| {
+                    serde::de::Error::custom(alloc::format!("unknown currency: {}", code))
+                }),
+            },
+            CodeOrNumber::Number(n) => Ok(Self::from(n)),
+        }
+    }
+}
+
+/// An amount of money, in the currency's minor unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    pub value: i64,
+    pub currency: Currency,
+}
+
+impl Amount {
+    pub fn new(value: i64, currency: Currency) -> Self {
+        Self { value, currency }
+    }
+}
+
+impl core::fmt::Display for Amount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let value = self.value.unsigned_abs();
+        let exp = u32::from(self.currency.minor_units());
+        let (major, minor) = (value / 10u64.pow(exp), value % 10u64.pow(exp));
+
+        write!(f, "{}", sign)?;
+        if let Some(symbol) = self.currency.symbol() {
+            write!(f, "{}", symbol)?;
+        }
+        match f.alternate() {
+            // {:#} groups thousands, eg. ¥2,329.
+            true => {
+                let digits = major.to_string();
+                for (i, c) in digits.chars().enumerate() {
+                    if i > 0 && (digits.len() - i) % 3 == 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+            }
+            false => write!(f, "{}", major)?,
+        }
+        if exp > 0 {
+            write!(f, ".{:0width$}", minor, width = exp as usize)?;
+        }
+        if self.currency.symbol().is_none() {
+            write!(f, " {}", self.currency)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Amount {
+    // Both the raw value (for scripts) and the formatted one (for humans reading JSON).
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("Amount", 3)?;
+        s.serialize_field("value", &self.value)?;
+        s.serialize_field("currency", &self.currency)?;
+        s.serialize_field("formatted", &self.to_string())?;
+        s.end()
+    }
+}
+
+/// Reads back what [Amount]'s Serialize writes; `formatted` is ignored.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            value: i64,
+            currency: Currency,
+        }
+        let Raw { value, currency } = Raw::deserialize(d)?;
+        Ok(Self::new(value, currency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_currency_from_bcd() {
+        assert_eq!(Currency::from_bcd([0x03, 0x92]), Currency::JPY);
+        assert_eq!(Currency::from_bcd([0x08, 0x26]), Currency::GBP);
+        assert_eq!(Currency::from_bcd([0x09, 0x99]), Currency::Unknown(999));
+    }
+
+    #[test]
+    fn test_currency_from_code() {
+        assert_eq!(Currency::from_code("JPY"), Some(Currency::JPY));
+        assert_eq!(Currency::from_code("gbp"), Some(Currency::GBP));
+        assert_eq!(Currency::from_code("XXX"), None);
+    }
+
+    #[test]
+    fn test_amount_display() {
+        assert_eq!(Amount::new(850, Currency::JPY).to_string(), "¥850");
+        assert_eq!(Amount::new(1234, Currency::GBP).to_string(), "£12.34");
+        assert_eq!(Amount::new(-5, Currency::EUR).to_string(), "-€0.05");
+        assert_eq!(Amount::new(1500, Currency::SEK).to_string(), "15.00 SEK");
+        assert_eq!(Amount::new(12345, Currency::KWD).to_string(), "12.345 KWD");
+        assert_eq!(
+            Amount::new(100, Currency::Unknown(999)).to_string(),
+            "1.00 999"
+        );
+        assert_eq!(format!("{:#}", Amount::new(2329, Currency::JPY)), "¥2,329");
+        assert_eq!(
+            format!("{:#}", Amount::new(-123456789, Currency::GBP)),
+            "-£1,234,567.89"
+        );
+        assert_eq!(format!("{:#}", Amount::new(999, Currency::JPY)), "¥999");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_amount_serialize() {
+        assert_eq!(
+            serde_json::to_value(Amount::new(2329, Currency::JPY)).unwrap(),
+            serde_json::json!({ "value": 2329, "currency": "JPY", "formatted": "¥2329" }),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_amount_deserialize() {
+        for amount in [
+            Amount::new(2329, Currency::JPY),
+            Amount::new(-5, Currency::EUR),
+            Amount::new(100, Currency::Unknown(999)),
+        ] {
+            let json = serde_json::to_value(amount).unwrap();
+            assert_eq!(serde_json::from_value::<Amount>(json).unwrap(), amount);
+        }
+        assert_eq!(
+            serde_json::from_str::<Amount>(r#"{"value": 1234, "currency": 826}"#).unwrap(),
+            Amount::new(1234, Currency::GBP),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_currency_deserialize() {
+        let parse = |v| serde_json::from_value::<Currency>(v);
+        assert_eq!(parse(serde_json::json!("EUR")).unwrap(), Currency::EUR);
+        assert_eq!(parse(serde_json::json!(978)).unwrap(), Currency::EUR);
+        assert_eq!(
+            parse(serde_json::json!(999)).unwrap(),
+            Currency::Unknown(999)
+        );
+        assert!(parse(serde_json::json!("Euros")).is_err());
+    }
+}
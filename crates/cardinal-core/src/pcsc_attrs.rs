@@ -0,0 +1,329 @@
+//! Making sense of PCSC reader attributes (SCardGetAttrib).
+//!
+//! PC/SC Part 3 says what's in the standard ones: strings, DWORDs (in the host's byte
+//! order, which is little-endian everywhere we run), and a few bitmasks and enums. Anything
+//! we don't know, or that doesn't look like it should, is best left as raw bytes.
+//!
+//! Attributes are looked up by name (what `pcsc::Attribute`'s Debug impl says, eg.
+//! "VendorName"), since that's what goes in probe reports, and old ones can be decoded
+//! without a reader to hand.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// A decoded attribute value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum AttrValue {
+    Text(String),
+    Integer(u64),
+    Bool(bool),
+    /// Clock rates are in kHz.
+    Kilohertz(u64),
+    /// Data rates are in bits per second.
+    BitsPerSecond(u64),
+    /// Vendor firmware version, from `0xMMmmbbbb`.
+    Version {
+        major: u8,
+        minor: u8,
+        build: u16,
+    },
+    /// Protocols, eg. "T=0", "T=1".
+    Protocols(Vec<String>),
+    /// What the reader is plugged into: the type ("USB", etc) and its number there.
+    Channel {
+        kind: String,
+        number: u16,
+    },
+    /// Mechanical features, eg. "swallows cards".
+    Characteristics(Vec<String>),
+    IccPresence(IccPresence),
+}
+
+/// Whether there's a card in the reader (SCARD_ATTR_ICC_PRESENCE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum IccPresence {
+    NotPresent,
+    /// Present, but not swallowed (for readers that do that).
+    Present,
+    Swallowed,
+    Confiscated,
+    Unknown(u8),
+}
+
+impl From<u8> for IccPresence {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::NotPresent,
+            1 => Self::Present,
+            2 => Self::Swallowed,
+            4 => Self::Confiscated,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+impl AttrValue {
+    /// Decodes an attribute, by name; None if we don't know it, or it doesn't look right.
+    pub fn decode(name: &str, raw: &[u8]) -> Option<Self> {
+        Some(match name {
+            "VendorName" | "VendorIfdType" | "VendorIfdSerialNo" | "DeviceFriendlyName"
+            | "DeviceSystemName" => Self::Text(text(raw)?),
+            "VendorIfdVersion" => {
+                let v = u32::try_from(dword(raw)?).ok()?;
+                Self::Version {
+                    major: (v >> 24) as u8,
+                    minor: (v >> 16) as u8,
+                    build: v as u16,
+                }
+            }
+            "ChannelId" => {
+                let v = dword(raw)?;
+                let kind = match (v >> 16) & 0xFFFF {
+                    0x01 => "serial".to_owned(),
+                    0x02 => "parallel".to_owned(),
+                    0x04 => "PS/2".to_owned(),
+                    0x08 => "SCSI".to_owned(),
+                    0x10 => "IDE".to_owned(),
+                    0x20 => "USB".to_owned(),
+                    kind @ 0xF0..=0xFF => format!("vendor ({:02X})", kind & 0x0F),
+                    kind => format!("unknown ({:04X})", kind),
+                };
+                Self::Channel {
+                    kind,
+                    number: v as u16,
+                }
+            }
+            "AsyncProtocolTypes" | "CurrentProtocolType" => Self::Protocols(protocols(dword(raw)?)),
+            "DefaultClk" | "MaxClk" | "CurrentClk" => Self::Kilohertz(dword(raw)?),
+            "DefaultDataRate" | "MaxDataRate" => Self::BitsPerSecond(dword(raw)?),
+            "MaxIfsd" | "CurrentF" | "CurrentD" | "CurrentN" | "CurrentW" | "CurrentIfsc"
+            | "CurrentIfsd" | "CurrentBwt" | "CurrentCwt" | "ExtendedBwt" | "Maxinput"
+            | "DeviceUnit" | "IccTypePerAtr" => Self::Integer(dword(raw)?),
+            "PowerMgmtSupport"
+            | "UserToCardAuthDevice"
+            | "UserAuthInputDevice"
+            | "IccInterfaceStatus"
+            | "DeviceInUse"
+            | "SupressT1IfsRequest" => Self::Bool(dword(raw)? != 0),
+            "Characteristics" => {
+                let v = dword(raw)?;
+                let names = ["swallows cards", "ejects cards", "captures cards"];
+                Self::Characteristics(
+                    (names.iter().enumerate())
+                        .filter(|(i, _)| v & (1 << i) != 0)
+                        .map(|(_, name)| name.to_string())
+                        .collect(),
+                )
+            }
+            "IccPresence" => Self::IccPresence(u8::try_from(dword(raw)?).ok()?.into()),
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(v) => write!(f, "{:?}", v),
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Bool(v) => write!(f, "{}", if *v { "yes" } else { "no" }),
+            Self::Kilohertz(v) => write!(f, "{} kHz", v),
+            Self::BitsPerSecond(v) => write!(f, "{} bps", v),
+            Self::Version {
+                major,
+                minor,
+                build,
+            } => write!(f, "{}.{}.{}", major, minor, build),
+            Self::Protocols(v) | Self::Characteristics(v) if v.is_empty() => write!(f, "none"),
+            Self::Protocols(v) | Self::Characteristics(v) => write!(f, "{}", v.join(", ")),
+            Self::Channel { kind, number } => write!(f, "{} #{}", kind, number),
+            Self::IccPresence(IccPresence::NotPresent) => write!(f, "no card"),
+            Self::IccPresence(IccPresence::Present) => write!(f, "card present"),
+            Self::IccPresence(IccPresence::Swallowed) => write!(f, "card swallowed"),
+            Self::IccPresence(IccPresence::Confiscated) => write!(f, "card confiscated"),
+            Self::IccPresence(IccPresence::Unknown(v)) => write!(f, "unknown ({})", v),
+        }
+    }
+}
+
+/// What the reader and the card settled on, from the Current* attributes: for a contact
+/// card, what PPS negotiated (or the ATR's defaults, if nothing was). ISO 7816-3.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Link {
+    /// CurrentProtocolType, eg. "T=1".
+    pub protocol: Option<String>,
+    /// CurrentClk, in kHz.
+    pub clock: Option<u64>,
+    /// CurrentF: clock rate conversion integer (Fi).
+    pub f: Option<u64>,
+    /// CurrentD: baud rate adjustment integer (Di).
+    pub d: Option<u64>,
+    /// CurrentN: extra guard time, in ETUs.
+    pub n: Option<u64>,
+    /// CurrentW: work waiting time integer, for T=0.
+    pub w: Option<u64>,
+    /// CurrentIfsc: the biggest block the card takes, for T=1.
+    pub ifsc: Option<u64>,
+    /// CurrentIfsd: the biggest block the reader takes, for T=1.
+    pub ifsd: Option<u64>,
+    /// CurrentBwt: block waiting time integer, for T=1.
+    pub bwt: Option<u64>,
+    /// CurrentCwt: character waiting time integer, for T=1.
+    pub cwt: Option<u64>,
+    /// CurrentEbcEncoding: T=1's error detection code, "LRC" or "CRC".
+    pub edc: Option<&'static str>,
+}
+
+impl Link {
+    /// Picks the Current* attributes out of `attrs`, as (name, raw value); None if there
+    /// aren't any, which is normal for contactless readers.
+    pub fn from_attributes<'a>(
+        attrs: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) -> Option<Self> {
+        let mut link = Self::default();
+        for (name, raw) in attrs {
+            match name {
+                "CurrentProtocolType" => {
+                    link.protocol = dword(raw).map(|v| protocols(v).join(", "))
+                }
+                "CurrentClk" => link.clock = dword(raw),
+                "CurrentF" => link.f = dword(raw),
+                "CurrentD" => link.d = dword(raw),
+                "CurrentN" => link.n = dword(raw),
+                "CurrentW" => link.w = dword(raw),
+                "CurrentIfsc" => link.ifsc = dword(raw),
+                "CurrentIfsd" => link.ifsd = dword(raw),
+                "CurrentBwt" => link.bwt = dword(raw),
+                "CurrentCwt" => link.cwt = dword(raw),
+                "CurrentEbcEncoding" => {
+                    link.edc = match dword(raw) {
+                        Some(0) => Some("LRC"),
+                        Some(1) => Some("CRC"),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        (link != Self::default()).then_some(link)
+    }
+
+    /// Bits per second on the wire: the clock, divided by F/D clocks per bit.
+    pub fn bit_rate(&self) -> Option<u64> {
+        let (clock, f, d) = (self.clock?, self.f?, self.d?);
+        (f != 0).then(|| clock * 1000 * d / f)
+    }
+}
+
+/// A NUL-terminated (or not) UTF-8 string.
+fn text(raw: &[u8]) -> Option<String> {
+    let s = std::str::from_utf8(raw).ok()?;
+    Some(s.trim_end_matches('\0').to_owned())
+}
+
+/// A little-endian integer; drivers aren't consistent about how wide (pcsc-lite's CCID
+/// driver hands out single bytes for some DWORDs), so anything up to 8 bytes goes.
+fn dword(raw: &[u8]) -> Option<u64> {
+    if raw.is_empty() || raw.len() > 8 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    buf[..raw.len()].copy_from_slice(raw);
+    Some(u64::from_le_bytes(buf))
+}
+
+/// Bit n is T=n; Windows puts raw mode in bit 16, pcsc-lite in bit 2, where T=2 would be,
+/// but nobody does T=2.
+fn protocols(v: u64) -> Vec<String> {
+    (0..=16)
+        .filter(|n| v & (1 << n) != 0)
+        .map(|n| match n {
+            2 | 16 => "raw".to_owned(),
+            n => format!("T={}", n),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            AttrValue::decode("VendorName", b"ACS\0"),
+            Some(AttrValue::Text("ACS".to_owned()))
+        );
+        assert_eq!(
+            AttrValue::decode("VendorIfdVersion", &[0x00, 0x00, 0x02, 0x04]),
+            Some(AttrValue::Version {
+                major: 4,
+                minor: 2,
+                build: 0
+            })
+        );
+        assert_eq!(
+            AttrValue::decode("DefaultClk", &[0xA0, 0x0F, 0x00, 0x00]),
+            Some(AttrValue::Kilohertz(4000))
+        );
+        assert_eq!(
+            AttrValue::decode("MaxDataRate", &[0x00, 0xA6, 0x0E, 0x00]).map(|v| v.to_string()),
+            Some("960000 bps".to_owned())
+        );
+        assert_eq!(
+            AttrValue::decode("AsyncProtocolTypes", &[0x03, 0x00, 0x00, 0x00]),
+            Some(AttrValue::Protocols(vec!["T=0".into(), "T=1".into()]))
+        );
+        assert_eq!(
+            AttrValue::decode("ChannelId", &[0x05, 0x01, 0x20, 0x00]).map(|v| v.to_string()),
+            Some("USB #261".to_owned())
+        );
+        assert_eq!(
+            AttrValue::decode("IccPresence", &[0x02]),
+            Some(AttrValue::IccPresence(IccPresence::Swallowed))
+        );
+        assert_eq!(
+            AttrValue::decode("IccInterfaceStatus", &[0x01]),
+            Some(AttrValue::Bool(true))
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        // An ACR39U, with a T=1 card that took a PPS up to Fi=512, Di=8.
+        let attrs: &[(&str, &[u8])] = &[
+            ("VendorName", b"ACS\0"),
+            ("CurrentProtocolType", &[0x02, 0x00, 0x00, 0x00]),
+            ("CurrentClk", &[0xA0, 0x0F, 0x00, 0x00]),
+            ("CurrentF", &[0x00, 0x02, 0x00, 0x00]),
+            ("CurrentD", &[0x08, 0x00, 0x00, 0x00]),
+            ("CurrentIfsc", &[0xFE]),
+            ("CurrentIfsd", &[0xFE]),
+            ("CurrentEbcEncoding", &[0x00]),
+        ];
+        let link = Link::from_attributes(attrs.iter().copied()).unwrap();
+        assert_eq!(link.protocol.as_deref(), Some("T=1"));
+        assert_eq!((link.f, link.d), (Some(512), Some(8)));
+        assert_eq!((link.ifsc, link.ifsd), (Some(254), Some(254)));
+        assert_eq!(link.edc, Some("LRC"));
+        assert_eq!(link.bit_rate(), Some(62500));
+
+        // Nothing current, or nothing that makes sense.
+        assert_eq!(Link::from_attributes(attrs[..1].iter().copied()), None);
+        assert_eq!(Link::from_attributes([("CurrentF", &[][..])]), None);
+        assert_eq!(Link { f: Some(0), ..link }.bit_rate(), None);
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        // Unknown, or not what it says on the tin: leave it raw.
+        assert_eq!(AttrValue::decode("AtrString", &[0x3B, 0x00]), None);
+        assert_eq!(AttrValue::decode("VendorName", &[0xFF, 0xFE]), None);
+        assert_eq!(AttrValue::decode("DefaultClk", &[]), None);
+        assert_eq!(AttrValue::decode("MaxClk", &[0; 9]), None);
+        assert_eq!(AttrValue::decode("IccPresence", &[0, 1, 0, 0]), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Transmission protocol (T=0 vs T=1) handling for APDUs.
+//!
+//! ISO 7816-3 says a command APDU comes in one of four cases, depending on whether it has
+//! command data and/or expects response data. T=1 just sends the whole thing, but T=0 can't
+//! send data and get data back in one exchange: the card replies 61xx instead, and you have
+//! to fetch the response with GET RESPONSE. It can also reply 6Cxx if you asked for the
+//! wrong amount of data. Most readers paper over this, but not all of them.
+
+use crate::secret;
+use crate::transport::CardTransport;
+use crate::{Error, Result};
+use tracing::{debug, trace, trace_span};
+
+/// Transmission protocol, as far as APDU encoding is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Character-oriented; needs GET RESPONSE to read data after sending data.
+    T0,
+    /// Block-oriented; APDUs go through as they are. (Contactless cards behave like this.)
+    T1,
+}
+
+#[cfg(feature = "hardware")]
+impl Protocol {
+    /// Asks PCSC which protocol the card is using. Anything that isn't T=0 acts like T=1.
+    pub fn detect(card: &pcsc::Card) -> Result<Self> {
+        Ok(match card.status2_owned()?.protocol2() {
+            Some(pcsc::Protocol::T0) => Self::T0,
+            _ => Self::T1,
+        })
+    }
+}
+
+/// ISO 7816-3 command APDU case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// No command data, no response data.
+    Case1,
+    /// No command data, response data (Le).
+    Case2,
+    /// Command data (Lc), no response data.
+    Case3,
+    /// Command data (Lc) and response data (Le).
+    Case4,
+}
+
+impl Case {
+    /// Figures out the case of an encoded (short) command APDU.
+    pub fn of(req: &[u8]) -> Option<Self> {
+        match req.len() {
+            4 => Some(Self::Case1),
+            5 => Some(Self::Case2),
+            n if n > 5 && n == 5 + req[4] as usize => Some(Self::Case3),
+            n if n > 6 && n == 6 + req[4] as usize => Some(Self::Case4),
+            _ => None,
+        }
+    }
+
+    /// Whether the APDU ends with an Le byte.
+    pub fn has_le(&self) -> bool {
+        matches!(self, Self::Case2 | Self::Case4)
+    }
+}
+
+/// Returns the APDU as it should go over the wire for the given protocol; for T=0, that
+/// means a case 4 APDU loses its Le, and we get the response later.
+pub fn encode_for(protocol: Protocol, req: &[u8]) -> &[u8] {
+    match (protocol, Case::of(req)) {
+        (Protocol::T0, Some(Case::Case4)) => &req[..req.len() - 1],
+        _ => req,
+    }
+}
+
+/// Most GET RESPONSEs to send for one command; every one that isn't the last should bring
+/// back at least a byte, so anything past this is a card (or relay) stringing us along.
+pub const MAX_GET_RESPONSES: usize = crate::MAX_BUFFER_SIZE / 2;
+
+/// Transmits an APDU, and returns the response (including SW1-SW2). On T=0, this chases
+/// 61xx with GET RESPONSE and retries 6Cxx with the right Le, so you get the same response
+/// you would've gotten over T=1.
+pub fn transmit<'r>(
+    card: &mut impl CardTransport,
+    req: &[u8],
+    rbuf: &'r mut [u8],
+) -> Result<&'r [u8]> {
+    // Pseudo-APDUs are for the reader, not the card, so the card's protocol doesn't apply.
+    let protocol = if req.first() == Some(&0xFF) {
+        Protocol::T1
+    } else {
+        card.protocol()
+    };
+    let span = trace_span!("transmit", ?protocol);
+    let _enter = span.enter();
+
+    let wire = encode_for(protocol, req);
+    trace!(req = format!("{:02X?}", wire), ">> TPDU");
+    let mut len = card.transmit(wire, rbuf)?.len();
+
+    // Wrong Le; the card tells us what it should've been, so ask again.
+    let sent_le = Case::of(wire).is_some_and(|c| c.has_le());
+    if len == 2 && rbuf[0] == 0x6C && sent_le {
+        debug!(le = rbuf[1], "Wrong Le, retrying");
+        let mut retry = wire.to_vec();
+        *retry.last_mut().unwrap() = rbuf[1];
+        let res = card.transmit(&retry, rbuf).map(|rsp| rsp.len());
+        secret::scrub(&mut retry);
+        len = res?;
+    }
+    // PC/SC never hands back less, but a relay or an emulated card could.
+    if len < 2 {
+        return Err(short_response());
+    }
+
+    // More data waiting; append it to what we have, replacing the 61xx. Readers that wrap
+    // frames in pseudo-APDUs (eg. FeliCa on CCID readers) want theirs fetched with FF C0.
+    let get_response_cla = if req.first() == Some(&0xFF) {
+        0xFF
+    } else {
+        0x00
+    };
+    let mut fetches = 0;
+    while len >= 2 && rbuf[len - 2] == 0x61 {
+        fetches += 1;
+        if fetches > MAX_GET_RESPONSES {
+            return Err(Error::Transport(
+                "apdu",
+                format!("still more data after {} GET RESPONSEs", MAX_GET_RESPONSES),
+            ));
+        }
+        let le = rbuf[len - 1];
+        debug!(le, "Fetching remaining response data");
+        let mut tmp = [0u8; 258];
+        let rsp_len = card
+            .transmit(&[get_response_cla, 0xC0, 0x00, 0x00, le], &mut tmp)?
+            .len();
+        // Otherwise the next SW1 we look at would be a data byte from before.
+        if rsp_len < 2 {
+            secret::scrub(&mut tmp);
+            return Err(short_response());
+        }
+        let start = len - 2;
+        if start + rsp_len > rbuf.len() {
+            secret::scrub(&mut tmp);
+            return Err(Error::InsufficientBuffer);
+        }
+        rbuf[start..start + rsp_len].copy_from_slice(&tmp[..rsp_len]);
+        // Whatever it was, the caller can scrub it out of rbuf; not out of here.
+        secret::scrub(&mut tmp[..rsp_len]);
+        len = start + rsp_len;
+    }
+
+    Ok(&rbuf[..len])
+}
+
+/// For a response that doesn't even have a status word.
+pub(crate) fn short_response() -> Error {
+    Error::Transport("apdu", "response shorter than a status word".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_of() {
+        assert_eq!(Case::of(&[0x00, 0xA4, 0x04, 0x00]), Some(Case::Case1));
+        assert_eq!(Case::of(&[0x00, 0xB2, 0x01, 0x0C, 0x00]), Some(Case::Case2));
+        assert_eq!(
+            Case::of(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00]),
+            Some(Case::Case3)
+        );
+        assert_eq!(
+            Case::of(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00, 0x00]),
+            Some(Case::Case4)
+        );
+        assert_eq!(Case::of(&[0x00, 0xA4, 0x04, 0x00, 0x05, 0x3F]), None);
+    }
+
+    #[test]
+    fn test_encode_for() {
+        let case4 = [0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00, 0x00];
+        assert_eq!(encode_for(Protocol::T1, &case4), &case4);
+        assert_eq!(encode_for(Protocol::T0, &case4), &case4[..7]);
+
+        let case2 = [0x00, 0xB2, 0x01, 0x0C, 0x00];
+        assert_eq!(encode_for(Protocol::T0, &case2), &case2);
+    }
+
+    /// Hands out a response in 61xx-sized pieces, and remembers what it was sent.
+    struct Chunked(Vec<u8>, Vec<Vec<u8>>);
+
+    impl CardTransport for Chunked {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.1.push(capdu.to_vec());
+            let n = self.0.len().min(4);
+            let chunk = self.0.drain(..n).collect::<Vec<_>>();
+            let sw = match self.0.len() {
+                0 => [0x90, 0x00],
+                left => [0x61, left as u8],
+            };
+            rbuf[..n].copy_from_slice(&chunk);
+            rbuf[n..n + 2].copy_from_slice(&sw);
+            Ok(&rbuf[..n + 2])
+        }
+    }
+
+    #[test]
+    fn test_transmit_get_response() {
+        let mut card = Chunked((1..=10).collect(), vec![]);
+        let mut rbuf = [0; 16];
+        let rsp = transmit(&mut card, &[0xFF, 0x00, 0x00, 0x00, 0x01, 0x01], &mut rbuf).unwrap();
+        assert_eq!(rsp, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0x90, 0x00]);
+        assert_eq!(card.1[1], &[0xFF, 0xC0, 0x00, 0x00, 0x06]);
+        assert_eq!(card.1[2], &[0xFF, 0xC0, 0x00, 0x00, 0x02]);
+
+        // Doesn't fit; say so, rather than handing back half of it.
+        let mut card = Chunked((1..=10).collect(), vec![]);
+        let err = transmit(&mut card, &[0x00, 0xB0, 0x00, 0x00, 0x00], &mut [0; 8]).unwrap_err();
+        assert!(matches!(err, Error::InsufficientBuffer));
+        assert_eq!(card.1[1], &[0x00, 0xC0, 0x00, 0x00, 0x06]);
+    }
+
+    /// Answers everything with the same thing, however little of it there is.
+    struct Fixed(&'static [u8]);
+
+    impl CardTransport for Fixed {
+        fn transmit<'r>(&mut self, _capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            rbuf[..self.0.len()].copy_from_slice(self.0);
+            Ok(&rbuf[..self.0.len()])
+        }
+    }
+
+    #[test]
+    fn test_transmit_short_response() {
+        for rsp in [&[][..], &[0x90]] {
+            let err = transmit(
+                &mut Fixed(rsp),
+                &[0x00, 0xB0, 0x00, 0x00, 0x00],
+                &mut [0; 8],
+            )
+            .unwrap_err();
+            assert!(matches!(err, Error::Transport("apdu", _)), "{:?}", err);
+        }
+    }
+
+    /// Says there's more, the first time, and then sends back one byte.
+    struct Stingy(usize);
+
+    impl CardTransport for Stingy {
+        fn transmit<'r>(&mut self, _capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.0 += 1;
+            let rsp: &[u8] = match self.0 {
+                1 => &[0x01, 0x02, 0x61, 0x04],
+                _ => &[0x03],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_transmit_get_response_stalls() {
+        // Always more, never any of it.
+        let mut rbuf = [0; 16];
+        let err = transmit(
+            &mut Fixed(&[0x61, 0x00]),
+            &[0x00, 0xB0, 0x00, 0x00, 0x00],
+            &mut rbuf,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Transport("apdu", _)), "{:?}", err);
+
+        // A GET RESPONSE with no status word; 02 isn't SW1.
+        let err = transmit(&mut Stingy(0), &[0x00, 0xB0, 0x00, 0x00, 0x00], &mut rbuf).unwrap_err();
+        assert!(matches!(err, Error::Transport("apdu", _)), "{:?}", err);
+    }
+}
@@ -0,0 +1,196 @@
+//! What a reader can do, beyond plain APDUs.
+//!
+//! Talking to the reader itself (rather than the card) is done with pseudo-APDUs, and
+//! everybody has their own. `FF CA` (GET DATA, for the card's UID) is in PC/SC Part 3, so
+//! most contactless readers have it, but passing raw FeliCa frames through is all over
+//! the place: ACS readers take them wrapped in `FF 00 00 00`, Identiv wants a PC/SC
+//! transparent session (see [crate::transparent]), and contact-only readers can't at all.
+//!
+//! [ReaderQuirks::detect] works out which reader we're talking to from its PCSC attributes,
+//! and transports hand the result out with [CardTransport::reader_quirks] (see `Session`
+//! in cardinal-transports), so commands can use the right encapsulation straight away,
+//! instead of trying one and falling back to the other. Readers we don't know get
+//! [ReaderQuirks::default], which does just that.
+//!
+//! [CardTransport::reader_quirks]: crate::CardTransport::reader_quirks
+
+use crate::CardTransport;
+use tracing::{debug, trace_span};
+
+/// How to send raw FeliCa frames through a reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FelicaPassthrough {
+    /// Don't know; try [FelicaPassthrough::Wrapper], then [FelicaPassthrough::Transparent].
+    #[default]
+    Auto,
+    /// ACS-style: the frame, wrapped in an `FF 00 00 00` pseudo-APDU.
+    Wrapper,
+    /// A one-off PC/SC Part 3 transparent session (`FF C2`).
+    Transparent,
+    /// Can't; it's a contact-only reader.
+    Unsupported,
+}
+
+/// How to ask a reader for the card's UID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UidMethod {
+    /// PC/SC Part 3 GET DATA (`FF CA 00 00`).
+    #[default]
+    GetData,
+    /// There isn't one; contact cards don't have UIDs, so contact-only readers don't ask.
+    None,
+}
+
+/// What a reader can do.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ReaderQuirks {
+    /// Vendor (eg. "ACS"), as the reader reports it.
+    pub vendor: String,
+    /// Model (eg. "ACR1252U"): its IFD type if it reports one, otherwise its name.
+    pub model: String,
+    pub felica: FelicaPassthrough,
+    /// Takes ACS-style escape pseudo-APDUs (`FF 00 40 ..` and friends), for the LED,
+    /// buzzer, etc.
+    pub escape: bool,
+    pub uid: UidMethod,
+}
+
+impl ReaderQuirks {
+    /// Looks up a reader by its vendor and model, as reported by PCSC. Anything we don't
+    /// recognise gets the defaults.
+    pub fn for_reader(vendor: &str, model: &str) -> Self {
+        let (vendor_lc, model_lc) = (vendor.to_lowercase(), model.to_lowercase());
+        let mut quirks = Self {
+            vendor: vendor.to_owned(),
+            model: model.to_owned(),
+            ..Self::default()
+        };
+        let model_has = |names: &[&str]| names.iter().any(|name| model_lc.contains(name));
+
+        if vendor_lc.contains("acs") || vendor_lc.contains("advanced card") || model_has(&["acr"]) {
+            if model_has(&["acr38", "acr39", "acr3x"]) {
+                quirks.felica = FelicaPassthrough::Unsupported;
+                quirks.uid = UidMethod::None;
+            } else {
+                quirks.felica = FelicaPassthrough::Wrapper;
+                quirks.escape = true;
+            }
+        } else if vendor_lc.contains("identiv") || vendor_lc.contains("scm microsystems") {
+            // Their contactless readers are SCLxxxx, or end in "F" (eg. uTrust 3700 F).
+            if model_has(&["scl"]) || model_lc.trim_end().ends_with(" f") {
+                quirks.felica = FelicaPassthrough::Transparent;
+            } else {
+                quirks.felica = FelicaPassthrough::Unsupported;
+                quirks.uid = UidMethod::None;
+            }
+        }
+        quirks
+    }
+
+    /// Works out what the reader is from its PCSC attributes; None if it won't say (or
+    /// isn't a PCSC reader at all).
+    #[cfg(feature = "hardware")]
+    pub fn detect(card: &mut (impl CardTransport + ?Sized)) -> Option<Self> {
+        let span = trace_span!("ReaderQuirks::detect");
+        let _enter = span.enter();
+
+        let mut rbuf = [0u8; 256];
+        let mut attr = |attr| {
+            (card.get_attribute(attr, &mut rbuf).ok())
+                .map(|v| {
+                    String::from_utf8_lossy(v)
+                        .trim_end_matches('\0')
+                        .trim()
+                        .to_owned()
+                })
+                .filter(|v| !v.is_empty())
+        };
+        let vendor = attr(pcsc::Attribute::VendorName);
+        let model = (attr(pcsc::Attribute::VendorIfdType))
+            .or_else(|| attr(pcsc::Attribute::DeviceFriendlyName));
+        if vendor.is_none() && model.is_none() {
+            debug!("Reader won't say what it is");
+            return None;
+        }
+        let quirks = Self::for_reader(
+            vendor.as_deref().unwrap_or_default(),
+            model.as_deref().unwrap_or_default(),
+        );
+        debug!(?quirks, "Detected reader");
+        Some(quirks)
+    }
+
+    #[cfg(not(feature = "hardware"))]
+    pub fn detect(_card: &mut (impl CardTransport + ?Sized)) -> Option<Self> {
+        let span = trace_span!("ReaderQuirks::detect");
+        let _enter = span.enter();
+        debug!("Built without PCSC, so there's nothing to detect readers with");
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_reader() {
+        let acr1252 = ReaderQuirks::for_reader("ACS", "ACR1252U");
+        assert_eq!(acr1252.felica, FelicaPassthrough::Wrapper);
+        assert!(acr1252.escape);
+        assert_eq!(acr1252.uid, UidMethod::GetData);
+
+        let acr39 = ReaderQuirks::for_reader("ACS", "ACS ACR39U ICC Reader 01 00");
+        assert_eq!(acr39.felica, FelicaPassthrough::Unsupported);
+        assert_eq!(acr39.uid, UidMethod::None);
+
+        let utrust = ReaderQuirks::for_reader("Identiv", "uTrust 3700 F");
+        assert_eq!(utrust.felica, FelicaPassthrough::Transparent);
+        assert!(!utrust.escape);
+        let scr = ReaderQuirks::for_reader("SCM Microsystems Inc.", "SCR3310");
+        assert_eq!(scr.felica, FelicaPassthrough::Unsupported);
+
+        let unknown = ReaderQuirks::for_reader("Some Vendor", "Reader 9000");
+        assert_eq!(unknown.felica, FelicaPassthrough::Auto);
+        assert_eq!(unknown.model, "Reader 9000");
+    }
+
+    #[cfg(feature = "hardware")]
+    struct AttrCard(&'static [(pcsc::Attribute, &'static [u8])]);
+
+    #[cfg(feature = "hardware")]
+    impl CardTransport for AttrCard {
+        fn transmit<'r>(&mut self, _: &[u8], _: &'r mut [u8]) -> crate::Result<&'r [u8]> {
+            unimplemented!()
+        }
+
+        fn get_attribute<'r>(
+            &mut self,
+            attr: pcsc::Attribute,
+            rbuf: &'r mut [u8],
+        ) -> crate::Result<&'r [u8]> {
+            let (_, v) =
+                (self.0.iter().find(|(a, _)| *a == attr)).ok_or(pcsc::Error::UnsupportedFeature)?;
+            rbuf[..v.len()].copy_from_slice(v);
+            Ok(&rbuf[..v.len()])
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "hardware")]
+    fn test_detect() {
+        // pcsc-lite's CCID driver has no IFD type, so we fall back to the reader's name.
+        let mut card = AttrCard(&[
+            (pcsc::Attribute::VendorName, b"ACS\0"),
+            (
+                pcsc::Attribute::DeviceFriendlyName,
+                b"ACS ACR1252 Dual Reader [ACR1252 Dual Reader PICC] 00 00\0",
+            ),
+        ]);
+        let quirks = ReaderQuirks::detect(&mut card).unwrap();
+        assert_eq!(quirks.vendor, "ACS");
+        assert_eq!(quirks.felica, FelicaPassthrough::Wrapper);
+
+        assert_eq!(ReaderQuirks::detect(&mut AttrCard(&[])), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! How much a card will send back at once.
+//!
+//! A short APDU can ask for up to 256 bytes, and most cards are happy to send that many,
+//! but some (or the reader in front of them) want less, and answer Le=00 with "wrong
+//! length" (6700), with no hint of what'd be right. The ATR gives us a guess: a T=1 card's
+//! IFSC (TA3) is the biggest block it deals in, and cards that choke on Le=00 are usually
+//! the ones that can't chain a response over several. FeliCa cards have their own version
+//! of this: how many blocks one Read Without Encryption returns.
+//!
+//! Rather than every command finding out the hard way, what's been learnt goes to
+//! [CardTransport::learn_response_limits], and transports that keep it (see `Session` in
+//! cardinal-transports) hand it out again from [CardTransport::response_limits].
+//!
+//! [CardTransport::learn_response_limits]: crate::CardTransport::learn_response_limits
+//! [CardTransport::response_limits]: crate::CardTransport::response_limits
+
+use crate::atr::{self, ATR};
+use crate::CardTransport;
+use tracing::debug;
+
+/// Largest Le a short APDU can ask for.
+pub const MAX_SHORT_LE: u16 = 256;
+
+/// Smallest Le worth trying, before giving up on a card that won't say how much it wants.
+pub const MIN_LE: u16 = 16;
+
+/// What we know about how much a card sends back at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLimits {
+    /// Most data (not counting SW1-SW2) to ask for in one response.
+    pub max_le: u16,
+    /// Most FeliCa blocks to read in one command, if we've found out; see
+    /// [crate::felica::MAX_READ_BLOCKS].
+    pub felica_blocks: Option<usize>,
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        Self {
+            max_le: MAX_SHORT_LE,
+            felica_blocks: None,
+        }
+    }
+}
+
+impl ResponseLimits {
+    /// Remembers that the card won't send more than `le` bytes at once.
+    pub fn learn_le(card: &mut (impl CardTransport + ?Sized), le: u16) {
+        let limits = card.response_limits();
+        if le < limits.max_le {
+            debug!(le, "Card wants shorter responses");
+            card.learn_response_limits(Self {
+                max_le: le,
+                ..limits
+            });
+        }
+    }
+
+    /// Remembers that the card won't read more than `blocks` FeliCa blocks at once.
+    pub fn learn_felica_blocks(card: &mut (impl CardTransport + ?Sized), blocks: usize) {
+        let limits = card.response_limits();
+        if limits.felica_blocks.is_none_or(|max| blocks < max) {
+            debug!(blocks, "Card wants to read fewer blocks at once");
+            card.learn_response_limits(Self {
+                felica_blocks: Some(blocks),
+                ..limits
+            });
+        }
+    }
+}
+
+/// A T=1 card's IFSC, from TA3; 32 if it doesn't say, and None if it isn't T=1.
+pub fn ifsc(atr: &ATR) -> Option<u16> {
+    let t1 = |td: Option<atr::TDn>| td.is_some_and(|td| td.protocol == atr::Protocol::T1);
+    if !t1(atr.tx1.td) && !t1(atr.tx2.td) {
+        return None;
+    }
+    // TA3 is only the IFSC if TD2 says T=1; 00 and FF are reserved.
+    match atr.tx3.ta.filter(|_| t1(atr.tx2.td)) {
+        Some(ifsc @ 0x01..=0xFE) => Some(ifsc.into()),
+        _ => Some(32),
+    }
+}
+
+/// What to ask for next, after the card said `le` was the wrong length: its IFSC, if that's
+/// less, otherwise half as much. None if it's not worth going any lower.
+pub fn next_le(card: &mut (impl CardTransport + ?Sized), le: u16) -> Option<u16> {
+    let ifsc = (card.atr().ok())
+        .and_then(|raw| atr::parse(&raw).ok())
+        .and_then(|atr| ifsc(&atr));
+    let next = match ifsc {
+        Some(ifsc) if ifsc < le => ifsc,
+        _ => le / 2,
+    };
+    (next >= MIN_LE).then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ifsc() {
+        // T=1, IFSC 40 (TA3).
+        let atr = atr::parse(&[0x3B, 0x80, 0x81, 0x31, 0x40, 0x45, 0x35]).unwrap();
+        assert_eq!(ifsc(&atr), Some(0x40));
+
+        // T=1, with no TA3.
+        let atr = atr::parse(&[0x3B, 0x80, 0x01, 0x81]).unwrap();
+        assert_eq!(ifsc(&atr), Some(32));
+
+        // T=0 only.
+        let atr = atr::parse(&[0x3B, 0x02, 0x14, 0x50]).unwrap();
+        assert_eq!(ifsc(&atr), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! Trying again when the card (or the air between it and the reader) has a bad moment.
+//!
+//! Contactless coupling is flaky: move the card a millimetre during a long FeliCa dump, and
+//! one command in a few hundred fails with a comms error, or the reader makes up a status
+//! word for it. A [RetryPolicy] says which of those failures are worth another go, and how
+//! long to keep at it; transports hand theirs out with [CardTransport::retry_policy]
+//! (see `Session` in cardinal-transports), and [crate::util::call_apdu] follows it.
+//!
+//! [CardTransport::retry_policy]: crate::CardTransport::retry_policy
+
+use crate::{Error, Result};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to try again after the first attempt.
+    pub max_retries: u32,
+    /// How long to wait before the first retry; it doubles for each one after that.
+    pub backoff: Duration,
+    /// Gives up on a call (retries and all) once it's been going for this long. A transmit
+    /// that's already under way can't be interrupted, so this is checked between attempts.
+    pub timeout: Option<Duration>,
+    /// PCSC errors worth retrying.
+    #[cfg(feature = "hardware")]
+    pub pcsc_errors: Vec<pcsc::Error>,
+    /// Status words worth retrying; readers tend to answer with these when they lost the
+    /// card halfway through a command, rather than when the card said no.
+    pub status_words: Vec<(u8, u8)>,
+}
+
+impl RetryPolicy {
+    /// Doesn't retry anything.
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        backoff: Duration::ZERO,
+        timeout: None,
+        #[cfg(feature = "hardware")]
+        pcsc_errors: vec![],
+        status_words: vec![],
+    };
+
+    /// Retries up to `max_retries` times, on the usual suspects.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            backoff: Duration::from_millis(10),
+            timeout: None,
+            #[cfg(feature = "hardware")]
+            pcsc_errors: vec![
+                pcsc::Error::CommError,
+                pcsc::Error::CommDataLost,
+                pcsc::Error::NotTransacted,
+                pcsc::Error::Timeout,
+            ],
+            // 6F00 (no precise diagnosis) and 6400 (execution error) are what PCSC readers
+            // usually say when the RF link drops.
+            status_words: vec![(0x6F, 0x00), (0x64, 0x00)],
+        }
+    }
+
+    /// Is this error worth another try?
+    pub fn retries_error(&self, err: &Error) -> bool {
+        match err.root() {
+            #[cfg(feature = "hardware")]
+            Error::PCSC(err) => self.pcsc_errors.contains(err),
+            Error::APDU(sw1, sw2) => self.retries_sw(*sw1, *sw2),
+            _ => false,
+        }
+    }
+
+    /// Is this status word worth another try?
+    pub fn retries_sw(&self, sw1: u8, sw2: u8) -> bool {
+        self.status_words.contains(&(sw1, sw2))
+    }
+
+    /// How long to wait before retry number `n` (starting at 1).
+    pub fn delay(&self, n: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(n.saturating_sub(1)))
+    }
+
+    /// Calls `f` until it returns something that isn't worth retrying, or we run out of
+    /// retries or time. `retry` says whether a successful result should be retried anyway
+    /// (eg. because it's a status word on the list).
+    pub fn run<T>(
+        &self,
+        mut f: impl FnMut() -> Result<T>,
+        retry: impl Fn(&T) -> bool,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let mut n = 0;
+        loop {
+            let res = f();
+            let retryable = match &res {
+                Ok(v) => retry(v),
+                Err(err) => self.retries_error(err),
+            };
+            if !retryable || n >= self.max_retries {
+                return res;
+            }
+            n += 1;
+            let mut delay = self.delay(n);
+            if let Some(timeout) = self.timeout {
+                let left = timeout.saturating_sub(start.elapsed());
+                if left <= delay {
+                    debug!(?timeout, "Out of time for retries");
+                    return Err(Error::Timeout(timeout));
+                }
+                delay = delay.min(left);
+            }
+            debug!(n, ?delay, "Retrying");
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let policy = RetryPolicy {
+            backoff: Duration::ZERO,
+            ..RetryPolicy::new(3)
+        };
+        let mut attempts = 0;
+        let res = policy.run(
+            || {
+                attempts += 1;
+                match attempts {
+                    1 => Err(Error::APDU(0x64, 0x00)),
+                    2 => Ok((0x6F, 0x00)),
+                    _ => Ok((0x90, 0x00)),
+                }
+            },
+            |&(sw1, sw2)| policy.retries_sw(sw1, sw2),
+        );
+        assert_eq!(res.unwrap(), (0x90, 0x00));
+        assert_eq!(attempts, 3);
+
+        // Card says no: that's not going to change.
+        let mut attempts = 0;
+        let res: Result<()> = policy.run(
+            || {
+                attempts += 1;
+                Err(Error::APDU(0x6A, 0x82))
+            },
+            |_| false,
+        );
+        assert!(matches!(res, Err(Error::APDU(0x6A, 0x82))));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_run_gives_up() {
+        let policy = RetryPolicy {
+            backoff: Duration::ZERO,
+            ..RetryPolicy::new(2)
+        };
+        let mut attempts = 0;
+        let res: Result<()> = policy.run(
+            || {
+                attempts += 1;
+                Err(Error::APDU(0x6F, 0x00))
+            },
+            |_| false,
+        );
+        assert!(matches!(res, Err(Error::APDU(0x6F, 0x00))));
+        assert_eq!(attempts, 3);
+
+        let policy = RetryPolicy {
+            backoff: Duration::from_secs(10),
+            timeout: Some(Duration::from_millis(1)),
+            ..RetryPolicy::new(2)
+        };
+        let res: Result<()> = policy.run(|| Err(Error::APDU(0x6F, 0x00)), |_| false);
+        assert!(matches!(res, Err(Error::Timeout(_))));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(5);
+        assert_eq!(policy.delay(1), Duration::from_millis(10));
+        assert_eq!(policy.delay(3), Duration::from_millis(40));
+    }
+}
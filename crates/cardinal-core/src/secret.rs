@@ -0,0 +1,79 @@
+//! Scrubbing secrets from memory once we're done with them.
+//!
+//! PINs, keys and session keys pass through the same wbuf/rbuf scratch space as everything
+//! else, and a long-running process (like `cardinal check --watch`) would otherwise keep
+//! whatever the last card said lying around until something else overwrote it.
+//!
+//! With the `zeroize` feature (on by default), this uses the `zeroize` crate, which makes
+//! sure the compiler doesn't optimise the writes away; without it, it's best effort.
+
+/// Overwrites a buffer with zeroes.
+pub fn scrub(buf: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(buf);
+    #[cfg(not(feature = "zeroize"))]
+    buf.fill(0);
+}
+
+/// A buffer that's scrubbed when it's dropped. Its [std::fmt::Debug] impl doesn't print
+/// what's in it, so it won't end up in logs either.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret<T: AsMut<[u8]>>(T);
+
+impl<T: AsMut<[u8]>> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: AsMut<[u8]>> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: AsMut<[u8]>> std::ops::Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: AsMut<[u8]>> std::ops::DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: AsMut<[u8]>> std::fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+impl<T: AsMut<[u8]>> Drop for Secret<T> {
+    fn drop(&mut self) {
+        scrub(self.0.as_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub() {
+        let mut buf = [0x12; 8];
+        scrub(&mut buf[..4]);
+        assert_eq!(buf, [0, 0, 0, 0, 0x12, 0x12, 0x12, 0x12]);
+    }
+
+    #[test]
+    fn test_secret() {
+        let mut key = Secret::new([0xAA; 4]);
+        key[0] = 0x55;
+        assert_eq!(*key, [0x55, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(format!("{:?}", key), "Secret(..)");
+    }
+}
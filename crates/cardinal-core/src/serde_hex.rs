@@ -0,0 +1,103 @@
+//! Hex strings, for `deserialize_with`: in config files (emulator profiles, check specs),
+//! and anything else people write by hand. Fields that aren't a [crate::HexVec] (eg. a
+//! fixed size array) can be written out the same way with [serialize].
+//!
+//! Whitespace (or colons, commas...) between bytes is fine, so long values can be split up
+//! readably; see [crate::hexvec::parse]. Plain arrays
+//! of bytes work too, since that's how reports before version 20 wrote byte fields out, and
+//! they should still read back in.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub use crate::hexvec::parse;
+
+/// Either hex or an array of bytes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HexOrBytes {
+    Hex(String),
+    Bytes(Vec<u8>),
+}
+
+impl HexOrBytes {
+    fn into_bytes<E: serde::de::Error>(self) -> Result<Vec<u8>, E> {
+        match self {
+            Self::Hex(s) => parse(&s).map_err(E::custom),
+            Self::Bytes(v) => Ok(v),
+        }
+    }
+}
+
+pub fn bytes<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    HexOrBytes::deserialize(d)?.into_bytes()
+}
+
+pub fn opt<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+    Option::<HexOrBytes>::deserialize(d)?
+        .map(HexOrBytes::into_bytes)
+        .transpose()
+}
+
+/// Exactly `N` bytes, eg. a FeliCa IDm.
+pub fn array<'de, D: Deserializer<'de>, const N: usize>(d: D) -> Result<[u8; N], D::Error> {
+    bytes(d)?.try_into().map_err(|v: Vec<u8>| {
+        serde::de::Error::invalid_length(v.len(), &alloc::format!("{N} bytes").as_str())
+    })
+}
+
+pub fn vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>, D::Error> {
+    Vec::<HexOrBytes>::deserialize(d)?
+        .into_iter()
+        .map(HexOrBytes::into_bytes)
+        .collect()
+}
+
+/// Uppercase hex, like a [crate::HexVec] is written.
+pub fn serialize<T: AsRef<[u8]>, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&hex::encode_upper(v.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Thing {
+        #[serde(deserialize_with = "bytes")]
+        a: Vec<u8>,
+        #[serde(default, deserialize_with = "opt")]
+        b: Option<Vec<u8>>,
+    }
+
+    #[derive(Debug, PartialEq, Eq, serde::Serialize, Deserialize)]
+    struct Fixed {
+        #[serde(serialize_with = "serialize", deserialize_with = "array")]
+        a: [u8; 2],
+    }
+
+    #[test]
+    fn test_bytes() {
+        let thing: Thing = serde_json::from_str(r#"{"a": "3B 8F", "b": [1, 2]}"#).unwrap();
+        assert_eq!(thing.a, vec![0x3B, 0x8F]);
+        assert_eq!(thing.b, Some(vec![0x01, 0x02]));
+        let thing: Thing = serde_json::from_str(r#"{"a": [59]}"#).unwrap();
+        assert_eq!(thing.a, vec![0x3B]);
+        assert_eq!(thing.b, None);
+        assert!(serde_json::from_str::<Thing>(r#"{"a": "3"}"#).is_err());
+    }
+
+    #[test]
+    fn test_array() {
+        let fixed = Fixed { a: [0x3B, 0x8F] };
+        let json = serde_json::to_value(&fixed).unwrap();
+        assert_eq!(json, serde_json::json!({ "a": "3B8F" }));
+        assert_eq!(serde_json::from_value::<Fixed>(json).unwrap(), fixed);
+        assert_eq!(
+            serde_json::from_str::<Fixed>(r#"{"a": [59, 143]}"#).unwrap(),
+            fixed
+        );
+        assert!(serde_json::from_str::<Fixed>(r#"{"a": "3B"}"#).is_err());
+    }
+}
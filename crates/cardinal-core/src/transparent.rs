@@ -0,0 +1,194 @@
+//! PC/SC Part 3 transparent sessions.
+//!
+//! Most contactless readers let you talk to non-ISO 7816 cards by wrapping raw frames in
+//! a vendor-specific pseudo-APDU (eg. ACS' `FF 00 00 00`), but that's not standardised.
+//! The standard way is a transparent session (PC/SC Part 3, section 3.2.2.1): you start a
+//! session with `FF C2 00 00`, switch to the card's protocol with `FF C2 00 02`, and then
+//! send it raw frames with `FF C2 00 01`. Everything is wrapped in BER-TLV data objects,
+//! and the reader reports errors for each one in a `C0` ("generic error status") object.
+
+use crate::{ber, Error, PCSCTransparentError, Result};
+use crate::{util, CardTransport};
+use tracing::{debug, trace_span};
+
+/// Protocols we can switch a session to (PC/SC Part 3, "Switch Protocol Data Object").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Framing {
+    ISO14443A = 0x00,
+    ISO14443B = 0x01,
+    ISO15693 = 0x02,
+    FeliCa = 0x03,
+}
+
+/// Manage Session: Start Transparent Session.
+const DO_START_SESSION: &[u8] = &[0x81, 0x00];
+/// Manage Session: End Transparent Session.
+const DO_END_SESSION: &[u8] = &[0x82, 0x00];
+/// Switch Protocol (protocol, layer).
+const TAG_SWITCH_PROTOCOL: &[u8] = &[0x8F];
+/// Transparent Exchange: Transceive (send a frame, and wait for the response).
+const TAG_TRANSCEIVE: &[u8] = &[0x95];
+/// Generic error status (DO number, SW1, SW2).
+const TAG_ERROR_STATUS: &[u8] = &[0xC0];
+/// ICC response.
+const TAG_RESPONSE: &[u8] = &[0x97];
+
+/// Encodes data objects for a Switch Protocol command. We always ask for layer 2 (raw
+/// frames); anything higher would just be the reader doing the ISO 7816-4 wrapping for us.
+pub fn switch_protocol_dos(framing: Framing) -> Vec<u8> {
+    tv(TAG_SWITCH_PROTOCOL, &[framing as u8, 0x02])
+}
+
+/// Encodes data objects for a Transparent Exchange command that sends `frame` to the card.
+pub fn transceive_dos(frame: &[u8]) -> Vec<u8> {
+    tv(TAG_TRANSCEIVE, frame)
+}
+
+/// Pulls the frame back out of a Transparent Exchange's data objects; the other way round
+/// from [transceive_dos].
+pub fn transceive_frame(dos: &[u8]) -> Option<&[u8]> {
+    (ber::iter(dos))
+        .map_while(|item| item.ok())
+        .find(|(tag, _)| *tag == TAG_TRANSCEIVE)
+        .map(|(_, value)| value)
+}
+
+fn tv(tag: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0u8; tag.len() + 9 + value.len()];
+    let len = scroll::Pwrite::pwrite(&mut buf[..], ber::TV(tag, value), 0)
+        .expect("buffer should always fit the TLV");
+    buf.truncate(len);
+    buf
+}
+
+/// Checks the data objects returned by a transparent session command for errors, and
+/// returns the card's response (if any).
+pub fn parse_response(data: &[u8]) -> Result<Option<&[u8]>> {
+    let mut response = None;
+    for item in ber::iter(data) {
+        let (tag, value) = item?;
+        if tag == TAG_ERROR_STATUS && value.len() == 3 {
+            let status = PCSCTransparentError::from(u16::from_be_bytes([value[1], value[2]]));
+            if status != PCSCTransparentError::NoError {
+                return Err(Error::PCSCTransparent(value[0], status));
+            }
+        } else if tag == TAG_RESPONSE {
+            response = Some(value);
+        }
+    }
+    Ok(response)
+}
+
+/// Sends a transparent session command; `p2` is the function (0 = manage session,
+/// 1 = transparent exchange, 2 = switch protocol).
+fn exchange<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    p2: u8,
+    dos: &[u8],
+) -> Result<Option<&'r [u8]>> {
+    parse_response(util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload_le(0xFF, 0xC2, 0x00, p2, 0x00, dos),
+    )?)
+}
+
+/// Sends a single frame in a one-off transparent session, and returns the card's response.
+///
+/// The session is always ended afterwards, even if the exchange fails, so the reader goes
+/// back to its normal behaviour.
+pub fn transceive<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    framing: Framing,
+    frame: &[u8],
+) -> Result<&'r [u8]> {
+    let span = trace_span!("transparent::transceive", ?framing);
+    let _enter = span.enter();
+
+    // A full-size frame, plus the data objects it comes wrapped in.
+    let mut tmp = [0u8; crate::MAX_BUFFER_SIZE + 16];
+    exchange(card, wbuf, &mut tmp, 0x00, DO_START_SESSION)?;
+
+    let result = transceive_in_session(card, wbuf, &mut tmp, framing, frame);
+
+    if let Err(err) = exchange(card, wbuf, &mut tmp, 0x00, DO_END_SESSION) {
+        debug!(?err, "Couldn't end transparent session");
+    }
+
+    let rsp = result?;
+    if rsp.len() > rbuf.len() {
+        return Err(Error::InsufficientBuffer);
+    }
+    rbuf[..rsp.len()].copy_from_slice(&rsp);
+    Ok(&rbuf[..rsp.len()])
+}
+
+fn transceive_in_session(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    tmp: &mut [u8],
+    framing: Framing,
+    frame: &[u8],
+) -> Result<Vec<u8>> {
+    exchange(card, wbuf, tmp, 0x02, &switch_protocol_dos(framing))?;
+    exchange(card, wbuf, tmp, 0x01, &transceive_dos(frame))?
+        .map(|rsp| rsp.to_vec())
+        .ok_or(Error::PCSCTransparent(0, PCSCTransparentError::NoInfo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dos() {
+        assert_eq!(
+            switch_protocol_dos(Framing::FeliCa),
+            vec![0x8F, 0x02, 0x03, 0x02]
+        );
+        assert_eq!(
+            transceive_dos(&[0x06, 0x00, 0xFF, 0xFF, 0x01, 0x00]),
+            vec![0x95, 0x06, 0x06, 0x00, 0xFF, 0xFF, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_transceive_frame() {
+        let frame = [0x06, 0x00, 0xFF, 0xFF, 0x01, 0x00];
+        assert_eq!(transceive_frame(&transceive_dos(&frame)), Some(&frame[..]));
+        assert_eq!(
+            transceive_frame(&switch_protocol_dos(Framing::FeliCa)),
+            None
+        );
+        assert_eq!(transceive_frame(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(&[0xC0, 0x03, 0x00, 0x90, 0x00]).unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_response(&[
+                0xC0, 0x03, 0x00, 0x90, 0x00, 0x92, 0x01, 0x00, 0x96, 0x02, 0x00, 0x00, 0x97, 0x03,
+                0x03, 0x01, 0x02,
+            ])
+            .unwrap(),
+            Some(&[0x03, 0x01, 0x02][..])
+        );
+        assert!(matches!(
+            parse_response(&[0xC0, 0x03, 0x01, 0x6A, 0x81]),
+            Err(Error::PCSCTransparent(
+                0x01,
+                PCSCTransparentError::NotSupported
+            ))
+        ));
+    }
+}
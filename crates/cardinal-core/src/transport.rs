@@ -0,0 +1,492 @@
+//! Abstraction over whatever's actually talking to the card.
+//!
+//! Commands don't care whether they're going through PCSC, a mock in a test, or something
+//! that logs everything on the way past; they just need something that can send an APDU
+//! and get a response back.
+
+use crate::buffers::Buffers;
+use crate::protocol::Protocol;
+use crate::reader_quirks::ReaderQuirks;
+use crate::response_limits::ResponseLimits;
+use crate::retry::RetryPolicy;
+use crate::{Error, Result};
+
+/// How hard to reset a card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    /// Warm reset: the card stays powered, but forgets everything (selected files,
+    /// security state, open channels...) and sends its ATR again.
+    Warm,
+    /// Cold reset: power the card down and back up again.
+    Cold,
+}
+
+/// Something that can exchange APDUs with a card.
+pub trait CardTransport {
+    /// Sends a command APDU, and returns the response APDU (including SW1-SW2), which is
+    /// written into `rbuf`.
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]>;
+
+    /// Transmission protocol in use; transports that don't know (or don't care) act
+    /// like T=1, and pass APDUs straight through.
+    fn protocol(&mut self) -> Protocol {
+        Protocol::T1
+    }
+
+    /// The card's ATR (Answer To Reset), if the transport knows it.
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        Err(Error::Transport(
+            "atr",
+            "not available from this transport".into(),
+        ))
+    }
+
+    /// Reads a PCSC reader attribute. Only PCSC (and things relaying it) has those.
+    #[cfg(feature = "hardware")]
+    fn get_attribute<'r>(
+        &mut self,
+        _attr: pcsc::Attribute,
+        _rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        Err(pcsc::Error::UnsupportedFeature.into())
+    }
+
+    /// Sends a control code to the reader (SCardControl), eg. a vendor escape command; see
+    /// `cardinal_transports::escape`. Works without a card, on a direct connection.
+    #[cfg(feature = "hardware")]
+    fn control<'r>(
+        &mut self,
+        _code: pcsc::DWORD,
+        _data: &[u8],
+        _rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        Err(pcsc::Error::UnsupportedFeature.into())
+    }
+
+    /// Is the card still there? Transports that can't tell assume it is.
+    fn is_present(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Reconnects to the card, eg. after it was removed and put back.
+    fn reconnect(&mut self) -> Result<()> {
+        Err(Error::Transport(
+            "reconnect",
+            "not supported by this transport".into(),
+        ))
+    }
+
+    /// Resets the card, and reconnects to it.
+    fn reset(&mut self, _kind: Reset) -> Result<()> {
+        Err(Error::Transport(
+            "reset",
+            "not supported by this transport".into(),
+        ))
+    }
+
+    /// How [crate::util::call_apdu] should retry commands that fail, if at all.
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        None
+    }
+
+    /// What the reader can do, if we know; see [crate::reader_quirks].
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        None
+    }
+
+    /// How much the card sends back at once, as far as we know; see
+    /// [crate::response_limits].
+    fn response_limits(&self) -> ResponseLimits {
+        ResponseLimits::default()
+    }
+
+    /// Remembers what a command found out about [CardTransport::response_limits], for
+    /// transports that can; the rest forget it straight away.
+    fn learn_response_limits(&mut self, _limits: ResponseLimits) {}
+
+    /// Buffers to send commands with; see [crate::buffers]. Transports without a pool of
+    /// their own hand out new ones.
+    fn buffers(&self) -> Buffers {
+        Buffers::new()
+    }
+
+    /// Runs `f` with the card to ourselves, so nobody else sharing the reader can get a
+    /// command in halfway through (and eg. select a different application). Transports that
+    /// don't share the card with anyone just run it.
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        f(&mut &mut *self)
+    }
+}
+
+/// Runs `f` in a [CardTransport::transaction], and hands back what it returned.
+///
+/// Anything that depends on what came before it (a READ RECORD after its SELECT, say)
+/// belongs in one: besides nobody else getting a command in, a transport that recovers from
+/// the card being reset or pulled out (like `Session` or `Reattach` in cardinal-transports)
+/// runs the whole thing again, rather than sending the one command to a card that's
+/// forgotten what it was about.
+pub fn transaction<R>(
+    card: &mut (impl CardTransport + ?Sized),
+    mut f: impl FnMut(&mut dyn CardTransport) -> Result<R>,
+) -> Result<R> {
+    let mut out = None;
+    card.transaction(&mut |card| {
+        out = Some(f(card)?);
+        Ok(())
+    })?;
+    Ok(out.expect("transaction succeeded without running"))
+}
+
+#[cfg(feature = "hardware")]
+impl CardTransport for pcsc::Card {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        Ok(pcsc::Card::transmit(self, capdu, rbuf)?)
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        // If we can't tell, the transmit itself is going to fail anyway.
+        Protocol::detect(self).unwrap_or(Protocol::T1)
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        Ok(self.get_attribute_owned(pcsc::Attribute::AtrString)?)
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        Ok(pcsc::Card::get_attribute(self, attr, rbuf)?)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        Ok(pcsc::Card::control(self, code, data, rbuf)?)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        match self.status2_owned() {
+            Ok(status) => Ok(status.status().contains(pcsc::Status::PRESENT)),
+            Err(pcsc::Error::RemovedCard | pcsc::Error::NoSmartcard) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let protocols = current_protocols(self);
+        Ok(pcsc::Card::reconnect(
+            self,
+            pcsc::ShareMode::Shared,
+            protocols,
+            pcsc::Disposition::LeaveCard,
+        )?)
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        let protocols = current_protocols(self);
+        Ok(pcsc::Card::reconnect(
+            self,
+            pcsc::ShareMode::Shared,
+            protocols,
+            match kind {
+                Reset::Warm => pcsc::Disposition::ResetCard,
+                Reset::Cold => pcsc::Disposition::UnpowerCard,
+            },
+        )?)
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        let tx = pcsc::Card::transaction(self)?;
+        let result = f(&mut PcscTransaction(&tx));
+        // If the card went away (or was reset), so did the transaction; don't cover up
+        // whatever `f` tripped over with an error about that.
+        match (result, tx.end(pcsc::Disposition::LeaveCard)) {
+            (Err(err), _) => Err(err),
+            (Ok(()), Err((_, err))) => Err(err.into()),
+            (Ok(()), Ok(())) => Ok(()),
+        }
+    }
+}
+
+/// Whatever protocol the card's on, so reconnecting doesn't quietly renegotiate one that
+/// was asked for (eg. with `--protocol t0`); ANY if PCSC won't say.
+#[cfg(feature = "hardware")]
+fn current_protocols(card: &pcsc::Card) -> pcsc::Protocols {
+    match card.status2_owned().ok().and_then(|s| s.protocol2()) {
+        Some(pcsc::Protocol::T0) => pcsc::Protocols::T0,
+        Some(pcsc::Protocol::T1) => pcsc::Protocols::T1,
+        _ => pcsc::Protocols::ANY,
+    }
+}
+
+/// A card with a transaction open on it. It's borrowed by the transaction, so anything that
+/// needs it mutably (like reconnecting) isn't possible until the transaction ends.
+#[cfg(feature = "hardware")]
+struct PcscTransaction<'t>(&'t pcsc::Card);
+
+#[cfg(feature = "hardware")]
+impl CardTransport for PcscTransaction<'_> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        Ok(self.0.transmit(capdu, rbuf)?)
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        Protocol::detect(self.0).unwrap_or(Protocol::T1)
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        Ok(self.0.get_attribute_owned(pcsc::Attribute::AtrString)?)
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        Ok(self.0.get_attribute(attr, rbuf)?)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        Ok(self.0.control(code, data, rbuf)?)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        match self.0.status2_owned() {
+            Ok(status) => Ok(status.status().contains(pcsc::Status::PRESENT)),
+            Err(pcsc::Error::RemovedCard | pcsc::Error::NoSmartcard) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl<T: CardTransport + ?Sized> CardTransport for &mut T {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        (**self).transmit(capdu, rbuf)
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        (**self).protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        (**self).atr()
+    }
+
+    #[cfg(feature = "hardware")]
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        (**self).get_attribute(attr, rbuf)
+    }
+
+    #[cfg(feature = "hardware")]
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        (**self).control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        (**self).is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        (**self).reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        (**self).reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        (**self).retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        (**self).reader_quirks()
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        (**self).response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        (**self).learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> Buffers {
+        (**self).buffers()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        (**self).transaction(f)
+    }
+}
+
+impl<T: CardTransport + ?Sized> CardTransport for Box<T> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        (**self).transmit(capdu, rbuf)
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        (**self).protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        (**self).atr()
+    }
+
+    #[cfg(feature = "hardware")]
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        (**self).get_attribute(attr, rbuf)
+    }
+
+    #[cfg(feature = "hardware")]
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        (**self).control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        (**self).is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        (**self).reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        (**self).reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        (**self).retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        (**self).reader_quirks()
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        (**self).response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        (**self).learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> Buffers {
+        (**self).buffers()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        (**self).transaction(f)
+    }
+}
+
+/// Transport that plays back canned responses, for tests.
+#[cfg(test)]
+pub(crate) struct MockTransport {
+    pub protocol: Protocol,
+    /// Expected (command, response) pairs, in order.
+    pub exchanges: std::collections::VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    pub fn new(protocol: Protocol, exchanges: &[(&[u8], &[u8])]) -> Self {
+        Self {
+            protocol,
+            exchanges: exchanges
+                .iter()
+                .map(|(c, r)| (c.to_vec(), r.to_vec()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl CardTransport for MockTransport {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let (expected, rsp) = self.exchanges.pop_front().expect("unexpected command");
+        assert_eq!(capdu, &expected[..]);
+        rbuf[..rsp.len()].copy_from_slice(&rsp);
+        Ok(&rbuf[..rsp.len()])
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        self.protocol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{iso7816, util};
+
+    #[test]
+    fn test_call_apdu_t0_get_response() {
+        // SELECT over T=0: Le is dropped, and the FCI is fetched with GET RESPONSE.
+        let mut card = MockTransport::new(
+            Protocol::T0,
+            &[
+                (&[0x00, 0xA4, 0x04, 0x00, 0x02, 0x3F, 0x00], &[0x61, 0x04]),
+                (
+                    &[0x00, 0xC0, 0x00, 0x00, 0x04],
+                    &[0x6F, 0x02, 0x84, 0x00, 0x61, 0x02],
+                ),
+                (&[0x00, 0xC0, 0x00, 0x00, 0x02], &[0xA5, 0x00, 0x90, 0x00]),
+            ],
+        );
+        let (mut wbuf, mut rbuf) = ([0; 64], [0; 64]);
+        let rsp = util::call_apdu(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            iso7816::Select {
+                id: iso7816::SelectID::Name(&[0x3F, 0x00]),
+                mode: iso7816::SelectMode::First,
+            }
+            .into(),
+        )
+        .unwrap();
+        assert_eq!(rsp, &[0x6F, 0x02, 0x84, 0x00, 0xA5, 0x00]);
+        assert!(card.exchanges.is_empty());
+    }
+
+    #[test]
+    fn test_call_apdu_wrong_le() {
+        let mut card = MockTransport::new(
+            Protocol::T1,
+            &[
+                (&[0x00, 0xB2, 0x01, 0x0C, 0x00], &[0x6C, 0x02]),
+                (&[0x00, 0xB2, 0x01, 0x0C, 0x02], &[0x70, 0x00, 0x90, 0x00]),
+            ],
+        );
+        let (mut wbuf, mut rbuf) = ([0; 64], [0; 64]);
+        let rsp = util::call_le(
+            &mut card, &mut wbuf, &mut rbuf, 0x00, 0xB2, 0x01, 0x0C, 0x00,
+        )
+        .unwrap();
+        assert_eq!(rsp, &[0x70, 0x00]);
+    }
+}
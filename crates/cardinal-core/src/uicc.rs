@@ -0,0 +1,727 @@
+//! SIM cards and UICCs: the ones in your phone.
+//!
+//! A SIM is an ISO 7816 card with a filesystem of well-known files, addressed by two-byte
+//! File IDs. Old (GSM 11.11) SIMs want CLA A0 for everything, answer SELECT with 9Fxx and
+//! a made-up response you fetch with GET RESPONSE, and keep the subscriber's files under
+//! DF.GSM (7F20). UICCs (ETSI TS 102 221) take CLA 00, describe files with a BER-TLV File
+//! Control Parameters template, and put them in a USIM application (listed in EF.DIR);
+//! most still take the old commands too, for the benefit of old phones.
+//!
+//! We stick to files anyone can read without a PIN: who made the card (EF.ICCID), who it's
+//! for (EF.IMSI), who sold it (EF.SPN) and how it wants to be treated (EF.AD).
+//!
+//! References: GSM 11.11, ETSI TS 102 221 (UICC), 3GPP TS 31.102 (USIM).
+
+use crate::uid::CardUid;
+use crate::{ber, iso7816, util, warnings, CardTransport, Error, HexVec, Result};
+use scroll::{Pread, BE};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace_span};
+
+/// File ID of the MF.
+pub const MF: u16 = 0x3F00;
+/// File ID of DF.GSM, where a GSM SIM (and most UICCs, for compatibility) keeps its files.
+pub const DF_GSM: u16 = 0x7F20;
+/// File ID of EF.DIR, which lists applications (ie. USIM) on a UICC.
+pub const EF_DIR: u16 = 0x2F00;
+/// The USIM's RID and application code (3GPP); the rest of the AID is up to the issuer.
+pub const USIM_AID_PREFIX: &[u8] = &[0xA0, 0x00, 0x00, 0x00, 0x87, 0x10, 0x02];
+
+/// Which command set the card speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Class {
+    /// ETSI TS 102 221: CLA 00, FCP templates.
+    Uicc,
+    /// GSM 11.11: CLA A0, and GET RESPONSE after every SELECT.
+    Gsm,
+}
+
+impl Class {
+    pub fn cla(self) -> u8 {
+        match self {
+            Self::Uicc => 0x00,
+            Self::Gsm => 0xA0,
+        }
+    }
+}
+
+/// Elementary files worth reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Ef {
+    /// ICC Identification: the card's serial number, under the MF.
+    Iccid,
+    /// International Mobile Subscriber Identity.
+    Imsi,
+    /// Service Provider Name.
+    Spn,
+    /// Administrative Data: operation mode, and how long the MNC is.
+    Ad,
+}
+
+impl Ef {
+    pub fn fid(self) -> u16 {
+        match self {
+            Self::Iccid => 0x2FE2,
+            Self::Imsi => 0x6F07,
+            Self::Spn => 0x6F46,
+            Self::Ad => 0x6FAD,
+        }
+    }
+}
+
+impl std::fmt::Display for Ef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Iccid => write!(f, "EF.ICCID"),
+            Self::Imsi => write!(f, "EF.IMSI"),
+            Self::Spn => write!(f, "EF.SPN"),
+            Self::Ad => write!(f, "EF.AD"),
+        }
+    }
+}
+
+/// How an EF is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Structure {
+    Transparent,
+    LinearFixed,
+    Cyclic,
+    /// BER-TLV structured, or anything else.
+    Other(u8),
+}
+
+/// What kind of file something is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FileKind {
+    /// A directory (the MF, a DF or an ADF).
+    DF,
+    EF(Structure),
+}
+
+/// What SELECT says about a file, from either a UICC FCP template (0x62), or a GSM
+/// SELECT response; anything the card didn't say is None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fcp {
+    pub fid: Option<u16>,
+    pub kind: Option<FileKind>,
+    /// Size of the file's contents, for EFs.
+    pub size: Option<usize>,
+    /// Length of each record, for record files.
+    pub record_len: Option<usize>,
+    /// Number of records, for record files.
+    pub records: Option<u8>,
+    /// Short File Identifier.
+    pub sfi: Option<u8>,
+    /// Life Cycle Status Integer (ISO 7816-4 5.3.3.2); 05 is activated, 04 deactivated.
+    pub lcs: Option<u8>,
+}
+
+impl Fcp {
+    /// Parses a UICC FCP template (ETSI TS 102 221, 11.1.1.3).
+    pub fn parse_uicc(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("Fcp::parse_uicc");
+        let _enter = span.enter();
+
+        let (_, (tag, value)) = ber::parse_next(data)?;
+        util::expect_tag(&[0x62], tag)?;
+
+        let mut slf = Self::default();
+        for res in ber::iter(value) {
+            match res? {
+                (&[0x82], &[desc, _, ref rest @ ..]) => {
+                    slf.kind = Some(match desc & 0x38 {
+                        0x38 => FileKind::DF,
+                        _ => FileKind::EF(match desc & 0x07 {
+                            0x01 => Structure::Transparent,
+                            0x02 => Structure::LinearFixed,
+                            0x06 => Structure::Cyclic,
+                            v => Structure::Other(v),
+                        }),
+                    });
+                    if let &[hi, lo, num] = rest {
+                        slf.record_len = Some(usize::from(u16::from_be_bytes([hi, lo])));
+                        slf.records = Some(num);
+                    }
+                }
+                (&[0x83], &[hi, lo]) => slf.fid = Some(u16::from_be_bytes([hi, lo])),
+                (&[0x80], v) => slf.size = Some(v.iter().fold(0, |n, b| n << 8 | *b as usize)),
+                (&[0x88], &[sfi]) => slf.sfi = Some(sfi >> 3),
+                (&[0x88], &[]) => {} // No SFI.
+                (&[0x8A], &[lcs]) => slf.lcs = Some(lcs),
+                // DF name, proprietary information, security attributes, total size, PIN
+                // status; nothing we need.
+                (&[0x84 | 0xA5 | 0x8B | 0x8C | 0xAB | 0x81 | 0xC6], _) => {}
+                (tag, value) => warnings::unknown_field("Fcp", tag, value),
+            }
+        }
+        Ok(slf)
+    }
+
+    /// Parses a GSM SELECT response (GSM 11.11, 9.2.1).
+    pub fn parse_gsm(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("Fcp::parse_gsm");
+        let _enter = span.enter();
+
+        let fid = Some(data.pread_with::<u16>(4, BE)?);
+        if data.pread::<u8>(6)? != 0x04 {
+            // MF (01) or DF (02); the "size" is how much free memory there is.
+            return Ok(Self {
+                fid,
+                kind: Some(FileKind::DF),
+                ..Default::default()
+            });
+        }
+        let size = usize::from(data.pread_with::<u16>(2, BE)?);
+        let structure = match data.get(13) {
+            Some(0x00) | None => Structure::Transparent,
+            Some(0x01) => Structure::LinearFixed,
+            Some(0x03) => Structure::Cyclic,
+            Some(&v) => Structure::Other(v),
+        };
+        let record_len = match structure {
+            Structure::Transparent => None,
+            _ => data.get(14).map(|&l| usize::from(l)).filter(|&l| l > 0),
+        };
+        Ok(Self {
+            fid,
+            kind: Some(FileKind::EF(structure)),
+            size: Some(size),
+            record_len,
+            records: record_len.map(|l| (size / l) as u8),
+            sfi: None,
+            lcs: data.get(11).copied(),
+        })
+    }
+}
+
+/// Selects a file by File ID, under the current DF (or its parent, or the MF).
+pub fn select(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    class: Class,
+    fid: u16,
+) -> Result<Fcp> {
+    let span = trace_span!("uicc::select", ?class, fid = format!("{:04X}", fid));
+    let _enter = span.enter();
+
+    let fid = fid.to_be_bytes();
+    match class {
+        // P2 04: return the FCP template.
+        Class::Uicc => Fcp::parse_uicc(util::call_apdu(
+            card,
+            wbuf,
+            rbuf,
+            apdu::Command::new_with_payload_le(0x00, 0xA4, 0x00, 0x04, 0x00, &fid),
+        )?),
+        // GSM SIMs don't do Le on a SELECT; they say how much there is with 9Fxx.
+        Class::Gsm => {
+            let cmd = apdu::Command::new_with_payload(0xA0, 0xA4, 0x00, 0x00, &fid);
+            let len = match util::call_apdu(card, wbuf, rbuf, cmd) {
+                Err(Error::APDU(0x9F, len)) => len,
+                Err(err) => return Err(err),
+                Ok(rsp) => return Fcp::parse_gsm(rsp),
+            };
+            let rsp = util::call_le(card, wbuf, rbuf, 0xA0, 0xC0, 0x00, 0x00, len.into())?;
+            Fcp::parse_gsm(rsp)
+        }
+    }
+}
+
+/// Selects an application (ie. a USIM) by AID; UICCs only.
+pub fn select_aid(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    aid: &[u8],
+) -> Result<Fcp> {
+    let span = trace_span!("uicc::select_aid", aid = hex::encode_upper(aid));
+    let _enter = span.enter();
+
+    Fcp::parse_uicc(util::call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_payload_le(0x00, 0xA4, 0x04, 0x04, 0x00, aid),
+    )?)
+}
+
+/// Reads `len` bytes from the currently selected transparent EF, in chunks no bigger than
+/// the card's [crate::response_limits::ResponseLimits] allow.
+pub fn read_binary(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    class: Class,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let span = trace_span!("uicc::read_binary", len);
+    let _enter = span.enter();
+
+    let max_le = usize::from(card.response_limits().max_le);
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let [hi, lo] = (data.len() as u16).to_be_bytes();
+        let le = (len - data.len()).min(max_le) as u16;
+        let rsp = util::call_le(card, wbuf, rbuf, class.cla(), 0xB0, hi, lo, le % 256)?;
+        if rsp.is_empty() {
+            break;
+        }
+        data.extend_from_slice(rsp);
+    }
+    Ok(data)
+}
+
+/// Reads one record, from the currently selected record EF.
+pub fn read_record(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    class: Class,
+    num: u8,
+    len: usize,
+) -> Result<Vec<u8>> {
+    // P2 04: absolute record number, in the current EF.
+    let le = (len.min(256) % 256) as u16;
+    Ok(util::call_le(card, wbuf, rbuf, class.cla(), 0xB2, num, 0x04, le)?.to_vec())
+}
+
+/// Selects an EF, and reads all of it: the whole thing for transparent ones, every record
+/// (concatenated) for the rest. Files that don't say how big they are get `default_len`.
+pub fn read_ef(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    class: Class,
+    fid: u16,
+    default_len: usize,
+) -> Result<(Fcp, Vec<u8>)> {
+    let fcp = select(card, wbuf, rbuf, class, fid)?;
+    let data = match (fcp.record_len, fcp.records) {
+        (Some(len), Some(num)) => {
+            let mut data = vec![];
+            for i in 1..=num {
+                data.extend(read_record(card, wbuf, rbuf, class, i, len)?);
+            }
+            data
+        }
+        _ => read_binary(card, wbuf, rbuf, class, fcp.size.unwrap_or(default_len))?,
+    };
+    Ok((fcp, data))
+}
+
+/// Decodes EF.IMSI: a length byte, then swapped BCD digits, the first of which is really
+/// a parity nibble.
+pub fn decode_imsi(data: &[u8]) -> Option<String> {
+    let (&len, rest) = data.split_first()?;
+    let imsi = (rest.get(..usize::from(len))?.iter())
+        .flat_map(|b| [b & 0x0F, b >> 4])
+        .skip(1)
+        .take_while(|&d| d < 10)
+        .map(|d| char::from(b'0' + d))
+        .collect::<String>();
+    (!imsi.is_empty()).then_some(imsi)
+}
+
+/// EF.SPN: the operator's name, as the phone is meant to show it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServiceProviderName {
+    /// When to show it; bit 1 is "show the network name too, when roaming".
+    pub display_condition: u8,
+    pub name: String,
+}
+
+impl ServiceProviderName {
+    /// Parses it. The name is in the SMS default alphabet, unpacked and padded with FF, or
+    /// UCS-2 if it starts with 80; the rest of TS 102 221 Annex A's encodings aren't seen
+    /// in the wild much.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (&display_condition, name) = data.split_first()?;
+        let name = match name.split_first() {
+            Some((0x80, ucs2)) => {
+                let units = (ucs2.chunks_exact(2))
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .take_while(|&u| u != 0xFFFF);
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            _ => (name.iter())
+                .take_while(|&&b| b != 0xFF)
+                .map(|&b| gsm_char(b))
+                .collect(),
+        };
+        Some(Self {
+            display_condition,
+            name,
+        })
+    }
+}
+
+/// A character in the GSM 03.38 default alphabet; the letters and digits are where ASCII
+/// has them, and most of the punctuation too.
+fn gsm_char(b: u8) -> char {
+    match b {
+        0x00 => '@',
+        0x01 => '£',
+        0x02 => '$',
+        0x11 => '_',
+        0x24 => '¤',
+        0x40 => '¡',
+        0x20..=0x7F if !b"[\\]^`{|}~".contains(&b) => char::from(b),
+        _ => '?',
+    }
+}
+
+/// EF.AD: Administrative Data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AdministrativeData {
+    /// UE operation mode; 00 is normal, anything else is for testing or maintenance.
+    pub mode: u8,
+    /// How many digits of the IMSI (after the 3-digit MCC) are the MNC; 2 or 3. Old SIMs
+    /// don't say, which means 2 in Europe and anyone's guess elsewhere.
+    pub mnc_len: Option<u8>,
+}
+
+impl AdministrativeData {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        Some(Self {
+            mode: *data.first()?,
+            mnc_len: (data.get(3))
+                .map(|b| b & 0x0F)
+                .filter(|l| (2..=3).contains(l)),
+        })
+    }
+
+    /// What the operation mode means.
+    pub fn mode_name(&self) -> &'static str {
+        match self.mode {
+            0x00 => "normal",
+            0x01 => "normal, with specific facilities",
+            0x02 => "maintenance (off-line)",
+            0x04 => "cell test",
+            0x80 => "type approval",
+            0x81 => "type approval, with specific facilities",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Everything [read_info] could find.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SimInfo {
+    pub class: Class,
+    /// The USIM's AID, if it has one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub usim: Option<HexVec>,
+    pub iccid: Option<String>,
+    pub imsi: Option<String>,
+    /// Mobile Country Code: the first 3 digits of the IMSI.
+    pub mcc: Option<String>,
+    /// Mobile Network Code: the next 2 or 3, per EF.AD.
+    pub mnc: Option<String>,
+    pub spn: Option<ServiceProviderName>,
+    pub ad: Option<AdministrativeData>,
+    /// What SELECT said about each file that could be selected.
+    pub files: Vec<(Ef, Fcp)>,
+}
+
+/// Works out whether the card is a UICC or a GSM SIM, by selecting the MF both ways.
+pub fn detect_class(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Class> {
+    let span = trace_span!("uicc::detect_class");
+    let _enter = span.enter();
+
+    match select(card, wbuf, rbuf, Class::Uicc, MF) {
+        Ok(_) => Ok(Class::Uicc),
+        // Class not supported, or it doesn't know the instruction in that class.
+        Err(Error::APDU(0x6E, 0x00) | Error::APDU(0x6D, 0x00)) => {
+            select(card, wbuf, rbuf, Class::Gsm, MF)?;
+            Ok(Class::Gsm)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Finds the USIM in EF.DIR, if there is one.
+fn find_usim(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Option<HexVec>> {
+    select(card, wbuf, rbuf, Class::Uicc, MF)?;
+    let fcp = select(card, wbuf, rbuf, Class::Uicc, EF_DIR)?;
+    let len = fcp.record_len.unwrap_or(0);
+    for num in 1..=fcp.records.unwrap_or(0) {
+        // One template per record, padded out with FF; AIDs can have FFs in them too, so
+        // it has to be one record at a time.
+        let record = read_record(card, wbuf, rbuf, Class::Uicc, num, len)?;
+        let apps = iso7816::parse_ef_dir(&record)?;
+        if let Some(app) = apps
+            .into_iter()
+            .find(|a| a.aid.starts_with(USIM_AID_PREFIX))
+        {
+            return Ok(Some(app.aid));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the ICCID, IMSI, SPN and AD. Files that can't be read (eg. because they're PIN
+/// protected, or not there) are left out.
+pub fn read_info(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<SimInfo> {
+    let span = trace_span!("uicc::read_info");
+    let _enter = span.enter();
+
+    let class = detect_class(card, wbuf, rbuf)?;
+    debug!(?class, "SIM card");
+    let mut info = SimInfo {
+        class,
+        usim: None,
+        iccid: None,
+        imsi: None,
+        mcc: None,
+        mnc: None,
+        spn: None,
+        ad: None,
+        files: vec![],
+    };
+
+    let iccid = try_read(card, wbuf, rbuf, class, Ef::Iccid, 10, &mut info.files);
+    info.iccid = iccid.and_then(|d| match CardUid::from_iccid(&d) {
+        CardUid::Iccid(iccid) => Some(iccid),
+        _ => None,
+    });
+
+    // The rest live in the USIM, or DF.GSM.
+    if class == Class::Uicc {
+        info.usim = find_usim(card, wbuf, rbuf)
+            .map_err(|err| debug!(%err, "Couldn't read EF.DIR"))
+            .ok()
+            .flatten();
+    }
+    let in_df = match info.usim.as_deref() {
+        Some(aid) => select_aid(card, wbuf, rbuf, aid).map(|_| ()),
+        None => select(card, wbuf, rbuf, class, MF)
+            .and_then(|_| select(card, wbuf, rbuf, class, DF_GSM))
+            .map(|_| ()),
+    };
+    if let Err(err) = in_df {
+        debug!(%err, "Couldn't select the USIM or DF.GSM");
+        return Ok(info);
+    }
+
+    let imsi = try_read(card, wbuf, rbuf, class, Ef::Imsi, 9, &mut info.files);
+    info.imsi = imsi.as_deref().and_then(decode_imsi);
+    let spn = try_read(card, wbuf, rbuf, class, Ef::Spn, 17, &mut info.files);
+    info.spn = spn.as_deref().and_then(ServiceProviderName::parse);
+    let ad = try_read(card, wbuf, rbuf, class, Ef::Ad, 4, &mut info.files);
+    info.ad = ad.as_deref().and_then(AdministrativeData::parse);
+
+    if let Some(imsi) = info.imsi.as_deref().filter(|imsi| imsi.len() >= 6) {
+        let mnc_len = (info.ad.as_ref()).and_then(|ad| ad.mnc_len).unwrap_or(2);
+        info.mcc = Some(imsi[..3].to_owned());
+        info.mnc = Some(imsi[3..3 + usize::from(mnc_len)].to_owned());
+    }
+    Ok(info)
+}
+
+/// Reads an EF for [read_info], noting its FCP down if it could be selected.
+fn try_read(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    class: Class,
+    ef: Ef,
+    default_len: usize,
+    files: &mut Vec<(Ef, Fcp)>,
+) -> Option<Vec<u8>> {
+    match read_ef(card, wbuf, rbuf, class, ef.fid(), default_len) {
+        Ok((fcp, data)) => {
+            files.push((ef, fcp));
+            Some(data)
+        }
+        Err(err) => {
+            debug!(%ef, %err, "Couldn't read file");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GSM 11.11 SIM, that doesn't do CLA 00 at all.
+    struct GsmSim {
+        selected: u16,
+    }
+
+    impl GsmSim {
+        fn file(fid: u16) -> Option<&'static [u8]> {
+            Some(match fid {
+                MF | DF_GSM => &[],
+                0x2FE2 => &[0x98, 0x44, 0x20, 0x00, 0x10, 0x32, 0x54, 0x76, 0x98, 0xF0],
+                0x6F07 => &[0x08, 0x29, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x10],
+                0x6F46 => &[
+                    0x01, b'T', b'e', b's', b't', 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                    0xFF, 0xFF, 0xFF, 0xFF,
+                ],
+                0x6FAD => &[0x00, 0x00, 0x00, 0x02],
+                _ => return None,
+            })
+        }
+    }
+
+    impl CardTransport for GsmSim {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: Vec<u8> = match *capdu {
+                [0x00, ..] => vec![0x6E, 0x00],
+                [0xA0, 0xA4, 0x00, 0x00, 0x02, hi, lo] => {
+                    let fid = u16::from_be_bytes([hi, lo]);
+                    match Self::file(fid) {
+                        Some(_) => {
+                            self.selected = fid;
+                            vec![0x9F, 0x0F]
+                        }
+                        None => vec![0x94, 0x04],
+                    }
+                }
+                [0xA0, 0xC0, 0x00, 0x00, 0x0F] => {
+                    let [hi, lo] = self.selected.to_be_bytes();
+                    let [s1, s2] = (Self::file(self.selected).unwrap().len() as u16).to_be_bytes();
+                    let kind = match self.selected {
+                        MF => 0x01,
+                        DF_GSM => 0x02,
+                        _ => 0x04,
+                    };
+                    let mut rsp = vec![0, 0, s1, s2, hi, lo, kind, 0, 0, 0, 0, 0x05, 0x02, 0, 0];
+                    rsp.extend([0x90, 0x00]);
+                    rsp
+                }
+                [0xA0, 0xB0, 0x00, off, le] => {
+                    let data = Self::file(self.selected).unwrap();
+                    let (off, le) = (usize::from(off), usize::from(le));
+                    [&data[off..off + le], &[0x90, 0x00]].concat()
+                }
+                _ => vec![0x6D, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_read_info_gsm() {
+        let (mut wbuf, mut rbuf) = ([0; 32], [0; 64]);
+        let info = read_info(&mut GsmSim { selected: MF }, &mut wbuf, &mut rbuf).unwrap();
+        assert_eq!(info.class, Class::Gsm);
+        assert_eq!(info.usim, None);
+        assert_eq!(info.iccid.as_deref(), Some("8944020001234567890"));
+        assert_eq!(info.imsi.as_deref(), Some("208100000000001"));
+        assert_eq!(info.mcc.as_deref(), Some("208"));
+        assert_eq!(info.mnc.as_deref(), Some("10"));
+        assert_eq!(
+            info.spn,
+            Some(ServiceProviderName {
+                display_condition: 0x01,
+                name: "Test@".into(),
+            })
+        );
+        assert_eq!(info.ad.unwrap().mode_name(), "normal");
+        assert_eq!(
+            (info.files.iter()).map(|(ef, _)| *ef).collect::<Vec<_>>(),
+            vec![Ef::Iccid, Ef::Imsi, Ef::Spn, Ef::Ad]
+        );
+        assert_eq!(info.files[1].1.size, Some(9));
+    }
+
+    /// A UICC with just a USIM in EF.DIR, whose AID has FFs in it.
+    struct Uicc;
+
+    impl CardTransport for Uicc {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x00, 0xA4, 0x00, 0x04, 0x02, 0x3F, 0x00, 0x00] => {
+                    &[0x62, 0x03, 0x82, 0x01, 0x78, 0x90, 0x00]
+                }
+                [0x00, 0xA4, 0x00, 0x04, 0x02, 0x2F, 0x00, 0x00] => &[
+                    0x62, 0x07, 0x82, 0x05, 0x42, 0x21, 0x00, 0x1A, 0x01, 0x90, 0x00,
+                ],
+                [0x00, 0xB2, 0x01, 0x04, 0x1A] => &[
+                    0x61, 0x14, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x00, 0x87, 0x10, 0x02, 0xFF, 0x49,
+                    0xFF, 0x05, 0x89, 0x50, 0x04, b'U', b'S', b'I', b'M', 0xFF, 0xFF, 0xFF, 0xFF,
+                    0x90, 0x00,
+                ],
+                _ => &[0x6A, 0x82],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_find_usim() {
+        let (mut wbuf, mut rbuf) = ([0; 32], [0; 64]);
+        assert_eq!(
+            find_usim(&mut Uicc, &mut wbuf, &mut rbuf).unwrap(),
+            Some(
+                vec![0xA0, 0x00, 0x00, 0x00, 0x87, 0x10, 0x02, 0xFF, 0x49, 0xFF, 0x05, 0x89].into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_fcp_uicc() {
+        // EF.DIR: linear fixed, 2 records of 38 bytes, SFI 1E.
+        let fcp = Fcp::parse_uicc(&[
+            0x62, 0x1A, 0x82, 0x05, 0x42, 0x21, 0x00, 0x26, 0x02, 0x83, 0x02, 0x2F, 0x00, 0x8A,
+            0x01, 0x05, 0x8B, 0x03, 0x6F, 0x06, 0x01, 0x80, 0x02, 0x00, 0x4C, 0x88, 0x01, 0xF0,
+        ])
+        .unwrap();
+        assert_eq!(
+            fcp,
+            Fcp {
+                fid: Some(EF_DIR),
+                kind: Some(FileKind::EF(Structure::LinearFixed)),
+                size: Some(0x4C),
+                record_len: Some(0x26),
+                records: Some(2),
+                sfi: Some(0x1E),
+                lcs: Some(0x05),
+            }
+        );
+        assert!(Fcp::parse_uicc(&[0x6F, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode_imsi(&[0x08, 0x09, 0x10, 0x10, 0x10, 0x32, 0x54, 0x76, 0x98]).as_deref(),
+            Some("001010123456789")
+        );
+        assert_eq!(decode_imsi(&[0x08, 0x09]), None);
+        assert_eq!(
+            ServiceProviderName::parse(&[0x00, 0x80, 0x30, 0xC9, 0x30, 0xB3, 0xFF, 0xFF])
+                .unwrap()
+                .name,
+            "ドコ"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Telling cards apart.
+//!
+//! Every kind of card has its own idea of an identifier: contactless cards have a UID (or
+//! an IDm, for FeliCa) that the reader can tell us, and SIMs have an ICCID in a file. A
+//! [CardUid] is whichever one we found, so anything that needs to know "is this the same
+//! card as before?" (archives, dedup) doesn't have to care which.
+
+use crate::atr::Standard;
+use crate::HexVec;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Whatever identifies a card.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CardUid {
+    /// ISO 14443 UID, from the reader; usually 4, 7 or 10 bytes, and may be random
+    /// (eg. for privacy-conscious passports), in which case it's only good for one session.
+    Iso14443Uid(HexVec),
+    /// FeliCa IDm (of System 0), from the reader.
+    FelicaIdm(u64),
+    /// ICCID, from EF.ICCID; the digits, as printed on a SIM.
+    Iccid(String),
+    /// Nothing we could find.
+    #[default]
+    None,
+}
+
+impl CardUid {
+    /// Makes sense of a card ID from the reader (`FF CA 00 00`), which is an IDm if the
+    /// card is FeliCa, and a UID otherwise.
+    pub fn from_cid(cid: &[u8], standard: Standard) -> Self {
+        match (standard, <[u8; 8]>::try_from(cid)) {
+            _ if cid.is_empty() => Self::None,
+            (Standard::FeliCa, Ok(idm)) => Self::FelicaIdm(u64::from_be_bytes(idm)),
+            _ => Self::Iso14443Uid(cid.into()),
+        }
+    }
+
+    /// Decodes the contents of EF.ICCID. The digits are BCD with the nibbles swapped (so
+    /// `98 44` is "8944"), padded out with F if there's an odd number of them.
+    pub fn from_iccid(data: &[u8]) -> Self {
+        let iccid = (data.iter())
+            .flat_map(|b| [b & 0x0F, b >> 4])
+            .take_while(|&d| d < 10)
+            .map(|d| char::from(b'0' + d))
+            .collect::<String>();
+        match iccid.is_empty() {
+            true => Self::None,
+            false => Self::Iccid(iccid),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::None
+    }
+
+    /// The identifier as the reader sends it, for UIDs and IDms; nothing for the rest.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Iso14443Uid(uid) => Some(uid.to_vec()),
+            Self::FelicaIdm(idm) => Some(idm.to_be_bytes().to_vec()),
+            Self::Iccid(_) | Self::None => None,
+        }
+    }
+}
+
+/// Formats it as hex, or digits for an ICCID; safe to use in a filename.
+impl std::fmt::Display for CardUid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Iso14443Uid(uid) => write!(f, "{}", hex::encode_upper(uid)),
+            Self::FelicaIdm(idm) => write!(f, "{:016X}", idm),
+            Self::Iccid(iccid) => write!(f, "{}", iccid),
+            Self::None => write!(f, "unknown"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_cid() {
+        let idm = [0x01, 0x2E, 0x45, 0x7A, 0x3B, 0x62, 0x91, 0x0C];
+        assert_eq!(
+            CardUid::from_cid(&idm, Standard::FeliCa),
+            CardUid::FelicaIdm(0x012E457A3B62910C)
+        );
+        assert_eq!(
+            CardUid::from_cid(&idm, Standard::Iso14443a3),
+            CardUid::Iso14443Uid(idm.into())
+        );
+        assert_eq!(
+            CardUid::from_cid(&[0x04, 0x11], Standard::FeliCa),
+            CardUid::Iso14443Uid(vec![0x04, 0x11].into())
+        );
+        assert_eq!(CardUid::from_cid(&[], Standard::FeliCa), CardUid::None);
+
+        // Either way, it looks the same in an archive.
+        assert_eq!(
+            CardUid::from_cid(&idm, Standard::FeliCa).to_string(),
+            CardUid::from_cid(&idm, Standard::Iso14443a3).to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_iccid() {
+        let uid =
+            CardUid::from_iccid(&[0x98, 0x44, 0x20, 0x00, 0x10, 0x32, 0x54, 0x76, 0x98, 0xF0]);
+        assert_eq!(uid, CardUid::Iccid("8944020001234567890".into()));
+        assert_eq!(uid.to_bytes(), None);
+        assert_eq!(CardUid::from_iccid(&[0xFF; 10]), CardUid::None);
+    }
+}
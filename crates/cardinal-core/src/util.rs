@@ -0,0 +1,391 @@
+#[cfg(feature = "write")]
+use crate::secret::{self, Secret};
+use crate::transport::CardTransport;
+use crate::{felica, protocol, transparent};
+use crate::{Error, Result};
+use tracing::{debug, trace, trace_span};
+
+pub fn call_le<'w, 'r>(
+    card: &mut impl CardTransport,
+    wbuf: &'w mut [u8],
+    rbuf: &'r mut [u8],
+    cla: u8,
+    ins: u8,
+    p1: u8,
+    p2: u8,
+    le: u16,
+) -> Result<&'r [u8]> {
+    call_apdu(
+        card,
+        wbuf,
+        rbuf,
+        apdu::Command::new_with_le(cla, ins, p1, p2, le),
+    )
+}
+
+/// Longest command data that fits in a short APDU.
+pub const MAX_SHORT_LC: usize = 255;
+
+/// CLA bit for command chaining: "more of this command is coming". ISO 7816-4, 5.4.1.
+pub const CLA_CHAINING: u8 = 0b0001_0000;
+
+/// Sends an APDU, and returns the response data if it succeeded (9000).
+///
+/// Command data that doesn't fit in a short APDU is split up with command chaining; the
+/// response comes with the last part. (Responses that don't fit come back as 61xx, which
+/// [protocol::transmit] takes care of.)
+pub fn call_apdu<'w, 'r>(
+    card: &mut impl CardTransport,
+    wbuf: &'w mut [u8],
+    rbuf: &'r mut [u8],
+    cmd: apdu::Command,
+) -> Result<&'r [u8]> {
+    let span = trace_span!("call_apdu");
+    let _enter = span.enter();
+
+    let mut cmd = cmd;
+    if let Some(payload) = cmd.payload.filter(|p| p.len() > MAX_SHORT_LC) {
+        let mut chunks = payload.chunks(MAX_SHORT_LC).peekable();
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_none() {
+                cmd.payload = Some(chunk);
+                break;
+            }
+            debug!(len = chunk.len(), "Chaining command data");
+            send_apdu(
+                card,
+                wbuf,
+                rbuf,
+                apdu::Command::new_with_payload(
+                    cmd.cla | CLA_CHAINING,
+                    cmd.ins,
+                    cmd.p1,
+                    cmd.p2,
+                    chunk,
+                ),
+            )?;
+        }
+    }
+    send_apdu(card, wbuf, rbuf, cmd)
+}
+
+/// Instructions that change what's on a card, or authenticate to it, which a build without
+/// the `write` feature refuses to send (and `cardinal::acquire` never sends): ISO 7816-4's
+/// writes, updates, erases, PUT DATA, VERIFY, CHANGE/RESET REFERENCE DATA, authentication
+/// and lifecycle commands, and EMV's GET PROCESSING OPTIONS and GENERATE AC (which count
+/// transactions).
+pub const MUTATING_INS: &[u8] = &[
+    0x0C, 0x0E, 0xD0, 0xD2, 0xD6, 0xDA, 0xDB, 0xDC, 0xE2, // Write, update, erase, put.
+    0x20, 0x21, 0x24, 0x2C, // Verify, change and reset reference data.
+    0x82, 0x86, 0x87, 0x88, // Authenticate.
+    0x04, 0x44, 0xE0, 0xE4, 0xE6, 0xE8, 0xFE, // Create, delete, (de)activate, terminate.
+    0xA8, 0xAE, // GPO, GENERATE AC.
+];
+
+/// Is this a command that could change what's on a card? (Or authenticate to it, which
+/// counts: a wrong PIN uses up a try.) This is what a build without the `write` feature,
+/// and `cardinal::acquire`, refuse to send.
+///
+/// Pseudo-APDUs (CLA FF) are for the reader, but some of them pass things on to the card;
+/// only the ones we know don't are let through.
+pub fn is_mutating(capdu: &[u8]) -> bool {
+    match capdu {
+        [0xFF, ins, p1, p2, rest @ ..] => {
+            let data = match rest {
+                [lc, data @ ..] => data.get(..*lc as usize).unwrap_or_default(),
+                [] => &[],
+            };
+            match (ins, p1, p2) {
+                // GET DATA (UID, ATS...), READ BINARY on a storage card, GET RESPONSE.
+                (0xCA | 0xB0 | 0xC0, _, _) => false,
+                // MIFARE Classic keys, loaded into the reader and tried on a sector; unlike
+                // a PIN, there's no counter for a wrong one to run down.
+                (0x82 | 0x86, _, _) => false,
+                // ACS' direct transmit: a FeliCa frame, or a PN532 command (could be anything).
+                (0x00, 0x00, 0x00) => is_mutating_felica(data),
+                // ACR122 LED and buzzer, firmware version, PICC operating parameter.
+                (0x00, 0x40 | 0x48 | 0x50 | 0x51, _) => false,
+                // Transparent sessions: start/end, switch protocol, and frames.
+                (0xC2, 0x00, 0x00 | 0x02) => false,
+                (0xC2, 0x00, 0x01) => {
+                    transparent::transceive_frame(data).is_none_or(is_mutating_felica)
+                }
+                // UPDATE BINARY on a storage card, vendor commands, anything else.
+                _ => true,
+            }
+        }
+        [_, ins, ..] => MUTATING_INS.contains(ins),
+        _ => false,
+    }
+}
+
+/// Is this (possibly) a FeliCa command that could change the card? Anything that doesn't
+/// look like a FeliCa frame (a length byte, then the command code) might be.
+fn is_mutating_felica(frame: &[u8]) -> bool {
+    use felica::CommandCode::*;
+    match frame {
+        [len, code, ..] if usize::from(*len) == frame.len() => !matches!(
+            felica::CommandCode::from(*code),
+            RequestService
+                | RequestResponse
+                | ReadWithoutEncryption
+                | SearchServiceCode
+                | RequestSystemCode
+        ),
+        _ => true,
+    }
+}
+
+/// Like [call_apdu], for commands with secrets in them (a PIN, a key...) or in their
+/// response: the response is copied out into a [Secret], and wbuf and rbuf are scrubbed,
+/// whether the command succeeded or not.
+///
+/// Only with the `write` feature, since those commands are for verifying and authenticating.
+#[cfg(feature = "write")]
+pub fn call_apdu_sensitive(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    cmd: apdu::Command,
+) -> Result<Secret<Vec<u8>>> {
+    let res = call_apdu(card, wbuf, rbuf, cmd).map(|data| Secret::new(data.to_vec()));
+    secret::scrub(wbuf);
+    secret::scrub(rbuf);
+    res
+}
+
+fn send_apdu<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    cmd: apdu::Command,
+) -> Result<&'r [u8]> {
+    let req = wbuf.get_mut(..cmd.len()).ok_or(Error::InsufficientBuffer)?;
+    cmd.write(req);
+    call_raw(card, req, rbuf)
+}
+
+/// Like [call_apdu], for a command that's already been put together (and fits in a short
+/// APDU), eg. in place at the start of a buffer, so it doesn't have to be copied again.
+pub fn call_raw<'r>(
+    card: &mut impl CardTransport,
+    req: &[u8],
+    rbuf: &'r mut [u8],
+) -> Result<&'r [u8]> {
+    #[cfg(not(feature = "write"))]
+    if is_mutating(req) {
+        return Err(Error::ReadOnly(req[1]));
+    }
+    trace!(req = format!("{:02X?}", req), ">> TX");
+    // Flaky links get another go, if the transport says so; see [crate::retry].
+    let policy = card.retry_policy().cloned().unwrap_or_default();
+    let (l, sw1, sw2) = policy.run(
+        || {
+            let rsp = protocol::transmit(card, req, rbuf)?;
+            match *rsp {
+                [.., sw1, sw2] => Ok((rsp.len(), sw1, sw2)),
+                _ => Err(protocol::short_response()),
+            }
+        },
+        |&(_, sw1, sw2)| policy.retries_sw(sw1, sw2),
+    )?;
+    let rsp = &rbuf[..l];
+    let data = &rsp[..l - 2];
+    trace!(rsp = format!("{:02X?}", rsp), "<< RX");
+
+    if (sw1, sw2) != (0x90, 0x00) {
+        Err(Error::APDU(sw1, sw2))
+    } else {
+        Ok(data)
+    }
+}
+
+pub(crate) fn expect_tag<'a>(expected: &'a [u8], actual: &'a [u8]) -> Result<&'a [u8]> {
+    if expected == actual {
+        Ok(expected)
+    } else {
+        Err(Error::WrongTag {
+            expected: expected.into(),
+            actual: actual.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Says 9000 to everything, and remembers what it was sent.
+    #[derive(Default)]
+    struct Log(Vec<Vec<u8>>);
+
+    impl CardTransport for Log {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.0.push(capdu.to_vec());
+            rbuf[..3].copy_from_slice(&[0x01, 0x90, 0x00]);
+            Ok(&rbuf[..3])
+        }
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_call_apdu_chaining() {
+        let mut card = Log::default();
+        let payload = (0..600).map(|i| i as u8).collect::<Vec<_>>();
+        let mut wbuf = [0; 261];
+        let mut rbuf = [0; 16];
+        let data = call_apdu(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            apdu::Command::new_with_payload_le(0x00, 0xDB, 0x3F, 0xFF, 0, &payload),
+        )
+        .unwrap();
+        assert_eq!(data, &[0x01]);
+
+        let headers = (card.0.iter())
+            .map(|c| (c[0], c[4], c.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            vec![(0x10, 255, 260), (0x10, 255, 260), (0x00, 90, 96)]
+        );
+        let sent = (card.0.iter())
+            .flat_map(|c| c[5..5 + c[4] as usize].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(sent, payload);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn test_call_apdu_sensitive() {
+        let mut card = Log::default();
+        let (mut wbuf, mut rbuf) = ([0; 16], [0; 16]);
+        let pin = [0x31, 0x32, 0x33, 0x34];
+        let data = call_apdu_sensitive(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            apdu::Command::new_with_payload(0x00, 0x20, 0x00, 0x80, &pin),
+        )
+        .unwrap();
+        assert_eq!(*data, vec![0x01]);
+        assert_eq!((wbuf, rbuf), ([0; 16], [0; 16]));
+    }
+
+    #[test]
+    fn test_call_apdu_short_buffer() {
+        let mut card = Log::default();
+        assert!(matches!(
+            call_apdu(
+                &mut card,
+                &mut [0; 4],
+                &mut [0; 16],
+                apdu::Command::new_with_le(0x00, 0xB0, 0x00, 0x00, 0),
+            ),
+            Err(Error::InsufficientBuffer)
+        ));
+        assert!(card.0.is_empty());
+    }
+
+    #[test]
+    fn test_is_mutating() {
+        assert!(is_mutating(&[0x00, 0xD6, 0x00, 0x00, 0x01, 0x00]));
+        assert!(is_mutating(&[0x00, 0x20, 0x00, 0x80, 0x00]));
+        assert!(!is_mutating(&[0x00, 0xA4, 0x04, 0x00, 0x00]));
+        assert!(!is_mutating(&[0x00, 0xB2, 0x01, 0x0C, 0x00]));
+        assert!(!is_mutating(&[0xFF, 0xCA, 0x00, 0x00, 0x00]));
+        // FeliCa Read Without Encryption, then Write Without Encryption.
+        assert!(!is_mutating(&[0xFF, 0x00, 0x00, 0x00, 0x02, 0x02, 0x06]));
+        assert!(is_mutating(&[0xFF, 0x00, 0x00, 0x00, 0x02, 0x02, 0x08]));
+        // A PN532 InDataExchange: no telling what's in it.
+        assert!(is_mutating(&[
+            0xFF, 0x00, 0x00, 0x00, 0x05, 0xD4, 0x40, 0x01, 0x30, 0x04
+        ]));
+    }
+
+    #[test]
+    fn test_is_mutating_transparent() {
+        let transceive = |frame: &[u8]| {
+            let dos = transparent::transceive_dos(frame);
+            [
+                &[0xFF, 0xC2, 0x00, 0x01, dos.len() as u8][..],
+                &dos,
+                &[0x00],
+            ]
+            .concat()
+        };
+        assert!(!is_mutating(&[
+            0xFF, 0xC2, 0x00, 0x00, 0x02, 0x81, 0x00, 0x00
+        ]));
+        assert!(!is_mutating(&[
+            0xFF, 0xC2, 0x00, 0x02, 0x04, 0x8F, 0x02, 0x03, 0x02, 0x00
+        ]));
+        assert!(!is_mutating(&transceive(&[0x02, 0x06])));
+        assert!(is_mutating(&transceive(&[0x02, 0x08])));
+        // Not a FeliCa frame; a MIFARE Ultralight WRITE, say.
+        assert!(is_mutating(&transceive(&[
+            0xA2, 0x04, 0x00, 0x00, 0x00, 0x00
+        ])));
+        // A transceive with no frame in it.
+        assert!(is_mutating(&[0xFF, 0xC2, 0x00, 0x01, 0x00]));
+    }
+
+    #[test]
+    fn test_is_mutating_storage() {
+        assert!(!is_mutating(&[0xFF, 0xB0, 0x00, 0x04, 0x10]));
+        assert!(is_mutating(&[
+            0xFF, 0xD6, 0x00, 0x04, 0x04, 0x01, 0x02, 0x03, 0x04
+        ]));
+        assert!(!is_mutating(&[0xFF, 0x00, 0x48, 0x00, 0x00]));
+        // Unknown pseudo-APDUs might do anything.
+        assert!(is_mutating(&[0xFF, 0x70, 0x07, 0x6B, 0x00]));
+    }
+
+    /// Says 90, and nothing else.
+    struct Mumble;
+
+    impl CardTransport for Mumble {
+        fn transmit<'r>(&mut self, _capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            rbuf[0] = 0x90;
+            Ok(&rbuf[..1])
+        }
+    }
+
+    #[test]
+    fn test_call_raw_short_response() {
+        let err = call_raw(&mut Mumble, &[0x00, 0xB0, 0x00, 0x00, 0x00], &mut [0; 16]).unwrap_err();
+        assert!(matches!(err, Error::Transport(..)), "{:?}", err);
+    }
+
+    #[cfg(not(feature = "write"))]
+    #[test]
+    fn test_call_apdu_read_only() {
+        let mut card = Log::default();
+        let (mut wbuf, mut rbuf) = ([0; 16], [0; 16]);
+        let err = call_apdu(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            apdu::Command::new_with_payload(0x00, 0xD6, 0x00, 0x00, &[0x00]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::ReadOnly(0xD6)));
+        // UPDATE BINARY on a storage card, and a FeliCa Write Without Encryption.
+        for cmd in [
+            apdu::Command::new_with_payload(0xFF, 0xD6, 0x00, 0x04, &[0x00; 4]),
+            apdu::Command::new_with_payload(0xFF, 0x00, 0x00, 0x00, &[0x02, 0x08]),
+        ] {
+            let err = call_apdu(&mut card, &mut wbuf, &mut rbuf, cmd).unwrap_err();
+            assert!(matches!(err, Error::ReadOnly(_)), "{:?}", err);
+        }
+        assert!(card.0.is_empty());
+        call_apdu(
+            &mut card,
+            &mut wbuf,
+            &mut rbuf,
+            apdu::Command::new_with_le(0x00, 0xB0, 0x00, 0x00, 0),
+        )
+        .unwrap();
+    }
+}
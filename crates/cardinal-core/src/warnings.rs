@@ -0,0 +1,166 @@
+//! Things that were off about what a card said, but not enough to give up on it.
+//!
+//! Parsers are lenient: an unknown tag, or a field that doesn't make sense, is skipped so
+//! we can carry on with the rest. That used to just go to the log, which is no use to
+//! anyone who isn't watching it; now parsers also [report] it, and [Warnings::collect]
+//! hands you everything reported while it ran, to show or save alongside the results.
+//!
+//! Collectors live in a thread-local, so the parsers' signatures (and all the `TryFrom`s)
+//! don't need an extra argument; they nest, and an inner one passes its warnings up to the
+//! one outside it too. Without the `std` feature there are no thread-locals, so there's no
+//! collecting either, and warnings only go to the log.
+
+use crate::HexVec;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+use tracing::warn;
+
+#[cfg(feature = "std")]
+thread_local! {
+    static COLLECTORS: RefCell<Vec<Vec<Warning>>> = const { RefCell::new(vec![]) };
+}
+
+/// Something nonstandard a parser ran into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Warning {
+    /// What was being parsed, eg. "Application" or "FileControlInfo".
+    pub context: &'static str,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum WarningKind {
+    /// A field we don't know about, with its tag and value.
+    UnknownField { tag: HexVec, value: HexVec },
+    /// A field we know about, but couldn't make sense of.
+    Unparseable { tag: HexVec, error: String },
+    /// Anything else.
+    Other(String),
+}
+
+impl core::fmt::Display for Warning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.kind {
+            WarningKind::UnknownField { tag, value } => write!(
+                f,
+                "{}: unknown field {} present: {}",
+                self.context,
+                hex::encode_upper(tag),
+                hex::encode_upper(value)
+            ),
+            WarningKind::Unparseable { tag, error } => write!(
+                f,
+                "{}: couldn't parse {}: {}",
+                self.context,
+                hex::encode_upper(tag),
+                error
+            ),
+            WarningKind::Other(msg) => write!(f, "{}: {}", self.context, msg),
+        }
+    }
+}
+
+/// Logs a warning, and hands it to whoever's collecting them (if anyone).
+pub fn report(context: &'static str, kind: WarningKind) {
+    let warning = Warning { context, kind };
+    warn!("{}", warning);
+    #[cfg(feature = "std")]
+    COLLECTORS.with_borrow_mut(|stack| {
+        if let Some(top) = stack.last_mut() {
+            top.push(warning);
+        }
+    });
+}
+
+pub fn unknown_field(context: &'static str, tag: &[u8], value: &[u8]) {
+    report(
+        context,
+        WarningKind::UnknownField {
+            tag: tag.into(),
+            value: value.into(),
+        },
+    );
+}
+
+pub fn unparseable(context: &'static str, tag: &[u8], error: impl core::fmt::Display) {
+    report(
+        context,
+        WarningKind::Unparseable {
+            tag: tag.into(),
+            error: error.to_string(),
+        },
+    );
+}
+
+pub fn other(context: &'static str, msg: impl Into<String>) {
+    report(context, WarningKind::Other(msg.into()));
+}
+
+/// Everything that was reported while a [Warnings::collect] ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+pub struct Warnings(pub Vec<Warning>);
+
+impl Warnings {
+    /// Runs `f`, and collects every warning reported on this thread while it does.
+    #[cfg(feature = "std")]
+    pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Self) {
+        // Pops the collector even if `f` panics, so the next one doesn't get ours.
+        struct Guard;
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                COLLECTORS.with_borrow_mut(|stack| {
+                    let warnings = stack.pop().unwrap_or_default();
+                    if let Some(outer) = stack.last_mut() {
+                        outer.extend(warnings);
+                    }
+                });
+            }
+        }
+
+        COLLECTORS.with_borrow_mut(|stack| stack.push(vec![]));
+        let guard = Guard;
+        let out = f();
+        let warnings = COLLECTORS.with_borrow(|stack| stack.last().cloned().unwrap_or_default());
+        drop(guard);
+        (out, Self(warnings))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, Warning> {
+        self.0.iter()
+    }
+}
+
+// Without std, there's nothing to collect.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let ((), warnings) = Warnings::collect(|| {
+            unknown_field("Test", &[0x9F, 0x6E], &[0x01]);
+            let ((), inner) = Warnings::collect(|| other("Inner", "hello"));
+            assert_eq!(inner.0.len(), 1);
+        });
+        assert_eq!(
+            warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+            vec!["Test: unknown field 9F6E present: 01", "Inner: hello"]
+        );
+
+        // Nobody's listening, so it just goes to the log.
+        other("Nobody", "hello?");
+        let ((), warnings) = Warnings::collect(|| {});
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+//! X.509 certificates, as found on PIV, eID and OpenPGP cards (among others).
+//!
+//! This isn't a validator, and doesn't check signatures; it pulls out the bits a human
+//! wants to see when asking "what's on this card?", so you don't have to pipe everything
+//! through `openssl x509` to find out. The structure is from RFC 5280, section 4.1.
+
+use crate::{ber, Error, Result};
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use base64::Engine as _;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+use tracing::trace_span;
+
+/// Summary of a certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    /// The certificate itself, without any padding that followed it.
+    pub der: Vec<u8>,
+    pub serial: Vec<u8>,
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    pub key: PublicKey,
+    /// Extended Key Usages, by name if we know it, or as a dotted OID if we don't.
+    pub ekus: Vec<String>,
+    /// SHA-256 of the DER encoding, like `openssl x509 -fingerprint -sha256`.
+    pub fingerprint: [u8; 32],
+}
+
+/// What kind of key a certificate is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa {
+        bits: usize,
+    },
+    Ec {
+        curve: String,
+    },
+    Ed25519,
+    Ed448,
+    /// Something else, by algorithm OID.
+    Other(String),
+}
+
+impl core::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Rsa { bits } => write!(f, "RSA {}", bits),
+            Self::Ec { curve } => write!(f, "EC {}", curve),
+            Self::Ed25519 => write!(f, "Ed25519"),
+            Self::Ed448 => write!(f, "Ed448"),
+            Self::Other(oid) => write!(f, "unknown ({})", oid),
+        }
+    }
+}
+
+impl Certificate {
+    /// Parses a DER-encoded certificate. Trailing data (eg. the rest of a padded EF) is
+    /// ignored.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let span = trace_span!("Certificate::parse");
+        let _enter = span.enter();
+
+        let (rest, (_, cert)) = ber::parse_next(data)?;
+        let der = &data[..data.len() - rest.len()];
+        let (_, (_, tbs)) = ber::parse_next(cert)?;
+
+        // TBSCertificate ::= SEQUENCE { [0] version, serialNumber, signature, issuer,
+        // validity, subject, subjectPublicKeyInfo, ..., [3] extensions }
+        let mut fields = ber::iter(tbs);
+        let mut next = || -> Result<(&[u8], &[u8])> {
+            fields
+                .next()
+                .ok_or(Error::X509("certificate is truncated"))?
+        };
+        let (mut tag, mut value) = next()?;
+        if tag == [0xA0] {
+            (tag, value) = next()?;
+        }
+        if tag != [0x02] {
+            return Err(Error::X509("expected a serial number"));
+        }
+        let serial = value.to_vec();
+        next()?; // signature
+        let (_, issuer) = next()?;
+        let (_, validity) = next()?;
+        let (_, subject) = next()?;
+        let (_, spki) = next()?;
+
+        let mut validity = ber::iter(validity);
+        let mut time = || -> Result<DateTime<Utc>> {
+            let (tag, value) = validity
+                .next()
+                .ok_or(Error::X509("validity is truncated"))??;
+            parse_time(tag, value).ok_or(Error::X509("invalid validity time"))
+        };
+        let (not_before, not_after) = (time()?, time()?);
+
+        let mut ekus = vec![];
+        for field in fields {
+            let (tag, value) = field?;
+            if tag == [0xA3] {
+                ekus = extended_key_usages(value)?;
+            }
+        }
+
+        Ok(Self {
+            der: der.to_vec(),
+            serial,
+            subject: name(subject).ok_or(Error::X509("invalid subject"))?,
+            issuer: name(issuer).ok_or(Error::X509("invalid issuer"))?,
+            not_before,
+            not_after,
+            key: public_key(spki)?,
+            ekus,
+            fingerprint: Sha256::digest(der).into(),
+        })
+    }
+
+    /// The certificate, PEM-encoded.
+    pub fn to_pem(&self) -> String {
+        let b64 = base64::engine::general_purpose::STANDARD.encode(&self.der);
+        let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+        for line in b64.as_bytes().chunks(64) {
+            pem.push_str(core::str::from_utf8(line).unwrap());
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+        pem
+    }
+}
+
+impl core::fmt::Display for Certificate {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "Subject:     {}", self.subject)?;
+        writeln!(f, "Issuer:      {}", self.issuer)?;
+        writeln!(f, "Serial:      {}", hex::encode_upper(&self.serial))?;
+        writeln!(f, "Not Before:  {}", self.not_before)?;
+        writeln!(f, "Not After:   {}", self.not_after)?;
+        writeln!(f, "Key:         {}", self.key)?;
+        if !self.ekus.is_empty() {
+            writeln!(f, "Key Usage:   {}", self.ekus.join(", "))?;
+        }
+        let fingerprint: Vec<_> = self
+            .fingerprint
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        write!(f, "SHA-256:     {}", fingerprint.join(":"))
+    }
+}
+
+/// UTCTime (YYMMDDHHMMSSZ) or GeneralizedTime (YYYYMMDDHHMMSSZ). RFC 5280 says both must
+/// be in UTC, with seconds, and no fractions.
+fn parse_time(tag: &[u8], value: &[u8]) -> Option<DateTime<Utc>> {
+    let s = core::str::from_utf8(value).ok()?;
+    let s = match tag {
+        // Two-digit years >= 50 are 19xx, the rest are 20xx (section 4.1.2.5.1).
+        [0x17] => format!("{}{}", if s.get(..2)? >= "50" { "19" } else { "20" }, s),
+        [0x18] => s.to_owned(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&s, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey
+/// BIT STRING }, where AlgorithmIdentifier ::= SEQUENCE { algorithm OID, parameters ANY }.
+fn public_key(spki: &[u8]) -> Result<PublicKey> {
+    let mut it = ber::iter(spki);
+    let (_, algo) = it.next().ok_or(Error::X509("missing key algorithm"))??;
+    let (_, key) = it.next().ok_or(Error::X509("missing public key"))??;
+    let mut algo = ber::iter(algo);
+    let (_, oid) = algo.next().ok_or(Error::X509("missing key algorithm"))??;
+    let params = algo.next().transpose()?.map(|(_, v)| v);
+    Ok(match oid {
+        // rsaEncryption; the key is an RSAPublicKey ::= SEQUENCE { modulus, exponent },
+        // after the BIT STRING's unused bits byte.
+        [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01] => {
+            let (_, (_, rsa)) = ber::parse_next(key.get(1..).unwrap_or_default())?;
+            let (_, (_, modulus)) = ber::parse_next(rsa)?;
+            let modulus = match modulus.iter().position(|&b| b != 0) {
+                Some(i) => &modulus[i..],
+                None => &[],
+            };
+            PublicKey::Rsa {
+                bits: modulus
+                    .first()
+                    .map_or(0, |b| modulus.len() * 8 - b.leading_zeros() as usize),
+            }
+        }
+        // id-ecPublicKey; the parameters are the curve's OID.
+        [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01] => PublicKey::Ec {
+            curve: match params {
+                Some([0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07]) => "P-256".into(),
+                Some([0x2B, 0x81, 0x04, 0x00, 0x22]) => "P-384".into(),
+                Some([0x2B, 0x81, 0x04, 0x00, 0x23]) => "P-521".into(),
+                Some([0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07]) => {
+                    "brainpoolP256r1".into()
+                }
+                Some([0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0B]) => {
+                    "brainpoolP384r1".into()
+                }
+                Some(oid) => oid_to_string(oid),
+                None => "(unspecified)".into(),
+            },
+        },
+        [0x2B, 0x65, 0x70] => PublicKey::Ed25519,
+        [0x2B, 0x65, 0x71] => PublicKey::Ed448,
+        oid => PublicKey::Other(oid_to_string(oid)),
+    })
+}
+
+/// Extensions ::= SEQUENCE OF Extension, where Extension ::= SEQUENCE { extnID OID,
+/// critical BOOLEAN DEFAULT FALSE, extnValue OCTET STRING }. We only care about one.
+fn extended_key_usages(exts: &[u8]) -> Result<Vec<String>> {
+    let (_, (_, exts)) = ber::parse_next(exts)?;
+    for ext in ber::iter(exts) {
+        let (_, ext) = ext?;
+        let mut it = ber::iter(ext);
+        let (_, oid) = it.next().ok_or(Error::X509("missing extension ID"))??;
+        if oid != [0x55, 0x1D, 0x25] {
+            continue;
+        }
+        let (_, value) = it
+            .find(|r| matches!(r, Ok(([0x04], _)) | Err(_)))
+            .ok_or(Error::X509("missing extension value"))??;
+        let (_, (_, usages)) = ber::parse_next(value)?;
+        return ber::iter(usages)
+            .map(|r| Ok(eku_name(r?.1)))
+            .collect::<Result<_>>();
+    }
+    Ok(vec![])
+}
+
+fn eku_name(oid: &[u8]) -> String {
+    match oid {
+        [0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, n] => match n {
+            0x01 => "serverAuth".into(),
+            0x02 => "clientAuth".into(),
+            0x03 => "codeSigning".into(),
+            0x04 => "emailProtection".into(),
+            0x08 => "timeStamping".into(),
+            0x09 => "OCSPSigning".into(),
+            _ => oid_to_string(oid),
+        },
+        [0x2B, 0x06, 0x01, 0x05, 0x02, 0x03, 0x04] => "pkinitClientAuth".into(),
+        [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x14, 0x02, 0x02] => "smartcardLogon".into(),
+        [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x0A, 0x03, 0x0C] => "documentSigning".into(),
+        _ => oid_to_string(oid),
+    }
+}
+
+/// Formats an X.501 Name (a SEQUENCE of SETs of (OID, value) pairs) as "CN=x, O=y".
+pub fn name(data: &[u8]) -> Option<String> {
+    let mut parts = vec![];
+    for rdn in ber::iter(data) {
+        let (_, rdn) = rdn.ok()?;
+        for atv in ber::iter(rdn) {
+            let (_, atv) = atv.ok()?;
+            let mut it = ber::iter(atv);
+            let (_, oid) = it.next()?.ok()?;
+            let (_, value) = it.next()?.ok()?;
+            let key = match oid {
+                [0x55, 0x04, 0x03] => "CN".into(),
+                [0x55, 0x04, 0x04] => "SN".into(),
+                [0x55, 0x04, 0x05] => "serialNumber".into(),
+                [0x55, 0x04, 0x06] => "C".into(),
+                [0x55, 0x04, 0x07] => "L".into(),
+                [0x55, 0x04, 0x08] => "ST".into(),
+                [0x55, 0x04, 0x0A] => "O".into(),
+                [0x55, 0x04, 0x0B] => "OU".into(),
+                [0x55, 0x04, 0x2A] => "GN".into(),
+                [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x01] => "emailAddress".into(),
+                _ => oid_to_string(oid),
+            };
+            parts.push(format!("{}={}", key, String::from_utf8_lossy(value)));
+        }
+    }
+    Some(parts.join(", "))
+}
+
+/// Formats an OID as dotted decimal (eg. "2.5.4.3").
+pub fn oid_to_string(oid: &[u8]) -> String {
+    let mut arcs = vec![];
+    let mut acc: u64 = 0;
+    for &b in oid {
+        acc = (acc << 7) | (b & 0x7F) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                // The first byte packs the first two arcs as (X * 40) + Y.
+                let first = (acc / 40).min(2);
+                arcs.push(first);
+                arcs.push(acc - first * 40);
+            } else {
+                arcs.push(acc);
+            }
+            acc = 0;
+        }
+    }
+    arcs.iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// `openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:P-256 -subj
+    /// "/CN=Test Card/O=Cardinal" -addext "extendedKeyUsage=clientAuth,1.3.6.1.4.1.311.20.2.2"`
+    const CERT: &str = concat!(
+        "308201C130820167A00302010202141782BBE5DE9AB4AC794DFBAAD25A6126638E0440300A06082A8648CE3D04030230",
+        "273112301006035504030C095465737420436172643111300F060355040A0C0843617264696E616C301E170D32363130",
+        "31363135313330315A170D3336313031333135313330315A30273112301006035504030C095465737420436172643111",
+        "300F060355040A0C0843617264696E616C3059301306072A8648CE3D020106082A8648CE3D03010703420004941814F1",
+        "E5111C607D0E5429D654C6A443A8FB3CFC060AEF2A43E073A72314B3827C33870E44801D911A9CA0D813CA277130A036",
+        "CB494D26283AE9D8F3FE1C4AA371306F301D0603551D0E041604149FA0AF5F9211CCDFD0B84760E6454031692DA5E830",
+        "1F0603551D230418301680149FA0AF5F9211CCDFD0B84760E6454031692DA5E8301F0603551D250418301606082B0601",
+        "0505070302060A2B060104018237140202300C0603551D130101FF04023000300A06082A8648CE3D0403020348003045",
+        "022100AAA710CAE606EC2169186F3DE26C25A29D8DCBF999CEF885EB67798159513F8B022026A6E94D156AF8CD95531D",
+        "16B22A451863D54387E2490B6222FDC68301479C24",
+    );
+
+    #[test]
+    fn test_parse_certificate() {
+        let mut data = hex::decode(CERT).unwrap();
+        let der = data.clone();
+        data.extend([0xFF; 16]); // Padding at the end of the EF.
+        let cert = Certificate::parse(&data).unwrap();
+        assert_eq!(cert.der, der);
+        assert_eq!(cert.subject, "CN=Test Card, O=Cardinal");
+        assert_eq!(cert.issuer, "CN=Test Card, O=Cardinal");
+        assert_eq!(
+            cert.not_before,
+            Utc.with_ymd_and_hms(2026, 10, 16, 15, 13, 1).unwrap()
+        );
+        assert_eq!(
+            cert.not_after,
+            Utc.with_ymd_and_hms(2036, 10, 13, 15, 13, 1).unwrap()
+        );
+        assert_eq!(
+            cert.key,
+            PublicKey::Ec {
+                curve: "P-256".into()
+            }
+        );
+        assert_eq!(cert.ekus, vec!["clientAuth", "smartcardLogon"]);
+        assert_eq!(
+            hex::encode_upper(cert.fingerprint),
+            "2FD657398D20030D9639120EC1C1FB4EFD6341EB501A53E0642A6067E1683287"
+        );
+        assert!(cert
+            .to_pem()
+            .starts_with("-----BEGIN CERTIFICATE-----\nMIIBwTCCAWeg"));
+    }
+
+    #[test]
+    fn test_oid_to_string() {
+        assert_eq!(oid_to_string(&[0x55, 0x04, 0x03]), "2.5.4.3");
+        assert_eq!(
+            oid_to_string(&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01]),
+            "1.2.840.113549.1.1.1"
+        );
+    }
+}
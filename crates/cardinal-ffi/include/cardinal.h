@@ -0,0 +1,55 @@
+/*
+ * C bindings for cardinal's probing and parsing layers; built as libcardinal_ffi by
+ * `cargo build -p cardinal-ffi --release`.
+ *
+ * Everything returns JSON, as a NUL-terminated string that you own, and have to give back
+ * to cardinal_free_string(). Nothing returns NULL: if something went wrong, you get
+ * {"error": "what happened"} instead.
+ *
+ * From Python:
+ *
+ *     lib = ctypes.CDLL("libcardinal_ffi.so")
+ *     lib.cardinal_parse_atr.restype = ctypes.c_void_p
+ *     s = lib.cardinal_parse_atr(raw, len(raw))
+ *     parsed = json.loads(ctypes.string_at(s))
+ *     lib.cardinal_free_string(ctypes.c_void_p(s))
+ */
+
+#ifndef CARDINAL_H
+#define CARDINAL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Probes the card in a PCSC reader. reader_name picks the reader, by name, index, substring
+ * or glob (like `cardinal --reader`); NULL for the first one with a card in it. The probe
+ * is in the same envelope as `cardinal probe -o json` writes.
+ */
+char *cardinal_probe_json(const char *reader_name);
+
+/*
+ * Parses an ATR: {"atr": ..., "warnings": [...], "known_as": [...]}.
+ */
+char *cardinal_parse_atr(const uint8_t *data, size_t len);
+
+/*
+ * Parses BER-TLV: an array of {"tag": "6F", "value": "..."} for primitive values, or
+ * {"tag": "6F", "children": [...]} for constructed ones, with tags and values in hex.
+ */
+char *cardinal_parse_tlv(const uint8_t *data, size_t len);
+
+/*
+ * Frees a string returned by any of the above. NULL is ignored.
+ */
+void cardinal_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CARDINAL_H */
@@ -0,0 +1,172 @@
+//! C bindings for the probing and parsing layers, so Python (via ctypes), C++ kiosk software
+//! and everything else that isn't Rust can use them too. The declarations are in
+//! `include/cardinal.h`.
+//!
+//! Everything returns JSON, as a NUL-terminated string that the caller owns, and has to give
+//! back to [cardinal_free_string]. Nothing returns NULL: if something went wrong, you get
+//! `{"error": "what happened"}` instead. Probes come in the same [Report] envelope as
+//! `cardinal probe -o json` writes, so `cardinal diff` can read them.
+
+use cardinal::probe::Probe;
+use cardinal::reader_quirks::ReaderQuirks;
+use cardinal::report::{Kind, Report};
+use cardinal::transports::{session::Session, Interface};
+use cardinal::{atr, ber};
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, UnwindSafe};
+
+/// Runs `f`, and turns whatever it returns (or its error, or a panic, which can't be allowed
+/// to unwind into C) into a JSON string for the caller.
+fn respond(f: impl FnOnce() -> Result<Value, String> + UnwindSafe) -> *mut c_char {
+    let value = match catch_unwind(f) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => json!({ "error": err }),
+        Err(_) => json!({ "error": "panicked" }),
+    };
+    // JSON escapes NULs in strings, and has no business having any anywhere else.
+    CString::new(value.to_string())
+        .expect("JSON with a NUL in it")
+        .into_raw()
+}
+
+/// Borrows `len` bytes from C; NULL is fine if there aren't any.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, if it's not NULL.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], String> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err("data is NULL".into()),
+        (false, _) => Ok(std::slice::from_raw_parts(data, len)),
+    }
+}
+
+/// Probes the card in a PCSC reader, and returns the probe as JSON. `reader_name` picks the
+/// reader, by name, index, substring or glob (like `cardinal --reader`); NULL for the first
+/// one with a card in it.
+///
+/// # Safety
+/// `reader_name` must be NULL, or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cardinal_probe_json(reader_name: *const c_char) -> *mut c_char {
+    let reader = match reader_name.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(reader_name).to_string_lossy().into_owned()),
+    };
+    respond(move || {
+        let mut card = Interface::Pcsc
+            .open(reader.as_deref())
+            .map_err(|err| err.to_string())?;
+        let mut card = match ReaderQuirks::detect(&mut card) {
+            Some(quirks) => Session::new(card).with_reader_quirks(quirks),
+            None => Session::new(card),
+        };
+        let probe = Probe::run(&mut card, None).map_err(|err| err.to_string())?;
+        serde_json::to_value(Report::new(Kind::Probe, &probe)).map_err(|err| err.to_string())
+    })
+}
+
+/// Parses an ATR, and returns it as JSON: `{"atr": ..., "warnings": [...], "known_as":
+/// [...]}`, where the warnings are what's wrong with it (it's parsed as far as it goes), and
+/// `known_as` is what the bundled ATR database calls it.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cardinal_parse_atr(data: *const u8, len: usize) -> *mut c_char {
+    let data = bytes(data, len);
+    respond(move || {
+        let data = data?;
+        let (parsed, warnings) = atr::parse_lenient(data).map_err(|err| err.to_string())?;
+        Ok(json!({
+            "atr": parsed,
+            "warnings": warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+            "known_as": atr::identify(data),
+        }))
+    })
+}
+
+/// Parses BER-TLV, and returns it as JSON: an array of `{"tag": "6F", "value": "..."}` for
+/// primitive values, or `{"tag": "6F", "children": [...]}` for constructed ones, with tags
+/// and values in hex.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cardinal_parse_tlv(data: *const u8, len: usize) -> *mut c_char {
+    let data = bytes(data, len);
+    respond(move || tlv_json(data?, 0))
+}
+
+fn tlv_json(data: &[u8], depth: usize) -> Result<Value, String> {
+    if depth >= ber::MAX_DEPTH {
+        return Err("TLV nested too deeply".into());
+    }
+    let mut out = vec![];
+    for tlv in ber::iter(data) {
+        let (tag, value) = tlv.map_err(|err| err.to_string())?;
+        out.push(match ber::is_constructed(tag) {
+            true => {
+                json!({ "tag": hex::encode_upper(tag), "children": tlv_json(value, depth + 1)? })
+            }
+            false => json!({ "tag": hex::encode_upper(tag), "value": hex::encode_upper(value) }),
+        });
+    }
+    Ok(Value::Array(out))
+}
+
+/// Frees a string returned by any of the above. NULL is ignored.
+///
+/// # Safety
+/// `s` must be NULL, or a string from this library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cardinal_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes a string from the library, and gives it back.
+    fn take(s: *mut c_char) -> Value {
+        let value = serde_json::from_slice(unsafe { CStr::from_ptr(s) }.to_bytes()).unwrap();
+        unsafe { cardinal_free_string(s) };
+        value
+    }
+
+    #[test]
+    fn test_parse_atr() {
+        let raw = [0x3B, 0x80, 0x81, 0x31, 0x40, 0x45, 0x35];
+        let v = take(unsafe { cardinal_parse_atr(raw.as_ptr(), raw.len()) });
+        assert_eq!(v["atr"]["tx3"]["ta"], 0x40);
+        assert_eq!(v["warnings"], json!([]));
+
+        let v = take(unsafe { cardinal_parse_atr(std::ptr::null(), 0) });
+        assert!(v["error"].is_string());
+        let v = take(unsafe { cardinal_parse_atr(std::ptr::null(), 1) });
+        assert_eq!(v["error"], "data is NULL");
+    }
+
+    #[test]
+    fn test_parse_tlv() {
+        // The 9F11 at the end is cut off before its length, so it's ignored.
+        let raw = [
+            0x6F, 0x07, 0x84, 0x02, 0x31, 0x50, 0x50, 0x01, 0x41, 0x9F, 0x11,
+        ];
+        let v = take(unsafe { cardinal_parse_tlv(raw.as_ptr(), raw.len()) });
+        assert_eq!(
+            v,
+            json!([{
+                "tag": "6F",
+                "children": [
+                    { "tag": "84", "value": "3150" },
+                    { "tag": "50", "value": "41" },
+                ],
+            }])
+        );
+    }
+}
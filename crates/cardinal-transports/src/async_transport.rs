@@ -0,0 +1,178 @@
+//! Talking to cards from async code, without blocking the executor.
+//!
+//! Everything else in cardinal blocks: PCSC does, and a contactless read can take a good
+//! fraction of a second (more, for a FeliCa dump). That's fine for the CLI, but a GUI or a
+//! network service running on tokio can't have its executor threads sat waiting for a card.
+//!
+//! An [AsyncCardTransport] owns a card, and hands it to tokio's blocking thread pool for
+//! each call, so the executor's free to do other things in the meantime. Commands are
+//! still the ordinary blocking ones, run inside [AsyncCardTransport::call]:
+//!
+//! ```no_run
+//! # async fn f() -> cardinal_core::Result<()> {
+//! use cardinal_core::iso7816::{ReadRecord, RecordID};
+//! use cardinal_transports::async_transport::AsyncCardTransport;
+//!
+//! let mut card = AsyncCardTransport::connect_pcsc(None).await?;
+//! let record = card
+//!     .call(|card, wbuf, rbuf| {
+//!         let cmd = ReadRecord { sfi: 1, id: RecordID::Number(1) };
+//!         cmd.call_owned(card, wbuf, rbuf)
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only with the `async` feature.
+
+use crate::session::Session;
+use cardinal_core::{util, CardTransport, Error, Result};
+use tracing::debug;
+
+/// A card, that's used from a blocking thread; see the [module docs](self).
+///
+/// If a call's future is dropped before it finishes, the card goes with it (the command
+/// can't be taken back once it's sent, so there's no telling what state it's in), and
+/// every call after that fails.
+pub struct AsyncCardTransport<T> {
+    card: Option<T>,
+}
+
+impl<T: CardTransport + Send + 'static> AsyncCardTransport<T> {
+    pub fn new(card: T) -> Self {
+        Self { card: Some(card) }
+    }
+
+    /// Gives the card back; None if it was lost to a cancelled call.
+    pub fn into_inner(self) -> Option<T> {
+        self.card
+    }
+
+    /// Runs `f` with the card, on tokio's blocking thread pool.
+    pub async fn run<R: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut T) -> R + Send + 'static,
+    ) -> Result<R> {
+        let mut card = self.card.take().ok_or_else(|| {
+            Error::Transport("async", "the card was lost to a cancelled call".into())
+        })?;
+        let (card, out) = tokio::task::spawn_blocking(move || {
+            let out = f(&mut card);
+            (card, out)
+        })
+        .await
+        .map_err(|err| Error::Transport("async", err.to_string()))?;
+        self.card = Some(card);
+        Ok(out)
+    }
+
+    /// Runs `f` with the card and a pair of buffers (see [CardTransport::buffers]), for
+    /// calling commands with; whatever it returns has to own its data.
+    pub async fn call<R: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut T, &mut [u8], &mut [u8]) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.run(|card| {
+            let mut bufs = card.buffers();
+            let (wbuf, rbuf) = bufs.split();
+            f(card, wbuf, rbuf)
+        })
+        .await?
+    }
+
+    /// Sends an APDU, and returns the response data, like [util::call_apdu]; but only short
+    /// ones, since it's been put together before it's sent. Use [AsyncCardTransport::call]
+    /// for anything that needs chaining.
+    pub async fn call_apdu(&mut self, cmd: apdu::Command<'_>) -> Result<Vec<u8>> {
+        let req: Vec<u8> = cmd.into();
+        debug!(req = hex::encode_upper(&req), "Sending APDU");
+        self.call(move |card, _, rbuf| Ok(util::call_raw(card, &req, rbuf)?.to_vec()))
+            .await
+    }
+
+    /// Sends a raw command APDU, and returns the whole response, SW1-SW2 and all; see
+    /// [CardTransport::transmit].
+    pub async fn transmit(&mut self, capdu: Vec<u8>) -> Result<Vec<u8>> {
+        self.call(move |card, _, rbuf| Ok(card.transmit(&capdu, rbuf)?.to_vec()))
+            .await
+    }
+
+    /// The card's ATR; see [CardTransport::atr].
+    pub async fn atr(&mut self) -> Result<Vec<u8>> {
+        self.run(|card| card.atr()).await?
+    }
+
+    /// Is the card still there? See [CardTransport::is_present].
+    pub async fn is_present(&mut self) -> Result<bool> {
+        self.run(|card| card.is_present()).await?
+    }
+}
+
+impl AsyncCardTransport<Session<pcsc::Card>> {
+    /// Connects to a card in a PCSC reader (see [crate::reader::match_reader] for `reader`),
+    /// in a [Session] that reconnects if someone else resets it.
+    pub async fn connect_pcsc(reader: Option<String>) -> Result<Self> {
+        let card = tokio::task::spawn_blocking(move || {
+            let ctx = pcsc::Context::establish(pcsc::Scope::User)?;
+            crate::reader::select_card(&ctx, reader.as_deref())
+        })
+        .await
+        .map_err(|err| Error::Transport("async", err.to_string()))??;
+        Ok(Self::new(Session::new(card).retry_on_reset(true)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cardinal_core::iso7816::{ReadRecord, RecordID};
+
+    /// Answers READ RECORD with the record number, and everything else with 6D00.
+    struct Card;
+
+    impl CardTransport for Card {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp: &[u8] = match capdu {
+                [0x00, 0xB2, num, ..] => &[0x80, 0x01, *num, 0x90, 0x00],
+                _ => &[0x6D, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_async_card_transport() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut card = AsyncCardTransport::new(Card);
+            let rsp = card
+                .call(|card, wbuf, rbuf| {
+                    let cmd = ReadRecord {
+                        sfi: 1,
+                        id: RecordID::Number(3),
+                    };
+                    cmd.call_owned(card, wbuf, rbuf)
+                })
+                .await
+                .unwrap();
+            assert_eq!(rsp.data, vec![0x80, 0x01, 0x03]);
+
+            let cmd = apdu::Command::new_with_le(0x00, 0xB2, 0x02, 0x0C, 0x00);
+            assert_eq!(card.call_apdu(cmd).await.unwrap(), vec![0x80, 0x01, 0x02]);
+            assert!(matches!(
+                card.call_apdu(apdu::Command::new(0x00, 0xCA, 0x00, 0x00))
+                    .await,
+                Err(Error::APDU(0x6D, 0x00))
+            ));
+            assert_eq!(
+                card.transmit(vec![0x00, 0xCA, 0x00, 0x00]).await.unwrap(),
+                vec![0x6D, 0x00]
+            );
+            assert!(card.into_inner().is_some());
+        });
+    }
+}
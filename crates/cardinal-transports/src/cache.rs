@@ -0,0 +1,170 @@
+//! Not asking the card the same thing twice.
+//!
+//! Selecting an application is how you ask a card about it, so things that poke at the
+//! same card over and over (`cardinal serve`, long probes) end up sending a lot of
+//! identical SELECTs. A [SelectCache] remembers what the card said, for as long as the
+//! card stays put; [crate::session::Session] uses one if you ask it to.
+//!
+//! A SELECT isn't just a question, though: it changes what's selected, and resets the
+//! application's state. So a remembered FCI is only handed out if that application is
+//! still selected, and nothing that could've changed its state has been sent since (anything
+//! but reads, basically). Applications the card doesn't have are safe to remember either
+//! way, since a failed SELECT leaves everything as it was.
+
+use std::collections::{HashMap, HashSet};
+
+/// Commands that don't change the selected application's state: READ BINARY, READ RECORD,
+/// GET DATA, GET RESPONSE and MANAGE CHANNEL.
+const READ_ONLY_INS: &[u8] = &[0xB0, 0xB1, 0xB2, 0xB3, 0xCA, 0xCB, 0xC0, 0x70];
+
+/// "File or application not found".
+const NOT_FOUND: [u8; 2] = [0x6A, 0x82];
+
+#[derive(Debug, Default)]
+pub struct SelectCache {
+    /// Responses to successful SELECTs, by P2 and DF name.
+    found: HashMap<(u8, Vec<u8>), Vec<u8>>,
+    /// DF names the card said it doesn't have.
+    not_found: HashSet<Vec<u8>>,
+    /// What's currently selected on the basic channel, as long as we're sure it's untouched.
+    current: Option<Vec<u8>>,
+    pub stats: CacheStats,
+}
+
+/// How well the cache is doing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// SELECTs answered from the cache.
+    pub hits: u64,
+    /// SELECTs that went to the card.
+    pub misses: u64,
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} hits, {} misses", self.hits, self.misses)
+    }
+}
+
+/// Picks apart a SELECT by DF name, on the basic channel: returns P2 and the name.
+fn select_by_name(capdu: &[u8]) -> Option<(u8, &[u8])> {
+    let [cla, 0xA4, 0x04, p2, lc, rest @ ..] = capdu else {
+        return None;
+    };
+    let name = rest.get(..*lc as usize)?;
+    // Only the first occurrence; "next" depends on what came before.
+    (is_basic_channel(*cla) && p2 & 0x03 == 0).then_some((*p2, name))
+}
+
+fn is_basic_channel(cla: u8) -> bool {
+    cla != 0xFF && cla & 0x43 == 0
+}
+
+impl SelectCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers a command from the cache, if it can: copies the response into `rbuf`, and
+    /// returns its length.
+    pub fn lookup(&mut self, capdu: &[u8], rbuf: &mut [u8]) -> Option<usize> {
+        let (p2, name) = select_by_name(capdu)?;
+        let rsp = match self.not_found.contains(name) {
+            true => &NOT_FOUND[..],
+            false if self.current.as_deref() == Some(name) => {
+                self.found.get(&(p2, name.to_vec()))?
+            }
+            false => return None,
+        };
+        let out = rbuf.get_mut(..rsp.len())?;
+        out.copy_from_slice(rsp);
+        self.stats.hits += 1;
+        Some(rsp.len())
+    }
+
+    /// Remembers what the card said to a command that went to it.
+    pub fn update(&mut self, capdu: &[u8], rapdu: &[u8]) {
+        let [cla, ins, ..] = *capdu else {
+            return;
+        };
+        if let Some((p2, name)) = select_by_name(capdu) {
+            self.stats.misses += 1;
+            match rapdu {
+                [.., 0x90, 0x00] => {
+                    self.found.insert((p2, name.to_vec()), rapdu.to_vec());
+                    self.current = Some(name.to_vec());
+                }
+                [.., 0x6A, 0x82] => {
+                    self.not_found.insert(name.to_vec());
+                }
+                _ => self.current = None,
+            }
+        } else if is_basic_channel(cla) && !READ_ONLY_INS.contains(&ins) {
+            self.current = None;
+        }
+    }
+
+    /// Forgets what's selected, eg. because commands were sent behind our back; what the
+    /// card has is still remembered.
+    pub fn deselect(&mut self) {
+        self.current = None;
+    }
+
+    /// Forgets everything, eg. because the card was reset or swapped.
+    pub fn clear(&mut self) {
+        self.found.clear();
+        self.not_found.clear();
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECT_PPSE: &[u8] = &[
+        0x00, 0xA4, 0x04, 0x00, 0x0E, 0x32, 0x50, 0x41, 0x59, 0x2E, 0x53, 0x59, 0x53, 0x2E, 0x44,
+        0x44, 0x46, 0x30, 0x31, 0x00,
+    ];
+
+    #[test]
+    fn test_select_cache() {
+        let mut cache = SelectCache::new();
+        let mut rbuf = [0; 16];
+        assert_eq!(cache.lookup(SELECT_PPSE, &mut rbuf), None);
+        cache.update(SELECT_PPSE, &[0x6F, 0x00, 0x90, 0x00]);
+        assert_eq!(cache.lookup(SELECT_PPSE, &mut rbuf), Some(4));
+        assert_eq!(rbuf[..4], [0x6F, 0x00, 0x90, 0x00]);
+
+        // Reading doesn't change anything; anything else might have.
+        cache.update(&[0x00, 0xB2, 0x01, 0x0C, 0x00], &[0x90, 0x00]);
+        assert_eq!(cache.lookup(SELECT_PPSE, &mut rbuf), Some(4));
+        cache.update(&[0x80, 0xA8, 0x00, 0x00, 0x02, 0x83, 0x00], &[0x90, 0x00]);
+        assert_eq!(cache.lookup(SELECT_PPSE, &mut rbuf), None);
+
+        // Not found is always not found.
+        let select_pse = &[0x00, 0xA4, 0x04, 0x00, 0x02, 0x31, 0x50, 0x00];
+        cache.update(select_pse, &NOT_FOUND);
+        assert_eq!(cache.lookup(select_pse, &mut rbuf), Some(2));
+        assert_eq!(rbuf[..2], NOT_FOUND);
+        assert_eq!(cache.stats, CacheStats { hits: 3, misses: 2 });
+
+        cache.clear();
+        assert_eq!(cache.lookup(select_pse, &mut rbuf), None);
+    }
+
+    #[test]
+    fn test_select_by_name() {
+        assert_eq!(
+            select_by_name(&[0x00, 0xA4, 0x04, 0x0C, 0x02, 0x31, 0x50]),
+            Some((0x0C, &[0x31, 0x50][..]))
+        );
+        // Next occurrence, a logical channel, and a FID.
+        assert_eq!(select_by_name(&[0x00, 0xA4, 0x04, 0x02, 0x01, 0x31]), None);
+        assert_eq!(select_by_name(&[0x01, 0xA4, 0x04, 0x00, 0x01, 0x31]), None);
+        assert_eq!(
+            select_by_name(&[0x00, 0xA4, 0x00, 0x00, 0x02, 0x2F, 0x00]),
+            None
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Escape commands: talking to the reader itself, through SCardControl.
+//!
+//! Unlike pseudo-APDUs (see [cardinal_core::reader_quirks]), these don't need a card in the
+//! reader, as long as you connect to it directly (see [crate::reader::connect_direct]);
+//! which is how you'd turn off a reader's polling before a card turns up, for instance.
+//!
+//! What's in them is entirely up to the vendor. The helpers in here speak ACS: the ACR122U
+//! takes its usual `FF 00 ..` pseudo-APDUs, and the ACR1252U and newer take `E0 00 00 ..`
+//! commands, and answer with `E1 00 00 ..`. (pcsc-lite's CCID driver only passes escapes
+//! through for readers marked as allowing it in its Info.plist; ACS' own driver does.)
+
+use crate::reader::Led;
+use cardinal_core::reader_quirks::ReaderQuirks;
+use cardinal_core::{CardTransport, Error, Result};
+use std::time::Duration;
+use tracing::{debug, trace_span};
+
+/// Control code for escape commands: IOCTL_CCID_ESCAPE on Windows, and pcsc-lite's
+/// IOCTL_SMARTCARD_VENDOR_IFD_EXCHANGE everywhere else.
+pub fn escape_code() -> pcsc::DWORD {
+    if cfg!(windows) {
+        pcsc::ctl_code(3500)
+    } else {
+        pcsc::ctl_code(1)
+    }
+}
+
+/// Sends an escape command to the reader, and returns whatever it says back.
+pub fn escape<'r>(
+    card: &mut impl CardTransport,
+    data: &[u8],
+    rbuf: &'r mut [u8],
+) -> Result<&'r [u8]> {
+    let span = trace_span!("escape");
+    let _enter = span.enter();
+
+    debug!(data = hex::encode_upper(data), "Sending escape");
+    let rsp = card.control(escape_code(), data, rbuf)?;
+    debug!(rsp = hex::encode_upper(rsp), "Escape response");
+    Ok(rsp)
+}
+
+/// Which escape commands an ACS reader takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// ACR122U: the same pseudo-APDUs it takes with a card in it.
+    Acr122,
+    /// ACR1252U, ACR1255U, ACR1281U and friends.
+    Acr1252,
+}
+
+impl Dialect {
+    /// Works out the dialect from what we know about the reader; None if it's not ACS.
+    pub fn of(quirks: &ReaderQuirks) -> Option<Self> {
+        match quirks.model.to_lowercase() {
+            _ if !quirks.escape => None,
+            model if model.contains("acr122") => Some(Self::Acr122),
+            _ => Some(Self::Acr1252),
+        }
+    }
+}
+
+/// Sends an ACR1252-style command, and returns the data from its answer.
+fn acr1252<'r>(
+    card: &mut impl CardTransport,
+    cmd: u8,
+    data: &[u8],
+    rbuf: &'r mut [u8],
+) -> Result<&'r [u8]> {
+    let wbuf = [&[0xE0, 0x00, 0x00, cmd, data.len() as u8][..], data].concat();
+    match escape(card, &wbuf, rbuf)? {
+        [0xE1, 0x00, 0x00, 0x00, len, data @ ..] if data.len() >= *len as usize => {
+            Ok(&data[..*len as usize])
+        }
+        rsp => Err(Error::Transport(
+            "escape",
+            format!("unexpected response: {}", hex::encode_upper(rsp)),
+        )),
+    }
+}
+
+/// Sends an ACR122-style pseudo-APDU; it answers with 90 and a value (or nothing else).
+fn acr122(card: &mut impl CardTransport, capdu: &[u8]) -> Result<Option<u8>> {
+    let mut rbuf = [0; 16];
+    match *escape(card, capdu, &mut rbuf)? {
+        [0x90, v] => Ok(Some(v)),
+        [] => Ok(None),
+        [sw1, sw2] => Err(Error::APDU(sw1, sw2)),
+        ref rsp => Err(Error::Transport(
+            "escape",
+            format!("unexpected response: {}", hex::encode_upper(rsp)),
+        )),
+    }
+}
+
+/// The reader's firmware version, eg. "ACR1252U_V402.0".
+pub fn firmware_version(card: &mut impl CardTransport, dialect: Dialect) -> Result<String> {
+    let span = trace_span!("firmware_version", ?dialect);
+    let _enter = span.enter();
+
+    let mut rbuf = [0; 64];
+    let version = match dialect {
+        // No status word; it's just the version.
+        Dialect::Acr122 => escape(card, &[0xFF, 0x00, 0x48, 0x00, 0x00], &mut rbuf)?,
+        Dialect::Acr1252 => acr1252(card, 0x18, &[], &mut rbuf)?,
+    };
+    Ok(String::from_utf8_lossy(version)
+        .trim_end_matches('\0')
+        .to_owned())
+}
+
+/// Beeps the buzzer, for about this long (in 100ms steps on the ACR122U, 10ms otherwise).
+pub fn buzzer(card: &mut impl CardTransport, dialect: Dialect, duration: Duration) -> Result<()> {
+    let span = trace_span!("buzzer", ?dialect, ?duration);
+    let _enter = span.enter();
+
+    let steps = |ms: u128| (duration.as_millis() / ms).clamp(1, 0xFF) as u8;
+    match dialect {
+        // LED untouched, T1 = duration, once, buzzer on during T1.
+        Dialect::Acr122 => {
+            acr122(
+                card,
+                &[0xFF, 0x00, 0x40, 0x00, 0x04, steps(100), 0x00, 0x01, 0x01],
+            )?;
+        }
+        Dialect::Acr1252 => {
+            acr1252(card, 0x28, &[steps(10)], &mut [0; 16])?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets the red/green LED; like [crate::reader::set_led], but with no card needed.
+pub fn led(card: &mut impl CardTransport, dialect: Dialect, led: Led) -> Result<()> {
+    let span = trace_span!("led", ?dialect, ?led);
+    let _enter = span.enter();
+
+    let (red, green) = (led == Led::Red, led == Led::Green);
+    match dialect {
+        // Same as set_led: update both, no blinking, buzzer off.
+        Dialect::Acr122 => {
+            let p2 = 0b0000_1100 | (red as u8) | ((green as u8) << 1);
+            acr122(card, &[0xFF, 0x00, 0x40, p2, 0x04, 0x00, 0x00, 0x00, 0x00])?;
+        }
+        Dialect::Acr1252 => {
+            acr1252(
+                card,
+                0x29,
+                &[(red as u8) | ((green as u8) << 1)],
+                &mut [0; 16],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Turns the reader's automatic polling for cards on or off. With it off, cards won't show
+/// up on their own; something has to ask the reader to look for them (eg. over libnfc).
+pub fn set_auto_polling(card: &mut impl CardTransport, dialect: Dialect, on: bool) -> Result<()> {
+    let span = trace_span!("set_auto_polling", ?dialect, on);
+    let _enter = span.enter();
+
+    match dialect {
+        // PICC Operating Parameter: bit 7 is auto polling; leave the rest as they are.
+        Dialect::Acr122 => {
+            let param = acr122(card, &[0xFF, 0x00, 0x50, 0x00, 0x00])?.unwrap_or(0xFF);
+            let param = (param & 0x7F) | ((on as u8) << 7);
+            acr122(card, &[0xFF, 0x00, 0x51, param, 0x00])?;
+        }
+        // Automatic PICC Polling: bit 0 is polling at all; the rest are power saving.
+        Dialect::Acr1252 => {
+            let mut rbuf = [0; 16];
+            let param = (acr1252(card, 0x23, &[], &mut rbuf)?.first().copied()).unwrap_or(0);
+            let param = (param & !0x01) | on as u8;
+            acr1252(card, 0x23, &[param], &mut rbuf)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers escapes like an ACR1252U, and remembers what it was sent.
+    #[derive(Default)]
+    struct Acr1252(Vec<Vec<u8>>);
+
+    impl CardTransport for Acr1252 {
+        fn transmit<'r>(&mut self, _: &[u8], _: &'r mut [u8]) -> Result<&'r [u8]> {
+            Err(pcsc::Error::NoSmartcard.into())
+        }
+
+        fn control<'r>(
+            &mut self,
+            code: pcsc::DWORD,
+            data: &[u8],
+            rbuf: &'r mut [u8],
+        ) -> Result<&'r [u8]> {
+            assert_eq!(code, escape_code());
+            self.0.push(data.to_vec());
+            let rsp: &[u8] = match data {
+                [0xE0, 0x00, 0x00, 0x18, 0x00] => b"\xE1\x00\x00\x00\x0FACR1252U_V402.0",
+                [0xE0, 0x00, 0x00, 0x23, 0x00] => &[0xE1, 0x00, 0x00, 0x00, 0x01, 0x8F],
+                [0xE0, 0x00, 0x00, cmd, 0x01, v] if *cmd >= 0x23 => {
+                    &[0xE1, 0x00, 0x00, 0x00, 0x01, *v]
+                }
+                _ => &[0x63, 0x00],
+            };
+            rbuf[..rsp.len()].copy_from_slice(rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_dialect() {
+        let acr1252 = ReaderQuirks::for_reader("ACS", "ACR1252U");
+        assert_eq!(Dialect::of(&acr1252), Some(Dialect::Acr1252));
+        let acr122 = ReaderQuirks::for_reader("ACS", "ACS ACR122U PICC Interface 00 00");
+        assert_eq!(Dialect::of(&acr122), Some(Dialect::Acr122));
+        let identiv = ReaderQuirks::for_reader("Identiv", "uTrust 3700 F");
+        assert_eq!(Dialect::of(&identiv), None);
+    }
+
+    #[test]
+    fn test_acr1252() {
+        let mut card = Acr1252::default();
+        assert_eq!(
+            firmware_version(&mut card, Dialect::Acr1252).unwrap(),
+            "ACR1252U_V402.0"
+        );
+        buzzer(&mut card, Dialect::Acr1252, Duration::from_millis(500)).unwrap();
+        assert_eq!(card.0[1], vec![0xE0, 0x00, 0x00, 0x28, 0x01, 50]);
+        led(&mut card, Dialect::Acr1252, Led::Green).unwrap();
+        assert_eq!(card.0[2], vec![0xE0, 0x00, 0x00, 0x29, 0x01, 0b10]);
+
+        // Reads the current setting, and only touches the polling bit.
+        set_auto_polling(&mut card, Dialect::Acr1252, false).unwrap();
+        assert_eq!(card.0[4], vec![0xE0, 0x00, 0x00, 0x23, 0x01, 0x8E]);
+
+        assert!(acr1252(&mut card, 0x99, &[], &mut [0; 16]).is_err());
+    }
+}
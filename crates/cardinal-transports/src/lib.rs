@@ -0,0 +1,144 @@
+//! Everything that talks to actual hardware, as opposed to parsing what it says.
+
+#[cfg(feature = "async")]
+pub mod async_transport;
+pub mod cache;
+pub mod escape;
+#[cfg(feature = "nfc")]
+pub mod nfc;
+#[cfg(feature = "pn532")]
+pub mod pn532;
+pub mod reader;
+pub mod session;
+pub mod tcp;
+pub mod trace;
+
+use cardinal_core::{CardTransport, Error, Result};
+
+/// How to reach the card; parsed from eg. `--interface nfc:pn532_uart:/dev/ttyUSB0`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Interface {
+    /// A PCSC reader (the default).
+    #[default]
+    Pcsc,
+    /// A libnfc device, by connstring (or the default one).
+    #[cfg(feature = "nfc")]
+    Nfc(Option<String>),
+    /// A PN532 on a serial port.
+    #[cfg(feature = "pn532")]
+    Pn532(String),
+    /// A reader on another machine, shared with `cardinal serve`; `host[:port]`.
+    Tcp(String),
+}
+
+impl Interface {
+    /// Is this PCSC? (Some things, like reader attributes, only exist there.)
+    pub fn is_pcsc(&self) -> bool {
+        matches!(self, Self::Pcsc)
+    }
+
+    /// Connects to a card through this interface. For PCSC, `reader` picks the reader (see
+    /// [reader::match_reader]); other interfaces don't have that concept.
+    pub fn open(&self, reader: Option<&str>) -> Result<Box<dyn CardTransport>> {
+        self.open_with(reader, pcsc::Protocols::ANY)
+    }
+
+    /// Like [Interface::open], but only with `protocols` (see [reader::select_card_with]).
+    /// Only PCSC gets a say in the protocol; everything else ignores it.
+    pub fn open_with(
+        &self,
+        reader: Option<&str>,
+        protocols: pcsc::Protocols,
+    ) -> Result<Box<dyn CardTransport>> {
+        match self {
+            Self::Pcsc => {
+                let ctx = pcsc::Context::establish(pcsc::Scope::User)?;
+                Ok(Box::new(reader::select_card_with(&ctx, reader, protocols)?))
+            }
+            #[cfg(feature = "nfc")]
+            Self::Nfc(connstring) => Ok(Box::new(nfc::NfcTransport::open(
+                connstring.as_deref(),
+                &[
+                    nfc::Modulation::ISO14443A,
+                    nfc::Modulation::ISO14443B,
+                    nfc::Modulation::FeliCa,
+                ],
+            )?)),
+            #[cfg(feature = "pn532")]
+            Self::Pn532(path) => Ok(Box::new(pn532::Pn532Transport::open_serial(
+                path,
+                &[
+                    pn532::Modulation::ISO14443A,
+                    pn532::Modulation::ISO14443B,
+                    pn532::Modulation::FeliCa,
+                ],
+            )?)),
+            Self::Tcp(addr) => Ok(Box::new(tcp::TcpTransport::connect(addr)?)),
+        }
+    }
+}
+
+impl std::str::FromStr for Interface {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        match (kind, arg) {
+            ("pcsc", None) => Ok(Self::Pcsc),
+            #[cfg(feature = "nfc")]
+            ("nfc", arg) => Ok(Self::Nfc(arg.map(Into::into))),
+            #[cfg(feature = "pn532")]
+            ("pn532", Some(path)) => Ok(Self::Pn532(path.into())),
+            ("tcp", Some(addr)) => Ok(Self::Tcp(addr.into())),
+            _ => Err(Error::Transport(
+                "interface",
+                format!("unknown or unsupported interface: {}", s),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Interface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pcsc => write!(f, "pcsc"),
+            #[cfg(feature = "nfc")]
+            Self::Nfc(None) => write!(f, "nfc"),
+            #[cfg(feature = "nfc")]
+            Self::Nfc(Some(connstring)) => write!(f, "nfc:{}", connstring),
+            #[cfg(feature = "pn532")]
+            Self::Pn532(path) => write!(f, "pn532:{}", path),
+            Self::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interface() {
+        assert_eq!("pcsc".parse::<Interface>().unwrap(), Interface::Pcsc);
+        assert!("pcsc:what".parse::<Interface>().is_err());
+        assert!("carrier-pigeon".parse::<Interface>().is_err());
+        let iface: Interface = "tcp:lab-pc:35963".parse().unwrap();
+        assert_eq!(iface, Interface::Tcp("lab-pc:35963".into()));
+        assert_eq!(iface.to_string(), "tcp:lab-pc:35963");
+        #[cfg(feature = "nfc")]
+        {
+            assert_eq!("nfc".parse::<Interface>().unwrap(), Interface::Nfc(None));
+            let iface: Interface = "nfc:pn532_uart:/dev/ttyUSB0".parse().unwrap();
+            assert_eq!(iface.to_string(), "nfc:pn532_uart:/dev/ttyUSB0");
+        }
+        #[cfg(feature = "pn532")]
+        {
+            let iface: Interface = "pn532:/dev/ttyUSB0".parse().unwrap();
+            assert_eq!(iface, Interface::Pn532("/dev/ttyUSB0".into()));
+            assert!("pn532".parse::<Interface>().is_err());
+        }
+    }
+}
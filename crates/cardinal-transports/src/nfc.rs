@@ -0,0 +1,307 @@
+//! libnfc backend, for PN53x/ACR122-style readers without (working) PCSC drivers.
+//!
+//! This talks to libnfc directly over FFI; it's a handful of functions, and it means you
+//! only need libnfc itself installed, not a -sys crate that wants bindgen and clang.
+//!
+//! ISO 14443-A/B cards get APDUs passed straight through (libnfc does the ISO-DEP framing
+//! for us). FeliCa cards don't speak APDUs at all, so we unwrap the `FF 00 00 00` FeliCa
+//! pseudo-APDU that [cardinal_core::felica] sends, and send the frame inside it as-is.
+
+use cardinal_core::felica;
+use cardinal_core::protocol::Protocol;
+use cardinal_core::{CardTransport, Error, Result};
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use tracing::{debug, trace, trace_span};
+
+/// Which kind of card to look for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modulation {
+    ISO14443A,
+    ISO14443B,
+    /// FeliCa, at 212kbps.
+    FeliCa,
+}
+
+/// A card found by a libnfc reader.
+pub struct NfcTransport {
+    ctx: *mut ffi::nfc_context,
+    dev: *mut ffi::nfc_device,
+    modulation: Modulation,
+    uid: Vec<u8>,
+}
+
+impl NfcTransport {
+    /// Opens a libnfc device (eg. `pn532_uart:/dev/ttyUSB0`, or None for the default one),
+    /// and looks for a card using each of the given modulations in turn.
+    pub fn open(connstring: Option<&str>, modulations: &[Modulation]) -> Result<Self> {
+        let span = trace_span!("NfcTransport::open", connstring, ?modulations);
+        let _enter = span.enter();
+
+        let connstring = connstring
+            .map(|s| CString::new(s).map_err(|err| Error::Transport("nfc", err.to_string())))
+            .transpose()?;
+
+        let mut ctx = ptr::null_mut();
+        unsafe { ffi::nfc_init(&mut ctx) };
+        if ctx.is_null() {
+            return Err(Error::Transport("nfc", "couldn't initialise libnfc".into()));
+        }
+        let dev =
+            unsafe { ffi::nfc_open(ctx, connstring.as_ref().map_or(ptr::null(), |s| s.as_ptr())) };
+        // From here on, dropping `transport` cleans up for us.
+        let mut transport = Self {
+            ctx,
+            dev,
+            modulation: Modulation::ISO14443A,
+            uid: vec![],
+        };
+        if dev.is_null() {
+            return Err(Error::Transport("nfc", "couldn't open device".into()));
+        }
+        debug!(name = ?unsafe { CStr::from_ptr(ffi::nfc_device_get_name(dev)) }, "Opened device");
+
+        transport.check(unsafe { ffi::nfc_initiator_init(dev) })?;
+        // Otherwise, it'll sit there waiting for the first modulation forever.
+        transport.check(unsafe {
+            ffi::nfc_device_set_property_bool(dev, ffi::NP_INFINITE_SELECT, false)
+        })?;
+        for &modulation in modulations {
+            transport.modulation = modulation;
+            if transport.select()? {
+                return Ok(transport);
+            }
+        }
+        Err(Error::Transport("nfc", "no card found".into()))
+    }
+
+    /// Which modulation the card was found with.
+    pub fn modulation(&self) -> Modulation {
+        self.modulation
+    }
+
+    /// UID (ISO 14443-A), PUPI (ISO 14443-B) or IDm (FeliCa) of the card.
+    pub fn uid(&self) -> &[u8] {
+        &self.uid
+    }
+
+    fn select(&mut self) -> Result<bool> {
+        let (nmt, nbr, init): (c_int, c_int, &[u8]) = match self.modulation {
+            Modulation::ISO14443A => (ffi::NMT_ISO14443A, ffi::NBR_106, &[]),
+            Modulation::ISO14443B => (ffi::NMT_ISO14443B, ffi::NBR_106, &[]),
+            // Polling for any system code (FFFF), requesting the system code back.
+            Modulation::FeliCa => (
+                ffi::NMT_FELICA,
+                ffi::NBR_212,
+                &[0x00, 0xFF, 0xFF, 0x01, 0x00],
+            ),
+        };
+        let mut target = std::mem::MaybeUninit::<ffi::nfc_target>::zeroed();
+        let found = self.check(unsafe {
+            ffi::nfc_initiator_select_passive_target(
+                self.dev,
+                ffi::nfc_modulation { nmt, nbr },
+                if init.is_empty() {
+                    ptr::null()
+                } else {
+                    init.as_ptr()
+                },
+                init.len(),
+                target.as_mut_ptr(),
+            )
+        })?;
+        if found == 0 {
+            debug!(modulation = ?self.modulation, "No card found");
+            return Ok(false);
+        }
+
+        let target = unsafe { target.assume_init() };
+        self.uid = unsafe {
+            match self.modulation {
+                Modulation::ISO14443A => {
+                    let info = &target.nti.nai;
+                    info.abtUid[..info.szUidLen.min(10)].to_vec()
+                }
+                Modulation::ISO14443B => target.nti.nbi.abtPupi.to_vec(),
+                Modulation::FeliCa => target.nti.nfi.abtId.to_vec(),
+            }
+        };
+        debug!(modulation = ?self.modulation, uid = format!("{:02X?}", self.uid), "Found card");
+        Ok(true)
+    }
+
+    /// Turns a libnfc return code into an error, if it is one.
+    fn check(&self, ret: c_int) -> Result<usize> {
+        if ret >= 0 {
+            Ok(ret as usize)
+        } else {
+            let msg = unsafe { CStr::from_ptr(ffi::nfc_strerror(self.dev)) };
+            Err(Error::Transport("nfc", msg.to_string_lossy().into()))
+        }
+    }
+}
+
+impl CardTransport for NfcTransport {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let span = trace_span!("NfcTransport::transmit");
+        let _enter = span.enter();
+
+        // FeliCa frames are wrapped in a pseudo-APDU for PCSC; here we can send them as-is,
+        // and fake a 9000 on the way back so it looks the same as it would through PCSC.
+        let (tx, wrapped) = match (self.modulation, felica::unwrap_apdu(capdu)) {
+            (Modulation::FeliCa, Some(frame)) => (frame, true),
+            (Modulation::FeliCa, None) => {
+                return Err(Error::Transport(
+                    "nfc",
+                    "FeliCa cards only take FeliCa commands".into(),
+                ))
+            }
+            _ => (capdu, false),
+        };
+        if wrapped && rbuf.len() < 2 {
+            return Err(Error::InsufficientBuffer);
+        }
+        let rx_max = rbuf.len() - if wrapped { 2 } else { 0 };
+
+        trace!(tx = format!("{:02X?}", tx), ">> NFC");
+        let len = self.check(unsafe {
+            ffi::nfc_initiator_transceive_bytes(
+                self.dev,
+                tx.as_ptr(),
+                tx.len(),
+                rbuf.as_mut_ptr(),
+                rx_max,
+                -1, // Default timeout.
+            )
+        })?;
+        trace!(rx = format!("{:02X?}", &rbuf[..len]), "<< NFC");
+
+        if wrapped {
+            rbuf[len..len + 2].copy_from_slice(&[0x90, 0x00]);
+            Ok(&rbuf[..len + 2])
+        } else {
+            Ok(&rbuf[..len])
+        }
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        Protocol::T1
+    }
+}
+
+impl Drop for NfcTransport {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.dev.is_null() {
+                ffi::nfc_close(self.dev);
+            }
+            ffi::nfc_exit(self.ctx);
+        }
+    }
+}
+
+/// Just enough of libnfc's API (1.7/1.8) to select a card and talk to it.
+#[allow(non_camel_case_types, non_snake_case, dead_code)]
+mod ffi {
+    use super::{c_char, c_int};
+
+    pub enum nfc_context {}
+    pub enum nfc_device {}
+
+    // nfc_modulation_type
+    pub const NMT_ISO14443A: c_int = 1;
+    pub const NMT_ISO14443B: c_int = 3;
+    pub const NMT_FELICA: c_int = 7;
+
+    // nfc_property
+    pub const NP_INFINITE_SELECT: c_int = 7;
+
+    // nfc_baud_rate
+    pub const NBR_106: c_int = 1;
+    pub const NBR_212: c_int = 2;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct nfc_modulation {
+        pub nmt: c_int,
+        pub nbr: c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct nfc_iso14443a_info {
+        pub abtAtqa: [u8; 2],
+        pub btSak: u8,
+        pub szUidLen: usize,
+        pub abtUid: [u8; 10],
+        pub szAtsLen: usize,
+        pub abtAts: [u8; 254],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct nfc_felica_info {
+        pub szLen: usize,
+        pub btResCode: u8,
+        pub abtId: [u8; 8],
+        pub abtPad: [u8; 8],
+        pub abtSysCode: [u8; 2],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct nfc_iso14443b_info {
+        pub abtPupi: [u8; 4],
+        pub abtApplicationData: [u8; 4],
+        pub abtProtocolInfo: [u8; 3],
+        pub ui8CardIdentifier: u8,
+    }
+
+    /// The real thing has more members, but nfc_iso14443a_info is the biggest, so the
+    /// layout is the same.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub union nfc_target_info {
+        pub nai: nfc_iso14443a_info,
+        pub nfi: nfc_felica_info,
+        pub nbi: nfc_iso14443b_info,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct nfc_target {
+        pub nti: nfc_target_info,
+        pub nm: nfc_modulation,
+    }
+
+    #[link(name = "nfc")]
+    extern "C" {
+        pub fn nfc_init(context: *mut *mut nfc_context);
+        pub fn nfc_exit(context: *mut nfc_context);
+        pub fn nfc_open(context: *mut nfc_context, connstring: *const c_char) -> *mut nfc_device;
+        pub fn nfc_close(pnd: *mut nfc_device);
+        pub fn nfc_device_get_name(pnd: *mut nfc_device) -> *const c_char;
+        pub fn nfc_strerror(pnd: *const nfc_device) -> *const c_char;
+        pub fn nfc_device_set_property_bool(
+            pnd: *mut nfc_device,
+            property: c_int,
+            bEnable: bool,
+        ) -> c_int;
+        pub fn nfc_initiator_init(pnd: *mut nfc_device) -> c_int;
+        pub fn nfc_initiator_select_passive_target(
+            pnd: *mut nfc_device,
+            nm: nfc_modulation,
+            pbtInitData: *const u8,
+            szInitData: usize,
+            pnt: *mut nfc_target,
+        ) -> c_int;
+        pub fn nfc_initiator_transceive_bytes(
+            pnd: *mut nfc_device,
+            pbtTx: *const u8,
+            szTx: usize,
+            pbtRx: *mut u8,
+            szRx: usize,
+            timeout: c_int,
+        ) -> c_int;
+    }
+}
@@ -0,0 +1,324 @@
+//! PN532 over a serial port (HSU), as commonly wired up to a Raspberry Pi or a USB-UART.
+//!
+//! The PN532 speaks its own framing (PN532 User Manual, section 6.2): every command goes
+//! in an information frame, which it ACKs, and then sends a response frame. We only need
+//! a few commands: SAMConfiguration to wake it up, InListPassiveTarget to find a card,
+//! and then either InDataExchange (ISO-DEP; it does the ISO 14443-4 framing for us) or
+//! InCommunicateThru (raw frames, for FeliCa) to talk to it.
+
+use cardinal_core::protocol::Protocol;
+use cardinal_core::{felica, CardTransport, Error, Result};
+use std::io::{Read, Write};
+use std::time::Duration;
+use tracing::{debug, trace, trace_span};
+
+const TFI_HOST: u8 = 0xD4;
+const TFI_PN532: u8 = 0xD5;
+
+const CMD_SAM_CONFIGURATION: u8 = 0x14;
+const CMD_RF_CONFIGURATION: u8 = 0x32;
+const CMD_IN_LIST_PASSIVE_TARGET: u8 = 0x4A;
+const CMD_IN_DATA_EXCHANGE: u8 = 0x40;
+const CMD_IN_COMMUNICATE_THRU: u8 = 0x42;
+
+/// Which kind of card to look for (InListPassiveTarget's BrTy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modulation {
+    ISO14443A = 0x00,
+    FeliCa = 0x01,
+    ISO14443B = 0x03,
+}
+
+fn err(msg: impl Into<String>) -> Error {
+    Error::Transport("pn532", msg.into())
+}
+
+/// Encodes a normal information frame.
+pub fn encode_frame(cmd: u8, data: &[u8]) -> Vec<u8> {
+    let len = (data.len() + 2) as u8;
+    let sum = data
+        .iter()
+        .fold(TFI_HOST.wrapping_add(cmd), |acc, b| acc.wrapping_add(*b));
+    let mut frame = vec![0x00, 0x00, 0xFF, len, len.wrapping_neg(), TFI_HOST, cmd];
+    frame.extend_from_slice(data);
+    frame.extend_from_slice(&[sum.wrapping_neg(), 0x00]);
+    frame
+}
+
+/// Reads one frame from the PN532, and returns its payload (everything after the TFI).
+fn read_frame(port: &mut impl Read) -> Result<Vec<u8>> {
+    fn byte(port: &mut impl Read) -> Result<u8> {
+        let mut b = [0u8];
+        port.read_exact(&mut b).map_err(|e| err(e.to_string()))?;
+        Ok(b[0])
+    }
+
+    // Skip over the preamble (and any noise) until the start code.
+    let mut prev = 0xFF;
+    loop {
+        let b = byte(port)?;
+        if prev == 0x00 && b == 0xFF {
+            break;
+        }
+        prev = b;
+    }
+    let (len, lcs) = (byte(port)?, byte(port)?);
+    if (len, lcs) == (0x00, 0xFF) {
+        byte(port)?; // Postamble.
+        return Ok(vec![]); // ACK.
+    }
+    if len == 0 || len.wrapping_add(lcs) != 0 {
+        return Err(err("bad length checksum"));
+    }
+    let mut body = vec![0u8; len as usize + 2]; // + DCS, postamble.
+    port.read_exact(&mut body).map_err(|e| err(e.to_string()))?;
+    let sum = body[..=len as usize]
+        .iter()
+        .fold(0u8, |acc, b| acc.wrapping_add(*b));
+    if sum != 0 {
+        return Err(err("bad data checksum"));
+    }
+    match body[0] {
+        TFI_PN532 => Ok(body[1..len as usize].to_vec()),
+        0x7F => Err(err("PN532 reported a syntax error")),
+        tfi => Err(err(format!("unexpected TFI: {:02X}", tfi))),
+    }
+}
+
+/// A card found by a PN532.
+pub struct Pn532Transport<P> {
+    port: P,
+    modulation: Modulation,
+    uid: Vec<u8>,
+}
+
+impl Pn532Transport<Box<dyn serialport::SerialPort>> {
+    /// Opens a PN532 on the given serial port, and looks for a card using each of the given
+    /// modulations in turn.
+    pub fn open_serial(path: &str, modulations: &[Modulation]) -> Result<Self> {
+        let port = serialport::new(path, 115_200)
+            .timeout(Duration::from_secs(1))
+            .open()
+            .map_err(|e| err(format!("{}: {}", path, e)))?;
+        Self::open(port, modulations)
+    }
+}
+
+impl<P: Read + Write> Pn532Transport<P> {
+    /// Wakes up a PN532, and looks for a card using each of the given modulations in turn.
+    pub fn open(port: P, modulations: &[Modulation]) -> Result<Self> {
+        let span = trace_span!("Pn532Transport::open", ?modulations);
+        let _enter = span.enter();
+
+        let mut pn532 = Self {
+            port,
+            modulation: Modulation::ISO14443A,
+            uid: vec![],
+        };
+
+        // It's asleep after power-on; a long run of 0x55s wakes it up (User Manual, 7.2.11).
+        pn532.write(&[0x55, 0x55, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])?;
+        // Normal mode (no SAM), and don't use the IRQ pin.
+        pn532.command(CMD_SAM_CONFIGURATION, &[0x01, 0x14, 0x00])?;
+        // Only try to activate a card once, so we can move on to the next modulation.
+        pn532.command(CMD_RF_CONFIGURATION, &[0x05, 0xFF, 0x01, 0x01])?;
+
+        for &modulation in modulations {
+            pn532.modulation = modulation;
+            if pn532.select()? {
+                return Ok(pn532);
+            }
+        }
+        Err(err("no card found"))
+    }
+
+    /// UID (ISO 14443-A), PUPI (ISO 14443-B) or IDm (FeliCa) of the card.
+    pub fn uid(&self) -> &[u8] {
+        &self.uid
+    }
+
+    /// Which modulation the card was found with.
+    pub fn modulation(&self) -> Modulation {
+        self.modulation
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.port
+            .write_all(data)
+            .and_then(|_| self.port.flush())
+            .map_err(|e| err(e.to_string()))
+    }
+
+    /// Sends a command, waits for the ACK, and returns the response data.
+    fn command(&mut self, cmd: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let frame = encode_frame(cmd, data);
+        trace!(frame = format!("{:02X?}", frame), ">> PN532");
+        self.write(&frame)?;
+        if !read_frame(&mut self.port)?.is_empty() {
+            return Err(err("expected an ACK"));
+        }
+        let rsp = read_frame(&mut self.port)?;
+        trace!(rsp = format!("{:02X?}", rsp), "<< PN532");
+        match rsp.split_first() {
+            Some((code, rest)) if *code == cmd + 1 => Ok(rest.to_vec()),
+            _ => Err(err(format!("unexpected response to {:02X}", cmd))),
+        }
+    }
+
+    fn select(&mut self) -> Result<bool> {
+        let mut data = vec![0x01, self.modulation as u8];
+        match self.modulation {
+            Modulation::ISO14443A => {}
+            // AFI: all families.
+            Modulation::ISO14443B => data.push(0x00),
+            // Polling for any system code (FFFF), requesting the system code back.
+            Modulation::FeliCa => data.extend_from_slice(&[0x00, 0xFF, 0xFF, 0x01, 0x00]),
+        }
+        let rsp = self.command(CMD_IN_LIST_PASSIVE_TARGET, &data)?;
+        if rsp.first().copied().unwrap_or(0) == 0 {
+            debug!(modulation = ?self.modulation, "No card found");
+            return Ok(false);
+        }
+
+        // [NbTg, Tg, ...target data]
+        let target = rsp.get(2..).unwrap_or_default();
+        self.uid = match self.modulation {
+            // SENS_RES (2), SEL_RES, NFCIDLength, NFCID1...
+            Modulation::ISO14443A => target
+                .get(3)
+                .and_then(|&len| target.get(4..4 + len as usize))
+                .map(|v| v.to_vec()),
+            // ATQB: 0x50, PUPI (4), ...
+            Modulation::ISO14443B => target.get(1..5).map(|v| v.to_vec()),
+            // POL_RES length, response code, IDm (8), ...
+            Modulation::FeliCa => target.get(2..10).map(|v| v.to_vec()),
+        }
+        .ok_or_else(|| err("truncated target data"))?;
+        debug!(modulation = ?self.modulation, uid = format!("{:02X?}", self.uid), "Found card");
+        Ok(true)
+    }
+}
+
+impl<P: Read + Write> CardTransport for Pn532Transport<P> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let span = trace_span!("Pn532Transport::transmit");
+        let _enter = span.enter();
+
+        // FeliCa goes through raw, and gets a fake 9000 on the way back, like through PCSC.
+        let (rsp, wrapped) = match (self.modulation, felica::unwrap_apdu(capdu)) {
+            (Modulation::FeliCa, Some(frame)) => {
+                (self.command(CMD_IN_COMMUNICATE_THRU, frame)?, true)
+            }
+            (Modulation::FeliCa, None) => {
+                return Err(err("FeliCa cards only take FeliCa commands"))
+            }
+            _ => {
+                let mut data = vec![0x01]; // Tg
+                data.extend_from_slice(capdu);
+                (self.command(CMD_IN_DATA_EXCHANGE, &data)?, false)
+            }
+        };
+
+        // [Status, DataIn...]
+        let (status, data) = rsp.split_first().ok_or_else(|| err("empty response"))?;
+        if status & 0x3F != 0 {
+            return Err(err(format!("error status {:02X}", status & 0x3F)));
+        }
+        let len = data.len() + if wrapped { 2 } else { 0 };
+        if len > rbuf.len() {
+            return Err(Error::InsufficientBuffer);
+        }
+        rbuf[..data.len()].copy_from_slice(data);
+        if wrapped {
+            rbuf[data.len()..len].copy_from_slice(&[0x90, 0x00]);
+        }
+        Ok(&rbuf[..len])
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        Protocol::T1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACK: &[u8] = &[0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+
+    /// Serial port that plays back canned responses, and records what was written.
+    struct MockPort {
+        rx: std::io::Cursor<Vec<u8>>,
+        tx: Vec<u8>,
+    }
+
+    impl Read for MockPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for MockPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A response frame, as the PN532 would send it.
+    fn response(cmd: u8, data: &[u8]) -> Vec<u8> {
+        let mut frame = encode_frame(cmd + 1, data);
+        frame[5] = TFI_PN532;
+        let dcs = frame.len() - 2;
+        frame[dcs] = frame[5..dcs]
+            .iter()
+            .fold(0u8, |acc, b| acc.wrapping_add(*b))
+            .wrapping_neg();
+        let mut v = ACK.to_vec();
+        v.extend(frame);
+        v
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        // GetFirmwareVersion, from the User Manual.
+        assert_eq!(
+            encode_frame(0x02, &[]),
+            vec![0x00, 0x00, 0xFF, 0x02, 0xFE, 0xD4, 0x02, 0x2A, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_transmit_iso14443a() {
+        let mut rx = vec![];
+        rx.extend(response(CMD_SAM_CONFIGURATION, &[]));
+        rx.extend(response(CMD_RF_CONFIGURATION, &[]));
+        rx.extend(response(
+            CMD_IN_LIST_PASSIVE_TARGET,
+            &[0x01, 0x01, 0x00, 0x04, 0x20, 0x04, 0xDE, 0xAD, 0xBE, 0xEF],
+        ));
+        rx.extend(response(
+            CMD_IN_DATA_EXCHANGE,
+            &[0x00, 0x6F, 0x00, 0x90, 0x00],
+        ));
+        let port = MockPort {
+            rx: std::io::Cursor::new(rx),
+            tx: vec![],
+        };
+
+        let mut pn532 = Pn532Transport::open(port, &[Modulation::ISO14443A]).unwrap();
+        assert_eq!(pn532.uid(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let mut rbuf = [0u8; 16];
+        let rsp = pn532
+            .transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+            .unwrap();
+        assert_eq!(rsp, &[0x6F, 0x00, 0x90, 0x00]);
+        assert!(pn532.port.tx.ends_with(&encode_frame(
+            CMD_IN_DATA_EXCHANGE,
+            &[0x01, 0x00, 0xA4, 0x04, 0x00, 0x00]
+        )));
+    }
+}
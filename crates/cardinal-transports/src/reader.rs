@@ -0,0 +1,680 @@
+//! Finding readers, and waiting for cards to show up in them.
+
+use cardinal_core::buffers::Buffers;
+use cardinal_core::reader_quirks::{ReaderQuirks, UidMethod};
+use cardinal_core::response_limits::ResponseLimits;
+use cardinal_core::retry::RetryPolicy;
+use cardinal_core::transport::Reset;
+use cardinal_core::{CardTransport, Error, Result};
+use std::ffi::CString;
+use std::time::Duration;
+use tracing::{debug, trace_span, warn};
+
+/// Something happened to a card in a reader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CardEvent {
+    Inserted(CString),
+    Removed(CString),
+}
+
+/// Blocks until a card is inserted into or removed from any reader. Returns None if the
+/// timeout passes, or if a reader was plugged in or out instead; just call it again.
+///
+/// This starts from scratch every time, so anything that happens in between calls is
+/// missed; to wait for cards in a loop, use a [CardWatcher].
+pub fn pcsc_wait_for_card(
+    ctx: &pcsc::Context,
+    timeout: Option<Duration>,
+) -> Result<Option<CardEvent>> {
+    CardWatcher::new(ctx)?.wait(ctx, timeout)
+}
+
+/// Watches readers for cards coming and going.
+///
+/// It remembers what it's already told you, so a card that comes or goes between calls to
+/// [CardWatcher::wait] (while you were busy with the last one, say) is still an event.
+pub struct CardWatcher {
+    /// The PnP notification pseudo-reader, then the readers.
+    states: Vec<pcsc::ReaderState>,
+}
+
+impl CardWatcher {
+    /// Starts watching every reader; cards that are already in them weren't inserted.
+    pub fn new(ctx: &pcsc::Context) -> Result<Self> {
+        let mut watcher = Self {
+            states: vec![pcsc::ReaderState::new(
+                pcsc::PNP_NOTIFICATION(),
+                pcsc::State::UNAWARE,
+            )],
+        };
+        watcher.refresh(ctx)?;
+        Ok(watcher)
+    }
+
+    /// Is there a card in this reader, as far as we know?
+    pub fn is_present(&self, name: &std::ffi::CStr) -> bool {
+        (self.states.iter().skip(1))
+            .find(|state| state.name() == name)
+            .is_some_and(|state| state.current_state().contains(pcsc::State::PRESENT))
+    }
+
+    /// Blocks until a card is inserted into or removed from any reader. Returns None if the
+    /// timeout passes, or if a reader was plugged in or out instead; just call it again.
+    pub fn wait(
+        &mut self,
+        ctx: &pcsc::Context,
+        timeout: Option<Duration>,
+    ) -> Result<Option<CardEvent>> {
+        let span = trace_span!("CardWatcher::wait");
+        let _enter = span.enter();
+
+        // Something we haven't said yet, from last time round.
+        if let Some(event) = self.next_event() {
+            return Ok(Some(event));
+        }
+        // Whatever else changed (eg. someone else using a card) doesn't interest us.
+        for state in self.states.iter_mut() {
+            state.sync_current_state();
+        }
+        match ctx.get_status_change(timeout, &mut self.states) {
+            Ok(()) => {}
+            Err(pcsc::Error::Timeout) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        if self.states[0].event_state().contains(pcsc::State::CHANGED) {
+            debug!("Readers plugged in or out");
+            self.refresh(ctx)?;
+        }
+        Ok(self.next_event())
+    }
+
+    /// The first reader where a card's come or gone since we last said; saying so brings
+    /// it up to date, and the rest are left for next time.
+    fn next_event(&mut self) -> Option<CardEvent> {
+        for state in self.states.iter_mut().skip(1) {
+            let was_present = state.current_state().contains(pcsc::State::PRESENT);
+            let is_present = state.event_state().contains(pcsc::State::PRESENT);
+            if was_present == is_present {
+                continue;
+            }
+            debug!(reader = ?state.name(), was_present, is_present, "Reader state changed");
+            state.sync_current_state();
+            let name = state.name().into();
+            return Some(if is_present {
+                CardEvent::Inserted(name)
+            } else {
+                CardEvent::Removed(name)
+            });
+        }
+        None
+    }
+
+    /// Picks up readers that were plugged in, and forgets ones that were unplugged; new
+    /// ones start from how they are now.
+    fn refresh(&mut self, ctx: &pcsc::Context) -> Result<()> {
+        let names = ctx.list_readers_owned()?;
+        let pnp = pcsc::PNP_NOTIFICATION();
+        (self.states)
+            .retain(|state| state.name() == pnp || names.iter().any(|n| **n == *state.name()));
+        for name in names {
+            if !self
+                .states
+                .iter()
+                .any(|state| state.name() == name.as_c_str())
+            {
+                self.states
+                    .push(pcsc::ReaderState::new(name, pcsc::State::UNAWARE));
+            }
+        }
+
+        match ctx.get_status_change(Duration::ZERO, &mut self.states) {
+            Ok(()) | Err(pcsc::Error::Timeout) => {}
+            Err(err) => return Err(err.into()),
+        }
+        for (i, state) in self.states.iter_mut().enumerate() {
+            if i == 0 || state.current_state() == pcsc::State::UNAWARE {
+                state.sync_current_state();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Connects to a reader, picked by `query` (see [match_reader]), or the first one if None.
+pub fn select_card(ctx: &pcsc::Context, query: Option<&str>) -> Result<pcsc::Card> {
+    select_card_with(ctx, query, pcsc::Protocols::ANY)
+}
+
+/// Like [select_card], but only with `protocols`; for a contact card that does both T=0 and
+/// T=1, this picks which one the reader negotiates.
+pub fn select_card_with(
+    ctx: &pcsc::Context,
+    query: Option<&str>,
+    protocols: pcsc::Protocols,
+) -> Result<pcsc::Card> {
+    let span = trace_span!("select_card", query, ?protocols);
+    let _enter = span.enter();
+
+    let names = ctx.list_readers_owned()?;
+    let name = match query {
+        Some(query) => match_reader(&names, query)?,
+        None => names.first().ok_or(pcsc::Error::NoReadersAvailable)?,
+    };
+    debug!(?name, "Connecting to reader");
+    Ok(ctx.connect(name, pcsc::ShareMode::Shared, protocols)?)
+}
+
+/// Connects to the reader itself, rather than a card in it, picked like [select_card]. This
+/// works with no card in it, for [crate::escape] commands; there's no card to transmit to.
+pub fn connect_direct(ctx: &pcsc::Context, query: Option<&str>) -> Result<pcsc::Card> {
+    let span = trace_span!("connect_direct", query);
+    let _enter = span.enter();
+
+    let names = ctx.list_readers_owned()?;
+    let name = match query {
+        Some(query) => match_reader(&names, query)?,
+        None => names.first().ok_or(pcsc::Error::NoReadersAvailable)?,
+    };
+    debug!(?name, "Connecting directly to reader");
+    Ok(ctx.connect(name, pcsc::ShareMode::Direct, pcsc::Protocols::UNDEFINED)?)
+}
+
+/// Picks a reader by name. In order of preference, `query` can be:
+///
+/// - The exact name of the reader.
+/// - An index into the list, as printed by `cardinal list-readers`.
+/// - A glob pattern (`*` and `?`), or otherwise a substring; both case-insensitive.
+///
+/// If more than one reader matches, you get an error instead of a coin toss.
+pub fn match_reader<'n>(names: &'n [CString], query: &str) -> Result<&'n CString> {
+    if let Some(name) = names.iter().find(|n| n.to_bytes() == query.as_bytes()) {
+        return Ok(name);
+    }
+    if let Some(name) = query.parse::<usize>().ok().and_then(|i| names.get(i)) {
+        return Ok(name);
+    }
+
+    let query_lc = query.to_lowercase();
+    let matches: Vec<_> = names
+        .iter()
+        .filter(|n| {
+            let name = n.to_string_lossy().to_lowercase();
+            if query.contains(['*', '?']) {
+                glob_match(query_lc.as_bytes(), name.as_bytes())
+            } else {
+                name.contains(&query_lc)
+            }
+        })
+        .collect();
+    let lossy = |ns: &[&CString]| -> Vec<String> {
+        ns.iter()
+            .map(|n| n.to_string_lossy().into_owned())
+            .collect()
+    };
+    match matches[..] {
+        [name] => Ok(name),
+        [] => Err(Error::ReaderNotFound {
+            query: query.into(),
+            available: lossy(&names.iter().collect::<Vec<_>>()),
+        }),
+        _ => Err(Error::AmbiguousReader {
+            query: query.into(),
+            matches: lossy(&matches),
+        }),
+    }
+}
+
+// Just enough glob for reader names: `*` is any number of anything, `?` is one anything.
+fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    match (pattern.first(), s.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], s) || (!s.is_empty() && glob_match(pattern, &s[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &s[1..]),
+        (Some(p), Some(c)) if p == c => glob_match(&pattern[1..], &s[1..]),
+        _ => false,
+    }
+}
+
+/// State of a reader's red/green LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Led {
+    Off,
+    Red,
+    Green,
+}
+
+/// Sets the reader's LED, on ACS readers (ACR122U and friends) that have a red/green one.
+/// Other readers will return an error, which is safe to ignore.
+pub fn set_led(card: &mut impl CardTransport, led: Led) -> Result<()> {
+    let span = trace_span!("set_led", ?led);
+    let _enter = span.enter();
+
+    // If we know it's not an ACS reader, don't send it ACS escapes.
+    if card.reader_quirks().is_some_and(|quirks| !quirks.escape) {
+        return Err(Error::Transport(
+            "pcsc",
+            "reader doesn't have LED control".into(),
+        ));
+    }
+
+    // P2: bit 1-2 = final red/green state, bit 3-4 = update red/green. No blinking, and
+    // leave the buzzer alone (T1, T2, repetitions, link = 0).
+    let p2 = 0b0000_1100
+        | match led {
+            Led::Off => 0b00,
+            Led::Red => 0b01,
+            Led::Green => 0b10,
+        };
+    let mut rbuf = [0; 8];
+    // ACS answers with 90 XX, where XX is the new LED state.
+    match *card.transmit(
+        &[0xFF, 0x00, 0x40, p2, 0x04, 0x00, 0x00, 0x00, 0x00],
+        &mut rbuf,
+    )? {
+        [0x90, _] => Ok(()),
+        [sw1, sw2] => Err(Error::APDU(sw1, sw2)),
+        _ => Err(Error::Transport(
+            "pcsc",
+            "malformed LED control response".into(),
+        )),
+    }
+}
+
+/// Survives the card being pulled out halfway through something long, like dumping every
+/// block on a FeliCa card.
+///
+/// When a command fails, this asks the reader if the card is still there. If it is, the
+/// card just said no, and you get its answer as usual. If it isn't, `wait` is called (to
+/// tell the user, and wait for the card to come back), then we reconnect and send the same
+/// command again.
+///
+/// That only carries on from where it stopped for commands that don't depend on what came
+/// before, like FeliCa's (which each carry the IDm). A card that's been put back has
+/// forgotten what was selected, so anything else belongs in a [CardTransport::transaction]
+/// (see [cardinal_core::transport::transaction]), which is run again from the top instead.
+pub struct Reattach<T: CardTransport, F: FnMut() -> Result<()>> {
+    pub inner: T,
+    wait: F,
+    /// The card's UID, so we can tell if a different one was put back.
+    uid: Option<Vec<u8>>,
+}
+
+impl<T: CardTransport, F: FnMut() -> Result<()>> Reattach<T, F> {
+    pub fn new(mut inner: T, wait: F) -> Self {
+        let uid = read_uid(&mut inner);
+        Self { inner, wait, uid }
+    }
+
+    fn reattach(&mut self) -> Result<()> {
+        let span = trace_span!("Reattach::reattach");
+        let _enter = span.enter();
+
+        loop {
+            (self.wait)()?;
+            match self.inner.reconnect() {
+                Ok(()) => break,
+                Err(err) => warn!("Couldn't reconnect to the card: {}", err),
+            }
+        }
+        let uid = read_uid(&mut self.inner);
+        if self.uid.is_some() && uid != self.uid {
+            return Err(Error::Transport(
+                "reattach",
+                format!(
+                    "a different card was put back: expected UID {}, got {}",
+                    hex::encode_upper(self.uid.as_deref().unwrap_or_default()),
+                    hex::encode_upper(uid.as_deref().unwrap_or_default()),
+                ),
+            ));
+        }
+        debug!("Card is back");
+        Ok(())
+    }
+}
+
+impl<T: CardTransport, F: FnMut() -> Result<()>> CardTransport for Reattach<T, F> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        loop {
+            let result = self.inner.transmit(capdu, rbuf).map(|rapdu| rapdu.len());
+            let ok = matches!(result, Ok(len) if len >= 2 && matches!(rbuf[len - 2], 0x90 | 0x61));
+            let removed = !ok
+                && match &result {
+                    Err(err) if err.is_card_removed() => true,
+                    _ => !self.inner.is_present().unwrap_or(true),
+                };
+            if !removed {
+                return result.map(move |len| &rbuf[..len]);
+            }
+            warn!(capdu = hex::encode_upper(capdu), "Card was removed");
+            self.reattach()?;
+        }
+    }
+
+    fn protocol(&mut self) -> cardinal_core::protocol::Protocol {
+        self.inner.protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.inner.atr()
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.inner.get_attribute(attr, rbuf)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        self.inner.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.inner.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.inner.reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        self.inner.reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.inner.retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        self.inner.reader_quirks()
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        self.inner.response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        self.inner.learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> Buffers {
+        self.inner.buffers()
+    }
+
+    /// A card that's pulled out takes the transaction with it; once it's back, `f` is run
+    /// again from the top.
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            let result = self.inner.transaction(&mut *f);
+            let removed = match &result {
+                Ok(()) => false,
+                Err(err) if err.is_card_removed() => true,
+                Err(_) => !self.inner.is_present().unwrap_or(true),
+            };
+            if !removed {
+                return result;
+            }
+            warn!("Card was removed during a transaction");
+            self.reattach()?;
+        }
+    }
+}
+
+/// Asks the reader for the card's UID; None if it won't say.
+fn read_uid(card: &mut impl CardTransport) -> Option<Vec<u8>> {
+    if card
+        .reader_quirks()
+        .is_some_and(|quirks| quirks.uid == UidMethod::None)
+    {
+        return None;
+    }
+    let mut rbuf = [0; 32];
+    match *card
+        .transmit(&[0xFF, 0xCA, 0x00, 0x00, 0x00], &mut rbuf)
+        .ok()?
+    {
+        [ref uid @ .., 0x90, 0x00] => Some(uid.to_vec()),
+        _ => None,
+    }
+}
+
+/// Extra methods for pcsc::Context.
+pub trait ContextExt {
+    /// Connects to every reader that has a card in it, one at a time, as you iterate.
+    fn cards(&self) -> Result<Cards<'_>> {
+        self.cards_with(pcsc::Protocols::ANY)
+    }
+
+    /// Like [ContextExt::cards], but only with `protocols` (see [select_card_with]).
+    fn cards_with(&self, protocols: pcsc::Protocols) -> Result<Cards<'_>>;
+}
+
+impl ContextExt for pcsc::Context {
+    fn cards_with(&self, protocols: pcsc::Protocols) -> Result<Cards<'_>> {
+        let span = trace_span!("cards");
+        let _enter = span.enter();
+
+        let mut states: Vec<_> = self
+            .list_readers_owned()?
+            .into_iter()
+            .map(|name| pcsc::ReaderState::new(name, pcsc::State::UNAWARE))
+            .collect();
+        self.get_status_change(Duration::ZERO, &mut states)?;
+
+        let names: Vec<_> = states
+            .iter()
+            .filter(|state| state.event_state().contains(pcsc::State::PRESENT))
+            .map(|state| state.name().to_owned())
+            .collect();
+        debug!(readers = ?names, "Readers with cards present");
+        Ok(Cards {
+            ctx: self,
+            names: names.into_iter(),
+            protocols,
+        })
+    }
+}
+
+/// Iterator returned by ContextExt::cards().
+pub struct Cards<'ctx> {
+    ctx: &'ctx pcsc::Context,
+    names: std::vec::IntoIter<CString>,
+    protocols: pcsc::Protocols,
+}
+
+impl Iterator for Cards<'_> {
+    type Item = Result<(CString, pcsc::Card)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let name = self.names.next()?;
+        Some(
+            self.ctx
+                .connect(&name, pcsc::ShareMode::Shared, self.protocols)
+                .map(|card| (name, card))
+                .map_err(Error::from),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readers() -> Vec<CString> {
+        [
+            "SONY FeliCa Port/PaSoRi 3.0 [00]",
+            "ACS ACR122U PICC Interface 00 00",
+            "ACS ACR39U ICC Reader 01 00",
+        ]
+        .into_iter()
+        .map(|n| CString::new(n).unwrap())
+        .collect()
+    }
+
+    /// Remembers the last command; answers everything like an ACR122U would.
+    #[derive(Default)]
+    struct AcsCard(Vec<u8>);
+
+    impl CardTransport for AcsCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            self.0 = capdu.to_vec();
+            rbuf[..2].copy_from_slice(&[0x90, capdu[3] & 0x03]);
+            Ok(&rbuf[..2])
+        }
+    }
+
+    #[test]
+    fn test_set_led() {
+        let mut card = AcsCard::default();
+        set_led(&mut card, Led::Green).unwrap();
+        assert_eq!(
+            card.0,
+            vec![0xFF, 0x00, 0x40, 0x0E, 0x04, 0x00, 0x00, 0x00, 0x00]
+        );
+        set_led(&mut card, Led::Off).unwrap();
+        assert_eq!(card.0[3], 0x0C);
+
+        // Readers we know aren't ACS don't get sent ACS escapes at all.
+        let quirks = ReaderQuirks::for_reader("Identiv", "uTrust 3700 F");
+        let mut card = crate::session::Session::new(AcsCard::default()).with_reader_quirks(quirks);
+        assert!(set_led(&mut card, Led::Green).is_err());
+        assert_eq!(card.inner.0, vec![]);
+    }
+
+    /// A card with a UID, that vanishes after answering `left` more commands.
+    struct FlakyCard {
+        uid: Vec<u8>,
+        left: usize,
+        gone: bool,
+        reconnects: usize,
+    }
+
+    impl CardTransport for FlakyCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            if self.left == 0 {
+                self.gone = true;
+                return Err(pcsc::Error::RemovedCard.into());
+            }
+            self.left -= 1;
+            let rapdu = match capdu {
+                [0xFF, 0xCA, ..] => [&self.uid[..], &[0x90, 0x00]].concat(),
+                _ => vec![0x6A, 0x82],
+            };
+            rbuf[..rapdu.len()].copy_from_slice(&rapdu);
+            Ok(&rbuf[..rapdu.len()])
+        }
+
+        fn is_present(&mut self) -> Result<bool> {
+            Ok(!self.gone)
+        }
+
+        fn reconnect(&mut self) -> Result<()> {
+            self.reconnects += 1;
+            (self.left, self.gone) = (10, false);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reattach() {
+        let card = FlakyCard {
+            uid: vec![0x01, 0x02, 0x03, 0x04],
+            left: 2,
+            gone: false,
+            reconnects: 0,
+        };
+        let mut waits = 0;
+        let mut card = Reattach::new(card, || {
+            waits += 1;
+            Ok(())
+        });
+        let mut rbuf = [0; 16];
+        // Refused, but still there.
+        assert_eq!(
+            card.transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+                .unwrap(),
+            &[0x6A, 0x82]
+        );
+        // Gone; it comes back, and the same command goes through.
+        assert_eq!(
+            card.transmit(&[0xFF, 0xCA, 0x00, 0x00, 0x00], &mut rbuf)
+                .unwrap(),
+            &[0x01, 0x02, 0x03, 0x04, 0x90, 0x00]
+        );
+        assert_eq!(card.inner.reconnects, 1);
+
+        // Someone else's card.
+        card.inner.left = 0;
+        card.inner.uid = vec![0x05, 0x06, 0x07, 0x08];
+        assert!(card
+            .transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+            .is_err());
+        drop(card);
+        assert_eq!(waits, 2);
+    }
+
+    #[test]
+    fn test_reattach_transaction() {
+        let card = FlakyCard {
+            uid: vec![0x01, 0x02, 0x03, 0x04],
+            left: 2,
+            gone: false,
+            reconnects: 0,
+        };
+        let mut card = Reattach::new(card, || Ok(()));
+        // Pulled out after the SELECT; once it's back, it's selected again before the read.
+        let mut sent = vec![];
+        cardinal_core::transport::transaction(&mut card, |card| {
+            let mut rbuf = [0; 16];
+            for capdu in [
+                &[0x00, 0xA4, 0x04, 0x00, 0x00][..],
+                &[0x00, 0xB2, 0x01, 0x0C],
+            ] {
+                card.transmit(capdu, &mut rbuf)?;
+                sent.push(capdu[1]);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sent, vec![0xA4, 0xA4, 0xB2]);
+        assert_eq!(card.inner.reconnects, 1);
+    }
+
+    #[test]
+    fn test_match_reader_exact_and_index() {
+        let names = readers();
+        assert_eq!(
+            match_reader(&names, "ACS ACR39U ICC Reader 01 00").unwrap(),
+            &names[2]
+        );
+        assert_eq!(match_reader(&names, "1").unwrap(), &names[1]);
+    }
+
+    #[test]
+    fn test_match_reader_fuzzy() {
+        let names = readers();
+        assert_eq!(match_reader(&names, "pasori").unwrap(), &names[0]);
+        assert_eq!(match_reader(&names, "acs*picc*").unwrap(), &names[1]);
+        assert_eq!(match_reader(&names, "*ACR39?*").unwrap(), &names[2]);
+    }
+
+    #[test]
+    fn test_match_reader_errors() {
+        let names = readers();
+        assert!(matches!(
+            match_reader(&names, "acs"),
+            Err(Error::AmbiguousReader { matches, .. }) if matches.len() == 2
+        ));
+        assert!(matches!(
+            match_reader(&names, "yubikey"),
+            Err(Error::ReaderNotFound { available, .. }) if available.len() == 3
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! Holding on to a card that other processes want too.
+//!
+//! PCSC readers are shared: anything else on the machine (a browser's smartcard
+//! middleware, gpg-agent, another cardinal) can send its own commands in the middle of a
+//! long probe, and select something else, or even reset the card. A [Session] gives you
+//! two ways to deal with that: do things in a [Session::transaction], so nobody else can get
+//! a word in, and/or have it reconnect and try again when the card was reset anyway.
+//!
+//! It's also where a [RetryPolicy] goes, for cards that are there, but flaky, a
+//! [SelectCache], for things that keep selecting the same applications, the reader's
+//! [ReaderQuirks], the card's [ResponseLimits], once they've been found out, and a
+//! [BufferPool] for commands to borrow buffers from.
+
+use crate::cache::{CacheStats, SelectCache};
+use cardinal_core::buffers::{BufferPool, Buffers};
+use cardinal_core::reader_quirks::ReaderQuirks;
+use cardinal_core::response_limits::ResponseLimits;
+use cardinal_core::retry::RetryPolicy;
+use cardinal_core::transport::Reset;
+use cardinal_core::{CardTransport, Result};
+use tracing::{debug, trace_span, warn};
+
+/// How many times in a row to reconnect after a reset, before giving up on the card.
+pub const MAX_RESET_RETRIES: usize = 3;
+
+pub struct Session<T: CardTransport> {
+    pub inner: T,
+    retry_on_reset: bool,
+    retry_policy: Option<RetryPolicy>,
+    select_cache: Option<SelectCache>,
+    reader_quirks: Option<ReaderQuirks>,
+    response_limits: ResponseLimits,
+    buffers: BufferPool,
+}
+
+impl<T: CardTransport> Session<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            retry_on_reset: false,
+            retry_policy: None,
+            select_cache: None,
+            reader_quirks: None,
+            response_limits: ResponseLimits::default(),
+            buffers: BufferPool::new(),
+        }
+    }
+
+    /// If the card is reset by someone else, reconnect and try again, instead of failing
+    /// with `Error::PCSC(ResetCard)`.
+    ///
+    /// A reset card has forgotten everything, including which application was selected, so
+    /// on its own this is only safe for commands that don't depend on what came before.
+    /// Transactions are retried from the top, which is what you want for everything else.
+    pub fn retry_on_reset(mut self, retry: bool) -> Self {
+        self.retry_on_reset = retry;
+        self
+    }
+
+    /// Retries commands sent with [cardinal_core::util::call_apdu] that fail in ways this
+    /// policy thinks are worth another go (and gives up on them after its timeout).
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Answers repeated SELECTs from a [SelectCache], rather than asking the card again;
+    /// it's forgotten whenever the card is reset, reconnected to or removed.
+    pub fn cache_selects(mut self, cache: bool) -> Self {
+        self.select_cache = cache.then(SelectCache::new);
+        self
+    }
+
+    /// Tells commands what the reader can do (eg. from [ReaderQuirks::detect]), so they
+    /// don't have to find out by trial and error.
+    pub fn with_reader_quirks(mut self, quirks: ReaderQuirks) -> Self {
+        self.reader_quirks = Some(quirks);
+        self
+    }
+
+    /// How the [SelectCache] is doing, if there is one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.select_cache.as_ref().map(|cache| cache.stats)
+    }
+
+    /// Forgets everything in the [SelectCache], eg. because something else reset the card.
+    /// The card's [ResponseLimits] go too, since it might not be the same card.
+    pub fn invalidate_cache(&mut self) {
+        if let Some(cache) = self.select_cache.as_mut() {
+            cache.clear();
+        }
+        self.response_limits = ResponseLimits::default();
+    }
+
+    /// Runs `f` with the card to ourselves; see [CardTransport::transaction]. With
+    /// [Session::retry_on_reset], if the card is reset partway through, `f` is run again.
+    ///
+    /// Commands in `f` go straight to the card, past the [SelectCache], but still get the
+    /// Session's [RetryPolicy], [ReaderQuirks], [ResponseLimits] and buffers.
+    pub fn transaction<R>(
+        &mut self,
+        mut f: impl FnMut(&mut dyn CardTransport) -> Result<R>,
+    ) -> Result<R> {
+        let span = trace_span!("Session::transaction");
+        let _enter = span.enter();
+
+        // Commands in here go straight to the card, so we can't tell what they selected.
+        if let Some(cache) = self.select_cache.as_mut() {
+            cache.deselect();
+        }
+        let mut retries = 0;
+        loop {
+            let mut out = None;
+            let result = self.inner.transaction(&mut |card| {
+                out = Some(f(&mut InTransaction {
+                    card,
+                    retry_policy: self.retry_policy.as_ref(),
+                    reader_quirks: self.reader_quirks.as_ref(),
+                    response_limits: &mut self.response_limits,
+                    buffers: &self.buffers,
+                })?);
+                Ok(())
+            });
+            match result {
+                Ok(()) => return Ok(out.expect("transaction succeeded without running")),
+                Err(err) if self.should_retry(&err, &mut retries) => {
+                    warn!("Card was reset during a transaction, starting over");
+                    self.reconnect()?;
+                }
+                Err(err) => {
+                    self.invalidate_on(&err);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Resets the card, and reconnects to it.
+    pub fn reset(&mut self, kind: Reset) -> Result<()> {
+        let span = trace_span!("Session::reset", ?kind);
+        let _enter = span.enter();
+
+        debug!("Resetting card");
+        self.invalidate_cache();
+        self.inner.reset(kind)
+    }
+
+    /// Forgets the [SelectCache] if an error means the card isn't the same as it was.
+    fn invalidate_on(&mut self, err: &cardinal_core::Error) {
+        if err.is_card_reset() || err.is_card_removed() {
+            self.invalidate_cache();
+        }
+    }
+
+    fn should_retry(&self, err: &cardinal_core::Error, retries: &mut usize) -> bool {
+        if !self.retry_on_reset || !err.is_card_reset() || *retries >= MAX_RESET_RETRIES {
+            return false;
+        }
+        *retries += 1;
+        true
+    }
+}
+
+impl<T: CardTransport> CardTransport for Session<T> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        if let Some(len) = (self.select_cache.as_mut()).and_then(|cache| cache.lookup(capdu, rbuf))
+        {
+            debug!(capdu = hex::encode_upper(capdu), "Answered from cache");
+            return Ok(&rbuf[..len]);
+        }
+        let mut retries = 0;
+        loop {
+            match self.inner.transmit(capdu, rbuf).map(|rapdu| rapdu.len()) {
+                Err(err) if self.should_retry(&err, &mut retries) => {
+                    warn!(
+                        capdu = hex::encode_upper(capdu),
+                        "Card was reset, reconnecting"
+                    );
+                    self.reconnect()?;
+                }
+                Err(err) => {
+                    self.invalidate_on(&err);
+                    return Err(err);
+                }
+                Ok(len) => {
+                    if let Some(cache) = self.select_cache.as_mut() {
+                        cache.update(capdu, &rbuf[..len]);
+                    }
+                    return Ok(&rbuf[..len]);
+                }
+            }
+        }
+    }
+
+    fn protocol(&mut self) -> cardinal_core::protocol::Protocol {
+        self.inner.protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.inner.atr()
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.inner.get_attribute(attr, rbuf)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        self.inner.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.inner.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.invalidate_cache();
+        self.inner.reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        Session::reset(self, kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy
+            .as_ref()
+            .or_else(|| self.inner.retry_policy())
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        self.reader_quirks
+            .as_ref()
+            .or_else(|| self.inner.reader_quirks())
+    }
+
+    /// Whatever's been learnt since the card was (re)connected to.
+    fn response_limits(&self) -> ResponseLimits {
+        self.response_limits
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        debug!(?limits, "Learnt the card's response limits");
+        self.response_limits = limits;
+    }
+
+    /// From the Session's own [BufferPool], so they're reused from command to command.
+    fn buffers(&self) -> Buffers {
+        self.buffers.get()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        Session::transaction(self, f)
+    }
+}
+
+/// The card inside a [Session::transaction]: commands go straight to it, and everything
+/// else comes from the Session.
+struct InTransaction<'a> {
+    card: &'a mut dyn CardTransport,
+    retry_policy: Option<&'a RetryPolicy>,
+    reader_quirks: Option<&'a ReaderQuirks>,
+    response_limits: &'a mut ResponseLimits,
+    buffers: &'a BufferPool,
+}
+
+impl CardTransport for InTransaction<'_> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.card.transmit(capdu, rbuf)
+    }
+
+    fn protocol(&mut self) -> cardinal_core::protocol::Protocol {
+        self.card.protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.card.atr()
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.card.get_attribute(attr, rbuf)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        self.card.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.card.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.card.reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        self.card.reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.or_else(|| self.card.retry_policy())
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        self.reader_quirks.or_else(|| self.card.reader_quirks())
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        *self.response_limits
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        debug!(?limits, "Learnt the card's response limits");
+        *self.response_limits = limits;
+    }
+
+    fn buffers(&self) -> Buffers {
+        self.buffers.get()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        f(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A card that somebody else resets after it's answered `left` more commands.
+    #[derive(Default)]
+    struct SharedCard {
+        left: usize,
+        reset: bool,
+        transactions: usize,
+        reconnects: usize,
+        resets: Vec<Reset>,
+    }
+
+    impl CardTransport for SharedCard {
+        fn transmit<'r>(&mut self, _capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            if self.reset || self.left == 0 {
+                self.reset = true;
+                return Err(pcsc::Error::ResetCard.into());
+            }
+            self.left -= 1;
+            rbuf[..2].copy_from_slice(&[0x90, 0x00]);
+            Ok(&rbuf[..2])
+        }
+
+        fn reconnect(&mut self) -> Result<()> {
+            self.reconnects += 1;
+            (self.left, self.reset) = (10, false);
+            Ok(())
+        }
+
+        fn reset(&mut self, kind: Reset) -> Result<()> {
+            self.resets.push(kind);
+            Ok(())
+        }
+
+        fn transaction(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+        ) -> Result<()> {
+            self.transactions += 1;
+            f(self)
+        }
+    }
+
+    #[test]
+    fn test_session_retry_on_reset() {
+        let card = SharedCard {
+            left: 1,
+            ..Default::default()
+        };
+        let mut session = Session::new(card).retry_on_reset(true);
+        let mut rbuf = [0; 16];
+        assert_eq!(
+            session
+                .transmit(&[0x00, 0xB0, 0x00, 0x00], &mut rbuf)
+                .unwrap(),
+            &[0x90, 0x00]
+        );
+        assert_eq!(
+            session
+                .transmit(&[0x00, 0xB0, 0x00, 0x00], &mut rbuf)
+                .unwrap(),
+            &[0x90, 0x00]
+        );
+        assert_eq!(session.inner.reconnects, 1);
+
+        // A transaction starts over from the top.
+        session.inner.left = 1;
+        let mut runs = 0;
+        let n = session
+            .transaction(|card| {
+                runs += 1;
+                let mut rbuf = [0; 16];
+                card.transmit(&[0x00, 0xA4, 0x04, 0x00], &mut rbuf)?;
+                card.transmit(&[0x00, 0xB2, 0x01, 0x0C], &mut rbuf)?;
+                Ok(runs)
+            })
+            .unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(session.inner.transactions, 2);
+        assert_eq!(session.inner.reconnects, 2);
+
+        session.reset(Reset::Cold).unwrap();
+        assert_eq!(session.inner.resets, vec![Reset::Cold]);
+    }
+
+    #[test]
+    fn test_session_no_retry() {
+        let mut session = Session::new(SharedCard::default());
+        let mut rbuf = [0; 16];
+        let err = session
+            .transmit(&[0x00, 0xB0, 0x00, 0x00], &mut rbuf)
+            .unwrap_err();
+        assert!(err.is_card_reset());
+        assert_eq!(session.inner.reconnects, 0);
+    }
+
+    #[test]
+    fn test_session_retry_policy() {
+        let session = Session::new(SharedCard::default());
+        assert_eq!(session.retry_policy(), None);
+        let mut session = session.with_retry_policy(RetryPolicy::new(2));
+        assert_eq!(session.retry_policy().map(|p| p.max_retries), Some(2));
+
+        // Commands in a transaction go straight to the card, but still follow it.
+        let retries = session
+            .transaction(|card| Ok(card.retry_policy().map(|p| p.max_retries)))
+            .unwrap();
+        assert_eq!(retries, Some(2));
+    }
+
+    #[test]
+    fn test_session_cache_selects() {
+        let card = SharedCard {
+            left: 2,
+            ..Default::default()
+        };
+        let mut session = Session::new(card).retry_on_reset(true).cache_selects(true);
+        let select = [0x00, 0xA4, 0x04, 0x00, 0x02, 0x31, 0x50, 0x00];
+        let mut rbuf = [0; 16];
+        for _ in 0..3 {
+            assert_eq!(session.transmit(&select, &mut rbuf).unwrap(), &[0x90, 0x00]);
+        }
+        assert_eq!(session.inner.left, 1);
+        assert_eq!(
+            session.cache_stats(),
+            Some(CacheStats { hits: 2, misses: 1 })
+        );
+
+        // The card forgets what was selected when it's reset, and so do we.
+        session.inner.reset = true;
+        session
+            .transmit(&[0x00, 0xB0, 0x00, 0x00], &mut rbuf)
+            .unwrap();
+        session.transmit(&select, &mut rbuf).unwrap();
+        assert_eq!(session.inner.reconnects, 1);
+        assert_eq!(
+            session.cache_stats(),
+            Some(CacheStats { hits: 2, misses: 2 })
+        );
+    }
+
+    #[test]
+    fn test_session_response_limits() {
+        let mut session = Session::new(SharedCard::default());
+        assert_eq!(session.response_limits(), ResponseLimits::default());
+        ResponseLimits::learn_le(&mut session, 0x40);
+        ResponseLimits::learn_felica_blocks(&mut session, 4);
+        ResponseLimits::learn_le(&mut session, 0x80);
+        assert_eq!(
+            session.response_limits(),
+            ResponseLimits {
+                max_le: 0x40,
+                felica_blocks: Some(4)
+            }
+        );
+
+        // What's learnt in a transaction is remembered after it.
+        session
+            .transaction(|card| {
+                assert_eq!(card.response_limits().max_le, 0x40);
+                ResponseLimits::learn_le(card, 0x20);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(session.response_limits().max_le, 0x20);
+
+        // It might not be the same card after a reconnect.
+        session.reconnect().unwrap();
+        assert_eq!(session.response_limits(), ResponseLimits::default());
+    }
+}
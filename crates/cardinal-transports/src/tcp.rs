@@ -0,0 +1,221 @@
+//! Remote readers, over TCP.
+//!
+//! The wire protocol is vsmartcard's VPCD protocol: every message is a 2-byte big-endian
+//! length, followed by that many bytes. A 1-byte message is a control command (power
+//! on/off, reset, or "send me the ATR"); anything longer is an APDU. The side with the
+//! reader answers each APDU (and ATR request) with a single message.
+//!
+//! This means `cardinal serve --vpcd` can also plug a card into a vpcd virtual reader,
+//! where it shows up as a normal PCSC card to anything on that machine.
+
+use cardinal_core::protocol::{self, Protocol};
+use cardinal_core::{CardTransport, Error, Result, MAX_BUFFER_SIZE};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use tracing::{debug, trace, trace_span, warn};
+
+/// Default port; the same one vpcd listens on.
+pub const DEFAULT_PORT: u16 = 35963;
+
+const CTRL_POWER_OFF: u8 = 0x00;
+const CTRL_POWER_ON: u8 = 0x01;
+const CTRL_RESET: u8 = 0x02;
+const CTRL_ATR: u8 = 0x04;
+
+/// A card in a reader on another machine, shared with `cardinal serve`.
+pub struct TcpTransport<S: Read + Write = TcpStream> {
+    stream: S,
+}
+
+impl TcpTransport {
+    /// Connects to a `cardinal serve` at `addr` (eg. `lab-pc:35963`; the port is optional).
+    pub fn connect(addr: &str) -> Result<Self> {
+        let span = trace_span!("TcpTransport::connect", addr);
+        let _enter = span.enter();
+
+        let stream = if addr.contains(':') {
+            TcpStream::connect(addr)
+        } else {
+            TcpStream::connect((addr, DEFAULT_PORT))
+        }
+        .map_err(|err| Error::Transport("tcp", format!("couldn't connect to {}: {}", addr, err)))?;
+        stream.set_nodelay(true).map_err(io_err)?;
+        debug!(peer = ?stream.peer_addr().ok(), "Connected");
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: Read + Write> TcpTransport<S> {
+    /// Wraps an already-connected stream.
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+}
+
+impl<S: Read + Write> CardTransport for TcpTransport<S> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let span = trace_span!("TcpTransport::transmit");
+        let _enter = span.enter();
+
+        write_msg(&mut self.stream, capdu)?;
+        let len = read_rsp(&mut self.stream, rbuf)?;
+        Ok(&rbuf[..len])
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        // The server deals with T=0 quirks on its end.
+        Protocol::T1
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        write_msg(&mut self.stream, &[CTRL_ATR])?;
+        let mut buf = [0; MAX_BUFFER_SIZE];
+        let len = read_rsp(&mut self.stream, &mut buf)?;
+        Ok(buf[..len].to_vec())
+    }
+}
+
+/// Serves a card over a connected stream, until the other end hangs up.
+///
+/// APDUs go through [protocol::transmit], so T=0 cards look like T=1 ones from the other
+/// end (which has no way to know the difference). Power control is ignored; the card is
+/// already powered, and resetting it is the local reader's job.
+pub fn serve(
+    card: &mut impl CardTransport,
+    atr: &[u8],
+    mut stream: impl Read + Write,
+) -> Result<()> {
+    let span = trace_span!("tcp::serve");
+    let _enter = span.enter();
+
+    let mut buf = [0; MAX_BUFFER_SIZE];
+    let mut rbuf = [0; MAX_BUFFER_SIZE];
+    loop {
+        let Some(len) = read_msg(&mut stream, &mut buf)? else {
+            debug!("Client hung up");
+            return Ok(());
+        };
+        match &buf[..len] {
+            [CTRL_ATR] => write_msg(&mut stream, atr)?,
+            [ctrl @ (CTRL_POWER_OFF | CTRL_POWER_ON | CTRL_RESET)] => {
+                debug!(ctrl, "Ignoring power control")
+            }
+            [ctrl] => warn!(ctrl, "Unknown control message"),
+            capdu => {
+                trace!(capdu = format!("{:02X?}", capdu), "Relaying APDU");
+                match protocol::transmit(card, capdu, &mut rbuf) {
+                    Ok(rapdu) => write_msg(&mut stream, rapdu)?,
+                    // There's no way to send back an error, so use "no precise diagnosis".
+                    Err(err) => {
+                        warn!("Couldn't relay APDU: {}", err);
+                        write_msg(&mut stream, &[0x6F, 0x00])?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn io_err(err: std::io::Error) -> Error {
+    Error::Transport("tcp", err.to_string())
+}
+
+fn write_msg(w: &mut impl Write, data: &[u8]) -> Result<()> {
+    let len = u16::try_from(data.len()).map_err(|_| Error::InsufficientBuffer)?;
+    w.write_all(&len.to_be_bytes()).map_err(io_err)?;
+    w.write_all(data).map_err(io_err)?;
+    w.flush().map_err(io_err)
+}
+
+/// Reads a message into `buf`, and returns its length; or None if the other end hung up.
+fn read_msg(r: &mut impl Read, buf: &mut [u8]) -> Result<Option<usize>> {
+    let mut len = [0; 2];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(io_err(err)),
+    }
+    let len = u16::from_be_bytes(len) as usize;
+    r.read_exact(buf.get_mut(..len).ok_or(Error::InsufficientBuffer)?)
+        .map_err(io_err)?;
+    Ok(Some(len))
+}
+
+/// Like [read_msg], but for when we're expecting a response.
+fn read_rsp(r: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    read_msg(r, buf)?.ok_or_else(|| Error::Transport("tcp", "connection closed".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// One end of a connection: reads come from `rx`, writes go to `tx`.
+    #[derive(Default)]
+    struct MockStream {
+        rx: VecDeque<u8>,
+        tx: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A card that echoes every command back, followed by 9000.
+    struct EchoCard;
+
+    impl CardTransport for EchoCard {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            rbuf[..capdu.len()].copy_from_slice(capdu);
+            rbuf[capdu.len()..capdu.len() + 2].copy_from_slice(&[0x90, 0x00]);
+            Ok(&rbuf[..capdu.len() + 2])
+        }
+    }
+
+    #[test]
+    fn test_client() {
+        let mut stream = MockStream::default();
+        stream.rx.extend([0x00, 0x02, 0x90, 0x00]);
+        stream.rx.extend([0x00, 0x03, 0x3B, 0x80, 0x80]);
+        let mut card = TcpTransport::new(stream);
+        let mut rbuf = [0; 16];
+        let rsp = card
+            .transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+            .unwrap();
+        assert_eq!(rsp, &[0x90, 0x00]);
+        assert_eq!(card.atr().unwrap(), vec![0x3B, 0x80, 0x80]);
+        assert_eq!(
+            card.stream.tx,
+            vec![0x00, 0x05, 0x00, 0xA4, 0x04, 0x00, 0x00, 0x00, 0x01, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut stream = MockStream::default();
+        stream.rx.extend([0x00, 0x01, CTRL_POWER_ON]);
+        stream.rx.extend([0x00, 0x01, CTRL_ATR]);
+        stream.rx.extend([0x00, 0x04, 0x00, 0xB0, 0x00, 0x00]);
+        serve(&mut EchoCard, &[0x3B, 0x00], &mut stream).unwrap();
+        assert_eq!(
+            stream.tx,
+            vec![
+                0x00, 0x02, 0x3B, 0x00, // ATR
+                0x00, 0x06, 0x00, 0xB0, 0x00, 0x00, 0x90, 0x00, // Echoed APDU
+            ]
+        );
+    }
+}
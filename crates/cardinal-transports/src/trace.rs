@@ -0,0 +1,309 @@
+//! Recording what goes to and from a card, and playing it back later.
+//!
+//! A [Recorder] wraps any transport and writes down every exchange; a [Replay] plays a
+//! recording back, and complains if it's asked anything that wasn't recorded (in the same
+//! order). Together, they turn a real card into a test fixture.
+//!
+//! Traces are plain text, so they're easy to read and to trim down by hand:
+//!
+//! ```text
+//! # Comments start with a #.
+//! atr 3B8F8001804F0CA0000003061100 3B00000000 42
+//! protocol T1
+//! > FFCA000000
+//! < 012E457A3B62910C 9000
+//! ```
+//!
+//! Reader attributes aren't recorded, so a replayed card's reader is always anonymous.
+//! Other tools' logs can be turned into traces too; see [import].
+
+pub mod import;
+
+use cardinal_core::buffers::Buffers;
+use cardinal_core::protocol::Protocol;
+use cardinal_core::reader_quirks::ReaderQuirks;
+use cardinal_core::response_limits::ResponseLimits;
+use cardinal_core::retry::RetryPolicy;
+use cardinal_core::transport::Reset;
+use cardinal_core::{CardTransport, Error, Result};
+use tracing::{trace, trace_span};
+
+/// A recorded session with a card.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub atr: Option<Vec<u8>>,
+    pub protocol: Option<Protocol>,
+    /// Commands, and the responses they got.
+    pub exchanges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Trace {
+    /// Parses a trace, as written by its [std::fmt::Display] impl.
+    pub fn parse(s: &str) -> Result<Self> {
+        let span = trace_span!("Trace::parse");
+        let _enter = span.enter();
+
+        let mut trace = Self::default();
+        let mut command = None;
+        for (i, line) in s.lines().enumerate() {
+            let err = |msg: &str| Error::Transport("trace", format!("line {}: {}", i + 1, msg));
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((kind, value)) = line.split_once(char::is_whitespace) else {
+                if line.is_empty() {
+                    continue;
+                }
+                return Err(err("expected a keyword and a value"));
+            };
+            let hex = || -> Result<Vec<u8>> {
+                hex::decode(value.split_whitespace().collect::<String>())
+                    .map_err(|e| err(&e.to_string()))
+            };
+            match kind {
+                "atr" => trace.atr = Some(hex()?),
+                "protocol" => {
+                    trace.protocol = Some(match value.trim() {
+                        "T0" => Protocol::T0,
+                        "T1" => Protocol::T1,
+                        _ => return Err(err("protocol should be T0 or T1")),
+                    })
+                }
+                ">" if command.is_none() => command = Some(hex()?),
+                "<" => match command.take() {
+                    Some(command) => trace.exchanges.push((command, hex()?)),
+                    None => return Err(err("response without a command")),
+                },
+                ">" => return Err(err("command without a response")),
+                _ => return Err(err("unknown keyword")),
+            }
+        }
+        match command {
+            Some(_) => Err(Error::Transport(
+                "trace",
+                "last command has no response".into(),
+            )),
+            None => Ok(trace),
+        }
+    }
+}
+
+impl std::fmt::Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(atr) = self.atr.as_ref() {
+            writeln!(f, "atr {}", hex::encode_upper(atr))?;
+        }
+        if let Some(protocol) = self.protocol {
+            writeln!(f, "protocol {:?}", protocol)?;
+        }
+        for (command, response) in self.exchanges.iter() {
+            writeln!(f, "> {}", hex::encode_upper(command))?;
+            writeln!(f, "< {}", hex::encode_upper(response))?;
+        }
+        Ok(())
+    }
+}
+
+/// Records everything that goes through another transport.
+pub struct Recorder<T: CardTransport> {
+    pub inner: T,
+    pub trace: Trace,
+}
+
+impl<T: CardTransport> Recorder<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            trace: Trace::default(),
+        }
+    }
+}
+
+impl<T: CardTransport> CardTransport for Recorder<T> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let rapdu = self.inner.transmit(capdu, rbuf)?;
+        self.trace.exchanges.push((capdu.to_vec(), rapdu.to_vec()));
+        Ok(rapdu)
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        let protocol = self.inner.protocol();
+        self.trace.protocol = Some(protocol);
+        protocol
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        let atr = self.inner.atr()?;
+        self.trace.atr = Some(atr.clone());
+        Ok(atr)
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.inner.get_attribute(attr, rbuf)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        self.inner.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.inner.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.inner.reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        self.inner.reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.inner.retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&ReaderQuirks> {
+        self.inner.reader_quirks()
+    }
+
+    fn response_limits(&self) -> ResponseLimits {
+        self.inner.response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: ResponseLimits) {
+        self.inner.learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> Buffers {
+        self.inner.buffers()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        // Keep recording whatever happens inside the transaction.
+        let trace = &mut self.trace;
+        self.inner.transaction(&mut |card| {
+            let mut recorder = Recorder {
+                inner: card,
+                trace: std::mem::take(trace),
+            };
+            let result = f(&mut recorder);
+            *trace = recorder.trace;
+            result
+        })
+    }
+}
+
+/// Plays back a [Trace], in order.
+pub struct Replay {
+    trace: Trace,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(trace: Trace) -> Self {
+        Self { trace, next: 0 }
+    }
+
+    /// Has everything in the trace been played back?
+    pub fn is_done(&self) -> bool {
+        self.next == self.trace.exchanges.len()
+    }
+}
+
+impl CardTransport for Replay {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        let span = trace_span!("Replay::transmit", next = self.next);
+        let _enter = span.enter();
+
+        let Some((command, response)) = self.trace.exchanges.get(self.next) else {
+            return Err(Error::Transport(
+                "replay",
+                format!(
+                    "unexpected command past the end: {}",
+                    hex::encode_upper(capdu)
+                ),
+            ));
+        };
+        if command != capdu {
+            return Err(Error::Transport(
+                "replay",
+                format!(
+                    "exchange {}: expected {}, got {}",
+                    self.next + 1,
+                    hex::encode_upper(command),
+                    hex::encode_upper(capdu)
+                ),
+            ));
+        }
+        trace!(response = hex::encode_upper(response), "Replaying");
+        self.next += 1;
+        rbuf.get_mut(..response.len())
+            .ok_or(Error::InsufficientBuffer)?
+            .copy_from_slice(response);
+        Ok(&rbuf[..response.len()])
+    }
+
+    fn protocol(&mut self) -> Protocol {
+        self.trace.protocol.unwrap_or(Protocol::T1)
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.trace
+            .atr
+            .clone()
+            .ok_or_else(|| Error::Transport("replay", "no ATR in the trace".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replay() {
+        let mut replay = Replay::new(
+            Trace::parse(
+                "# A card.\natr 3B 80 80\n> 00A4040000\n< 6A82\n> 00B2010C00 # Read.\n< 01 9000\n",
+            )
+            .unwrap(),
+        );
+        let mut recorder = Recorder::new(&mut replay);
+        let mut rbuf = [0; 16];
+        assert_eq!(recorder.atr().unwrap(), vec![0x3B, 0x80, 0x80]);
+        assert_eq!(
+            recorder
+                .transmit(&[0x00, 0xA4, 0x04, 0x00, 0x00], &mut rbuf)
+                .unwrap(),
+            &[0x6A, 0x82]
+        );
+        assert!(recorder
+            .transmit(&[0x00, 0xB2, 0x02, 0x0C, 0x00], &mut rbuf)
+            .is_err());
+        recorder
+            .transmit(&[0x00, 0xB2, 0x01, 0x0C, 0x00], &mut rbuf)
+            .unwrap();
+        let trace = recorder.trace;
+        assert_eq!(
+            trace.to_string(),
+            "atr 3B8080\n> 00A4040000\n< 6A82\n> 00B2010C00\n< 019000\n"
+        );
+        assert!(replay.is_done());
+        assert_eq!(Trace::parse(&trace.to_string()).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Trace::parse("> 00A4").is_err());
+        assert!(Trace::parse("< 9000").is_err());
+        assert!(Trace::parse("> 00A4\n> 00A4\n< 9000").is_err());
+        assert!(Trace::parse("atr 3B8").is_err());
+        assert!(Trace::parse("protocol T2").is_err());
+        assert!(Trace::parse("what 00").is_err());
+    }
+}
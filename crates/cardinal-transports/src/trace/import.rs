@@ -0,0 +1,458 @@
+//! Reading other tools' APDU logs into [Trace]s.
+//!
+//! Lots of interesting traces only exist as text: pasted into a bug report, a forum post, or
+//! a thesis appendix. Imported, they can be replayed, or turned into an emulated card and
+//! probed like a real one (which is what `cardinal import` does).
+//!
+//! - [Format::Opensc]: `opensc-tool -s` ("Sending: ..." / "Received (SW1=0x90, SW2=0x00):"),
+//!   or OpenSC's debug log ("Outgoing APDU" / "Incoming APDU"). An ATR from
+//!   `opensc-tool -a` is picked up too.
+//! - [Format::PcscSpy]: pcsc-lite's `pcsc-spy`; SCardTransmit's buffers, and the ATR from
+//!   SCardStatus.
+//! - [Format::Proxmark]: a Proxmark3's `trace list -t 14a` (the ISO 14443-4 I-blocks, with
+//!   the framing and CRCs taken off; the ATS becomes the ATR a PC/SC reader would've made
+//!   up), or the `>>>`/`<<<` lines from `hf 14a apdu` and `hf emv`.
+//!
+//! Anything else in the log is skipped, as is a command that never got a response (or
+//! one that doesn't even have a status word).
+
+use super::Trace;
+use cardinal_core::{ats, Error, Result};
+use tracing::{debug, trace_span, warn};
+
+/// What wrote the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Opensc,
+    PcscSpy,
+    Proxmark,
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "opensc" => Ok(Self::Opensc),
+            "pcsc-spy" => Ok(Self::PcscSpy),
+            "proxmark" => Ok(Self::Proxmark),
+            _ => Err(Error::Transport(
+                "import",
+                format!(
+                    "unknown log format: {} (try opensc, pcsc-spy or proxmark)",
+                    s
+                ),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Opensc => write!(f, "opensc"),
+            Self::PcscSpy => write!(f, "pcsc-spy"),
+            Self::Proxmark => write!(f, "proxmark"),
+        }
+    }
+}
+
+/// Parses a log; it's an error if there isn't a single exchange in it, since that's
+/// usually the wrong [Format].
+pub fn parse(format: Format, s: &str) -> Result<Trace> {
+    let span = trace_span!("import::parse", %format);
+    let _enter = span.enter();
+
+    let trace = match format {
+        Format::Opensc => parse_opensc(s),
+        Format::PcscSpy => parse_pcsc_spy(s),
+        Format::Proxmark => parse_proxmark(s),
+    };
+    debug!(exchanges = trace.exchanges.len(), "Imported");
+    if trace.exchanges.is_empty() {
+        return Err(Error::Transport(
+            "import",
+            format!("no APDUs found; is this really a {} log?", format),
+        ));
+    }
+    Ok(trace)
+}
+
+/// Most bytes on a line of a hex dump; anything after them is the ASCII column.
+const DUMP_WIDTH: usize = 16;
+
+/// Leading tokens that are a byte each (`6F`, or `6f!` with a parity error marked), up to
+/// `max` of them.
+fn dump_bytes<'a>(tokens: impl Iterator<Item = &'a str>, max: usize) -> Vec<u8> {
+    tokens
+        .map(|t| t.trim_end_matches('!'))
+        .map_while(|t| match t.len() {
+            2 => u8::from_str_radix(t, 16).ok(),
+            _ => None,
+        })
+        .take(max)
+        .collect()
+}
+
+/// Leading tokens that are hex, run together or not (`00A40400`, `00 A4 04 00`).
+fn hex_tokens<'a>(tokens: impl Iterator<Item = &'a str>) -> Vec<u8> {
+    tokens
+        .map_while(|t| hex::decode(t).ok())
+        .flatten()
+        .collect()
+}
+
+/// Which way a hex dump that's being read is going.
+enum Dump {
+    Command,
+    /// A response; with its status word, if that was given separately.
+    Response(Option<[u8; 2]>),
+    Atr,
+}
+
+/// Adds up commands and responses as they come, pairing each response with the last
+/// command before it.
+#[derive(Default)]
+struct Builder {
+    trace: Trace,
+    command: Option<Vec<u8>>,
+}
+
+impl Builder {
+    fn add(&mut self, dump: Dump, data: Vec<u8>) {
+        match dump {
+            Dump::Command => self.command = Some(data),
+            Dump::Response(sw) => match self.command.take() {
+                Some(command) => {
+                    let response = [data, sw.map(Vec::from).unwrap_or_default()].concat();
+                    if response.len() < 2 {
+                        warn!(
+                            command = hex::encode_upper(&command),
+                            response = hex::encode_upper(&response),
+                            "Skipping a response without a status word"
+                        );
+                        return;
+                    }
+                    self.trace.exchanges.push((command, response));
+                }
+                None => debug!("Skipping a response without a command"),
+            },
+            Dump::Atr if self.trace.atr.is_none() => self.trace.atr = Some(data),
+            Dump::Atr => {}
+        }
+    }
+}
+
+fn parse_opensc(s: &str) -> Trace {
+    let mut b = Builder::default();
+    // The hex dump we're in the middle of, and how long it's meant to be.
+    let mut dump: Option<(Dump, Option<usize>, Vec<u8>)> = None;
+    for line in s.lines().map(str::trim) {
+        if let Some((_, _, data)) = dump.as_mut() {
+            let bytes = dump_bytes(line.split_whitespace(), DUMP_WIDTH);
+            if !bytes.is_empty() {
+                data.extend(bytes);
+                continue;
+            }
+        }
+        if let Some((kind, len, mut data)) = dump.take() {
+            data.truncate(len.unwrap_or(data.len()));
+            b.add(kind, data);
+        }
+
+        // "Outgoing APDU (5 bytes):", "Received (SW1=0x90, SW2=0x00):"
+        let bytes_in = |line: &str| {
+            let (_, rest) = line.split_once('(')?;
+            rest.split_whitespace().next()?.parse().ok()
+        };
+        if let Some(hex) = line.strip_prefix("Sending:") {
+            b.add(
+                Dump::Command,
+                dump_bytes(hex.split_whitespace(), usize::MAX),
+            );
+        } else if line.starts_with("Received (") {
+            let sw = |name: &str| {
+                let (_, rest) = line.split_once(name)?;
+                u8::from_str_radix(rest.get(..2)?, 16).ok()
+            };
+            let sw = sw("SW1=0x").zip(sw("SW2=0x")).map(|(sw1, sw2)| [sw1, sw2]);
+            dump = Some((Dump::Response(sw), None, vec![]));
+        } else if line.starts_with("Outgoing APDU") {
+            dump = Some((Dump::Command, bytes_in(line), vec![]));
+        } else if line.starts_with("Incoming APDU") {
+            dump = Some((Dump::Response(None), bytes_in(line), vec![]));
+        } else if line.split(':').all(|b| b.len() == 2) {
+            // opensc-tool -a: 3b:8f:80:01:... (TS is always 3B or 3F.)
+            match hex::decode(line.replace(':', "")) {
+                Ok(atr) if matches!(atr[..], [0x3B | 0x3F, _, ..]) => b.add(Dump::Atr, atr),
+                _ => {}
+            }
+        }
+    }
+    if let Some((kind, len, mut data)) = dump {
+        data.truncate(len.unwrap_or(data.len()));
+        b.add(kind, data);
+    }
+    b.trace
+}
+
+fn parse_pcsc_spy(s: &str) -> Trace {
+    let mut b = Builder::default();
+    // The buffer being dumped; it's only added once we know how long it is, since a short
+    // last line's ASCII column can look like more hex.
+    let mut dump: Option<(Dump, Vec<u8>)> = None;
+    for line in s.lines() {
+        // " i pbSendBuffer", " o     0000 6F 2C 84 07 ...", " o pcbRecvLength: 46"
+        let mut tokens = line.split_whitespace();
+        if !matches!(tokens.next(), Some("i" | "o")) {
+            continue;
+        }
+        let Some(field) = tokens.next() else {
+            continue;
+        };
+        match field {
+            "pbSendBuffer" | "pbRecvBuffer" | "pbAtr" => {
+                if let Some((kind, data)) = dump.take() {
+                    b.add(kind, data);
+                }
+                let kind = match field {
+                    "pbSendBuffer" => Dump::Command,
+                    "pbRecvBuffer" => Dump::Response(None),
+                    _ => Dump::Atr,
+                };
+                dump = Some((kind, vec![]));
+            }
+            "cbSendLength:" | "pcbRecvLength:" | "pcbAtrLen:" => {
+                if let Some((kind, mut data)) = dump.take() {
+                    if let Some(len) = tokens.next().and_then(|len| len.parse().ok()) {
+                        data.truncate(len);
+                    }
+                    b.add(kind, data);
+                }
+            }
+            offset if offset.len() == 4 && u16::from_str_radix(offset, 16).is_ok() => {
+                if let Some((_, data)) = dump.as_mut() {
+                    data.extend(dump_bytes(tokens, DUMP_WIDTH));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((kind, data)) = dump {
+        b.add(kind, data);
+    }
+    b.trace
+}
+
+fn parse_proxmark(s: &str) -> Trace {
+    let mut b = Builder::default();
+    let mut frames = Iso14443::default();
+    // The `trace list` frame being read: whether the reader sent it, and its bytes.
+    let mut frame: Option<(bool, Vec<u8>)> = None;
+    for line in s.lines() {
+        // "  49120 |  59648 | Rdr |02  00  a4  04 ... |  ok | I-block"; frames too long for
+        // one line carry on in the next, with nothing in the first three columns.
+        let cols: Vec<&str> = line.split('|').map(str::trim).collect();
+        if let ([start, _, "", data, ..], Some((_, bytes))) = (&cols[..], frame.as_mut()) {
+            if start.is_empty() {
+                bytes.extend(dump_bytes(data.split_whitespace(), usize::MAX));
+                continue;
+            }
+        }
+        if let Some((from_reader, bytes)) = frame.take() {
+            frames.add(&mut b, from_reader, &bytes);
+        }
+        if let [_, _, src @ ("Rdr" | "Tag"), data, ..] = cols[..] {
+            frame = Some((
+                src == "Rdr",
+                dump_bytes(data.split_whitespace(), usize::MAX),
+            ));
+        } else if let Some((_, rest)) = line.split_once(">>>") {
+            let cmd = hex_tokens(
+                rest.split('|')
+                    .next()
+                    .unwrap_or_default()
+                    .split_whitespace(),
+            );
+            b.add(Dump::Command, cmd);
+        } else if let Some((_, rest)) = line.split_once("<<<") {
+            let rsp = hex_tokens(
+                rest.split('|')
+                    .next()
+                    .unwrap_or_default()
+                    .split_whitespace(),
+            );
+            // There's sometimes a "<<< status: 90 00 - ..." line after the response.
+            if !rsp.is_empty() {
+                b.add(Dump::Response(None), rsp);
+            }
+        }
+    }
+    if let Some((from_reader, bytes)) = frame {
+        frames.add(&mut b, from_reader, &bytes);
+    }
+    b.trace
+}
+
+/// Takes the ISO 14443-4 framing off, and puts chained blocks back together.
+#[derive(Default)]
+struct Iso14443 {
+    chain: Vec<u8>,
+    /// Was the last frame a RATS? Then this one's the ATS.
+    rats: bool,
+}
+
+impl Iso14443 {
+    fn add(&mut self, b: &mut Builder, from_reader: bool, frame: &[u8]) {
+        if frame.len() < 3 {
+            return; // REQA, ACKs and the like; there's not even room for a CRC.
+        }
+        let (pcb, body) = (frame[0], &frame[..frame.len() - 2]);
+        if std::mem::take(&mut self.rats) && !from_reader {
+            match ats::synthesize_atr(frame) {
+                Ok(atr) => b.add(Dump::Atr, atr),
+                Err(err) => debug!(%err, "Skipping an ATS that doesn't parse"),
+            }
+            return;
+        }
+        if from_reader && pcb == 0xE0 {
+            self.rats = true;
+            return;
+        }
+        // I-blocks only: 000x xx1x, with a CID (bit 4) and a NAD (bit 3) if they say so.
+        if pcb & 0xE2 != 0x02 {
+            return;
+        }
+        let skip = 1 + usize::from(pcb & 0x08 != 0) + usize::from(pcb & 0x04 != 0);
+        self.chain.extend(body.get(skip..).unwrap_or_default());
+        if pcb & 0x10 != 0 {
+            return; // More to come.
+        }
+        let data = std::mem::take(&mut self.chain);
+        b.add(
+            match from_reader {
+                true => Dump::Command,
+                false => Dump::Response(None),
+            },
+            data,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_opensc() {
+        let log = "\
+Using reader with a card: ACS ACR122U PICC Interface 00 00
+3b:8f:80:01:80:4f:0c:a0:00:00:03:06:03:00:01:00:00:00:00:6a
+Sending: 00 A4 04 00 07 A0 00 00 00 03 10 10 00
+Received (SW1=0x90, SW2=0x00):
+6F 1A 84 07 A0 00 00 00 03 10 10 A5 0F 50 0A 56 o.............PV
+49 53 41 20 44 45 42 49 54                      ISA DEBIT
+Sending: 00 B2 01 0C 00
+Received (SW1=0x6A, SW2=0x83)
+
+P:123; T:0x456 12:00:00.000 [opensc-tool] apdu.c:378:sc_single_transmit:
+Outgoing APDU (5 bytes):
+80 CA 9F 17 00 .....
+Incoming APDU (6 bytes):
+9F 17 01 03 90 00 ......
+";
+        let trace = parse(Format::Opensc, log).unwrap();
+        assert_eq!(trace.atr.as_ref().map(|atr| atr.len()), Some(20));
+        assert_eq!(
+            trace.to_string().lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "> 00A4040007A000000003101000",
+                "< 6F1A8407A0000000031010A50F500A564953412044454249549000",
+                "> 00B2010C00",
+                "< 6A83",
+                "> 80CA9F1700",
+                "< 9F1701039000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_pcsc_spy() {
+        let log = "\
+SCardStatus
+ i hCard: 0x4E630000
+ o pbAtr
+ o     0000 3B 8A 80 01 80 31 F8 73 F7 41 E0 82 90 00 75    ;....1.s.A....u
+ o pcbAtrLen: 15
+ => Command successful. (SCARD_S_SUCCESS [0x00000000])  [0.000033]
+SCardTransmit
+ i hCard: 0x4E630000
+ i pioSendPci
+ i    dwProtocol: 2
+ i pbSendBuffer
+ i     0000 00 A4 04 00 0E 31 50 41 59 2E 53 59 53 2E 44 44 .....1PAY.SYS.DD
+ i     0010 46 30 31 00 F01.
+ i cbSendLength: 20
+ o pbRecvBuffer
+ o     0000 6A 82 AB                                        j..
+ o pcbRecvLength: 2
+ => Command successful. (SCARD_S_SUCCESS [0x00000000])  [0.010101]
+";
+        let trace = parse(Format::PcscSpy, log).unwrap();
+        assert_eq!(trace.atr.as_ref().map(|atr| atr.len()), Some(15));
+        assert_eq!(
+            trace.exchanges,
+            vec![(
+                hex::decode("00A404000E315041592E5359532E444446303100").unwrap(),
+                vec![0x6A, 0x82]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_proxmark() {
+        // RATS and ATS, then a SELECT chained over two I-blocks (with an R(ACK) between),
+        // and its response, split over two lines.
+        let log = "\
+      Start |        End | Src | Data (! denotes parity error)           | CRC | Annotation
+------------+------------+-----+----------------------------------------+-----+-----------
+          0 |        992 | Rdr |26(7)                                   |     | REQA
+       7040 |      11680 | Rdr |e0  80  31  73                          |  ok | RATS
+      13316 |      20452 | Tag |06  75  77  81  02  80  02  f0          |  ok |
+      30000 |      40000 | Rdr |12  00  a4  04  00  07  d1  7e          |  ok | I-block
+      41000 |      42000 | Tag |a2  6f  46                              |  ok | R-block
+      43000 |      48000 | Rdr |03  a0  00  00  00  03  10  10  00  aa  |  ok | I-block
+            |            |     |bb                                      |     |
+      50000 |      60000 | Tag |03  6a  82  4b! 4c                      |  ok |
+[+] >>> 00 B2 01 0C 00
+[+] <<< 6A83 | 6A 83 - Record not found
+[+] <<< status: 6A 83
+";
+        let trace = parse(Format::Proxmark, log).unwrap();
+        assert_eq!(trace.atr, Some(vec![0x3B, 0x81, 0x80, 0x01, 0x80, 0x80]));
+        assert_eq!(
+            trace.to_string(),
+            "atr 3B8180018080\n> 00A4040007A000000003101000\n< 6A82\n> 00B2010C00\n< 6A83\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_short_response() {
+        let log = "\
+[+] >>> 00 A4 04 00 0E 32 50 41 59 2E 53 59 53 2E 44 44 46 30 31 00
+[+] <<< 90
+[+] >>> 00 B2 01 0C 00
+[+] <<< 6A 83
+";
+        let trace = parse(Format::Proxmark, log).unwrap();
+        assert_eq!(trace.to_string(), "> 00B2010C00\n< 6A83\n");
+        assert!(parse(Format::Proxmark, "[+] >>> 00 B2 01 0C 00\n[+] <<< 90\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_nothing() {
+        assert!(parse(Format::PcscSpy, "Sending: 00 A4 04 00\n").is_err());
+        assert!("proxmark".parse::<Format>().is_ok());
+        assert!("pm3".parse::<Format>().is_err());
+    }
+}
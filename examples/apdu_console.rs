@@ -0,0 +1,37 @@
+//! A bare-bones APDU console: type APDUs as hex, one per line, and see what the card says.
+//! `cardinal apdu` is the full-featured version of this.
+//!
+//! ```sh
+//! cargo run --example apdu_console                    # The first PCSC reader.
+//! cargo run --example apdu_console -- tcp:lab-pc      # A card shared with `cardinal serve`.
+//! ```
+
+use cardinal::transports::Interface;
+use cardinal::{protocol, CardTransport as _, MAX_BUFFER_SIZE};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let interface: Interface = match std::env::args().nth(1) {
+        Some(arg) => arg.parse()?,
+        None => Interface::default(),
+    };
+    let mut card = interface.open(None)?;
+    println!("ATR: {}", hex::encode_upper(card.atr()?));
+
+    let mut rbuf = [0; MAX_BUFFER_SIZE];
+    for line in std::io::stdin().lines() {
+        let capdu = match hex::decode(line?.split_whitespace().collect::<String>()) {
+            Ok(capdu) if capdu.is_empty() => continue,
+            Ok(capdu) => capdu,
+            Err(err) => {
+                eprintln!("That's not hex: {}", err);
+                continue;
+            }
+        };
+        // This deals with T=0 for us, so responses look the same on every card.
+        match protocol::transmit(&mut card, &capdu, &mut rbuf) {
+            Ok(rapdu) => println!("< {}", hex::encode_upper(rapdu)),
+            Err(err) => eprintln!("Error: {}", err),
+        }
+    }
+    Ok(())
+}
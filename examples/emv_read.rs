@@ -0,0 +1,37 @@
+//! Selects the EMV directory on the card in the first reader, then every application
+//! listed in it, and prints what they say about themselves.
+//!
+//! ```sh
+//! cargo run --example emv_read
+//! ```
+
+use cardinal::transports::Interface;
+use cardinal::{emv, MAX_BUFFER_SIZE};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut card = Interface::default().open(None)?;
+    let mut wbuf = [0; MAX_BUFFER_SIZE];
+    let mut rbuf = [0; MAX_BUFFER_SIZE];
+
+    let dir = emv::Directory::select(&mut card, &mut wbuf, &mut rbuf)?;
+    println!("Directory: records in SFI {}", dir.ef_sfi);
+
+    // This reads the directory's records until the card says there are no more.
+    let apps = dir
+        .applications(&mut card, &mut wbuf, &mut rbuf)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for entry in apps {
+        println!();
+        println!(
+            "{} ({})",
+            entry.app_label,
+            hex::encode_upper(&entry.adf_name)
+        );
+        match emv::Application::select(&mut card, &mut wbuf, &mut rbuf, &entry.adf_name) {
+            Ok(app) => println!("{:#?}", app),
+            Err(err) => println!("Couldn't select it: {}", err),
+        }
+    }
+    Ok(())
+}
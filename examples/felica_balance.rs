@@ -0,0 +1,40 @@
+//! Reads the balance off a Suica (or PASMO, ICOCA, or any other compatible card) in the
+//! first reader, from the newest entry in its transaction history.
+//!
+//! ```sh
+//! cargo run --example felica_balance
+//! ```
+
+use cardinal::felica::{self, Command as _};
+use cardinal::probe::pcsc_get_data;
+use cardinal::transports::Interface;
+use cardinal::MAX_BUFFER_SIZE;
+
+/// Transaction history: a cyclic service, newest entry first, readable without a key.
+const SUICA_HISTORY: u16 = 0x090F;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut card = Interface::default().open(None)?;
+    let mut wbuf = [0; MAX_BUFFER_SIZE];
+    let mut rbuf = [0; MAX_BUFFER_SIZE];
+
+    // The reader knows the IDm; it's the card ID, as far as PCSC is concerned.
+    let idm = felica::cid_to_idm(pcsc_get_data(&mut card, &mut wbuf, &mut rbuf, 0x00)?)?;
+
+    let rsp = felica::ReadWithoutEncryption {
+        idm,
+        services: vec![SUICA_HISTORY],
+        blocks: vec![felica::BlockListElement {
+            mode: felica::AccessMode::Normal,
+            service_idx: 0,
+            block_num: 0,
+        }],
+    }
+    .call(&mut card, &mut wbuf, &mut rbuf)?;
+    let entry = rsp.blocks.first().ok_or("no transaction history")?;
+
+    // Bytes 10-11 are the balance after the transaction, in yen, little-endian.
+    let balance = u16::from_le_bytes([entry[10], entry[11]]);
+    println!("{:016X}: ¥{}", idm, balance);
+    Ok(())
+}
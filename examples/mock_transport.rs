@@ -0,0 +1,56 @@
+//! A template for testing code that talks to cards, without a card: write it against
+//! `impl CardTransport`, then test it against a recorded trace (see `cardinal probe
+//! --record`), or an emulated card (see `cardinal::emulate`).
+//!
+//! ```sh
+//! cargo run --example mock_transport      # Against the card in the first reader.
+//! cargo test --example mock_transport     # Against the mocks.
+//! ```
+
+use cardinal::probe::pcsc_get_data;
+use cardinal::transports::Interface;
+use cardinal::{CardTransport, MAX_BUFFER_SIZE};
+
+/// The code under test: gets the card's UID from the reader, as hex.
+fn uid(card: &mut impl CardTransport) -> cardinal::Result<String> {
+    let mut wbuf = [0; MAX_BUFFER_SIZE];
+    let mut rbuf = [0; MAX_BUFFER_SIZE];
+    Ok(hex::encode_upper(pcsc_get_data(
+        card, &mut wbuf, &mut rbuf, 0x00,
+    )?))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut card = Interface::default().open(None)?;
+    println!("{}", uid(&mut card)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cardinal::emulate::{EmulatedCard, Profile};
+    use cardinal::transports::trace::{Replay, Trace};
+
+    #[test]
+    fn test_uid_replay() {
+        // Traces can be written by hand, too; `>` is a command, `<` its response.
+        let mut card = Replay::new(Trace::parse("> FFCA000000\n< 08A1B2C3 9000").unwrap());
+        assert_eq!(uid(&mut card).unwrap(), "08A1B2C3");
+        assert!(card.is_done());
+
+        let mut card = Replay::new(Trace::parse("> FFCA000000\n< 6A81").unwrap());
+        assert!(uid(&mut card).is_err());
+    }
+
+    #[test]
+    fn test_uid_emulated() {
+        let profile = Profile::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/profiles/emv-test-card.toml"
+        ))
+        .unwrap();
+        let mut card = EmulatedCard::new(profile);
+        assert_eq!(uid(&mut card).unwrap(), "08A1B2C3");
+    }
+}
@@ -0,0 +1,304 @@
+//! Forensic acquisition: dump everything we can read off a card, without changing anything,
+//! and write down enough to show later that the dump hasn't changed either.
+//!
+//! An [Acquisition] is a [Probe] (with every optional scan turned on) run through a
+//! [ReadOnly] guard and a [Recorder]. What you get is three files: the full trace of every
+//! command and response (which replays with [crate::transports::trace::Replay], so anyone
+//! can re-run the probe against it), the probe report, and a [Manifest] with SHA-256 hashes
+//! of every response and both files, plus who took it, when, and for what case.
+//!
+//! Signing the manifest is left to a real signing tool (`cardinal acquire` uses minisign),
+//! so the keys never have to go near this.
+
+use crate::probe::{self, Probe};
+use crate::report::{Kind, Report};
+use crate::transport::Reset;
+use crate::transports::trace::{Recorder, Trace};
+use crate::uid::CardUid;
+use crate::util::is_mutating;
+use crate::{CardTransport, Error, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, trace_span, warn};
+
+/// Name of the trace in a saved acquisition.
+pub const TRACE_FILE: &str = "trace.txt";
+/// Name of the probe report in a saved acquisition.
+pub const REPORT_FILE: &str = "probe.json";
+/// Name of the manifest in a saved acquisition.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Who's acquiring what, and why; all optional, and recorded as given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Case {
+    /// Case (or incident) number.
+    pub case_id: Option<String>,
+    /// Exhibit/evidence number of the card itself.
+    pub evidence_id: Option<String>,
+    /// Who ran the acquisition.
+    pub operator: Option<String>,
+    /// Anything else worth writing down.
+    pub notes: Option<String>,
+}
+
+/// Something that was captured, and its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Blob {
+    /// What it is: `atr`, `response/N` (the Nth response in the trace, from 0), or a file.
+    pub name: String,
+    pub len: usize,
+    /// SHA-256, as lowercase hex (like sha256sum says it).
+    pub sha256: String,
+}
+
+impl Blob {
+    pub fn new(name: impl Into<String>, data: &[u8]) -> Self {
+        Self {
+            name: name.into(),
+            len: data.len(),
+            sha256: hex::encode(Sha256::digest(data)),
+        }
+    }
+}
+
+/// Chain of custody for an acquisition.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Manifest {
+    /// What took it, and which version.
+    pub tool: String,
+    pub case: Case,
+    /// When the acquisition started and finished, in RFC 3339.
+    pub started: String,
+    pub finished: String,
+    /// What identifies the card, if anything.
+    pub uid: CardUid,
+    /// Everything the card said, in order.
+    pub blobs: Vec<Blob>,
+    /// The files the acquisition is saved as (except this one).
+    pub files: Vec<Blob>,
+}
+
+/// A finished acquisition.
+pub struct Acquisition {
+    pub probe: Probe,
+    /// Everything that was sent to and from the card.
+    pub trace: Trace,
+    pub manifest: Manifest,
+    /// Contents of [TRACE_FILE] and [REPORT_FILE], exactly as hashed in the manifest.
+    pub files: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl Acquisition {
+    /// Dumps a card. Nothing that could change it gets through; see [ReadOnly].
+    pub fn run(card: &mut impl CardTransport, case: Case, opts: &probe::Options) -> Result<Self> {
+        let span = trace_span!("Acquisition::run");
+        let _enter = span.enter();
+
+        let started = chrono::Utc::now();
+        let opts = probe::Options {
+            felica_scan: true,
+            ..opts.clone()
+        };
+        let mut recorder = Recorder::new(ReadOnly::new(card));
+        let probe = Probe::run_with(&mut recorder, &opts)?;
+        let trace = recorder.trace;
+        let finished = chrono::Utc::now();
+
+        let mut blobs = vec![];
+        if let Some(atr) = trace.atr.as_ref() {
+            blobs.push(Blob::new("atr", atr));
+        }
+        for (i, (_, response)) in trace.exchanges.iter().enumerate() {
+            blobs.push(Blob::new(format!("response/{}", i), response));
+        }
+        let report = serde_json::to_vec_pretty(&Report::new(Kind::Probe, &probe))
+            .map_err(|err| Error::Transport("acquire", err.to_string()))?;
+        let files = vec![
+            (TRACE_FILE, trace.to_string().into_bytes()),
+            (REPORT_FILE, report),
+        ];
+        debug!(blobs = blobs.len(), "Acquired");
+
+        let manifest = Manifest {
+            tool: format!("cardinal {}", env!("CARGO_PKG_VERSION")),
+            case,
+            started: started.to_rfc3339(),
+            finished: finished.to_rfc3339(),
+            uid: probe.uid.clone(),
+            blobs,
+            files: (files.iter())
+                .map(|(name, data)| Blob::new(*name, data))
+                .collect(),
+        };
+        Ok(Self {
+            probe,
+            trace,
+            manifest,
+            files,
+        })
+    }
+
+    /// Writes the acquisition out to a directory (which must not exist yet, so nothing is
+    /// overwritten), and returns the path to the manifest, for signing.
+    pub fn save(&self, dir: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        std::fs::create_dir(dir)?;
+        for (name, data) in self.files.iter() {
+            std::fs::write(dir.join(name), data)?;
+        }
+        let path = dir.join(MANIFEST_FILE);
+        let manifest = serde_json::to_vec_pretty(&Report::new(Kind::Acquisition, &self.manifest))?;
+        std::fs::write(&path, manifest)?;
+        Ok(path)
+    }
+}
+
+/// Refuses to send anything that [is_mutating] says could change the card, whoever asks.
+pub struct ReadOnly<T: CardTransport> {
+    pub inner: T,
+}
+
+impl<T: CardTransport> ReadOnly<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: CardTransport> CardTransport for ReadOnly<T> {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        if is_mutating(capdu) {
+            warn!(capdu = hex::encode_upper(capdu), "Refusing to send");
+            return Err(Error::ReadOnly(capdu[1]));
+        }
+        self.inner.transmit(capdu, rbuf)
+    }
+
+    fn protocol(&mut self) -> crate::protocol::Protocol {
+        self.inner.protocol()
+    }
+
+    fn atr(&mut self) -> Result<Vec<u8>> {
+        self.inner.atr()
+    }
+
+    fn get_attribute<'r>(&mut self, attr: pcsc::Attribute, rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+        self.inner.get_attribute(attr, rbuf)
+    }
+
+    fn control<'r>(
+        &mut self,
+        code: pcsc::DWORD,
+        data: &[u8],
+        rbuf: &'r mut [u8],
+    ) -> Result<&'r [u8]> {
+        // Escapes are whatever the vendor says they are, and some go straight to the card
+        // (an ACR122 takes PN532 frames in FF 00 00 00); only pseudo-APDUs we know are let
+        // through, same as for transmit.
+        if !matches!(data, [0xFF, ..]) || is_mutating(data) {
+            warn!(data = hex::encode_upper(data), "Refusing to send escape");
+            return Err(Error::ReadOnly(data.get(1).copied().unwrap_or_default()));
+        }
+        self.inner.control(code, data, rbuf)
+    }
+
+    fn is_present(&mut self) -> Result<bool> {
+        self.inner.is_present()
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.inner.reconnect()
+    }
+
+    fn reset(&mut self, kind: Reset) -> Result<()> {
+        self.inner.reset(kind)
+    }
+
+    fn retry_policy(&self) -> Option<&crate::retry::RetryPolicy> {
+        self.inner.retry_policy()
+    }
+
+    fn reader_quirks(&self) -> Option<&crate::reader_quirks::ReaderQuirks> {
+        self.inner.reader_quirks()
+    }
+
+    fn response_limits(&self) -> crate::response_limits::ResponseLimits {
+        self.inner.response_limits()
+    }
+
+    fn learn_response_limits(&mut self, limits: crate::response_limits::ResponseLimits) {
+        self.inner.learn_response_limits(limits)
+    }
+
+    fn buffers(&self) -> crate::buffers::Buffers {
+        self.inner.buffers()
+    }
+
+    fn transaction(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn CardTransport) -> Result<()>,
+    ) -> Result<()> {
+        self.inner
+            .transaction(&mut |card| f(&mut ReadOnly::new(card)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_control() {
+        let profile = crate::emulate::Profile::from_toml(r#"atr = "3B 00""#).unwrap();
+        let mut card = ReadOnly::new(crate::emulate::EmulatedCard::new(profile));
+        let mut rbuf = [0; 16];
+        // An InDataExchange with a MIFARE Ultralight WRITE in it.
+        let escape = [
+            0xFF, 0x00, 0x00, 0x00, 0x09, 0xD4, 0x40, 0x01, 0xA2, 0x04, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert!(matches!(
+            card.control(1, &escape, &mut rbuf),
+            Err(Error::ReadOnly(_))
+        ));
+        // ACR1252-style escapes aren't pseudo-APDUs at all.
+        assert!(matches!(
+            card.control(1, &[0xE0, 0x00, 0x00, 0x18, 0x00], &mut rbuf),
+            Err(Error::ReadOnly(_))
+        ));
+    }
+
+    #[test]
+    fn test_acquire() {
+        let profile = crate::emulate::Profile::from_toml(
+            r#"
+            atr = "3B 8E 80 01 80 31 80 66 B1 84 0C 01 6E 01 83 00 90 00 1C"
+
+            [[file]]
+            fid = "3F00"
+
+            [[file]]
+            fid = "2F00"
+            records = ["61 0F 4F 09 A00000030800001000 50 02 5049"]
+            "#,
+        )
+        .unwrap();
+        let mut card = crate::emulate::EmulatedCard::new(profile);
+        let case = Case {
+            case_id: Some("2026-0042".into()),
+            ..Default::default()
+        };
+        let acq = Acquisition::run(&mut card, case, &Default::default()).unwrap();
+        assert!(acq.probe.ef_dir.is_some());
+
+        let manifest = &acq.manifest;
+        assert_eq!(manifest.case.case_id.as_deref(), Some("2026-0042"));
+        assert_eq!(manifest.blobs[0].name, "atr");
+        assert_eq!(manifest.blobs.len(), acq.trace.exchanges.len() + 1);
+        for ((name, data), blob) in acq.files.iter().zip(manifest.files.iter()) {
+            assert_eq!(*name, blob.name);
+            assert_eq!(Blob::new(*name, data), *blob);
+        }
+        assert_eq!(
+            Blob::new("empty", &[]).sha256,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
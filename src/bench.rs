@@ -0,0 +1,201 @@
+//! Benchmarking readers: how long a round trip to the card takes, for commands of
+//! different sizes. Keep the results around, and a new driver or firmware that makes
+//! things slower shows up as a regression against them.
+//!
+//! Every command is harmless. A size of 0 is the reader's own GET DATA pseudo-APDU (for
+//! the UID), which never reaches the card, so it times just the reader and its driver.
+//! Anything bigger is a SELECT by a name that no application has, padded out to that
+//! size; the card says it isn't there, which is fine, we're only timing it.
+
+use crate::{CardTransport, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{debug, trace_span};
+
+/// Command sizes to try, if not told otherwise.
+pub const DEFAULT_SIZES: &[usize] = &[0, 16, 64, 128, 255];
+
+/// How much slower than the baseline (as a fraction) counts as a regression.
+pub const REGRESSION_THRESHOLD: f64 = 0.2;
+
+/// Results of a benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bench {
+    /// Name of the reader, if it'll tell us; baselines are only compared with the same one.
+    pub reader: Option<String>,
+    pub results: Vec<Stats>,
+}
+
+/// Round trip times for one command size, in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Size of the command's data field; 0 means a GET DATA to the reader.
+    pub size: usize,
+    pub iterations: usize,
+    pub min_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    /// Bytes sent and received, per second.
+    pub bytes_per_sec: f64,
+}
+
+impl Stats {
+    fn new(size: usize, bytes: usize, mut times: Vec<Duration>) -> Self {
+        times.sort();
+        let total: Duration = times.iter().sum();
+        let us = |d: Option<&Duration>| d.map_or(0, |d| d.as_micros() as u64);
+        let pct = |p: usize| us(times.get((times.len() * p).div_ceil(100).saturating_sub(1)));
+        Self {
+            size,
+            iterations: times.len(),
+            min_us: us(times.first()),
+            p50_us: pct(50),
+            p90_us: pct(90),
+            p99_us: pct(99),
+            max_us: us(times.last()),
+            bytes_per_sec: match total.as_secs_f64() {
+                0.0 => 0.0,
+                secs => bytes as f64 / secs,
+            },
+        }
+    }
+}
+
+/// How a command size did, compared to a baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub size: usize,
+    pub baseline_p50_us: u64,
+    pub p50_us: u64,
+    /// Change in median round trip time, as a fraction; 0.5 is 50% slower.
+    pub change: f64,
+}
+
+impl Comparison {
+    pub fn is_regression(&self) -> bool {
+        self.change > REGRESSION_THRESHOLD
+    }
+}
+
+/// Sends `iterations` commands of each size (at most 255), and times them.
+pub fn run(card: &mut impl CardTransport, sizes: &[usize], iterations: usize) -> Result<Bench> {
+    let span = trace_span!("bench::run");
+    let _enter = span.enter();
+
+    let mut rbuf = [0; crate::MAX_BUFFER_SIZE];
+    let reader = card
+        .get_attribute(pcsc::Attribute::DeviceFriendlyName, &mut rbuf)
+        .ok()
+        .map(|name| {
+            String::from_utf8_lossy(name)
+                .trim_end_matches('\0')
+                .to_owned()
+        });
+
+    let mut results = vec![];
+    for &size in sizes {
+        let capdu = command(size);
+        let (mut bytes, mut times) = (0, Vec::with_capacity(iterations));
+        for _ in 0..iterations {
+            let start = Instant::now();
+            let rapdu = card.transmit(&capdu, &mut rbuf)?;
+            times.push(start.elapsed());
+            bytes += capdu.len() + rapdu.len();
+        }
+        let stats = Stats::new(size, bytes, times);
+        debug!(size, p50_us = stats.p50_us, "Benchmarked");
+        results.push(stats);
+    }
+    Ok(Bench { reader, results })
+}
+
+/// A harmless command whose data field is `size` bytes long (at most 255).
+fn command(size: usize) -> Vec<u8> {
+    if size == 0 {
+        return vec![0xFF, 0xCA, 0x00, 0x00, 0x00];
+    }
+    // SELECT by name, first occurrence, no response data. No RID starts with FF.
+    let size = size.min(0xFF);
+    let mut capdu = vec![0x00, 0xA4, 0x04, 0x0C, size as u8];
+    capdu.resize(5 + size, 0xFF);
+    capdu
+}
+
+/// Compares the median round trip times of every size that's in both.
+pub fn compare(baseline: &Bench, current: &Bench) -> Vec<Comparison> {
+    current
+        .results
+        .iter()
+        .filter_map(|stats| {
+            let base = baseline.results.iter().find(|b| b.size == stats.size)?;
+            Some(Comparison {
+                size: stats.size,
+                baseline_p50_us: base.p50_us,
+                p50_us: stats.p50_us,
+                change: match base.p50_us {
+                    0 => 0.0,
+                    b => (stats.p50_us as f64 - b as f64) / b as f64,
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulate::{EmulatedCard, Profile};
+
+    fn stats(size: usize, p50_us: u64) -> Stats {
+        Stats {
+            p50_us,
+            ..Stats::new(size, 0, vec![])
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::new(16, 2100, (1..=100).map(Duration::from_millis).collect());
+        assert_eq!(stats.iterations, 100);
+        assert_eq!(stats.min_us, 1_000);
+        assert_eq!(stats.p50_us, 50_000);
+        assert_eq!(stats.p90_us, 90_000);
+        assert_eq!(stats.p99_us, 99_000);
+        assert_eq!(stats.max_us, 100_000);
+        assert!((stats.bytes_per_sec - 2100.0 / 5.05).abs() < 0.001);
+        assert_eq!(Stats::new(0, 0, vec![]).p99_us, 0);
+    }
+
+    #[test]
+    fn test_run() {
+        let mut card = EmulatedCard::new(
+            Profile::from_toml(include_str!("../examples/profiles/emv-test-card.toml")).unwrap(),
+        );
+        let bench = run(&mut card, &[0, 16, 255], 3).unwrap();
+        assert_eq!(bench.reader, None);
+        assert_eq!(
+            bench.results.iter().map(|s| s.size).collect::<Vec<_>>(),
+            vec![0, 16, 255]
+        );
+        assert!(bench.results.iter().all(|s| s.iterations == 3));
+        assert_eq!(command(255).len(), 5 + 255);
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = Bench {
+            reader: None,
+            results: vec![stats(0, 1000), stats(16, 2000)],
+        };
+        let current = Bench {
+            reader: None,
+            results: vec![stats(16, 3000), stats(64, 4000)],
+        };
+        let cmp = compare(&baseline, &current);
+        assert_eq!(cmp.len(), 1);
+        assert_eq!(cmp[0].change, 0.5);
+        assert!(cmp[0].is_regression());
+    }
+}
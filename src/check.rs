@@ -0,0 +1,317 @@
+//! Checking a card against a spec, for QA'ing freshly personalised cards.
+//!
+//! A [Spec] (usually a YAML file) says what should and shouldn't be on a card; [check]
+//! looks, and returns a list of [Violation]s. No violations means the card passed.
+//!
+//! ```yaml
+//! # Applications that must (not) be selectable.
+//! required_aids: [A0000000031010]
+//! forbidden_aids: [A0000000032010]
+//!
+//! # Tags that must be in an application's FCI; without a value, any value will do.
+//! # (Quote anything that YAML might mistake for a number.)
+//! tags:
+//!   - { aid: A0000000031010, tag: "50", value: "56495341204445424954" }
+//!   - { aid: A0000000031010, tag: 9F38 }
+//!
+//! # FeliCa services that must not be there.
+//! forbidden_services: [0x090F]
+//! ```
+
+use crate::probe::{felica::FelicaNode, Probe};
+use crate::{ber, iso7816, serde_hex, CardTransport, Error as CardError, HexVec};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{debug, trace_span};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Card(#[from] CardError),
+}
+
+/// What a card should look like.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    /// Applications that must be on the card.
+    #[serde(default, deserialize_with = "serde_hex::vec")]
+    pub required_aids: Vec<Vec<u8>>,
+    /// Applications that must not be on the card.
+    #[serde(default, deserialize_with = "serde_hex::vec")]
+    pub forbidden_aids: Vec<Vec<u8>>,
+    /// Tags that must be in an application's FCI.
+    #[serde(default)]
+    pub tags: Vec<ExpectedTag>,
+    /// FeliCa services that must not be on the card.
+    #[serde(default)]
+    pub forbidden_services: Vec<u16>,
+}
+
+impl Spec {
+    /// Loads a spec from a YAML (or JSON) file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_yaml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a spec from YAML (or JSON, which is also YAML).
+    pub fn from_yaml(s: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(s)?)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedTag {
+    /// Application whose FCI the tag should be in.
+    #[serde(deserialize_with = "serde_hex::bytes")]
+    pub aid: Vec<u8>,
+    /// The tag, eg. "9F38". It's found anywhere in the FCI, however deeply nested.
+    #[serde(deserialize_with = "serde_hex::bytes")]
+    pub tag: Vec<u8>,
+    /// What the value should be; if not given, anything goes.
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub value: Option<Vec<u8>>,
+}
+
+/// Something about the card that doesn't match the spec.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Violation {
+    MissingApplication {
+        aid: HexVec,
+    },
+    ForbiddenApplication {
+        aid: HexVec,
+    },
+    MissingTag {
+        aid: HexVec,
+        tag: HexVec,
+    },
+    WrongValue {
+        aid: HexVec,
+        tag: HexVec,
+        expected: HexVec,
+        actual: HexVec,
+    },
+    ForbiddenService {
+        code: u16,
+    },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex = hex::encode_upper;
+        match self {
+            Self::MissingApplication { aid } => write!(f, "missing application {}", hex(aid)),
+            Self::ForbiddenApplication { aid } => {
+                write!(f, "forbidden application {} is present", hex(aid))
+            }
+            Self::MissingTag { aid, tag } => {
+                write!(f, "{}: missing tag {}", hex(aid), hex(tag))
+            }
+            Self::WrongValue {
+                aid,
+                tag,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: tag {} is {}, expected {}",
+                hex(aid),
+                hex(tag),
+                hex(actual),
+                hex(expected)
+            ),
+            Self::ForbiddenService { code } => {
+                write!(f, "forbidden service {:04X} is present", code)
+            }
+        }
+    }
+}
+
+/// Checks a card against a spec. FeliCa services need a full [Probe], so that only
+/// happens if the spec has any.
+pub fn check(card: &mut impl CardTransport, spec: &Spec) -> Result<Vec<Violation>> {
+    let span = trace_span!("check");
+    let _enter = span.enter();
+
+    let mut violations = vec![];
+    if !spec.forbidden_services.is_empty() {
+        let probe = Probe::run(card, None)?;
+        violations.extend(
+            services(&probe)
+                .filter(|code| spec.forbidden_services.contains(code))
+                .map(|code| Violation::ForbiddenService { code }),
+        );
+    }
+
+    for aid in spec.required_aids.iter() {
+        if select(card, aid)?.is_none() {
+            violations.push(Violation::MissingApplication {
+                aid: aid.clone().into(),
+            });
+        }
+    }
+    for aid in spec.forbidden_aids.iter() {
+        if select(card, aid)?.is_some() {
+            violations.push(Violation::ForbiddenApplication {
+                aid: aid.clone().into(),
+            });
+        }
+    }
+
+    for expected in spec.tags.iter() {
+        let (aid, tag) = (
+            HexVec::from(&expected.aid[..]),
+            HexVec::from(&expected.tag[..]),
+        );
+        let Some(fci) = select(card, &expected.aid)? else {
+            // Only complain about a missing application once.
+            if !violations.contains(&Violation::MissingApplication { aid: aid.clone() }) {
+                violations.push(Violation::MissingApplication { aid });
+            }
+            continue;
+        };
+        match (find_tag(&fci, &expected.tag)?, expected.value.as_ref()) {
+            (None, _) => violations.push(Violation::MissingTag { aid, tag }),
+            (Some(actual), Some(value)) if actual != value => {
+                violations.push(Violation::WrongValue {
+                    aid,
+                    tag,
+                    expected: value.clone().into(),
+                    actual: actual.into(),
+                })
+            }
+            (Some(_), _) => {}
+        }
+    }
+    Ok(violations)
+}
+
+/// FeliCa service codes on the card, in every system.
+fn services(probe: &Probe) -> impl Iterator<Item = u16> + '_ {
+    (probe.felica.iter())
+        .flat_map(|f| f.systems.iter())
+        .flat_map(|s| s.nodes.iter())
+        .filter_map(|node| match node {
+            FelicaNode::Service { code, .. } => Some(code.code),
+            _ => None,
+        })
+}
+
+/// Selects an application, and returns its FCI; or None if it's not there.
+fn select(card: &mut impl CardTransport, aid: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut bufs = card.buffers();
+    let (wbuf, rbuf) = bufs.split();
+    match (iso7816::Select {
+        id: iso7816::SelectID::Name(aid),
+        mode: iso7816::SelectMode::First,
+    })
+    .exec(card, wbuf, rbuf)
+    {
+        Ok(fci) => Ok(Some(fci.to_vec())),
+        // Any error from the card means it's not there (or not usable, same difference).
+        Err(err) if err.sw().is_some() => {
+            debug!(aid = hex::encode_upper(aid), %err, "Couldn't select");
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Finds a tag anywhere in a BER-TLV blob.
+fn find_tag<'a>(data: &'a [u8], tag: &[u8]) -> Result<Option<&'a [u8]>> {
+    for res in ber::iter_deep(data) {
+        let (_, t, value) = res?;
+        if t == tag {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulate::{EmulatedCard, Profile};
+
+    fn card(profile: &str) -> EmulatedCard {
+        EmulatedCard::new(Profile::from_toml(profile).unwrap())
+    }
+
+    #[test]
+    fn test_check_emv() {
+        let mut card = card(include_str!("../examples/profiles/emv-test-card.toml"));
+        let spec = Spec::from_yaml(
+            r#"
+            required_aids: [A0000000031010]
+            forbidden_aids: [A0000000032010]
+            tags:
+              - { aid: A0000000031010, tag: "50", value: "56495341204445424954" }
+              - { aid: A0000000031010, tag: 9F5E }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(check(&mut card, &spec).unwrap(), vec![]);
+
+        let spec = Spec::from_yaml(
+            r#"
+            required_aids: [A0000000041010]
+            forbidden_aids: [A0000000031010]
+            tags:
+              - { aid: A0000000031010, tag: "50", value: "56495341" }
+              - { aid: A0000000031010, tag: 9F12 }
+              - { aid: A0000000041010, tag: "50" }
+            "#,
+        )
+        .unwrap();
+        let aid = vec![0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10];
+        let mc = vec![0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10];
+        assert_eq!(
+            check(&mut card, &spec).unwrap(),
+            vec![
+                Violation::MissingApplication {
+                    aid: mc.clone().into()
+                },
+                Violation::ForbiddenApplication {
+                    aid: aid.clone().into()
+                },
+                Violation::WrongValue {
+                    aid: aid.clone().into(),
+                    tag: vec![0x50].into(),
+                    expected: b"VISA"[..].into(),
+                    actual: b"VISA DEBIT"[..].into(),
+                },
+                Violation::MissingTag {
+                    aid: aid.into(),
+                    tag: vec![0x9F, 0x12].into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_example_spec() {
+        let mut card = card(include_str!("../examples/profiles/emv-test-card.toml"));
+        let spec = Spec::from_yaml(include_str!("../examples/specs/emv-test-card.yaml")).unwrap();
+        assert_eq!(check(&mut card, &spec).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_check_felica() {
+        let mut card = card(include_str!("../examples/profiles/felica-lite-s.toml"));
+        let spec = Spec::from_yaml("forbidden_services: [0x0009, 0x090F]").unwrap();
+        assert_eq!(
+            check(&mut card, &spec).unwrap(),
+            vec![Violation::ForbiddenService { code: 0x0009 }]
+        );
+        assert!(Spec::from_yaml("forbidden_sevrices: [0x0009]").is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! Differential probing: probe the same card a few times, and see what changes.
+//!
+//! Most of what a card says is the same every time, but not all of it: contactless cards
+//! often make up a new UID for every tap, EMV applications count transactions, and some
+//! commands just answer with random data. Knowing which is which matters if you're trying
+//! to identify a card (or trying not to be identified by one). And if something changes
+//! that *counts up* between taps, something in the probe changed the card, which it's not
+//! supposed to do.
+//!
+//! Each [Tap] is a [Probe] and the [Trace] of everything it sent; [Differential::compare]
+//! lines them up and says which report fields and which responses differ.
+//!
+//! The other way round, [DumpDiff::compare] takes two saved probe reports of the same card,
+//! eg. from before and after a ride or a purchase, and says what the card itself changed:
+//! which fields, which FeliCa blocks, and which balances.
+
+use crate::felica::{purse, SystemCode};
+use crate::hexvec;
+use crate::money::Amount;
+use crate::probe::{self, Probe};
+use crate::transports::trace::{Recorder, Trace};
+use crate::{util, CardTransport, HexVec, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::{debug, trace_span};
+
+/// One probe of the card.
+pub struct Tap {
+    pub probe: Probe,
+    /// Everything sent to and from the card, for this probe.
+    pub trace: Trace,
+}
+
+impl Tap {
+    pub fn run(card: &mut impl CardTransport, opts: &probe::Options) -> Result<Self> {
+        let mut recorder = Recorder::new(card);
+        let probe = Probe::run_with(&mut recorder, opts)?;
+        Ok(Self {
+            probe,
+            trace: recorder.trace,
+        })
+    }
+}
+
+/// How something changed between taps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    /// Went up every time: a counter, so something in the probe is counted by the card.
+    Counting,
+    /// Anything else, eg. a random UID or unpredictable number.
+    Varying,
+}
+
+/// Fewest values it takes to call something [Change::Counting]; any two different values
+/// are in order half the time.
+const MIN_COUNTING: usize = 3;
+
+impl Change {
+    /// Counting if every value is bigger than the last (as big-endian numbers), and there
+    /// are enough of them to tell; else varying.
+    fn of(values: &[Option<Vec<u8>>]) -> Self {
+        let counting = values.len() >= MIN_COUNTING
+            && values.windows(2).all(|w| match w {
+                [Some(a), Some(b)] => a.len() == b.len() && a < b,
+                _ => false,
+            });
+        match counting {
+            true => Self::Counting,
+            false => Self::Varying,
+        }
+    }
+}
+
+/// A field in the probe report that wasn't the same every time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDiff {
+    /// Where it is in the report, as a JSON pointer (eg. `/uid/value`).
+    pub path: String,
+    pub change: Change,
+    /// What it was on each tap; null if it wasn't there.
+    pub values: Vec<Value>,
+}
+
+/// A command that got different responses on different taps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExchangeDiff {
+    /// Where it is in the trace, from 0.
+    pub index: usize,
+    pub command: HexVec,
+    pub change: Change,
+    /// What the card said on each tap.
+    pub responses: Vec<HexVec>,
+}
+
+/// What changed across a number of taps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Differential {
+    pub taps: usize,
+    pub fields: Vec<FieldDiff>,
+    pub exchanges: Vec<ExchangeDiff>,
+    /// If the probes didn't send the same commands in the same order, where they stopped
+    /// doing so; nothing after this is compared.
+    pub diverged_at: Option<usize>,
+    /// Commands the probe sent that could've changed the card (see [util::is_mutating]);
+    /// it shouldn't send any.
+    pub mutating: Vec<HexVec>,
+}
+
+impl Differential {
+    pub fn compare(taps: &[Tap]) -> Self {
+        let span = trace_span!("Differential::compare", taps = taps.len());
+        let _enter = span.enter();
+
+        // Fields, by flattening each report into a map of JSON pointers.
+        let reports = (taps.iter())
+            .map(|tap| {
+                let mut leaves = BTreeMap::new();
+                flatten(
+                    String::new(),
+                    serde_json::to_value(&tap.probe).unwrap_or_default(),
+                    &mut leaves,
+                );
+                leaves
+            })
+            .collect::<Vec<_>>();
+        let mut paths = (reports.iter()).flat_map(|r| r.keys()).collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        let mut fields = vec![];
+        for path in paths {
+            let values = (reports.iter())
+                .map(|r| r.get(path).cloned().unwrap_or_default())
+                .collect::<Vec<_>>();
+            if values.windows(2).all(|w| w[0] == w[1]) {
+                continue;
+            }
+            let bytes = values.iter().map(leaf_bytes).collect::<Vec<_>>();
+            fields.push(FieldDiff {
+                path: path.clone(),
+                change: Change::of(&bytes),
+                values,
+            });
+        }
+
+        // Responses, for as long as every tap sent the same commands.
+        let traces = taps.iter().map(|t| &t.trace.exchanges).collect::<Vec<_>>();
+        let len = traces.iter().map(|t| t.len()).max().unwrap_or_default();
+        let mut exchanges = vec![];
+        let mut diverged_at = None;
+        for index in 0..len {
+            let at = (traces.iter())
+                .map(|t| t.get(index))
+                .collect::<Option<Vec<_>>>()
+                .filter(|at| at.iter().all(|(c, _)| *c == at[0].0));
+            let Some(at) = at else {
+                debug!(index, "Taps diverged");
+                diverged_at = Some(index);
+                break;
+            };
+            let command = &at[0].0;
+            let responses = at.iter().map(|(_, r)| r).collect::<Vec<_>>();
+            if responses.windows(2).all(|w| w[0] == w[1]) {
+                continue;
+            }
+            let bytes = (responses.iter())
+                .map(|r| {
+                    Some(
+                        r.get(..r.len().saturating_sub(2))
+                            .unwrap_or_default()
+                            .to_vec(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            exchanges.push(ExchangeDiff {
+                index,
+                command: command.clone().into(),
+                change: Change::of(&bytes),
+                responses: responses.into_iter().cloned().map(HexVec).collect(),
+            });
+        }
+
+        let mut mutating: Vec<HexVec> = vec![];
+        for (command, _) in traces.iter().flat_map(|t| t.iter()) {
+            if util::is_mutating(command) && !mutating.iter().any(|m| m.0 == *command) {
+                mutating.push(command.clone().into());
+            }
+        }
+
+        Self {
+            taps: taps.len(),
+            fields,
+            exchanges,
+            diverged_at,
+            mutating,
+        }
+    }
+}
+
+/// A field that's different in the second report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// Where it is in the report, as a JSON pointer.
+    pub path: String,
+    /// What it was; null if it wasn't there.
+    pub before: Value,
+    /// What it is now; null if it's gone.
+    pub after: Value,
+}
+
+/// A FeliCa block whose contents changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockChange {
+    pub system: SystemCode,
+    pub service: u16,
+    pub num: u16,
+    /// What was in it; None if it wasn't read.
+    pub before: Option<HexVec>,
+    pub after: Option<HexVec>,
+}
+
+/// A balance that changed, in a purse we know (see [purse]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BalanceChange {
+    /// What it's called, eg. "Suica".
+    pub purse: &'static str,
+    pub before: Option<Amount>,
+    pub after: Option<Amount>,
+}
+
+/// What changed between two probes of the same card.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DumpDiff {
+    /// Fields (mostly TLV values) that changed, appeared or disappeared; FeliCa blocks
+    /// are in `blocks` instead.
+    pub fields: Vec<FieldChange>,
+    pub blocks: Vec<BlockChange>,
+    pub balances: Vec<BalanceChange>,
+}
+
+impl DumpDiff {
+    /// Compares two probes, as they are in a (migrated) report's `data`.
+    pub fn compare(before: &Value, after: &Value) -> Self {
+        let span = trace_span!("DumpDiff::compare");
+        let _enter = span.enter();
+
+        let leaves = |v: &Value| {
+            let mut leaves = BTreeMap::new();
+            flatten(String::new(), v.clone(), &mut leaves);
+            leaves.retain(|path, _| !path.contains("/blocks/"));
+            leaves
+        };
+        let (old, new) = (leaves(before), leaves(after));
+        let mut paths = old.keys().chain(new.keys()).collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        let fields = (paths.into_iter())
+            .filter(|&path| old.get(path) != new.get(path))
+            .map(|path| FieldChange {
+                path: path.clone(),
+                before: old.get(path).cloned().unwrap_or_default(),
+                after: new.get(path).cloned().unwrap_or_default(),
+            })
+            .collect();
+
+        let (old, new) = (felica_blocks(before), felica_blocks(after));
+        let mut keys = old.keys().chain(new.keys()).copied().collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        let mut blocks = vec![];
+        let mut balances = vec![];
+        for key @ (system, service, num) in keys {
+            let (before, after) = (
+                old.get(&key).cloned().flatten(),
+                new.get(&key).cloned().flatten(),
+            );
+            if before == after {
+                continue;
+            }
+            let system = SystemCode::from(system);
+            let balance = |data: &Option<Vec<u8>>| {
+                let data = data.as_deref()?;
+                purse::balance(Some(system), service, num, data)
+            };
+            let (old_balance, new_balance) = (balance(&before), balance(&after));
+            if let Some((purse, _)) = old_balance.or(new_balance) {
+                let (before, after) = (old_balance.map(|b| b.1), new_balance.map(|b| b.1));
+                if before != after
+                    && !balances
+                        .iter()
+                        .any(|b: &BalanceChange| b.purse == purse.name)
+                {
+                    balances.push(BalanceChange {
+                        purse: purse.name,
+                        before,
+                        after,
+                    });
+                }
+            }
+            blocks.push(BlockChange {
+                system,
+                service,
+                num,
+                before: before.map(HexVec),
+                after: after.map(HexVec),
+            });
+        }
+        Self {
+            fields,
+            blocks,
+            balances,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.blocks.is_empty()
+    }
+}
+
+/// Every FeliCa block in a probe, by (System, Service, block number); None if it couldn't
+/// be read.
+fn felica_blocks(probe: &Value) -> BTreeMap<(u16, u16, u16), Option<Vec<u8>>> {
+    let mut blocks = BTreeMap::new();
+    let systems = probe.pointer("/felica/systems").and_then(Value::as_array);
+    for system in systems.into_iter().flatten() {
+        let Ok(code) = serde_json::from_value::<SystemCode>(system["code"].clone()) else {
+            continue;
+        };
+        let services = (system["nodes"].as_array().into_iter().flatten())
+            .filter_map(|node| node.get("Service"));
+        for service in services {
+            let Some(service_code) = service["code"]["code"].as_u64() else {
+                continue;
+            };
+            for block in service["blocks"].as_array().into_iter().flatten() {
+                let Some(num) = block["num"].as_u64() else {
+                    continue;
+                };
+                let data = leaf_bytes(&block["data"]);
+                blocks.insert((code.into(), service_code as u16, num as u16), data);
+            }
+        }
+    }
+    blocks
+}
+
+/// Collects every leaf of a JSON value, by JSON pointer. Arrays of bytes count as leaves,
+/// so a changed UID is one change, not one per byte.
+fn flatten(path: String, value: Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                flatten(
+                    format!("{}/{}", path, k.replace('~', "~0").replace('/', "~1")),
+                    v,
+                    out,
+                );
+            }
+        }
+        Value::Array(items) if !is_bytes(&items) => {
+            for (i, v) in items.into_iter().enumerate() {
+                flatten(format!("{}/{}", path, i), v, out);
+            }
+        }
+        v => {
+            out.insert(path, v);
+        }
+    }
+}
+
+/// Reads a leaf as bytes, if it looks like some: an array of bytes, a hex string, or a
+/// number (big-endian, so they compare the same way).
+fn leaf_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Array(items) if is_bytes(items) => Some(
+            items
+                .iter()
+                .filter_map(|v| v.as_u64())
+                .map(|n| n as u8)
+                .collect(),
+        ),
+        Value::String(s) => hexvec::parse(s).ok(),
+        Value::Number(n) => n.as_u64().map(|n| n.to_be_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+fn is_bytes(items: &[Value]) -> bool {
+    !items.is_empty() && (items.iter()).all(|v| v.as_u64().is_some_and(|n| n <= 0xFF))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulate::{EmulatedCard, Profile};
+    use crate::money::Currency;
+    use serde_json::json;
+
+    fn tap(profile: &str) -> Tap {
+        let mut card = EmulatedCard::new(Profile::from_toml(profile).unwrap());
+        Tap::run(&mut card, &Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_change_of() {
+        let b = |v: &[u8]| Some(v.to_vec());
+        assert_eq!(
+            Change::of(&[b(&[0, 1]), b(&[0, 2]), b(&[1, 0])]),
+            Change::Counting
+        );
+        assert_eq!(Change::of(&[b(&[5]), b(&[2])]), Change::Varying);
+        assert_eq!(Change::of(&[b(&[1]), b(&[1, 2])]), Change::Varying);
+        assert_eq!(Change::of(&[None, None]), Change::Varying);
+        // Two taps can't tell a counter from a random number that happened to go up.
+        assert_eq!(Change::of(&[b(&[0, 1]), b(&[0, 2])]), Change::Varying);
+    }
+
+    #[test]
+    fn test_dump_diff() {
+        let probe = |balance: [u8; 2], atc: &str| {
+            let mut edy = balance.to_vec();
+            edy.resize(16, 0);
+            json!({
+                "uid": { "FelicaIdm": 1234 },
+                "emv": { "atc": atc },
+                "felica": { "systems": [{
+                    "code": "FeliCaCommon",
+                    "nodes": [
+                        { "Area": { "code": { "code": 0 } } },
+                        { "Service": { "code": { "code": 0x1317 }, "blocks": [
+                            { "num": 0, "name": null, "data": edy },
+                            { "num": 1, "name": null, "data": null },
+                        ]}},
+                    ],
+                }]},
+            })
+        };
+        // ¥10,000 -> ¥9,000, and the transaction counter went up.
+        let diff = DumpDiff::compare(&probe([0x10, 0x27], "0001"), &probe([0x28, 0x23], "0002"));
+        assert_eq!(
+            diff.fields,
+            vec![FieldChange {
+                path: "/emv/atc".into(),
+                before: json!("0001"),
+                after: json!("0002"),
+            }]
+        );
+        assert_eq!(diff.blocks.len(), 1);
+        assert_eq!(diff.blocks[0].system, SystemCode::FeliCaCommon);
+        assert_eq!((diff.blocks[0].service, diff.blocks[0].num), (0x1317, 0));
+        assert_eq!(
+            diff.balances,
+            vec![BalanceChange {
+                purse: "Edy",
+                before: Some(Amount::new(10000, Currency::JPY)),
+                after: Some(Amount::new(9000, Currency::JPY)),
+            }]
+        );
+
+        let same = DumpDiff::compare(&probe([0x10, 0x27], "0001"), &probe([0x10, 0x27], "0001"));
+        assert!(same.is_empty());
+    }
+
+    #[test]
+    fn test_compare() {
+        let profile = |uid: &str| {
+            format!(
+                r#"
+                atr = "3B 8F 80 01 80 4F 0C A0 00 00 03 06 03 00 01 00 00 00 00 6A"
+                uid = "{}"
+                "#,
+                uid
+            )
+        };
+        let taps = [
+            tap(&profile("08 11 22 33")),
+            tap(&profile("08 44 55 66")),
+            tap(&profile("08 44 55 66")),
+        ];
+        let diff = Differential::compare(&taps);
+        assert_eq!(diff.taps, 3);
+        assert_eq!(diff.diverged_at, None);
+        assert!(diff.mutating.is_empty());
+        assert!(diff.fields.iter().any(|f| f.path.starts_with("/uid")));
+        assert_eq!(diff.exchanges.len(), 1);
+        assert_eq!(diff.exchanges[0].change, Change::Varying);
+
+        // Nothing changes between identical taps.
+        let same =
+            Differential::compare(&[tap(&profile("01 02 03 04")), tap(&profile("01 02 03 04"))]);
+        assert!(same.fields.is_empty());
+        assert!(same.exchanges.is_empty());
+    }
+}
@@ -0,0 +1,656 @@
+//! Fake cards, for testing things that talk to cards without needing a real one.
+//!
+//! An emulated card is described by a [Profile] (a TOML or JSON file): an ATR, plus
+//! scripted responses to specific APDUs, and/or a little filesystem that SELECT, READ
+//! BINARY and READ RECORD work on. [EmulatedCard] is a [CardTransport], so you can point
+//! any command at it directly (which is also how the tests use it, as a mock card), or
+//! plug it into a vpcd virtual reader with [crate::transports::tcp::serve], so it shows
+//! up as a real card to the host's PCSC stack.
+//!
+//! ```toml
+//! atr = "3B 88 80 01 00 00 00 00 00 00 00 00 09"
+//! uid = "04 11 22 33"  # For GET DATA (FF CA 00 00).
+//!
+//! # Checked first, in order; "XX" matches any byte.
+//! [[apdu]]
+//! command = "80 CA 9F 17 XX"
+//! response = "9F 17 01 03 90 00"
+//!
+//! # Selecting an application by AID scopes [[file]]s and [[apdu]]s to it; anything
+//! # that isn't found there falls back to the top-level ones.
+//! [[application]]
+//! aid = "A0000000031010"
+//! fci = "6F 09 84 07 A0000000031010"
+//!
+//! [[application.apdu]]
+//! command = "80 A8 00 00 02 83 00 00"
+//! response = "77 0A 82 02 1800 94 04 08010100 90 00"
+//!
+//! [[application.file]]
+//! sfi = 1
+//! records = ["70 03 5A 01 42"]
+//! ```
+//!
+//! FeliCa cards get a `[felica]` section instead, with an IDm, PMm and blocks; see
+//! `examples/profiles` for a complete example of each.
+
+use crate::transports::trace::Trace;
+use crate::{serde_hex, CardTransport, Result as CardResult};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{debug, trace, trace_span};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error(transparent)]
+    ParseJSON(#[from] serde_json::Error),
+}
+
+/// Description of an emulated card.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(deserialize_with = "serde_hex::bytes")]
+    pub atr: Vec<u8>,
+    /// UID, as returned by the reader for GET DATA.
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub uid: Option<Vec<u8>>,
+    /// Scripted responses.
+    #[serde(default, rename = "apdu")]
+    pub apdus: Vec<ScriptedApdu>,
+    /// Applications, for SELECT by AID.
+    #[serde(default, rename = "application")]
+    pub applications: Vec<Application>,
+    /// Files, for SELECT/READ BINARY/READ RECORD.
+    #[serde(default, rename = "file")]
+    pub files: Vec<File>,
+    /// FeliCa card, behind the reader's `FF 00 00 00` wrapper.
+    pub felica: Option<Felica>,
+}
+
+impl Profile {
+    /// Loads a profile from a file; anything called `*.json` is JSON, everything else
+    /// is assumed to be TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&s),
+            _ => Self::from_toml(&s),
+        }
+    }
+
+    /// Parses a profile from TOML.
+    pub fn from_toml(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Parses a profile from JSON; same structure as the TOML.
+    pub fn from_json(s: &str) -> Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// A card that answers what was asked of it in a trace (eg. one from
+    /// [crate::transports::trace::import]), and nothing else. Unlike a
+    /// [crate::transports::trace::Replay], commands can come in any order; if the same one
+    /// was sent more than once, it gets the first answer every time.
+    pub fn from_trace(trace: &Trace) -> Self {
+        Self {
+            atr: trace.atr.clone().unwrap_or_default(),
+            apdus: (trace.exchanges.iter())
+                .map(|(command, response)| ScriptedApdu {
+                    command: command.iter().copied().map(Some).collect(),
+                    response: response.clone(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptedApdu {
+    /// Command to match, as hex; "XX" matches any byte.
+    #[serde(deserialize_with = "hex_pattern")]
+    pub command: Vec<Option<u8>>,
+    /// Response to send back, including SW1-SW2.
+    #[serde(deserialize_with = "serde_hex::bytes")]
+    pub response: Vec<u8>,
+}
+
+impl ScriptedApdu {
+    fn matches(&self, capdu: &[u8]) -> bool {
+        self.command.len() == capdu.len()
+            && self
+                .command
+                .iter()
+                .zip(capdu)
+                .all(|(pat, b)| pat.is_none_or(|p| p == *b))
+    }
+}
+
+/// An application, with its own files and scripted responses.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Application {
+    #[serde(deserialize_with = "serde_hex::bytes")]
+    pub aid: Vec<u8>,
+    /// Response to SELECT. (Default: nothing, just 9000.)
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub fci: Option<Vec<u8>>,
+    /// Scripted responses, while the application is selected.
+    #[serde(default, rename = "apdu")]
+    pub apdus: Vec<ScriptedApdu>,
+    /// Files inside the application.
+    #[serde(default, rename = "file")]
+    pub files: Vec<File>,
+}
+
+/// A DF or EF. Everything is optional; a file only responds to the commands it has
+/// enough information for.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct File {
+    /// DF name (eg. an AID), for SELECT by name.
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub name: Option<Vec<u8>>,
+    /// File identifier, for SELECT by FID.
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub fid: Option<Vec<u8>>,
+    /// Short File Identifier, for READ RECORD/READ BINARY without selecting first.
+    pub sfi: Option<u8>,
+    /// Response to SELECT. (Default: nothing, just 9000.)
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub fci: Option<Vec<u8>>,
+    /// Contents of a transparent EF.
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub data: Option<Vec<u8>>,
+    /// Records of a record-oriented EF, starting at 1.
+    #[serde(default, deserialize_with = "serde_hex::vec")]
+    pub records: Vec<Vec<u8>>,
+}
+
+/// A FeliCa card. This only does what a FeliCa Lite(-S) does: there's one flat set of
+/// blocks, which every service sees, and RequestSystemCode (etc.) goes unanswered.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Felica {
+    /// IDm; also the UID, unless the profile has one.
+    #[serde(deserialize_with = "serde_hex::array")]
+    pub idm: [u8; 8],
+    /// PMm, for GET DATA (FF CA 01 00).
+    #[serde(default, deserialize_with = "serde_hex::opt")]
+    pub pmm: Option<Vec<u8>>,
+    /// Blocks for ReadWithoutEncryption, by (hex) block number.
+    #[serde(default, deserialize_with = "hex_blocks")]
+    pub blocks: BTreeMap<u16, [u8; 16]>,
+}
+
+impl Felica {
+    /// Handles a ReadWithoutEncryption (minus the header), and returns the status flags
+    /// and block data; or None if the command is malformed.
+    fn read_without_encryption(&self, cmd: &[u8]) -> Option<Vec<u8>> {
+        // [nsvc] [service codes (2 bytes each)] [nblk] [block list elements]
+        let (&nsvc, rest) = cmd.split_first()?;
+        let (&nblk, mut rest) = rest.get(nsvc as usize * 2..)?.split_first()?;
+        let mut blocks = vec![];
+        for _ in 0..nblk {
+            // Elements are 2 bytes if bit 8 is set (1-byte block number), otherwise 3.
+            let num = match *rest {
+                [b, num, ref tail @ ..] if b & 0x80 != 0 => {
+                    rest = tail;
+                    num as u16
+                }
+                [_, lo, hi, ref tail @ ..] => {
+                    rest = tail;
+                    u16::from_le_bytes([lo, hi])
+                }
+                _ => return None,
+            };
+            blocks.push(self.blocks.get(&num));
+        }
+        Some(match blocks.into_iter().collect::<Option<Vec<_>>>() {
+            Some(blocks) => {
+                let mut rsp = vec![0x00, 0x00, blocks.len() as u8];
+                blocks.into_iter().for_each(|b| rsp.extend(b));
+                rsp
+            }
+            None => vec![0x01, 0xA8], // Illegal block number.
+        })
+    }
+}
+
+/// Which file is selected: an index into an application's files, or the top-level ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileRef {
+    app: Option<usize>,
+    idx: usize,
+}
+
+/// A card that exists only as a [Profile].
+pub struct EmulatedCard {
+    pub profile: Profile,
+    /// Index of the currently selected application.
+    app: Option<usize>,
+    /// The currently selected file.
+    current: Option<FileRef>,
+}
+
+impl EmulatedCard {
+    pub fn new(profile: Profile) -> Self {
+        Self {
+            profile,
+            app: None,
+            current: None,
+        }
+    }
+
+    /// Handles a command, and returns the response (data + SW1-SW2).
+    pub fn respond(&mut self, capdu: &[u8]) -> Vec<u8> {
+        let span = trace_span!("EmulatedCard::respond");
+        let _enter = span.enter();
+
+        if let Some(script) = self.scripts().find(|s| s.matches(capdu)) {
+            trace!("Scripted response");
+            return script.response.clone();
+        }
+        if let Some(frame) = crate::felica::unwrap_apdu(capdu) {
+            return self.felica(frame);
+        }
+        let (hdr, body) = match capdu.split_first_chunk::<4>() {
+            Some((hdr, body)) => (*hdr, body),
+            None => return sw(0x67, 0x00),
+        };
+        // Short APDUs only: [Lc data] [Le].
+        let (data, le) = match body {
+            [] => (&[][..], None),
+            [le] => (&[][..], Some(*le)),
+            [lc, rest @ ..] if rest.len() == *lc as usize => (rest, None),
+            [lc, rest @ ..] if rest.len() == *lc as usize + 1 => {
+                (&rest[..*lc as usize], rest.last().copied())
+            }
+            _ => return sw(0x67, 0x00),
+        };
+        let le = le.map(|le| if le == 0 { 256 } else { le as usize });
+
+        match hdr {
+            [0x00, 0xA4, p1, _] => self.select(p1, data),
+            [0x00, 0xB0, p1, p2] => self.read_binary(p1, p2, le),
+            [0x00, 0xB2, p1, p2] => self.read_record(p1, p2),
+            [0x00, ..] => sw(0x6D, 0x00),
+            // PCSC pseudo-APDU, answered by the reader.
+            [0xFF, 0xCA, p1, 0x00] => self.get_data(p1),
+            _ => sw(0x6E, 0x00),
+        }
+    }
+
+    fn select(&mut self, p1: u8, id: &[u8]) -> Vec<u8> {
+        if p1 == 0x04 {
+            if let Some(i) = self.profile.applications.iter().position(|a| a.aid == id) {
+                debug!(aid = hex::encode_upper(id), "SELECT application");
+                self.app = Some(i);
+                self.current = None;
+                let fci = self.profile.applications[i].fci.clone().unwrap_or_default();
+                return [fci, sw(0x90, 0x00)].concat();
+            }
+        }
+        let found = self
+            .files()
+            .find(|(_, f)| match p1 {
+                0x04 => f.name.as_deref() == Some(id),
+                0x00..=0x02 => f.fid.as_deref() == Some(id),
+                _ => false,
+            })
+            .map(|(r, _)| r);
+        debug!(p1, id = hex::encode_upper(id), ?found, "SELECT");
+        match found {
+            Some(r) => {
+                // Selecting a DF by name outside the application leaves the application.
+                if p1 == 0x04 {
+                    self.app = r.app;
+                }
+                self.current = Some(r);
+                let fci = self.file(r).fci.clone().unwrap_or_default();
+                [fci, sw(0x90, 0x00)].concat()
+            }
+            None => sw(0x6A, 0x82),
+        }
+    }
+
+    fn read_binary(&mut self, p1: u8, p2: u8, le: Option<usize>) -> Vec<u8> {
+        // If bit 8 of P1 is set, P1 has an SFI and P2 is the offset; otherwise it's all
+        // offset, into the current EF.
+        let (file, offset) = if p1 & 0x80 != 0 {
+            (self.by_sfi(p1 & 0x1F), p2 as usize)
+        } else {
+            (self.current, u16::from_be_bytes([p1, p2]) as usize)
+        };
+        let Some(data) = file.and_then(|r| self.file(r).data.as_ref()) else {
+            return sw(0x69, 0x86); // Command not allowed (no current EF).
+        };
+        let Some(rest) = data.get(offset..).filter(|r| !r.is_empty()) else {
+            return sw(0x6B, 0x00);
+        };
+        match le.unwrap_or(256) {
+            // Le=00 means "as much as you've got"; otherwise, tell them what Le to use.
+            256 => [&rest[..rest.len().min(256)], &[0x90, 0x00]].concat(),
+            le if le > rest.len() => sw(0x6C, rest.len() as u8),
+            le => [&rest[..le], &[0x90, 0x00]].concat(),
+        }
+    }
+
+    fn read_record(&mut self, p1: u8, p2: u8) -> Vec<u8> {
+        if p2 & 0x07 != 0x04 {
+            return sw(0x6A, 0x86); // We only do "read record P1".
+        }
+        let file = match p2 >> 3 {
+            0 => self.current,
+            sfi => self.by_sfi(sfi),
+        };
+        let Some(file) = file else {
+            return sw(0x6A, 0x82);
+        };
+        match (p1 as usize)
+            .checked_sub(1)
+            .and_then(|i| self.file(file).records.get(i))
+        {
+            Some(rec) => [&rec[..], &[0x90, 0x00]].concat(),
+            None => sw(0x6A, 0x83),
+        }
+    }
+
+    fn get_data(&self, p1: u8) -> Vec<u8> {
+        let felica = self.profile.felica.as_ref();
+        let data = match p1 {
+            0x00 => (self.profile.uid.clone()).or_else(|| felica.map(|f| f.idm.to_vec())),
+            0x01 => felica.and_then(|f| f.pmm.clone()),
+            _ => None,
+        };
+        match data {
+            Some(data) => [data, sw(0x90, 0x00)].concat(),
+            None => sw(0x6A, 0x81),
+        }
+    }
+
+    /// Handles a FeliCa frame (length byte and all), as a reader would.
+    fn felica(&self, frame: &[u8]) -> Vec<u8> {
+        let Some(felica) = self.profile.felica.as_ref() else {
+            return sw(0x6A, 0x81); // Not a FeliCa card, so no wrapper.
+        };
+        // Commands all start with [len] [code] [IDm]. The IDm isn't checked.
+        let rsp = match frame {
+            [_, 0x06, rest @ ..] => rest.split_first_chunk::<8>().and_then(|(idm, cmd)| {
+                let body = felica.read_without_encryption(cmd)?;
+                Some([&[0x07][..], idm, &body].concat())
+            }),
+            _ => None,
+        };
+        debug!(cmd = ?frame.get(1), ok = rsp.is_some(), "FeliCa");
+        match rsp {
+            Some(rsp) => [&[rsp.len() as u8 + 1][..], &rsp, &[0x90, 0x00]].concat(),
+            None => sw(0x63, 0x00), // The card didn't answer.
+        }
+    }
+
+    /// Scripted responses in scope: the selected application's, then the top-level ones.
+    fn scripts(&self) -> impl Iterator<Item = &ScriptedApdu> {
+        let app = self.app.map(|i| &self.profile.applications[i]);
+        app.into_iter()
+            .flat_map(|app| app.apdus.iter())
+            .chain(self.profile.apdus.iter())
+    }
+
+    /// Files in scope: the selected application's, then the top-level ones.
+    fn files(&self) -> impl Iterator<Item = (FileRef, &File)> {
+        let app = self.app.map(|i| (i, &self.profile.applications[i]));
+        let app_files = app.into_iter().flat_map(|(i, app)| {
+            (app.files.iter().enumerate()).map(move |(idx, f)| (FileRef { app: Some(i), idx }, f))
+        });
+        let files =
+            (self.profile.files.iter().enumerate()).map(|(idx, f)| (FileRef { app: None, idx }, f));
+        app_files.chain(files)
+    }
+
+    fn file(&self, r: FileRef) -> &File {
+        match r.app {
+            Some(app) => &self.profile.applications[app].files[r.idx],
+            None => &self.profile.files[r.idx],
+        }
+    }
+
+    fn by_sfi(&self, sfi: u8) -> Option<FileRef> {
+        self.files()
+            .find(|(_, f)| f.sfi == Some(sfi))
+            .map(|(r, _)| r)
+    }
+}
+
+impl CardTransport for EmulatedCard {
+    fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> CardResult<&'r [u8]> {
+        let rsp = self.respond(capdu);
+        rbuf.get_mut(..rsp.len())
+            .ok_or(crate::Error::InsufficientBuffer)?
+            .copy_from_slice(&rsp);
+        Ok(&rbuf[..rsp.len()])
+    }
+
+    fn atr(&mut self) -> CardResult<Vec<u8>> {
+        Ok(self.profile.atr.clone())
+    }
+
+    /// Forgets what was selected, like a real card would.
+    fn reset(&mut self, _kind: crate::transport::Reset) -> CardResult<()> {
+        (self.app, self.current) = (None, None);
+        Ok(())
+    }
+}
+
+fn sw(sw1: u8, sw2: u8) -> Vec<u8> {
+    vec![sw1, sw2]
+}
+
+/// A map of hex block numbers to hex block contents.
+fn hex_blocks<'de, D: Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<BTreeMap<u16, [u8; 16]>, D::Error> {
+    BTreeMap::<String, String>::deserialize(d)?
+        .into_iter()
+        .map(|(num, data)| {
+            let num = u16::from_str_radix(&num, 16).map_err(serde::de::Error::custom)?;
+            let data = serde_hex::parse(&data).map_err(serde::de::Error::custom)?;
+            let data = data.try_into().map_err(|v: Vec<u8>| {
+                serde::de::Error::custom(format!("block {num:02X} is {} bytes, not 16", v.len()))
+            })?;
+            Ok((num, data))
+        })
+        .collect()
+}
+
+fn hex_pattern<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<Option<u8>>, D::Error> {
+    let s: String = String::deserialize(d)?.split_whitespace().collect();
+    if !s.len().is_multiple_of(2) {
+        return Err(serde::de::Error::custom("odd number of hex digits"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| match &s[i..i + 2] {
+            "XX" | "xx" => Ok(None),
+            byte => u8::from_str_radix(byte, 16)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iso7816;
+
+    const PROFILE: &str = r#"
+        atr = "3B 88 80 01 00 00 00 00 00 00 00 00 09"
+
+        [[apdu]]
+        command = "80 CA 9F 17 XX"
+        response = "9F 17 01 03 90 00"
+
+        [[file]]
+        name = "A0000000031010"
+        fci = "6F 09 84 07 A0000000031010"
+
+        [[file]]
+        sfi = 1
+        records = ["70 03 5A 01 42", "70 00"]
+
+        [[file]]
+        fid = "0101"
+        data = "0102030405"
+    "#;
+
+    #[test]
+    fn test_emulated_card() {
+        let mut card = EmulatedCard::new(Profile::from_toml(PROFILE).unwrap());
+        assert_eq!(card.atr().unwrap()[0], 0x3B);
+        assert_eq!(
+            card.respond(&[0x80, 0xCA, 0x9F, 0x17, 0x00]),
+            vec![0x9F, 0x17, 0x01, 0x03, 0x90, 0x00]
+        );
+
+        let (mut wbuf, mut rbuf) = ([0; 64], [0; 64]);
+        let fci = iso7816::Select {
+            id: iso7816::SelectID::Name(&[0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10]),
+            mode: iso7816::SelectMode::First,
+        }
+        .exec(&mut card, &mut wbuf, &mut rbuf)
+        .unwrap();
+        assert_eq!(fci[0], 0x6F);
+
+        let rec = iso7816::ReadRecord {
+            sfi: 1,
+            id: iso7816::RecordID::Number(1),
+        }
+        .call(&mut card, &mut wbuf, &mut rbuf)
+        .unwrap();
+        assert_eq!(rec.data, &[0x70, 0x03, 0x5A, 0x01, 0x42]);
+        assert_eq!(
+            card.respond(&[0x00, 0xB2, 0x03, 0x0C, 0x00]),
+            vec![0x6A, 0x83]
+        );
+
+        // Selecting an EF by FID, then reading past the end of it.
+        assert_eq!(
+            card.respond(&[0x00, 0xA4, 0x02, 0x0C, 0x02, 0x01, 0x01]),
+            vec![0x90, 0x00]
+        );
+        let data = iso7816::read_binary(&mut card, &mut wbuf, &mut rbuf, 0, 16).unwrap();
+        assert_eq!(data, vec![0x01, 0x02, 0x03, 0x04, 0x05]);
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        assert!(Profile::from_toml(r#"atr = "3B 8""#).is_err());
+        assert!(Profile::from_toml("atr = \"3B\"\nwhat = 1").is_err());
+        assert!(Profile::from_json(r#"{"atr": "3B", "felica": {"idm": "0102"}}"#).is_err());
+        assert!(Profile::from_toml(
+            "atr = \"3B\"\n[felica]\nidm = \"0102030405060708\"\nblocks = { 00 = \"00\" }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_application_scope() {
+        let mut card = EmulatedCard::new(
+            Profile::from_json(
+                r#"{
+                    "atr": "3B 00",
+                    "file": [{"sfi": 1, "records": ["01"]}, {"sfi": 2, "records": ["02"]}],
+                    "application": [{"aid": "A000", "file": [{"sfi": 1, "records": ["A1"]}]}]
+                }"#,
+            )
+            .unwrap(),
+        );
+        let read_sfi = |card: &mut EmulatedCard, sfi: u8| {
+            card.respond(&[0x00, 0xB2, 0x01, (sfi << 3) | 0x04, 0x00])
+        };
+        assert_eq!(read_sfi(&mut card, 1), vec![0x01, 0x90, 0x00]);
+        assert_eq!(
+            card.respond(&[0x00, 0xA4, 0x04, 0x00, 0x02, 0xA0, 0x00]),
+            vec![0x90, 0x00]
+        );
+        assert_eq!(read_sfi(&mut card, 1), vec![0xA1, 0x90, 0x00]);
+        assert_eq!(read_sfi(&mut card, 2), vec![0x02, 0x90, 0x00]);
+
+        // A reset leaves the application.
+        card.reset(crate::transport::Reset::Warm).unwrap();
+        assert_eq!(read_sfi(&mut card, 1), vec![0x01, 0x90, 0x00]);
+    }
+
+    #[test]
+    fn test_example_emv() {
+        let mut card = EmulatedCard::new(
+            Profile::from_toml(include_str!("../examples/profiles/emv-test-card.toml")).unwrap(),
+        );
+        let probe = crate::probe::Probe::run(&mut card, None).unwrap();
+        let emv = probe.emv.expect("no EMV probe");
+        assert_eq!(emv.records.len(), 1);
+        assert_eq!(emv.applications.len(), 1);
+        assert_eq!(probe.xrefs.len(), 1);
+        assert_eq!(probe.xrefs[0].what, "PAN");
+        assert!(probe.xrefs[0].consistent);
+
+        // GET PROCESSING OPTIONS only works inside the application.
+        let gpo = [0x80, 0xA8, 0x00, 0x00, 0x04, 0x83, 0x02, 0x08, 0x26, 0x00];
+        assert_eq!(card.respond(&gpo)[0], 0x77);
+        card.respond(&[0x00, 0xA4, 0x04, 0x00, 0x01, 0xFF]);
+        assert_eq!(card.respond(&gpo)[0], 0x77);
+        card.respond(&[
+            0x00, 0xA4, 0x04, 0x00, 0x0E, b'1', b'P', b'A', b'Y', b'.', b'S', b'Y', b'S', b'.',
+            b'D', b'D', b'F', b'0', b'1',
+        ]);
+        assert_eq!(card.respond(&gpo), vec![0x6E, 0x00]);
+    }
+
+    #[test]
+    fn test_from_trace() {
+        // Probing a card made from a trace of a probe finds the same things.
+        let card = EmulatedCard::new(
+            Profile::from_toml(include_str!("../examples/profiles/emv-test-card.toml")).unwrap(),
+        );
+        let mut recorder = crate::transports::trace::Recorder::new(card);
+        let probe = crate::probe::Probe::run(&mut recorder, None).unwrap();
+
+        let mut card = EmulatedCard::new(Profile::from_trace(&recorder.trace));
+        let again = crate::probe::Probe::run(&mut card, None).unwrap();
+        assert_eq!(again.atr, probe.atr);
+        let records = |probe: crate::probe::Probe| probe.emv.map(|emv| emv.records.len());
+        assert_eq!(records(again), records(probe));
+
+        // It doesn't know anything that wasn't asked.
+        assert_eq!(
+            card.respond(&[0x00, 0xB2, 0x07, 0x3C, 0x00]),
+            vec![0x6A, 0x82]
+        );
+    }
+
+    #[test]
+    fn test_example_felica_lite_s() {
+        let mut card = EmulatedCard::new(
+            Profile::from_toml(include_str!("../examples/profiles/felica-lite-s.toml")).unwrap(),
+        );
+        let probe = crate::probe::Probe::run(&mut card, None).unwrap();
+        let felica = probe.felica.expect("no FeliCa probe");
+        assert_eq!(felica.idm, 0x012E457A3B62910C);
+        assert_eq!(felica.systems.len(), 1);
+        assert_eq!(probe.xrefs.len(), 1);
+        assert_eq!(probe.xrefs[0].what, "IDm");
+        assert!(probe.xrefs[0].consistent);
+    }
+}
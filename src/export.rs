@@ -0,0 +1,448 @@
+//! Card dumps, in the formats hobbyist tools use: Flipper Zero's `.nfc` files, and the
+//! Proxmark3's `.bin` and `.json` dumps.
+//!
+//! Only memory cards have anything to dump this way: MIFARE Classic and Ultralight (see
+//! [crate::mifare]), and FeliCa Lite-S. Other FeliCa cards come out with just their IDm
+//! and PMm, since neither tool has anywhere to put a card's services.
+//!
+//! A PC/SC reader won't tell us the ATQA and SAK, so they're made up from what the card's
+//! called in the ATR (and how long the UID is); a Flipper wants them, even if it doesn't
+//! do much with them. Anything else we can't know, like an Ultralight's signature and
+//! counters, is zeroes. Classic blocks we couldn't get into are `??` in a Flipper file,
+//! and zeroes in a Proxmark one, which has no way to say so.
+
+use crate::probe::{self, felica::FelicaProbe, felica::LITE_S_BLOCKS};
+use crate::uid::CardUid;
+use crate::{atr, felica, mifare, CardTransport, Error, Result};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use tracing::{debug, trace_span};
+
+/// What was read from a card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dump {
+    /// MIFARE Classic (or Mini, or a MIFARE Plus in SL1); None for blocks in sectors that
+    /// wouldn't take the default key.
+    Classic {
+        uid: Vec<u8>,
+        blocks: Vec<Option<[u8; mifare::BLOCK_SIZE]>>,
+    },
+    /// MIFARE Ultralight, or an NTAG.
+    Ultralight {
+        uid: Vec<u8>,
+        pages: Vec<[u8; mifare::PAGE_SIZE]>,
+    },
+    /// FeliCa, with the FeliCa Lite-S blocks we could read, by block number.
+    Felica {
+        idm: u64,
+        pmm: Option<Vec<u8>>,
+        blocks: BTreeMap<u16, Vec<u8>>,
+    },
+}
+
+impl Dump {
+    /// Reads everything off the card; it's an error if it's not one we can dump.
+    pub fn read(card: &mut impl CardTransport) -> Result<Self> {
+        let span = trace_span!("Dump::read");
+        let _enter = span.enter();
+
+        let mut bufs = card.buffers();
+        let (wbuf, rbuf) = bufs.split();
+        let (atr, _) = atr::parse_lenient(&card.atr()?)?;
+        let standard = probe::get_atr_card_standard(&atr);
+        let name = probe::get_atr_card_name(&atr);
+        debug!(?standard, ?name, "Card");
+        let uid = match probe::probe_uid(card, wbuf, rbuf, standard) {
+            CardUid::FelicaIdm(idm) => {
+                let felica = probe::felica::probe_felica(card, wbuf, rbuf, idm, false, None)?;
+                return Ok(Self::from_felica(&felica));
+            }
+            CardUid::Iso14443Uid(uid) => uid,
+            _ => {
+                return Err(Error::Transport(
+                    "export",
+                    "the reader won't say the UID".into(),
+                ))
+            }
+        };
+        if let Some(blocks) = name.and_then(mifare::classic_blocks) {
+            let blocks = mifare::read_classic(card, wbuf, rbuf, blocks)?;
+            return Ok(Self::Classic {
+                uid: uid.to_vec(),
+                blocks,
+            });
+        }
+        if name.is_some_and(mifare::is_ultralight) {
+            let pages = mifare::read_ultralight(card, wbuf, rbuf)?;
+            return Ok(Self::Ultralight {
+                uid: uid.to_vec(),
+                pages,
+            });
+        }
+        Err(Error::Transport(
+            "export",
+            match name {
+                Some(name) => format!("can't dump a {}", name),
+                None => "can only dump MIFARE Classic, Ultralight and FeliCa cards".into(),
+            },
+        ))
+    }
+
+    /// Takes the FeliCa Lite-S blocks out of a probe, if it's one.
+    pub fn from_felica(probe: &FelicaProbe) -> Self {
+        let mut blocks = BTreeMap::new();
+        let systems = (probe.systems.iter()).filter(|s| s.code == felica::SystemCode::FeliCaLiteS);
+        for node in systems.flat_map(|s| &s.nodes) {
+            if let probe::felica::FelicaNode::Service { blocks: bs, .. } = node {
+                for block in bs {
+                    if let Some(data) = &block.data {
+                        blocks.entry(block.num).or_insert_with(|| data.to_vec());
+                    }
+                }
+            }
+        }
+        Self::Felica {
+            idm: probe.idm,
+            pmm: probe.pmm.as_deref().map(<[u8]>::to_vec),
+            blocks,
+        }
+    }
+
+    /// The UID, or the IDm for FeliCa.
+    pub fn uid(&self) -> Vec<u8> {
+        match self {
+            Self::Classic { uid, .. } | Self::Ultralight { uid, .. } => uid.clone(),
+            Self::Felica { idm, .. } => idm.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// ATQA (as it's sent, LSB first) and SAK, as the card would've answered them; see the
+    /// [module docs](self).
+    fn atqa_sak(&self) -> ([u8; 2], u8) {
+        let uid_size = match self.uid().len() {
+            4 => 0x00,
+            7 => 0x40,
+            _ => 0x80,
+        };
+        match self {
+            Self::Classic { blocks, .. } => match blocks.len() {
+                20 => ([0x04 | uid_size, 0x00], 0x09),
+                256 => ([0x02 | uid_size, 0x00], 0x18),
+                _ => ([0x04 | uid_size, 0x00], 0x08),
+            },
+            _ => ([0x04 | uid_size, 0x00], 0x00),
+        }
+    }
+
+    /// As a Flipper Zero `.nfc` file.
+    pub fn to_flipper(&self) -> String {
+        let mut s = String::new();
+        self.write_flipper(&mut s).expect("writing to a String");
+        s
+    }
+
+    fn write_flipper(&self, s: &mut impl std::fmt::Write) -> std::fmt::Result {
+        let hex = |data: &[u8]| hex_spaced(data.iter().copied().map(Some));
+        writeln!(s, "Filetype: Flipper NFC device")?;
+        writeln!(s, "Version: 4")?;
+        writeln!(s, "# Device type can be ISO14443-3A, ISO14443-3B, ISO14443-4A, ISO14443-4B, ISO15693-3, FeliCa, NTAG/Ultralight, Mifare Classic, Mifare DESFire, SLIX, ST25TB")?;
+        writeln!(
+            s,
+            "Device type: {}",
+            match self {
+                Self::Classic { .. } => "Mifare Classic",
+                Self::Ultralight { .. } => "NTAG/Ultralight",
+                Self::Felica { .. } => "FeliCa",
+            }
+        )?;
+        writeln!(s, "# UID is common for all formats")?;
+        writeln!(s, "UID: {}", hex(&self.uid()))?;
+        if !matches!(self, Self::Felica { .. }) {
+            let ([atqa0, atqa1], sak) = self.atqa_sak();
+            writeln!(s, "# ISO14443-3A specific data")?;
+            writeln!(s, "ATQA: {}", hex(&[atqa1, atqa0]))?;
+            writeln!(s, "SAK: {}", hex(&[sak]))?;
+        }
+        match self {
+            Self::Classic { blocks, .. } => {
+                writeln!(s, "# Mifare Classic specific data")?;
+                let kind = match blocks.len() {
+                    20 => "MINI",
+                    256 => "4K",
+                    _ => "1K",
+                };
+                writeln!(s, "Mifare Classic type: {}", kind)?;
+                writeln!(s, "Data format version: 2")?;
+                writeln!(s, "# Mifare Classic blocks, '??' means unknown data")?;
+                for (i, block) in blocks.iter().enumerate() {
+                    let data: Vec<_> = match block {
+                        Some(block) => block.iter().copied().map(Some).collect(),
+                        None => vec![None; mifare::BLOCK_SIZE],
+                    };
+                    writeln!(s, "Block {}: {}", i, hex_spaced(data))?;
+                }
+            }
+            Self::Ultralight { pages, .. } => {
+                writeln!(s, "# Mifare Ultralight specific data")?;
+                writeln!(s, "Data format version: 2")?;
+                writeln!(s, "NTAG/Ultralight type: {}", ultralight_type(pages.len()))?;
+                writeln!(s, "Signature: {}", hex(&[0; 32]))?;
+                writeln!(s, "Mifare version: {}", hex(&[0; 8]))?;
+                for i in 0..3 {
+                    writeln!(s, "Counter {}: 0", i)?;
+                    writeln!(s, "Tearing {}: 00", i)?;
+                }
+                writeln!(s, "Pages total: {}", pages.len())?;
+                writeln!(s, "Pages read: {}", pages.len())?;
+                for (i, page) in pages.iter().enumerate() {
+                    writeln!(s, "Page {}: {}", i, hex(page))?;
+                }
+                writeln!(s, "Failed authentication attempts: 0")?;
+            }
+            Self::Felica { idm, pmm, blocks } => {
+                writeln!(s, "# FeliCa specific data")?;
+                writeln!(s, "Data format version: 1")?;
+                writeln!(s, "Manufacture id: {}", hex(&idm.to_be_bytes()))?;
+                let pmm = pmm.as_deref().unwrap_or(&[0; 8]);
+                writeln!(s, "Manufacture parameter: {}", hex(pmm))?;
+                if !blocks.is_empty() {
+                    writeln!(s, "Blocks total: {}", LITE_S_BLOCKS.len())?;
+                    writeln!(s, "Blocks read: {}", blocks.len())?;
+                    // Each block starts with the status flags it was read with; a failed
+                    // read is FF FF, with no data.
+                    for (i, (num, _)) in LITE_S_BLOCKS.iter().enumerate() {
+                        let mut block = vec![0xFF, 0xFF];
+                        block.resize(2 + 16, 0x00);
+                        if let Some(data) = blocks.get(num) {
+                            block[..2].copy_from_slice(&[0x00, 0x00]);
+                            block[2..2 + data.len()].copy_from_slice(data);
+                        }
+                        writeln!(s, "Block {}: {}", i, hex(&block))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// As a Proxmark3 `.bin` dump: just the memory, apart from an Ultralight, which gets
+    /// the header `hf mfu dump` puts in front of it.
+    pub fn to_proxmark_bin(&self) -> Vec<u8> {
+        match self {
+            Self::Classic { blocks, .. } => (blocks.iter())
+                .flat_map(|block| block.unwrap_or_default())
+                .collect(),
+            Self::Ultralight { pages, .. } => {
+                // Version (8), TBO (2+1), last page number (1), signature (32), and 3
+                // counters and tearing flags (4 each).
+                let mut out = vec![0; 56];
+                out[11] = pages.len().saturating_sub(1) as u8;
+                out.extend(pages.iter().flatten());
+                out
+            }
+            Self::Felica { blocks, .. } => blocks.values().flatten().copied().collect(),
+        }
+    }
+
+    /// As a Proxmark3 `.json` dump.
+    pub fn to_proxmark_json(&self) -> Value {
+        let hex = |data: &[u8]| hex::encode_upper(data);
+        let ([atqa0, atqa1], sak) = self.atqa_sak();
+        let (file_type, card, blocks): (_, _, Vec<Vec<u8>>) = match self {
+            Self::Classic { blocks, .. } => (
+                "mfcard",
+                json!({
+                    "UID": hex(&self.uid()),
+                    "ATQA": hex(&[atqa0, atqa1]),
+                    "SAK": hex(&[sak]),
+                }),
+                (blocks.iter())
+                    .map(|block| block.unwrap_or_default().to_vec())
+                    .collect(),
+            ),
+            Self::Ultralight { pages, .. } => (
+                "mfu",
+                json!({
+                    "UID": hex(&self.uid()),
+                    "Version": hex(&[0; 8]),
+                    "TBO_0": "0000",
+                    "TBO_1": "00",
+                    "Signature": hex(&[0; 32]),
+                    "Counter0": "000000",
+                    "Tearing0": "00",
+                    "Counter1": "000000",
+                    "Tearing1": "00",
+                    "Counter2": "000000",
+                    "Tearing2": "00",
+                }),
+                pages.iter().map(|page| page.to_vec()).collect(),
+            ),
+            Self::Felica { idm, pmm, blocks } => (
+                "felica",
+                json!({
+                    "IDm": hex(&idm.to_be_bytes()),
+                    "PMm": pmm.as_deref().map(hex),
+                }),
+                blocks.values().cloned().collect(),
+            ),
+        };
+        // FeliCa blocks are numbered (and not all of them can be read); everything else is
+        // in order, from 0.
+        let blocks: Map<_, _> = match self {
+            Self::Felica { blocks, .. } => (blocks.iter())
+                .map(|(num, data)| (num.to_string(), hex(data).into()))
+                .collect(),
+            _ => (blocks.iter().enumerate())
+                .map(|(i, data)| (i.to_string(), hex(data).into()))
+                .collect(),
+        };
+        json!({
+            "Created": "cardinal",
+            "FileType": file_type,
+            "Card": card,
+            "blocks": blocks,
+        })
+    }
+}
+
+/// Bytes as spaced-out hex, with `??` for the ones we don't know.
+fn hex_spaced(data: impl IntoIterator<Item = Option<u8>>) -> String {
+    (data.into_iter())
+        .map(|b| b.map_or_else(|| "??".into(), |b| format!("{:02X}", b)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What a Flipper calls an Ultralight with this many pages.
+fn ultralight_type(pages: usize) -> &'static str {
+    match pages {
+        20 => "Mifare Ultralight 11",
+        41 => "Mifare Ultralight 21",
+        45 => "NTAG213",
+        135 => "NTAG215",
+        231 => "NTAG216",
+        _ => "Mifare Ultralight",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A MIFARE Classic 1K, whose sector 1 has had its keys changed; block n is all n.
+    struct Classic {
+        authed: Option<u16>,
+    }
+
+    impl CardTransport for Classic {
+        fn atr(&mut self) -> Result<Vec<u8>> {
+            Ok(hex::decode("3B8F8001804F0CA000000306030001000000006A").unwrap())
+        }
+
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            let rsp = match capdu {
+                [0xFF, 0xCA, 0x00, 0x00, 0x00] => vec![0xDE, 0xAD, 0xBE, 0xEF, 0x90, 0x00],
+                [0xFF, 0x82, 0x00, 0x00, 0x06, ..] => vec![0x90, 0x00],
+                [0xFF, 0x86, 0x00, 0x00, 0x05, 0x01, 0x00, block, ..] => {
+                    self.authed = (*block != 4).then_some(u16::from(*block) / 4);
+                    match self.authed {
+                        Some(_) => vec![0x90, 0x00],
+                        None => vec![0x63, 0x00],
+                    }
+                }
+                [0xFF, 0xB0, 0x00, block, 0x10] if self.authed == Some(u16::from(*block) / 4) => {
+                    let mut rsp = vec![*block; 16];
+                    rsp.extend([0x90, 0x00]);
+                    rsp
+                }
+                _ => vec![0x69, 0x82],
+            };
+            rbuf[..rsp.len()].copy_from_slice(&rsp);
+            Ok(&rbuf[..rsp.len()])
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let Dump::Classic { uid, blocks } = Dump::read(&mut Classic { authed: None }).unwrap()
+        else {
+            panic!("not a Classic");
+        };
+        assert_eq!(uid, vec![0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(blocks.len(), 64);
+        assert_eq!(blocks[3], Some([3; 16]));
+        assert_eq!(blocks[4..8], [None; 4]);
+        assert_eq!(blocks[63], Some([63; 16]));
+    }
+
+    #[test]
+    fn test_classic() {
+        let mut blocks = vec![Some([0x11; 16]); 64];
+        blocks[4] = None;
+        let dump = Dump::Classic {
+            uid: vec![0xDE, 0xAD, 0xBE, 0xEF],
+            blocks,
+        };
+
+        let nfc = dump.to_flipper();
+        assert!(nfc.contains("\nDevice type: Mifare Classic\n"));
+        assert!(nfc.contains("\nUID: DE AD BE EF\n"));
+        assert!(nfc.contains("\nATQA: 00 04\nSAK: 08\n"));
+        assert!(nfc.contains("\nMifare Classic type: 1K\n"));
+        assert!(nfc.contains("\nBlock 4: ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ?? ??\n"));
+        assert!(nfc.ends_with("\nBlock 63: 11 11 11 11 11 11 11 11 11 11 11 11 11 11 11 11\n"));
+
+        let bin = dump.to_proxmark_bin();
+        assert_eq!(bin.len(), 1024);
+        assert_eq!(bin[64..80], [0; 16]);
+
+        let json = dump.to_proxmark_json();
+        assert_eq!(json["FileType"], "mfcard");
+        assert_eq!(json["Card"]["ATQA"], "0400");
+        assert_eq!(json["blocks"]["63"], "11111111111111111111111111111111");
+    }
+
+    #[test]
+    fn test_ultralight() {
+        let dump = Dump::Ultralight {
+            uid: vec![0x04, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06],
+            pages: vec![[0x22; 4]; 45],
+        };
+
+        let nfc = dump.to_flipper();
+        assert!(nfc.contains("\nATQA: 00 44\nSAK: 00\n"));
+        assert!(nfc.contains("\nNTAG/Ultralight type: NTAG213\n"));
+        assert!(nfc.contains("\nPages read: 45\nPage 0: 22 22 22 22\n"));
+
+        let bin = dump.to_proxmark_bin();
+        assert_eq!(bin.len(), 56 + 45 * 4);
+        assert_eq!(bin[11], 44);
+
+        let json = dump.to_proxmark_json();
+        assert_eq!(json["FileType"], "mfu");
+        assert_eq!(json["Card"]["UID"], "04010203040506");
+        assert_eq!(json["blocks"]["44"], "22222222");
+    }
+
+    #[test]
+    fn test_felica() {
+        let dump = Dump::Felica {
+            idm: 0x0123456789ABCDEF,
+            pmm: None,
+            blocks: [(0x00, vec![0x33; 16]), (0x82, vec![0x44; 16])].into(),
+        };
+
+        let nfc = dump.to_flipper();
+        assert!(nfc.contains("\nDevice type: FeliCa\n"));
+        assert!(nfc.contains("\nUID: 01 23 45 67 89 AB CD EF\n"));
+        assert!(nfc.contains("\nBlocks total: 28\nBlocks read: 2\n"));
+        assert!(nfc.contains(&format!("\nBlock 0: 00 00{}\n", " 33".repeat(16))));
+        assert!(nfc.contains(&format!("\nBlock 1: FF FF{}\n", " 00".repeat(16))));
+        assert!(nfc.contains(&format!("\nBlock 17: 00 00{}\n", " 44".repeat(16))));
+
+        assert_eq!(dump.to_proxmark_bin().len(), 32);
+        let json = dump.to_proxmark_json();
+        assert_eq!(json["Card"]["IDm"], "0123456789ABCDEF");
+        assert_eq!(json["blocks"]["130"], "44".repeat(16));
+    }
+}
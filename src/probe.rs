@@ -0,0 +1,919 @@
+//! High-level card probing: figure out what a card is, and dump everything we can find.
+//!
+//! This is what `cardinal probe` uses under the hood, but nothing in here prints anything;
+//! you get a [Probe] back and can do whatever you want with it.
+//!
+//! Anything that selects something and then reads it (a directory, an application, EF.DIR
+//! and so on) runs in a [transport::transaction], so if the card is reset or pulled out
+//! halfway through, a transport that can recover starts it over from the SELECT.
+
+pub mod calypso;
+pub mod capabilities;
+pub mod felica;
+pub mod progress;
+pub mod summary;
+pub mod xref;
+
+use crate::pcsc_attrs::{AttrValue, Link};
+use crate::probe::progress::Event;
+use crate::reader_quirks::UidMethod;
+use crate::uid::CardUid;
+use crate::warnings::Warnings;
+use crate::CardTransport;
+use crate::{atr, ats, eid, emv, iso7816, transport, util, HexVec, Result};
+use serde::Serialize;
+use tap::{TapFallible, TapOptional};
+use tracing::{debug, error, trace_span, warn};
+
+/// Everything we learned about a card.
+#[derive(Debug, Serialize)]
+pub struct Probe {
+    /// What the card probably is, in a nutshell; see [summary].
+    pub summary: summary::Summary,
+    /// What the card can do: protocols, extended lengths, logical channels and so on; see
+    /// [capabilities].
+    pub capabilities: capabilities::Capabilities,
+    /// PCSC attributes reported by the reader.
+    pub reader: Vec<ReaderAttribute>,
+    /// The protocol, clock and so on the reader and card settled on, from [Probe::reader];
+    /// see [Link].
+    pub link: Option<Link>,
+    /// What identifies the card, if anything; see [CardUid].
+    pub uid: CardUid,
+    /// Raw ATR, as reported by the reader.
+    pub atr_raw: HexVec,
+    /// Parsed ATR.
+    pub atr: atr::ATR,
+    /// What was wrong with the ATR, if anything; it's parsed leniently, so a weird card
+    /// doesn't stop the probe, but some of it may be missing.
+    pub atr_warnings: Vec<String>,
+    /// What the ATR is known as, from an ATR database; see [atr::db].
+    pub known_as: Vec<String>,
+    /// ATS, for ISO 14443-4 cards, whose ATR is made up by the reader; see [ats].
+    pub ats: Option<ats::ATS>,
+    /// EF.ATR/INFO, raw, for cards whose historical bytes say it's there.
+    pub ef_atr: Option<HexVec>,
+    /// EMV directory and applications, for ISO 14443 cards.
+    pub emv: Option<EmvProbe>,
+    /// National eID applications, for cards that answered to one; see [eid].
+    pub eid: Option<Vec<eid::Application>>,
+    /// Applications listed in EF.DIR, for cards that don't have EMV directories (eIDs, PIV
+    /// and other JavaCard applets, etc).
+    pub ef_dir: Option<Vec<iso7816::ApplicationTemplate>>,
+    /// FeliCa systems, services and blocks, for FeliCa cards.
+    pub felica: Option<felica::FelicaProbe>,
+    /// Transit application files, for Calypso cards.
+    pub calypso: Option<calypso::CalypsoProbe>,
+    /// Identifiers that showed up in more than one place; see [xref].
+    pub xrefs: Vec<xref::CrossRef>,
+    /// Anything nonstandard the parsers ran into along the way, eg. unknown fields.
+    pub warnings: Warnings,
+}
+
+/// What an ATR says on its own, decoded the same way [Probe] does it; for ATRs from logs
+/// and bug reports, when there's no card to hand.
+#[derive(Debug, Serialize)]
+pub struct AtrProbe {
+    /// Raw ATR.
+    pub atr_raw: HexVec,
+    /// Parsed ATR.
+    pub atr: atr::ATR,
+    /// What was wrong with the ATR, if anything; see [Probe::atr_warnings].
+    pub atr_warnings: Vec<String>,
+    /// What the ATR is known as, from an ATR database; see [atr::db].
+    pub known_as: Vec<String>,
+}
+
+impl AtrProbe {
+    /// Decodes an ATR, and looks it up in `atr_db` (or the bundled database).
+    pub fn decode(raw: &[u8], atr_db: Option<&atr::db::Database>) -> Result<Self> {
+        let span = trace_span!("AtrProbe::decode");
+        let _enter = span.enter();
+
+        let (atr, warnings) = atr::parse_lenient(raw)?;
+        let known_as = identify_atr(atr_db, raw, &atr);
+        Ok(Self {
+            atr_raw: raw.into(),
+            atr,
+            atr_warnings: warnings.iter().map(|w| w.to_string()).collect(),
+            known_as,
+        })
+    }
+}
+
+/// Knobs for [Probe::run_with]; the defaults are what [Probe::run] does.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Use this standard instead of the one in the ATR.
+    pub standard: Option<atr::Standard>,
+    /// Scan for FeliCa services on systems that won't list them; see [felica::SCAN_RANGE].
+    pub felica_scan: bool,
+    /// FeliCa blocks to read per command; if not given, as many as the card will take.
+    pub felica_batch: Option<usize>,
+    /// ATR database to identify the card with; if not given, the bundled one is used.
+    pub atr_db: Option<atr::db::Database>,
+    /// Gets told what the probe finds as it goes; see [progress].
+    pub observer: Option<std::sync::Arc<dyn progress::ProbeObserver>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReaderAttribute {
+    pub attribute: String,
+    pub value: HexVec,
+    /// What the value means, if it's a standard attribute; see [AttrValue::decode].
+    pub decoded: Option<AttrValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmvProbe {
+    /// The PSE, if the card has one.
+    pub directory: Option<emv::Directory>,
+    /// The PSE's records.
+    pub records: Vec<EmvRecord>,
+    /// The PPSE, if the card has one.
+    pub proximity_directory: Option<emv::ProximityDirectory>,
+    /// Every application listed in either directory, once.
+    pub applications: Vec<EmvApplication>,
+}
+
+/// Which directory an application was listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmvDirectory {
+    Pse,
+    Ppse,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmvRecord {
+    pub num: u8,
+    pub record: emv::DirectoryRecord,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmvApplication {
+    pub adf_name: HexVec,
+    /// Directories the application was listed in.
+    pub directories: Vec<EmvDirectory>,
+    pub application: emv::Application,
+}
+
+impl Probe {
+    /// Probes the card. If `standard` is given, it's used instead of the one in the ATR.
+    pub fn run(card: &mut impl CardTransport, standard: Option<atr::Standard>) -> Result<Self> {
+        Self::run_with(
+            card,
+            &Options {
+                standard,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Probes the card, with [Options].
+    pub fn run_with(card: &mut impl CardTransport, opts: &Options) -> Result<Self> {
+        let span = trace_span!("probe");
+        let _enter = span.enter();
+
+        let (probe, warnings) = progress::observe(opts.observer.clone(), || {
+            Warnings::collect(|| Self::run_inner(card, opts))
+        });
+        let mut probe = probe?;
+        probe.warnings = warnings;
+        Ok(probe)
+    }
+
+    fn run_inner(card: &mut impl CardTransport, opts: &Options) -> Result<Self> {
+        let mut bufs = card.buffers();
+        let (wbuf, rbuf) = bufs.split();
+
+        let reader = probe_reader(card, rbuf);
+        let link = Link::from_attributes(
+            (reader.iter()).map(|attr| (attr.attribute.as_str(), &attr.value[..])),
+        );
+        let AtrProbe {
+            atr_raw,
+            atr,
+            atr_warnings,
+            known_as,
+        } = probe_atr(card, opts.atr_db.as_ref())?;
+
+        let mut probe = Self {
+            summary: Default::default(),
+            capabilities: Default::default(),
+            reader,
+            link,
+            uid: CardUid::None,
+            atr_raw,
+            atr,
+            atr_warnings,
+            known_as,
+            ats: None,
+            ef_atr: None,
+            emv: None,
+            eid: None,
+            ef_dir: None,
+            felica: None,
+            calypso: None,
+            xrefs: vec![],
+            warnings: Warnings::default(),
+        };
+        let standard = opts
+            .standard
+            .tap_some(|std| debug!(?std, "Ignoring ATR, using forced standard"))
+            .unwrap_or_else(|| probe.standard());
+        probe.uid = probe_uid(card, wbuf, rbuf, standard);
+        if standard == atr::Standard::Iso14443a3 && ats::is_synthesized(&probe.atr) {
+            probe.ats = Some(probe_ats(card, wbuf, rbuf, &probe.atr_raw));
+        }
+        if standard != atr::Standard::FeliCa && capabilities::has_ef_atr(&probe) {
+            debug!("Historical bytes say there's more in EF.ATR/INFO...");
+            probe.ef_atr = transport::transaction(card, |mut card| {
+                iso7816::read_ef_atr(&mut card, wbuf, rbuf)
+            })
+            .tap_err(|err| warn!("couldn't read EF.ATR/INFO: {}", err))
+            .ok()
+            .map(HexVec);
+        }
+        match standard {
+            atr::Standard::FeliCa => {
+                progress::emit(Event::Stage("FeliCa"));
+                if let CardUid::FelicaIdm(idm) = probe.uid {
+                    probe.felica = felica::probe_felica(
+                        card,
+                        wbuf,
+                        rbuf,
+                        idm,
+                        opts.felica_scan,
+                        opts.felica_batch,
+                    )
+                    .tap_err(|err| warn!("couldn't probe FeliCa: {}", err))
+                    .ok();
+                } else {
+                    error!("trying to probe FeliCa card, but we have no IDm!");
+                }
+            }
+            _ => {
+                progress::emit(Event::Stage("EMV"));
+                probe.emv = probe_emv(card, wbuf, rbuf)
+                    .tap_err(|err| warn!("couldn't probe EMV: {}", err))
+                    .ok()
+                    .flatten();
+                if probe.emv.is_none() {
+                    debug!("Not a payment card; trying Calypso...");
+                    progress::emit(Event::Stage("Calypso"));
+                    probe.calypso = transport::transaction(card, |mut card| {
+                        calypso::probe_calypso(&mut card, wbuf, rbuf)
+                    })
+                    .tap_err(|err| warn!("couldn't probe Calypso: {}", err))
+                    .ok()
+                    .flatten();
+                }
+                if probe.emv.is_none() && probe.calypso.is_none() {
+                    debug!("Not a transit card either; trying eIDs...");
+                    progress::emit(Event::Stage("eID"));
+                    probe.eid = transport::transaction(card, |mut card| {
+                        eid::discover(&mut card, wbuf, rbuf)
+                    })
+                    .tap_err(|err| warn!("couldn't look for eIDs: {}", err))
+                    .ok()
+                    .filter(|apps| !apps.is_empty());
+                    debug!("Trying EF.DIR...");
+                    progress::emit(Event::Stage("EF.DIR"));
+                    probe.ef_dir = transport::transaction(card, |mut card| {
+                        iso7816::read_ef_dir(&mut card, wbuf, rbuf)
+                    })
+                    .tap_err(|err| warn!("couldn't read EF.DIR: {}", err))
+                    .ok();
+                }
+            }
+        }
+
+        probe.xrefs = xref::cross_reference(&probe);
+        for xref in probe.xrefs.iter().filter(|x| !x.consistent) {
+            warn!(what = xref.what, sightings = ?xref.sightings, "Cross-reference mismatch!");
+        }
+        probe.summary = summary::Summary::new(&probe);
+        probe.capabilities = capabilities::Capabilities::new(&probe);
+        Ok(probe)
+    }
+
+    /// Returns the card standard, according to the ATR.
+    pub fn standard(&self) -> atr::Standard {
+        get_atr_card_standard(&self.atr)
+    }
+}
+
+/// Looks the ATR up in the database; if it's not in there, but the historical bytes have a
+/// product name in them, looks for that instead.
+fn identify_atr(db: Option<&atr::db::Database>, atr_raw: &[u8], atr: &atr::ATR) -> Vec<String> {
+    let bundled;
+    let db = match db {
+        Some(db) => db,
+        None => {
+            bundled = atr::db::Database::bundled();
+            &bundled
+        }
+    };
+    let known_as = db.identify(atr_raw);
+    if !known_as.is_empty() {
+        return known_as.into_iter().map(|s| s.to_owned()).collect();
+    }
+    let Some(text) = atr.historical_bytes.as_ref().and_then(|hb| hb.text()) else {
+        return vec![];
+    };
+    debug!(
+        text,
+        "ATR isn't in the database, searching for its historical bytes"
+    );
+    (db.search(&text).into_iter())
+        .map(|s| format!("{} (guessed from {:?})", s, text))
+        .collect()
+}
+
+fn probe_reader(card: &mut impl CardTransport, rbuf: &mut [u8]) -> Vec<ReaderAttribute> {
+    let mut attrs = vec![];
+    for attr in [
+        pcsc::Attribute::VendorName,
+        pcsc::Attribute::VendorIfdType,
+        pcsc::Attribute::VendorIfdVersion,
+        pcsc::Attribute::VendorIfdSerialNo,
+        pcsc::Attribute::ChannelId,
+        pcsc::Attribute::AsyncProtocolTypes,
+        pcsc::Attribute::DefaultClk,
+        pcsc::Attribute::MaxClk,
+        pcsc::Attribute::DefaultDataRate,
+        pcsc::Attribute::MaxDataRate,
+        pcsc::Attribute::MaxIfsd,
+        pcsc::Attribute::SyncProtocolTypes,
+        pcsc::Attribute::PowerMgmtSupport,
+        pcsc::Attribute::UserToCardAuthDevice,
+        pcsc::Attribute::UserAuthInputDevice,
+        pcsc::Attribute::Characteristics,
+        pcsc::Attribute::CurrentProtocolType,
+        pcsc::Attribute::CurrentClk,
+        pcsc::Attribute::CurrentF,
+        pcsc::Attribute::CurrentD,
+        pcsc::Attribute::CurrentN,
+        pcsc::Attribute::CurrentW,
+        pcsc::Attribute::CurrentIfsc,
+        pcsc::Attribute::CurrentIfsd,
+        pcsc::Attribute::CurrentBwt,
+        pcsc::Attribute::CurrentCwt,
+        pcsc::Attribute::CurrentEbcEncoding,
+        pcsc::Attribute::ExtendedBwt,
+        pcsc::Attribute::IccPresence,
+        pcsc::Attribute::IccInterfaceStatus,
+        pcsc::Attribute::CurrentIoState,
+        pcsc::Attribute::AtrString,
+        pcsc::Attribute::IccTypePerAtr,
+        pcsc::Attribute::EscReset,
+        pcsc::Attribute::EscCancel,
+        pcsc::Attribute::EscAuthrequest,
+        pcsc::Attribute::Maxinput,
+        pcsc::Attribute::DeviceUnit,
+        pcsc::Attribute::DeviceInUse,
+        pcsc::Attribute::DeviceFriendlyName,
+        pcsc::Attribute::DeviceSystemName,
+        pcsc::Attribute::SupressT1IfsRequest,
+    ] {
+        if let Ok(v) = card
+            .get_attribute(attr, rbuf)
+            .tap_err(|err| debug!(?attr, ?err, "Couldn't query reader attribute"))
+        {
+            let attribute = format!("{:?}", attr);
+            attrs.push(ReaderAttribute {
+                decoded: AttrValue::decode(&attribute, v),
+                attribute,
+                value: v.into(),
+            });
+        }
+    }
+    attrs
+}
+
+pub fn pcsc_get_data<'r>(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &'r mut [u8],
+    p1: u8,
+) -> Result<&'r [u8]> {
+    // PCSC pseudo-APDU, doesn't actually talk to the card.
+    util::call_le(card, wbuf, rbuf, 0xFF, 0xCA, p1, 0x00, 0)
+}
+
+/// Works out what identifies the card: the card ID, if the reader knows one (it only will
+/// for contactless cards), otherwise the ICCID, if the card has one.
+pub fn probe_uid(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    standard: atr::Standard,
+) -> CardUid {
+    let span = trace_span!("probe_uid");
+    let _enter = span.enter();
+
+    let uid = card.reader_quirks().map(|q| q.uid).unwrap_or_default();
+    match uid {
+        UidMethod::GetData => match pcsc_get_data(card, wbuf, rbuf, 0x00) {
+            Ok(cid) => return CardUid::from_cid(cid, standard),
+            Err(err) => debug!("No card ID from the reader: {}", err),
+        },
+        UidMethod::None => debug!("Reader can't tell us the card ID"),
+    }
+    if standard == atr::Standard::FeliCa {
+        return CardUid::None;
+    }
+    debug!("Trying EF.ICCID...");
+    transport::transaction(card, |mut card| iso7816::read_iccid(&mut card, wbuf, rbuf))
+        .tap_err(|err| debug!("No ICCID: {}", err))
+        .map_or(CardUid::None, |iccid| CardUid::from_iccid(&iccid))
+}
+
+/// Returns the card standard, according to an ATR.
+pub fn get_atr_card_standard(atr: &atr::ATR) -> atr::Standard {
+    // Am I doing Rust right?
+    if let Some(atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV {
+        initial_access: Some(atr::InitialAccess { standard, .. }),
+        ..
+    })) = atr.historical_bytes
+    {
+        standard
+    } else {
+        atr::Standard::Iso14443a3
+    }
+}
+
+/// Returns the card's name (eg. MIFARE Classic 1K), if the ATR is one a PC/SC reader made
+/// up for a storage card.
+pub fn get_atr_card_name(atr: &atr::ATR) -> Option<atr::CardName> {
+    match atr.historical_bytes {
+        Some(atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV {
+            initial_access: Some(atr::InitialAccess { card_name, .. }),
+            ..
+        })) => Some(card_name),
+        _ => None,
+    }
+}
+
+/// Probes the ISO 7816 ATR (Answer-to-Reset), and returns it raw, parsed, and whatever
+/// was wrong with it.
+fn probe_atr(
+    card: &mut impl CardTransport,
+    atr_db: Option<&atr::db::Database>,
+) -> Result<AtrProbe> {
+    let span = trace_span!("probe_atr");
+    let _enter = span.enter();
+
+    let raw = card.atr()?;
+    debug!(atr = format!("{:02X?}", raw), "Raw ATR");
+
+    AtrProbe::decode(&raw, atr_db).tap_err(|err| error!(?err, atr = ?raw, "Couldn't parse ATR"))
+}
+
+/// Asks the reader for the ATS of an ISO 14443-4 card. Some readers give out the whole
+/// thing, some only the historical bytes; if it won't say, they're in the ATR anyway.
+fn probe_ats(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    atr_raw: &[u8],
+) -> ats::ATS {
+    let span = trace_span!("probe_ats");
+    let _enter = span.enter();
+
+    // Historical bytes are at most 15 bytes, and start with a category indicator (0x00,
+    // 0x10, 0x80...), not their own length; an ATS starts with TL.
+    match pcsc_get_data(card, wbuf, rbuf, 0x01) {
+        Ok(rsp) if rsp.first() == Some(&(rsp.len() as u8)) => match ats::ATS::parse(rsp) {
+            Ok(ats) => return ats,
+            Err(err) => warn!(ats = hex::encode_upper(rsp), "Couldn't parse ATS: {}", err),
+        },
+        Ok(rsp) => return ats::ATS::from_historical_bytes(rsp),
+        Err(err) => debug!("No ATS from the reader: {}", err),
+    }
+    // 3B 8n 80 01 [historical bytes] TCK.
+    let hb = atr_raw
+        .get(4..atr_raw.len().saturating_sub(1))
+        .unwrap_or_default();
+    ats::ATS::from_historical_bytes(hb)
+}
+
+/// Probes the card to figure out if it's an EMV payment card. Returns None if it has
+/// neither directory, which means it's probably not one.
+fn probe_emv(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Option<EmvProbe>> {
+    let span = trace_span!("EMV");
+    let _enter = span.enter();
+
+    // TODO: Some cards don't have directories; we should fall back to AID spamming.
+    debug!("Trying to select EMV directory...");
+    let (directory, records) = transport::transaction(card, |mut card| {
+        let directory = found_directory("PSE", emv::Directory::lookup(&mut card, wbuf, rbuf)?);
+        let records = match directory.as_ref() {
+            Some(dir) => match probe_emv_directory(&mut card, wbuf, rbuf, dir) {
+                Err(err) if err.is_card_lost() => return Err(err),
+                res => res
+                    .tap_err(|err| warn!("Couldn't read PSE records: {}", err))
+                    .unwrap_or_default(),
+            },
+            None => vec![],
+        };
+        Ok((directory, records))
+    })?;
+    debug!("Trying to select EMV proximity directory...");
+    let proximity_directory = transport::transaction(card, |mut card| {
+        emv::ProximityDirectory::lookup(&mut card, wbuf, rbuf)
+    })?;
+    let proximity_directory = found_directory("PPSE", proximity_directory);
+    if directory.is_none() && proximity_directory.is_none() {
+        return Ok(None);
+    }
+
+    // Dual-interface cards tend to list the same applications in both directories, and
+    // there's no point probing them twice.
+    let mut listed: Vec<(&[u8], Vec<EmvDirectory>)> = vec![];
+    let pse_apps = (records.iter())
+        .flat_map(|r| r.record.entry.applications.iter())
+        .map(|app| (app, EmvDirectory::Pse));
+    let ppse_apps = (proximity_directory.iter())
+        .flat_map(|dir| dir.applications.iter())
+        .map(|app| (app, EmvDirectory::Ppse));
+    for (app, dir) in pse_apps.chain(ppse_apps) {
+        match listed
+            .iter_mut()
+            .find(|(name, _)| *name == &app.adf_name[..])
+        {
+            Some((_, dirs)) if dirs.contains(&dir) => {}
+            Some((_, dirs)) => dirs.push(dir),
+            None => listed.push((&app.adf_name, vec![dir])),
+        }
+    }
+
+    let mut applications = vec![];
+    let total = listed.len();
+    for (index, (adf_name, directories)) in listed.into_iter().enumerate() {
+        progress::emit(Event::ApplicationDiscovered {
+            aid: adf_name,
+            index,
+            total,
+        });
+        debug!(
+            adf_name = hex::encode_upper(adf_name),
+            ?directories,
+            "Probing application..."
+        );
+        match transport::transaction(card, |mut card| {
+            probe_emv_application(&mut card, wbuf, rbuf, adf_name)
+        }) {
+            Ok(application) => applications.push(EmvApplication {
+                adf_name: adf_name.into(),
+                directories,
+                application,
+            }),
+            Err(err) => warn!(
+                adf_name = hex::encode_upper(adf_name),
+                "Couldn't select application: {}", err
+            ),
+        }
+    }
+    Ok(Some(EmvProbe {
+        directory,
+        records,
+        proximity_directory,
+        applications,
+    }))
+}
+
+/// Logs why there's no directory, if there isn't one. A missing one is normal; a refused
+/// one probably isn't.
+fn found_directory<T>(what: &str, lookup: emv::DirectoryLookup<T>) -> Option<T> {
+    match lookup {
+        emv::DirectoryLookup::Present(dir) => Some(dir),
+        emv::DirectoryLookup::Absent => {
+            debug!("No {}", what);
+            None
+        }
+        emv::DirectoryLookup::Refused(sw) => {
+            warn!(%sw, "Card refused to select the {}", what);
+            None
+        }
+    }
+}
+
+/// Reads the EMV directory's records.
+fn probe_emv_directory(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    dir: &emv::Directory,
+) -> Result<Vec<EmvRecord>> {
+    let span = trace_span!("directory");
+    let _enter = span.enter();
+
+    let mut records = vec![];
+    for (i, res) in dir.records(card, wbuf, rbuf).enumerate() {
+        match res {
+            Ok((num, record)) => {
+                debug!(sfi = dir.ef_sfi, num, "Got a record!");
+                progress::emit(Event::RecordRead {
+                    sfi: dir.ef_sfi,
+                    num,
+                });
+                records.push(EmvRecord { num, record });
+            }
+            Err(err) if err.sw().is_some() => warn!(
+                sfi = dir.ef_sfi,
+                num = i + 1,
+                "Couldn't query record: {}",
+                err
+            ),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(records)
+}
+
+fn probe_emv_application(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+    adf_name: &[u8],
+) -> Result<emv::Application> {
+    let span = trace_span!("application");
+    let _enter = span.enter();
+
+    debug!(
+        adf_name = hex::encode_upper(adf_name),
+        "Selecting application..."
+    );
+    emv::Application::select(card, wbuf, rbuf, adf_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulate::{EmulatedCard, Profile};
+
+    /// A revision 2 Navigo card, with just the Environment and an empty Contract.
+    const NAVIGO: &str = r#"
+        atr = "3B 88 80 01 00 00 00 00 00 00 00 00 09"
+
+        [[application]]
+        aid = "315449432E494341"
+
+        [[application.file]]
+        sfi = 7
+        records = ["04 09 28 48 08 00"]
+
+        [[application.file]]
+        sfi = 9
+        records = ["00 00 00 00"]
+    "#;
+
+    /// An emulated card that something happens to right after an application is first
+    /// selected: it's reset by somebody else (and forgets what was selected), or it's
+    /// pulled out and put back. Until it's reconnected to, everything fails with `lost`.
+    struct Interrupted {
+        card: EmulatedCard,
+        lost: Option<pcsc::Error>,
+        failing: bool,
+        reconnects: usize,
+    }
+
+    impl Interrupted {
+        fn new(profile: &str, lost: pcsc::Error) -> Self {
+            Self {
+                card: EmulatedCard::new(Profile::from_toml(profile).unwrap()),
+                lost: Some(lost),
+                failing: false,
+                reconnects: 0,
+            }
+        }
+    }
+
+    impl CardTransport for Interrupted {
+        fn transmit<'r>(&mut self, capdu: &[u8], rbuf: &'r mut [u8]) -> Result<&'r [u8]> {
+            if self.failing {
+                return Err(self.lost.unwrap().into());
+            }
+            let rsp = self.card.transmit(capdu, rbuf)?;
+            if self.lost.is_some()
+                && capdu.starts_with(&[0x00, 0xA4, 0x04])
+                && rsp.ends_with(&[0x90, 0x00])
+            {
+                self.card.reset(transport::Reset::Warm)?;
+                self.failing = true;
+            }
+            Ok(rsp)
+        }
+
+        fn atr(&mut self) -> Result<Vec<u8>> {
+            self.card.atr()
+        }
+
+        fn is_present(&mut self) -> Result<bool> {
+            Ok(!(self.failing && self.lost == Some(pcsc::Error::RemovedCard)))
+        }
+
+        fn reconnect(&mut self) -> Result<()> {
+            self.reconnects += 1;
+            if self.failing {
+                (self.lost, self.failing) = (None, false);
+            }
+            Ok(())
+        }
+    }
+
+    /// What a probe of [NAVIGO] should find.
+    fn assert_navigo(probe: &Probe) {
+        let calypso = probe.calypso.as_ref().unwrap();
+        assert_eq!(calypso.revision, crate::calypso::Revision::Rev2);
+        assert_eq!(
+            (calypso.files.iter())
+                .map(|f| (f.file, f.records.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                (crate::calypso::File::Environment, 1),
+                (crate::calypso::File::Contracts, 1),
+            ]
+        );
+        let env = calypso.files[0].records[0].fields.as_ref().unwrap();
+        assert_eq!(
+            env.get("EnvNetworkId"),
+            Some(&crate::calypso::intercode::Value::Int(0x250901))
+        );
+        assert!(calypso.files[1].records[0].fields.is_none());
+    }
+
+    #[test]
+    fn test_get_atr_card_standard_felica() {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let atr = atr::parse(&[
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ])
+        .expect("couldn't parse ATR");
+        assert_eq!(get_atr_card_standard(&atr), atr::Standard::FeliCa);
+    }
+
+    #[test]
+    fn test_get_atr_card_standard_fallback() {
+        // ATR from a 2018 Curve (UK, Gemalto) card; no initial access data.
+        let atr = atr::parse(&[
+            0x3B, 0x8E, 0x80, 0x01, 0x80, 0x31, 0x80, 0x66, 0xB1, 0x84, 0x0C, 0x01, 0x6E, 0x01,
+            0x83, 0x00, 0x90, 0x00, 0x1C,
+        ])
+        .expect("couldn't parse ATR");
+        assert_eq!(get_atr_card_standard(&atr), atr::Standard::Iso14443a3);
+    }
+
+    #[test]
+    fn test_identify_atr_from_historical_bytes() {
+        // Proprietary historical bytes that spell out "JCOP41V221", and an ATR nobody's
+        // put in the database.
+        let mut raw = vec![0x3B, 0x8A, 0x80, 0x01];
+        raw.extend(b"JCOP41V221");
+        raw.push(raw[1..].iter().fold(0, |a, b| a ^ b));
+        let atr = atr::parse(&raw).expect("couldn't parse ATR");
+        let db = atr::db::Database::parse("3B 00\n\tNXP JCOP41V221\n");
+        assert_eq!(
+            identify_atr(Some(&db), &raw, &atr),
+            vec![r#"NXP JCOP41V221 (guessed from "JCOP41V221")"#.to_owned()]
+        );
+
+        // An exact match wins.
+        let db = atr::db::Database::parse(&format!("{}\n\tThe real thing\n", hex::encode(&raw)));
+        assert_eq!(
+            identify_atr(Some(&db), &raw, &atr),
+            vec!["The real thing".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_atr_probe_decode() {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let raw = [
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        let db = atr::db::Database::parse(&format!("{}\n\tPASMO\n", hex::encode(raw)));
+        let decoded = AtrProbe::decode(&raw, Some(&db)).unwrap();
+        assert_eq!(get_atr_card_standard(&decoded.atr), atr::Standard::FeliCa);
+        assert_eq!(decoded.known_as, vec!["PASMO".to_owned()]);
+        assert!(decoded.atr_warnings.is_empty());
+        assert!(AtrProbe::decode(&[0x3B], None).is_err());
+    }
+
+    #[test]
+    fn test_probe_ef_dir() {
+        // A PIV card: no EMV directories, just EF.DIR.
+        let profile = crate::emulate::Profile::from_toml(
+            r#"
+            atr = "3B 8E 80 01 80 31 80 66 B1 84 0C 01 6E 01 83 00 90 00 1C"
+
+            [[file]]
+            fid = "3F00"
+
+            [[file]]
+            fid = "2F00"
+            records = ["61 12 4F 09 A00000030800001000 50 02 5049 99 01 00"]
+            "#,
+        )
+        .unwrap();
+        let mut card = crate::emulate::EmulatedCard::new(profile);
+        let probe = Probe::run(&mut card, None).unwrap();
+        assert!(probe.emv.is_none());
+        let ef_dir = probe.ef_dir.unwrap();
+        assert_eq!(
+            ef_dir
+                .iter()
+                .map(|a| a.label.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("PI")]
+        );
+        assert_eq!(
+            probe
+                .warnings
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>(),
+            vec!["ApplicationTemplate: unknown field 99 present: 00"]
+        );
+    }
+
+    #[test]
+    fn test_probe_progress() {
+        let profile = crate::emulate::Profile::from_toml(
+            r#"
+            atr = "3B 8E 80 01 80 31 80 66 B1 84 0C 01 6E 01 83 00 90 00 1C"
+
+            [[file]]
+            fid = "2F00"
+            records = ["61 12 4F 09 A00000030800001000 50 02 5049 99 01 00"]
+            "#,
+        )
+        .unwrap();
+        let stages = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let observer = {
+            let stages = stages.clone();
+            move |event: &progress::Event| {
+                if let progress::Event::Stage(stage) = event {
+                    stages.lock().unwrap().push(*stage);
+                }
+            }
+        };
+        let opts = Options {
+            observer: Some(std::sync::Arc::new(observer)),
+            ..Default::default()
+        };
+        let mut card = crate::emulate::EmulatedCard::new(profile);
+        Probe::run_with(&mut card, &opts).unwrap();
+        assert_eq!(
+            *stages.lock().unwrap(),
+            vec!["EMV", "Calypso", "eID", "EF.DIR"]
+        );
+    }
+
+    #[test]
+    fn test_probe_calypso() {
+        let mut card = EmulatedCard::new(Profile::from_toml(NAVIGO).unwrap());
+        let probe = Probe::run(&mut card, None).unwrap();
+        assert!(probe.ef_dir.is_none());
+        assert_navigo(&probe);
+        assert_eq!(probe.summary.technologies, vec!["Calypso"]);
+    }
+
+    #[test]
+    fn test_probe_reset_after_select() {
+        // Resending just the READ RECORD would get the wrong file (or none at all), now that
+        // the application isn't selected any more; the whole Calypso section starts over.
+        let card = Interrupted::new(NAVIGO, pcsc::Error::ResetCard);
+        let mut card = crate::transports::session::Session::new(card)
+            .retry_on_reset(true)
+            .cache_selects(true);
+        let probe = Probe::run(&mut card, None).unwrap();
+        assert_navigo(&probe);
+        assert_eq!(card.inner.reconnects, 1);
+    }
+
+    #[test]
+    fn test_probe_removed_after_select() {
+        // Same again, but the card's pulled out and put back, like `cardinal probe` does it.
+        let card = Interrupted::new(NAVIGO, pcsc::Error::RemovedCard);
+        let card = crate::transports::session::Session::new(card)
+            .retry_on_reset(true)
+            .cache_selects(true);
+        let waits = std::cell::Cell::new(0);
+        let mut card = crate::transports::reader::Reattach::new(card, || {
+            waits.set(waits.get() + 1);
+            Ok(())
+        });
+        let probe = Probe::run(&mut card, None).unwrap();
+        assert_navigo(&probe);
+        assert_eq!((waits.get(), card.inner.inner.reconnects), (1, 1));
+    }
+}
@@ -0,0 +1,72 @@
+//! Calypso-specific probing: the transit application's files, and what Intercode says is in
+//! them.
+//!
+//! Calypso cards are plain ISO 7816 cards as far as the ATR goes, so the only way to tell
+//! is to try selecting the transit application; cards that don't have one say so, and
+//! that's the end of it.
+
+use crate::calypso::{self, intercode, Revision};
+use crate::{CardTransport, HexVec, Result};
+use serde::Serialize;
+use tracing::{debug, trace_span};
+
+#[derive(Debug, Serialize)]
+pub struct CalypsoProbe {
+    /// Which CLA the card wanted.
+    pub revision: Revision,
+    /// Files that could be read, in [calypso::File::ALL] order.
+    pub files: Vec<CalypsoFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalypsoFile {
+    pub file: calypso::File,
+    pub records: Vec<CalypsoRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalypsoRecord {
+    pub num: u8,
+    pub data: HexVec,
+    /// Intercode fields, for files it describes (and records that aren't empty).
+    pub fields: Option<intercode::Fields>,
+}
+
+impl From<calypso::FileRecords> for CalypsoFile {
+    fn from(fr: calypso::FileRecords) -> Self {
+        let records = (fr.records.into_iter().enumerate())
+            .map(|(i, data)| CalypsoRecord {
+                num: i as u8 + 1,
+                fields: intercode::parse(fr.file, &data),
+                data,
+            })
+            .collect();
+        Self {
+            file: fr.file,
+            records,
+        }
+    }
+}
+
+/// Reads everything off a Calypso card; None if it doesn't have a transit application.
+pub fn probe_calypso(
+    card: &mut impl CardTransport,
+    wbuf: &mut [u8],
+    rbuf: &mut [u8],
+) -> Result<Option<CalypsoProbe>> {
+    let span = trace_span!("calypso");
+    let _enter = span.enter();
+
+    match calypso::read_all(card, wbuf, rbuf) {
+        Ok((revision, files)) => Ok(Some(CalypsoProbe {
+            revision,
+            files: files.into_iter().map(Into::into).collect(),
+        })),
+        // File (application) not found, or the card doesn't do SELECT by name at all.
+        Err(err) if matches!(err.sw(), Some((0x6A, 0x82) | (0x6D, 0x00) | (0x6E, 0x00))) => {
+            debug!("No transit application, not a Calypso card");
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
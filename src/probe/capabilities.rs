@@ -0,0 +1,289 @@
+//! What a card can do, pieced together from everything the probe found.
+//!
+//! Cards say surprisingly little about this up front. The ATR lists the protocols it talks,
+//! and the historical bytes (or EF.ATR/INFO, if they ran out of room) may have a "card
+//! capabilities" object saying whether it does extended lengths, command chaining and
+//! logical channels; everything else has to be inferred from what answered when we asked.
+//! Anything nobody said is None, rather than a guess.
+
+use crate::eid::Scheme;
+use crate::probe::Probe;
+use crate::{atr, ats, ber};
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// Transmission protocols, eg. "T=0" or "T=1"; "T=CL" for contactless cards, whose ATR
+    /// is made up by the reader.
+    pub protocols: Vec<String>,
+    /// Whether the card takes extended Lc and Le fields.
+    pub extended_length: Option<bool>,
+    /// Longest command data the card takes, in bytes, if EF.ATR/INFO says.
+    pub max_command_len: Option<u32>,
+    /// Longest response data the card sends, in bytes, if EF.ATR/INFO says.
+    pub max_response_len: Option<u32>,
+    /// Whether the card does command chaining.
+    pub command_chaining: Option<bool>,
+    /// How many logical channels the card has, counting the basic one.
+    pub logical_channels: Option<u8>,
+    /// Secure messaging (or similar) the card wants before it'll say anything interesting,
+    /// eg. "PACE".
+    pub secure_messaging: Vec<String>,
+    /// How many applications (or FeliCa systems) answered.
+    pub applications: usize,
+    /// Standards the card implements, going by what answered.
+    pub standards: Vec<String>,
+}
+
+impl Capabilities {
+    /// Works out what a probed card can do.
+    pub fn new(probe: &Probe) -> Self {
+        let mut caps = Self {
+            protocols: protocols(probe),
+            secure_messaging: secure_messaging(probe),
+            applications: applications(probe),
+            standards: standards(probe),
+            ..Default::default()
+        };
+        // EF.ATR/INFO is there for what doesn't fit in the historical bytes, so it wins.
+        let ef_atr = probe
+            .ef_atr
+            .as_deref()
+            .map(parse_ef_atr)
+            .unwrap_or_default();
+        let card_caps = (ef_atr.card_capabilities.as_ref())
+            .or_else(|| historical_bytes(probe).find_map(|tlv| tlv.card_capabilities.as_ref()));
+        if let Some(cc) = card_caps {
+            caps.extended_length = Some(cc.extended_length);
+            caps.command_chaining = Some(cc.command_chaining);
+            caps.logical_channels = Some(cc.logical_channels);
+        }
+        if let Some((cmd, rsp)) = ef_atr.extended_length_info {
+            caps.extended_length = Some(true);
+            (caps.max_command_len, caps.max_response_len) = (Some(cmd), Some(rsp));
+        }
+        caps
+    }
+}
+
+/// Every set of TLV historical bytes the card gave us: the ATR's, and the ATS' if they're
+/// not the same ones.
+pub fn historical_bytes(probe: &Probe) -> impl Iterator<Item = &atr::HistoricalBytesTLV> {
+    let ats = (probe.ats.as_ref()).and_then(|ats| ats.historical_bytes.as_ref());
+    [probe.atr.historical_bytes.as_ref(), ats]
+        .into_iter()
+        .flat_map(|hb| match hb {
+            Some(atr::HistoricalBytes::TLV(tlv)) => Some(tlv),
+            _ => None,
+        })
+}
+
+/// Does the card say it has an EF.ATR/INFO worth reading?
+pub fn has_ef_atr(probe: &Probe) -> bool {
+    historical_bytes(probe).any(|tlv| {
+        tlv.service_data.is_some_and(|v| v & 0b0001_0000 != 0)
+            || (tlv.card_capabilities.as_ref()).is_some_and(|cc| cc.extended_length_info)
+    })
+}
+
+#[derive(Debug, Default)]
+struct EfAtr {
+    card_capabilities: Option<atr::CardCapabilities>,
+    /// Max command and response lengths.
+    extended_length_info: Option<(u32, u32)>,
+}
+
+/// EF.ATR/INFO can be BER-TLV or COMPACT-TLV, and doesn't say which; the only thing we
+/// care about that's BER-only is 7F66, so look for that first.
+fn parse_ef_atr(data: &[u8]) -> EfAtr {
+    let mut ef_atr = EfAtr::default();
+    for (_, tag, value) in ber::iter_deep(data).flatten() {
+        if tag == [0x7F, 0x66] {
+            ef_atr.extended_length_info = parse_extended_length_info(value);
+            return ef_atr;
+        }
+    }
+    match atr::parse_ef_atr(data) {
+        Ok(tlv) => ef_atr.card_capabilities = tlv.card_capabilities,
+        Err(err) => debug!(%err, "Couldn't parse EF.ATR/INFO"),
+    }
+    ef_atr
+}
+
+/// 7F66: two INTEGERs (02), max command length and max response length.
+fn parse_extended_length_info(data: &[u8]) -> Option<(u32, u32)> {
+    let mut ints = ber::iter(data).flatten().filter(|(tag, _)| *tag == [0x02]);
+    let mut next = || {
+        let (_, value) = ints.next()?;
+        (value.len() <= 4).then(|| value.iter().fold(0, |n, &b| (n << 8) | u32::from(b)))
+    };
+    Some((next()?, next()?))
+}
+
+fn protocols(probe: &Probe) -> Vec<String> {
+    if ats::is_synthesized(&probe.atr) {
+        return vec!["T=CL".to_owned()];
+    }
+    let mut protocols = vec![];
+    for td in [probe.atr.tx1.td, probe.atr.tx2.td, probe.atr.tx3.td]
+        .into_iter()
+        .flatten()
+    {
+        let protocol = format!("T={}", u8::from(td.protocol));
+        if !protocols.contains(&protocol) {
+            protocols.push(protocol);
+        }
+    }
+    // No TD1 means T=0, and nothing else.
+    if protocols.is_empty() {
+        protocols.push("T=0".to_owned());
+    }
+    protocols
+}
+
+fn secure_messaging(probe: &Probe) -> Vec<String> {
+    let mut sm = vec![];
+    for app in probe.eid.iter().flatten() {
+        let what = match app.scheme {
+            Scheme::GermanyNpa => "PACE",
+            Scheme::DrivingLicence => "BAP",
+            _ if app.fields.iter().any(|f| f.name == "EF.CardAccess") => "PACE",
+            _ => continue,
+        };
+        if !sm.iter().any(|s| s == what) {
+            sm.push(what.to_owned());
+        }
+    }
+    if probe.calypso.is_some() {
+        sm.push("Calypso secure session".to_owned());
+    }
+    sm
+}
+
+fn applications(probe: &Probe) -> usize {
+    (probe.emv.as_ref()).map_or(0, |emv| emv.applications.len())
+        + probe.eid.as_ref().map_or(0, |apps| apps.len())
+        + probe.ef_dir.as_ref().map_or(0, |apps| apps.len())
+        + probe.felica.as_ref().map_or(0, |f| f.systems.len())
+        + usize::from(probe.calypso.is_some())
+}
+
+fn standards(probe: &Probe) -> Vec<String> {
+    let mut stds = vec![];
+    if let Some(atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV {
+        initial_access: Some(atr::InitialAccess { standard, .. }),
+        ..
+    })) = &probe.atr.historical_bytes
+    {
+        stds.push(standard.to_string());
+    }
+    if probe.ats.is_some() {
+        stds.push("ISO 14443-4".to_owned());
+    }
+    let apdus = probe.emv.is_some()
+        || probe.calypso.is_some()
+        || probe.eid.is_some()
+        || probe.ef_dir.is_some();
+    if apdus {
+        stds.push("ISO 7816-4".to_owned());
+    }
+    if probe.emv.is_some() {
+        stds.push("EMV".to_owned());
+    }
+    if probe.calypso.is_some() {
+        stds.push("Calypso".to_owned());
+    }
+    for app in probe.eid.iter().flatten() {
+        let std = match app.scheme {
+            Scheme::Estonia | Scheme::Belgium => "PKCS #15",
+            Scheme::GermanyNpa => "BSI TR-03110",
+            Scheme::GermanyEgk => "gematik eGK",
+            Scheme::DrivingLicence => "ISO 18013-2",
+        };
+        if !stds.iter().any(|s| s == std) {
+            stds.push(std.to_owned());
+        }
+    }
+    stds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::summary::Summary;
+    use crate::uid::CardUid;
+
+    fn probe(atr_raw: Vec<u8>) -> Probe {
+        Probe {
+            summary: Summary::default(),
+            capabilities: Capabilities::default(),
+            reader: vec![],
+            link: None,
+            uid: CardUid::None,
+            atr: atr::parse(&atr_raw).unwrap(),
+            atr_raw: atr_raw.into(),
+            atr_warnings: vec![],
+            known_as: vec![],
+            ats: None,
+            ef_atr: None,
+            emv: None,
+            eid: None,
+            ef_dir: None,
+            felica: None,
+            calypso: None,
+            xrefs: vec![],
+            warnings: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_contact() {
+        // T=1, with card capabilities in the historical bytes: chaining, extended lengths,
+        // 4 logical channels, and more in EF.ATR/INFO.
+        let mut probe = probe(vec![
+            0x3B, 0xD8, 0x18, 0xFF, 0x81, 0x31, 0xFE, 0x45, 0x80, 0x73, 0xC0, 0x21, 0xFB, 0x82,
+            0x90, 0x00, 0xCF,
+        ]);
+        assert!(has_ef_atr(&probe));
+        let caps = Capabilities::new(&probe);
+        assert_eq!(caps.protocols, vec!["T=1"]);
+        assert_eq!(caps.extended_length, Some(true));
+        assert_eq!(caps.command_chaining, Some(true));
+        assert_eq!(caps.logical_channels, Some(4));
+        assert_eq!(caps.max_command_len, None);
+
+        probe.ef_atr = Some(
+            vec![
+                0x7F, 0x66, 0x08, 0x02, 0x02, 0x04, 0x00, 0x02, 0x02, 0x10, 0x00,
+            ]
+            .into(),
+        );
+        let caps = Capabilities::new(&probe);
+        assert_eq!(caps.max_command_len, Some(0x400));
+        assert_eq!(caps.max_response_len, Some(0x1000));
+        assert_eq!(caps.logical_channels, Some(4));
+    }
+
+    #[test]
+    fn test_capabilities_contactless() {
+        // 2019 PASMO (FeliCa) card; says nothing about itself.
+        let probe = probe(vec![
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ]);
+        assert!(!has_ef_atr(&probe));
+        let caps = Capabilities::new(&probe);
+        assert_eq!(caps.protocols, vec!["T=CL"]);
+        assert_eq!(caps.standards, vec!["FeliCa"]);
+        assert_eq!(caps.extended_length, None);
+        assert_eq!(caps.applications, 0);
+    }
+
+    #[test]
+    fn test_parse_ef_atr_compact() {
+        let ef_atr = parse_ef_atr(&[0x73, 0x00, 0x00, 0x40]);
+        assert!(ef_atr.card_capabilities.unwrap().extended_length);
+        assert_eq!(ef_atr.extended_length_info, None);
+    }
+}
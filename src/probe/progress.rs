@@ -0,0 +1,115 @@
+//! Progress reporting, for probes that take a while.
+//!
+//! Dumping a big FeliCa card (or one that has to be scanned) can take tens of seconds, and
+//! nothing comes back until it's done. Put a [ProbeObserver] in [super::Options], and it'll
+//! hear about everything the probe finds as it finds it.
+//!
+//! Like [crate::warnings], the observer lives in a thread-local while the probe runs, so
+//! the probing functions don't all need an extra argument to pass it along.
+
+use crate::felica::SystemCode;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+thread_local! {
+    static OBSERVERS: RefCell<Vec<Arc<dyn ProbeObserver>>> = const { RefCell::new(vec![]) };
+}
+
+/// Something that happened during a probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// Started on a part of the probe, eg. "EMV" or "FeliCa".
+    Stage(&'static str),
+    /// Read a record from an EMV directory.
+    RecordRead { sfi: u8, num: u8 },
+    /// About to probe an EMV application; `index` is from 0.
+    ApplicationDiscovered {
+        aid: &'a [u8],
+        index: usize,
+        total: usize,
+    },
+    /// About to probe a FeliCa System; `index` is from 0.
+    SystemDiscovered {
+        code: SystemCode,
+        index: usize,
+        total: usize,
+    },
+    /// Found a FeliCa Service, listed or scanned.
+    ServiceDiscovered { code: u16 },
+    /// Read a block from a FeliCa Service.
+    BlockRead { service: u16, num: u16 },
+}
+
+/// Gets told about [Event]s as a probe goes. Closures taking an `&Event` are observers.
+/// They have to be thread-safe, since `cardinal probe --all-readers` shares one between
+/// probes.
+pub trait ProbeObserver: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> ProbeObserver for F {
+    fn event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+impl std::fmt::Debug for dyn ProbeObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ProbeObserver")
+    }
+}
+
+/// Tells whoever's observing (if anyone) about an event.
+pub fn emit(event: Event) {
+    // Clone it out first, in case the observer starts a probe of its own.
+    let Some(observer) = OBSERVERS.with_borrow(|stack| stack.last().cloned()) else {
+        return;
+    };
+    observer.event(&event);
+}
+
+/// Runs `f`, with `observer` hearing about everything emitted on this thread while it does.
+pub fn observe<T>(observer: Option<Arc<dyn ProbeObserver>>, f: impl FnOnce() -> T) -> T {
+    let Some(observer) = observer else {
+        return f();
+    };
+    // Pops the observer even if `f` panics.
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            OBSERVERS.with_borrow_mut(|stack| stack.pop());
+        }
+    }
+
+    OBSERVERS.with_borrow_mut(|stack| stack.push(observer));
+    let _guard = Guard;
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_observe() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let observer = {
+            let seen = seen.clone();
+            move |event: &Event| seen.lock().unwrap().push(format!("{:?}", event))
+        };
+        emit(Event::Stage("before"));
+        observe(Some(Arc::new(observer)), || {
+            emit(Event::Stage("EMV"));
+            observe(None, || emit(Event::RecordRead { sfi: 1, num: 2 }));
+        });
+        emit(Event::Stage("after"));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                "Stage(\"EMV\")".to_owned(),
+                "RecordRead { sfi: 1, num: 2 }".to_owned()
+            ]
+        );
+    }
+}
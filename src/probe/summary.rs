@@ -0,0 +1,339 @@
+//! A summary of a probe, for people who don't want to read the whole thing.
+//!
+//! Everything in here is a guess, pieced together from whatever the rest of the probe
+//! found: the ATR says what the card talks, its applications (or FeliCa systems) say what
+//! it's for, and odds and ends like the ICCID or an EMV Form Factor Indicator fill in the
+//! rest. It's meant to answer "what is this?", not to be relied on.
+
+use crate::atr;
+use crate::emv::scheme::{Decoded9F6E, FormFactor, Scheme};
+use crate::felica::SystemCode;
+use crate::probe::Probe;
+use crate::uid::CardUid;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// What the card talks, eg. "ISO 14443", "EMV" or "FeliCa".
+    pub technologies: Vec<String>,
+    /// Best guess at what the card is.
+    pub product: Option<String>,
+    /// Where it was issued, if anything on it says.
+    pub country: Option<String>,
+    /// Anything else worth knowing about it.
+    pub capabilities: Vec<String>,
+    /// All of the above, as a paragraph.
+    pub text: String,
+}
+
+impl Summary {
+    /// Summarises a probe.
+    pub fn new(probe: &Probe) -> Self {
+        let mut summary = Self {
+            technologies: technologies(probe),
+            product: product(probe),
+            country: country(probe),
+            capabilities: capabilities(probe),
+            text: String::new(),
+        };
+        summary.text = summary.to_text();
+        summary
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = match self.technologies.as_slice() {
+            [] => "A card".to_owned(),
+            techs => {
+                let techs = techs.join(" / ");
+                match techs.starts_with(['A', 'E', 'I', 'O', 'U']) {
+                    true => format!("An {} card", techs),
+                    false => format!("A {} card", techs),
+                }
+            }
+        };
+        if let Some(product) = self.product.as_ref() {
+            text += &format!(", probably {}", product);
+        }
+        if let Some(country) = self.country.as_ref() {
+            text += &format!(", from {}", country);
+        }
+        text += ".";
+        if !self.capabilities.is_empty() {
+            text += &format!(" Notably: {}.", self.capabilities.join(", "));
+        }
+        text
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn technologies(probe: &Probe) -> Vec<String> {
+    let mut techs = vec![];
+    // Readers make up an ATR with this in for contactless cards; without it, we don't
+    // actually know, and it's probably a contact card.
+    if let Some(atr::HistoricalBytes::TLV(atr::HistoricalBytesTLV {
+        initial_access: Some(atr::InitialAccess { standard, .. }),
+        ..
+    })) = &probe.atr.historical_bytes
+    {
+        techs.push(standard.to_string());
+    }
+    if probe.emv.is_some() {
+        techs.push("EMV".into());
+    }
+    if probe.calypso.is_some() {
+        techs.push("Calypso".into());
+    }
+    if probe.ef_dir.is_some() {
+        techs.push("ISO 7816".into());
+    }
+    techs
+}
+
+/// Which FeliCa systems say what a card is; the rest are on all sorts of cards.
+fn is_product_system(code: SystemCode) -> bool {
+    !matches!(
+        code,
+        SystemCode::NDEF
+            | SystemCode::HostEmulation
+            | SystemCode::FeliCaCommon
+            | SystemCode::FeliCaPlug
+            | SystemCode::Unknown(_)
+    )
+}
+
+fn product(probe: &Probe) -> Option<String> {
+    // Payment cards have names, and they're better than anything an ATR database knows.
+    if let Some(app) = probe.emv.as_ref().and_then(|emv| emv.applications.first()) {
+        let name = (app.application.app_preferred_name.as_deref())
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or(app.application.app_label.trim());
+        let what = match Scheme::from_aid(&app.adf_name) {
+            Some(scheme) => format!("a {} payment card", scheme),
+            None => "a payment card".to_owned(),
+        };
+        return Some(match name.is_empty() {
+            true => what,
+            false => format!("{} ({:?})", what, name),
+        });
+    }
+    if let Some(felica) = probe.felica.as_ref() {
+        let systems = (felica.systems.iter())
+            .map(|s| s.code)
+            .filter(|&code| is_product_system(code))
+            .map(|code| code.to_string())
+            .collect::<Vec<_>>();
+        if !systems.is_empty() {
+            return Some(systems.join(" + "));
+        }
+    }
+    if let Some(app) = probe.eid.iter().flatten().next() {
+        return Some(app.scheme.to_string());
+    }
+    if let Some(known_as) = probe.known_as.first() {
+        return Some(known_as.clone());
+    }
+    let labels = (probe.ef_dir.iter().flatten())
+        .filter_map(|app| app.label.as_deref())
+        .collect::<Vec<_>>();
+    (!labels.is_empty()).then(|| format!("a card with {}", labels.join(", ")))
+}
+
+fn country(probe: &Probe) -> Option<String> {
+    if let CardUid::Iccid(iccid) = &probe.uid {
+        if let Some(country) = iccid_country(iccid) {
+            return Some(country.to_owned());
+        }
+    }
+    if let Some(country) = (probe.eid.iter().flatten()).find_map(|app| app.scheme.country()) {
+        return Some(country.to_owned());
+    }
+    (probe.felica.iter().flat_map(|f| f.systems.iter()))
+        .find_map(|s| felica_country(s.code))
+        .map(|s| s.to_owned())
+}
+
+/// Country calling codes (E.164), which is what an ICCID has after its "89" prefix; only
+/// the big ones, longest first.
+const CALLING_CODES: &[(&str, &str)] = &[
+    ("852", "Hong Kong"),
+    ("886", "Taiwan"),
+    ("971", "the UAE"),
+    ("31", "the Netherlands"),
+    ("33", "France"),
+    ("34", "Spain"),
+    ("39", "Italy"),
+    ("41", "Switzerland"),
+    ("44", "the UK"),
+    ("45", "Denmark"),
+    ("46", "Sweden"),
+    ("47", "Norway"),
+    ("48", "Poland"),
+    ("49", "Germany"),
+    ("61", "Australia"),
+    ("64", "New Zealand"),
+    ("65", "Singapore"),
+    ("81", "Japan"),
+    ("82", "South Korea"),
+    ("86", "China"),
+    ("91", "India"),
+    ("1", "North America"),
+    ("7", "Russia or Kazakhstan"),
+];
+
+fn iccid_country(iccid: &str) -> Option<&'static str> {
+    let rest = iccid.strip_prefix("89")?;
+    (CALLING_CODES.iter())
+        .find(|(code, _)| rest.starts_with(code))
+        .map(|(_, country)| *country)
+}
+
+fn felica_country(code: SystemCode) -> Option<&'static str> {
+    match code {
+        SystemCode::Octopus => Some("Hong Kong"),
+        SystemCode::Suica
+        | SystemCode::IruCa
+        | SystemCode::PASPY
+        | SystemCode::SAPICA
+        | SystemCode::OKICA
+        | SystemCode::Ryuto => Some("Japan"),
+        _ => None,
+    }
+}
+
+fn capabilities(probe: &Probe) -> Vec<String> {
+    let mut caps = vec![];
+    if let Some(emv) = probe.emv.as_ref() {
+        if emv.directory.is_some() {
+            caps.push("contact payments".to_owned());
+        }
+        if emv.proximity_directory.is_some() {
+            caps.push("contactless payments".to_owned());
+        }
+        for app in emv.applications.iter() {
+            let Some(fci_idd) = app.application.fci_issuer_discretionary_data.as_ref() else {
+                continue;
+            };
+            if fci_idd.log_entry.is_some() {
+                caps.push("a transaction log".to_owned());
+            }
+            match fci_idd
+                .ffi_or_third_party_data
+                .as_ref()
+                .and_then(|d| d.decoded.as_ref())
+            {
+                Some(Decoded9F6E::FormFactorIndicator(ffi)) => {
+                    if ffi.form_factor != FormFactor::StandardCard {
+                        caps.push(format!("form factor: {}", ffi.form_factor));
+                    }
+                    caps.extend(ffi.features.iter().map(|f| f.to_string()));
+                }
+                Some(Decoded9F6E::ThirdPartyData(tpd)) => {
+                    if let Some(name) = tpd.device_type_name().filter(|&n| n != "Card") {
+                        caps.push(format!("form factor: {}", name));
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+    for system in probe.felica.iter().flat_map(|f| f.systems.iter()) {
+        match system.code {
+            SystemCode::NDEF => caps.push("NFC NDEF".to_owned()),
+            SystemCode::HostEmulation => caps.push("emulated by a phone".to_owned()),
+            _ => {}
+        }
+    }
+    for xref in probe.xrefs.iter().filter(|x| !x.consistent) {
+        caps.push(format!("mismatched {} (a test card?)", xref.what));
+    }
+    // Multi-application cards tend to say the same things about each one.
+    let mut unique = vec![];
+    for cap in caps {
+        if !unique.contains(&cap) {
+            unique.push(cap);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::felica;
+    use crate::probe::felica::{FelicaProbe, FelicaSystem};
+
+    fn probe() -> Probe {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let atr_raw = vec![
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        Probe {
+            summary: Summary::default(),
+            capabilities: Default::default(),
+            reader: vec![],
+            link: None,
+            uid: CardUid::FelicaIdm(0x01120412711A6A0E),
+            atr: atr::parse(&atr_raw).unwrap(),
+            atr_raw: atr_raw.into(),
+            atr_warnings: vec![],
+            known_as: vec![],
+            ats: None,
+            ef_atr: None,
+            emv: None,
+            eid: None,
+            ef_dir: None,
+            felica: None,
+            calypso: None,
+            xrefs: vec![],
+            warnings: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_summary_felica() {
+        let mut probe = probe();
+        probe.felica = Some(FelicaProbe {
+            idm: 0x01120412711A6A0E,
+            pmm: None,
+            systems: [felica::SystemCode::Suica, felica::SystemCode::FeliCaCommon]
+                .into_iter()
+                .map(|code| FelicaSystem {
+                    code,
+                    idm: 0x01120412711A6A0E,
+                    nodes: vec![],
+                })
+                .collect(),
+        });
+        let summary = Summary::new(&probe);
+        assert_eq!(summary.technologies, vec!["FeliCa".to_owned()]);
+        assert_eq!(summary.product.as_deref(), Some("Suica"));
+        assert_eq!(summary.country.as_deref(), Some("Japan"));
+        assert_eq!(
+            summary.to_string(),
+            "A FeliCa card, probably Suica, from Japan."
+        );
+    }
+
+    #[test]
+    fn test_summary_sim() {
+        let mut probe = probe();
+        probe.atr.historical_bytes = None;
+        probe.uid = CardUid::Iccid("8944020001234567890".into());
+        probe.known_as = vec!["Some SIM card".into()];
+        let summary = Summary::new(&probe);
+        assert_eq!(summary.text, "A card, probably Some SIM card, from the UK.");
+    }
+
+    #[test]
+    fn test_iccid_country() {
+        assert_eq!(iccid_country("8985212345"), Some("Hong Kong"));
+        assert_eq!(iccid_country("8910123"), Some("North America"));
+        assert_eq!(iccid_country("12345"), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! Cross-referencing: the same identifier often turns up in more than one place on a
+//! card, and if the copies don't agree, something went wrong when it was personalised.
+//!
+//! This is surprisingly good at catching test cards that were put together by hand.
+
+use crate::probe::Probe;
+use crate::HexVec;
+use serde::Serialize;
+
+/// An identifier that was seen in more than one place.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CrossRef {
+    /// What the identifier is, eg. "PAN" or "IDm".
+    pub what: &'static str,
+    /// Where it was seen, and what it was there.
+    pub sightings: Vec<Sighting>,
+    /// Do all the sightings agree?
+    pub consistent: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sighting {
+    pub source: String,
+    pub value: HexVec,
+}
+
+impl CrossRef {
+    /// Builds a cross-reference, if there's anything to cross-reference. `key` picks out
+    /// the part of each value that should match.
+    fn new(
+        what: &'static str,
+        sightings: Vec<Sighting>,
+        key: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> Option<Self> {
+        if sightings.len() < 2 {
+            return None;
+        }
+        let first = key(&sightings[0].value);
+        let consistent = sightings.iter().all(|s| key(&s.value) == first);
+        Some(Self {
+            what,
+            sightings,
+            consistent,
+        })
+    }
+}
+
+fn sighting(source: impl Into<String>, value: &[u8]) -> Sighting {
+    Sighting {
+        source: source.into(),
+        value: value.into(),
+    }
+}
+
+/// Finds everything in a probe that can be cross-referenced.
+pub fn cross_reference(probe: &Probe) -> Vec<CrossRef> {
+    [uid(probe), idm(probe), pan(probe)]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Some cards put their UID in the ATR's historical bytes; we can't tell where it should
+/// be, so this only ever finds matches, never mismatches.
+fn uid(probe: &Probe) -> Option<CrossRef> {
+    let cid = probe.uid.to_bytes().filter(|cid| cid.len() >= 4)?;
+    let in_atr = probe.atr_raw.get(2..)?.windows(cid.len()).any(|w| w == cid);
+    CrossRef::new(
+        "UID",
+        [
+            Some(sighting("GET DATA", &cid)),
+            in_atr.then(|| sighting("ATR", &cid)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        |v| v.to_vec(),
+    )
+}
+
+/// FeliCa IDm: from the reader, from each System (which only differ in the top nibble,
+/// the system number), and from the ID block on FeliCa Lite(-S).
+fn idm(probe: &Probe) -> Option<CrossRef> {
+    let felica = probe.felica.as_ref()?;
+    let mut sightings = vec![sighting("GET DATA", &felica.idm.to_be_bytes())];
+    for system in felica.systems.iter() {
+        sightings.push(sighting(
+            format!("System {:04X}", u16::from(system.code)),
+            &system.idm.to_be_bytes(),
+        ));
+    }
+    for node in felica.systems.iter().flat_map(|s| s.nodes.iter()) {
+        if let super::felica::FelicaNode::Service { blocks, .. } = node {
+            for block in blocks.iter().filter(|b| b.name == Some("ID")) {
+                if let Some(id) = block.data.as_deref().and_then(|d| d.get(..8)) {
+                    sightings.push(sighting("ID block", id));
+                }
+            }
+        }
+    }
+    CrossRef::new("IDm", sightings, |v| {
+        let mut v = v.to_vec();
+        v[0] &= 0x0F;
+        v
+    })
+}
+
+/// PAN (+ sequence number), from the Data Storage Identifier in the directory's and each
+/// application's FCI.
+fn pan(probe: &Probe) -> Option<CrossRef> {
+    let emv = probe.emv.as_ref()?;
+    let mut sightings = vec![];
+    if let Some(ds_id) = (emv.directory.as_ref())
+        .and_then(|d| d.fci_issuer_discretionary_data.as_ref())
+        .and_then(|d| d.ds_id.as_deref())
+    {
+        sightings.push(sighting("Directory DS ID", ds_id));
+    }
+    for app in emv.applications.iter() {
+        if let Some(ds_id) = app
+            .application
+            .fci_issuer_discretionary_data
+            .as_ref()
+            .and_then(|d| d.ds_id.as_deref())
+        {
+            sightings.push(sighting(
+                format!("{} DS ID", hex::encode_upper(&app.adf_name)),
+                ds_id,
+            ));
+        }
+    }
+    CrossRef::new("PAN", sightings, |v| v.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::felica::{FelicaProbe, FelicaSystem};
+    use crate::uid::CardUid;
+    use crate::{atr, felica};
+
+    fn probe() -> Probe {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let atr_raw = vec![
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        Probe {
+            summary: Default::default(),
+            capabilities: Default::default(),
+            reader: vec![],
+            link: None,
+            uid: CardUid::FelicaIdm(0x01120412711A6A0E),
+            atr: atr::parse(&atr_raw).unwrap(),
+            atr_raw: atr_raw.into(),
+            atr_warnings: vec![],
+            known_as: vec![],
+            ats: None,
+            ef_atr: None,
+            emv: None,
+            eid: None,
+            ef_dir: None,
+            felica: None,
+            calypso: None,
+            xrefs: vec![],
+            warnings: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_cross_reference_idm() {
+        let mut probe = probe();
+        let system = |code: u16, idm: u64| FelicaSystem {
+            code: felica::SystemCode::from(code),
+            idm,
+            nodes: vec![],
+        };
+        probe.felica = Some(FelicaProbe {
+            idm: 0x01120412711A6A0E,
+            pmm: None,
+            systems: vec![
+                system(0x0003, 0x01120412711A6A0E),
+                system(0xFE00, 0x11120412711A6A0E),
+            ],
+        });
+        let xrefs = cross_reference(&probe);
+        assert_eq!(xrefs.len(), 1);
+        assert_eq!(xrefs[0].what, "IDm");
+        assert_eq!(xrefs[0].sightings.len(), 3);
+        assert!(xrefs[0].consistent);
+
+        probe.felica.as_mut().unwrap().systems[1].idm = 0x11120412711A6A0F;
+        assert!(!cross_reference(&probe)[0].consistent);
+    }
+
+    #[test]
+    fn test_cross_reference_nothing() {
+        // A CID that isn't in the ATR, and nothing else to compare it to.
+        assert_eq!(cross_reference(&probe()), vec![]);
+    }
+}
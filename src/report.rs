@@ -0,0 +1,969 @@
+//! Versioned envelope for anything we write to disk (probe results, and so on).
+//!
+//! Everything written out is wrapped in a [Report], which says what it is and which
+//! version of the schema it follows. Old files can be read with [load], which upgrades
+//! them to the current version one step at a time, so archives keep working.
+//!
+//! Versions:
+//! - 1: A bare [crate::probe::Probe] object, with no envelope. (Never had a version field.)
+//! - 2: `{"version": 2, "kind": "probe", "data": {...}}`.
+//! - 3: Probes gained `xrefs`.
+//! - 4: Probes gained `atr_warnings`, and `atr.tck` can be null.
+//! - 5: Probes gained `known_as`.
+//! - 6: TLV historical bytes in ATRs gained `category`.
+//! - 7: EMV probes gained `proximity_directory`, `directory` can be null, and
+//!   applications gained `directories`.
+//! - 8: Probes gained `ef_dir`.
+//! - 9: Probes' `cid` became `uid`, which says what kind of identifier it is.
+//! - 10: EMV FCI Issuer Discretionary Data's `unknown_9f6e` became `ffi_or_third_party_data`,
+//!   which is decoded for Visa and Mastercard applications.
+//! - 11: Probes gained `summary`.
+//! - 12: Probes gained `warnings`.
+//! - 13: FeliCa Areas' `end` moved into `code`, as `end` and `end_number`, and
+//!   `can_subdivide` is no longer backwards.
+//! - 14: Reader attributes gained `decoded`.
+//! - 15: Probes gained `ats`.
+//! - 16: Probes gained `calypso`.
+//! - 17: Probes gained `eid`.
+//! - 18: Probes gained `ef_atr` and `capabilities`; historical bytes gained
+//!   `card_capabilities`.
+//! - 19: Probes gained `link`, the negotiated protocol parameters.
+//! - 20: Byte strings (AIDs, raw ATRs, FCIs and so on) are hex, not arrays of numbers.
+
+use crate::atr::{self, Standard};
+use crate::emv::scheme::{Data9F6E, Scheme};
+use crate::hexvec;
+use crate::pcsc_attrs::{AttrValue, Link};
+use crate::probe::capabilities::Capabilities;
+use crate::probe::summary::Summary;
+use crate::uid::CardUid;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// Current schema version; bump this and add a migration whenever the format changes.
+pub const VERSION: u64 = 20;
+
+/// Migrations, where `MIGRATIONS[n]` upgrades from version n+1 to n+2.
+const MIGRATIONS: &[fn(Value) -> Result<Value>] = &[
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+    migrate_v4,
+    migrate_v5,
+    migrate_v6,
+    migrate_v7,
+    migrate_v8,
+    migrate_v9,
+    migrate_v10,
+    migrate_v11,
+    migrate_v12,
+    migrate_v13,
+    migrate_v14,
+    migrate_v15,
+    migrate_v16,
+    migrate_v17,
+    migrate_v18,
+    migrate_v19,
+];
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("report is version {0}, but we only understand up to {VERSION}")]
+    UnsupportedVersion(u64),
+
+    #[error("not a cardinal report")]
+    Unrecognized,
+
+    #[error(transparent)]
+    Parse(#[from] serde_yaml::Error),
+}
+
+/// What's in a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// A single [crate::probe::Probe].
+    Probe,
+    /// Multiple probes, by reader name (`cardinal probe --all-readers`).
+    ProbeMulti,
+    /// [crate::check::Violation]s from `cardinal check`.
+    Check,
+    /// A [crate::bench::Bench] from `cardinal bench-reader`.
+    Bench,
+    /// A [crate::acquire::Manifest] from `cardinal acquire`.
+    Acquisition,
+    /// A [crate::diff::Differential] from `cardinal probe --repeat`.
+    Differential,
+    /// A [crate::diff::DumpDiff] from `cardinal diff`.
+    Diff,
+}
+
+/// Envelope for anything written to disk.
+#[derive(Debug, Serialize)]
+pub struct Report<T> {
+    pub version: u64,
+    pub kind: Kind,
+    pub data: T,
+}
+
+impl<T: Serialize> Report<T> {
+    pub fn new(kind: Kind, data: T) -> Self {
+        Self {
+            version: VERSION,
+            kind,
+            data,
+        }
+    }
+}
+
+/// Loads a report (as JSON or YAML), and upgrades it to the current version.
+pub fn load(input: &str) -> Result<Value> {
+    // YAML is a superset of JSON, so this reads both.
+    migrate(serde_yaml::from_str(input)?)
+}
+
+/// Upgrades a report to the current version.
+pub fn migrate(mut report: Value) -> Result<Value> {
+    let mut version = version_of(&report)?;
+    if version > VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    while version < VERSION {
+        debug!(version, "Migrating report");
+        report = MIGRATIONS[(version - 1) as usize](report)?;
+        version += 1;
+    }
+    Ok(report)
+}
+
+fn version_of(report: &Value) -> Result<u64> {
+    match report.get("version") {
+        Some(v) => v.as_u64().filter(|&v| v > 0).ok_or(Error::Unrecognized),
+        // Version 1 was just a probe, with no envelope; we know it by its ATR.
+        None if report.get("atr").is_some() => Ok(1),
+        None => Err(Error::Unrecognized),
+    }
+}
+
+fn migrate_v1(report: Value) -> Result<Value> {
+    Ok(json!({ "version": 2, "kind": Kind::Probe, "data": report }))
+}
+
+/// Calls `f` on every probe in a report, whether it has one or many.
+fn for_each_probe(report: &mut Value, f: impl FnMut(&mut Value)) {
+    match report["kind"].as_str() {
+        Some("probe") => [&mut report["data"]].into_iter().for_each(f),
+        Some("probe-multi") => {
+            if let Some(probes) = report["data"].as_object_mut() {
+                probes.values_mut().for_each(f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads a byte string: an array of numbers up to version 19, and hex after that.
+fn bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Array(items) => Some(
+            (items.iter().filter_map(Value::as_u64))
+                .map(|b| b as u8)
+                .collect(),
+        ),
+        Value::String(s) => hexvec::parse(s).ok(),
+        _ => None,
+    }
+}
+
+fn migrate_v2(mut report: Value) -> Result<Value> {
+    // Old probes weren't cross-referenced, so there's nothing to put here.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("xrefs").or_insert_with(|| json!([]));
+        }
+    });
+    report["version"] = json!(3);
+    Ok(report)
+}
+
+fn migrate_v3(mut report: Value) -> Result<Value> {
+    // Old ATRs were parsed strictly; if there was anything wrong, there was no probe.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("atr_warnings").or_insert_with(|| json!([]));
+        }
+    });
+    report["version"] = json!(4);
+    Ok(report)
+}
+
+fn migrate_v4(mut report: Value) -> Result<Value> {
+    // Old probes could be looked up again, but which database would be a guess.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("known_as").or_insert_with(|| json!([]));
+        }
+    });
+    report["version"] = json!(5);
+    Ok(report)
+}
+
+fn migrate_v5(mut report: Value) -> Result<Value> {
+    // The category byte comes right before the rest of the historical bytes, which are
+    // followed by the TCK, if there is one.
+    for_each_probe(&mut report, |probe| {
+        let (Some(raw), Some(k)) = (bytes(&probe["atr_raw"]), probe["atr"]["t0"]["k"].as_u64())
+        else {
+            return;
+        };
+        let tck = probe["atr"]["tck"].is_u64() as usize;
+        let category = (raw.len().checked_sub(k as usize + tck))
+            .and_then(|i| raw.get(i).copied())
+            .unwrap_or(0x80);
+        if let Some(tlv) = probe["atr"]["historical_bytes"]["TLV"].as_object_mut() {
+            tlv.entry("category").or_insert_with(|| json!(category));
+        }
+    });
+    report["version"] = json!(6);
+    Ok(report)
+}
+
+fn migrate_v6(mut report: Value) -> Result<Value> {
+    // Old probes only ever looked at the PSE.
+    for_each_probe(&mut report, |probe| {
+        let Some(emv) = probe["emv"].as_object_mut() else {
+            return;
+        };
+        emv.entry("proximity_directory").or_insert(Value::Null);
+        if let Some(apps) = emv.get_mut("applications").and_then(Value::as_array_mut) {
+            for app in apps.iter_mut().filter_map(Value::as_object_mut) {
+                app.entry("directories").or_insert_with(|| json!(["pse"]));
+            }
+        }
+    });
+    report["version"] = json!(7);
+    Ok(report)
+}
+
+fn migrate_v7(mut report: Value) -> Result<Value> {
+    // Old probes never looked at EF.DIR.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("ef_dir").or_insert(Value::Null);
+        }
+    });
+    report["version"] = json!(8);
+    Ok(report)
+}
+
+fn migrate_v8(mut report: Value) -> Result<Value> {
+    // A CID was an IDm if we probed the card as FeliCa, which we can only tell from the
+    // probe having FeliCa results, or the ATR saying it was one.
+    for_each_probe(&mut report, |probe| {
+        let standard =
+            match probe["atr"]["historical_bytes"]["TLV"]["initial_access"]["standard"].as_str() {
+                _ if !probe["felica"].is_null() => Standard::FeliCa,
+                Some("FeliCa") => Standard::FeliCa,
+                _ => Standard::Iso14443a3,
+            };
+        let Some(probe) = probe.as_object_mut() else {
+            return;
+        };
+        let cid = (probe.remove("cid").as_ref())
+            .and_then(bytes)
+            .map(|cid| CardUid::from_cid(&cid, standard))
+            .unwrap_or_default();
+        probe
+            .entry("uid")
+            .or_insert_with(|| serde_json::to_value(cid).unwrap_or(Value::Null));
+    });
+    report["version"] = json!(9);
+    Ok(report)
+}
+
+fn migrate_v9(mut report: Value) -> Result<Value> {
+    // The raw bytes are all there, so applications' can be decoded now; the directory's
+    // aren't any scheme's in particular.
+    fn upgrade(fci_idd: &mut Value, scheme: Option<Scheme>) {
+        let Some(fci_idd) = fci_idd.as_object_mut() else {
+            return;
+        };
+        let raw = fci_idd.remove("unknown_9f6e").as_ref().and_then(bytes);
+        let data = raw.map(|raw| {
+            let mut data = Data9F6E::new(&raw);
+            if let Some(scheme) = scheme {
+                data.decode(scheme);
+            }
+            data
+        });
+        fci_idd
+            .entry("ffi_or_third_party_data")
+            .or_insert_with(|| serde_json::to_value(data).unwrap_or(Value::Null));
+    }
+
+    for_each_probe(&mut report, |probe| {
+        let emv = &mut probe["emv"];
+        if emv.is_null() {
+            return;
+        }
+        upgrade(&mut emv["directory"]["fci_issuer_discretionary_data"], None);
+        if let Some(apps) = emv["applications"].as_array_mut() {
+            for app in apps.iter_mut() {
+                let adf_name = bytes(&app["adf_name"]).unwrap_or_default();
+                upgrade(
+                    &mut app["application"]["fci_issuer_discretionary_data"],
+                    Scheme::from_aid(&adf_name),
+                );
+            }
+        }
+    });
+    report["version"] = json!(10);
+    Ok(report)
+}
+
+fn migrate_v10(mut report: Value) -> Result<Value> {
+    // Old probes weren't summarised; probing the card again will.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe
+                .entry("summary")
+                .or_insert_with(|| serde_json::to_value(Summary::default()).unwrap_or(Value::Null));
+        }
+    });
+    report["version"] = json!(11);
+    Ok(report)
+}
+
+fn migrate_v11(mut report: Value) -> Result<Value> {
+    // Old probes only logged warnings.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("warnings").or_insert_with(|| json!([]));
+        }
+    });
+    report["version"] = json!(12);
+    Ok(report)
+}
+
+fn migrate_v12(mut report: Value) -> Result<Value> {
+    // Areas' end moved into their code, and can_subdivide (which was backwards) is redone.
+    for_each_probe(&mut report, |probe| {
+        let Some(systems) = probe
+            .pointer_mut("/felica/systems")
+            .and_then(|v| v.as_array_mut())
+        else {
+            return;
+        };
+        for system in systems.iter_mut() {
+            let Some(nodes) = system["nodes"].as_array_mut() else {
+                continue;
+            };
+            for area in nodes.iter_mut().filter_map(|node| node.get_mut("Area")) {
+                let end = area["end"].take();
+                if let Some(area) = area.as_object_mut() {
+                    area.remove("end");
+                }
+                let code = &mut area["code"];
+                let attrs = code["code"].as_u64().unwrap_or_default() & 0x3F;
+                code["can_subdivide"] = json!(attrs == 0);
+                code["end"] = end["code"].clone();
+                code["end_number"] = end["number"].clone();
+            }
+        }
+    });
+    report["version"] = json!(13);
+    Ok(report)
+}
+
+fn migrate_v13(mut report: Value) -> Result<Value> {
+    // The raw values are all there, so they can be decoded now.
+    for_each_probe(&mut report, |probe| {
+        let Some(attrs) = probe["reader"].as_array_mut() else {
+            return;
+        };
+        for attr in attrs.iter_mut() {
+            let raw = bytes(&attr["value"]).unwrap_or_default();
+            let decoded =
+                (attr["attribute"].as_str()).and_then(|name| AttrValue::decode(name, &raw));
+            if let Some(attr) = attr.as_object_mut() {
+                attr.entry("decoded")
+                    .or_insert_with(|| serde_json::to_value(decoded).unwrap_or(Value::Null));
+            }
+        }
+    });
+    report["version"] = json!(14);
+    Ok(report)
+}
+
+fn migrate_v14(mut report: Value) -> Result<Value> {
+    // Old probes didn't ask for the ATS; the historical bytes are in the ATR, if anyone
+    // needs them.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("ats").or_insert(Value::Null);
+        }
+    });
+    report["version"] = json!(15);
+    Ok(report)
+}
+
+fn migrate_v15(mut report: Value) -> Result<Value> {
+    // Old probes never tried Calypso.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("calypso").or_insert(Value::Null);
+        }
+    });
+    report["version"] = json!(16);
+    Ok(report)
+}
+
+fn migrate_v16(mut report: Value) -> Result<Value> {
+    // Old probes never looked for eIDs.
+    for_each_probe(&mut report, |probe| {
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("eid").or_insert(Value::Null);
+        }
+    });
+    report["version"] = json!(17);
+    Ok(report)
+}
+
+fn migrate_v17(mut report: Value) -> Result<Value> {
+    // Card capabilities used to be an unknown field; the raw historical bytes are there, so
+    // they can be decoded now. Old probes never read EF.ATR/INFO, and there's not enough
+    // left of them to work out the rest, so they get empty capabilities like the summary.
+    for_each_probe(&mut report, |probe| {
+        for ptr in ["/atr/historical_bytes/TLV", "/ats/historical_bytes/TLV"] {
+            let Some(tlv) = probe.pointer_mut(ptr) else {
+                continue;
+            };
+            let raw = (tlv["category"].as_u64().map(|b| b as u8).into_iter())
+                .chain(bytes(&tlv["raw"]).unwrap_or_default())
+                .collect::<Vec<_>>();
+            let caps = match atr::historical_bytes(&raw) {
+                Some(atr::HistoricalBytes::TLV(hb)) => hb.card_capabilities,
+                _ => None,
+            };
+            if let Some(tlv) = tlv.as_object_mut() {
+                tlv.entry("card_capabilities")
+                    .or_insert_with(|| serde_json::to_value(caps).unwrap_or(Value::Null));
+            }
+        }
+        if let Some(probe) = probe.as_object_mut() {
+            probe.entry("ef_atr").or_insert(Value::Null);
+            probe.entry("capabilities").or_insert_with(|| {
+                serde_json::to_value(Capabilities::default()).unwrap_or(Value::Null)
+            });
+        }
+    });
+    report["version"] = json!(18);
+    Ok(report)
+}
+
+fn migrate_v18(mut report: Value) -> Result<Value> {
+    // Like decoding the attributes, this only needs the raw values.
+    for_each_probe(&mut report, |probe| {
+        let attrs = (probe["reader"].as_array().into_iter().flatten())
+            .filter_map(|attr| {
+                let raw = bytes(&attr["value"])?;
+                Some((attr["attribute"].as_str()?.to_owned(), raw))
+            })
+            .collect::<Vec<_>>();
+        let link = Link::from_attributes(attrs.iter().map(|(name, raw)| (name.as_str(), &raw[..])));
+        if let Some(probe) = probe.as_object_mut() {
+            probe
+                .entry("link")
+                .or_insert_with(|| serde_json::to_value(link).unwrap_or(Value::Null));
+        }
+    });
+    report["version"] = json!(19);
+    Ok(report)
+}
+
+/// Fields that are byte strings, wherever they are. Nothing else in a probe (or a check,
+/// or an acquisition's manifest) has one of these names, so there's no need to know where
+/// each one lives.
+const BYTE_FIELDS: &[&str] = &[
+    "ac",
+    "actual",
+    "adf_name",
+    "aid",
+    "atr_raw",
+    "Bits",
+    "cvr",
+    "data",
+    "df_name",
+    "dir_discretionary_template",
+    "discretionary_data",
+    "ds_id",
+    "ef_atr",
+    "expected",
+    "fci",
+    "iad",
+    "Iso14443Uid",
+    "path",
+    "pmm",
+    "pre_issuing_data",
+    "proprietary_data",
+    "pt",
+    "raw",
+    "rest",
+    "tag",
+    "Unknown",
+    "usim",
+    "value",
+];
+
+fn migrate_v19(mut report: Value) -> Result<Value> {
+    // Every byte string becomes hex; the bytes themselves are all there.
+    fn is_bytes(value: &Value) -> bool {
+        (value.as_array()).is_some_and(|v| v.iter().all(|b| b.as_u64().is_some_and(|b| b <= 0xFF)))
+    }
+    fn to_hex(value: &mut Value) {
+        if is_bytes(value) {
+            *value = json!(hex::encode_upper(bytes(value).unwrap_or_default()));
+        }
+    }
+    fn walk(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, value) in fields.iter_mut() {
+                    match (name.as_str(), value) {
+                        (name, value) if BYTE_FIELDS.contains(&name) && is_bytes(value) => {
+                            to_hex(value)
+                        }
+                        // Unknown historical bytes come with their category, and registered
+                        // proprietary data with its tag.
+                        ("Unknown", Value::Array(v)) if v.len() == 2 => to_hex(&mut v[1]),
+                        ("app_selection_reg_propr_data", Value::Array(entries)) => {
+                            for entry in entries.iter_mut().filter_map(Value::as_array_mut) {
+                                if let Some(data) = entry.get_mut(1) {
+                                    to_hex(data);
+                                }
+                            }
+                        }
+                        (_, value) => walk(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(walk),
+            _ => {}
+        }
+    }
+
+    match report["kind"].as_str() {
+        Some("check" | "acquisition") => walk(&mut report["data"]),
+        _ => for_each_probe(&mut report, walk),
+    }
+    report["version"] = json!(20);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atr, probe::Probe};
+
+    fn probe() -> Probe {
+        // ATR from a 2019 PASMO (FeliCa) card.
+        let atr_raw = vec![
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ];
+        Probe {
+            summary: Summary::default(),
+            capabilities: Default::default(),
+            reader: vec![],
+            link: None,
+            uid: CardUid::FelicaIdm(0x01120412711A6A0E),
+            atr: atr::parse(&atr_raw).unwrap(),
+            atr_raw: atr_raw.into(),
+            atr_warnings: vec![],
+            known_as: vec![],
+            ats: None,
+            ef_atr: None,
+            emv: None,
+            eid: None,
+            ef_dir: None,
+            felica: None,
+            calypso: None,
+            xrefs: vec![],
+            warnings: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let mut v1 = serde_json::to_value(probe()).unwrap();
+        v1.as_object_mut().unwrap().remove("xrefs");
+        v1.as_object_mut().unwrap().remove("atr_warnings");
+        v1.as_object_mut().unwrap().remove("known_as");
+        v1.as_object_mut().unwrap().remove("summary");
+        v1.as_object_mut().unwrap().remove("warnings");
+        assert_eq!(
+            migrate_v1(v1.clone()).unwrap(),
+            json!({ "version": 2, "kind": "probe", "data": v1 })
+        );
+        assert_eq!(
+            migrate(v1).unwrap(),
+            serde_json::to_value(Report::new(Kind::Probe, probe())).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_migrate_v2() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("xrefs");
+        let v2 = json!({ "version": 2, "kind": "probe-multi", "data": { "Reader 0": probe } });
+        let v3 = migrate_v2(v2).unwrap();
+        assert_eq!(v3["version"], 3);
+        assert_eq!(v3["data"]["Reader 0"]["xrefs"], json!([]));
+    }
+
+    #[test]
+    fn test_migrate_v3() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("atr_warnings");
+        let v3 = json!({ "version": 3, "kind": "probe", "data": probe });
+        let v4 = migrate_v3(v3).unwrap();
+        assert_eq!(v4["version"], 4);
+        assert_eq!(v4["data"]["atr_warnings"], json!([]));
+    }
+
+    #[test]
+    fn test_migrate_v4() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("known_as");
+        let v4 = json!({ "version": 4, "kind": "probe", "data": probe });
+        let v5 = migrate_v4(v4).unwrap();
+        assert_eq!(v5["version"], 5);
+        assert_eq!(v5["data"]["known_as"], json!([]));
+    }
+
+    #[test]
+    fn test_migrate_v5() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        let tlv = &mut probe["atr"]["historical_bytes"]["TLV"];
+        assert_eq!(tlv["category"], 0x80);
+        tlv.as_object_mut().unwrap().remove("category");
+        let v5 = json!({ "version": 5, "kind": "probe", "data": probe });
+        let v6 = migrate_v5(v5).unwrap();
+        assert_eq!(v6["version"], 6);
+        assert_eq!(
+            v6["data"]["atr"]["historical_bytes"]["TLV"]["category"],
+            0x80
+        );
+    }
+
+    #[test]
+    fn test_migrate_v6() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe["emv"] = json!({
+            "directory": {},
+            "records": [],
+            "applications": [{ "adf_name": [0xA0], "application": {} }],
+        });
+        let v6 = json!({ "version": 6, "kind": "probe", "data": probe });
+        let v7 = migrate_v6(v6).unwrap();
+        assert_eq!(v7["version"], 7);
+        assert_eq!(v7["data"]["emv"]["proximity_directory"], Value::Null);
+        assert_eq!(
+            v7["data"]["emv"]["applications"][0]["directories"],
+            json!(["pse"])
+        );
+    }
+
+    #[test]
+    fn test_migrate_v7() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("ef_dir");
+        let v7 = json!({ "version": 7, "kind": "probe-multi", "data": { "Reader": probe } });
+        let v8 = migrate_v7(v7).unwrap();
+        assert_eq!(v8["version"], 8);
+        assert_eq!(v8["data"]["Reader"]["ef_dir"], Value::Null);
+    }
+
+    #[test]
+    fn test_migrate_v8() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        let uid = probe.as_object_mut().unwrap().remove("uid").unwrap();
+        assert_eq!(uid, json!({ "FelicaIdm": 0x01120412711A6A0E_u64 }));
+        probe["cid"] = json!([0x01, 0x12, 0x04, 0x12, 0x71, 0x1A, 0x6A, 0x0E]);
+        let mut contact = probe.clone();
+        contact["atr"]["historical_bytes"] = Value::Null;
+        contact["cid"] = Value::Null;
+        let v8 = json!({
+            "version": 8,
+            "kind": "probe-multi",
+            "data": { "FeliCa": probe, "Contact": contact },
+        });
+        let v9 = migrate_v8(v8).unwrap();
+        assert_eq!(v9["version"], 9);
+        assert_eq!(v9["data"]["FeliCa"]["uid"], uid);
+        assert_eq!(v9["data"]["FeliCa"].get("cid"), None);
+        assert_eq!(v9["data"]["Contact"]["uid"], json!("None"));
+    }
+
+    #[test]
+    fn test_migrate_v9() {
+        let fci_idd =
+            || json!({ "ds_id": null, "unknown_9f6e": [0x08, 0x26, 0x00, 0x00, 0x30, 0x30, 0x00] });
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe["emv"] = json!({
+            "directory": { "ef_sfi": 1, "fci_issuer_discretionary_data": fci_idd() },
+            "applications": [{
+                "adf_name": [0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10],
+                "application": { "fci_issuer_discretionary_data": fci_idd() },
+            }],
+        });
+        let v9 = json!({ "version": 9, "kind": "probe", "data": probe });
+        let v10 = migrate_v9(v9).unwrap();
+        assert_eq!(v10["version"], 10);
+
+        let emv = &v10["data"]["emv"];
+        let dir = &emv["directory"]["fci_issuer_discretionary_data"];
+        assert_eq!(dir.get("unknown_9f6e"), None);
+        assert_eq!(dir["ffi_or_third_party_data"]["raw"], "08260000303000");
+        assert_eq!(dir["ffi_or_third_party_data"]["decoded"], Value::Null);
+        let app = &emv["applications"][0]["application"]["fci_issuer_discretionary_data"];
+        assert_eq!(
+            app["ffi_or_third_party_data"]["decoded"]["ThirdPartyData"]["country_code"],
+            826
+        );
+    }
+
+    #[test]
+    fn test_migrate_v10() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("summary");
+        let v10 = json!({ "version": 10, "kind": "probe", "data": probe });
+        let v11 = migrate_v10(v10).unwrap();
+        assert_eq!(v11["version"], 11);
+        assert_eq!(v11["data"]["summary"]["text"], "");
+        assert_eq!(v11["data"]["summary"]["technologies"], json!([]));
+    }
+
+    #[test]
+    fn test_migrate_v11() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("warnings");
+        let v11 = json!({ "version": 11, "kind": "probe", "data": probe });
+        let v12 = migrate(v11).unwrap();
+        assert_eq!(v12["version"], VERSION);
+        assert_eq!(v12["data"]["warnings"], json!([]));
+    }
+
+    #[test]
+    fn test_migrate_v12() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe["felica"] = json!({ "idm": 0, "pmm": null, "systems": [{
+            "code": "Suica",
+            "idm": 0,
+            "nodes": [{ "Area": {
+                "code": { "code": 0x1001, "number": 0x040, "can_subdivide": true },
+                "end": { "code": 0x17FF, "number": 0x05F, "kind": "Invalid",
+                         "access": "Invalid", "is_authenticated": false },
+            } }],
+        }] });
+        let v12 = json!({ "version": 12, "kind": "probe", "data": probe });
+        let v13 = migrate(v12).unwrap();
+        assert_eq!(v13["version"], VERSION);
+        assert_eq!(
+            v13["data"]["felica"]["systems"][0]["nodes"][0],
+            json!({ "Area": { "code": {
+                "code": 0x1001,
+                "number": 0x040,
+                "can_subdivide": false,
+                "end": 0x17FF,
+                "end_number": 0x05F,
+            } } })
+        );
+    }
+
+    #[test]
+    fn test_migrate_v13() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe["reader"] = json!([
+            { "attribute": "VendorName", "value": [0x41, 0x43, 0x53, 0x00] },
+            { "attribute": "AtrString", "value": [0x3B, 0x00] },
+        ]);
+        let v13 = json!({ "version": 13, "kind": "probe", "data": probe });
+        let v14 = migrate(v13).unwrap();
+        assert_eq!(v14["version"], VERSION);
+        assert_eq!(
+            v14["data"]["reader"][0]["decoded"],
+            json!({ "Text": "ACS" })
+        );
+        assert_eq!(v14["data"]["reader"][1]["decoded"], Value::Null);
+    }
+
+    #[test]
+    fn test_migrate_v14() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("ats");
+        let v14 = json!({ "version": 14, "kind": "probe", "data": probe });
+        let v15 = migrate(v14).unwrap();
+        assert_eq!(v15["version"], VERSION);
+        assert!(v15["data"].as_object().unwrap().contains_key("ats"));
+    }
+
+    #[test]
+    fn test_migrate_v15() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("calypso");
+        let v15 = json!({ "version": 15, "kind": "probe", "data": probe });
+        let v16 = migrate(v15).unwrap();
+        assert_eq!(v16["version"], VERSION);
+        assert!(v16["data"]["calypso"].is_null());
+    }
+
+    #[test]
+    fn test_migrate_v16() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("eid");
+        let v16 = json!({ "version": 16, "kind": "probe", "data": probe });
+        let v17 = migrate(v16).unwrap();
+        assert_eq!(v17["version"], VERSION);
+        assert!(v17["data"]["eid"].is_null());
+    }
+
+    #[test]
+    fn test_migrate_v17() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        let obj = probe.as_object_mut().unwrap();
+        obj.remove("ef_atr");
+        obj.remove("capabilities");
+        // Card capabilities, with chaining, extended lengths and 4 logical channels.
+        probe["atr"]["historical_bytes"] = json!({ "TLV": {
+            "category": 0x80,
+            "raw": [0x73, 0xC0, 0x21, 0xDB],
+            "service_data": null,
+            "initial_access": null,
+            "pre_issuing_data": null,
+            "status": null,
+        }});
+        let v17 = json!({ "version": 17, "kind": "probe", "data": probe });
+        let v18 = migrate(v17).unwrap();
+        assert_eq!(v18["version"], VERSION);
+        assert!(v18["data"]["ef_atr"].is_null());
+        assert_eq!(v18["data"]["capabilities"]["applications"], 0);
+        let caps = &v18["data"]["atr"]["historical_bytes"]["TLV"]["card_capabilities"];
+        assert_eq!(caps["extended_length"], true);
+        assert_eq!(caps["logical_channels"], 4);
+    }
+
+    #[test]
+    fn test_migrate_v18() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("link");
+        probe["reader"] = json!([
+            { "attribute": "CurrentProtocolType", "value": [0x01, 0x00, 0x00, 0x00] },
+            { "attribute": "CurrentF", "value": [0x74, 0x01, 0x00, 0x00] },
+        ]);
+        let v18 = json!({ "version": 18, "kind": "probe", "data": probe });
+        let v19 = migrate(v18).unwrap();
+        assert_eq!(v19["version"], VERSION);
+        assert_eq!(v19["data"]["link"]["protocol"], "T=0");
+        assert_eq!(v19["data"]["link"]["f"], 372);
+
+        // No attributes at all, like a probe over TCP.
+        let mut probe = serde_json::to_value(self::probe()).unwrap();
+        probe.as_object_mut().unwrap().remove("link");
+        let v18 = json!({ "version": 18, "kind": "probe", "data": probe });
+        assert!(migrate(v18).unwrap()["data"]["link"].is_null());
+    }
+
+    #[test]
+    fn test_migrate_v19() {
+        let mut probe = serde_json::to_value(probe()).unwrap();
+        probe["atr_raw"] = json!([0x3B, 0x8F, 0x80, 0x01]);
+        probe["atr"]["historical_bytes"]["TLV"]["raw"] = json!([0x4F, 0x0C]);
+        probe["uid"] = json!({ "Iso14443Uid": [0x04, 0x11] });
+        probe["reader"] = json!([{ "attribute": "CurrentF", "value": [0x74, 0x01] }]);
+        probe["emv"] = json!({ "applications": [{
+            "adf_name": [0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10],
+            "application": {
+                "fci_issuer_discretionary_data": {
+                    "app_selection_reg_propr_data": [[0x0001, [0x05, 0x01]]],
+                },
+            },
+        }]});
+        probe["calypso"] = json!({ "files": [{ "records": [{
+            "num": 1,
+            "data": [0x05, 0x35],
+            "fields": [{ "name": "EnvNetworkId", "value": { "Bits": [0x25, 0x09] } }],
+        }]}]});
+        probe["ef_dir"] = json!([{ "aid": [0xD2, 0x76], "path": [] }]);
+        let v19 = json!({ "version": 19, "kind": "probe", "data": probe });
+        let v20 = migrate_v19(v19).unwrap();
+        assert_eq!(v20["version"], 20);
+
+        let probe = &v20["data"];
+        assert_eq!(probe["atr_raw"], "3B8F8001");
+        assert_eq!(probe["atr"]["historical_bytes"]["TLV"]["raw"], "4F0C");
+        assert_eq!(probe["atr"]["historical_bytes"]["TLV"]["category"], 0x80);
+        assert_eq!(probe["uid"], json!({ "Iso14443Uid": "0411" }));
+        assert_eq!(probe["reader"][0]["value"], "7401");
+        let app = &probe["emv"]["applications"][0];
+        assert_eq!(app["adf_name"], "A0000000031010");
+        assert_eq!(
+            app["application"]["fci_issuer_discretionary_data"]["app_selection_reg_propr_data"],
+            json!([[1, "0501"]])
+        );
+        let record = &probe["calypso"]["files"][0]["records"][0];
+        assert_eq!(record["num"], 1);
+        assert_eq!(record["data"], "0535");
+        assert_eq!(record["fields"][0]["value"], json!({ "Bits": "2509" }));
+        assert_eq!(probe["ef_dir"], json!([{ "aid": "D276", "path": "" }]));
+
+        // Unknown historical bytes have their category in front.
+        let mut probe = serde_json::to_value(self::probe()).unwrap();
+        probe["atr"]["historical_bytes"] = json!({ "Unknown": [0x10, [0x01, 0x02]] });
+        let v19 = json!({ "version": 19, "kind": "probe-multi", "data": { "Reader": probe } });
+        let v20 = migrate_v19(v19).unwrap();
+        assert_eq!(
+            v20["data"]["Reader"]["atr"]["historical_bytes"],
+            json!({ "Unknown": [0x10, "0102"] })
+        );
+
+        let v19 = json!({ "version": 19, "kind": "check", "data": [
+            { "missing_tag": { "aid": [0xA0, 0x00], "tag": [0x9F, 0x12] } },
+            { "forbidden_service": { "code": 0x090F } },
+        ]});
+        let v20 = migrate_v19(v19).unwrap();
+        assert_eq!(
+            v20["data"],
+            json!([
+                { "missing_tag": { "aid": "A000", "tag": "9F12" } },
+                { "forbidden_service": { "code": 0x090F } },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_v2() {
+        let report = serde_json::to_value(Report::new(Kind::Probe, probe())).unwrap();
+        assert_eq!(report["version"], VERSION);
+        let json = serde_json::to_string(&report).unwrap();
+        let yaml = serde_yaml::to_string(&report).unwrap();
+        assert_eq!(load(&json).unwrap(), report);
+        assert_eq!(load(&yaml).unwrap(), report);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(
+            load(r#"{"version": 99, "kind": "probe", "data": {}}"#),
+            Err(Error::UnsupportedVersion(99))
+        ));
+        assert!(matches!(
+            load(r#"{"hello": "world"}"#),
+            Err(Error::Unrecognized)
+        ));
+        assert!(matches!(load("{"), Err(Error::Parse(_))));
+    }
+}
@@ -0,0 +1,173 @@
+//! Transit card history, exported for spreadsheets and accounting tools (eg. beancount or
+//! ledger importers): one row per transaction, with an ISO date, the stations (by name, if
+//! you have a list of them), what it cost, and what was left.
+//!
+//! Only CyberNet cards (Suica, PASMO, ICOCA, etc.) keep a history that can be read without
+//! keys; Octopus and the e-money purses only have a balance (see [crate::felica::purse]).
+
+use crate::felica::cybernet::{self, HistoryRecord, Station};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, trace_span};
+
+/// Station names, by line and station code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stations(pub HashMap<Station, String>);
+
+impl Stations {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parses a list of `line,station,name` lines, with the codes in hex (eg.
+    /// `E0,2E,Tokaidaigaku-mae`). Anything else is skipped, including a header.
+    pub fn parse(s: &str) -> Self {
+        let span = trace_span!("Stations::parse");
+        let _enter = span.enter();
+
+        let code = |v: &str| u8::from_str_radix(v.trim(), 16).ok();
+        let mut stations = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let mut fields = line.splitn(3, ',');
+            let (Some(l), Some(s), Some(name)) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            match (code(l), code(s)) {
+                (Some(line), Some(station)) => {
+                    stations.insert(Station { line, station }, name.trim().to_owned());
+                }
+                _ => debug!(line = i + 1, "Not a station, skipping"),
+            }
+        }
+        Self(stations)
+    }
+
+    /// The station's name, or its code if we don't know it.
+    pub fn name(&self, station: Station) -> String {
+        (self.0.get(&station).cloned()).unwrap_or_else(|| station.to_string())
+    }
+}
+
+/// One transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transaction {
+    /// Transaction sequence number; handy for not importing the same one twice.
+    pub seq: u16,
+    /// When it happened, as an ISO 8601 date; cards don't record the time for everything.
+    pub date: String,
+    pub terminal: String,
+    pub kind: String,
+    /// Where you got on and off, for train journeys.
+    pub entry: Option<String>,
+    pub exit: Option<String>,
+    /// How much the balance changed by (negative for spending), in minor units; unknown for
+    /// the oldest record, since there's nothing before it to compare against.
+    pub amount: Option<i64>,
+    /// Balance afterwards, in minor units.
+    pub balance: i64,
+    /// ISO 4217 code, eg. JPY.
+    pub currency: String,
+}
+
+/// Turns history records (newest first, like the card has them) into transactions, oldest
+/// first, like a bank statement.
+pub fn transactions(records: &[HistoryRecord], stations: &Stations) -> Vec<Transaction> {
+    let mut txs = vec![];
+    for (i, rec) in records.iter().enumerate() {
+        let previous = records.get(i + 1).map(|prev| prev.balance.value);
+        txs.push(Transaction {
+            seq: rec.seq,
+            date: rec.date.format("%Y-%m-%d").to_string(),
+            terminal: format!("{:?}", rec.terminal_type),
+            kind: format!("{:?}", rec.tx_type),
+            entry: rec.stations().map(|(entry, _)| stations.name(entry)),
+            exit: rec.stations().map(|(_, exit)| stations.name(exit)),
+            amount: previous.map(|prev| rec.balance.value - prev),
+            balance: rec.balance.value,
+            currency: rec.balance.currency.to_string(),
+        });
+    }
+    txs.reverse();
+    txs
+}
+
+/// Parses every block that's a history record, and skips the ones that aren't (unused
+/// records are all zeroes).
+pub fn parse_history<'a>(blocks: impl IntoIterator<Item = &'a [u8]>) -> Vec<HistoryRecord> {
+    (blocks.into_iter())
+        .filter_map(|block| cybernet::HistoryRecord::parse(block).ok())
+        .map(|(_, rec)| rec)
+        .collect()
+}
+
+/// Writes transactions as CSV, with a header.
+pub fn to_csv(txs: &[Transaction]) -> String {
+    let field = |v: &str| match v.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", v.replace('"', "\"\"")),
+        false => v.to_owned(),
+    };
+    let mut out = String::from("seq,date,terminal,kind,entry,exit,amount,balance,currency\n");
+    for tx in txs.iter() {
+        let row = [
+            tx.seq.to_string(),
+            tx.date.clone(),
+            tx.terminal.clone(),
+            tx.kind.clone(),
+            tx.entry.clone().unwrap_or_default(),
+            tx.exit.clone().unwrap_or_default(),
+            tx.amount.map(|v| v.to_string()).unwrap_or_default(),
+            tx.balance.to_string(),
+            tx.currency.clone(),
+        ];
+        out += &row.iter().map(|v| field(v)).collect::<Vec<_>>().join(",");
+        out += "\n";
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIP: [u8; 16] = [
+        0x16, 0x01, 0x00, 0x02, 0x27, 0x76, 0xE0, 0x2E, 0xE0, 0x27, 0x19, 0x09, 0x00, 0x00, 0x6F,
+        0x00,
+    ];
+    const SNACK: [u8; 16] = [
+        0xC8, 0x46, 0x00, 0x00, 0x27, 0x77, 0x31, 0x2B, 0x20, 0x21, 0x52, 0x03, 0x00, 0x00, 0x72,
+        0x00,
+    ];
+
+    #[test]
+    fn test_stations() {
+        let stations = Stations::parse("line,station,name\nE0,2E,Tokaidaigaku-mae\nnope\n");
+        assert_eq!(stations.0.len(), 1);
+        let station = |line, station| Station { line, station };
+        assert_eq!(stations.name(station(0xE0, 0x2E)), "Tokaidaigaku-mae");
+        assert_eq!(stations.name(station(0xE0, 0x27)), "E0-27");
+    }
+
+    #[test]
+    fn test_transactions() {
+        let records = parse_history([&SNACK[..], &TRIP[..], &[0; 16][..]]);
+        assert_eq!(records.len(), 2);
+        let stations = Stations::parse("E0,2E,Tokaidaigaku-mae, Odakyu");
+        let txs = transactions(&records, &stations);
+        assert_eq!(txs[0].seq, 111);
+        assert_eq!(txs[0].amount, None);
+        assert_eq!(txs[0].entry.as_deref(), Some("Tokaidaigaku-mae, Odakyu"));
+        assert_eq!(txs[1].amount, Some(850 - 2329));
+        assert_eq!(txs[1].entry, None);
+
+        let csv = to_csv(&txs);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[2],
+            "114,2019-11-23,VendingMachine,ProductSale,,,-1479,850,JPY"
+        );
+        assert!(lines[1].contains(",\"Tokaidaigaku-mae, Odakyu\",E0-27,"));
+    }
+}
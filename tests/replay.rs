@@ -0,0 +1,63 @@
+//! Golden-file tests: replay recorded traces through the whole probe, and compare what
+//! comes out to what came out last time.
+//!
+//! To add a fixture, record a card with `cardinal probe --record tests/fixtures/NAME.trace`,
+//! then run `UPDATE_GOLDEN=1 cargo test --test replay` to write `NAME.json` next to it,
+//! and read it over before committing it. (Mind what's on the card; traces are verbatim.)
+
+use cardinal::probe::Probe;
+use cardinal::report::{self, Kind, Report};
+use cardinal::transports::trace::{Replay, Trace};
+use std::path::{Path, PathBuf};
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut traces: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "trace"))
+        .collect();
+    traces.sort();
+    traces
+}
+
+fn replay(path: &Path) -> serde_json::Value {
+    let trace = Trace::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let mut card = Replay::new(trace);
+    let probe = Probe::run(&mut card, None).unwrap();
+    assert!(
+        card.is_done(),
+        "{}: trace wasn't fully replayed",
+        path.display()
+    );
+    serde_json::to_value(Report::new(Kind::Probe, &probe)).unwrap()
+}
+
+#[test]
+fn test_replay_fixtures() {
+    let traces = fixtures();
+    assert!(!traces.is_empty(), "no fixtures?");
+    for path in traces {
+        let actual = replay(&path);
+        let golden = path.with_extension("json");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let json = serde_json::to_string_pretty(&actual).unwrap();
+            std::fs::write(&golden, json + "\n").unwrap();
+            continue;
+        }
+        // Old golden files are migrated, so they don't all need redoing on a schema change.
+        let expected = report::load(&std::fs::read_to_string(&golden).unwrap()).unwrap();
+        assert_eq!(actual, expected, "{}: output changed", path.display());
+    }
+}
+
+#[test]
+fn test_replay_past_the_end() {
+    // Commands that aren't in the trace are errors, not made-up responses; the probe
+    // treats them like any other error from a card, and gives up on that part.
+    let mut trace = Trace::parse(&std::fs::read_to_string(&fixtures()[0]).unwrap()).unwrap();
+    trace.exchanges.truncate(1);
+    let mut card = Replay::new(trace);
+    let probe = Probe::run(&mut card, None).unwrap();
+    assert!(probe.emv.is_none() && probe.felica.is_none());
+}